@@ -0,0 +1,248 @@
+//! The Binary Exponential Forthon™ module.
+//!
+//! In both RENEWING and REBINDING states, if the client receives no
+//! response to its DHCPREQUEST message, the client SHOULD wait one-half
+//! of the remaining time until T2 (in RENEWING state) and one-half of
+//! the remaining lease time (in REBINDING state), down to a minimum of
+//! 60 seconds, before retransmitting the DHCPREQUEST message.
+
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll, Stream};
+use tokio::timer::{Delay, Error};
+
+/// The retry-until-deadline timer implemented as a `Stream`.
+///
+/// Sleeps a `1/divisor` part of the time left until the deadline before each retry,
+/// and the rest of it at once if the part is shorter than the minimal interval.
+/// The time left is measured anew after each wakeup, so a late timer only shortens the next sleep.
+///
+/// Yields the duration slept and whether the deadline has been reached,
+/// and ends after reaching it.
+pub struct Forthon {
+    /// The retries are over at it.
+    deadline: Instant,
+    /// The time left is slept at once if its part is shorter than it.
+    minimal: Duration,
+    /// The part of the time left slept each time. The RFC 2131 value is 2.
+    divisor: u32,
+    /// The duration of the running timer.
+    sleep: Duration,
+    /// Whether the running timer ends at the deadline.
+    last: bool,
+    /// Not set after the deadline or while paused.
+    timer: Option<Delay>,
+    /// The time left until the deadline when paused.
+    paused: Option<Duration>,
+}
+
+impl Forthon {
+    /// Constructs a timer and starts it.
+    ///
+    /// * `deadline`
+    /// The duration until expiration.
+    ///
+    /// * `minimal`
+    /// The minimal interval between the retries. The last timeout may be shorter.
+    ///
+    /// * `divisor`
+    /// The remaining duration is divided by it to get the next timeout.
+    ///
+    /// # Panics
+    /// If `divisor` is zero.
+    pub fn new(deadline: Duration, minimal: Duration, divisor: u32) -> Forthon {
+        assert!(divisor > 0, "The Forthon divisor must be positive");
+        let now = Instant::now();
+        let mut forthon = Forthon {
+            deadline: now + deadline,
+            minimal,
+            divisor,
+            sleep: Duration::from_secs(0),
+            last: false,
+            timer: None,
+            paused: None,
+        };
+        forthon.start_next(now);
+        forthon
+    }
+
+    /// The instant the retries are over at.
+    ///
+    /// Is moved forward by the time spent paused.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// The time left until the deadline.
+    pub fn left(&self) -> Duration {
+        match self.paused {
+            Some(left) => left,
+            None => remaining(self.deadline, Instant::now()),
+        }
+    }
+
+    /// Whether the timer is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// Stops the timer, keeping the time left until the deadline.
+    ///
+    /// The paused timer is never ready. Does nothing if the deadline has been reached.
+    pub fn pause(&mut self) {
+        if self.paused.is_some() || self.timer.is_none() {
+            return;
+        }
+        self.paused = Some(self.left());
+        self.timer = None;
+    }
+
+    /// Moves the deadline forward by the time spent paused and restarts the timer.
+    ///
+    /// The timer must be polled again to be woken up.
+    pub fn resume(&mut self) {
+        if let Some(left) = self.paused.take() {
+            let now = Instant::now();
+            self.deadline = now + left;
+            self.start_next(now);
+        }
+    }
+
+    /// Starts the timer of the next retry measuring the time left from `now`.
+    fn start_next(&mut self, now: Instant) {
+        let (sleep, last) = next_sleep(remaining(self.deadline, now), self.minimal, self.divisor);
+        self.sleep = sleep;
+        self.last = last;
+        self.timer = Some(Delay::new(now + sleep));
+    }
+}
+
+impl Stream for Forthon {
+    type Item = (Duration, bool);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.paused.is_some() {
+            return Ok(Async::NotReady);
+        }
+        match self.timer {
+            Some(ref mut timer) => try_ready!(timer.poll()),
+            None => return Ok(Async::Ready(None)),
+        }
+        let slept = self.sleep;
+        if self.last {
+            self.timer = None;
+        } else {
+            self.start_next(Instant::now());
+        }
+        Ok(Async::Ready(Some((slept, self.timer.is_none()))))
+    }
+}
+
+/// The time left from `now` until the `deadline`, zero if it has passed.
+fn remaining(deadline: Instant, now: Instant) -> Duration {
+    if deadline > now {
+        deadline - now
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+/// The next sleep duration and whether it ends at the deadline.
+fn next_sleep(left: Duration, minimal: Duration, divisor: u32) -> (Duration, bool) {
+    let part = left / divisor;
+    if part < minimal {
+        (left, true)
+    } else {
+        (part, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: u64 = 60;
+    const DIVISOR: u32 = 2;
+
+    fn forthon(deadline: u64) -> Forthon {
+        Forthon::new(
+            Duration::from_secs(deadline),
+            Duration::from_secs(MINIMAL),
+            DIVISOR,
+        )
+    }
+
+    #[test]
+    fn sleeps_half_of_time_left() {
+        let forthon = forthon(400);
+
+        assert_eq!(forthon.sleep, Duration::from_secs(200));
+        assert!(!forthon.last);
+    }
+
+    #[test]
+    fn sleeps_until_deadline_below_minimal() {
+        let forthon = forthon(100);
+
+        assert_eq!(forthon.sleep, Duration::from_secs(100));
+        assert!(forthon.last);
+    }
+
+    #[test]
+    fn late_wakeup_shortens_next_sleep() {
+        let mut forthon = forthon(400);
+        let deadline = forthon.deadline();
+
+        forthon.start_next(deadline - Duration::from_secs(150));
+
+        assert_eq!(forthon.sleep, Duration::from_secs(75));
+        assert!(!forthon.last);
+        assert_eq!(forthon.deadline(), deadline);
+    }
+
+    #[test]
+    fn wakeup_after_deadline_expires_at_once() {
+        let mut forthon = forthon(400);
+        let deadline = forthon.deadline();
+
+        forthon.start_next(deadline + Duration::from_secs(10));
+
+        assert_eq!(forthon.sleep, Duration::from_secs(0));
+        assert!(forthon.last);
+    }
+
+    #[test]
+    fn early_wakeup_keeps_deadline() {
+        let mut forthon = forthon(400);
+        let deadline = forthon.deadline();
+
+        forthon.start_next(deadline - Duration::from_secs(400));
+
+        assert_eq!(forthon.sleep, Duration::from_secs(200));
+        assert_eq!(forthon.deadline(), deadline);
+    }
+
+    #[test]
+    #[should_panic(expected = "divisor must be positive")]
+    fn rejects_zero_divisor() {
+        Forthon::new(Duration::from_secs(400), Duration::from_secs(MINIMAL), 0);
+    }
+
+    #[test]
+    fn pause_keeps_time_left() {
+        let mut forthon = forthon(400);
+        let deadline = forthon.deadline();
+
+        forthon.pause();
+        let left = forthon.left();
+        assert!(forthon.is_paused());
+        assert!(forthon.timer.is_none());
+
+        forthon.resume();
+        assert!(!forthon.is_paused());
+        assert!(forthon.deadline() >= deadline);
+        assert!(forthon.left() <= left);
+        assert!(forthon.timer.is_some());
+    }
+}
@@ -0,0 +1,385 @@
+//! The retry timers shared by the DHCP client and server.
+//!
+//! A `Backoff` sleeps before every retry for as long as its `Strategy` tells,
+//! and a `Forthon` retries until a deadline with the intervals halving towards it.
+//! A `Retry` repeats a failed future on a `Backoff`.
+//! The `Exponential` strategy is the Binary Exponential Backoff of RFC 2131:
+//!
+//! DHCP clients are responsible for all message retransmission.  The
+//! client MUST adopt a retransmission strategy that incorporates a
+//! randomized exponential backoff algorithm to determine the delay
+//! between retransmissions.  The delay between retransmissions SHOULD be
+//! chosen to allow sufficient time for replies from the server to be
+//! delivered based on the characteristics of the internetwork between
+//! the client and the server.  For example, in a 10Mb/sec Ethernet
+//! internetwork, the delay before the first retransmission SHOULD be 4
+//! seconds randomized by the value of a uniform random number chosen
+//! from the range -1 to +1.  Clients with clocks that provide resolution
+//! granularity of less than one second may choose a non-integer
+//! randomization value.  The delay before the next retransmission SHOULD
+//! be 8 seconds randomized by the value of a uniform number chosen from
+//! the range -1 to +1.  The retransmission delay SHOULD be doubled with
+//! subsequent retransmissions up to a maximum of 64 seconds.  The client
+//! MAY provide an indication of retransmission attempts to the user as
+//! an indication of the progress of the configuration process.
+
+#[macro_use]
+extern crate futures;
+extern crate rand;
+extern crate tokio;
+
+mod forthon;
+
+pub use forthon::Forthon;
+
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, IntoFuture, Poll, Stream};
+use rand::Rng;
+use tokio::timer::{Delay, Error};
+
+/// Tells how long to sleep before each retry.
+pub trait Strategy {
+    /// The delay before the retry `attempt`, counted from one,
+    /// or `None` if there must be no more retries.
+    fn delay(&self, attempt: u32) -> Option<Duration>;
+}
+
+/// Multiplies the delay after each retry up to the maximal one.
+#[derive(Debug, Clone)]
+pub struct Exponential {
+    /// The delay before the first retry.
+    initial: Duration,
+    /// The delay stops growing at it.
+    maximal: Duration,
+    /// The delay is multiplied by it after each retry.
+    multiplier: u32,
+    /// A uniform random offset from `-jitter` to `jitter` is added to each delay.
+    jitter: Duration,
+    /// The retries are over after it if set.
+    retries: Option<u32>,
+}
+
+impl Exponential {
+    /// Doubles the delay from `initial` up to `maximal` without randomization,
+    /// retrying forever.
+    pub fn new(initial: Duration, maximal: Duration) -> Self {
+        Exponential {
+            initial,
+            maximal,
+            multiplier: 2,
+            jitter: Duration::from_secs(0),
+            retries: None,
+        }
+    }
+
+    /// Sets the factor the delay is multiplied by after each retry.
+    pub fn with_multiplier(&mut self, multiplier: u32) -> &mut Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Randomizes each delay by up to `jitter` both ways, with a millisecond granularity.
+    ///
+    /// RFC 2131 suggests one second.
+    pub fn with_jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Caps the number of retries.
+    pub fn with_retries(&mut self, retries: u32) -> &mut Self {
+        self.retries = Some(retries);
+        self
+    }
+}
+
+impl Strategy for Exponential {
+    fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.retries.map_or(false, |retries| attempt > retries) {
+            return None;
+        }
+        let mut delay = self.initial;
+        for _ in 1..attempt {
+            if delay >= self.maximal {
+                break;
+            }
+            delay = delay.checked_mul(self.multiplier).unwrap_or(self.maximal);
+        }
+        Some(randomize(delay.min(self.maximal), self.jitter))
+    }
+}
+
+/// Sleeps for the same delay before each retry.
+#[derive(Debug, Clone)]
+pub struct Constant {
+    delay: Duration,
+    /// The retries are over after it if set.
+    retries: Option<u32>,
+}
+
+impl Constant {
+    /// Retries forever after the `delay`.
+    pub fn new(delay: Duration) -> Self {
+        Constant {
+            delay,
+            retries: None,
+        }
+    }
+
+    /// Caps the number of retries.
+    pub fn with_retries(&mut self, retries: u32) -> &mut Self {
+        self.retries = Some(retries);
+        self
+    }
+}
+
+impl Strategy for Constant {
+    fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.retries.map_or(false, |retries| attempt > retries) {
+            return None;
+        }
+        Some(self.delay)
+    }
+}
+
+/// The retry timer implemented as a `Stream`.
+///
+/// Yields the delay slept before each retry and whether the retry is the last one,
+/// and ends after the last one.
+pub struct Backoff<S = Exponential> {
+    strategy: S,
+    /// The retry the timer is running before.
+    attempt: u32,
+    /// The delay of the running timer.
+    delay: Duration,
+    /// Not set after the last retry.
+    timer: Option<Delay>,
+}
+
+impl<S> Backoff<S>
+where
+    S: Strategy,
+{
+    /// Starts the timer of the first retry.
+    pub fn new(strategy: S) -> Self {
+        let mut backoff = Backoff {
+            strategy,
+            attempt: 0,
+            delay: Duration::from_secs(0),
+            timer: None,
+        };
+        backoff.reset();
+        backoff
+    }
+
+    /// The number of the retry the timer is running before, counted from one.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Starts over from the first retry, e.g. after a success.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+        self.start_next();
+    }
+
+    /// Starts the timer of the next retry if there is one.
+    fn start_next(&mut self) {
+        self.attempt += 1;
+        let delay = self.strategy.delay(self.attempt);
+        if let Some(delay) = delay {
+            self.delay = delay;
+        }
+        self.timer = delay.map(|delay| Delay::new(Instant::now() + delay));
+    }
+}
+
+impl<S> Stream for Backoff<S>
+where
+    S: Strategy,
+{
+    type Item = (Duration, bool);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.timer {
+            Some(ref mut timer) => try_ready!(timer.poll()),
+            None => return Ok(Async::Ready(None)),
+        }
+        let slept = self.delay;
+        self.start_next();
+        Ok(Async::Ready(Some((slept, self.timer.is_none()))))
+    }
+}
+
+/// Runs the action until its future succeeds or the retries are over.
+///
+/// The first attempt is made at once and the retries after the delays the `Strategy` tells,
+/// the first one counted from the first failure. Resolves with the first success
+/// or with the last failure, which is also returned if the timer fails.
+pub struct Retry<S, A, F>
+where
+    S: Strategy,
+    A: FnMut() -> F,
+    F: IntoFuture,
+{
+    action: A,
+    /// Moved into the backoff at the first failure.
+    strategy: Option<S>,
+    backoff: Option<Backoff<S>>,
+    /// Not set while waiting for the next retry.
+    attempt: Option<F::Future>,
+    /// The failure of the last attempt.
+    error: Option<F::Error>,
+}
+
+impl<S, A, F> Retry<S, A, F>
+where
+    S: Strategy,
+    A: FnMut() -> F,
+    F: IntoFuture,
+{
+    /// Makes the first attempt.
+    pub fn new(strategy: S, mut action: A) -> Self {
+        let attempt = action().into_future();
+        Retry {
+            action,
+            strategy: Some(strategy),
+            backoff: None,
+            attempt: Some(attempt),
+            error: None,
+        }
+    }
+}
+
+impl<S, A, F> Future for Retry<S, A, F>
+where
+    S: Strategy,
+    A: FnMut() -> F,
+    F: IntoFuture,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if let Some(mut attempt) = self.attempt.take() {
+                match attempt.poll() {
+                    Ok(Async::Ready(item)) => return Ok(Async::Ready(item)),
+                    Ok(Async::NotReady) => {
+                        self.attempt = Some(attempt);
+                        return Ok(Async::NotReady);
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+                if let Some(strategy) = self.strategy.take() {
+                    self.backoff = Some(Backoff::new(strategy));
+                }
+            }
+
+            let backoff = self
+                .backoff
+                .as_mut()
+                .expect("A bug in the retry logic: no backoff after a failure");
+            match backoff.poll() {
+                Ok(Async::Ready(Some(_))) => self.attempt = Some((self.action)().into_future()),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(None)) | Err(_) => {
+                    return Err(self
+                        .error
+                        .take()
+                        .expect("A bug in the retry logic: no failure to return"))
+                }
+            }
+        }
+    }
+}
+
+/// Adds a uniform random offset from `-jitter` to `jitter` milliseconds, never going below zero.
+fn randomize(duration: Duration, jitter: Duration) -> Duration {
+    let jitter = jitter.as_secs() * 1000 + u64::from(jitter.subsec_millis());
+    if jitter == 0 {
+        return duration;
+    }
+    let offset = rand::thread_rng().gen_range(0, 2 * jitter + 1);
+    let duration = duration + Duration::from_millis(offset);
+    duration
+        .checked_sub(Duration::from_millis(jitter))
+        .unwrap_or_else(|| Duration::from_secs(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::Cell, rc::Rc};
+
+    use futures::future;
+    use tokio::runtime::current_thread::Runtime;
+
+    fn constant(retries: u32) -> Constant {
+        let mut strategy = Constant::new(Duration::from_millis(1));
+        strategy.with_retries(retries);
+        strategy
+    }
+
+    #[test]
+    fn doubles_delay_up_to_maximal() {
+        let mut strategy = Exponential::new(Duration::from_secs(4), Duration::from_secs(64));
+        strategy.with_retries(6);
+
+        let delays: Vec<_> = (1..8).map(|attempt| strategy.delay(attempt)).collect();
+        let expected: Vec<_> = [4, 8, 16, 32, 64, 64]
+            .iter()
+            .map(|secs| Some(Duration::from_secs(*secs)))
+            .chain(Some(None))
+            .collect();
+        assert_eq!(delays, expected);
+    }
+
+    #[test]
+    fn retries_until_success() {
+        let attempts = Rc::new(Cell::new(0));
+        let counter = attempts.clone();
+        let retry = Retry::new(constant(5), move || {
+            counter.set(counter.get() + 1);
+            if counter.get() < 3 {
+                Err("failure")
+            } else {
+                Ok(counter.get())
+            }
+        });
+
+        let result = Runtime::new().unwrap().block_on(retry);
+        assert_eq!(result, Ok(3));
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn returns_last_failure_after_retries() {
+        let attempts = Rc::new(Cell::new(0));
+        let counter = attempts.clone();
+        let retry = Retry::new(constant(2), move || {
+            counter.set(counter.get() + 1);
+            future::err::<(), _>(counter.get())
+        });
+
+        let result = Runtime::new().unwrap().block_on(retry);
+        assert_eq!(result, Err(3));
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn attempts_once_without_retries() {
+        let attempts = Rc::new(Cell::new(0));
+        let counter = attempts.clone();
+        let retry = Retry::new(constant(0), move || {
+            counter.set(counter.get() + 1);
+            Err::<(), _>(())
+        });
+
+        assert!(Runtime::new().unwrap().block_on(retry).is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+}
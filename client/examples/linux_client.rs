@@ -27,7 +27,7 @@ use eui48::MacAddress;
 use tokio::prelude::*;
 use tokio::reactor::Handle;
 
-use dhcp_client::{Client, Command};
+use dhcp_client::{Client, ClientError, Command};
 use dhcp_framed::{DhcpFramed, DhcpSinkItem, DhcpStreamItem};
 use dhcp_protocol::{DHCP_PORT_CLIENT, SIZE_MESSAGE_MINIMAL};
 use ifcontrol::Iface;
@@ -62,7 +62,7 @@ where
     O: Sink<SinkItem = DhcpSinkItem, SinkError = io::Error> + Send + Sync,
 {
     type Item = ();
-    type Error = io::Error;
+    type Error = ClientError;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         loop {
@@ -123,7 +123,7 @@ fn main() {
         address_request,
         address_time,
         max_message_size,
-        false,
+        None,
     ));
 
     let future = client.map_err(|error| error!("Error: {}", error));
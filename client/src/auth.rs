@@ -0,0 +1,143 @@
+//! The RFC 3118 delayed authentication module.
+
+use chrono::prelude::*;
+use tokio::io;
+
+use dhcp_protocol::{
+    auth::{self, Authentication},
+    Message, MessageType,
+};
+
+/// The buffer size used to serialize a message before signing it.
+const SIZE_SIGNING_BUFFER: usize = 8192;
+
+/// The shared key the client and the servers are configured with.
+#[derive(Debug, Clone)]
+pub struct AuthenticationKey {
+    /// The DHCP realm the key belongs to. May be empty.
+    pub realm: Vec<u8>,
+    /// The key identifier within the realm.
+    pub secret_id: u32,
+    /// The key itself.
+    pub key: Vec<u8>,
+}
+
+/// Signs the outgoing messages and verifies the incoming ones.
+pub struct Authenticator {
+    /// The shared key.
+    key: AuthenticationKey,
+    /// The last replay detection value sent.
+    replay_sent: u64,
+    /// The last replay detection value received from a server.
+    replay_received: Option<u64>,
+}
+
+impl Authenticator {
+    /// Constructs an authenticator.
+    ///
+    /// The replay detection counter starts from the current time,
+    /// so it keeps increasing across restarts.
+    pub fn new(key: AuthenticationKey) -> Self {
+        Authenticator {
+            key,
+            replay_sent: (Utc::now().timestamp() as u64) << 32,
+            replay_received: None,
+        }
+    }
+
+    /// Adds the authentication option to the message.
+    ///
+    /// A `DHCPDISCOVER` only announces the delayed authentication support,
+    /// while the other messages carry the secret ID and the MAC.
+    ///
+    /// # Errors
+    /// `io::Error` if the message cannot be serialized.
+    pub fn sign(&mut self, message: &mut Message) -> io::Result<()> {
+        self.replay_sent = self.replay_sent.wrapping_add(1);
+        let is_discover = match message.options.dhcp_message_type {
+            Some(MessageType::DhcpDiscover) => true,
+            _ => false,
+        };
+
+        let mut information = Vec::new();
+        if !is_discover {
+            information.extend_from_slice(&self.key.realm);
+            information.push((self.key.secret_id >> 24) as u8);
+            information.push((self.key.secret_id >> 16) as u8);
+            information.push((self.key.secret_id >> 8) as u8);
+            information.push(self.key.secret_id as u8);
+            information.extend_from_slice(&[0u8; auth::SIZE_HMAC_MD5]);
+        }
+        message.options.authentication = Some(Authentication {
+            protocol: auth::PROTOCOL_DELAYED,
+            algorithm: auth::ALGORITHM_HMAC_MD5,
+            replay_detection_method: auth::RDM_MONOTONIC,
+            replay_detection: self.replay_sent,
+            information,
+            authenticated_data: None,
+        });
+        if is_discover {
+            return Ok(());
+        }
+
+        // the serializer is deterministic, so the sent message will be the same
+        let mut buffer = vec![0u8; SIZE_SIGNING_BUFFER];
+        let amount = message.to_bytes(&mut buffer, None)?;
+        let data = match auth::authenticated_data(&buffer[..amount]) {
+            Some(data) => data,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The authentication option has not been serialized",
+                ))
+            }
+        };
+        let mac = auth::hmac_md5(&self.key.key, &data);
+
+        let authentication = expect!(message.options.authentication.as_mut());
+        let start = authentication.information.len() - auth::SIZE_HMAC_MD5;
+        authentication.information[start..].copy_from_slice(&mac);
+        Ok(())
+    }
+
+    /// Checks the server response MAC and replay detection value.
+    pub fn verify(&mut self, message: &Message) -> bool {
+        let authentication = match message.options.authentication {
+            Some(ref authentication) => authentication,
+            None => {
+                warn!("The response is not authenticated");
+                return false;
+            }
+        };
+
+        if authentication.secret_id() != Some(self.key.secret_id)
+            || authentication.realm() != Some(self.key.realm.as_slice())
+        {
+            warn!("The response is authenticated with an unknown key");
+            return false;
+        }
+        if authentication.replay_detection_method != auth::RDM_MONOTONIC {
+            warn!(
+                "Unsupported replay detection method {}",
+                authentication.replay_detection_method
+            );
+            return false;
+        }
+        if let Some(replay_received) = self.replay_received {
+            if authentication.replay_detection <= replay_received {
+                warn!(
+                    "The response is replayed: {} (the last one is {})",
+                    authentication.replay_detection, replay_received
+                );
+                return false;
+            }
+        }
+        if !authentication.verify(&self.key.key) {
+            warn!("The response MAC is invalid");
+            return false;
+        }
+
+        self.replay_received = Some(authentication.replay_detection);
+        true
+    }
+}
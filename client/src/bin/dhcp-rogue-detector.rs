@@ -0,0 +1,275 @@
+//! Detects the rogue DHCP servers by probing the network with throwaway clients.
+//!
+//! Run this with administrator privileges where it is required
+//! in order to bind the socket to the DHCP client port 68.
+//!
+//! Each probe broadcasts a `DHCPDISCOVER` with a new random hardware address
+//! and reports the `DHCPOFFER`s from the servers whose identifiers are not allowed.
+//! The options are:
+//! * `--allow <server identifier>`, repeated for each legitimate server
+//! * `--interval <seconds>`, probes periodically instead of once
+//! * `--wait <seconds>`, the time the offers are collected for, 5 by default
+//! * `--interface <name>`, the interface to bind the socket to
+//! * `--json`, prints the alerts as JSON lines
+//! * `--syslog`, sends the alerts to the local syslog as well
+//!
+//! A single probe exits with 1 if a rogue server has been found and 2 on errors.
+//! The periodic probes keep running and only exit on errors.
+
+extern crate chrono;
+extern crate eui48;
+extern crate rand;
+
+extern crate dhcp_client;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+extern crate dhcp_framed;
+extern crate dhcp_protocol;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    process, thread,
+    time::Duration,
+};
+
+use chrono::prelude::*;
+use eui48::MacAddress;
+
+use dhcp_client::blocking;
+use dhcp_protocol::{Message, DHCP_PORT_CLIENT};
+
+/// The seconds the offers are collected for by default.
+const DEFAULT_WAIT: u64 = 5;
+/// The first byte of the throwaway hardware addresses (locally administered, unicast).
+const PROBE_MAC_PREFIX: u8 = 0x02;
+/// The local syslog socket.
+#[cfg(unix)]
+const SYSLOG_PATH: &str = "/dev/log";
+/// The `daemon` facility and the `warning` severity.
+#[cfg(unix)]
+const SYSLOG_PRIORITY: u8 = 3 * 8 + 4;
+
+const USAGE: &str = "Usage: dhcp-rogue-detector [--allow <server id> ...] [--interval <seconds>] \
+                     [--wait <seconds>] [--interface <name>] [--json] [--syslog]";
+
+/// The command line options.
+struct Options {
+    allowed: Vec<Ipv4Addr>,
+    interval: Option<Duration>,
+    wait: Duration,
+    interface: Option<String>,
+    is_json: bool,
+    is_syslog: bool,
+}
+
+impl Options {
+    /// Parses the options from the command line arguments.
+    ///
+    /// # Errors
+    /// The description of the first invalid argument.
+    fn parse<A: Iterator<Item = String>>(mut args: A) -> Result<Self, String> {
+        let mut options = Options {
+            allowed: Vec::new(),
+            interval: None,
+            wait: Duration::from_secs(DEFAULT_WAIT),
+            interface: None,
+            is_json: false,
+            is_syslog: false,
+        };
+        while let Some(name) = args.next() {
+            match name.as_str() {
+                "--json" => {
+                    options.is_json = true;
+                    continue;
+                }
+                "--syslog" => {
+                    options.is_syslog = true;
+                    continue;
+                }
+                _ => {}
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("No value after '{}'", name))?;
+            let invalid = || format!("Invalid value '{}' of '{}'", value, name);
+            match name.as_str() {
+                "--allow" => options.allowed.push(value.parse().map_err(|_| invalid())?),
+                "--interval" => {
+                    let seconds = value.parse().map_err(|_| invalid())?;
+                    options.interval = Some(Duration::from_secs(seconds));
+                }
+                "--wait" => {
+                    let seconds = value.parse().map_err(|_| invalid())?;
+                    options.wait = Duration::from_secs(seconds);
+                }
+                "--interface" => options.interface = Some(value.to_owned()),
+                _ => return Err(format!("Unknown option '{}'", name)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// An offer from a server not in the allowlist.
+struct Alert {
+    /// The server identifier, or the source address if the offer has none.
+    server_id: Ipv4Addr,
+    source: SocketAddr,
+    offered_address: Ipv4Addr,
+    /// The throwaway hardware address of the probe.
+    probe: MacAddress,
+}
+
+impl Alert {
+    /// The fields are addresses, so nothing needs escaping.
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"time\":\"{}\",\"server_id\":\"{}\",\"source\":\"{}\",\
+             \"offered_address\":\"{}\",\"probe\":\"{}\"}}",
+            Utc::now().to_rfc3339(),
+            self.server_id,
+            self.source,
+            self.offered_address,
+            self.probe.to_hex_string(),
+        )
+    }
+
+    fn to_text(&self) -> String {
+        format!(
+            "Rogue DHCP server {} at {} has offered {} to {}",
+            self.server_id,
+            self.source,
+            self.offered_address,
+            self.probe.to_hex_string(),
+        )
+    }
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let socket = match bind(options.interface.as_ref().map(String::as_str)) {
+        Ok(socket) => socket,
+        Err(error) => {
+            eprintln!("Socket binding error: {}", error);
+            process::exit(2);
+        }
+    };
+
+    loop {
+        let alerts = match probe(&socket, &options) {
+            Ok(alerts) => alerts,
+            Err(error) => {
+                eprintln!("Probing error: {}", error);
+                process::exit(2);
+            }
+        };
+        for alert in alerts.iter() {
+            report(alert, &options);
+        }
+        match options.interval {
+            Some(interval) => thread::sleep(interval),
+            None => process::exit(if alerts.is_empty() { 0 } else { 1 }),
+        }
+    }
+}
+
+/// Binds the socket to the DHCP client port and the interface, if any.
+fn bind(interface: Option<&str>) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        DHCP_PORT_CLIENT,
+    ))?;
+    if let Some(interface) = interface {
+        bind_to_device(&socket, interface)?;
+    }
+    Ok(socket)
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+fn bind_to_device(socket: &UdpSocket, interface: &str) -> io::Result<()> {
+    dhcp_framed::bind_to_device(socket, interface)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "macos")))]
+fn bind_to_device(_socket: &UdpSocket, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "The socket cannot be bound to an interface on this platform",
+    ))
+}
+
+/// Sends a `DHCPDISCOVER` with a new identity and returns the offers of the unknown servers.
+fn probe(socket: &UdpSocket, options: &Options) -> io::Result<Vec<Alert>> {
+    let mut address = rand::random::<[u8; 6]>();
+    address[0] = PROBE_MAC_PREFIX;
+    let probe = MacAddress::new(address);
+
+    let offers = blocking::discover_offers(socket.try_clone()?, probe, options.wait)
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+    Ok(offers
+        .into_iter()
+        .filter_map(|(source, offer)| alert(source, &offer, probe, &options.allowed))
+        .collect())
+}
+
+/// Checks the offer against the allowlist.
+fn alert(
+    source: SocketAddr,
+    offer: &Message,
+    probe: MacAddress,
+    allowed: &[Ipv4Addr],
+) -> Option<Alert> {
+    let server_id = match (offer.options.dhcp_server_id, source.ip()) {
+        (Some(server_id), _) => server_id,
+        (None, IpAddr::V4(address)) => address,
+        (None, IpAddr::V6(_)) => return None,
+    };
+    if allowed.contains(&server_id) {
+        return None;
+    }
+    Some(Alert {
+        server_id,
+        source,
+        offered_address: offer.your_ip_address,
+        probe,
+    })
+}
+
+fn report(alert: &Alert, options: &Options) {
+    if options.is_json {
+        println!("{}", alert.to_json());
+    } else {
+        println!("{}", alert.to_text());
+    }
+    if options.is_syslog {
+        if let Err(error) = syslog(&alert.to_text()) {
+            eprintln!("Syslog error: {}", error);
+        }
+    }
+}
+
+/// Sends the message to the local syslog in the RFC 3164 format.
+#[cfg(unix)]
+fn syslog(message: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    let line = format!("<{}>dhcp-rogue-detector: {}", SYSLOG_PRIORITY, message);
+    socket.send_to(line.as_bytes(), SYSLOG_PATH)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn syslog(_message: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "The syslog is supported only on Unix",
+    ))
+}
@@ -0,0 +1,186 @@
+//! The DHCP server load generator built on the client simulation.
+//!
+//! Run this with administrator privileges where it is required
+//! in order to bind the socket to the DHCP client port 68.
+//!
+//! The options are:
+//! * `--clients <count>`, 100 by default
+//! * `--rate <clients per second>`, 10 by default
+//! * `--renew-ratio <0 to 1>`, the share of the bound clients renewing at once, 0 by default
+//! * `--duration <seconds>`, the time limit, unlimited by default
+//! * `--server <address>`, the server to unicast to instead of broadcasting
+//! * `--interface <name>`, the interface to bind the socket to
+//!
+//! The `DHCPOFFER` and `DHCPACK` latency percentiles and the drop counts are printed at the end.
+
+extern crate tokio;
+
+extern crate dhcp_client;
+extern crate dhcp_framed;
+extern crate dhcp_protocol;
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    process,
+    time::Duration,
+};
+
+use tokio::{net::UdpSocket, prelude::*};
+
+use dhcp_client::simulate::{Simulation, Statistics};
+use dhcp_framed::{DhcpTransport, TransportFramed};
+use dhcp_protocol::DHCP_PORT_CLIENT;
+
+const DEFAULT_CLIENTS: usize = 100;
+const DEFAULT_RATE: u32 = 10;
+/// The percentiles printed for each latency.
+const PERCENTILES: [u8; 4] = [50, 90, 99, 100];
+
+const USAGE: &str = "Usage: dhcperf [--clients <count>] [--rate <per second>] \
+                     [--renew-ratio <0..1>] [--duration <seconds>] [--server <address>] \
+                     [--interface <name>]";
+
+/// The command line options.
+struct Options {
+    clients: usize,
+    rate: u32,
+    renew_ratio: f64,
+    duration: Option<Duration>,
+    server_address: Option<Ipv4Addr>,
+    interface: Option<String>,
+}
+
+impl Options {
+    /// Parses the options from the command line arguments.
+    ///
+    /// # Errors
+    /// The description of the first invalid argument.
+    fn parse<A: Iterator<Item = String>>(mut args: A) -> Result<Self, String> {
+        let mut options = Options {
+            clients: DEFAULT_CLIENTS,
+            rate: DEFAULT_RATE,
+            renew_ratio: 0.0,
+            duration: None,
+            server_address: None,
+            interface: None,
+        };
+        while let Some(name) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("No value after '{}'", name))?;
+            let invalid = || format!("Invalid value '{}' of '{}'", value, name);
+            match name.as_str() {
+                "--clients" => options.clients = value.parse().map_err(|_| invalid())?,
+                "--rate" => options.rate = value.parse().map_err(|_| invalid())?,
+                "--renew-ratio" => {
+                    let ratio: f64 = value.parse().map_err(|_| invalid())?;
+                    if ratio < 0.0 || ratio > 1.0 {
+                        return Err(invalid());
+                    }
+                    options.renew_ratio = ratio;
+                }
+                "--duration" => {
+                    let seconds = value.parse().map_err(|_| invalid())?;
+                    options.duration = Some(Duration::from_secs(seconds));
+                }
+                "--server" => options.server_address = Some(value.parse().map_err(|_| invalid())?),
+                "--interface" => options.interface = Some(value.to_owned()),
+                _ => return Err(format!("Unknown option '{}'", name)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    // nothing but sending and receiving is needed, so any transport will do
+    let socket = UdpSocket::bind_broadcast(&SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        DHCP_PORT_CLIENT,
+    )).expect("Socket binding error");
+    if let Some(ref interface) = options.interface {
+        bind_to_device(&socket, interface);
+    }
+    let (sink, stream) = TransportFramed::new(socket).split();
+
+    let mut simulation = Simulation::new(
+        stream,
+        sink,
+        options.clients,
+        options.rate,
+        options.server_address,
+    );
+    simulation.with_renew_ratio(options.renew_ratio);
+    if let Some(duration) = options.duration {
+        simulation.with_duration(duration);
+    }
+
+    println!(
+        "Starting {} clients at {} per second",
+        options.clients, options.rate
+    );
+    let future = simulation
+        .map(|statistics| print_report(&statistics))
+        .map_err(|error| eprintln!("Simulation error: {}", error));
+    tokio::run(future);
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+fn bind_to_device(socket: &UdpSocket, interface: &str) {
+    dhcp_framed::bind_to_device(socket, interface).expect("Interface binding error");
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "macos")))]
+fn bind_to_device(_socket: &UdpSocket, _interface: &str) {
+    eprintln!("The socket cannot be bound to an interface on this platform");
+    process::exit(2);
+}
+
+fn print_report(statistics: &Statistics) {
+    println!(
+        "Clients: {} started, {} bound, {} failed, {} unfinished",
+        statistics.started, statistics.bound, statistics.failed, statistics.unfinished,
+    );
+    println!(
+        "Renewals: {} requested, {} acknowledged",
+        statistics.renewing, statistics.renewed,
+    );
+    println!(
+        "Drops: {} retransmitted, {} NAKed, {} discarded",
+        statistics.retransmitted, statistics.naks, statistics.discarded,
+    );
+    print_percentiles("DHCPOFFER", statistics.offer_latencies.len(), |percentile| {
+        statistics.offer_percentile(percentile)
+    });
+    print_percentiles("DHCPACK", statistics.ack_latencies.len(), |percentile| {
+        statistics.ack_percentile(percentile)
+    });
+    print_percentiles("Lease", statistics.latencies.len(), |percentile| {
+        statistics.percentile(percentile)
+    });
+}
+
+fn print_percentiles<F>(name: &str, count: usize, percentile: F)
+where
+    F: Fn(u8) -> Option<Duration>,
+{
+    print!("{} latency of {} responses:", name, count);
+    for value in PERCENTILES.iter() {
+        if let Some(latency) = percentile(*value) {
+            print!(" p{} {} ms", value, milliseconds(latency));
+        }
+    }
+    println!();
+}
+
+fn milliseconds(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
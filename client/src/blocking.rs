@@ -0,0 +1,236 @@
+//! The blocking DHCP client for the applications without an async runtime.
+//!
+//! Drives the `Client` state machine over a `std` UDP socket on the calling thread,
+//! so the exchanges, the backoff and the client options are the same as with
+//! the `Client` stream. The timers are run by a single-threaded runtime owned
+//! by the client, the datagrams are received by the reader thread of the transport.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use eui48::MacAddress;
+use futures::{
+    future,
+    stream::{SplitSink, SplitStream},
+};
+use rand;
+use tokio::{io, prelude::*, runtime::current_thread::Runtime, timer::Delay};
+
+use dhcp_framed::{BlockingTransport, DhcpTransport, TransportFramed};
+use dhcp_protocol::{Message, MessageType, DHCP_PORT_CLIENT, DHCP_PORT_SERVER};
+
+use builder::MessageBuilder;
+use client::{Client, Command, Configuration, Event};
+use error::Error;
+
+type Framed = TransportFramed<BlockingTransport>;
+
+/// The client state machine driven by `BlockingClient`.
+pub type Inner = Client<SplitStream<Framed>, SplitSink<Framed>>;
+
+/// The DHCP client blocking the calling thread until the next event.
+pub struct BlockingClient {
+    runtime: Runtime,
+    client: Inner,
+}
+
+impl BlockingClient {
+    /// Binds the client socket to the DHCP client port.
+    ///
+    /// The `client_id` is defaulted to the `client_hardware_address` bytes.
+    ///
+    /// # Errors
+    /// `ClientError::Transport` if the socket cannot be bound.
+    pub fn new(
+        client_hardware_address: MacAddress,
+        client_id: Option<Vec<u8>>,
+        hostname: Option<String>,
+    ) -> Result<Self, Error> {
+        let transport = BlockingTransport::bind_broadcast(&SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+            DHCP_PORT_CLIENT,
+        ))?;
+        Self::from_transport(transport, client_hardware_address, client_id, hostname)
+    }
+
+    /// Wraps an already bound socket, e.g. one bound to a device.
+    ///
+    /// # Errors
+    /// `ClientError::Transport` if the socket options cannot be set.
+    pub fn from_socket(
+        socket: UdpSocket,
+        client_hardware_address: MacAddress,
+        client_id: Option<Vec<u8>>,
+        hostname: Option<String>,
+    ) -> Result<Self, Error> {
+        socket.set_broadcast(true)?;
+        let transport = BlockingTransport::new(socket)?;
+        Self::from_transport(transport, client_hardware_address, client_id, hostname)
+    }
+
+    fn from_transport(
+        transport: BlockingTransport,
+        client_hardware_address: MacAddress,
+        client_id: Option<Vec<u8>>,
+        hostname: Option<String>,
+    ) -> Result<Self, Error> {
+        let (sink, stream) = TransportFramed::new(transport).split();
+        let client = Client::new(
+            stream,
+            sink,
+            client_hardware_address,
+            client_id,
+            hostname,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        Ok(BlockingClient {
+            runtime: Runtime::new()?,
+            client,
+        })
+    }
+
+    /// The client state machine, e.g. to get the lease status.
+    pub fn get_ref(&self) -> &Inner {
+        &self.client
+    }
+
+    /// The client state machine to be configured, e.g. with the lease limits,
+    /// the authentication key, the identity file or the MUD URL.
+    pub fn get_mut(&mut self) -> &mut Inner {
+        &mut self.client
+    }
+
+    /// Blocks until the next client event or the `timeout`, returning `None` at the latter.
+    ///
+    /// The lease is renewed and rebound while the events are awaited,
+    /// so a bound client must keep calling it to keep the lease.
+    ///
+    /// # Errors
+    /// `ClientError::Transport` on a socket error or if the client has stopped,
+    /// `ClientError::Timeout` if the client has given up.
+    pub fn next_event(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+        let client = &mut self.client;
+        let mut timer = timeout.map(|timeout| Delay::new(Instant::now() + timeout));
+        let event = self.runtime.block_on(future::poll_fn(move || {
+            if let Async::Ready(event) = client.poll()? {
+                return match event {
+                    Some(event) => Ok(Async::Ready(Some(event))),
+                    None => Err(Error::Transport(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "The client has stopped",
+                    ))),
+                };
+            }
+            if let Some(ref mut timer) = timer {
+                match timer.poll() {
+                    Ok(Async::Ready(())) => return Ok(Async::Ready(None)),
+                    Ok(Async::NotReady) => {}
+                    Err(error) => panic!("Timer error: {}", error),
+                }
+            }
+            Ok(Async::NotReady)
+        }))?;
+        Ok(event)
+    }
+
+    /// Blocks until a lease is obtained or the `timeout`, returning `None` at the latter.
+    ///
+    /// The other events are skipped, so `next_event` must be used to handle them.
+    ///
+    /// # Errors
+    /// `ClientError::Transport` on a socket error or if the client has stopped,
+    /// `ClientError::Timeout` if the client has given up.
+    pub fn acquire(&mut self, timeout: Option<Duration>) -> Result<Option<Configuration>, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(None);
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+            match self.next_event(timeout)? {
+                Some(Event::Bound(configuration)) => return Ok(Some(configuration)),
+                Some(event) => debug!("Skipping the event {:?}", event),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Sends a `DHCPRELEASE`, `DHCPDECLINE` or `DHCPINFORM` and blocks until it is sent.
+    ///
+    /// # Errors
+    /// `ClientError::Transport` on a socket error, `ClientError::Codec` if the message
+    /// cannot be signed, `ClientError::NotBound` if there is nothing to release or decline.
+    pub fn command(&mut self, command: Command) -> Result<(), Error> {
+        self.runtime.block_on((&mut self.client).send(command))?;
+        Ok(())
+    }
+}
+
+/// Broadcasts a `DHCPDISCOVER` and collects the `DHCPOFFER`s arriving within the `wait`
+/// with the addresses they have come from, e.g. to find the servers of the network.
+///
+/// Nothing is requested afterwards, so the offered addresses are left to expire.
+///
+/// # Errors
+/// `ClientError::Transport` on a socket error.
+pub fn discover_offers(
+    socket: UdpSocket,
+    client_hardware_address: MacAddress,
+    wait: Duration,
+) -> Result<Vec<(SocketAddr, Message)>, Error> {
+    socket.set_broadcast(true)?;
+    let framed = TransportFramed::new(BlockingTransport::new(socket)?);
+
+    let client_id = client_hardware_address.as_bytes().to_vec();
+    let builder = MessageBuilder::new(client_hardware_address, client_id, None, None);
+    let discover = builder.discover(rand::random::<u32>(), true, None, None);
+    let transaction_id = discover.transaction_id;
+    let destination = SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255)),
+        DHCP_PORT_SERVER,
+    );
+    let mut framed = framed.send((destination, (discover, None))).wait()?;
+
+    let mut offers = Vec::new();
+    let deadline = Instant::now() + wait;
+    future::poll_fn(|| -> Poll<(), io::Error> {
+        loop {
+            if Instant::now() >= deadline {
+                return Ok(Async::Ready(()));
+            }
+            let (address, response) = match framed.poll()? {
+                Async::Ready(Some(received)) => received,
+                Async::Ready(None) => continue,
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            if response.transaction_id != transaction_id
+                || response.client_hardware_address != client_hardware_address
+            {
+                trace!("Skipped a foreign response {}", response);
+                continue;
+            }
+            match response.validate() {
+                Ok(MessageType::DhcpOffer) => {
+                    info!("Offered {} by {}", response.your_ip_address, address);
+                    offers.push((address, response));
+                }
+                Ok(_) => {}
+                Err(error) => warn!("The response from {} is invalid: {}", address, error),
+            }
+        }
+    }).wait()?;
+    Ok(offers)
+}
@@ -16,6 +16,12 @@ pub struct MessageBuilder {
     hostname: Option<String>,
     /// The optional maximum DHCP message size the client will accept.
     max_message_size: Option<u16>,
+    /// Whether the client requests the IPv6-only preferred option.
+    ipv6_only_preferred: bool,
+    /// The optional vendor class identifier.
+    class_id: Option<Vec<u8>>,
+    /// The optional Manufacturer Usage Description URL.
+    mud_url: Option<String>,
 }
 
 impl MessageBuilder {
@@ -31,9 +37,47 @@ impl MessageBuilder {
             client_id,
             hostname,
             max_message_size,
+            ipv6_only_preferred: false,
+            class_id: None,
+            mud_url: None,
         }
     }
 
+    /// The client hardware address the messages are built with.
+    pub fn client_hardware_address(&self) -> MacAddress {
+        self.client_hardware_address.to_owned()
+    }
+
+    /// The client identifier sent in the `client_id` option.
+    pub fn client_id(&self) -> Vec<u8> {
+        self.client_id.to_owned()
+    }
+
+    /// Replaces the client identifier, e.g. with a persistent one.
+    pub fn set_client_id(&mut self, client_id: Vec<u8>) {
+        self.client_id = client_id;
+    }
+
+    /// Makes the client request the IPv6-only preferred option (RFC 8925).
+    pub fn set_ipv6_only_preferred(&mut self, value: bool) {
+        self.ipv6_only_preferred = value;
+    }
+
+    /// The vendor class identifier sent in the `class_id` option.
+    pub fn class_id(&self) -> Option<&[u8]> {
+        self.class_id.as_ref().map(Vec::as_slice)
+    }
+
+    /// Sets the vendor class identifier.
+    pub fn set_class_id(&mut self, class_id: Option<Vec<u8>>) {
+        self.class_id = class_id;
+    }
+
+    /// Sets the MUD URL sent in `DHCPDISCOVER` and `DHCPREQUEST` messages.
+    pub fn set_mud_url(&mut self, mud_url: Option<String>) {
+        self.mud_url = mud_url;
+    }
+
     /// Creates a general `DHCPDISCOVER` message.
     pub fn discover(
         &self,
@@ -44,10 +88,11 @@ impl MessageBuilder {
     ) -> Message {
         let mut options = Options::default();
         self.append_default_options(&mut options);
+        options.mud_url = self.mud_url.to_owned();
 
         options.dhcp_message_type = Some(MessageType::DhcpDiscover);
         options.dhcp_max_message_size = self.max_message_size;
-        options.parameter_list = Some(Self::parameter_list());
+        options.parameter_list = Some(self.parameter_list());
         options.address_request = address_request;
         options.address_time = address_time;
 
@@ -85,11 +130,12 @@ impl MessageBuilder {
     ) -> Message {
         let mut options = Options::default();
         self.append_default_options(&mut options);
+        options.mud_url = self.mud_url.to_owned();
 
         options.dhcp_message_type = Some(MessageType::DhcpRequest);
         options.dhcp_max_message_size = self.max_message_size;
         options.dhcp_server_id = Some(dhcp_server_id);
-        options.parameter_list = Some(Self::parameter_list());
+        options.parameter_list = Some(self.parameter_list());
         options.address_request = Some(address_request);
         options.address_time = address_time;
 
@@ -126,10 +172,11 @@ impl MessageBuilder {
     ) -> Message {
         let mut options = Options::default();
         self.append_default_options(&mut options);
+        options.mud_url = self.mud_url.to_owned();
 
         options.dhcp_message_type = Some(MessageType::DhcpRequest);
         options.dhcp_max_message_size = self.max_message_size;
-        options.parameter_list = Some(Self::parameter_list());
+        options.parameter_list = Some(self.parameter_list());
         options.address_request = Some(address_request);
         options.address_time = address_time;
 
@@ -166,10 +213,11 @@ impl MessageBuilder {
     ) -> Message {
         let mut options = Options::default();
         self.append_default_options(&mut options);
+        options.mud_url = self.mud_url.to_owned();
 
         options.dhcp_message_type = Some(MessageType::DhcpRequest);
         options.dhcp_max_message_size = self.max_message_size;
-        options.parameter_list = Some(Self::parameter_list());
+        options.parameter_list = Some(self.parameter_list());
         options.address_time = address_time;
 
         Message {
@@ -207,7 +255,7 @@ impl MessageBuilder {
 
         options.dhcp_message_type = Some(MessageType::DhcpInform);
         options.dhcp_max_message_size = self.max_message_size;
-        options.parameter_list = Some(Self::parameter_list());
+        options.parameter_list = Some(self.parameter_list());
 
         Message {
             operation_code: OperationCode::BootRequest,
@@ -312,10 +360,11 @@ impl MessageBuilder {
     fn append_default_options(&self, options: &mut Options) {
         options.hostname = self.hostname.to_owned();
         options.client_id = Some(self.client_id.to_owned());
+        options.class_id = self.class_id.to_owned();
     }
 
-    fn parameter_list() -> Vec<u8> {
-        vec![
+    fn parameter_list(&self) -> Vec<u8> {
+        let mut parameter_list = vec![
             OptionTag::SubnetMask as u8,
             OptionTag::DomainNameServers as u8,
             /*
@@ -330,6 +379,18 @@ impl MessageBuilder {
             OptionTag::ClasslessStaticRoutes as u8,
             OptionTag::Routers as u8,
             OptionTag::StaticRoutes as u8,
-        ]
+            OptionTag::VendorSpecific as u8,
+            OptionTag::CaptivePortal as u8,
+            OptionTag::WebProxyAutoDiscovery as u8,
+        ];
+        /*
+        RFC 8925 §3.2
+        DHCPv4 clients that can operate in IPv6-only mode include the
+        IPv6-Only Preferred option code in the Parameter Request List.
+        */
+        if self.ipv6_only_preferred {
+            parameter_list.push(OptionTag::Ipv6OnlyPreferred as u8);
+        }
+        parameter_list
     }
 }
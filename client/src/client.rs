@@ -1,6 +1,10 @@
 //! The main DHCP client module.
 
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
 
 use eui48::MacAddress;
 use futures::StartSend;
@@ -9,8 +13,21 @@ use tokio::{io, prelude::*};
 
 use dhcp_protocol::{Message, MessageType, DHCP_PORT_SERVER};
 
+use auth::{AuthenticationKey, Authenticator};
 use builder::MessageBuilder;
-use state::{DhcpState, State};
+use error::Error;
+use fallback::{Fallback, FallbackProfile};
+use identity::Identity;
+use limits::{LeaseLimits, LimitPolicy};
+use link::{LinkEvent, LinkMonitor};
+use observer::Observer;
+use state::{DhcpState, LeaseStatus, State};
+use timing::Timing;
+use transport::Transport;
+use vendor::{VendorDecoders, VendorInfo};
+
+/// The default number of declined addresses after which the conflict is terminal.
+const DECLINE_LIMIT_DEFAULT: usize = 3;
 
 /// May be used to request stuff explicitly.
 struct RequestOptions {
@@ -30,6 +47,16 @@ pub struct Configuration {
     pub domain_name_servers: Option<Vec<Ipv4Addr>>,
     pub static_routes: Option<Vec<(Ipv4Addr, Ipv4Addr)>>,
     pub classless_static_routes: Option<Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>>,
+    /// The captive portal API URI (RFC 8910).
+    pub captive_portal: Option<String>,
+    /// The proxy auto-configuration file URL (WPAD).
+    pub web_proxy_auto_discovery: Option<String>,
+    /// The vendor class identifier the server has responded for.
+    pub class_id: Option<Vec<u8>>,
+    /// The raw vendor specific information.
+    pub vendor_specific: Option<Vec<u8>>,
+    /// The vendor specific information decoded by the decoder registered for the vendor class.
+    pub vendor_info: Option<VendorInfo>,
 }
 
 impl Configuration {
@@ -55,10 +82,54 @@ impl Configuration {
             domain_name_servers: response.options.domain_name_servers,
             static_routes: response.options.static_routes,
             classless_static_routes: response.options.classless_static_routes,
+            captive_portal: response.options.captive_portal,
+            web_proxy_auto_discovery: response.options.web_proxy_auto_discovery,
+            class_id: response.options.class_id,
+            vendor_specific: response.options.vendor_specific,
+            vendor_info: None,
         }
     }
 }
 
+/// The `Client` stream item type.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A lease has been obtained, renewed or rebound.
+    Bound(Configuration),
+    /// The network prefers the client to be IPv6-only (RFC 8925).
+    ///
+    /// Contains the `V6ONLY_WAIT` seconds sent by the server. The IPv4 configuration
+    /// is paused for this time, but not less than `MIN_V6ONLY_WAIT` (300 seconds).
+    Ipv6OnlyPreferred(u32),
+    /// No servers respond, so the static profile must be applied.
+    Fallback(FallbackProfile),
+    /// A lease has been obtained, so the static profile must be torn down.
+    ///
+    /// Is always followed by `Bound`.
+    FallbackRevoked(FallbackProfile),
+}
+
+/// The numbers of received packets discarded by the client.
+#[derive(Debug, Clone, Default)]
+pub struct Discarded {
+    /// Malformed or invalid messages.
+    pub invalid: u64,
+    /// Messages of other transactions, e.g. late retransmissions.
+    pub transaction_id: u64,
+    /// Messages addressed to other clients.
+    pub client_hardware_address: u64,
+    /// Messages from servers other than the leasing one while renewing.
+    pub dhcp_server_id: u64,
+    /// Duplicates of the responses which have been already processed.
+    pub duplicate: u64,
+    /// Messages of types unexpected in the current state.
+    pub message_type: u64,
+    /// Offers of the addresses declined by the client.
+    pub declined: u64,
+    /// Messages failed the RFC 3118 authentication.
+    pub authentication: u64,
+}
+
 /// The commands used for `Sink` to send `DHCPRELEASE`, `DHCPDECLINE` and `DHCPINFORM` messages.
 #[derive(Clone)]
 pub enum Command {
@@ -88,6 +159,34 @@ where
     builder: MessageBuilder,
     state: State,
     options: RequestOptions,
+    /// The persistent identity state file.
+    identity_file: Option<PathBuf>,
+    /// If the host is dual-stack and may operate in IPv6-only mode.
+    ipv6_only_preferred: bool,
+    /// The option 43 decoders.
+    vendor_decoders: VendorDecoders,
+    /// The acceptable lease times.
+    limits: LeaseLimits,
+    /// The static profile applied if no servers respond.
+    fallback: Option<Fallback>,
+    /// The events to yield before doing anything else.
+    pending: VecDeque<Event>,
+    /// The discarded packet counters.
+    discarded: Discarded,
+    /// The explicit broadcast flag overriding the transport capability.
+    broadcast: Option<bool>,
+    /// The addresses declined during the current acquisition.
+    declined: Vec<Ipv4Addr>,
+    /// The number of declined addresses after which the conflict is terminal.
+    decline_limit: usize,
+    /// The link carrier change source.
+    link_monitor: Option<Box<LinkMonitor>>,
+    /// If the link carrier has been lost.
+    is_link_down: bool,
+    /// If the missing `dhcp_server_id` is filled with the response source address.
+    is_server_id_lenient: bool,
+    /// Signs the requests and verifies the responses if set.
+    authenticator: Option<Authenticator>,
 }
 
 impl<I, O> Client<I, O>
@@ -143,6 +242,7 @@ where
     ///
     /// * `broadcast`
     /// If true, the client will ask DHCP server to use broadcasting.
+    /// If `None`, it is decided by the transport (see `with_transport`).
     ///
     pub fn new(
         stream: I,
@@ -155,7 +255,7 @@ where
         address_request: Option<Ipv4Addr>,
         address_time: Option<u32>,
         max_message_size: Option<u16>,
-        broadcast: bool,
+        broadcast: Option<bool>,
     ) -> Self {
         let hostname: Option<String> = if hostname.is_none() {
             hostname::get_hostname()
@@ -185,7 +285,8 @@ where
             None => DhcpState::Init,
         };
 
-        let state = State::new(dhcp_state, server_address, broadcast);
+        let is_broadcast = broadcast.unwrap_or(Transport::default().requires_broadcast());
+        let state = State::new(dhcp_state, server_address, is_broadcast);
 
         Client {
             stream,
@@ -193,6 +294,430 @@ where
             builder,
             state,
             options,
+            identity_file: None,
+            ipv6_only_preferred: false,
+            vendor_decoders: VendorDecoders::default(),
+            limits: LeaseLimits::default(),
+            fallback: None,
+            pending: VecDeque::new(),
+            discarded: Discarded::default(),
+            broadcast,
+            declined: Vec::new(),
+            decline_limit: DECLINE_LIMIT_DEFAULT,
+            link_monitor: None,
+            is_link_down: false,
+            is_server_id_lenient: false,
+            authenticator: None,
+        }
+    }
+
+    /// Makes the client identity persistent across restarts.
+    ///
+    /// The client identifier is loaded from the state file, or generated and saved
+    /// if the file does not exist yet. It overrides the `client_id` passed to `new`.
+    /// The transaction ID sequence is continued from the last saved one,
+    /// which is updated each time the client gets bound.
+    ///
+    /// # Errors
+    /// `ClientError::Identity` if the state file cannot be read or written.
+    pub fn with_identity_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self, Error> {
+        let identity = Identity::load_or_generate(
+            path.as_ref(),
+            &self.builder.client_hardware_address(),
+        ).map_err(Error::Identity)?;
+        self.builder.set_client_id(identity.client_id);
+        if let Some(transaction_id) = identity.transaction_id {
+            self.state.set_xid(transaction_id.wrapping_add(1));
+        }
+        self.identity_file = Some(path.as_ref().to_owned());
+        Ok(self)
+    }
+
+    /// Enables the IPv6-only preferred mode (RFC 8925).
+    ///
+    /// Set it if the host is configured dual-stack and can operate without IPv4.
+    /// If a server returns the IPv6-only preferred option, the client skips or releases
+    /// the IPv4 lease, yields `Event::Ipv6OnlyPreferred` and retries after `V6ONLY_WAIT`.
+    pub fn with_ipv6_only_preferred(&mut self, value: bool) -> &mut Self {
+        self.ipv6_only_preferred = value;
+        self.builder.set_ipv6_only_preferred(value);
+        self
+    }
+
+    /// Sets the static profile applied after the number of failed acquisition rounds.
+    ///
+    /// A round fails when no `DHCPOFFER` is received until the retransmission
+    /// timer expires. Without a fallback profile the client stops with a timeout
+    /// error instead. The profile is yielded as `Event::Fallback` and the client
+    /// keeps trying. Once a lease is obtained, `Event::FallbackRevoked` is yielded
+    /// right before `Event::Bound`.
+    pub fn with_fallback(&mut self, profile: FallbackProfile, rounds: u32) -> &mut Self {
+        self.fallback = Some(Fallback::new(profile, rounds));
+        self
+    }
+
+    /// Handles a failed acquisition round.
+    ///
+    /// # Errors
+    /// `Error::Timeout` if there is no fallback profile.
+    fn fail_round(&mut self) -> Result<Option<Event>, Error> {
+        match self.fallback {
+            Some(ref mut fallback) => {
+                let profile = fallback.fail();
+                if profile.is_some() {
+                    warn!("No DHCP servers respond, applying the fallback profile");
+                }
+                Ok(profile.map(Event::Fallback))
+            }
+            None => Err(Error::Timeout),
+        }
+    }
+
+    /// Makes the `Bound` event tearing down the fallback profile first if it is applied.
+    fn bound(&mut self, response: Message) -> Event {
+        let event = Event::Bound(self.configuration(response));
+        let revoked = match self.fallback {
+            Some(ref mut fallback) => fallback.succeed(),
+            None => None,
+        };
+        match revoked {
+            Some(profile) => {
+                info!("Got a lease, revoking the fallback profile");
+                self.pending.push_back(event);
+                Event::FallbackRevoked(profile)
+            }
+            None => event,
+        }
+    }
+
+    /// The numbers of received packets discarded so far.
+    pub fn discarded(&self) -> &Discarded {
+        &self.discarded
+    }
+
+    /// Checks if the response belongs to the current exchange of this client.
+    ///
+    /// Counts the discarded responses.
+    fn is_relevant(&mut self, current: DhcpState, response: &Message) -> bool {
+        if self.state.processed_xid() == Some(response.transaction_id) {
+            debug!(
+                "Got a duplicate of an already processed response {}",
+                response.transaction_id
+            );
+            self.discarded.duplicate += 1;
+            return false;
+        }
+        if response.transaction_id != self.state.xid() {
+            warn!(
+                "Got a response with wrong transaction ID: {} (yours is {})",
+                response.transaction_id,
+                self.state.xid()
+            );
+            self.discarded.transaction_id += 1;
+            return false;
+        }
+        if response.client_hardware_address != self.builder.client_hardware_address() {
+            warn!(
+                "Got a response for another client: {}",
+                response.client_hardware_address
+            );
+            self.discarded.client_hardware_address += 1;
+            return false;
+        }
+        /*
+        RFC 2131 §4.4.5
+        In RENEWING state, the client sends the DHCPREQUEST to the server
+        that originally issued the lease, so only its response is expected.
+        */
+        if let DhcpState::RenewingSent = current {
+            if response.options.dhcp_server_id != self.state.dhcp_server_id() {
+                warn!(
+                    "Got a response from a server other than the leasing one: {:?}",
+                    response.options.dhcp_server_id
+                );
+                self.discarded.dhcp_server_id += 1;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the current state, the assigned address and the lease timer moments.
+    ///
+    /// Supervisory code may use it to display the time remaining or schedule its own work.
+    pub fn lease_status(&self) -> LeaseStatus {
+        self.state.lease_status()
+    }
+
+    /// Starts renewing the lease at once as if T1 has come, e.g. to load a server.
+    ///
+    /// Does nothing unless the client is bound. The client must be polled afterwards.
+    pub fn renew(&mut self) {
+        self.state.expire_renewal();
+    }
+
+    /// Sets the transport the client socket is built on.
+    ///
+    /// The broadcast flag is chosen by the transport capability,
+    /// unless it has been explicitly set in `new`.
+    pub fn with_transport(&mut self, transport: Transport) -> &mut Self {
+        let is_broadcast = self
+            .broadcast
+            .unwrap_or_else(|| transport.requires_broadcast());
+        self.state.set_broadcast(is_broadcast);
+        self
+    }
+
+    /// Sets the number of addresses the client may decline before giving up.
+    ///
+    /// After a `Command::Decline` the client waits for ten seconds and restarts
+    /// the configuration process, ignoring the offers of the declined addresses.
+    /// Once the limit is exceeded, the client stream fails with `Error::DeclineLimit`.
+    pub fn with_decline_limit(&mut self, limit: usize) -> &mut Self {
+        self.decline_limit = limit;
+        self
+    }
+
+    /// Sets the link carrier change source.
+    ///
+    /// While the carrier is down, the client sends nothing and its timers are paused.
+    /// When the carrier is up again, the client confirms its lease in INIT-REBOOT state
+    /// or restarts the configuration process if it has no lease.
+    pub fn with_link_monitor(&mut self, monitor: Box<LinkMonitor>) -> &mut Self {
+        self.link_monitor = Some(monitor);
+        self
+    }
+
+    /// Handles the link carrier changes.
+    ///
+    /// # Errors
+    /// `Error::Link` if the link monitor fails.
+    fn poll_link(&mut self) -> Result<(), Error> {
+        loop {
+            let event = match self.link_monitor {
+                Some(ref mut monitor) => match monitor.poll().map_err(Error::Link)? {
+                    Async::Ready(Some(event)) => event,
+                    Async::Ready(None) => {
+                        warn!("The link monitor has stopped");
+                        self.is_link_down = false;
+                        break;
+                    }
+                    Async::NotReady => return Ok(()),
+                },
+                None => return Ok(()),
+            };
+
+            match event {
+                LinkEvent::Down => {
+                    info!("The link carrier is down, the client is paused");
+                    self.is_link_down = true;
+                }
+                LinkEvent::Up => {
+                    if self.is_link_down {
+                        info!("The link carrier is up, the client is resumed");
+                        self.is_link_down = false;
+                        self.reacquire();
+                    }
+                }
+            }
+        }
+        self.link_monitor = None;
+        Ok(())
+    }
+
+    /// Restarts the configuration process after the link carrier has come back.
+    fn reacquire(&mut self) {
+        match self.state.dhcp_state() {
+            DhcpState::Bound
+            | DhcpState::Renewing
+            | DhcpState::RenewingSent
+            | DhcpState::Rebinding
+            | DhcpState::RebindingSent => {
+                /*
+                RFC 2131 §3.2
+                If a client remembers and wishes to reuse a previously allocated
+                network address, a client may choose to omit some of the steps
+                described in the previous section.
+                */
+                self.options.address_request = Some(self.state.assigned_address());
+                self.state.restart(DhcpState::InitReboot);
+            }
+            DhcpState::InitReboot | DhcpState::Rebooting | DhcpState::RebootingSent => {
+                self.state.restart(DhcpState::InitReboot);
+            }
+            DhcpState::Selecting
+            | DhcpState::SelectingSent
+            | DhcpState::Requesting
+            | DhcpState::RequestingSent => {
+                self.state.restart(DhcpState::Init);
+            }
+            DhcpState::Init | DhcpState::Ipv6OnlyWaiting | DhcpState::DeclineWaiting => {}
+        }
+    }
+
+    /// Enables the RFC 3118 delayed authentication with the shared key.
+    ///
+    /// The outgoing messages are signed, and the offers and acknowledgements
+    /// without a valid MAC are discarded.
+    pub fn with_authentication(&mut self, key: AuthenticationKey) -> &mut Self {
+        self.authenticator = Some(Authenticator::new(key));
+        self
+    }
+
+    /// Checks the response authentication if it is enabled.
+    fn is_authentic(&mut self, response: &Message) -> bool {
+        let is_authentic = match self.authenticator {
+            Some(ref mut authenticator) => authenticator.verify(response),
+            None => true,
+        };
+        if !is_authentic {
+            self.discarded.authentication += 1;
+        }
+        is_authentic
+    }
+
+    /// Makes the client accept the responses without the `dhcp_server_id` option.
+    ///
+    /// Some embedded servers omit option 54, so the response source address is
+    /// used instead. It is wrong if the response is relayed, so the mode is disabled
+    /// by default and such responses are discarded as invalid.
+    pub fn with_lenient_server_id(&mut self, enabled: bool) -> &mut Self {
+        self.is_server_id_lenient = enabled;
+        self
+    }
+
+    /// Fills the missing `dhcp_server_id` with the source address in the lenient mode.
+    fn fill_dhcp_server_id(&self, source: &SocketAddr, response: &mut Message) {
+        if !self.is_server_id_lenient || response.options.dhcp_server_id.is_some() {
+            return;
+        }
+        if let IpAddr::V4(address) = source.ip() {
+            debug!("Using the source address {} as the server identifier", address);
+            response.options.dhcp_server_id = Some(address);
+        }
+    }
+
+    /// Registers an observer notified about the state transitions and messages.
+    pub fn with_observer(&mut self, observer: Box<Observer>) -> &mut Self {
+        self.state.add_observer(observer);
+        self
+    }
+
+    /// Sets the acceptable lease, renewal and rebinding times.
+    ///
+    /// The offers out of the limits are rejected or clamped according to the policy.
+    /// The acknowledged times are always clamped.
+    pub fn with_lease_limits(&mut self, limits: LeaseLimits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the retransmission timing parameters.
+    ///
+    /// Affects the timers started after the call, so it should be called before polling.
+    ///
+    /// # Errors
+    /// `ClientError::Timing` if the timers cannot work with the parameters.
+    pub fn with_timing(&mut self, timing: Timing) -> Result<&mut Self, Error> {
+        timing.validate()?;
+        self.state.set_timing(timing);
+        Ok(self)
+    }
+
+    /// Sets the Manufacturer Usage Description URL sent in `DHCPDISCOVER` and `DHCPREQUEST`.
+    ///
+    /// # Errors
+    /// `ClientError::MudUrl` if the URL does not use the `https` scheme
+    /// or does not fit into the option.
+    pub fn with_mud_url(&mut self, mud_url: String) -> Result<&mut Self, Error> {
+        // the MUD URLs are required to use the https scheme (RFC 8520)
+        if !mud_url.starts_with("https://") {
+            return Err(Error::MudUrl("The MUD URL must use the https scheme"));
+        }
+        if mud_url.len() > u8::max_value() as usize {
+            return Err(Error::MudUrl("The MUD URL is too long"));
+        }
+        self.builder.set_mud_url(Some(mud_url));
+        Ok(self)
+    }
+
+    /// Sets the vendor class identifier sent in the `class_id` option.
+    ///
+    /// It is also used to choose the vendor decoder if the server does not echo the class.
+    pub fn with_vendor_class(&mut self, class_id: Vec<u8>) -> &mut Self {
+        self.builder.set_class_id(Some(class_id));
+        self
+    }
+
+    /// Registers a decoder of the vendor specific information (option 43).
+    ///
+    /// The decoder is chosen by the vendor class identifier the server responded with,
+    /// or by the client's own one. The decoded value is put into `Configuration::vendor_info`.
+    pub fn with_vendor_decoder<F>(&mut self, class_id: Vec<u8>, decoder: F) -> &mut Self
+    where
+        F: Fn(&[u8]) -> Option<VendorInfo> + Send + Sync + 'static,
+    {
+        self.vendor_decoders.register(class_id, Box::new(decoder));
+        self
+    }
+
+    /// Makes the configuration from a `DHCPACK` decoding the vendor specific information.
+    fn configuration(&self, response: Message) -> Configuration {
+        let mut configuration = Configuration::from_response(response);
+        if let Some(ref data) = configuration.vendor_specific {
+            let class_id = match configuration.class_id {
+                Some(ref class_id) => Some(class_id.as_slice()),
+                None => self.builder.class_id(),
+            };
+            configuration.vendor_info = self.vendor_decoders.decode(class_id, data);
+        }
+        configuration
+    }
+
+    /// Handles the IPv6-only preferred option in a `DHCPACK` (RFC 8925).
+    ///
+    /// Returns the event to yield if the IPv4 configuration must be paused.
+    fn check_ipv6_only_ack(
+        &mut self,
+        current: DhcpState,
+        ack: &Message,
+    ) -> Result<Option<Event>, Error> {
+        if !self.ipv6_only_preferred {
+            return Ok(None);
+        }
+        let wait = match ack.options.ipv6_only_preferred {
+            Some(wait) => wait,
+            None => return Ok(None),
+        };
+
+        // the address has already been assigned, so it is given back to the server
+        let dhcp_server_id = expect!(ack.options.dhcp_server_id);
+        let release = self.builder.release(
+            self.state.xid(),
+            ack.your_ip_address,
+            dhcp_server_id,
+            Some("IPv6-only preferred".to_owned()),
+        );
+        let destination = SocketAddr::new(IpAddr::V4(dhcp_server_id), DHCP_PORT_SERVER);
+        log_send!(self.state, release, destination);
+        start_send!(self.sink, destination, (release, None));
+
+        info!("IPv6-only is preferred, IPv4 is paused for {} seconds", wait);
+        self.state
+            .transcend(current, DhcpState::Ipv6OnlyWaiting, Some(ack));
+        Ok(Some(Event::Ipv6OnlyPreferred(wait)))
+    }
+
+    /// Saves the current transaction ID to the identity state file if it is set.
+    fn save_identity(&self) {
+        if let Some(ref path) = self.identity_file {
+            let identity = Identity {
+                client_id: self.builder.client_id(),
+                transaction_id: Some(self.state.xid()),
+            };
+            if let Err(error) = identity.save(path) {
+                warn!("Identity saving error: {}", error);
+            }
         }
     }
 
@@ -223,9 +748,13 @@ where
     }
 
     /// Sends a request.
-    fn send_request(&mut self, request: Message) -> io::Result<()> {
+    fn send_request(&mut self, mut request: Message) -> Result<(), Error> {
+        if let Some(ref mut authenticator) = self.authenticator {
+            authenticator.sign(&mut request).map_err(Error::Codec)?;
+        }
+
         let destination = self.destination();
-        log_send!(request, destination);
+        log_send!(self.state, request, destination);
 
         let destination = SocketAddr::new(IpAddr::V4(destination), DHCP_PORT_SERVER);
         start_send!(self.sink, destination, (request, None));
@@ -238,10 +767,10 @@ where
     I: Stream<Item = DhcpStreamItem, Error = io::Error> + Send + Sync,
     O: Sink<SinkItem = DhcpSinkItem, SinkError = io::Error> + Send + Sync,
 {
-    type Item = Configuration;
-    type Error = io::Error;
+    type Item = Event;
+    type Error = Error;
 
-    /// Yields a `Configuration` after each configuration update.
+    /// Yields an `Event` after each configuration update.
     ///
     ///               The DHCP client lifecycle (RFC 2131)
     ///  --------                               -------
@@ -293,6 +822,15 @@ where
         loop {
             poll_complete!(self.sink);
 
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Async::Ready(Some(event)));
+            }
+
+            self.poll_link()?;
+            if self.is_link_down {
+                return Ok(Async::NotReady);
+            }
+
             match self.state.dhcp_state() {
                 current @ DhcpState::Init => {
                     /*
@@ -313,10 +851,14 @@ where
                     DhcpRequest broadcast message.
                     */
 
+                    let address_request = match self.options.address_request {
+                        Some(address) if self.declined.contains(&address) => None,
+                        address_request => address_request,
+                    };
                     let request = self.builder.discover(
                         self.state.xid(),
                         self.state.is_broadcast(),
-                        self.options.address_request,
+                        address_request,
                         self.options.address_time,
                     );
 
@@ -325,15 +867,27 @@ where
                         .transcend(current, DhcpState::SelectingSent, None);
                 }
                 current @ DhcpState::SelectingSent => {
-                    let (addr, response) = match self.stream.poll() {
+                    let (addr, mut response) = match self.stream.poll() {
                         Ok(Async::Ready(Some(data))) => data,
                         Ok(Async::Ready(None)) => {
                             warn!("Received an invalid packet");
+                            self.discarded.invalid += 1;
                             continue;
                         }
                         Ok(Async::NotReady) => {
-                            poll_backoff!(self.state.timer_offer);
-                            self.state.transcend(current, DhcpState::Selecting, None);
+                            let next = poll_backoff!(
+                                self.state.timer_offer,
+                                DhcpState::Selecting,
+                                DhcpState::Init
+                            );
+                            let event = match next {
+                                DhcpState::Init => self.fail_round()?,
+                                _ => None,
+                            };
+                            self.state.transcend(current, next, None);
+                            if let Some(event) = event {
+                                return Ok(Async::Ready(Some(event)));
+                            }
                             continue;
                         }
                         Err(error) => {
@@ -342,10 +896,57 @@ where
                         }
                     };
 
-                    let dhcp_message_type = validate!(response, addr);
-                    log_receive!(response, addr.ip());
-                    check_xid!(self.state.xid(), response.transaction_id);
-                    check_message_type!(dhcp_message_type, MessageType::DhcpOffer);
+                    self.fill_dhcp_server_id(&addr, &mut response);
+                    let dhcp_message_type = validate!(response, addr, self.discarded.invalid);
+                    log_receive!(self.state, response, addr.ip());
+                    if !self.is_relevant(current, &response) {
+                        continue;
+                    }
+                    if !self.is_authentic(&response) {
+                        continue;
+                    }
+                    check_message_type!(
+                        dhcp_message_type,
+                        MessageType::DhcpOffer,
+                        self.discarded.message_type
+                    );
+                    if self.declined.contains(&response.your_ip_address) {
+                        warn!(
+                            "Discarding an offer of the declined address {}",
+                            response.your_ip_address
+                        );
+                        self.discarded.declined += 1;
+                        continue;
+                    }
+
+                    /*
+                    RFC 8925 §3.2
+                    If the client receives a DHCPOFFER containing the IPv6-Only
+                    Preferred option, it MUST NOT request the offered address and
+                    SHOULD stop the DHCPv4 configuration process for V6ONLY_WAIT seconds.
+                    */
+                    if self.ipv6_only_preferred {
+                        if let Some(wait) = response.options.ipv6_only_preferred {
+                            info!("IPv6-only is preferred, IPv4 is paused for {} seconds", wait);
+                            self.state.transcend(
+                                current,
+                                DhcpState::Ipv6OnlyWaiting,
+                                Some(&response),
+                            );
+                            return Ok(Async::Ready(Some(Event::Ipv6OnlyPreferred(wait))));
+                        }
+                    }
+
+                    if !self.limits.is_acceptable(&response) {
+                        match self.limits.policy {
+                            LimitPolicy::Reject => {
+                                warn!("Discarding an offer with the lease times out of the limits");
+                                continue;
+                            }
+                            LimitPolicy::Clamp => self.limits.clamp(&mut response),
+                        }
+                    }
+
                     self.state
                         .transcend(current, DhcpState::Requesting, Some(&response));
                 }
@@ -369,10 +970,11 @@ where
                         .transcend(current, DhcpState::RequestingSent, None);
                 }
                 current @ DhcpState::RequestingSent => {
-                    let (addr, response) = match self.stream.poll() {
+                    let (addr, mut response) = match self.stream.poll() {
                         Ok(Async::Ready(Some(data))) => data,
                         Ok(Async::Ready(None)) => {
                             warn!("Received an invalid packet");
+                            self.discarded.invalid += 1;
                             continue;
                         }
                         Ok(Async::NotReady) => {
@@ -390,9 +992,15 @@ where
                         }
                     };
 
-                    let dhcp_message_type = validate!(response, addr);
-                    log_receive!(response, addr.ip());
-                    check_xid!(self.state.xid(), response.transaction_id);
+                    self.fill_dhcp_server_id(&addr, &mut response);
+                    let dhcp_message_type = validate!(response, addr, self.discarded.invalid);
+                    log_receive!(self.state, response, addr.ip());
+                    if !self.is_relevant(current, &response) {
+                        continue;
+                    }
+                    if !self.is_authentic(&response) {
+                        continue;
+                    }
 
                     match dhcp_message_type {
                         MessageType::DhcpNak => {
@@ -403,13 +1011,20 @@ where
                         MessageType::DhcpAck => {}
                         _ => {
                             warn!("Got an unexpected DHCP message type {}", dhcp_message_type);
+                            self.discarded.message_type += 1;
                             continue;
                         }
                     }
 
+                    if let Some(event) = self.check_ipv6_only_ack(current, &response)? {
+                        return Ok(Async::Ready(Some(event)));
+                    }
+
+                    self.limits.clamp(&mut response);
                     self.state
                         .transcend(current, DhcpState::Bound, Some(&response));
-                    return Ok(Async::Ready(Some(Configuration::from_response(response))));
+                    self.save_identity();
+                    return Ok(Async::Ready(Some(self.bound(response))));
                 }
 
                 current @ DhcpState::InitReboot => {
@@ -442,10 +1057,11 @@ where
                         .transcend(current, DhcpState::RebootingSent, None);
                 }
                 current @ DhcpState::RebootingSent => {
-                    let (addr, response) = match self.stream.poll() {
+                    let (addr, mut response) = match self.stream.poll() {
                         Ok(Async::Ready(Some(data))) => data,
                         Ok(Async::Ready(None)) => {
                             warn!("Received an invalid packet");
+                            self.discarded.invalid += 1;
                             continue;
                         }
                         Ok(Async::NotReady) => {
@@ -463,9 +1079,15 @@ where
                         }
                     };
 
-                    let dhcp_message_type = validate!(response, addr);
-                    log_receive!(response, addr.ip());
-                    check_xid!(self.state.xid(), response.transaction_id);
+                    self.fill_dhcp_server_id(&addr, &mut response);
+                    let dhcp_message_type = validate!(response, addr, self.discarded.invalid);
+                    log_receive!(self.state, response, addr.ip());
+                    if !self.is_relevant(current, &response) {
+                        continue;
+                    }
+                    if !self.is_authentic(&response) {
+                        continue;
+                    }
 
                     match dhcp_message_type {
                         MessageType::DhcpNak => {
@@ -476,13 +1098,20 @@ where
                         MessageType::DhcpAck => {}
                         _ => {
                             warn!("Got an unexpected DHCP message type {}", dhcp_message_type);
+                            self.discarded.message_type += 1;
                             continue;
                         }
                     }
 
+                    if let Some(event) = self.check_ipv6_only_ack(current, &response)? {
+                        return Ok(Async::Ready(Some(event)));
+                    }
+
+                    self.limits.clamp(&mut response);
                     self.state
                         .transcend(current, DhcpState::Bound, Some(&response));
-                    return Ok(Async::Ready(Some(Configuration::from_response(response))));
+                    self.save_identity();
+                    return Ok(Async::Ready(Some(self.bound(response))));
                 }
 
                 current @ DhcpState::Bound => {
@@ -498,6 +1127,8 @@ where
                     */
 
                     poll_delay!(self.state.timer_renewal);
+                    // the lease has survived until T1, so the acquisition is over
+                    self.declined.clear();
                     self.state.transcend(current, DhcpState::Renewing, None);
                 }
                 current @ DhcpState::Renewing => {
@@ -521,10 +1152,11 @@ where
                     self.state.transcend(current, DhcpState::RenewingSent, None);
                 }
                 current @ DhcpState::RenewingSent => {
-                    let (addr, response) = match self.stream.poll() {
+                    let (addr, mut response) = match self.stream.poll() {
                         Ok(Async::Ready(Some(data))) => data,
                         Ok(Async::Ready(None)) => {
                             warn!("Received an invalid packet");
+                            self.discarded.invalid += 1;
                             continue;
                         }
                         Ok(Async::NotReady) => {
@@ -542,14 +1174,26 @@ where
                         }
                     };
 
-                    let dhcp_message_type = validate!(response, addr);
-                    log_receive!(response, addr.ip());
-                    check_xid!(self.state.xid(), response.transaction_id);
-                    check_message_type!(dhcp_message_type, MessageType::DhcpAck);
+                    self.fill_dhcp_server_id(&addr, &mut response);
+                    let dhcp_message_type = validate!(response, addr, self.discarded.invalid);
+                    log_receive!(self.state, response, addr.ip());
+                    if !self.is_relevant(current, &response) {
+                        continue;
+                    }
+                    if !self.is_authentic(&response) {
+                        continue;
+                    }
+                    check_message_type!(
+                        dhcp_message_type,
+                        MessageType::DhcpAck,
+                        self.discarded.message_type
+                    );
 
+                    self.limits.clamp(&mut response);
                     self.state
                         .transcend(current, DhcpState::Bound, Some(&response));
-                    return Ok(Async::Ready(Some(Configuration::from_response(response))));
+                    self.save_identity();
+                    return Ok(Async::Ready(Some(self.bound(response))));
                 }
                 current @ DhcpState::Rebinding => {
                     /*
@@ -576,10 +1220,11 @@ where
                         .transcend(current, DhcpState::RebindingSent, None);
                 }
                 current @ DhcpState::RebindingSent => {
-                    let (addr, response) = match self.stream.poll() {
+                    let (addr, mut response) = match self.stream.poll() {
                         Ok(Async::Ready(Some(data))) => data,
                         Ok(Async::Ready(None)) => {
                             warn!("Received an invalid packet");
+                            self.discarded.invalid += 1;
                             continue;
                         }
                         Ok(Async::NotReady) => {
@@ -597,14 +1242,45 @@ where
                         }
                     };
 
-                    let dhcp_message_type = validate!(response, addr);
-                    log_receive!(response, addr.ip());
-                    check_xid!(self.state.xid(), response.transaction_id);
-                    check_message_type!(dhcp_message_type, MessageType::DhcpAck);
+                    self.fill_dhcp_server_id(&addr, &mut response);
+                    let dhcp_message_type = validate!(response, addr, self.discarded.invalid);
+                    log_receive!(self.state, response, addr.ip());
+                    if !self.is_relevant(current, &response) {
+                        continue;
+                    }
+                    if !self.is_authentic(&response) {
+                        continue;
+                    }
+                    check_message_type!(
+                        dhcp_message_type,
+                        MessageType::DhcpAck,
+                        self.discarded.message_type
+                    );
 
+                    self.limits.clamp(&mut response);
                     self.state
                         .transcend(current, DhcpState::Bound, Some(&response));
-                    return Ok(Async::Ready(Some(Configuration::from_response(response))));
+                    self.save_identity();
+                    return Ok(Async::Ready(Some(self.bound(response))));
+                }
+
+                current @ DhcpState::Ipv6OnlyWaiting => {
+                    /*
+                    RFC 8925 §3.2
+                    After V6ONLY_WAIT seconds the client restarts the DHCPv4
+                    configuration process from the INIT state.
+                    */
+
+                    poll_delay!(self.state.timer_ipv6_only);
+                    self.state.transcend(current, DhcpState::Init, None);
+                }
+                current @ DhcpState::DeclineWaiting => {
+                    if self.declined.len() > self.decline_limit {
+                        return Err(Error::DeclineLimit);
+                    }
+
+                    poll_delay!(self.state.timer_decline);
+                    self.state.transcend(current, DhcpState::Init, None);
                 }
             }
         }
@@ -617,23 +1293,18 @@ where
     O: Sink<SinkItem = DhcpSinkItem, SinkError = io::Error> + Send + Sync,
 {
     type SinkItem = Command;
-    type SinkError = io::Error;
+    type SinkError = Error;
 
     /// Translates a `Command` into a DHCP message and sends it to the user provided `Sink`.
     fn start_send(
         &mut self,
         command: Self::SinkItem,
     ) -> StartSend<Self::SinkItem, Self::SinkError> {
-        let (request, destination) = match command {
+        let (mut request, destination) = match command {
             Command::Release { ref message } => {
                 let dhcp_server_id = match self.state.dhcp_server_id() {
                     Some(dhcp_server_id) => dhcp_server_id,
-                    None => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::AddrNotAvailable,
-                            "Nothing to release",
-                        ))
-                    }
+                    None => return Err(Error::NotBound("Nothing to release")),
                 };
                 let destination = SocketAddr::new(IpAddr::V4(dhcp_server_id), DHCP_PORT_SERVER);
                 let request = self.builder.release(
@@ -650,12 +1321,7 @@ where
             } => {
                 let dhcp_server_id = match self.state.dhcp_server_id() {
                     Some(dhcp_server_id) => dhcp_server_id,
-                    None => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::AddrNotAvailable,
-                            "Nothing to decline",
-                        ))
-                    }
+                    None => return Err(Error::NotBound("Nothing to decline")),
                 };
                 let destination = SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255)),
@@ -684,17 +1350,36 @@ where
             }
         };
 
-        log_send!(request, destination);
+        if let Some(ref mut authenticator) = self.authenticator {
+            authenticator.sign(&mut request).map_err(Error::Codec)?;
+        }
+
+        log_send!(self.state, request, destination);
         match self.sink.start_send((destination, (request, None))) {
-            Ok(AsyncSink::Ready) => Ok(AsyncSink::Ready),
+            Ok(AsyncSink::Ready) => {
+                /*
+                RFC 2131 §3.1
+                If the client detects that the address is already in use (e.g.,
+                through the use of ARP), the client MUST send a DHCPDECLINE message
+                to the server and restarts the configuration process.
+                */
+                if let Command::Decline { address, .. } = command {
+                    if let current @ DhcpState::Bound = self.state.dhcp_state() {
+                        self.declined.push(address);
+                        self.state
+                            .transcend(current, DhcpState::DeclineWaiting, None);
+                    }
+                }
+                Ok(AsyncSink::Ready)
+            }
             Ok(AsyncSink::NotReady(_item)) => Ok(AsyncSink::NotReady(command)),
-            Err(error) => Err(error),
+            Err(error) => Err(Error::Transport(error)),
         }
     }
 
     /// Just a proxy.
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
-        self.sink.poll_complete()
+        self.sink.poll_complete().map_err(Error::Transport)
     }
 
     /// Just a proxy.
@@ -702,3 +1387,61 @@ where
         self.poll_complete()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{sink::SinkMapErr, stream::Empty};
+
+    type TestClient = Client<
+        Empty<DhcpStreamItem, io::Error>,
+        SinkMapErr<Vec<DhcpSinkItem>, fn(()) -> io::Error>,
+    >;
+
+    fn client() -> TestClient {
+        fn closed(_: ()) -> io::Error {
+            io::Error::new(io::ErrorKind::BrokenPipe, "The sink is closed")
+        }
+
+        Client::new(
+            stream::empty(),
+            Vec::new().sink_map_err(closed as fn(()) -> io::Error),
+            MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]),
+            None,
+            Some("client".to_owned()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn fails_commands_without_lease() {
+        let mut client = client();
+        match client.start_send(Command::Release { message: None }) {
+            Err(Error::NotBound(_)) => {}
+            result => panic!("Unexpected result {:?}", result.map(|_| ())),
+        }
+        let decline = Command::Decline {
+            address: Ipv4Addr::new(192, 168, 0, 10),
+            message: None,
+        };
+        match client.start_send(decline) {
+            Err(Error::NotBound(_)) => {}
+            result => panic!("Unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn times_out_without_fallback() {
+        let mut client = client();
+        match client.fail_round() {
+            Err(Error::Timeout) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
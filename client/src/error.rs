@@ -0,0 +1,66 @@
+//! The error type of the client.
+//!
+//! The causes are kept as the sources, so the whole chain can be reported.
+
+use std::{error, fmt, io};
+
+/// The client cannot be configured, set up or driven any further.
+#[derive(Debug)]
+pub enum Error {
+    /// The timers cannot work with the timing parameters.
+    Timing(&'static str),
+    /// The MUD URL cannot be sent.
+    MudUrl(&'static str),
+    /// The identity file cannot be read or written.
+    Identity(io::Error),
+    /// The socket, the stream or the sink of the client has failed.
+    Transport(io::Error),
+    /// A message cannot be encoded or signed.
+    Codec(io::Error),
+    /// The link monitor has failed.
+    Link(io::Error),
+    /// No server has responded and there is no fallback profile.
+    Timeout,
+    /// Too many offered addresses have been declined.
+    DeclineLimit,
+    /// There is no lease the command can be applied to.
+    NotBound(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Timing(reason) => write!(f, "Timing error: {}", reason),
+            Error::MudUrl(reason) => write!(f, "MUD URL error: {}", reason),
+            Error::Identity(_) => f.write_str("Identity file error"),
+            Error::Transport(_) => f.write_str("Client transport error"),
+            Error::Codec(_) => f.write_str("Message encoding error"),
+            Error::Link(_) => f.write_str("Link monitor error"),
+            Error::Timeout => f.write_str("No DHCP servers respond"),
+            Error::DeclineLimit => f.write_str("Too many declined addresses"),
+            Error::NotBound(reason) => write!(f, "Not bound: {}", reason),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Timing(_)
+            | Error::MudUrl(_)
+            | Error::Timeout
+            | Error::DeclineLimit
+            | Error::NotBound(_) => None,
+            Error::Identity(ref error)
+            | Error::Transport(ref error)
+            | Error::Codec(ref error)
+            | Error::Link(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Transport(error)
+    }
+}
@@ -0,0 +1,61 @@
+//! The static fallback profile module.
+
+use std::net::Ipv4Addr;
+
+/// The static configuration applied if no DHCP server responds.
+#[derive(Debug, Clone)]
+pub struct FallbackProfile {
+    pub address: Ipv4Addr,
+    pub subnet_mask: Ipv4Addr,
+    pub routers: Option<Vec<Ipv4Addr>>,
+    pub domain_name_servers: Option<Vec<Ipv4Addr>>,
+    pub classless_static_routes: Option<Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>>,
+}
+
+/// Tracks the failed acquisition rounds.
+pub struct Fallback {
+    /// The profile to apply.
+    profile: FallbackProfile,
+    /// The number of failed rounds after which the profile is applied.
+    rounds: u32,
+    /// The number of failed rounds since the last lease.
+    failures: u32,
+    /// If the profile is applied now.
+    is_applied: bool,
+}
+
+impl Fallback {
+    /// Creates a tracker with no failures.
+    pub fn new(profile: FallbackProfile, rounds: u32) -> Self {
+        Fallback {
+            profile,
+            rounds,
+            failures: 0,
+            is_applied: false,
+        }
+    }
+
+    /// Records a failed round.
+    ///
+    /// Returns the profile if it must be applied now.
+    pub fn fail(&mut self) -> Option<FallbackProfile> {
+        self.failures = self.failures.saturating_add(1);
+        if self.is_applied || self.failures < self.rounds {
+            return None;
+        }
+        self.is_applied = true;
+        Some(self.profile.to_owned())
+    }
+
+    /// Records an obtained lease.
+    ///
+    /// Returns the profile if it has been applied and must be torn down now.
+    pub fn succeed(&mut self) -> Option<FallbackProfile> {
+        self.failures = 0;
+        if !self.is_applied {
+            return None;
+        }
+        self.is_applied = false;
+        Some(self.profile.to_owned())
+    }
+}
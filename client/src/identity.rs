@@ -0,0 +1,177 @@
+//! The persistent client identity module.
+//!
+//! RFC 4361 §6.1
+//! DHCPv4 clients that conform to this specification MUST use stable
+//! DHCP node identifiers in the dhcp-client-identifier option.
+//! The DUID MUST be stable across restarts of the DHCP client.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use chrono::prelude::*;
+use eui48::MacAddress;
+
+/// The `DUID-LLT` type code (RFC 3315 §9.2).
+const DUID_TYPE_LLT: u16 = 1;
+/// The Ethernet hardware type code (RFC 826).
+const DUID_HARDWARE_TYPE_ETHERNET: u16 = 1;
+/// The `DUID-LLT` time base is midnight (UTC), January 1, 2000.
+const DUID_TIME_EPOCH: i64 = 946_684_800;
+/// The client identifier type denoting an `IAID` followed by a `DUID` (RFC 4361 §6.1).
+const CLIENT_ID_TYPE_DUID: u8 = 255;
+
+/// The state file key of the client identifier.
+const KEY_CLIENT_ID: &str = "client_id";
+/// The state file key of the last transaction ID.
+const KEY_TRANSACTION_ID: &str = "transaction_id";
+
+/// The client identity which must survive reboots.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// Sent in the `client_id` option.
+    pub client_id: Vec<u8>,
+    /// The last transaction ID used by the client.
+    pub transaction_id: Option<u32>,
+}
+
+impl Identity {
+    /// Generates a RFC 4361 client identifier with a `DUID-LLT` inside.
+    ///
+    /// The `DUID-LLT` contains the current time, so the identifier must be stored
+    /// and reused instead of being generated on each start.
+    pub fn generate(client_hardware_address: &MacAddress) -> Self {
+        let hwaddr = client_hardware_address.as_bytes();
+        let time = (Utc::now().timestamp() - DUID_TIME_EPOCH) as u32;
+
+        let mut client_id = Vec::with_capacity(1 + 4 + 8 + hwaddr.len());
+        client_id.push(CLIENT_ID_TYPE_DUID);
+        // the IAID is derived from the interface link-layer address
+        client_id.extend_from_slice(&hwaddr[hwaddr.len() - 4..]);
+        client_id.push((DUID_TYPE_LLT >> 8) as u8);
+        client_id.push(DUID_TYPE_LLT as u8);
+        client_id.push((DUID_HARDWARE_TYPE_ETHERNET >> 8) as u8);
+        client_id.push(DUID_HARDWARE_TYPE_ETHERNET as u8);
+        client_id.push((time >> 24) as u8);
+        client_id.push((time >> 16) as u8);
+        client_id.push((time >> 8) as u8);
+        client_id.push(time as u8);
+        client_id.extend_from_slice(hwaddr);
+
+        Identity {
+            client_id,
+            transaction_id: None,
+        }
+    }
+
+    /// Loads the identity from the state file.
+    ///
+    /// Returns `None` if the file does not exist.
+    ///
+    /// # Errors
+    /// `io::Error` if the file cannot be read or is malformed.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let mut file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+
+        let mut client_id = None;
+        let mut transaction_id = None;
+        for line in data.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut pair = line.splitn(2, '=');
+            let key = pair.next().unwrap_or_default().trim();
+            let value = pair.next().unwrap_or_default().trim();
+            match key {
+                KEY_CLIENT_ID => client_id = Some(hex_decode(value)?),
+                KEY_TRANSACTION_ID => {
+                    transaction_id = Some(value.parse::<u32>().map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidData, "Invalid transaction ID")
+                    })?)
+                }
+                _ => warn!("Unknown identity file key: {}", key),
+            }
+        }
+
+        match client_id {
+            Some(ref client_id) if !client_id.is_empty() => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The identity file does not contain a client ID",
+                ))
+            }
+        }
+
+        Ok(Some(Identity {
+            client_id: client_id.unwrap(),
+            transaction_id,
+        }))
+    }
+
+    /// Saves the identity to the state file.
+    ///
+    /// The data is written to a temporary file which is renamed afterwards,
+    /// so the state file is never left half-written.
+    ///
+    /// # Errors
+    /// `io::Error` if the file cannot be written.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        {
+            let mut file = fs::File::create(&temporary)?;
+            writeln!(file, "{}={}", KEY_CLIENT_ID, hex_encode(&self.client_id))?;
+            if let Some(transaction_id) = self.transaction_id {
+                writeln!(file, "{}={}", KEY_TRANSACTION_ID, transaction_id)?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&temporary, path)
+    }
+
+    /// Loads the identity from the state file or generates and saves a new one.
+    ///
+    /// # Errors
+    /// `io::Error` if the file cannot be read or written.
+    pub fn load_or_generate(path: &Path, client_hardware_address: &MacAddress) -> io::Result<Self> {
+        if let Some(identity) = Self::load(path)? {
+            trace!("Loaded the client identity from {}", path.display());
+            return Ok(identity);
+        }
+        let identity = Self::generate(client_hardware_address);
+        identity.save(path)?;
+        info!("Generated a new client identity at {}", path.display());
+        Ok(identity)
+    }
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(data: &str) -> io::Result<Vec<u8>> {
+    if data.len() % 2 != 0 || !data.is_ascii() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid hexadecimal string",
+        ));
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&data[i..i + 2], 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid hexadecimal"))
+        })
+        .collect()
+}
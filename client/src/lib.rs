@@ -2,16 +2,25 @@
 
 #[macro_use]
 mod macros;
-mod backoff;
+mod auth;
+pub mod blocking;
 mod builder;
 mod client;
-mod forthon;
+mod error;
+mod fallback;
+mod identity;
+mod limits;
+mod link;
+mod observer;
+pub mod simulate;
 mod state;
+mod timing;
+mod transport;
+mod vendor;
 
 #[macro_use]
 extern crate log;
 extern crate tokio;
-#[macro_use]
 extern crate futures;
 extern crate bytes;
 extern crate chrono;
@@ -19,7 +28,24 @@ extern crate eui48;
 extern crate hostname;
 extern crate rand;
 
+extern crate dhcp_backoff;
 extern crate dhcp_framed;
 extern crate dhcp_protocol;
 
-pub use self::client::{Client, Command, Configuration};
+pub use self::{
+    auth::AuthenticationKey,
+    client::{Client, Command, Configuration, Discarded, Event},
+    error::Error as ClientError,
+    fallback::FallbackProfile,
+    identity::Identity,
+    limits::{LeaseLimits, LimitPolicy},
+    link::{LinkEvent, LinkMonitor},
+    observer::{Context, Observer},
+    state::{DhcpState, LeaseStatus},
+    timing::Timing,
+    transport::Transport,
+    vendor::{VendorDecoder, VendorInfo},
+};
+
+#[cfg(target_os = "linux")]
+pub use self::link::CarrierPoller;
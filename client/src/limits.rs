@@ -0,0 +1,206 @@
+//! The lease time sanity limits module.
+
+use std::cmp;
+
+use dhcp_protocol::Message;
+
+/// What to do with a `DHCPOFFER` whose times are out of the limits.
+///
+/// The `DHCPACK` times are always clamped, since the lease is already committed by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitPolicy {
+    /// Discard the offer and wait for another one.
+    Reject,
+    /// Accept the offer with the times clamped to the limits.
+    Clamp,
+}
+
+/// The acceptable lease, renewal and rebinding times in seconds.
+///
+/// Protects devices from the 10-second or 100-year leases offered by buggy servers.
+#[derive(Debug, Clone)]
+pub struct LeaseLimits {
+    pub lease_time: (u32, u32),
+    pub renewal_time: (u32, u32),
+    pub rebinding_time: (u32, u32),
+    pub policy: LimitPolicy,
+}
+
+impl Default for LeaseLimits {
+    /// Accepts anything the server offers.
+    fn default() -> Self {
+        LeaseLimits {
+            lease_time: (0, u32::max_value()),
+            renewal_time: (0, u32::max_value()),
+            rebinding_time: (0, u32::max_value()),
+            policy: LimitPolicy::Clamp,
+        }
+    }
+}
+
+impl LeaseLimits {
+    /// Checks if all the times present in the message are within the limits.
+    pub fn is_acceptable(&self, message: &Message) -> bool {
+        fn within(value: Option<u32>, limits: (u32, u32)) -> bool {
+            value.map_or(true, |value| value >= limits.0 && value <= limits.1)
+        }
+
+        within(message.options.address_time, self.lease_time)
+            && within(message.options.renewal_time, self.renewal_time)
+            && within(message.options.rebinding_time, self.rebinding_time)
+    }
+
+    /// Clamps the times present in the message to the limits.
+    ///
+    /// The order of `T1 <= T2 <= lease time` is preserved after clamping.
+    pub fn clamp(&self, message: &mut Message) {
+        fn clamp(value: u32, limits: (u32, u32)) -> u32 {
+            cmp::min(cmp::max(value, limits.0), limits.1)
+        }
+
+        let options = &mut message.options;
+        if let Some(address_time) = options.address_time {
+            let clamped = clamp(address_time, self.lease_time);
+            if clamped != address_time {
+                warn!("Lease time {} is clamped to {}", address_time, clamped);
+            }
+            options.address_time = Some(clamped);
+        }
+        if let Some(rebinding_time) = options.rebinding_time {
+            let mut clamped = clamp(rebinding_time, self.rebinding_time);
+            if let Some(address_time) = options.address_time {
+                clamped = cmp::min(clamped, address_time);
+            }
+            if clamped != rebinding_time {
+                warn!("Rebinding time {} is clamped to {}", rebinding_time, clamped);
+            }
+            options.rebinding_time = Some(clamped);
+        }
+        if let Some(renewal_time) = options.renewal_time {
+            let mut clamped = clamp(renewal_time, self.renewal_time);
+            if let Some(limit) = options.rebinding_time.or(options.address_time) {
+                clamped = cmp::min(clamped, limit);
+            }
+            if clamped != renewal_time {
+                warn!("Renewal time {} is clamped to {}", renewal_time, clamped);
+            }
+            options.renewal_time = Some(clamped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use eui48::MacAddress;
+
+    use dhcp_protocol::*;
+
+    fn message(times: (Option<u32>, Option<u32>, Option<u32>)) -> Message {
+        let mut options = Options::default();
+        options.dhcp_message_type = Some(MessageType::DhcpOffer);
+        options.address_time = times.0;
+        options.renewal_time = times.1;
+        options.rebinding_time = times.2;
+
+        Message {
+            operation_code: OperationCode::BootReply,
+            hardware_type: HardwareType::Ethernet,
+            hardware_address_length: 6,
+            hardware_options: Default::default(),
+
+            transaction_id: 1,
+            seconds: Default::default(),
+            is_broadcast: false,
+
+            client_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            your_ip_address: Ipv4Addr::new(192, 168, 0, 2),
+            server_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            gateway_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+
+            client_hardware_address: MacAddress::new([0x00, 0x0c, 0x29, 0x13, 0x0e, 0x37]),
+            server_name: Default::default(),
+            boot_filename: Default::default(),
+
+            options,
+        }
+    }
+
+    fn limits() -> LeaseLimits {
+        LeaseLimits {
+            lease_time: (60, 86400),
+            renewal_time: (30, 43200),
+            rebinding_time: (50, 75600),
+            policy: LimitPolicy::Clamp,
+        }
+    }
+
+    #[test]
+    fn clamps_times() {
+        // (lease, T1, T2) offered and expected
+        let cases = [
+            (
+                (Some(3600), Some(1800), Some(3150)),
+                (Some(3600), Some(1800), Some(3150)),
+            ),
+            ((Some(10), None, None), (Some(60), None, None)),
+            (
+                (Some(u32::max_value()), None, None),
+                (Some(86400), None, None),
+            ),
+            (
+                (Some(3600), Some(5), Some(10)),
+                (Some(3600), Some(30), Some(50)),
+            ),
+            (
+                (Some(100), Some(200), Some(300)),
+                (Some(100), Some(100), Some(100)),
+            ),
+            ((Some(100), Some(200), None), (Some(100), Some(100), None)),
+            (
+                (None, Some(80000), Some(70000)),
+                (None, Some(43200), Some(70000)),
+            ),
+            ((None, Some(60000), None), (None, Some(43200), None)),
+            ((None, None, None), (None, None, None)),
+        ];
+        for &(offered, expected) in cases.iter() {
+            let mut message = message(offered);
+            limits().clamp(&mut message);
+            let options = &message.options;
+            assert_eq!(
+                (
+                    options.address_time,
+                    options.renewal_time,
+                    options.rebinding_time
+                ),
+                expected,
+                "{:?}",
+                offered
+            );
+        }
+    }
+
+    #[test]
+    fn checks_acceptable_times() {
+        let cases = [
+            ((Some(3600), Some(1800), Some(3150)), true),
+            ((None, None, None), true),
+            ((Some(59), None, None), false),
+            ((Some(86401), None, None), false),
+            ((Some(3600), Some(29), None), false),
+            ((Some(3600), None, Some(75601)), false),
+        ];
+        for &(offered, is_acceptable) in cases.iter() {
+            assert_eq!(
+                limits().is_acceptable(&message(offered)),
+                is_acceptable,
+                "{:?}",
+                offered
+            );
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! The link carrier monitoring module.
+
+#[cfg(target_os = "linux")]
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "linux")]
+use tokio::timer::Interval;
+use tokio::{io, prelude::*};
+
+/// The link carrier change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// The carrier has appeared, so the lease must be confirmed.
+    Up,
+    /// The carrier has been lost, so the timers are paused.
+    Down,
+}
+
+/// A source of the link carrier changes.
+///
+/// Implemented for any `Stream` of `LinkEvent`, so a netlink watcher
+/// or a platform specific notifier can be plugged in.
+pub trait LinkMonitor: Stream<Item = LinkEvent, Error = io::Error> + Send + Sync {}
+
+impl<S> LinkMonitor for S where S: Stream<Item = LinkEvent, Error = io::Error> + Send + Sync {}
+
+/// Polls the Linux `/sys/class/net/<interface>/carrier` file periodically.
+///
+/// The carrier is initially considered up, so `Down` is yielded first if it is not.
+#[cfg(target_os = "linux")]
+pub struct CarrierPoller {
+    /// The sysfs carrier file path.
+    path: String,
+    /// The last known carrier state.
+    is_up: bool,
+    /// The polling timer.
+    interval: Interval,
+}
+
+#[cfg(target_os = "linux")]
+impl CarrierPoller {
+    /// Constructs a poller and starts it.
+    ///
+    /// * `interface`
+    /// The interface name, e.g. `eth0`.
+    ///
+    /// * `period`
+    /// The carrier file reading period.
+    pub fn new(interface: &str, period: Duration) -> Self {
+        CarrierPoller {
+            path: format!("/sys/class/net/{}/carrier", interface),
+            is_up: true,
+            interval: Interval::new(Instant::now(), period),
+        }
+    }
+
+    /// The file cannot be read if the interface is administratively down.
+    fn read_carrier(&self) -> bool {
+        match ::std::fs::read_to_string(&self.path) {
+            Ok(value) => value.trim() == "1",
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Stream for CarrierPoller {
+    type Item = LinkEvent;
+    type Error = io::Error;
+
+    /// Yields only the carrier changes.
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.interval.poll() {
+                Ok(Async::Ready(Some(_))) => {}
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(error) => return Err(io::Error::new(io::ErrorKind::Other, error)),
+            }
+
+            let is_up = self.read_carrier();
+            if is_up != self.is_up {
+                self.is_up = is_up;
+                let event = if is_up {
+                    LinkEvent::Up
+                } else {
+                    LinkEvent::Down
+                };
+                return Ok(Async::Ready(Some(event)));
+            }
+        }
+    }
+}
@@ -7,19 +7,21 @@ macro_rules! expect (
     );
 );
 
-/// Just to move some code from the overwhelmed `poll` method.
+/// Logs the message and notifies the state observers.
 macro_rules! log_send(
-    ($message:expr, $destination:expr) => (
+    ($state:expr, $message:expr, $destination:expr) => (
         info!("Sending {} to {}", expect!($message.options.dhcp_message_type), $destination);
         debug!("{}", $message);
+        $state.notify_message(::dhcp_framed::Direction::Outbound, &$message);
     );
 );
 
-/// Just to move some code from the overwhelmed `poll` method.
+/// Logs the message and notifies the state observers.
 macro_rules! log_receive(
-    ($message:expr, $source:expr) => (
+    ($state:expr, $message:expr, $source:expr) => (
         info!("Received {} from {}", expect!($message.options.dhcp_message_type), $source);
         debug!("{}", $message);
+        $state.notify_message(::dhcp_framed::Direction::Inbound, &$message);
     );
 );
 
@@ -48,32 +50,24 @@ macro_rules! poll_complete (
 
 /// Just to move some code from the overwhelmed `poll` method.
 macro_rules! validate (
-    ($message:expr, $address:expr) => (
+    ($message:expr, $address:expr, $counter:expr) => (
         match $message.validate() {
             Ok(dhcp_message_type) => dhcp_message_type,
             Err(error) => {
                 warn!("The response from {} is invalid: {} {}", $address, error, $message);
+                $counter += 1;
                 continue;
             },
         };
     );
 );
 
-/// Just to move some code from the overwhelmed `poll` method.
-macro_rules! check_xid (
-    ($yours:expr, $response:expr) => (
-        if $response != $yours {
-            warn!("Got a response with wrong transaction ID: {} (yours is {})", $response, $yours);
-            continue;
-        }
-    );
-);
-
 /// Just to move some code from the overwhelmed `poll` method.
 macro_rules! check_message_type (
-    ($message:expr, $needed:pat) => (
+    ($message:expr, $needed:pat, $counter:expr) => (
         if let $needed = $message {} else {
             warn!("Got an unexpected DHCP message type {}", $message);
+            $counter += 1;
             continue;
         }
     );
@@ -99,10 +93,10 @@ macro_rules! poll_backoff (
     ($backoff:expr) => (
         if let Some(ref mut backoff) = $backoff {
             match backoff.poll() {
-                Ok(Async::Ready(Some((secs, expired)))) => {
-                    warn!("No responses after {} seconds", secs);
+                Ok(Async::Ready(Some((slept, expired)))) => {
+                    warn!("No responses after {} seconds", slept.as_secs());
                     if expired {
-                        return Err(io::Error::new(io::ErrorKind::TimedOut, "Timeout"));
+                        return Err(::error::Error::Timeout);
                     }
                 },
                 Ok(Async::Ready(None)) => panic!("Timer returned None"),
@@ -116,8 +110,8 @@ macro_rules! poll_backoff (
     ($backoff:expr, $revert:expr, $restart:expr) => (
         if let Some(ref mut backoff) = $backoff {
             match backoff.poll() {
-                Ok(Async::Ready(Some((secs, expired)))) => {
-                    warn!("No responses after {} seconds", secs);
+                Ok(Async::Ready(Some((slept, expired)))) => {
+                    warn!("No responses after {} seconds", slept.as_secs());
                     if expired {
                         $restart
                     } else {
@@ -139,8 +133,8 @@ macro_rules! poll_forthon (
     ($forthon:expr, $revert:expr, $restart:expr) => (
         if let Some(ref mut forthon) = $forthon {
             match forthon.poll() {
-                Ok(Async::Ready(Some((secs, expired)))) => {
-                    warn!("No responses after {} seconds", secs);
+                Ok(Async::Ready(Some((slept, expired)))) => {
+                    warn!("No responses after {} seconds", slept.as_secs());
                     if expired {
                         $restart
                     } else {
@@ -0,0 +1,32 @@
+//! The state machine observer module.
+
+use std::net::Ipv4Addr;
+
+use dhcp_framed::Direction;
+use dhcp_protocol::Message;
+
+use state::DhcpState;
+
+/// The client data at the moment of a state transition.
+pub struct Context<'a> {
+    /// The current transaction ID.
+    pub transaction_id: u32,
+    /// The address of the selected or leasing server.
+    pub dhcp_server_id: Option<Ipv4Addr>,
+    /// The address assigned to the client, if it is bound.
+    pub assigned_address: Ipv4Addr,
+    /// The response which has caused the transition.
+    pub response: Option<&'a Message>,
+}
+
+/// Is notified about the client state transitions and messages.
+///
+/// May be used for custom logging, metrics or policy enforcement
+/// without changing the state machine. Both hooks do nothing by default.
+pub trait Observer: Send + Sync {
+    /// Called after the client has moved from one state to another.
+    fn on_transition(&self, _from: DhcpState, _to: DhcpState, _context: &Context) {}
+
+    /// Called for each message sent or received and successfully validated.
+    fn on_message(&self, _direction: Direction, _message: &Message) {}
+}
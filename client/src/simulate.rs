@@ -0,0 +1,554 @@
+//! The load-testing simulation module.
+//!
+//! Runs a number of independent virtual clients over a single shared socket
+//! in the manner of `dhcperf`. Each virtual client has its own state machine
+//! and a synthetic hardware address the server responses are demultiplexed by.
+//!
+//! The `DHCPOFFER` and `DHCPACK` latencies are measured from the messages passing
+//! the shared socket, and the retransmissions tell how many messages have been dropped.
+//!
+//! The shared socket is a plain UDP one rather than a raw socket, so the responses
+//! addressed to the virtual clients reach the simulation only if they are broadcast.
+//! That is why the clients always set the broadcast flag. The unicast responses are
+//! not covered, e.g. the renewal `DHCPACK`s the servers send to the leased addresses,
+//! so the renewing clients are only acknowledged by the servers broadcasting them.
+
+use std::{
+    cmp,
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use eui48::MacAddress;
+use futures::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    StartSend,
+};
+use tokio::{
+    io,
+    prelude::*,
+    timer::{Delay, Interval},
+};
+
+use dhcp_protocol::{Message, MessageType};
+
+use client::{Client, Event};
+
+type DhcpStreamItem = (SocketAddr, Message);
+type DhcpSinkItem = (SocketAddr, (Message, Option<u16>));
+
+/// The first byte of the synthetic hardware addresses (locally administered, unicast).
+const SYNTHETIC_MAC_PREFIX: u8 = 0x02;
+/// The broadcast flag of the virtual clients, since only the broadcast responses reach the shared socket.
+const BROADCAST: bool = true;
+
+/// The virtual client socket `Stream` part fed by the simulation.
+pub struct VirtualStream(UnboundedReceiver<DhcpStreamItem>);
+
+impl Stream for VirtualStream {
+    type Item = DhcpStreamItem;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.0
+            .poll()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "The simulation is stopped"))
+    }
+}
+
+/// The virtual client socket `Sink` part drained by the simulation.
+pub struct VirtualSink(UnboundedSender<DhcpSinkItem>);
+
+impl Sink for VirtualSink {
+    type SinkItem = DhcpSinkItem;
+    type SinkError = io::Error;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.0
+            .unbounded_send(item)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "The simulation is stopped"))?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.poll_complete()
+    }
+}
+
+/// A client running inside the simulation.
+struct VirtualClient {
+    client: Client<VirtualStream, VirtualSink>,
+    /// The synthetic hardware address.
+    client_hardware_address: MacAddress,
+    /// When the client was started.
+    started: Instant,
+    /// If the client has obtained a lease.
+    is_bound: bool,
+    /// If the client has been asked to renew its lease and has not been acknowledged yet.
+    is_renewing: bool,
+}
+
+/// The requests of a virtual client the response latencies are measured from.
+#[derive(Default)]
+struct Transaction {
+    /// The transaction ID and the message type of the last request.
+    last_sent: Option<(u32, u8)>,
+    /// When the `DHCPDISCOVER` waiting for a `DHCPOFFER` has been sent.
+    discover_sent: Option<Instant>,
+    /// When the `DHCPREQUEST` waiting for a `DHCPACK` has been sent.
+    request_sent: Option<Instant>,
+}
+
+/// The simulation results.
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    /// The number of started virtual clients.
+    pub started: usize,
+    /// The number of clients which have obtained a lease.
+    pub bound: usize,
+    /// The number of clients stopped by an error.
+    pub failed: usize,
+    /// The number of received messages addressed to unknown hardware addresses.
+    pub discarded: usize,
+    /// The number of clients neither bound nor failed, including the ones not started,
+    /// when the simulation duration has passed.
+    pub unfinished: usize,
+    /// The number of clients asked to renew their leases once bound.
+    pub renewing: usize,
+    /// The number of the acknowledged renewals.
+    pub renewed: usize,
+    /// The number of received `DHCPNAK` messages.
+    pub naks: usize,
+    /// The number of retransmitted requests, i.e. the requests or responses dropped on the way.
+    pub retransmitted: usize,
+    /// The time between a client start and its first lease.
+    pub latencies: Vec<Duration>,
+    /// The time between a `DHCPDISCOVER` and the first `DHCPOFFER` for it.
+    pub offer_latencies: Vec<Duration>,
+    /// The time between a `DHCPREQUEST` and the `DHCPACK` for it, the renewals included.
+    pub ack_latencies: Vec<Duration>,
+}
+
+impl Statistics {
+    /// The minimal lease acquisition latency.
+    pub fn min(&self) -> Option<Duration> {
+        self.latencies.iter().min().cloned()
+    }
+
+    /// The maximal lease acquisition latency.
+    pub fn max(&self) -> Option<Duration> {
+        self.latencies.iter().max().cloned()
+    }
+
+    /// The mean lease acquisition latency.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let total = self
+            .latencies
+            .iter()
+            .fold(Duration::default(), |total, latency| total + *latency);
+        Some(total / self.latencies.len() as u32)
+    }
+
+    /// The lease acquisition latency percentile.
+    ///
+    /// * `percentile`
+    /// The value from 0 to 100.
+    ///
+    pub fn percentile(&self, percentile: u8) -> Option<Duration> {
+        nth_percentile(&self.latencies, percentile)
+    }
+
+    /// The `DHCPOFFER` latency percentile from 0 to 100.
+    pub fn offer_percentile(&self, percentile: u8) -> Option<Duration> {
+        nth_percentile(&self.offer_latencies, percentile)
+    }
+
+    /// The `DHCPACK` latency percentile from 0 to 100.
+    pub fn ack_percentile(&self, percentile: u8) -> Option<Duration> {
+        nth_percentile(&self.ack_latencies, percentile)
+    }
+}
+
+/// The future running the virtual clients until all of them are bound or failed.
+pub struct Simulation<I, O>
+where
+    I: Stream<Item = DhcpStreamItem, Error = io::Error> + Send + Sync,
+    O: Sink<SinkItem = DhcpSinkItem, SinkError = io::Error> + Send + Sync,
+{
+    stream: I,
+    sink: O,
+    /// The DHCP server address the clients are given.
+    server_address: Option<Ipv4Addr>,
+    /// The total number of virtual clients.
+    count: usize,
+    /// Starts a client on each tick.
+    interval: Interval,
+    /// The share of the bound clients renewing their leases at once.
+    renew_ratio: f64,
+    /// Stops the simulation if set.
+    deadline: Option<Delay>,
+    clients: Vec<VirtualClient>,
+    /// The inbound channels by the client hardware addresses.
+    inbound: HashMap<MacAddress, UnboundedSender<DhcpStreamItem>>,
+    /// The latency measurements by the client hardware addresses.
+    transactions: HashMap<MacAddress, Transaction>,
+    /// Cloned into each client sink.
+    outbound_tx: UnboundedSender<DhcpSinkItem>,
+    /// Merges all the client messages.
+    outbound_rx: UnboundedReceiver<DhcpSinkItem>,
+    /// The message the shared sink has not accepted yet.
+    pending: Option<DhcpSinkItem>,
+    statistics: Statistics,
+}
+
+impl<I, O> Simulation<I, O>
+where
+    I: Stream<Item = DhcpStreamItem, Error = io::Error> + Send + Sync,
+    O: Sink<SinkItem = DhcpSinkItem, SinkError = io::Error> + Send + Sync,
+{
+    /// Creates a simulation future.
+    ///
+    /// * `stream`
+    /// The shared socket `Stream` part.
+    ///
+    /// * `sink`
+    /// The shared socket `Sink` part.
+    ///
+    /// * `count`
+    /// The number of virtual clients.
+    ///
+    /// * `rate`
+    /// The number of clients started per second.
+    ///
+    /// * `server_address`
+    /// The DHCP server address.
+    /// If set, the clients communicate with the server using unicast.
+    ///
+    /// The clients always ask the server to broadcast responses, since the virtual
+    /// addresses are never assigned to the host interface.
+    pub fn new(
+        stream: I,
+        sink: O,
+        count: usize,
+        rate: u32,
+        server_address: Option<Ipv4Addr>,
+    ) -> Self {
+        let period = Duration::from_secs(1) / cmp::max(rate, 1);
+        let (outbound_tx, outbound_rx) = mpsc::unbounded();
+
+        Simulation {
+            stream,
+            sink,
+            server_address,
+            count,
+            interval: Interval::new(Instant::now(), period),
+            renew_ratio: 0.0,
+            deadline: None,
+            clients: Vec::with_capacity(count),
+            inbound: HashMap::with_capacity(count),
+            transactions: HashMap::with_capacity(count),
+            outbound_tx,
+            outbound_rx,
+            pending: None,
+            statistics: Statistics::default(),
+        }
+    }
+
+    /// Makes the share of the bound clients from 0 to 1 renew their leases at once,
+    /// so the server handles the renewals as well. No client renews by default.
+    ///
+    /// The renewal responses are usually unicast to the leased addresses and never
+    /// reach the shared socket, so a duration should be set to stop the simulation.
+    pub fn with_renew_ratio(&mut self, ratio: f64) -> &mut Self {
+        self.renew_ratio = ratio;
+        self
+    }
+
+    /// Stops the simulation after the `duration` at the latest,
+    /// counting the clients neither bound nor failed as unfinished.
+    pub fn with_duration(&mut self, duration: Duration) -> &mut Self {
+        self.deadline = Some(Delay::new(Instant::now() + duration));
+        self
+    }
+
+    /// The synthetic hardware address of the client with the index.
+    fn synthetic_mac(index: usize) -> MacAddress {
+        MacAddress::new([
+            SYNTHETIC_MAC_PREFIX,
+            0x00,
+            (index >> 24) as u8,
+            (index >> 16) as u8,
+            (index >> 8) as u8,
+            index as u8,
+        ])
+    }
+
+    /// Starts the clients scheduled by the interval timer.
+    fn poll_start(&mut self) -> io::Result<()> {
+        while self.statistics.started < self.count {
+            match self.interval.poll() {
+                Ok(Async::Ready(Some(_))) => {}
+                Ok(_) => break,
+                Err(error) => return Err(io::Error::new(io::ErrorKind::Other, error)),
+            }
+
+            let client_hardware_address = Self::synthetic_mac(self.statistics.started);
+            let (inbound_tx, inbound_rx) = mpsc::unbounded();
+            self.inbound.insert(client_hardware_address, inbound_tx);
+            self.transactions
+                .insert(client_hardware_address, Transaction::default());
+
+            let client = Client::new(
+                VirtualStream(inbound_rx),
+                VirtualSink(self.outbound_tx.clone()),
+                client_hardware_address,
+                None,
+                None,
+                self.server_address,
+                None,
+                None,
+                None,
+                None,
+                Some(BROADCAST),
+            );
+            self.clients.push(VirtualClient {
+                client,
+                client_hardware_address,
+                started: Instant::now(),
+                is_bound: false,
+                is_renewing: false,
+            });
+            self.statistics.started += 1;
+            trace!("Virtual client {} started", client_hardware_address);
+        }
+        Ok(())
+    }
+
+    /// Dispatches the received messages to the clients by the hardware address.
+    fn poll_receive(&mut self) -> io::Result<()> {
+        loop {
+            let (addr, message) = match self.stream.poll()? {
+                Async::Ready(Some(item)) => item,
+                // the framed socket yields `None` on a message parsing error
+                Async::Ready(None) => continue,
+                Async::NotReady => return Ok(()),
+            };
+            if let Some(transaction) = self
+                .transactions
+                .get_mut(&message.client_hardware_address)
+            {
+                record_received(transaction, &mut self.statistics, &message);
+            }
+            match self.inbound.get(&message.client_hardware_address) {
+                Some(channel) => {
+                    if channel.unbounded_send((addr, message)).is_err() {
+                        self.statistics.discarded += 1;
+                    }
+                }
+                None => self.statistics.discarded += 1,
+            }
+        }
+    }
+
+    /// Drives the clients and collects their statistics.
+    fn poll_clients(&mut self) {
+        let renew_ratio = self.renew_ratio;
+        let statistics = &mut self.statistics;
+        let mut index = 0;
+        while index < self.clients.len() {
+            let mut is_stopped = false;
+            {
+                let virtual_client = &mut self.clients[index];
+                loop {
+                    match virtual_client.client.poll() {
+                        Ok(Async::Ready(Some(Event::Bound(_)))) => {
+                            if !virtual_client.is_bound {
+                                virtual_client.is_bound = true;
+                                statistics.bound += 1;
+                                statistics
+                                    .latencies
+                                    .push(virtual_client.started.elapsed());
+                                if (statistics.renewing as f64)
+                                    < renew_ratio * statistics.bound as f64
+                                {
+                                    virtual_client.is_renewing = true;
+                                    statistics.renewing += 1;
+                                    virtual_client.client.renew();
+                                }
+                            } else if virtual_client.is_renewing {
+                                virtual_client.is_renewing = false;
+                                statistics.renewed += 1;
+                            }
+                        }
+                        Ok(Async::Ready(Some(_))) => {}
+                        Ok(Async::Ready(None)) => {
+                            warn!(
+                                "Virtual client {} has stopped",
+                                virtual_client.client_hardware_address
+                            );
+                            statistics.failed += 1;
+                            is_stopped = true;
+                            break;
+                        }
+                        Ok(Async::NotReady) => break,
+                        Err(error) => {
+                            warn!("Virtual client error: {}", error);
+                            statistics.failed += 1;
+                            is_stopped = true;
+                            break;
+                        }
+                    }
+                }
+            }
+            if is_stopped {
+                let virtual_client = self.clients.swap_remove(index);
+                self.inbound.remove(&virtual_client.client_hardware_address);
+                self.transactions
+                    .remove(&virtual_client.client_hardware_address);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Sends the client messages through the shared socket.
+    fn poll_send(&mut self) -> io::Result<()> {
+        loop {
+            let item = match self.pending.take() {
+                Some(item) => item,
+                None => match self.outbound_rx.poll() {
+                    Ok(Async::Ready(Some(item))) => {
+                        {
+                            let message = &(item.1).0;
+                            if let Some(transaction) =
+                                self.transactions.get_mut(&message.client_hardware_address)
+                            {
+                                record_sent(transaction, &mut self.statistics, message);
+                            }
+                        }
+                        item
+                    }
+                    _ => break,
+                },
+            };
+            if let AsyncSink::NotReady(item) = self.sink.start_send(item)? {
+                self.pending = Some(item);
+                break;
+            }
+        }
+        self.sink.poll_complete()?;
+        Ok(())
+    }
+}
+
+impl<I, O> Future for Simulation<I, O>
+where
+    I: Stream<Item = DhcpStreamItem, Error = io::Error> + Send + Sync,
+    O: Sink<SinkItem = DhcpSinkItem, SinkError = io::Error> + Send + Sync,
+{
+    type Item = Statistics;
+    type Error = io::Error;
+
+    /// Resolves with the statistics when all the clients are either bound or failed
+    /// and the renewals are over, or when the duration has passed.
+    ///
+    /// The bound clients keep running until then, so the server sees the renewals as well.
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.poll_start()?;
+        self.poll_receive()?;
+        self.poll_clients();
+        self.poll_send()?;
+
+        let is_expired = match self.deadline {
+            Some(ref mut deadline) => match deadline.poll() {
+                Ok(Async::Ready(())) => true,
+                Ok(Async::NotReady) => false,
+                Err(error) => return Err(io::Error::new(io::ErrorKind::Other, error)),
+            },
+            None => false,
+        };
+        let is_finished = self.statistics.started == self.count
+            && self.statistics.bound + self.statistics.failed >= self.count
+            && !self.clients.iter().any(|client| client.is_renewing);
+        if is_finished || is_expired {
+            self.statistics.unfinished = self
+                .count
+                .saturating_sub(self.statistics.bound + self.statistics.failed);
+            info!(
+                "Simulation finished: {} bound, {} failed, mean latency {:?}",
+                self.statistics.bound,
+                self.statistics.failed,
+                self.statistics.mean(),
+            );
+            return Ok(Async::Ready(self.statistics.to_owned()));
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+/// Records the request sent by the client, counting the retransmissions.
+fn record_sent(transaction: &mut Transaction, statistics: &mut Statistics, message: &Message) {
+    let message_type = match message.options.dhcp_message_type {
+        Some(message_type) => message_type,
+        None => return,
+    };
+    let key = (message.transaction_id, message_type as u8);
+    if transaction.last_sent == Some(key) {
+        statistics.retransmitted += 1;
+        return;
+    }
+    transaction.last_sent = Some(key);
+    match message_type {
+        MessageType::DhcpDiscover => transaction.discover_sent = Some(Instant::now()),
+        MessageType::DhcpRequest => transaction.request_sent = Some(Instant::now()),
+        _ => {}
+    }
+}
+
+/// Records the latency of the response to the client.
+fn record_received(
+    transaction: &mut Transaction,
+    statistics: &mut Statistics,
+    message: &Message,
+) {
+    match message.options.dhcp_message_type {
+        Some(MessageType::DhcpOffer) => {
+            if let Some(sent) = transaction.discover_sent.take() {
+                statistics.offer_latencies.push(sent.elapsed());
+                transaction.last_sent = None;
+            }
+        }
+        Some(MessageType::DhcpAck) => {
+            if let Some(sent) = transaction.request_sent.take() {
+                statistics.ack_latencies.push(sent.elapsed());
+                transaction.last_sent = None;
+            }
+        }
+        Some(MessageType::DhcpNak) => {
+            statistics.naks += 1;
+            transaction.request_sent = None;
+            transaction.last_sent = None;
+        }
+        _ => {}
+    }
+}
+
+/// The percentile from 0 to 100 of the latencies.
+fn nth_percentile(latencies: &[Duration], percentile: u8) -> Option<Duration> {
+    if latencies.is_empty() {
+        return None;
+    }
+    let mut latencies = latencies.to_owned();
+    latencies.sort();
+    let percentile = cmp::min(percentile, 100) as usize;
+    let index = (latencies.len() - 1) * percentile / 100;
+    Some(latencies[index])
+}
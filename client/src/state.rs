@@ -1,6 +1,7 @@
 //! The DHCP client state module.
 
 use std::{
+    cmp,
     fmt,
     net::Ipv4Addr,
     time::{Duration, Instant},
@@ -10,27 +11,35 @@ use chrono::prelude::*;
 use rand;
 use tokio::timer::Delay;
 
+use dhcp_backoff::{Backoff, Exponential, Forthon};
+use dhcp_framed::Direction;
 use dhcp_protocol::Message;
 
-use backoff::Backoff;
-use forthon::Forthon;
+use observer::{Context, Observer};
+use timing::Timing;
 
-/// Initial timeout in seconds for the BEB timers.
-const BACKOFF_TIMEOUT_INITIAL: u64 = 4;
-/// Maximum timeout in seconds for the BEB timers.
-const BACKOFF_TIMEOUT_MAXIMUM: u64 = 64;
-/// Minimal stimeout in seconds for the BEF™ timers.
-const FORTHON_TIMEOUT_MINIMAL: u64 = 60;
 /// Is used if a server does not provide the `renewal_time` option.
 const RENEWAL_TIME_FACTOR: f64 = 0.5;
 /// Is used if a server does not provide the `rebinding_time` option.
 const REBINDING_TIME_FACTOR: f64 = 0.875;
+/// The minimal time in seconds the client stays IPv6-only (RFC 8925 §3.4).
+const MIN_V6ONLY_WAIT: u32 = 300;
+/// The time in seconds the client waits after a `DHCPDECLINE` (RFC 2131 §3.1).
+const DECLINE_WAIT: u64 = 10;
+/// The backoff timeouts are randomized by up to this number of seconds (RFC 2131 §4.1).
+const BACKOFF_JITTER: u64 = 1;
 
 /// RFC 2131 DHCP states.
 ///
 /// The ones end with `Sent` are not described in RFC 2131 and
 /// are just substates to tell if the request has been sent or not.
-#[derive(Clone, Copy)]
+///
+/// `Ipv6OnlyWaiting` is described in RFC 8925 and means the IPv4 configuration
+/// is paused since the network prefers the client to be IPv6-only.
+///
+/// `DeclineWaiting` means the offered address has been declined
+/// and the client is waiting before restarting the configuration process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DhcpState {
     Init,
     Selecting,
@@ -45,6 +54,8 @@ pub enum DhcpState {
     RenewingSent,
     Rebinding,
     RebindingSent,
+    Ipv6OnlyWaiting,
+    DeclineWaiting,
 }
 
 impl fmt::Display for DhcpState {
@@ -64,10 +75,43 @@ impl fmt::Display for DhcpState {
             RenewingSent => write!(f, "RENEWING_SENT"),
             Rebinding => write!(f, "REBINDING"),
             RebindingSent => write!(f, "REBINDING_SENT"),
+            Ipv6OnlyWaiting => write!(f, "IPV6ONLY_WAITING"),
+            DeclineWaiting => write!(f, "DECLINE_WAITING"),
         }
     }
 }
 
+/// The current lease snapshot returned by `Client::lease_status`.
+#[derive(Debug, Clone)]
+pub struct LeaseStatus {
+    /// Current DHCP client state.
+    pub state: DhcpState,
+    /// The address in use. `None` if there is no lease.
+    pub assigned_address: Option<Ipv4Addr>,
+    /// The leasing server. `None` if it is unknown (e.g. in `REBINDING` state).
+    pub dhcp_server_id: Option<Ipv4Addr>,
+    /// The moment the client enters `RENEWING` state (so called T1).
+    pub renewal_at: Option<Instant>,
+    /// The moment the client enters `REBINDING` state (so called T2).
+    pub rebinding_at: Option<Instant>,
+    /// The moment the lease expires.
+    pub expiration_at: Option<Instant>,
+}
+
+impl LeaseStatus {
+    /// The time left until the lease expiration. `None` if there is no lease.
+    pub fn expires_in(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.expiration_at.map(|expiration_at| {
+            if expiration_at > now {
+                expiration_at - now
+            } else {
+                Duration::from_secs(0)
+            }
+        })
+    }
+}
+
 /// Mutable `Client` data.
 pub struct State {
     /// Current DHCP client state (RFC 2131).
@@ -76,6 +120,8 @@ pub struct State {
     is_broadcast: bool,
     /// Generated by the client for each session.
     transaction_id: u32,
+    /// The transaction whose final response has already been processed.
+    processed_xid: Option<u32>,
     /// Recorded by the client from the selected `DHCPOFFER`.
     offered_address: Ipv4Addr,
     /// Recorded by the client from the selected `DHCPOFFER`.
@@ -87,12 +133,16 @@ pub struct State {
 
     /// Recorded by the client right before sending the `DhcpRequest`.
     requested_at: i64,
+    /// The monotonic counterpart of `requested_at`.
+    requested_instant: Instant,
     /// Seconds from `BOUND` till `RENEWING` state.
     renewal_after: u64,
     /// Seconds from `RENEWING` till `REBINDING` state.
     rebinding_after: u64,
     /// Seconds from `REBINDING` till lease expiration.
     expiration_after: u64,
+    /// The T1, T2 and expiration moments of the current lease.
+    lease_instants: Option<(Instant, Instant, Instant)>,
 
     /// DHCPOFFER receive deadline.
     pub timer_offer: Option<Backoff>,
@@ -104,6 +154,15 @@ pub struct State {
     pub timer_rebinding: Option<Forthon>,
     /// Lease expiration timer.
     pub timer_expiration: Option<Forthon>,
+    /// The IPv6-only mode timer (so called V6ONLY_WAIT in RFC 8925).
+    pub timer_ipv6_only: Option<Delay>,
+    /// The restart timer after a `DHCPDECLINE`.
+    pub timer_decline: Option<Delay>,
+
+    /// Notified about the transitions and messages.
+    observers: Vec<Box<Observer>>,
+    /// The BEB and BEF™ timers parameters.
+    timing: Timing,
 }
 
 impl State {
@@ -117,21 +176,29 @@ impl State {
             dhcp_state,
             is_broadcast,
             transaction_id: rand::random::<u32>(),
+            processed_xid: None,
             offered_address: Ipv4Addr::new(0, 0, 0, 0),
             offered_time: 0u32,
             dhcp_server_id: server_address,
             assigned_address: Ipv4Addr::new(0, 0, 0, 0),
 
             requested_at: 0i64,
+            requested_instant: Instant::now(),
             renewal_after: 0u64,
             rebinding_after: 0u64,
             expiration_after: 0u64,
+            lease_instants: None,
 
             timer_offer: None,
             timer_ack: None,
             timer_renewal: None,
             timer_rebinding: None,
             timer_expiration: None,
+            timer_ipv6_only: None,
+            timer_decline: None,
+
+            observers: Vec::new(),
+            timing: Timing::default(),
         }
     }
 
@@ -146,6 +213,7 @@ impl State {
         match from {
             Init => match to {
                 next @ Selecting => {
+                    self.next_xid();
                     self.set_dhcp_server_id(None);
                     self.run_timer_offer();
                     self.dhcp_state = next;
@@ -159,7 +227,13 @@ impl State {
                 _ => panic_state!(from, to),
             },
             SelectingSent => match to {
+                next @ Init => self.dhcp_state = next,
                 next @ Selecting => self.dhcp_state = next,
+                next @ Ipv6OnlyWaiting => {
+                    let offer = expect!(response);
+                    self.run_timer_ipv6_only(expect!(offer.options.ipv6_only_preferred));
+                    self.dhcp_state = next;
+                }
                 next @ Requesting => {
                     let offer = expect!(response);
                     self.set_dhcp_server_id(Some(expect!(offer.options.dhcp_server_id)));
@@ -180,6 +254,11 @@ impl State {
             RequestingSent => match to {
                 next @ Init => self.dhcp_state = next,
                 next @ Requesting => self.dhcp_state = next,
+                next @ Ipv6OnlyWaiting => {
+                    let ack = expect!(response);
+                    self.run_timer_ipv6_only(expect!(ack.options.ipv6_only_preferred));
+                    self.dhcp_state = next;
+                }
                 next @ Bound => {
                     let ack = expect!(response);
                     self.set_assigned_address(ack.your_ip_address);
@@ -196,6 +275,7 @@ impl State {
 
             InitReboot => match to {
                 next @ Rebooting => {
+                    self.next_xid();
                     self.run_timer_ack();
                     self.dhcp_state = next;
                 }
@@ -211,6 +291,11 @@ impl State {
             RebootingSent => match to {
                 next @ Init => self.dhcp_state = next,
                 next @ Rebooting => self.dhcp_state = next,
+                next @ Ipv6OnlyWaiting => {
+                    let ack = expect!(response);
+                    self.run_timer_ipv6_only(expect!(ack.options.ipv6_only_preferred));
+                    self.dhcp_state = next;
+                }
                 next @ Bound => {
                     let ack = expect!(response);
                     self.set_assigned_address(ack.your_ip_address);
@@ -228,9 +313,14 @@ impl State {
 
             Bound => match to {
                 next @ Renewing => {
+                    self.next_xid();
                     self.run_timer_rebinding();
                     self.dhcp_state = next;
                 }
+                next @ DeclineWaiting => {
+                    self.run_timer_decline();
+                    self.dhcp_state = next;
+                }
                 _ => panic_state!(from, to),
             },
             Renewing => match to {
@@ -255,6 +345,7 @@ impl State {
                 }
                 next @ Renewing => self.dhcp_state = next,
                 next @ Rebinding => {
+                    self.next_xid();
                     self.set_dhcp_server_id(None);
                     self.run_timer_expiration();
                     self.dhcp_state = next;
@@ -285,7 +376,123 @@ impl State {
                 next @ Rebinding => self.dhcp_state = next,
                 _ => panic_state!(from, to),
             },
+
+            Ipv6OnlyWaiting => match to {
+                next @ Init => self.dhcp_state = next,
+                _ => panic_state!(from, to),
+            },
+            DeclineWaiting => match to {
+                next @ Init => self.dhcp_state = next,
+                _ => panic_state!(from, to),
+            },
+        }
+
+        match to {
+            Bound => self.processed_xid = Some(self.transaction_id),
+            Ipv6OnlyWaiting => {
+                self.processed_xid = Some(self.transaction_id);
+                self.lease_instants = None;
+            }
+            Init | DeclineWaiting => self.lease_instants = None,
+            _ => {}
         }
+
+        self.notify_transition(from, to, response);
+    }
+
+    /// Abandons the current exchange and restarts the configuration process.
+    ///
+    /// Is used if the current timers make no sense anymore, e.g. after the link carrier loss.
+    ///
+    /// # Panics
+    /// If `to` is neither `Init` nor `InitReboot`.
+    pub fn restart(&mut self, to: DhcpState) {
+        let from = self.dhcp_state;
+        match to {
+            DhcpState::Init => self.lease_instants = None,
+            DhcpState::InitReboot => {}
+            _ => panic_state!(from, to),
+        }
+
+        self.timer_offer = None;
+        self.timer_ack = None;
+        self.timer_renewal = None;
+        self.timer_rebinding = None;
+        self.timer_expiration = None;
+        self.timer_ipv6_only = None;
+        self.timer_decline = None;
+        self.dhcp_state = to;
+
+        self.notify_transition(from, to, None);
+    }
+
+    fn notify_transition(&self, from: DhcpState, to: DhcpState, response: Option<&Message>) {
+        if !self.observers.is_empty() {
+            let context = Context {
+                transaction_id: self.transaction_id,
+                dhcp_server_id: self.dhcp_server_id,
+                assigned_address: self.assigned_address,
+                response,
+            };
+            for observer in self.observers.iter() {
+                observer.on_transition(from, to, &context);
+            }
+        }
+    }
+
+    /// Registers an observer.
+    pub fn add_observer(&mut self, observer: Box<Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Sets the parameters of the timers started afterwards.
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    /// Makes the renewal timer (T1) expire at once if the client is bound.
+    pub fn expire_renewal(&mut self) {
+        if self.dhcp_state == DhcpState::Bound {
+            self.timer_renewal = Some(Delay::new(Instant::now()));
+        }
+    }
+
+    /// Notifies the observers about a message.
+    pub fn notify_message(&self, direction: Direction, message: &Message) {
+        for observer in self.observers.iter() {
+            observer.on_message(direction, message);
+        }
+    }
+
+    /// Starts a new transaction.
+    ///
+    /// The ID sequence is continued instead of being regenerated,
+    /// so the ID saved in a persistent identity remains meaningful.
+    fn next_xid(&mut self) {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+    }
+
+    /// Makes a snapshot of the current lease.
+    pub fn lease_status(&self) -> LeaseStatus {
+        let (renewal_at, rebinding_at, expiration_at) = match self.lease_instants {
+            Some((renewal_at, rebinding_at, expiration_at)) => {
+                (Some(renewal_at), Some(rebinding_at), Some(expiration_at))
+            }
+            None => (None, None, None),
+        };
+
+        LeaseStatus {
+            state: self.dhcp_state,
+            assigned_address: self.lease_instants.map(|_| self.assigned_address),
+            dhcp_server_id: self.dhcp_server_id,
+            renewal_at,
+            rebinding_at,
+            expiration_at,
+        }
+    }
+
+    pub fn processed_xid(&self) -> Option<u32> {
+        self.processed_xid
     }
 
     pub fn dhcp_state(&self) -> DhcpState {
@@ -300,6 +507,10 @@ impl State {
         self.transaction_id
     }
 
+    pub fn set_xid(&mut self, value: u32) {
+        self.transaction_id = value;
+    }
+
     pub fn offered_address(&self) -> Ipv4Addr {
         self.offered_address.to_owned()
     }
@@ -316,8 +527,7 @@ impl State {
         self.assigned_address.to_owned()
     }
 
-    #[allow(dead_code)]
-    fn set_broadcast(&mut self, value: bool) {
+    pub fn set_broadcast(&mut self, value: bool) {
         self.is_broadcast = value;
     }
 
@@ -339,6 +549,7 @@ impl State {
 
     fn record_request_time(&mut self) {
         self.requested_at = Utc::now().timestamp();
+        self.requested_instant = Instant::now();
     }
 
     fn set_times(
@@ -352,6 +563,17 @@ impl State {
         let rebinding_time =
             rebinding_time.unwrap_or(((expiration_time as f64) * REBINDING_TIME_FACTOR) as u32);
 
+        /*
+        RFC 2131 §4.4.1
+        The client records the lease expiration time as the sum of the time at which
+        the original request was sent and the duration of the lease from the DHCPACK message.
+        */
+        self.lease_instants = Some((
+            self.requested_instant + Duration::from_secs(renewal_time as u64),
+            self.requested_instant + Duration::from_secs(rebinding_time as u64),
+            self.requested_instant + Duration::from_secs(expiration_time as u64),
+        ));
+
         self.renewal_after =
             ((renewal_time as i64) - (Utc::now().timestamp() - self.requested_at)) as u64;
         self.rebinding_after = (rebinding_time as u64) - self.renewal_after;
@@ -360,17 +582,28 @@ impl State {
     }
 
     fn run_timer_offer(&mut self) {
-        self.timer_offer = Some(Backoff::new(
-            Duration::from_secs(BACKOFF_TIMEOUT_INITIAL),
-            Duration::from_secs(BACKOFF_TIMEOUT_MAXIMUM),
-        ));
+        self.timer_offer = Some(Backoff::new(self.backoff_strategy()));
     }
 
     fn run_timer_ack(&mut self) {
-        self.timer_ack = Some(Backoff::new(
-            Duration::from_secs(BACKOFF_TIMEOUT_INITIAL),
-            Duration::from_secs(BACKOFF_TIMEOUT_MAXIMUM),
-        ));
+        self.timer_ack = Some(Backoff::new(self.backoff_strategy()));
+    }
+
+    /// The RFC 2131 backoff, expiring after the retry with the maximal timeout.
+    fn backoff_strategy(&self) -> Exponential {
+        let mut retries = 1;
+        let mut timeout = self.timing.backoff_initial;
+        while timeout > Duration::from_secs(0) && timeout * 2 <= self.timing.backoff_maximal {
+            timeout *= 2;
+            retries += 1;
+        }
+
+        let mut strategy =
+            Exponential::new(self.timing.backoff_initial, self.timing.backoff_maximal);
+        strategy
+            .with_jitter(Duration::from_secs(BACKOFF_JITTER))
+            .with_retries(retries);
+        strategy
     }
 
     fn run_timer_renewal(&mut self) {
@@ -382,14 +615,34 @@ impl State {
     fn run_timer_rebinding(&mut self) {
         self.timer_rebinding = Some(Forthon::new(
             Duration::from_secs(self.rebinding_after),
-            Duration::from_secs(FORTHON_TIMEOUT_MINIMAL),
+            self.timing.forthon_minimal,
+            self.timing.forthon_divisor,
+        ));
+    }
+
+    fn run_timer_ipv6_only(&mut self, wait: u32) {
+        let wait = cmp::max(wait, MIN_V6ONLY_WAIT);
+        self.timer_ipv6_only = Some(Delay::new(
+            Instant::now() + Duration::from_secs(wait as u64),
+        ));
+    }
+
+    /*
+    RFC 2131 §3.1
+    The client SHOULD wait a minimum of ten seconds before restarting the
+    configuration process to avoid excessive network traffic in case of looping.
+    */
+    fn run_timer_decline(&mut self) {
+        self.timer_decline = Some(Delay::new(
+            Instant::now() + Duration::from_secs(DECLINE_WAIT),
         ));
     }
 
     fn run_timer_expiration(&mut self) {
         self.timer_expiration = Some(Forthon::new(
             Duration::from_secs(self.expiration_after),
-            Duration::from_secs(FORTHON_TIMEOUT_MINIMAL),
+            self.timing.forthon_minimal,
+            self.timing.forthon_divisor,
         ));
     }
 }
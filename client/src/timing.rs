@@ -0,0 +1,57 @@
+//! The retransmission timing configuration module.
+
+use std::time::Duration;
+
+use error::Error;
+
+/// The retransmission timing parameters.
+///
+/// The defaults follow RFC 2131 and suit the usual leases of several hours.
+/// Lab setups with one-minute leases need smaller timeouts, while carrier networks
+/// with week-long leases may prefer to retransmit more sparsely.
+#[derive(Debug, Clone)]
+pub struct Timing {
+    /// The initial timeout of the `DHCPDISCOVER` and `DHCPREQUEST` retransmission (RFC 2131 §4.1).
+    pub backoff_initial: Duration,
+    /// The retransmission stops after the timeout exceeds this value.
+    pub backoff_maximal: Duration,
+    /// The minimal timeout of the `RENEWING` and `REBINDING` retransmission (RFC 2131 §4.4.5).
+    pub forthon_minimal: Duration,
+    /// The remaining time is divided by this value before each retransmission.
+    ///
+    /// The RFC 2131 value is 2, that is, the client waits one-half of the remaining time.
+    pub forthon_divisor: u32,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            backoff_initial: Duration::from_secs(4),
+            backoff_maximal: Duration::from_secs(64),
+            forthon_minimal: Duration::from_secs(60),
+            forthon_divisor: 2,
+        }
+    }
+}
+
+impl Timing {
+    /// Checks if the timers are able to work with the parameters.
+    ///
+    /// # Errors
+    /// `ClientError::Timing` with the reason description.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.backoff_initial == Duration::from_secs(0) {
+            return Err(Error::Timing("The initial backoff timeout must be positive"));
+        }
+        if self.backoff_initial > self.backoff_maximal {
+            return Err(Error::Timing("The initial backoff timeout exceeds the maximal one"));
+        }
+        if self.forthon_minimal == Duration::from_secs(0) {
+            return Err(Error::Timing("The minimal forthon timeout must be positive"));
+        }
+        if self.forthon_divisor < 2 {
+            return Err(Error::Timing("The forthon divisor must be at least 2"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! The client transport capability module.
+
+/// The way the client socket receives the server responses.
+///
+/// Determines whether the server must broadcast its responses.
+///
+/// RFC 2131 §4.1
+/// A client that cannot receive unicast IP datagrams until its protocol
+/// software has been configured with an IP address SHOULD set the
+/// BROADCAST bit in the 'flags' field to 1 in any DHCPDISCOVER or
+/// DHCPREQUEST messages that client sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// A raw or packet socket bypassing the IP stack.
+    /// Receives the datagrams unicast to the offered address.
+    Raw,
+    /// A UDP socket bound to `0.0.0.0:68` on an unconfigured interface.
+    /// The IP stack drops the datagrams unicast to an address it does not own.
+    Udp,
+    /// A UDP socket on an interface already configured with the leased address.
+    Configured,
+    /// A `Dhcp4o6Framed` socket on an IPv6-only network (RFC 7341).
+    /// The responses are encapsulated into DHCPv6 and never broadcast.
+    Dhcp4o6,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Udp
+    }
+}
+
+impl Transport {
+    /// Checks if the server must broadcast its responses to reach the client.
+    pub fn requires_broadcast(self) -> bool {
+        match self {
+            Transport::Raw => false,
+            Transport::Udp => true,
+            Transport::Configured => false,
+            Transport::Dhcp4o6 => false,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! The vendor specific information decoding module.
+//!
+//! RFC 2132 §8.4
+//! The encapsulated vendor-specific options field SHOULD be encoded as a
+//! sequence of code/length/value fields of identical syntax to the DHCP
+//! options field. The interpretation of this option is vendor specific,
+//! so the decoders are registered by the user for each vendor class.
+
+use std::{any::Any, collections::HashMap, fmt, sync::Arc};
+
+/// The decoded vendor specific information carried in the lease.
+///
+/// Use `downcast_ref` to get the application type back.
+pub type VendorInfo = Arc<Any + Send + Sync>;
+
+/// The option 43 decoding callback.
+///
+/// Returns `None` if the data cannot be decoded.
+pub type VendorDecoder = Box<Fn(&[u8]) -> Option<VendorInfo> + Send + Sync>;
+
+/// The vendor decoders keyed by the vendor class identifier.
+#[derive(Default)]
+pub struct VendorDecoders {
+    decoders: HashMap<Vec<u8>, VendorDecoder>,
+}
+
+impl fmt::Debug for VendorDecoders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.decoders.keys().map(|key| String::from_utf8_lossy(key)))
+            .finish()
+    }
+}
+
+impl VendorDecoders {
+    /// Registers a decoder for the vendor class, replacing the previous one.
+    pub fn register(&mut self, class_id: Vec<u8>, decoder: VendorDecoder) {
+        self.decoders.insert(class_id, decoder);
+    }
+
+    /// Decodes the vendor specific information.
+    ///
+    /// * `class_id`
+    /// The vendor class identifier the server responded with
+    /// or the one sent by the client if the server has not echoed it.
+    ///
+    /// * `data`
+    /// The raw option 43 contents.
+    ///
+    pub fn decode(&self, class_id: Option<&[u8]>, data: &[u8]) -> Option<VendorInfo> {
+        let class_id = class_id?;
+        let decoder = match self.decoders.get(class_id) {
+            Some(decoder) => decoder,
+            None => {
+                trace!(
+                    "No vendor decoder for class {}",
+                    String::from_utf8_lossy(class_id)
+                );
+                return None;
+            }
+        };
+        let info = decoder(data);
+        if info.is_none() {
+            warn!(
+                "Vendor specific information of class {} cannot be decoded",
+                String::from_utf8_lossy(class_id)
+            );
+        }
+        info
+    }
+}
@@ -0,0 +1,222 @@
+//! A builder for common DHCPv6 client messages.
+
+use std::net::Ipv6Addr;
+
+use eui48::MacAddress;
+
+use dhcp_protocol::v6::*;
+
+/// The `DUID-LL` type code (RFC 8415 §11.4).
+const DUID_TYPE_LL: u16 = 3;
+/// The Ethernet hardware type code (RFC 826).
+const DUID_HARDWARE_TYPE_ETHERNET: u16 = 1;
+
+/// Builds common client messages with some parameters.
+pub struct MessageBuilder {
+    /// The client DUID sent in the `Client Identifier` option.
+    client_id: Vec<u8>,
+    /// The identity association ID of the only `IA_NA` and `IA_PD` of the client.
+    iaid: u32,
+    /// Whether an `IA_PD` is requested.
+    prefix_delegation: bool,
+    /// The delegated prefix length hint.
+    prefix_length: Option<u8>,
+}
+
+impl MessageBuilder {
+    /// Creates a builder with message parameters which will not be changed.
+    ///
+    /// If `client_id` is `None`, a `DUID-LL` is made of the hardware address.
+    /// The IAID is derived from the last four bytes of the hardware address.
+    pub fn new(client_hardware_address: &MacAddress, client_id: Option<Vec<u8>>) -> Self {
+        let hwaddr = client_hardware_address.as_bytes();
+        let client_id = client_id.unwrap_or_else(|| {
+            let mut duid = Vec::with_capacity(4 + hwaddr.len());
+            duid.push((DUID_TYPE_LL >> 8) as u8);
+            duid.push(DUID_TYPE_LL as u8);
+            duid.push((DUID_HARDWARE_TYPE_ETHERNET >> 8) as u8);
+            duid.push(DUID_HARDWARE_TYPE_ETHERNET as u8);
+            duid.extend_from_slice(hwaddr);
+            duid
+        });
+        let iaid = hwaddr[hwaddr.len() - 4..]
+            .iter()
+            .fold(0u32, |iaid, byte| (iaid << 8) | u32::from(*byte));
+
+        MessageBuilder {
+            client_id,
+            iaid,
+            prefix_delegation: false,
+            prefix_length: None,
+        }
+    }
+
+    /// Enables requesting an `IA_PD` with an optional prefix length hint.
+    pub fn set_prefix_delegation(&mut self, prefix_length: Option<u8>) {
+        self.prefix_delegation = true;
+        self.prefix_length = prefix_length;
+    }
+
+    /// The client DUID.
+    pub fn client_id(&self) -> &[u8] {
+        &self.client_id
+    }
+
+    /// The identity association ID.
+    pub fn iaid(&self) -> u32 {
+        self.iaid
+    }
+
+    /// Creates a `SOLICIT` message with an empty `IA_NA` and, if enabled, an `IA_PD`.
+    pub fn solicit(&self, transaction_id: u32, elapsed_time: u16) -> Message {
+        let mut options = self.default_options(elapsed_time);
+        options.ia_na.push(self.empty_ia_na());
+        if self.prefix_delegation {
+            options.ia_pd.push(self.empty_ia_pd());
+        }
+        Self::message(MessageType::Solicit, transaction_id, options)
+    }
+
+    /// Creates a `REQUEST` message for the leases from the selected `ADVERTISE`.
+    pub fn request(
+        &self,
+        transaction_id: u32,
+        elapsed_time: u16,
+        server_id: Vec<u8>,
+        ia_na: Option<IaNa>,
+        ia_pd: Option<IaPd>,
+    ) -> Message {
+        let mut options = self.default_options(elapsed_time);
+        options.server_id = Some(server_id);
+        Self::append_ia(&mut options, ia_na, ia_pd);
+        Self::message(MessageType::Request, transaction_id, options)
+    }
+
+    /// Creates a `RENEW` message sent to the leasing server.
+    pub fn renew(
+        &self,
+        transaction_id: u32,
+        elapsed_time: u16,
+        server_id: Vec<u8>,
+        ia_na: Option<IaNa>,
+        ia_pd: Option<IaPd>,
+    ) -> Message {
+        let mut options = self.default_options(elapsed_time);
+        options.server_id = Some(server_id);
+        Self::append_ia(&mut options, ia_na, ia_pd);
+        Self::message(MessageType::Renew, transaction_id, options)
+    }
+
+    /// Creates a `REBIND` message sent to any server.
+    pub fn rebind(
+        &self,
+        transaction_id: u32,
+        elapsed_time: u16,
+        ia_na: Option<IaNa>,
+        ia_pd: Option<IaPd>,
+    ) -> Message {
+        let mut options = self.default_options(elapsed_time);
+        Self::append_ia(&mut options, ia_na, ia_pd);
+        Self::message(MessageType::Rebind, transaction_id, options)
+    }
+
+    /// Creates a `RELEASE` message.
+    pub fn release(
+        &self,
+        transaction_id: u32,
+        server_id: Vec<u8>,
+        ia_na: Option<IaNa>,
+        ia_pd: Option<IaPd>,
+    ) -> Message {
+        let mut options = self.default_options(0);
+        options.server_id = Some(server_id);
+        options.option_request = None;
+        Self::append_ia(&mut options, ia_na, ia_pd);
+        Self::message(MessageType::Release, transaction_id, options)
+    }
+
+    fn message(message_type: MessageType, transaction_id: u32, options: Options) -> Message {
+        Message {
+            message_type,
+            transaction_id,
+            options,
+        }
+    }
+
+    fn default_options(&self, elapsed_time: u16) -> Options {
+        let mut options = Options::default();
+        options.client_id = Some(self.client_id.to_owned());
+        options.elapsed_time = Some(elapsed_time);
+        options.option_request = Some(vec![
+            OptionCode::DnsServers as u16,
+            OptionCode::DomainList as u16,
+        ]);
+        options
+    }
+
+    fn empty_ia_na(&self) -> IaNa {
+        IaNa {
+            iaid: self.iaid,
+            renewal_time: 0,
+            rebinding_time: 0,
+            addresses: Vec::new(),
+            status_code: None,
+        }
+    }
+
+    /// The prefix length hint is sent as the `::/length` prefix (RFC 8415 §18.2.1).
+    fn empty_ia_pd(&self) -> IaPd {
+        IaPd {
+            iaid: self.iaid,
+            renewal_time: 0,
+            rebinding_time: 0,
+            prefixes: self
+                .prefix_length
+                .map(|prefix_length| IaPrefix {
+                    preferred_lifetime: 0,
+                    valid_lifetime: 0,
+                    prefix_length,
+                    prefix: Ipv6Addr::UNSPECIFIED,
+                    status_code: None,
+                })
+                .into_iter()
+                .collect(),
+            status_code: None,
+        }
+    }
+
+    fn append_ia(options: &mut Options, ia_na: Option<IaNa>, ia_pd: Option<IaPd>) {
+        if let Some(ia_na) = ia_na {
+            options.ia_na.push(Self::hint(ia_na));
+        }
+        if let Some(ia_pd) = ia_pd {
+            options.ia_pd.push(Self::hint_pd(ia_pd));
+        }
+    }
+
+    /// Resets the times, since the client-sent addresses and lifetimes are only hints.
+    fn hint(mut ia_na: IaNa) -> IaNa {
+        ia_na.renewal_time = 0;
+        ia_na.rebinding_time = 0;
+        ia_na.status_code = None;
+        for address in ia_na.addresses.iter_mut() {
+            address.preferred_lifetime = 0;
+            address.valid_lifetime = 0;
+            address.status_code = None;
+        }
+        ia_na
+    }
+
+    /// The `IA_PD` counterpart of `hint`.
+    fn hint_pd(mut ia_pd: IaPd) -> IaPd {
+        ia_pd.renewal_time = 0;
+        ia_pd.rebinding_time = 0;
+        ia_pd.status_code = None;
+        for prefix in ia_pd.prefixes.iter_mut() {
+            prefix.preferred_lifetime = 0;
+            prefix.valid_lifetime = 0;
+            prefix.status_code = None;
+        }
+        ia_pd
+    }
+}
@@ -0,0 +1,460 @@
+//! The main DHCPv6 client module.
+
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+
+use eui48::MacAddress;
+use futures::StartSend;
+use tokio::{io, prelude::*};
+
+use dhcp_protocol::v6::{constants::*, IaAddress, IaPrefix, Message, MessageType, Status};
+
+use builder::MessageBuilder;
+use state::{self, Dhcp6State, State};
+
+/// The lease data yielded by the client.
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    /// The leasing server DUID.
+    pub server_id: Vec<u8>,
+    /// Empty if the server has assigned no addresses.
+    pub addresses: Vec<IaAddress>,
+    /// The delegated prefixes with their lifetimes.
+    /// Empty if prefix delegation is disabled or the server has delegated no prefixes.
+    pub prefixes: Vec<IaPrefix>,
+    /// So called T1. The smallest one of the identity associations.
+    pub renewal_time: u32,
+    /// So called T2. The smallest one of the identity associations.
+    pub rebinding_time: u32,
+    pub dns_servers: Option<Vec<Ipv6Addr>>,
+    pub domain_list: Option<Vec<String>>,
+}
+
+impl Configuration {
+    pub fn from_response(response: Message) -> Self {
+        let ia_na = state::leased_ia_na(&response).cloned();
+        let ia_pd = state::leased_ia_pd(&response).cloned();
+
+        let mut times = Vec::with_capacity(2);
+        if let Some(ref ia_na) = ia_na {
+            times.push((ia_na.renewal_time, ia_na.rebinding_time));
+        }
+        if let Some(ref ia_pd) = ia_pd {
+            times.push((ia_pd.renewal_time, ia_pd.rebinding_time));
+        }
+
+        Configuration {
+            server_id: response.options.server_id.unwrap_or_default(),
+            addresses: ia_na.map(|ia_na| ia_na.addresses).unwrap_or_default(),
+            prefixes: ia_pd.map(|ia_pd| ia_pd.prefixes).unwrap_or_default(),
+            renewal_time: times.iter().map(|times| times.0).min().unwrap_or(0),
+            rebinding_time: times.iter().map(|times| times.1).min().unwrap_or(0),
+            dns_servers: response.options.dns_servers,
+            domain_list: response.options.domain_list,
+        }
+    }
+}
+
+/// The `Client` stream item type.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A lease has been obtained, renewed or rebound.
+    Bound(Configuration),
+    /// The lease has expired and the addresses must not be used anymore.
+    Expired,
+}
+
+/// The commands used for `Sink` to send `RELEASE` messages.
+#[derive(Clone)]
+pub enum Command {
+    Release,
+}
+
+type Dhcp6StreamItem = (SocketAddr, Message);
+type Dhcp6SinkItem = (SocketAddr, Message);
+
+/// The struct implementing the `Stream` trait.
+pub struct Client<I, O>
+where
+    I: Stream<Item = Dhcp6StreamItem, Error = io::Error> + Send + Sync,
+    O: Sink<SinkItem = Dhcp6SinkItem, SinkError = io::Error> + Send + Sync,
+{
+    stream: I,
+    sink: O,
+    builder: MessageBuilder,
+    state: State,
+    /// The link-local multicast destination.
+    destination: SocketAddr,
+}
+
+impl<I, O> Client<I, O>
+where
+    I: Stream<Item = Dhcp6StreamItem, Error = io::Error> + Send + Sync,
+    O: Sink<SinkItem = Dhcp6SinkItem, SinkError = io::Error> + Send + Sync,
+{
+    /// Creates a client stream.
+    ///
+    /// * `stream`
+    /// The external socket `Stream` part.
+    ///
+    /// * `sink`
+    /// The external socket `Sink` part.
+    ///
+    /// * `client_hardware_address`
+    /// The mandatory client MAC address.
+    ///
+    /// * `client_id`
+    /// The optional client DUID.
+    /// If `None`, is defaulted to the `DUID-LL` made of the MAC address.
+    ///
+    /// * `scope_id`
+    /// The index of the interface the link-local multicast messages are sent through.
+    ///
+    pub fn new(
+        stream: I,
+        sink: O,
+        client_hardware_address: MacAddress,
+        client_id: Option<Vec<u8>>,
+        scope_id: u32,
+    ) -> Self {
+        let builder = MessageBuilder::new(&client_hardware_address, client_id);
+        let destination = SocketAddr::V6(SocketAddrV6::new(
+            ALL_DHCP_RELAY_AGENTS_AND_SERVERS,
+            DHCP6_PORT_SERVER,
+            0,
+            scope_id,
+        ));
+
+        Client {
+            stream,
+            sink,
+            builder,
+            state: State::new(),
+            destination,
+        }
+    }
+
+    /// Enables requesting delegated prefixes (RFC 8415 §6.3).
+    ///
+    /// * `prefix_length`
+    /// The optional delegated prefix length hint for the server.
+    pub fn with_prefix_delegation(&mut self, prefix_length: Option<u8>) -> &mut Self {
+        self.builder.set_prefix_delegation(prefix_length);
+        self
+    }
+
+    /// Checks if the response is addressed to this client in the current exchange.
+    fn is_relevant(&self, response: &Message, expected: MessageType) -> bool {
+        if response.message_type != expected {
+            warn!("Got an unexpected message type {}", response.message_type);
+            return false;
+        }
+        if response.transaction_id != self.state.xid() {
+            warn!(
+                "Got a response with wrong transaction ID: {:06x} (yours is {:06x})",
+                response.transaction_id,
+                self.state.xid()
+            );
+            return false;
+        }
+        /*
+        RFC 8415 §16.3
+        Clients MUST discard any received Advertise message that meets
+        any of the following conditions:
+        -  the message does not include a Server Identifier option.
+        -  the message does not include a Client Identifier option.
+        -  the contents of the Client Identifier option do not match the
+           client's DUID.
+        -  the "transaction-id" field value does not match the value the
+           client used in its Solicit message.
+        */
+        if response.options.server_id.is_none() {
+            warn!("Got a response without the server identifier");
+            return false;
+        }
+        match response.options.client_id {
+            Some(ref client_id) if client_id.as_slice() == self.builder.client_id() => true,
+            _ => {
+                warn!("Got a response with a wrong client identifier");
+                false
+            }
+        }
+    }
+
+    /// Checks if the response contains an `IA_NA` with addresses or an `IA_PD` with prefixes.
+    fn has_leases(response: &Message) -> bool {
+        if let Some(ref status_code) = response.options.status_code {
+            if status_code.status() != Status::Success {
+                warn!("The server responded with status {}", status_code);
+                return false;
+            }
+        }
+        state::leased_ia_na(response).is_some() || state::leased_ia_pd(response).is_some()
+    }
+
+    /// Sends the message of the current exchange.
+    fn send_current(&mut self) -> io::Result<()> {
+        let xid = self.state.xid();
+        let elapsed_time = self.state.elapsed_time();
+        let request = match self.state.dhcp_state() {
+            Dhcp6State::Soliciting => self.builder.solicit(xid, elapsed_time),
+            Dhcp6State::Requesting => self.builder.request(
+                xid,
+                elapsed_time,
+                expect!(self.state.server_id()),
+                self.state.ia_na(),
+                self.state.ia_pd(),
+            ),
+            Dhcp6State::Renewing => self.builder.renew(
+                xid,
+                elapsed_time,
+                expect!(self.state.server_id()),
+                self.state.ia_na(),
+                self.state.ia_pd(),
+            ),
+            Dhcp6State::Rebinding => {
+                self.builder
+                    .rebind(xid, elapsed_time, self.state.ia_na(), self.state.ia_pd())
+            }
+            current => panic!("Nothing to send in {} state", current),
+        };
+
+        log_send!(request, self.destination);
+        start_send!(self.sink, self.destination, request);
+        Ok(())
+    }
+}
+
+impl<I, O> Stream for Client<I, O>
+where
+    I: Stream<Item = Dhcp6StreamItem, Error = io::Error> + Send + Sync,
+    O: Sink<SinkItem = Dhcp6SinkItem, SinkError = io::Error> + Send + Sync,
+{
+    type Item = Event;
+    type Error = io::Error;
+
+    /// Yields an `Event` after each configuration update.
+    ///
+    /// [RFC 8415](https://tools.ietf.org/html/rfc8415)
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            poll_complete!(self.sink);
+
+            match self.state.dhcp_state() {
+                current @ Dhcp6State::Init => {
+                    /*
+                    RFC 8415 §18.2.1
+                    A client uses the Solicit message to discover DHCP servers
+                    configured to assign leases or return other configuration
+                    parameters on the link to which the client is attached.
+                    */
+                    self.state.transcend(current, Dhcp6State::Soliciting, None);
+                    self.send_current()?;
+                }
+                current @ Dhcp6State::Soliciting => {
+                    let (addr, response) = match self.stream.poll() {
+                        Ok(Async::Ready(Some(data))) => data,
+                        Ok(Async::Ready(None)) => {
+                            warn!("Received an invalid packet");
+                            continue;
+                        }
+                        Ok(Async::NotReady) => {
+                            poll_retransmission!(self.state.timer_retransmission);
+                            if self.state.best_preference().is_some() {
+                                self.state.transcend(current, Dhcp6State::Requesting, None);
+                            }
+                            self.send_current()?;
+                            continue;
+                        }
+                        Err(error) => {
+                            warn!("Socket error: {}", error);
+                            continue;
+                        }
+                    };
+
+                    log_receive!(response, addr.ip());
+                    if !self.is_relevant(&response, MessageType::Advertise) {
+                        continue;
+                    }
+                    if !Self::has_leases(&response) {
+                        continue;
+                    }
+
+                    /*
+                    RFC 8415 §18.2.1
+                    If the client receives a valid Advertise message that
+                    includes a Preference option with a preference value of 255,
+                    the client immediately begins a client-initiated message
+                    exchange by sending a Request message to the server from
+                    which the Advertise message was received. If the client
+                    receives an Advertise message that does not include a
+                    Preference option with a preference value of 255, the client
+                    continues to wait until the first RT elapses. If the first RT
+                    elapses and the client has received an Advertise message, the
+                    client SHOULD continue with a client-initiated message exchange.
+                    */
+                    let is_collected = self.state.collect_advertise(&response);
+                    let is_first_rt_elapsed = self
+                        .state
+                        .timer_retransmission
+                        .as_ref()
+                        .map_or(false, |timer| timer.count() > 1);
+                    if (is_collected && response.options.preference == Some(PREFERENCE_MAXIMAL))
+                        || is_first_rt_elapsed
+                    {
+                        self.state.transcend(current, Dhcp6State::Requesting, None);
+                        self.send_current()?;
+                    }
+                }
+                current @ Dhcp6State::Requesting => {
+                    let (addr, response) = match self.stream.poll() {
+                        Ok(Async::Ready(Some(data))) => data,
+                        Ok(Async::Ready(None)) => {
+                            warn!("Received an invalid packet");
+                            continue;
+                        }
+                        Ok(Async::NotReady) => {
+                            if poll_retransmission!(self.state.timer_retransmission) {
+                                warn!("No replies to the request, restarting");
+                                self.state.transcend(current, Dhcp6State::Init, None);
+                            } else {
+                                self.send_current()?;
+                            }
+                            continue;
+                        }
+                        Err(error) => {
+                            warn!("Socket error: {}", error);
+                            continue;
+                        }
+                    };
+
+                    log_receive!(response, addr.ip());
+                    if !self.is_relevant(&response, MessageType::Reply) {
+                        continue;
+                    }
+                    if !Self::has_leases(&response) {
+                        self.state.transcend(current, Dhcp6State::Init, None);
+                        continue;
+                    }
+
+                    self.state
+                        .transcend(current, Dhcp6State::Bound, Some(&response));
+                    return Ok(Async::Ready(Some(Event::Bound(
+                        Configuration::from_response(response),
+                    ))));
+                }
+                current @ Dhcp6State::Bound => {
+                    /*
+                    RFC 8415 §18.2.4
+                    At time T1, the client initiates a Renew/Reply message
+                    exchange to extend the lifetimes on any leases in the IA.
+                    */
+                    poll_delay!(self.state.timer_renewal);
+                    self.state.transcend(current, Dhcp6State::Renewing, None);
+                    self.send_current()?;
+                }
+                current @ Dhcp6State::Renewing | current @ Dhcp6State::Rebinding => {
+                    let (addr, response) = match self.stream.poll() {
+                        Ok(Async::Ready(Some(data))) => data,
+                        Ok(Async::Ready(None)) => {
+                            warn!("Received an invalid packet");
+                            continue;
+                        }
+                        Ok(Async::NotReady) => {
+                            if !poll_retransmission!(self.state.timer_retransmission) {
+                                self.send_current()?;
+                                continue;
+                            }
+                            if let Dhcp6State::Renewing = current {
+                                /*
+                                RFC 8415 §18.2.5
+                                At time T2 (which will only be reached if the
+                                server to which the Renew message was sent
+                                starting at time T1 has not responded), the client
+                                initiates a Rebind/Reply message exchange with any
+                                available server.
+                                */
+                                self.state.transcend(current, Dhcp6State::Rebinding, None);
+                                self.send_current()?;
+                                continue;
+                            }
+                            warn!("The lease has expired");
+                            self.state.transcend(current, Dhcp6State::Init, None);
+                            return Ok(Async::Ready(Some(Event::Expired)));
+                        }
+                        Err(error) => {
+                            warn!("Socket error: {}", error);
+                            continue;
+                        }
+                    };
+
+                    log_receive!(response, addr.ip());
+                    if !self.is_relevant(&response, MessageType::Reply) {
+                        continue;
+                    }
+                    if !Self::has_leases(&response) {
+                        warn!("The lease cannot be extended");
+                        self.state.transcend(current, Dhcp6State::Init, None);
+                        return Ok(Async::Ready(Some(Event::Expired)));
+                    }
+
+                    self.state
+                        .transcend(current, Dhcp6State::Bound, Some(&response));
+                    return Ok(Async::Ready(Some(Event::Bound(
+                        Configuration::from_response(response),
+                    ))));
+                }
+            }
+        }
+    }
+}
+
+impl<I, O> Sink for Client<I, O>
+where
+    I: Stream<Item = Dhcp6StreamItem, Error = io::Error> + Send + Sync,
+    O: Sink<SinkItem = Dhcp6SinkItem, SinkError = io::Error> + Send + Sync,
+{
+    type SinkItem = Command;
+    type SinkError = io::Error;
+
+    /// Translates a `Command` into a DHCPv6 message and sends it to the user provided `Sink`.
+    fn start_send(
+        &mut self,
+        command: Self::SinkItem,
+    ) -> StartSend<Self::SinkItem, Self::SinkError> {
+        let request = match command {
+            Command::Release => {
+                let (ia_na, ia_pd) = (self.state.ia_na(), self.state.ia_pd());
+                let server_id = match self.state.server_id() {
+                    Some(ref server_id) if ia_na.is_some() || ia_pd.is_some() => {
+                        server_id.to_owned()
+                    }
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AddrNotAvailable,
+                            "Nothing to release",
+                        ))
+                    }
+                };
+                self.builder
+                    .release(self.state.xid(), server_id, ia_na, ia_pd)
+            }
+        };
+
+        log_send!(request, self.destination);
+        match self.sink.start_send((self.destination, request)) {
+            Ok(AsyncSink::Ready) => Ok(AsyncSink::Ready),
+            Ok(AsyncSink::NotReady(_item)) => Ok(AsyncSink::NotReady(command)),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Just a proxy.
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.sink.poll_complete()
+    }
+
+    /// Just a proxy.
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.poll_complete()
+    }
+}
@@ -0,0 +1,25 @@
+//! The Rust DHCPv6 stateful client implementation.
+
+#[macro_use]
+mod macros;
+mod builder;
+mod client;
+mod retransmission;
+mod state;
+
+#[macro_use]
+extern crate log;
+extern crate tokio;
+#[macro_use]
+extern crate futures;
+extern crate eui48;
+extern crate rand;
+
+extern crate dhcp_backoff;
+extern crate dhcp_framed;
+extern crate dhcp_protocol;
+
+pub use self::{
+    client::{Client, Command, Configuration, Event},
+    state::Dhcp6State,
+};
@@ -0,0 +1,85 @@
+//! Macro functions used in the `Client:poll` method.
+
+/// A panic indicates a bug in the application logic.
+macro_rules! expect (
+    ($option:expr) => (
+        $option.expect("A bug in the Option setting logic")
+    );
+);
+
+/// Just to move some code from the overwhelmed `poll` method.
+macro_rules! log_send(
+    ($message:expr, $destination:expr) => (
+        info!("Sending {} to {}", $message.message_type, $destination);
+        debug!("{}", $message);
+    );
+);
+
+/// Just to move some code from the overwhelmed `poll` method.
+macro_rules! log_receive(
+    ($message:expr, $source:expr) => (
+        info!("Received {} from {}", $message.message_type, $source);
+        debug!("{}", $message);
+    );
+);
+
+/// By design the pending message must be flushed before sending the next one.
+macro_rules! start_send (
+    ($socket:expr, $address:expr, $message:expr) => (
+        if let AsyncSink::NotReady(_) = $socket.start_send(($address, $message))? {
+            panic!("Must wait for poll_complete first");
+        }
+    );
+);
+
+/// Just to move some code from the overwhelmed `poll` method.
+macro_rules! poll_complete (
+    ($socket:expr) => (
+        match $socket.poll_complete() {
+            Ok(Async::Ready(_)) => {},
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(error) => {
+                warn!("Socket error: {}", error);
+                continue;
+            },
+        }
+    );
+);
+
+/// Polls the retransmission timer yielding the expiration flag.
+macro_rules! poll_retransmission (
+    ($timer:expr) => (
+        if let Some(ref mut timer) = $timer {
+            match timer.poll() {
+                Ok(Async::Ready(Some(expired))) => expired,
+                Ok(Async::Ready(None)) => panic!("Timer returned None"),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(error) => panic!("Timer error: {}", error),
+            }
+        } else {
+            panic!("A bug in the timer setting logic");
+        }
+    );
+);
+
+/// Just to move some code from the overwhelmed `poll` method.
+macro_rules! poll_delay (
+    ($delay:expr) => (
+        if let Some(ref mut delay) = $delay {
+            match delay.poll() {
+                Ok(Async::Ready(_)) => {},
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(error) => panic!("Timer error: {}", error),
+            }
+        } else {
+            panic!("A bug in the timer setting logic");
+        }
+    );
+);
+
+/// Panic if there is a bug in the state changing logic.
+macro_rules! panic_state(
+    ($from:expr, $to:expr) => (
+        panic!("Invalid state transcension from {} to {}", $from, $to);
+    );
+);
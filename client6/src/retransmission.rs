@@ -0,0 +1,185 @@
+//! The RFC 8415 retransmission module.
+//!
+//! RFC 8415 §15
+//! RT for the first message transmission is based on IRT:
+//!     RT = IRT + RAND*IRT
+//! RT for each subsequent message transmission is based on the previous value of RT:
+//!     RT = 2*RTprev + RAND*RTprev
+//! MRT specifies an upper bound on the value of RT (disregarding the randomization
+//! added by the use of RAND). If MRT has a value of 0, there is no upper limit on
+//! the value of RT. Otherwise:
+//!     if (RT > MRT)
+//!         RT = MRT + RAND*MRT
+//! MRC specifies an upper bound on the number of times a client may retransmit
+//! a message. MRD specifies an upper bound on the length of time a client may
+//! retransmit a message.
+
+use std::{
+    cell::Cell,
+    cmp,
+    time::{Duration, Instant},
+};
+
+use futures::{Async, Poll, Stream};
+use rand::{self, Rng};
+use tokio::timer::Error;
+
+use dhcp_backoff::{Backoff, Strategy};
+
+/// RAND is a random number chosen with a uniform distribution between -0.1 and +0.1.
+const RAND_AMPLITUDE: f64 = 0.1;
+
+/// The RFC 8415 retransmission algorithm implemented as a `Backoff` strategy.
+pub struct Rfc8415 {
+    /// The initial retransmission timeout (IRT).
+    initial: Duration,
+    /// The maximal retransmission timeout (MRT).
+    maximal_timeout: Option<Duration>,
+    /// The maximal retransmission count (MRC).
+    maximal_count: Option<u32>,
+    /// The moment the retransmission stops (MRD).
+    deadline: Option<Instant>,
+    /// Whether the first RAND must be strictly greater than 0.
+    is_solicit: bool,
+    /// The previous timeout (RTprev), since each timeout is based on it.
+    previous: Cell<Duration>,
+}
+
+impl Strategy for Rfc8415 {
+    fn delay(&self, attempt: u32) -> Option<Duration> {
+        let now = Instant::now();
+        if attempt > 1 {
+            let count_expired = self
+                .maximal_count
+                .map_or(false, |maximal_count| attempt > maximal_count);
+            let duration_expired = self.deadline.map_or(false, |deadline| now >= deadline);
+            if count_expired || duration_expired {
+                return None;
+            }
+        }
+
+        let current = if attempt == 1 {
+            let mut rand = random();
+            if self.is_solicit {
+                rand = rand.abs();
+                if rand == 0.0 {
+                    rand = RAND_AMPLITUDE;
+                }
+            }
+            add(self.initial, self.initial, rand)
+        } else {
+            let previous = self.previous.get();
+            let mut current = add(previous * 2, previous, random());
+            if let Some(maximal_timeout) = self.maximal_timeout {
+                if current > maximal_timeout {
+                    current = add(maximal_timeout, maximal_timeout, random());
+                }
+            }
+            current
+        };
+        self.previous.set(current);
+
+        // the next timeout never exceeds the MRD deadline
+        Some(match self.deadline {
+            Some(deadline) if deadline > now => cmp::min(current, deadline - now),
+            Some(_) => Duration::from_secs(0),
+            None => current,
+        })
+    }
+}
+
+/// The RFC 8415 retransmission timer implemented as a `Stream`.
+///
+/// Yields after each timeout.
+pub struct Retransmission {
+    /// The timer himself.
+    backoff: Backoff<Rfc8415>,
+    /// The exchange start used for the `Elapsed Time` option.
+    started: Instant,
+}
+
+impl Retransmission {
+    /// Constructs a timer and starts it.
+    ///
+    /// * `initial`
+    /// The initial retransmission timeout (IRT).
+    ///
+    /// * `maximal_timeout`
+    /// The maximal retransmission timeout (MRT). `None` means no limit.
+    ///
+    /// * `maximal_count`
+    /// The maximal retransmission count (MRC). `None` means no limit.
+    ///
+    /// * `maximal_duration`
+    /// The maximal retransmission duration (MRD). `None` means no limit.
+    ///
+    /// * `is_solicit`
+    /// RFC 8415 §18.2.1
+    /// The client collects valid Advertise messages until the first RT has elapsed.
+    /// Also, the first RT MUST be selected to be strictly greater than IRT
+    /// by choosing RAND to be strictly greater than 0.
+    pub fn new(
+        initial: Duration,
+        maximal_timeout: Option<Duration>,
+        maximal_count: Option<u32>,
+        maximal_duration: Option<Duration>,
+        is_solicit: bool,
+    ) -> Self {
+        let now = Instant::now();
+        let strategy = Rfc8415 {
+            initial,
+            maximal_timeout,
+            maximal_count,
+            deadline: maximal_duration.map(|duration| now + duration),
+            is_solicit,
+            previous: Cell::new(initial),
+        };
+
+        Retransmission {
+            backoff: Backoff::new(strategy),
+            started: now,
+        }
+    }
+
+    /// The number of transmissions so far.
+    pub fn count(&self) -> u32 {
+        self.backoff.attempt()
+    }
+
+    /// The time elapsed since the exchange start in hundredths of a second (RFC 8415 §21.9).
+    pub fn elapsed_time(&self) -> u16 {
+        let elapsed = self.started.elapsed();
+        let hundredths = elapsed.as_secs() * 100 + u64::from(elapsed.subsec_millis() / 10);
+        cmp::min(hundredths, u64::from(u16::max_value())) as u16
+    }
+}
+
+impl Stream for Retransmission {
+    type Item = bool;
+    type Error = Error;
+
+    /// Yields the expiration flag.
+    ///
+    /// If the flag is set, the message must not be retransmitted anymore.
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match try_ready!(self.backoff.poll()) {
+            Some((_, is_last)) => Ok(Async::Ready(Some(is_last))),
+            None => Ok(Async::Ready(Some(true))),
+        }
+    }
+}
+
+fn random() -> f64 {
+    rand::thread_rng().gen_range(-RAND_AMPLITUDE, RAND_AMPLITUDE)
+}
+
+/// Computes `base + rand * value`.
+fn add(base: Duration, value: Duration, rand: f64) -> Duration {
+    let nanos = value.as_secs() as f64 * 1e9 + f64::from(value.subsec_nanos());
+    let offset = Duration::from_nanos((nanos * rand.abs()) as u64);
+    if rand < 0.0 {
+        base - offset
+    } else {
+        base + offset
+    }
+}
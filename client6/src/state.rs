@@ -0,0 +1,322 @@
+//! The DHCPv6 client state module.
+
+use std::{
+    cmp, fmt,
+    time::{Duration, Instant},
+};
+
+use rand;
+use tokio::timer::Delay;
+
+use dhcp_protocol::v6::{constants::*, IaNa, IaPd, Message, Status, StatusCode};
+
+use retransmission::Retransmission;
+
+/// RFC 8415 §7.6 Transmission and Retransmission Parameters (in seconds).
+const SOL_TIMEOUT: u64 = 1;
+const SOL_MAX_RT: u64 = 3600;
+const REQ_TIMEOUT: u64 = 1;
+const REQ_MAX_RT: u64 = 30;
+const REQ_MAX_RC: u32 = 10;
+const REN_TIMEOUT: u64 = 10;
+const REN_MAX_RT: u64 = 600;
+const REB_TIMEOUT: u64 = 10;
+const REB_MAX_RT: u64 = 600;
+
+/// Is used if a server sets T1 to zero (RFC 8415 §14.2).
+const RENEWAL_TIME_FACTOR: f64 = 0.5;
+/// Is used if a server sets T2 to zero (RFC 8415 §14.2).
+const REBINDING_TIME_FACTOR: f64 = 0.8;
+
+/// RFC 8415 client states.
+///
+/// Unlike DHCPv4, the RFC does not name the states explicitly,
+/// so they are named after the message the client is waiting a response to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dhcp6State {
+    Init,
+    Soliciting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+}
+
+impl fmt::Display for Dhcp6State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Dhcp6State::*;
+        match self {
+            Init => write!(f, "INIT"),
+            Soliciting => write!(f, "SOLICITING"),
+            Requesting => write!(f, "REQUESTING"),
+            Bound => write!(f, "BOUND"),
+            Renewing => write!(f, "RENEWING"),
+            Rebinding => write!(f, "REBINDING"),
+        }
+    }
+}
+
+/// Mutable `Client` data.
+pub struct State {
+    /// Current client state.
+    dhcp_state: Dhcp6State,
+    /// Generated by the client for each exchange.
+    transaction_id: u32,
+    /// The selected or leasing server DUID.
+    server_id: Option<Vec<u8>>,
+    /// The offered or leased address identity association.
+    ia_na: Option<IaNa>,
+    /// The offered or delegated prefix identity association.
+    ia_pd: Option<IaPd>,
+    /// The preference of the best `ADVERTISE` collected so far.
+    best_preference: Option<u8>,
+
+    /// The renewal time (so called T1).
+    renewal_after: Duration,
+    /// The rebinding time (so called T2).
+    rebinding_after: Duration,
+    /// The shortest valid lifetime of the addresses and prefixes.
+    expiration_after: Duration,
+
+    /// The current message retransmission timer.
+    pub timer_retransmission: Option<Retransmission>,
+    /// The renewal timer (so called T1).
+    pub timer_renewal: Option<Delay>,
+}
+
+impl State {
+    /// Constructs a default state.
+    pub fn new() -> Self {
+        State {
+            dhcp_state: Dhcp6State::Init,
+            transaction_id: Self::generate_xid(),
+            server_id: None,
+            ia_na: None,
+            ia_pd: None,
+            best_preference: None,
+
+            renewal_after: Duration::default(),
+            rebinding_after: Duration::default(),
+            expiration_after: Duration::default(),
+
+            timer_retransmission: None,
+            timer_renewal: None,
+        }
+    }
+
+    /// Moves the client from one state to another, starting the timers.
+    ///
+    /// `response` is the `ADVERTISE` for `Requesting` and the `REPLY` for `Bound`.
+    ///
+    /// # Panics
+    /// On an unexpected state transcension.
+    pub fn transcend(&mut self, from: Dhcp6State, to: Dhcp6State, response: Option<&Message>) {
+        use self::Dhcp6State::*;
+        trace!("Transcending from {} to {}", from, to);
+
+        match (from, to) {
+            (_, Init) => {
+                self.server_id = None;
+                self.ia_na = None;
+                self.ia_pd = None;
+                self.best_preference = None;
+                self.timer_retransmission = None;
+                self.timer_renewal = None;
+            }
+            (Init, Soliciting) => {
+                self.transaction_id = Self::generate_xid();
+                self.timer_retransmission = Some(Retransmission::new(
+                    Duration::from_secs(SOL_TIMEOUT),
+                    Some(Duration::from_secs(SOL_MAX_RT)),
+                    None,
+                    None,
+                    true,
+                ));
+            }
+            (Soliciting, Requesting) => {
+                self.transaction_id = Self::generate_xid();
+                self.timer_retransmission = Some(Retransmission::new(
+                    Duration::from_secs(REQ_TIMEOUT),
+                    Some(Duration::from_secs(REQ_MAX_RT)),
+                    Some(REQ_MAX_RC),
+                    None,
+                    false,
+                ));
+            }
+            (Requesting, Bound) | (Renewing, Bound) | (Rebinding, Bound) => {
+                let reply = expect!(response);
+                self.server_id = reply.options.server_id.to_owned();
+                self.set_lease(leased_ia_na(reply).cloned(), leased_ia_pd(reply).cloned());
+                self.timer_retransmission = None;
+                self.timer_renewal = Some(Delay::new(Instant::now() + self.renewal_after));
+            }
+            (Bound, Renewing) => {
+                self.transaction_id = Self::generate_xid();
+                self.timer_renewal = None;
+                self.timer_retransmission = Some(Retransmission::new(
+                    Duration::from_secs(REN_TIMEOUT),
+                    Some(Duration::from_secs(REN_MAX_RT)),
+                    None,
+                    Some(self.rebinding_after - self.renewal_after),
+                    false,
+                ));
+            }
+            (Renewing, Rebinding) => {
+                self.transaction_id = Self::generate_xid();
+                self.timer_retransmission = Some(Retransmission::new(
+                    Duration::from_secs(REB_TIMEOUT),
+                    Some(Duration::from_secs(REB_MAX_RT)),
+                    None,
+                    Some(self.expiration_after - self.rebinding_after),
+                    false,
+                ));
+            }
+            _ => panic_state!(from, to),
+        }
+
+        self.dhcp_state = to;
+    }
+
+    /// Remembers the `ADVERTISE` if it is better than the previous one.
+    ///
+    /// Returns `true` if it has been remembered.
+    pub fn collect_advertise(&mut self, advertise: &Message) -> bool {
+        let preference = advertise.options.preference.unwrap_or(0);
+        if let Some(best_preference) = self.best_preference {
+            if preference <= best_preference {
+                return false;
+            }
+        }
+        self.best_preference = Some(preference);
+        self.server_id = advertise.options.server_id.to_owned();
+        self.ia_na = leased_ia_na(advertise).cloned();
+        self.ia_pd = leased_ia_pd(advertise).cloned();
+        true
+    }
+
+    pub fn dhcp_state(&self) -> Dhcp6State {
+        self.dhcp_state
+    }
+
+    pub fn xid(&self) -> u32 {
+        self.transaction_id
+    }
+
+    pub fn server_id(&self) -> Option<Vec<u8>> {
+        self.server_id.to_owned()
+    }
+
+    pub fn ia_na(&self) -> Option<IaNa> {
+        self.ia_na.to_owned()
+    }
+
+    pub fn ia_pd(&self) -> Option<IaPd> {
+        self.ia_pd.to_owned()
+    }
+
+    pub fn best_preference(&self) -> Option<u8> {
+        self.best_preference
+    }
+
+    /// The time elapsed since the current exchange start in hundredths of a second.
+    pub fn elapsed_time(&self) -> u16 {
+        self.timer_retransmission
+            .as_ref()
+            .map_or(0, |timer| timer.elapsed_time())
+    }
+
+    fn generate_xid() -> u32 {
+        rand::random::<u32>() & TRANSACTION_ID_MASK
+    }
+
+    /// Records the lease times.
+    ///
+    /// The timers are set by the identity association which expires first.
+    fn set_lease(&mut self, ia_na: Option<IaNa>, ia_pd: Option<IaPd>) {
+        let mut times = Vec::with_capacity(2);
+        if let Some(ref ia_na) = ia_na {
+            let lifetimes = ia_na
+                .addresses
+                .iter()
+                .map(|address| (address.preferred_lifetime, address.valid_lifetime));
+            times.push(Self::times(ia_na.renewal_time, ia_na.rebinding_time, lifetimes));
+        }
+        if let Some(ref ia_pd) = ia_pd {
+            let lifetimes = ia_pd
+                .prefixes
+                .iter()
+                .map(|prefix| (prefix.preferred_lifetime, prefix.valid_lifetime));
+            times.push(Self::times(ia_pd.renewal_time, ia_pd.rebinding_time, lifetimes));
+        }
+
+        let renewal_time = times.iter().map(|times| times.0).min().unwrap_or(0);
+        let rebinding_time = times.iter().map(|times| times.1).min().unwrap_or(0);
+        let valid_lifetime = times.iter().map(|times| times.2).min().unwrap_or(0);
+
+        self.renewal_after = Self::lifetime(renewal_time);
+        self.rebinding_after = Self::lifetime(rebinding_time);
+        self.expiration_after = Self::lifetime(valid_lifetime);
+        self.ia_na = ia_na;
+        self.ia_pd = ia_pd;
+    }
+
+    /// Computes T1, T2 and the shortest valid lifetime of an identity association.
+    fn times<L>(renewal_time: u32, rebinding_time: u32, lifetimes: L) -> (u32, u32, u32)
+    where
+        L: Iterator<Item = (u32, u32)>,
+    {
+        let (preferred_lifetime, valid_lifetime) = lifetimes.fold(
+            (INFINITY, INFINITY),
+            |(preferred_min, valid_min), (preferred, valid)| {
+                (cmp::min(preferred_min, preferred), cmp::min(valid_min, valid))
+            },
+        );
+
+        // the zero T1 and T2 are left to the client discretion (RFC 8415 §14.2)
+        let renewal_time = match renewal_time {
+            0 => ((preferred_lifetime as f64) * RENEWAL_TIME_FACTOR) as u32,
+            renewal_time => renewal_time,
+        };
+        let rebinding_time = match rebinding_time {
+            0 => ((preferred_lifetime as f64) * REBINDING_TIME_FACTOR) as u32,
+            rebinding_time => rebinding_time,
+        };
+        let rebinding_time = cmp::max(rebinding_time, renewal_time);
+        let valid_lifetime = cmp::max(valid_lifetime, rebinding_time);
+        (renewal_time, rebinding_time, valid_lifetime)
+    }
+
+    /// The infinite lifetime is replaced with a year to keep the timers sane.
+    fn lifetime(seconds: u32) -> Duration {
+        match seconds {
+            INFINITY => Duration::from_secs(365 * 24 * 3600),
+            seconds => Duration::from_secs(u64::from(seconds)),
+        }
+    }
+}
+
+/// Checks whether a status code option reports a failure.
+fn is_failure(status_code: &Option<StatusCode>) -> bool {
+    match status_code {
+        Some(ref status_code) => status_code.status() != Status::Success,
+        None => false,
+    }
+}
+
+/// Returns the first `IA_NA` of the message if it contains any addresses.
+pub fn leased_ia_na(message: &Message) -> Option<&IaNa> {
+    message
+        .options
+        .ia_na
+        .first()
+        .filter(|ia_na| !is_failure(&ia_na.status_code) && !ia_na.addresses.is_empty())
+}
+
+/// Returns the first `IA_PD` of the message if it contains any prefixes.
+pub fn leased_ia_pd(message: &Message) -> Option<&IaPd> {
+    message
+        .options
+        .ia_pd
+        .first()
+        .filter(|ia_pd| !is_failure(&ia_pd.status_code) && !ia_pd.prefixes.is_empty())
+}
@@ -0,0 +1,114 @@
+//! The capture filters in the style of the `tcpdump` expressions.
+//!
+//! The primitives are `mac <address>`, `xid <hex>` and `type <name>`, e.g. `type discover`.
+//! They may be negated with `not` and joined with `and` and `or`, `and` binding tighter.
+//! An empty filter passes every message.
+
+use eui48::MacAddress;
+
+use dhcp_protocol::{Message, MessageType};
+
+/// A single condition on the message.
+enum Primitive {
+    /// The client hardware address.
+    Mac(MacAddress),
+    /// The transaction ID.
+    Xid(u32),
+    /// The DHCP message type.
+    Type(MessageType),
+}
+
+impl Primitive {
+    fn matches(&self, message: &Message) -> bool {
+        match *self {
+            Primitive::Mac(ref address) => message.client_hardware_address == *address,
+            Primitive::Xid(xid) => message.transaction_id == xid,
+            Primitive::Type(kind) => {
+                message.options.dhcp_message_type.map(|own| own as u8) == Some(kind as u8)
+            }
+        }
+    }
+}
+
+/// A possibly negated primitive.
+struct Term {
+    primitive: Primitive,
+    negated: bool,
+}
+
+/// The alternatives of the conjunctions of the terms.
+pub struct Filter {
+    alternatives: Vec<Vec<Term>>,
+}
+
+impl Filter {
+    /// Parses the filter from the command line words.
+    ///
+    /// # Errors
+    /// The description of the first invalid word.
+    pub fn parse(words: &[String]) -> Result<Self, String> {
+        let mut alternatives = Vec::new();
+        if words.is_empty() {
+            return Ok(Filter { alternatives });
+        }
+
+        let mut terms = Vec::new();
+        let mut words = words.iter().map(|word| word.as_str());
+        loop {
+            let mut word = words.next().ok_or("The filter ends unexpectedly")?;
+            let negated = word == "not";
+            if negated {
+                word = words.next().ok_or("The filter ends after 'not'")?;
+            }
+            let value = words
+                .next()
+                .ok_or_else(|| format!("No value after '{}'", word))?;
+            let primitive = match word {
+                "mac" => Primitive::Mac(
+                    MacAddress::parse_str(value)
+                        .map_err(|_| format!("Invalid MAC address '{}'", value))?,
+                ),
+                "xid" => Primitive::Xid(
+                    u32::from_str_radix(value.trim_left_matches("0x"), 16)
+                        .map_err(|_| format!("Invalid transaction ID '{}'", value))?,
+                ),
+                "type" => Primitive::Type(
+                    message_type(value).ok_or_else(|| format!("Invalid message type '{}'", value))?,
+                ),
+                primitive => return Err(format!("Unknown primitive '{}'", primitive)),
+            };
+            terms.push(Term { primitive, negated });
+
+            match words.next() {
+                Some("and") => {}
+                Some("or") => alternatives.push(terms.drain(..).collect()),
+                Some(word) => return Err(format!("Expected 'and' or 'or', found '{}'", word)),
+                None => break,
+            }
+        }
+        alternatives.push(terms);
+        Ok(Filter { alternatives })
+    }
+
+    /// Checks whether the message passes the filter.
+    pub fn matches(&self, message: &Message) -> bool {
+        self.alternatives.is_empty()
+            || self.alternatives.iter().any(|terms| {
+                terms
+                    .iter()
+                    .all(|term| term.primitive.matches(message) != term.negated)
+            })
+    }
+}
+
+/// Finds the message type by its name with or without the `DHCP` prefix, case-insensitively.
+fn message_type(name: &str) -> Option<MessageType> {
+    let name = name.to_uppercase();
+    (1..=u8::max_value())
+        .map(MessageType::from)
+        .filter(|kind| *kind as u8 != MessageType::Undefined as u8)
+        .find(|kind| {
+            let full = kind.to_string();
+            full == name || full.trim_left_matches("DHCP") == name
+        })
+}
@@ -0,0 +1,55 @@
+//! Decoding the DHCP messages from the captured Ethernet frames.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddrV4},
+};
+
+use etherparse::{InternetSlice, LinkSlice, SlicedPacket, TransportSlice};
+use eui48::MacAddress;
+
+use dhcp_protocol::{Message, DHCP_PORT_CLIENT, DHCP_PORT_PXE, DHCP_PORT_SERVER};
+
+/// A DHCP message with the link and network layer addresses it has been sent with.
+pub struct Frame {
+    pub source_mac: MacAddress,
+    pub source: SocketAddrV4,
+    pub destination: SocketAddrV4,
+    pub message: Message,
+}
+
+/// Decodes the Ethernet, IPv4 and UDP headers and the DHCP payload.
+///
+/// Returns `None` if the frame does not carry a datagram to or from a DHCP port.
+///
+/// # Errors
+/// `io::Error` if the DHCP payload is malformed.
+pub fn decode(frame: &[u8]) -> Option<io::Result<Frame>> {
+    let packet = SlicedPacket::from_ethernet(frame).ok()?;
+    let source_mac = match packet.link {
+        Some(LinkSlice::Ethernet2(ref header)) => MacAddress::from_bytes(header.source()).ok()?,
+        _ => return None,
+    };
+    let (source_ip, destination_ip): (Ipv4Addr, Ipv4Addr) = match packet.ip {
+        Some(InternetSlice::Ipv4(ref header)) => (header.source_addr(), header.destination_addr()),
+        _ => return None,
+    };
+    let (source_port, destination_port) = match packet.transport {
+        Some(TransportSlice::Udp(ref header)) => (header.source_port(), header.destination_port()),
+        _ => return None,
+    };
+    if !is_dhcp_port(source_port) && !is_dhcp_port(destination_port) {
+        return None;
+    }
+
+    Some(Message::from_bytes(packet.payload).map(|message| Frame {
+        source_mac,
+        source: SocketAddrV4::new(source_ip, source_port),
+        destination: SocketAddrV4::new(destination_ip, destination_port),
+        message,
+    }))
+}
+
+fn is_dhcp_port(port: u16) -> bool {
+    port == DHCP_PORT_SERVER || port == DHCP_PORT_CLIENT || port == DHCP_PORT_PXE
+}
@@ -0,0 +1,112 @@
+//! Captures the DHCP traffic on an interface and prints the decoded messages live.
+//!
+//! Run this with `CAP_NET_RAW` as `dhcpdump [-q] <interface> [filter]`.
+//!
+//! A line with the time, the addresses, the message type and the transaction ID
+//! is printed for every message, followed by its fields and options without `-q`.
+//! The filter is described in the `filter` module, e.g. `type discover or type request`.
+//!
+//! Only the Linux `AF_PACKET` capture is supported.
+
+mod filter;
+mod frame;
+#[cfg(target_os = "linux")]
+mod socket;
+
+extern crate chrono;
+extern crate etherparse;
+extern crate eui48;
+#[cfg(target_os = "linux")]
+extern crate libc;
+
+extern crate dhcp_protocol;
+
+use std::io;
+
+use chrono::prelude::*;
+
+use filter::Filter;
+use frame::Frame;
+#[cfg(target_os = "linux")]
+use socket::CaptureSocket;
+
+/// Fits any Ethernet frame.
+#[cfg(target_os = "linux")]
+const BUFFER_SIZE: usize = 65536;
+
+const USAGE: &str = "Usage: dhcpdump [-q] <interface> [[not] mac|xid|type <value> [and|or ...]]";
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let is_quiet = args.first().map_or(false, |arg| arg == "-q");
+    if is_quiet {
+        args.remove(0);
+    }
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+    let interface = args.remove(0);
+    let filter = match Filter::parse(&args) {
+        Ok(filter) => filter,
+        Err(error) => {
+            eprintln!("Filter error: {}\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(error) = run(&interface, &filter, is_quiet) {
+        eprintln!("Capture error on {}: {}", interface, error);
+        std::process::exit(1);
+    }
+}
+
+/// Captures and prints the messages until an error occurs.
+#[cfg(target_os = "linux")]
+fn run(interface: &str, filter: &Filter, is_quiet: bool) -> io::Result<()> {
+    let socket = CaptureSocket::new(interface)?;
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        let amount = socket.recv(&mut buffer)?;
+        match frame::decode(&buffer[..amount]) {
+            Some(Ok(frame)) => {
+                if filter.matches(&frame.message) {
+                    print_frame(&frame, is_quiet);
+                }
+            }
+            Some(Err(error)) => println!("{} malformed DHCP message: {}", timestamp(), error),
+            None => {}
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run(_interface: &str, _filter: &Filter, _is_quiet: bool) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "The capture is only supported on Linux",
+    ))
+}
+
+fn print_frame(frame: &Frame, is_quiet: bool) {
+    let kind = match frame.message.options.dhcp_message_type {
+        Some(kind) => kind.to_string(),
+        None => "BOOTP".to_owned(),
+    };
+    println!(
+        "{} {} {} > {} {} xid {:#010x}",
+        timestamp(),
+        frame.source_mac.to_hex_string(),
+        frame.source,
+        frame.destination,
+        kind,
+        frame.message.transaction_id,
+    );
+    if !is_quiet {
+        println!("{}", frame.message);
+    }
+}
+
+fn timestamp() -> String {
+    Local::now().format("%H:%M:%S%.6f").to_string()
+}
@@ -0,0 +1,80 @@
+//! The `AF_PACKET` socket capturing the IPv4 frames of an interface on Linux.
+//!
+//! Both the incoming and the outgoing frames are captured. The interface is not switched
+//! to the promiscuous mode, so the unicasts between the other hosts are not seen.
+
+use std::{ffi::CString, io, mem, os::unix::io::RawFd};
+
+use libc::{self, c_int, c_void, sockaddr_ll, socklen_t};
+
+/// `ETH_P_IP` in the network byte order, as `AF_PACKET` expects.
+const PROTOCOL_IP: u16 = 0x0800;
+
+/// A raw link-layer socket bound to an interface.
+pub struct CaptureSocket {
+    fd: RawFd,
+}
+
+impl CaptureSocket {
+    /// Opens the socket on the interface, which requires `CAP_NET_RAW`.
+    ///
+    /// # Errors
+    /// `io::Error` if the interface is not found or the socket cannot be opened.
+    pub fn new(iface_name: &str) -> io::Result<Self> {
+        let name = CString::new(iface_name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid interface name"))?;
+        let index = match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+            0 => return Err(io::Error::last_os_error()),
+            index => index as c_int,
+        };
+
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                c_int::from(PROTOCOL_IP.to_be()),
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = CaptureSocket { fd };
+
+        let mut address: sockaddr_ll = unsafe { mem::zeroed() };
+        address.sll_family = libc::AF_PACKET as u16;
+        address.sll_protocol = PROTOCOL_IP.to_be();
+        address.sll_ifindex = index;
+        let result = unsafe {
+            libc::bind(
+                socket.fd,
+                &address as *const sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<sockaddr_ll>() as socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(socket)
+    }
+
+    /// Blocks until a frame is captured and returns its size.
+    ///
+    /// # Errors
+    /// `io::Error` on a receiving error.
+    pub fn recv(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let received =
+            unsafe { libc::recv(self.fd, buffer.as_mut_ptr() as *mut c_void, buffer.len(), 0) };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(received as usize)
+    }
+}
+
+impl Drop for CaptureSocket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Compares the per-packet receiving with the batched `recvmmsg` one on the loopback,
+//! and with the `io_uring` one if the `io-uring-receive` feature is enabled.
+//!
+//! Every round sends a burst of `DHCPDISCOVER` packets and measures how long the socket
+//! takes to receive and decode them. The burst size and the batch size, which is the ring
+//! size as well, are the optional arguments, 512 and 64 by default:
+//!
+//! ```text
+//! cargo run --release --features io-uring-receive --example receive_benchmark -- 512 64
+//! ```
+
+extern crate dhcp_framed;
+extern crate net2;
+extern crate tokio;
+
+#[cfg(target_os = "linux")]
+use std::{
+    net::{SocketAddr, UdpSocket as StdUdpSocket},
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "linux")]
+use dhcp_framed::DhcpFramed;
+#[cfg(target_os = "linux")]
+use net2::{UdpBuilder, UdpSocketExt};
+#[cfg(target_os = "linux")]
+use tokio::{net::UdpSocket, prelude::*, reactor::Handle};
+
+#[cfg(target_os = "linux")]
+const ROUNDS: usize = 200;
+/// Holds a whole burst, so the packets are not dropped by the kernel.
+#[cfg(target_os = "linux")]
+const RECEIVE_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+#[cfg(target_os = "linux")]
+fn main() {
+    let mut args = std::env::args().skip(1).map(|arg| {
+        arg.parse::<usize>()
+            .expect("The arguments must be positive numbers")
+    });
+    let burst = args.next().unwrap_or(512);
+    let batch = args.next().unwrap_or(64);
+
+    let per_packet = measure(burst, |_| {});
+    let batched = measure(burst, |framed| framed.enable_batching(batch));
+    let total = (ROUNDS * burst) as f64;
+    println!("{} rounds of {} packets", ROUNDS, burst);
+    println!("per-packet: {:>10.0} packets/s", total / seconds(per_packet));
+    println!("batched {:>3}: {:>9.0} packets/s", batch, total / seconds(batched));
+    #[cfg(feature = "io-uring-receive")]
+    {
+        let ring = measure(burst, |framed| {
+            framed
+                .enable_ring(batch)
+                .expect("io_uring setting up error")
+        });
+        println!("io_uring {:>3}: {:>8.0} packets/s", batch, total / seconds(ring));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    println!("The batched receiving is supported only on Linux");
+}
+
+/// Returns the time spent receiving all the rounds.
+#[cfg(target_os = "linux")]
+fn measure<F>(burst: usize, setup: F) -> Duration
+where
+    F: FnOnce(&mut DhcpFramed),
+{
+    let receiver = UdpBuilder::new_v4()
+        .expect("Socket creating error")
+        .bind("127.0.0.1:0")
+        .expect("Socket binding error");
+    receiver
+        .set_recv_buffer_size(RECEIVE_BUFFER_SIZE)
+        .expect("Receive buffer setting error");
+    let destination: SocketAddr = receiver.local_addr().expect("Local address getting error");
+    let receiver =
+        UdpSocket::from_std(receiver, &Handle::default()).expect("Socket registering error");
+    let mut framed = DhcpFramed::new(receiver).expect("Socket creating error");
+    setup(&mut framed);
+
+    let sender = StdUdpSocket::bind("127.0.0.1:0").expect("Socket binding error");
+    let packet = discover();
+
+    let mut elapsed = Duration::default();
+    for _ in 0..ROUNDS {
+        for _ in 0..burst {
+            sender
+                .send_to(&packet, &destination)
+                .expect("Packet sending error");
+        }
+        let start = Instant::now();
+        let received = (&mut framed)
+            .take(burst as u64)
+            .fold(0, |received, _| Ok::<usize, std::io::Error>(received + 1))
+            .wait()
+            .expect("Packet receiving error");
+        elapsed += start.elapsed();
+        assert_eq!(received, burst, "Some packets have been lost");
+    }
+    elapsed
+}
+
+/// A minimal `DHCPDISCOVER` padded to the common 300 bytes.
+#[cfg(target_os = "linux")]
+fn discover() -> Vec<u8> {
+    let mut packet = vec![0u8; 240];
+    packet[0] = 1; // BOOTREQUEST
+    packet[1] = 1; // Ethernet
+    packet[2] = 6; // the hardware address length
+    packet[28..34].copy_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    packet[236..240].copy_from_slice(&[99, 130, 83, 99]);
+    packet.extend_from_slice(&[53, 1, 1, 255]);
+    packet.resize(300, 0);
+    packet
+}
+
+#[cfg(target_os = "linux")]
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
@@ -0,0 +1,135 @@
+//! The transport driven by the async-std reactor.
+//!
+//! The `futures` 0.1 task is woken up by the reactor through a `std` waker,
+//! so the tokio runtime polling the framing and the async-std reactor thread
+//! may work side by side.
+
+use std::{
+    convert::TryFrom,
+    net::{SocketAddr, UdpSocket as StdUdpSocket},
+    sync::Arc,
+    task::{Context, Poll as StdPoll, Wake, Waker},
+};
+
+use async_io::Async as Watcher;
+use async_std::net::UdpSocket;
+use futures::task::{self, Task};
+use tokio::{io, prelude::*};
+
+use transport::DhcpTransport;
+
+/// An async-std socket.
+///
+/// The datagrams are received and sent without blocking once the reactor
+/// reports the socket is ready, like async-std does itself.
+pub struct AsyncStdTransport {
+    socket: Watcher<StdUdpSocket>,
+}
+
+impl AsyncStdTransport {
+    /// Wraps an async-std socket.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot be registered again.
+    pub fn new(socket: UdpSocket) -> io::Result<Self> {
+        Self::from_std(StdUdpSocket::try_from(socket)?)
+    }
+
+    pub fn get_ref(&self) -> &StdUdpSocket {
+        self.socket.get_ref()
+    }
+}
+
+/// Notifies the `futures` 0.1 task woken up by the reactor.
+struct TaskWaker(Task);
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.notify();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.notify();
+    }
+}
+
+/// Tries the operation until it would block and the reactor will wake the current task up.
+fn poll_io<T, R, O>(
+    socket: &Watcher<StdUdpSocket>,
+    ready: R,
+    mut operation: O,
+) -> Poll<T, io::Error>
+where
+    R: Fn(&Watcher<StdUdpSocket>, &mut Context) -> StdPoll<io::Result<()>>,
+    O: FnMut(&StdUdpSocket) -> io::Result<T>,
+{
+    let waker = Waker::from(Arc::new(TaskWaker(task::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match operation(socket.get_ref()) {
+            Ok(result) => return Ok(Async::Ready(result)),
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => {}
+            Err(error) => return Err(error),
+        }
+        match ready(socket, &mut context) {
+            StdPoll::Ready(Ok(())) => {}
+            StdPoll::Ready(Err(error)) => return Err(error),
+            StdPoll::Pending => return Ok(Async::NotReady),
+        }
+    }
+}
+
+impl DhcpTransport for AsyncStdTransport {
+    fn bind_broadcast(addr: &SocketAddr) -> io::Result<Self> {
+        let socket = StdUdpSocket::bind(addr)?;
+        socket.set_broadcast(true)?;
+        Self::from_std(socket)
+    }
+
+    fn from_std(socket: StdUdpSocket) -> io::Result<Self> {
+        Ok(AsyncStdTransport {
+            socket: Watcher::new(socket)?,
+        })
+    }
+
+    fn poll_recv_from(&mut self, buf: &mut [u8]) -> Poll<(usize, SocketAddr), io::Error> {
+        poll_io(&self.socket, Watcher::poll_readable, |socket| socket.recv_from(buf))
+    }
+
+    fn poll_send_to(&mut self, buf: &[u8], target: &SocketAddr) -> Poll<usize, io::Error> {
+        poll_io(&self.socket, Watcher::poll_writable, |socket| socket.send_to(buf, target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use tokio::{runtime::current_thread::Runtime, timer::Timeout};
+
+    use codec::tests::discover;
+    use transport::TransportFramed;
+
+    #[test]
+    fn receives_past_malformed_datagram() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let receiver = AsyncStdTransport::bind_broadcast(&address).unwrap();
+        let destination = receiver.get_ref().local_addr().unwrap();
+        let sender = StdUdpSocket::bind(address).unwrap();
+        sender.send_to(&[1, 2, 3], destination).unwrap();
+        sender.send_to(&discover(), destination).unwrap();
+
+        let future = TransportFramed::new(receiver)
+            .into_future()
+            .map_err(|(error, _)| error);
+        let (received, _) = Runtime::new()
+            .unwrap()
+            .block_on(Timeout::new(future, Duration::from_secs(5)))
+            .unwrap();
+        let (source, message) = received.expect("The stream has ended");
+        assert_eq!(message.transaction_id, 0x1234_5678);
+        assert_eq!(source, sender.local_addr().unwrap());
+    }
+}
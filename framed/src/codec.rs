@@ -0,0 +1,314 @@
+//! The DHCP codecs for `tokio::net::UdpFramed` and the other framed transports.
+//!
+//! A malformed datagram is consumed and decoded as `None`. The framings decoding
+//! until the datagram is consumed go on receiving after it, like the ones made with
+//! `DhcpCodec::framed` and the `tokio-util` 0.7 `UdpFramed`, while the `UdpFramed::new`
+//! stream ends at it.
+//!
+//! The `tokio-util-codec` feature implements the `tokio-util` codec traits as well,
+//! so the codecs may be used with the `std` futures runtimes.
+//!
+//! `DhcpFramed` is still needed for the `IP_PKTINFO` interfaces, the batched receiving,
+//! the reply headers and the packet capture, which are not expressible with a codec.
+
+use bytes::{BufMut, BytesMut};
+use tokio::{
+    codec::{Decoder, Encoder},
+    io,
+    net::UdpSocket,
+};
+use tokio_udp::UdpFramed;
+
+use dhcp_protocol::{v6, Message};
+
+use socket::BUFFER_WRITE_CAPACITY;
+
+/// Decodes and encodes the DHCPv4 messages, a message per datagram.
+///
+/// ```ignore
+/// let framed = DhcpCodec::framed(socket);
+/// ```
+pub struct DhcpCodec {
+    /// The messages are serialized into a slice.
+    buf_write: Vec<u8>,
+}
+
+impl DhcpCodec {
+    pub fn new() -> Self {
+        DhcpCodec {
+            buf_write: vec![0u8; BUFFER_WRITE_CAPACITY],
+        }
+    }
+
+    /// Frames the socket with the codec, receiving past the malformed datagrams.
+    pub fn framed(socket: UdpSocket) -> UdpFramed<Self> {
+        UdpFramed::with_decode(socket, Self::new(), true)
+    }
+
+    /// Decodes a whole datagram, logging it if it is malformed.
+    fn decode_datagram(data: &[u8]) -> Option<Message> {
+        match Message::from_bytes(data) {
+            Ok(message) => Some(message),
+            Err(error) => {
+                warn!("Malformed datagram is skipped: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Serializes the message into the write buffer, returning the datagram.
+    fn encode_datagram(&mut self, message: &Message, max_size: Option<u16>) -> io::Result<&[u8]> {
+        let amount = message.to_bytes(&mut self.buf_write, max_size)?;
+        Ok(&self.buf_write[..amount])
+    }
+}
+
+impl Default for DhcpCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for DhcpCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    /// The datagram is consumed even if it cannot be decoded.
+    /// A malformed datagram is logged and decoded as `None`.
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        if src.is_empty() {
+            return Ok(None);
+        }
+        let message = Self::decode_datagram(&src[..]);
+        // the buffer is cleared instead of split, so its capacity is reused
+        src.clear();
+        Ok(message)
+    }
+}
+
+impl Encoder for DhcpCodec {
+    /// The message and its maximal size like in the `DhcpFramed` sink.
+    type Item = (Message, Option<u16>);
+    type Error = io::Error;
+
+    /// # Errors
+    /// `io::Error` on an encoding error.
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> io::Result<()> {
+        let (message, max_size) = item;
+        let datagram = self.encode_datagram(&message, max_size)?;
+        dst.reserve(datagram.len());
+        dst.put_slice(datagram);
+        Ok(())
+    }
+}
+
+/// The DHCPv6 counterpart of `DhcpCodec`.
+pub struct Dhcp6Codec {
+    /// The messages are serialized into a slice.
+    buf_write: Vec<u8>,
+}
+
+impl Dhcp6Codec {
+    pub fn new() -> Self {
+        Dhcp6Codec {
+            buf_write: vec![0u8; BUFFER_WRITE_CAPACITY],
+        }
+    }
+
+    /// Frames the socket with the codec, receiving past the malformed datagrams.
+    pub fn framed(socket: UdpSocket) -> UdpFramed<Self> {
+        UdpFramed::with_decode(socket, Self::new(), true)
+    }
+
+    /// Decodes a whole datagram, logging it if it is malformed.
+    fn decode_datagram(data: &[u8]) -> Option<v6::Message> {
+        match v6::Message::from_bytes(data) {
+            Ok(message) => Some(message),
+            Err(error) => {
+                warn!("Malformed datagram is skipped: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Serializes the message into the write buffer, returning the datagram.
+    fn encode_datagram(&mut self, message: &v6::Message) -> io::Result<&[u8]> {
+        let amount = message.to_bytes(&mut self.buf_write)?;
+        Ok(&self.buf_write[..amount])
+    }
+}
+
+impl Default for Dhcp6Codec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for Dhcp6Codec {
+    type Item = v6::Message;
+    type Error = io::Error;
+
+    /// The datagram is consumed even if it cannot be decoded.
+    /// A malformed datagram is logged and decoded as `None`.
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        if src.is_empty() {
+            return Ok(None);
+        }
+        let message = Self::decode_datagram(&src[..]);
+        // the buffer is cleared instead of split, so its capacity is reused
+        src.clear();
+        Ok(message)
+    }
+}
+
+impl Encoder for Dhcp6Codec {
+    type Item = v6::Message;
+    type Error = io::Error;
+
+    /// # Errors
+    /// `io::Error` on an encoding error.
+    fn encode(&mut self, message: Self::Item, dst: &mut BytesMut) -> io::Result<()> {
+        let datagram = self.encode_datagram(&message)?;
+        dst.reserve(datagram.len());
+        dst.put_slice(datagram);
+        Ok(())
+    }
+}
+
+/// The `tokio-util` codec traits over the `bytes` 1 buffers.
+#[cfg(feature = "tokio-util-codec")]
+mod util {
+    use bytes1::{BufMut, BytesMut};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::*;
+
+    impl Decoder for DhcpCodec {
+        type Item = Message;
+        type Error = io::Error;
+
+        fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+            if src.is_empty() {
+                return Ok(None);
+            }
+            let message = Self::decode_datagram(&src[..]);
+            src.clear();
+            Ok(message)
+        }
+    }
+
+    impl Encoder<(Message, Option<u16>)> for DhcpCodec {
+        type Error = io::Error;
+
+        fn encode(&mut self, item: (Message, Option<u16>), dst: &mut BytesMut) -> io::Result<()> {
+            let (message, max_size) = item;
+            let datagram = self.encode_datagram(&message, max_size)?;
+            dst.reserve(datagram.len());
+            dst.put_slice(datagram);
+            Ok(())
+        }
+    }
+
+    impl Decoder for Dhcp6Codec {
+        type Item = v6::Message;
+        type Error = io::Error;
+
+        fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+            if src.is_empty() {
+                return Ok(None);
+            }
+            let message = Self::decode_datagram(&src[..]);
+            src.clear();
+            Ok(message)
+        }
+    }
+
+    impl Encoder<v6::Message> for Dhcp6Codec {
+        type Error = io::Error;
+
+        fn encode(&mut self, message: v6::Message, dst: &mut BytesMut) -> io::Result<()> {
+            let datagram = self.encode_datagram(&message)?;
+            dst.reserve(datagram.len());
+            dst.put_slice(datagram);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use std::net::SocketAddr;
+
+    use tokio::{prelude::*, runtime::current_thread::Runtime};
+
+    /// A `DHCPDISCOVER` with the zero addresses and no options but the message type.
+    pub(crate) fn discover() -> Vec<u8> {
+        let mut data = vec![0u8; 236];
+        // the operation code, the hardware type and the hardware address length
+        data[..3].copy_from_slice(&[1, 1, 6]);
+        // the transaction ID
+        data[4..8].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        // the magic cookie, the message type and the end
+        data.extend_from_slice(&[99, 130, 83, 99, 53, 1, 1, 255]);
+        data
+    }
+
+    #[test]
+    fn skips_malformed_datagram() {
+        let mut codec = DhcpCodec::new();
+        let mut src = BytesMut::from(&[1u8, 2, 3][..]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert!(src.is_empty());
+
+        let mut codec = Dhcp6Codec::new();
+        let mut src = BytesMut::from(&[1u8][..]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert!(src.is_empty());
+
+        let mut codec = DhcpCodec::new();
+        let mut src = BytesMut::from(&discover()[..]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap().unwrap().transaction_id,
+            0x1234_5678
+        );
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn receives_past_malformed_datagram() {
+        let mut runtime = Runtime::new().unwrap();
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let receiver = UdpSocket::bind(&address).unwrap();
+        let destination = receiver.local_addr().unwrap();
+        let sender = ::std::net::UdpSocket::bind(address).unwrap();
+        sender.send_to(&[1, 2, 3], destination).unwrap();
+        sender.send_to(&discover(), destination).unwrap();
+
+        let framed = DhcpCodec::framed(receiver);
+        let (received, _) = runtime
+            .block_on(framed.into_future().map_err(|(error, _)| error))
+            .unwrap();
+        let (message, source) = received.expect("The stream has ended");
+        assert_eq!(message.transaction_id, 0x1234_5678);
+        assert_eq!(source, sender.local_addr().unwrap());
+    }
+
+    #[cfg(feature = "tokio-util-codec")]
+    #[test]
+    fn decodes_with_tokio_util() {
+        use bytes1::BytesMut;
+        use tokio_util::codec::Decoder;
+
+        let mut codec = DhcpCodec::new();
+        let mut src = BytesMut::from(&[1u8, 2, 3][..]);
+        assert!(Decoder::decode(&mut codec, &mut src).unwrap().is_none());
+        assert!(src.is_empty());
+
+        let mut src = BytesMut::from(&discover()[..]);
+        let message = Decoder::decode(&mut codec, &mut src).unwrap().unwrap();
+        assert_eq!(message.transaction_id, 0x1234_5678);
+        assert!(src.is_empty());
+    }
+}
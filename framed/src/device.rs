@@ -0,0 +1,73 @@
+//! Binding the sockets to an interface, so the `255.255.255.255` broadcasts leave through it.
+//!
+//! The routing table sends the limited broadcasts out of the default route interface,
+//! which is wrong for DHCP on the multi-homed hosts. `IP_MULTICAST_IF` does not steer
+//! the broadcasts, so the socket is bound with `SO_BINDTODEVICE` on Linux and `IP_BOUND_IF`
+//! on macOS. Elsewhere, or without the privileges, the broadcasts must be sent
+//! over a raw socket of the interface.
+
+#[cfg(target_os = "macos")]
+use std::mem;
+use std::{io, os::unix::io::AsRawFd};
+
+#[cfg(target_os = "macos")]
+use libc::c_int;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use libc::{self, c_void, socklen_t};
+
+#[cfg(target_os = "macos")]
+use pktinfo::interface_index;
+
+/// The macOS socket option binding the socket to the interface index.
+#[cfg(target_os = "macos")]
+const IP_BOUND_IF: c_int = 25;
+
+/// Binds the socket to the named interface, so it sends and receives only through it.
+///
+/// Requires `CAP_NET_RAW` on Linux.
+///
+/// # Errors
+/// `io::Error` if there is no such interface or the binding is not permitted,
+/// or with `Other` if it is not supported on the platform.
+#[cfg(target_os = "linux")]
+pub fn bind_to_device<S: AsRawFd>(socket: &S, name: &str) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            name.as_ptr() as *const c_void,
+            name.len() as socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn bind_to_device<S: AsRawFd>(socket: &S, name: &str) -> io::Result<()> {
+    let index = interface_index(name)? as c_int;
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            IP_BOUND_IF,
+            &index as *const c_int as *const c_void,
+            mem::size_of::<c_int>() as socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "freebsd")]
+pub fn bind_to_device<S: AsRawFd>(_socket: &S, _name: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "The sockets cannot be bound to an interface on FreeBSD",
+    ))
+}
@@ -1,15 +1,61 @@
 //! A modified version of `tokio::UdpFramed` socket
 //! designed to work with high level DHCP messages.
 
+#[cfg(feature = "async-std-transport")]
+mod async_transport;
+mod codec;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+mod device;
+#[cfg(target_os = "linux")]
+mod mmsg;
+mod pcap;
+mod pool;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+mod pktinfo;
 mod socket;
+mod socket4o6;
+mod socket6;
+mod transport;
+#[cfg(all(target_os = "linux", feature = "io-uring-receive"))]
+mod uring;
 
+#[cfg(feature = "async-std-transport")]
+extern crate async_io;
+#[cfg(feature = "async-std-transport")]
+extern crate async_std;
+#[cfg(all(target_os = "linux", feature = "io-uring-receive"))]
+extern crate io_uring;
+#[macro_use]
+extern crate log;
 extern crate tokio;
+extern crate tokio_udp;
 #[macro_use]
 extern crate futures;
+extern crate bytes;
+#[cfg(feature = "tokio-util-codec")]
+extern crate bytes1;
+#[cfg(feature = "tokio-util-codec")]
+extern crate tokio_util;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+extern crate libc;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+extern crate mio;
 extern crate net2;
 
 extern crate dhcp_protocol;
 
+#[cfg(feature = "async-std-transport")]
+pub use async_transport::AsyncStdTransport;
+pub use codec::{Dhcp6Codec, DhcpCodec};
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+pub use device::bind_to_device;
+pub use pcap::{Direction, PcapWriter};
+pub use pool::{BufferPool, PoolStats};
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+pub use pktinfo::interface_index;
 pub use socket::{
     DhcpFramed, DhcpSinkItem, DhcpStreamItem, BUFFER_READ_CAPACITY, BUFFER_WRITE_CAPACITY,
 };
+pub use socket4o6::Dhcp4o6Framed;
+pub use socket6::{Dhcp6Framed, Dhcp6SinkItem, Dhcp6StreamItem};
+pub use transport::{BlockingTransport, DhcpTransport, ServerSocket, TransportFramed};
@@ -0,0 +1,113 @@
+//! The batched receiving taking up to a batch of datagrams with a single `recvmmsg` call
+//! instead of a system call per datagram.
+//!
+//! The buffers, the source addresses and the control messages are allocated once
+//! and reused by every call, so a boot storm costs no allocations on the receive path.
+
+use std::{
+    io, mem,
+    net::{Ipv4Addr, SocketAddr},
+    os::unix::io::RawFd,
+    ptr,
+};
+
+use libc::{self, c_uint, c_void, sockaddr_in, socklen_t};
+
+use pktinfo::{self, CONTROL_SIZE};
+
+/// The ring of the pre-allocated receive buffers.
+pub struct RecvBatch {
+    /// Never resized, since the vectors point into them.
+    buffers: Vec<Vec<u8>>,
+    names: Vec<sockaddr_in>,
+    controls: Vec<[u64; CONTROL_SIZE]>,
+    iovecs: Vec<libc::iovec>,
+    headers: Vec<libc::mmsghdr>,
+    /// The number of datagrams received by the last call.
+    received: usize,
+    /// The index of the next datagram to take.
+    next: usize,
+}
+
+/// The raw pointers only refer to the heap memory owned by the batch.
+unsafe impl Send for RecvBatch {}
+
+impl RecvBatch {
+    /// Allocates `size` buffers of `buffer_size` bytes, one at least.
+    pub fn new(size: usize, buffer_size: usize) -> Self {
+        let size = size.max(1);
+        let mut buffers = vec![vec![0u8; buffer_size]; size];
+        let iovecs = buffers
+            .iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr() as *mut c_void,
+                iov_len: buffer.len(),
+            }).collect();
+        RecvBatch {
+            buffers,
+            names: vec![unsafe { mem::zeroed() }; size],
+            controls: vec![[0u64; CONTROL_SIZE]; size],
+            iovecs,
+            headers: vec![unsafe { mem::zeroed() }; size],
+            received: 0,
+            next: 0,
+        }
+    }
+
+    /// Whether all the received datagrams have been taken.
+    pub fn is_empty(&self) -> bool {
+        self.next >= self.received
+    }
+
+    /// Receives as many datagrams as there are buffers without blocking.
+    ///
+    /// # Errors
+    /// `io::Error` with `WouldBlock` if there are no datagrams.
+    pub fn recv(&mut self, fd: RawFd) -> io::Result<usize> {
+        // the kernel overwrites the lengths with the received ones
+        for (index, header) in self.headers.iter_mut().enumerate() {
+            header.msg_hdr.msg_name = &mut self.names[index] as *mut sockaddr_in as *mut c_void;
+            header.msg_hdr.msg_iov = &mut self.iovecs[index];
+            header.msg_hdr.msg_iovlen = 1;
+            header.msg_hdr.msg_control = self.controls[index].as_mut_ptr() as *mut c_void;
+            header.msg_hdr.msg_namelen = mem::size_of::<sockaddr_in>() as socklen_t;
+            header.msg_hdr.msg_controllen = mem::size_of::<[u64; CONTROL_SIZE]>() as _;
+            header.msg_len = 0;
+        }
+        let amount = unsafe {
+            libc::recvmmsg(
+                fd,
+                self.headers.as_mut_ptr(),
+                self.headers.len() as c_uint,
+                libc::MSG_DONTWAIT as _,
+                ptr::null_mut(),
+            )
+        };
+        if amount < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.received = amount as usize;
+        self.next = 0;
+        Ok(self.received)
+    }
+
+    /// Takes the next received datagram with its source, the index of the interface
+    /// it has come from and its destination address, which are unknown
+    /// unless `IP_PKTINFO` is enabled.
+    pub fn pop(&mut self) -> Option<(&[u8], SocketAddr, u32, Option<Ipv4Addr>)> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = self.next;
+        self.next += 1;
+        let header = &self.headers[index];
+        let amount = (header.msg_len as usize).min(self.buffers[index].len());
+        let (interface, destination) = pktinfo::packet_info(&header.msg_hdr);
+        Some((
+            &self.buffers[index][..amount],
+            pktinfo::source(&self.names[index]),
+            interface,
+            destination,
+        ))
+    }
+}
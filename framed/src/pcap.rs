@@ -0,0 +1,385 @@
+//! The pcapng packet recorder module.
+//!
+//! The DHCP payloads are wrapped into synthesized IPv4 and UDP headers,
+//! so the capture may be opened by Wireshark or tcpdump as usual.
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{BufMut, BytesMut};
+
+use pool::{BufferPool, PoolStats};
+use socket::BUFFER_WRITE_CAPACITY;
+
+/// The Section Header Block type.
+const BLOCK_TYPE_SHB: u32 = 0x0A0D_0D0A;
+/// The Interface Description Block type.
+const BLOCK_TYPE_IDB: u32 = 0x0000_0001;
+/// The Enhanced Packet Block type.
+const BLOCK_TYPE_EPB: u32 = 0x0000_0006;
+/// Is used by readers to detect the section endianness.
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+/// Raw IPv4 packets without a link-layer header.
+const LINKTYPE_IPV4: u16 = 228;
+/// The `epb_flags` option code.
+const OPTION_EPB_FLAGS: u16 = 2;
+/// The `epb_flags` inbound direction bits.
+const EPB_FLAGS_INBOUND: u32 = 0b01;
+/// The `epb_flags` outbound direction bits.
+const EPB_FLAGS_OUTBOUND: u32 = 0b10;
+
+/// The packet and the block buffers are taken for each written packet.
+const POOL_SIZE: usize = 2;
+/// Holds the largest block with a packet of a message.
+const POOL_BUFFER_CAPACITY: usize = BUFFER_WRITE_CAPACITY + 64;
+
+/// The IPv4 header size without options.
+const SIZE_HEADER_IPV4: usize = 20;
+/// The UDP header size.
+const SIZE_HEADER_UDP: usize = 8;
+/// The IPv4 version and the header length in 32-bit words.
+const IPV4_VERSION_IHL: u8 = 0x45;
+/// The default IPv4 time-to-live.
+const IPV4_TTL: u8 = 64;
+/// The UDP protocol number.
+const IPV4_PROTOCOL_UDP: u8 = 17;
+
+/// The packet direction recorded in the `epb_flags` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Writes DHCP packets to a pcapng stream.
+pub struct PcapWriter {
+    writer: Box<Write + Send + Sync>,
+    /// The number of bytes written since the writer was created.
+    written: u64,
+    /// The packets and blocks are built in the reused buffers.
+    pool: BufferPool,
+}
+
+impl PcapWriter {
+    /// Creates a capture file writing the section and interface headers.
+    ///
+    /// # Errors
+    /// `io::Error` if the file cannot be created or written.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = fs::File::create(path)?;
+        Self::new(Box::new(BufWriter::new(file)))
+    }
+
+    /// Wraps an arbitrary writer writing the section and interface headers.
+    ///
+    /// # Errors
+    /// `io::Error` if the headers cannot be written.
+    pub fn new(writer: Box<Write + Send + Sync>) -> io::Result<Self> {
+        let mut pcap = PcapWriter {
+            writer,
+            written: 0,
+            pool: BufferPool::new(POOL_BUFFER_CAPACITY, POOL_SIZE),
+        };
+
+        let mut shb = BytesMut::with_capacity(28);
+        shb.put_u32_le(BLOCK_TYPE_SHB);
+        shb.put_u32_le(28);
+        shb.put_u32_le(BYTE_ORDER_MAGIC);
+        shb.put_u16_le(1); // major version
+        shb.put_u16_le(0); // minor version
+        shb.put_u64_le(u64::max_value()); // the section length is unknown
+        shb.put_u32_le(28);
+        pcap.write_block(&shb)?;
+
+        let mut idb = BytesMut::with_capacity(20);
+        idb.put_u32_le(BLOCK_TYPE_IDB);
+        idb.put_u32_le(20);
+        idb.put_u16_le(LINKTYPE_IPV4);
+        idb.put_u16_le(0); // reserved
+        idb.put_u32_le(0); // no snapshot length limit
+        idb.put_u32_le(20);
+        pcap.write_block(&idb)?;
+
+        pcap.writer.flush()?;
+        Ok(pcap)
+    }
+
+    /// The number of bytes written so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// The counters of the packet buffer pool.
+    pub fn buffer_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// Writes a packet with the current timestamp.
+    ///
+    /// * `direction`
+    /// Whether the packet has been sent or received.
+    ///
+    /// * `source`
+    /// The source address put into the synthesized headers.
+    ///
+    /// * `destination`
+    /// The destination address put into the synthesized headers.
+    ///
+    /// * `payload`
+    /// The raw DHCP message.
+    ///
+    /// # Errors
+    /// `io::Error` if any of the addresses is not IPv4 or on a writer error.
+    pub fn write_packet(
+        &mut self,
+        direction: Direction,
+        source: &SocketAddr,
+        destination: &SocketAddr,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let mut packet = self.pool.get();
+        if let Err(error) = Self::synthesize(source, destination, payload, &mut packet) {
+            self.pool.put(packet);
+            return Err(error);
+        }
+        let padding = (4 - packet.len() % 4) % 4;
+        // the fixed fields, the padded data, the flags option, the end of options and the length
+        let total = 28 + packet.len() + padding + 8 + 4 + 4;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let timestamp =
+            timestamp.as_secs() * 1_000_000 + u64::from(timestamp.subsec_nanos() / 1_000);
+
+        let mut epb = self.pool.get();
+        epb.reserve(total);
+        epb.put_u32_le(BLOCK_TYPE_EPB);
+        epb.put_u32_le(total as u32);
+        epb.put_u32_le(0); // the interface ID
+        epb.put_u32_le((timestamp >> 32) as u32);
+        epb.put_u32_le(timestamp as u32);
+        epb.put_u32_le(packet.len() as u32); // captured length
+        epb.put_u32_le(packet.len() as u32); // original length
+        epb.put_slice(&packet);
+        epb.put_slice(&[0u8; 3][..padding]);
+        epb.put_u16_le(OPTION_EPB_FLAGS);
+        epb.put_u16_le(4);
+        epb.put_u32_le(match direction {
+            Direction::Inbound => EPB_FLAGS_INBOUND,
+            Direction::Outbound => EPB_FLAGS_OUTBOUND,
+        });
+        epb.put_u32_le(0); // opt_endofopt
+        epb.put_u32_le(total as u32);
+        let result = self.write_block(&epb);
+        self.pool.put(packet);
+        self.pool.put(epb);
+        result?;
+
+        self.writer.flush()
+    }
+
+    fn write_block(&mut self, block: &[u8]) -> io::Result<()> {
+        self.writer.write_all(block)?;
+        self.written += block.len() as u64;
+        Ok(())
+    }
+
+    /// Wraps the payload into IPv4 and UDP headers.
+    fn synthesize(
+        source: &SocketAddr,
+        destination: &SocketAddr,
+        payload: &[u8],
+        packet: &mut BytesMut,
+    ) -> io::Result<()> {
+        let (source_ip, destination_ip) = match (source.ip(), destination.ip()) {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => (source, destination),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Only IPv4 packets can be recorded",
+                ))
+            }
+        };
+        let udp_length = SIZE_HEADER_UDP + payload.len();
+        let total_length = SIZE_HEADER_IPV4 + udp_length;
+
+        packet.reserve(total_length);
+        packet.put_u8(IPV4_VERSION_IHL);
+        packet.put_u8(0); // type of service
+        packet.put_u16_be(total_length as u16);
+        packet.put_u16_be(0); // identification
+        packet.put_u16_be(0); // flags and fragment offset
+        packet.put_u8(IPV4_TTL);
+        packet.put_u8(IPV4_PROTOCOL_UDP);
+        packet.put_u16_be(0); // the checksum placeholder
+        packet.put_slice(&source_ip.octets());
+        packet.put_slice(&destination_ip.octets());
+        let checksum = Self::checksum(&packet[..SIZE_HEADER_IPV4]);
+        packet[10] = (checksum >> 8) as u8;
+        packet[11] = checksum as u8;
+
+        packet.put_u16_be(source.port());
+        packet.put_u16_be(destination.port());
+        packet.put_u16_be(udp_length as u16);
+        packet.put_u16_be(0); // the UDP checksum is optional over IPv4
+        packet.put_slice(payload);
+        Ok(())
+    }
+
+    /// The RFC 1071 Internet checksum.
+    fn checksum(data: &[u8]) -> u16 {
+        let mut sum = data.chunks(2).fold(0u32, |sum, chunk| {
+            let word = (u32::from(chunk[0]) << 8) | u32::from(*chunk.get(1).unwrap_or(&0));
+            sum + word
+        });
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps the written data accessible after the writer is boxed.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The section and interface header blocks.
+    const SIZE_HEADERS: usize = 28 + 20;
+
+    fn u32_le(data: &[u8], offset: usize) -> u32 {
+        data[offset..offset + 4]
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| (value << 8) | u32::from(byte))
+    }
+
+    #[test]
+    fn writes_headers() {
+        let shared = Shared::default();
+        let pcap = PcapWriter::new(Box::new(shared.clone())).unwrap();
+        let data = shared.0.lock().unwrap();
+
+        assert_eq!(pcap.written(), SIZE_HEADERS as u64);
+        assert_eq!(data.len(), SIZE_HEADERS);
+        // the block type, length and trailing length of each block
+        let cases = [(0, BLOCK_TYPE_SHB, 28), (28, BLOCK_TYPE_IDB, 20)];
+        for &(offset, block_type, length) in cases.iter() {
+            assert_eq!(u32_le(&data, offset), block_type);
+            assert_eq!(u32_le(&data, offset + 4), length);
+            assert_eq!(u32_le(&data, offset + length as usize - 4), length);
+        }
+        assert_eq!(u32_le(&data, 8), BYTE_ORDER_MAGIC);
+        assert_eq!(u32_le(&data, 36), u32::from(LINKTYPE_IPV4));
+    }
+
+    #[test]
+    fn writes_padded_packets() {
+        let source: SocketAddr = "192.168.0.2:67".parse().unwrap();
+        let destination: SocketAddr = "255.255.255.255:68".parse().unwrap();
+        // the payload size, the direction, and the expected block length and flags
+        let cases = [
+            (0, Direction::Inbound, 72, EPB_FLAGS_INBOUND),
+            (1, Direction::Outbound, 76, EPB_FLAGS_OUTBOUND),
+            (2, Direction::Inbound, 76, EPB_FLAGS_INBOUND),
+            (3, Direction::Inbound, 76, EPB_FLAGS_INBOUND),
+            (4, Direction::Inbound, 76, EPB_FLAGS_INBOUND),
+            (300, Direction::Outbound, 372, EPB_FLAGS_OUTBOUND),
+        ];
+        for &(size, direction, length, flags) in cases.iter() {
+            let shared = Shared::default();
+            let mut pcap = PcapWriter::new(Box::new(shared.clone())).unwrap();
+            let payload: Vec<u8> = (0..size).map(|byte| byte as u8).collect();
+            pcap.write_packet(direction, &source, &destination, &payload)
+                .unwrap();
+
+            let data = shared.0.lock().unwrap();
+            let block = &data[SIZE_HEADERS..];
+            let packet_length = SIZE_HEADER_IPV4 + SIZE_HEADER_UDP + size;
+            assert_eq!(pcap.written(), (SIZE_HEADERS + length) as u64, "{}", size);
+            assert_eq!(block.len(), length, "{}", size);
+            assert_eq!(u32_le(block, 0), BLOCK_TYPE_EPB);
+            assert_eq!(u32_le(block, 4) as usize, length);
+            assert_eq!(u32_le(block, 20) as usize, packet_length);
+            assert_eq!(u32_le(block, 24) as usize, packet_length);
+            assert_eq!(u32_le(block, length - 12), flags, "{}", size);
+            assert_eq!(u32_le(block, length - 4) as usize, length);
+
+            let packet = &block[28..28 + packet_length];
+            assert_eq!(
+                PcapWriter::checksum(&packet[..SIZE_HEADER_IPV4]),
+                0,
+                "{}",
+                size
+            );
+            assert_eq!(&packet[12..16], &[192, 168, 0, 2]);
+            assert_eq!(&packet[16..20], &[255, 255, 255, 255]);
+            assert_eq!(&packet[20..22], &[0, 67]);
+            assert_eq!(&packet[22..24], &[0, 68]);
+            assert_eq!(
+                &packet[SIZE_HEADER_IPV4 + SIZE_HEADER_UDP..],
+                payload.as_slice()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_ipv6_packets() {
+        let cases = [
+            ("[::1]:547", "192.168.0.2:67"),
+            ("192.168.0.2:67", "[ff02::1:2]:547"),
+        ];
+        for &(source, destination) in cases.iter() {
+            let shared = Shared::default();
+            let mut pcap = PcapWriter::new(Box::new(shared.clone())).unwrap();
+            let error = pcap
+                .write_packet(
+                    Direction::Inbound,
+                    &source.parse().unwrap(),
+                    &destination.parse().unwrap(),
+                    &[1, 2, 3],
+                )
+                .unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(pcap.written(), SIZE_HEADERS as u64);
+            assert_eq!(shared.0.lock().unwrap().len(), SIZE_HEADERS);
+        }
+    }
+
+    #[test]
+    fn computes_checksum() {
+        // RFC 1071 §3 and the odd length case
+        let cases: &[(&[u8], u16)] = &[
+            (&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7], !0xddf2),
+            (&[0xff, 0xff], 0),
+            (&[0x01], !0x0100),
+            (&[], 0xffff),
+        ];
+        for &(data, checksum) in cases {
+            assert_eq!(PcapWriter::checksum(data), checksum, "{:?}", data);
+        }
+    }
+}
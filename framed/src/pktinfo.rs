@@ -0,0 +1,205 @@
+//! The `IP_PKTINFO` socket operations learning the ingress interface and the destination
+//! address of the datagrams and choosing the egress interface and the source address.
+//!
+//! The BSDs have no `IP_PKTINFO`, so the `IP_RECVIF` and `IP_RECVDSTADDR` control messages
+//! are received there instead, and the egress is left to the routing table.
+//!
+//! The standard library and `tokio` do not expose the ancillary data, so `recvmsg`
+//! and `sendmsg` are called directly on the non-blocking socket.
+
+use std::{
+    ffi::CString,
+    io, mem,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    os::unix::io::RawFd,
+    ptr,
+};
+
+#[cfg(target_os = "linux")]
+use libc::in_pktinfo;
+use libc::{self, c_int, c_void, sockaddr_in, socklen_t};
+
+/// Enough for an `in_pktinfo` or the `IP_RECVIF` and `IP_RECVDSTADDR` control messages,
+/// `u64` for the alignment.
+pub const CONTROL_SIZE: usize = 16;
+
+/// The BSD socket options, which are the same on FreeBSD and macOS.
+#[cfg(any(target_os = "freebsd", target_os = "macos"))]
+const IP_RECVDSTADDR: c_int = 7;
+#[cfg(any(target_os = "freebsd", target_os = "macos"))]
+const IP_RECVIF: c_int = 20;
+
+/// Makes the socket receive the `IP_PKTINFO` control messages.
+#[cfg(target_os = "linux")]
+pub fn enable(fd: RawFd) -> io::Result<()> {
+    set_option(fd, libc::IP_PKTINFO)
+}
+
+/// Makes the socket receive the `IP_RECVIF` and `IP_RECVDSTADDR` control messages.
+#[cfg(any(target_os = "freebsd", target_os = "macos"))]
+pub fn enable(fd: RawFd) -> io::Result<()> {
+    set_option(fd, IP_RECVIF)?;
+    set_option(fd, IP_RECVDSTADDR)
+}
+
+fn set_option(fd: RawFd, option: c_int) -> io::Result<()> {
+    let enable: c_int = 1;
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::IPPROTO_IP,
+            option,
+            &enable as *const c_int as *const c_void,
+            mem::size_of::<c_int>() as socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns the index of the named network interface.
+///
+/// # Errors
+/// `io::Error` if there is no such interface.
+pub fn interface_index(name: &str) -> io::Result<u32> {
+    let name = CString::new(name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid interface name"))?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::last_os_error()),
+        index => Ok(index),
+    }
+}
+
+/// Receives a datagram with its source, the index of the interface it has come from
+/// and its destination address.
+///
+/// The index is zero if the kernel has not provided it.
+pub fn recv_from(
+    fd: RawFd,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, u32, Option<Ipv4Addr>)> {
+    let mut name: sockaddr_in = unsafe { mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = [0u64; CONTROL_SIZE];
+    let mut header: libc::msghdr = unsafe { mem::zeroed() };
+    header.msg_name = &mut name as *mut sockaddr_in as *mut c_void;
+    header.msg_namelen = mem::size_of::<sockaddr_in>() as socklen_t;
+    header.msg_iov = &mut iov;
+    header.msg_iovlen = 1;
+    header.msg_control = control.as_mut_ptr() as *mut c_void;
+    header.msg_controllen = mem::size_of_val(&control) as _;
+
+    let amount = unsafe { libc::recvmsg(fd, &mut header, 0) };
+    if amount < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let (interface, destination) = packet_info(&header);
+    Ok((amount as usize, source(&name), interface, destination))
+}
+
+/// Returns the interface index and the destination address from the control messages
+/// of a received datagram, or zero and `None` if there are none.
+pub fn packet_info(header: &libc::msghdr) -> (u32, Option<Ipv4Addr>) {
+    let mut interface = 0;
+    let mut destination = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(header);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::IPPROTO_IP {
+                let data = libc::CMSG_DATA(cmsg);
+                match (*cmsg).cmsg_type {
+                    #[cfg(target_os = "linux")]
+                    libc::IP_PKTINFO => {
+                        let info = ptr::read_unaligned(data as *const in_pktinfo);
+                        interface = info.ipi_ifindex as u32;
+                        destination = Some(Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr)));
+                    }
+                    #[cfg(any(target_os = "freebsd", target_os = "macos"))]
+                    IP_RECVDSTADDR => {
+                        let address = ptr::read_unaligned(data as *const libc::in_addr);
+                        destination = Some(Ipv4Addr::from(u32::from_be(address.s_addr)));
+                    }
+                    // a `sockaddr_dl` starting with the length, the family and the index
+                    #[cfg(any(target_os = "freebsd", target_os = "macos"))]
+                    IP_RECVIF => {
+                        interface = u32::from(ptr::read_unaligned(data.offset(2) as *const u16));
+                    }
+                    _ => {}
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(header, cmsg);
+        }
+    }
+    (interface, destination)
+}
+
+/// Converts the source address filled by the kernel.
+pub fn source(name: &sockaddr_in) -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(
+        Ipv4Addr::from(u32::from_be(name.sin_addr.s_addr)),
+        u16::from_be(name.sin_port),
+    ))
+}
+
+/// Sends a datagram out of the interface, which is required for the broadcasts
+/// on a socket bound to the unspecified address, from the `source` address if set.
+#[cfg(target_os = "linux")]
+pub fn send_to(
+    fd: RawFd,
+    buf: &[u8],
+    destination: &SocketAddr,
+    interface: u32,
+    source: Option<Ipv4Addr>,
+) -> io::Result<usize> {
+    let destination = match *destination {
+        SocketAddr::V4(ref destination) => destination,
+        SocketAddr::V6(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "IP_PKTINFO requires an IPv4 destination",
+            ))
+        }
+    };
+    let mut name: sockaddr_in = unsafe { mem::zeroed() };
+    name.sin_family = libc::AF_INET as libc::sa_family_t;
+    name.sin_port = destination.port().to_be();
+    name.sin_addr.s_addr = u32::from(*destination.ip()).to_be();
+
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = [0u64; CONTROL_SIZE];
+    let mut header: libc::msghdr = unsafe { mem::zeroed() };
+    header.msg_name = &mut name as *mut sockaddr_in as *mut c_void;
+    header.msg_namelen = mem::size_of::<sockaddr_in>() as socklen_t;
+    header.msg_iov = &mut iov;
+    header.msg_iovlen = 1;
+    header.msg_control = control.as_mut_ptr() as *mut c_void;
+    header.msg_controllen =
+        unsafe { libc::CMSG_SPACE(mem::size_of::<in_pktinfo>() as u32) } as _;
+
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&header);
+        (*cmsg).cmsg_level = libc::IPPROTO_IP;
+        (*cmsg).cmsg_type = libc::IP_PKTINFO;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<in_pktinfo>() as u32) as _;
+        let mut info: in_pktinfo = mem::zeroed();
+        info.ipi_ifindex = interface as c_int;
+        if let Some(source) = source {
+            info.ipi_spec_dst.s_addr = u32::from(source).to_be();
+        }
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut in_pktinfo, info);
+    }
+
+    let amount = unsafe { libc::sendmsg(fd, &header, 0) };
+    if amount < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(amount as usize)
+}
@@ -0,0 +1,69 @@
+//! The pool of the reusable datagram buffers.
+
+use bytes::BytesMut;
+
+/// The buffer pool counters exposed in the metrics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The buffers allocated because the pool was empty.
+    pub allocated: u64,
+    /// The buffers taken from the pool.
+    pub reused: u64,
+    /// The returned buffers dropped because the pool was full or they had shrunk.
+    pub discarded: u64,
+    /// The buffers in the pool now.
+    pub idle: usize,
+}
+
+/// Lends the buffers of at least `capacity` bytes and keeps up to `limit` returned ones,
+/// so the busy paths do not allocate a buffer per datagram.
+pub struct BufferPool {
+    free: Vec<BytesMut>,
+    capacity: usize,
+    limit: usize,
+    stats: PoolStats,
+}
+
+impl BufferPool {
+    pub fn new(capacity: usize, limit: usize) -> Self {
+        BufferPool {
+            free: Vec::with_capacity(limit),
+            capacity,
+            limit,
+            stats: PoolStats::default(),
+        }
+    }
+
+    /// Returns an empty buffer, allocating it only if the pool is empty.
+    pub fn get(&mut self) -> BytesMut {
+        match self.free.pop() {
+            Some(buffer) => {
+                self.stats.reused += 1;
+                buffer
+            }
+            None => {
+                self.stats.allocated += 1;
+                BytesMut::with_capacity(self.capacity)
+            }
+        }
+    }
+
+    /// Takes the buffer back, clearing it.
+    ///
+    /// A buffer which has been split and lost a part of its capacity is dropped.
+    pub fn put(&mut self, mut buffer: BytesMut) {
+        buffer.clear();
+        if self.free.len() >= self.limit || buffer.capacity() < self.capacity {
+            self.stats.discarded += 1;
+            return;
+        }
+        self.free.push(buffer);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            idle: self.free.len(),
+            ..self.stats
+        }
+    }
+}
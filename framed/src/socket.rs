@@ -1,29 +1,79 @@
 //! The main DHCP socket module.
 
-use std::net::SocketAddr;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+use std::{
+    net,
+    os::unix::io::{AsRawFd, FromRawFd},
+};
+use std::net::{Ipv4Addr, SocketAddr};
 
 use futures::StartSend;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+use libc;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+use mio::{self, Ready};
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+use tokio::reactor::PollEvented2;
 use tokio::{io, net::UdpSocket, prelude::*};
 
 use dhcp_protocol::*;
 
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+use device;
+use pcap::{Direction, PcapWriter};
+use pool::PoolStats;
+#[cfg(target_os = "linux")]
+use mmsg::RecvBatch;
+#[cfg(all(target_os = "linux", feature = "io-uring-receive"))]
+use uring::RecvRing;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+use pktinfo;
+
 /// Must be enough to decode all the options.
 pub const BUFFER_READ_CAPACITY: usize = 8192;
 /// Must be enough to encode all the options.
 pub const BUFFER_WRITE_CAPACITY: usize = 8192;
 
+/// Registered with the reactor directly where the datagrams are received and sent
+/// with the raw system calls, which must clear the readiness on `WouldBlock`,
+/// while `tokio::net::UdpSocket` keeps its readiness private.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+type Socket = PollEvented2<mio::net::UdpSocket>;
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "macos")))]
+type Socket = UdpSocket;
+
 /// The modified version of the `tokio::UdpFramed`.
 ///
 /// Works with high level DHCP messages.
 pub struct DhcpFramed {
-    /// `tokio::UdpSocket`.
-    socket: UdpSocket,
+    /// The UDP socket.
+    socket: Socket,
     /// Stores received data and is used for deserialization.
     buf_read: Vec<u8>,
     /// Stores pending data and is used for serialization.
     buf_write: Vec<u8>,
-    /// Stores the destination address and the number of bytes to send.
-    pending: Option<(SocketAddr, usize)>,
+    /// Stores the destination address, the number of bytes to send and the egress interface.
+    pending: Option<(SocketAddr, usize, Option<u32>)>,
+    /// Records all the sent and received packets if set.
+    recorder: Option<PcapWriter>,
+    /// Whether the interfaces are learned and chosen with `IP_PKTINFO`.
+    pktinfo: bool,
+    /// The interface index of the last received message.
+    ingress_interface: Option<u32>,
+    /// The destination address of the last received message.
+    destination_address: Option<Ipv4Addr>,
+    /// The interface index the next message is sent out of.
+    egress_interface: Option<u32>,
+    /// The source address the next message is sent from.
+    source_address: Option<Ipv4Addr>,
+    /// The fixed header of the request the next messages answer.
+    reply_header: Option<[u8; OFFSET_SERVER_NAME]>,
+    /// Receives the datagrams with `recvmmsg` if set.
+    #[cfg(target_os = "linux")]
+    batch: Option<RecvBatch>,
+    /// Receives the datagrams with `io_uring` if set, dropped after the socket.
+    #[cfg(all(target_os = "linux", feature = "io-uring-receive"))]
+    ring: Option<RecvRing>,
 }
 
 pub type DhcpStreamItem = (SocketAddr, Message);
@@ -36,13 +86,312 @@ impl DhcpFramed {
     /// `io::Error` on unsuccessful socket building or binding.
     #[allow(unused_variables)]
     pub fn new(socket: UdpSocket) -> io::Result<Self> {
+        #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+        let socket = register(socket)?;
         Ok(DhcpFramed {
             socket,
             buf_read: vec![0u8; BUFFER_READ_CAPACITY],
             buf_write: vec![0u8; BUFFER_WRITE_CAPACITY],
             pending: None,
+            recorder: None,
+            pktinfo: false,
+            ingress_interface: None,
+            destination_address: None,
+            egress_interface: None,
+            source_address: None,
+            reply_header: None,
+            #[cfg(target_os = "linux")]
+            batch: None,
+            #[cfg(all(target_os = "linux", feature = "io-uring-receive"))]
+            ring: None,
         })
     }
+
+    /// Makes the socket learn the ingress interface and the destination address
+    /// of the received messages and send the messages out of the interface
+    /// and from the address set with `set_egress_interface` and `set_source_address`.
+    ///
+    /// Required to serve several interfaces with a socket bound to the unspecified address.
+    /// The BSDs learn the ingress with `IP_RECVIF` and `IP_RECVDSTADDR`, but cannot choose
+    /// the egress, which is left to the routing table there.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket options cannot be set.
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+    pub fn enable_pktinfo(&mut self) -> io::Result<()> {
+        pktinfo::enable(self.socket.get_ref().as_raw_fd())?;
+        self.pktinfo = true;
+        Ok(())
+    }
+
+    /// Makes the socket receive up to `size` datagrams with a single `recvmmsg` call
+    /// into the buffers allocated once, instead of a system call per datagram.
+    ///
+    /// The messages are still yielded one by one, so the stream behaves the same.
+    #[cfg(target_os = "linux")]
+    pub fn enable_batching(&mut self, size: usize) {
+        self.batch = Some(RecvBatch::new(size, BUFFER_READ_CAPACITY));
+    }
+
+    /// Makes the socket keep a `recvmsg` request in flight with `io_uring` for each of
+    /// the `size` buffers allocated once, which the kernel fills as the datagrams arrive.
+    ///
+    /// Takes precedence over `enable_batching`. The messages are still yielded one by one.
+    ///
+    /// # Errors
+    /// `io::Error` if `io_uring` is not supported by the kernel or not permitted.
+    #[cfg(all(target_os = "linux", feature = "io-uring-receive"))]
+    pub fn enable_ring(&mut self, size: usize) -> io::Result<()> {
+        let fd = self.socket.get_ref().as_raw_fd();
+        self.ring = Some(RecvRing::new(fd, size, BUFFER_READ_CAPACITY)?);
+        Ok(())
+    }
+
+    /// Binds the socket to the named interface, so the broadcasts leave through it
+    /// instead of the default route interface.
+    ///
+    /// The socket receives only through the interface afterwards.
+    ///
+    /// # Errors
+    /// `io::Error` if the binding is not permitted or not supported on the platform.
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+    pub fn bind_to_device(&self, name: &str) -> io::Result<()> {
+        device::bind_to_device(self.socket.get_ref(), name)
+    }
+
+    /// The interface index the last message has been received on.
+    ///
+    /// Always `None` unless `enable_pktinfo` has been called.
+    pub fn ingress_interface(&self) -> Option<u32> {
+        self.ingress_interface
+    }
+
+    /// The destination address of the last received message,
+    /// which tells the broadcasts from the messages unicast to an address of the host.
+    ///
+    /// Always `None` unless `enable_pktinfo` has been called.
+    pub fn destination_address(&self) -> Option<Ipv4Addr> {
+        self.destination_address
+    }
+
+    /// Sets the interface index the next messages are sent out of,
+    /// or the routing table is used if `None`.
+    ///
+    /// Ignored unless `enable_pktinfo` has been called.
+    pub fn set_egress_interface(&mut self, interface: Option<u32>) {
+        self.egress_interface = interface;
+    }
+
+    /// Sets the source address of the next messages, or the one of the egress interface
+    /// is used if `None`.
+    ///
+    /// Ignored unless `enable_pktinfo` has been called on Linux.
+    pub fn set_source_address(&mut self, address: Option<Ipv4Addr>) {
+        self.source_address = address;
+    }
+
+    /// The fixed header of the last received message, i.e. the fields before `server_name`.
+    pub fn received_header(&self) -> &[u8] {
+        &self.buf_read[..OFFSET_SERVER_NAME]
+    }
+
+    /// Sets the fixed header of the request the next messages answer,
+    /// which is patched into the replies instead of encoding them from scratch.
+    ///
+    /// The messages which are not replies to the request are encoded as usual.
+    pub fn set_reply_header(&mut self, header: Option<[u8; OFFSET_SERVER_NAME]>) {
+        self.reply_header = header;
+    }
+
+    /// Makes the socket record every sent and received packet.
+    ///
+    /// If the recorder fails, the recording is stopped, but the socket keeps working.
+    pub fn with_recorder(&mut self, recorder: PcapWriter) -> &mut Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// The counters of the recorder buffer pool, the socket itself reuses its two buffers.
+    pub fn buffer_stats(&self) -> Option<PoolStats> {
+        self.recorder.as_ref().map(PcapWriter::buffer_stats)
+    }
+
+    /// Writes the packet to the recorder if it is set.
+    fn record(&mut self, direction: Direction, peer: &SocketAddr, amount: usize) {
+        let result = match self.recorder {
+            Some(ref mut recorder) => {
+                let local = match local_addr(&self.socket) {
+                    Ok(local) => local,
+                    Err(error) => {
+                        warn!("Unable to get the local address for recording: {}", error);
+                        return;
+                    }
+                };
+                let (source, destination, data) = match direction {
+                    Direction::Inbound => (peer, &local, &self.buf_read[..amount]),
+                    Direction::Outbound => (&local, peer, &self.buf_write[..amount]),
+                };
+                recorder.write_packet(direction, source, destination, data)
+            }
+            None => return,
+        };
+        if let Err(error) = result {
+            warn!("Packet recording error, the recording is stopped: {}", error);
+            self.recorder = None;
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+    fn poll_recv(&mut self) -> Poll<(usize, SocketAddr), io::Error> {
+        #[cfg(all(target_os = "linux", feature = "io-uring-receive"))]
+        {
+            if let Some(ref mut ring) = self.ring {
+                let (amount, addr, interface, destination) =
+                    try_ready!(ring.poll_recv(&mut self.buf_read));
+                if self.pktinfo {
+                    self.ingress_interface = Some(interface);
+                    self.destination_address = destination;
+                }
+                return Ok(Async::Ready((amount, addr)));
+            }
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let is_pending = match self.batch {
+                Some(ref batch) => !batch.is_empty(),
+                None => false,
+            };
+            if is_pending {
+                return Ok(Async::Ready(self.recv_from()?));
+            }
+        }
+        try_ready!(self.socket.poll_read_ready(Ready::readable()));
+        match self.recv_from() {
+            Ok(received) => Ok(Async::Ready(received)),
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => {
+                self.socket.clear_read_ready(Ready::readable())?;
+                Ok(Async::NotReady)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "macos")))]
+    fn poll_recv(&mut self) -> Poll<(usize, SocketAddr), io::Error> {
+        self.socket.poll_recv_from(&mut self.buf_read)
+    }
+
+    /// Takes a datagram from the batch, refilling it if it is empty,
+    /// or receives a single one with or without the packet info.
+    ///
+    /// # Errors
+    /// `io::Error` with `WouldBlock` if there are no datagrams.
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+    fn recv_from(&mut self) -> io::Result<(usize, SocketAddr)> {
+        let fd = self.socket.get_ref().as_raw_fd();
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(ref mut batch) = self.batch {
+                if batch.is_empty() && batch.recv(fd)? == 0 {
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+                let (data, addr, interface, destination) =
+                    batch.pop().expect("The batch is not empty");
+                // copying a datagram is much cheaper than the system call saved
+                let amount = data.len();
+                self.buf_read[..amount].copy_from_slice(data);
+                if self.pktinfo {
+                    self.ingress_interface = Some(interface);
+                    self.destination_address = destination;
+                }
+                return Ok((amount, addr));
+            }
+        }
+        if self.pktinfo {
+            let (amount, addr, interface, destination) =
+                pktinfo::recv_from(fd, &mut self.buf_read)?;
+            self.ingress_interface = Some(interface);
+            self.destination_address = destination;
+            return Ok((amount, addr));
+        }
+        self.socket.get_ref().recv_from(&mut self.buf_read)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+    fn poll_send(
+        &mut self,
+        addr: &SocketAddr,
+        amount: usize,
+        interface: Option<u32>,
+    ) -> Poll<usize, io::Error> {
+        try_ready!(self.socket.poll_write_ready());
+        match self.send_to(addr, amount, interface) {
+            Ok(sent) => Ok(Async::Ready(sent)),
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => {
+                self.socket.clear_write_ready()?;
+                Ok(Async::NotReady)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "macos")))]
+    fn poll_send(
+        &mut self,
+        addr: &SocketAddr,
+        amount: usize,
+        _interface: Option<u32>,
+    ) -> Poll<usize, io::Error> {
+        self.socket.poll_send_to(&self.buf_write[..amount], addr)
+    }
+
+    /// Sends the datagram out of the interface and from the source address if they are set.
+    ///
+    /// # Errors
+    /// `io::Error` with `WouldBlock` if the socket buffer is full.
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+    #[allow(unused_variables)]
+    fn send_to(
+        &self,
+        addr: &SocketAddr,
+        amount: usize,
+        interface: Option<u32>,
+    ) -> io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        {
+            if self.pktinfo && (interface.is_some() || self.source_address.is_some()) {
+                let fd = self.socket.get_ref().as_raw_fd();
+                let (buf, interface) = (&self.buf_write[..amount], interface.unwrap_or(0));
+                return pktinfo::send_to(fd, buf, addr, interface, self.source_address);
+            }
+        }
+        self.socket.get_ref().send_to(&self.buf_write[..amount], addr)
+    }
+}
+
+/// Moves the descriptor of the tokio socket into a registration of its own.
+///
+/// The original descriptor is deregistered and closed before the duplicate is registered,
+/// which happens on the first poll.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+fn register(socket: UdpSocket) -> io::Result<Socket> {
+    let fd = unsafe { libc::fcntl(socket.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    drop(socket);
+    let socket = unsafe { net::UdpSocket::from_raw_fd(fd) };
+    Ok(PollEvented2::new(mio::net::UdpSocket::from_socket(socket)?))
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+fn local_addr(socket: &Socket) -> io::Result<SocketAddr> {
+    socket.get_ref().local_addr()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "macos")))]
+fn local_addr(socket: &Socket) -> io::Result<SocketAddr> {
+    socket.local_addr()
 }
 
 impl Stream for DhcpFramed {
@@ -56,7 +405,8 @@ impl Stream for DhcpFramed {
     /// # Errors
     /// `io::Error` on a socket error.
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        let (amount, addr) = try_ready!(self.socket.poll_recv_from(&mut self.buf_read));
+        let (amount, addr) = try_ready!(self.poll_recv());
+        self.record(Direction::Inbound, &addr, amount);
         match Message::from_bytes(&self.buf_read[..amount]) {
             Ok(frame) => Ok(Async::Ready(Some((addr, frame)))),
             Err(_) => Ok(Async::Ready(None)),
@@ -80,8 +430,11 @@ impl Sink for DhcpFramed {
         }
 
         let (addr, (message, max_size)) = item;
-        let amount = message.to_bytes(&mut self.buf_write, max_size)?;
-        self.pending = Some((addr, amount));
+        let amount = match self.reply_header {
+            Some(ref header) => message.reply_to_bytes(header, &mut self.buf_write, max_size)?,
+            None => message.to_bytes(&mut self.buf_write, max_size)?,
+        };
+        self.pending = Some((addr, amount, self.egress_interface));
 
         Ok(AsyncSink::Ready)
     }
@@ -94,14 +447,15 @@ impl Sink for DhcpFramed {
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
         match self.pending {
             None => return Ok(Async::Ready(())),
-            Some((addr, amount)) => {
-                let sent = try_ready!(self.socket.poll_send_to(&self.buf_write[..amount], &addr));
+            Some((addr, amount, interface)) => {
+                let sent = try_ready!(self.poll_send(&addr, amount, interface));
                 if sent != amount {
                     return Err(io::Error::new(
                         io::ErrorKind::WriteZero,
                         "Failed to write entire datagram to socket",
                     ));
                 }
+                self.record(Direction::Outbound, &addr, amount);
             }
         }
         self.pending = None;
@@ -0,0 +1,129 @@
+//! The DHCPv4-over-DHCPv6 socket module (RFC 7341).
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use futures::StartSend;
+use tokio::{io, net::UdpSocket, prelude::*};
+
+use dhcp_protocol::{
+    v6::{self, constants::FLAG_DHCP4O6_UNICAST},
+    Message,
+};
+
+use socket::{DhcpSinkItem, DhcpStreamItem, BUFFER_WRITE_CAPACITY};
+use socket6::Dhcp6Framed;
+
+/// The `DhcpFramed` counterpart for IPv6-only access networks.
+///
+/// Encapsulates the DHCPv4 messages into `DHCPV4-QUERY` messages
+/// and extracts them from `DHCPV4-RESPONSE` ones, so the DHCPv4 client
+/// can be used as is.
+pub struct Dhcp4o6Framed {
+    /// The underlying DHCPv6 socket.
+    inner: Dhcp6Framed,
+    /// The DHCP 4o6 server or relay agent address.
+    server: SocketAddr,
+    /// Used for the DHCPv4 message serialization.
+    buf_write: Vec<u8>,
+}
+
+impl Dhcp4o6Framed {
+    /// Wraps a bound IPv6 UDP socket into a DHCPv4 `Stream+Sink` abstraction.
+    ///
+    /// * `server`
+    /// The address all the queries are sent to, usually the
+    /// `All_DHCP_Relay_Agents_and_Servers` group or one of the addresses
+    /// from the `OPTION_DHCP4_O_DHCP6_SERVER` option.
+    ///
+    /// # Errors
+    /// `io::Error` on unsuccessful socket building.
+    pub fn new(socket: UdpSocket, server: SocketAddr) -> io::Result<Self> {
+        Ok(Dhcp4o6Framed {
+            inner: Dhcp6Framed::new(socket)?,
+            server,
+            buf_write: vec![0u8; BUFFER_WRITE_CAPACITY],
+        })
+    }
+}
+
+impl Stream for Dhcp4o6Framed {
+    type Item = DhcpStreamItem;
+    type Error = io::Error;
+
+    /// Returns `Ok(Async::Ready(Some(_)))` on a `DHCPV4-RESPONSE`
+    /// with a valid DHCPv4 message inside.
+    /// Returns `Ok(Async::Ready(None))` on any other message or a parsing error.
+    ///
+    /// # Errors
+    /// `io::Error` on a socket error.
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let (addr, message) = match try_ready!(self.inner.poll()) {
+            Some(item) => item,
+            None => return Ok(Async::Ready(None)),
+        };
+        if message.message_type != v6::MessageType::Dhcpv4Response {
+            return Ok(Async::Ready(None));
+        }
+        let data = match message.options.dhcpv4_message {
+            Some(data) => data,
+            None => return Ok(Async::Ready(None)),
+        };
+        match Message::from_bytes(&data) {
+            Ok(frame) => Ok(Async::Ready(Some((addr, frame)))),
+            Err(_) => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+impl Sink for Dhcp4o6Framed {
+    type SinkItem = DhcpSinkItem;
+    type SinkError = io::Error;
+
+    /// Returns `Ok(AsyncSink::Ready)` on successful sending or
+    /// storing the data in order to send it when the socket is ready.
+    /// Returns `Ok(AsyncSink::NotReady(item))` if there is pending data.
+    ///
+    /// # Errors
+    /// `io::Error` on an encoding error.
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        let amount = {
+            let (_, (ref message, max_size)) = item;
+            message.to_bytes(&mut self.buf_write, max_size)?
+        };
+
+        // the unicast flag tells the 4o6 server the message would have been unicast (RFC 7341 §6)
+        let flags = match item.0.ip() {
+            IpAddr::V4(address) if address == Ipv4Addr::new(255, 255, 255, 255) => 0,
+            _ => FLAG_DHCP4O6_UNICAST,
+        };
+        let mut options = v6::Options::default();
+        options.dhcpv4_message = Some(self.buf_write[..amount].to_vec());
+        let query = v6::Message {
+            message_type: v6::MessageType::Dhcpv4Query,
+            transaction_id: flags,
+            options,
+        };
+
+        match self.inner.start_send((self.server, query))? {
+            AsyncSink::Ready => Ok(AsyncSink::Ready),
+            AsyncSink::NotReady(_) => Ok(AsyncSink::NotReady(item)),
+        }
+    }
+
+    /// Returns `Ok(Async::Ready(()))` on successful sending.
+    /// Returns `Ok(Async::NotReady)` if the socket is not ready for sending.
+    ///
+    /// # Errors
+    /// `io::Error` on a socket error.
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.inner.poll_complete()
+    }
+
+    /// Just a `poll_complete` proxy.
+    ///
+    /// # Errors
+    /// `io::Error` on a socket error.
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.poll_complete()
+    }
+}
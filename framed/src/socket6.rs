@@ -0,0 +1,118 @@
+//! The DHCPv6 socket module.
+
+use std::net::SocketAddr;
+
+use futures::StartSend;
+use tokio::{io, net::UdpSocket, prelude::*};
+
+use dhcp_protocol::v6::Message;
+
+use socket::{BUFFER_READ_CAPACITY, BUFFER_WRITE_CAPACITY};
+
+pub type Dhcp6StreamItem = (SocketAddr, Message);
+pub type Dhcp6SinkItem = (SocketAddr, Message);
+
+/// The DHCPv6 counterpart of `DhcpFramed`.
+///
+/// Works with high level DHCPv6 messages.
+pub struct Dhcp6Framed {
+    /// `tokio::UdpSocket`.
+    socket: UdpSocket,
+    /// Stores received data and is used for deserialization.
+    buf_read: Vec<u8>,
+    /// Stores pending data and is used for serialization.
+    buf_write: Vec<u8>,
+    /// Stores the destination address and the number of bytes to send.
+    pending: Option<(SocketAddr, usize)>,
+}
+
+impl Dhcp6Framed {
+    /// Wraps a bound IPv6 UDP socket into a `Stream+Sink` abstraction.
+    ///
+    /// The socket must have joined the `All_DHCP_Relay_Agents_and_Servers`
+    /// multicast group if it is used by a server.
+    ///
+    /// # Errors
+    /// `io::Error` on unsuccessful socket building.
+    pub fn new(socket: UdpSocket) -> io::Result<Self> {
+        Ok(Dhcp6Framed {
+            socket,
+            buf_read: vec![0u8; BUFFER_READ_CAPACITY],
+            buf_write: vec![0u8; BUFFER_WRITE_CAPACITY],
+            pending: None,
+        })
+    }
+}
+
+impl Stream for Dhcp6Framed {
+    type Item = Dhcp6StreamItem;
+    type Error = io::Error;
+
+    /// Returns `Ok(Async::Ready(Some(_)))` on successful
+    /// both read from socket and decoding the message.
+    /// Returns `Ok(Async::Ready(None))` a on parsing error.
+    ///
+    /// # Errors
+    /// `io::Error` on a socket error.
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let (amount, addr) = try_ready!(self.socket.poll_recv_from(&mut self.buf_read));
+        match Message::from_bytes(&self.buf_read[..amount]) {
+            Ok(frame) => Ok(Async::Ready(Some((addr, frame)))),
+            Err(_) => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+impl Sink for Dhcp6Framed {
+    type SinkItem = Dhcp6SinkItem;
+    type SinkError = io::Error;
+
+    /// Returns `Ok(AsyncSink::Ready)` on successful sending or
+    /// storing the data in order to send it when the socket is ready.
+    /// Returns `Ok(AsyncSink::NotReady(item))` if there is pending data.
+    ///
+    /// # Errors
+    /// `io::Error` on an encoding error.
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if self.pending.is_some() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+
+        let (addr, message) = item;
+        let amount = message.to_bytes(&mut self.buf_write)?;
+        self.pending = Some((addr, amount));
+
+        Ok(AsyncSink::Ready)
+    }
+
+    /// Returns `Ok(Async::Ready(()))` on successful sending.
+    /// Returns `Ok(Async::NotReady)` if the socket is not ready for sending.
+    ///
+    /// # Errors
+    /// `io::Error` on a socket error.
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        match self.pending {
+            None => return Ok(Async::Ready(())),
+            Some((addr, amount)) => {
+                let sent = try_ready!(self.socket.poll_send_to(&self.buf_write[..amount], &addr));
+                if sent != amount {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "Failed to write entire datagram to socket",
+                    ));
+                }
+            }
+        }
+        self.pending = None;
+
+        Ok(Async::Ready(()))
+    }
+
+    /// Just a `poll_complete` proxy.
+    ///
+    /// # Errors
+    /// `io::Error` on a socket error.
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.poll_complete()
+    }
+}
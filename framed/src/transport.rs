@@ -0,0 +1,480 @@
+//! The runtime-agnostic datagram transports the DHCP messages are framed over.
+//!
+//! The tokio socket is the transport of the reactor the crate is built on,
+//! the `BlockingTransport` needs no reactor at all and the `AsyncStdTransport`
+//! (the `async-std-transport` feature) is driven by the async-std reactor.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr, UdpSocket as StdUdpSocket},
+    thread,
+    time::Duration,
+};
+
+use futures::{sync::mpsc, StartSend};
+use tokio::{io, net::UdpSocket, prelude::*, reactor::Handle};
+
+use dhcp_protocol::{Message, OFFSET_SERVER_NAME};
+
+use socket::{
+    DhcpFramed, DhcpSinkItem, DhcpStreamItem, BUFFER_READ_CAPACITY, BUFFER_WRITE_CAPACITY,
+};
+
+/// How long the reader thread of a `BlockingTransport` blocks
+/// before checking whether the transport has been dropped.
+const BLOCKING_TIMEOUT_MS: u64 = 100;
+/// The datagrams the reader thread receives ahead of the consumer.
+const BLOCKING_QUEUE_SIZE: usize = 64;
+
+/// The datagram socket operations the DHCP framing needs.
+///
+/// `Async::NotReady` means the current task is notified when the operation may succeed,
+/// like with the `futures` I/O objects.
+pub trait DhcpTransport: Sized {
+    /// Binds the socket allowed to send the broadcast datagrams.
+    ///
+    /// # Errors
+    /// `io::Error` on unsuccessful socket binding.
+    fn bind_broadcast(addr: &SocketAddr) -> io::Result<Self>;
+
+    /// Wraps an already bound socket, e.g. one passed by the service manager.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot be registered or set up.
+    fn from_std(socket: StdUdpSocket) -> io::Result<Self>;
+
+    /// Receives a datagram into the buffer.
+    ///
+    /// # Errors
+    /// `io::Error` on a socket error.
+    fn poll_recv_from(&mut self, buf: &mut [u8]) -> Poll<(usize, SocketAddr), io::Error>;
+
+    /// Sends the datagram to the target.
+    ///
+    /// # Errors
+    /// `io::Error` on a socket error.
+    fn poll_send_to(&mut self, buf: &[u8], target: &SocketAddr) -> Poll<usize, io::Error>;
+}
+
+impl DhcpTransport for UdpSocket {
+    fn bind_broadcast(addr: &SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_broadcast(true)?;
+        Ok(socket)
+    }
+
+    fn from_std(socket: StdUdpSocket) -> io::Result<Self> {
+        UdpSocket::from_std(socket, &Handle::default())
+    }
+
+    fn poll_recv_from(&mut self, buf: &mut [u8]) -> Poll<(usize, SocketAddr), io::Error> {
+        UdpSocket::poll_recv_from(self, buf)
+    }
+
+    fn poll_send_to(&mut self, buf: &[u8], target: &SocketAddr) -> Poll<usize, io::Error> {
+        UdpSocket::poll_send_to(self, buf, target)
+    }
+}
+
+/// A datagram received by the reader thread of a `BlockingTransport`.
+type Received = io::Result<(Vec<u8>, SocketAddr)>;
+
+/// A blocking `std` socket for the programs without a reactor, e.g. driven by `Future::wait`.
+///
+/// The datagrams are received by a reader thread, which wakes the task up when one arrives,
+/// so the thread polling the transport never blocks on the socket. The reader thread stops
+/// within 100 milliseconds after the transport is dropped. The datagrams are sent at once.
+pub struct BlockingTransport {
+    socket: StdUdpSocket,
+    /// Fed by the reader thread.
+    received: mpsc::Receiver<Received>,
+}
+
+impl BlockingTransport {
+    /// Wraps a bound socket and starts its reader thread.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot be cloned or the thread cannot be spawned.
+    pub fn new(socket: StdUdpSocket) -> io::Result<Self> {
+        let reader = socket.try_clone()?;
+        reader.set_read_timeout(Some(Duration::from_millis(BLOCKING_TIMEOUT_MS)))?;
+        let (sender, received) = mpsc::channel(BLOCKING_QUEUE_SIZE);
+        thread::Builder::new()
+            .name("dhcp-transport-reader".to_owned())
+            .spawn(move || read(&reader, sender))?;
+        Ok(BlockingTransport { socket, received })
+    }
+
+    pub fn get_ref(&self) -> &StdUdpSocket {
+        &self.socket
+    }
+}
+
+/// Receives the datagrams until the transport is dropped.
+///
+/// Blocks while the queue is full, so the socket buffer fills up instead of the memory.
+fn read(socket: &StdUdpSocket, mut sender: mpsc::Sender<Received>) {
+    let mut buf = vec![0u8; BUFFER_READ_CAPACITY];
+    loop {
+        let received = match socket.recv_from(&mut buf) {
+            Ok((amount, addr)) => Ok((buf[..amount].to_vec(), addr)),
+            Err(ref error)
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut =>
+            {
+                if sender.is_closed() {
+                    return;
+                }
+                continue;
+            }
+            Err(error) => Err(error),
+        };
+        sender = match sender.send(received).wait() {
+            Ok(sender) => sender,
+            Err(_) => return,
+        };
+    }
+}
+
+impl DhcpTransport for BlockingTransport {
+    fn bind_broadcast(addr: &SocketAddr) -> io::Result<Self> {
+        let socket = StdUdpSocket::bind(addr)?;
+        socket.set_broadcast(true)?;
+        Self::new(socket)
+    }
+
+    fn from_std(socket: StdUdpSocket) -> io::Result<Self> {
+        Self::new(socket)
+    }
+
+    fn poll_recv_from(&mut self, buf: &mut [u8]) -> Poll<(usize, SocketAddr), io::Error> {
+        match self.received.poll() {
+            Ok(Async::Ready(Some(Ok((datagram, addr))))) => {
+                let amount = datagram.len().min(buf.len());
+                buf[..amount].copy_from_slice(&datagram[..amount]);
+                Ok(Async::Ready((amount, addr)))
+            }
+            Ok(Async::Ready(Some(Err(error)))) => Err(error),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(None)) | Err(()) => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "The reader thread has stopped",
+            )),
+        }
+    }
+
+    fn poll_send_to(&mut self, buf: &[u8], target: &SocketAddr) -> Poll<usize, io::Error> {
+        Ok(Async::Ready(self.socket.send_to(buf, target)?))
+    }
+}
+
+/// The `DhcpFramed` counterpart working over any `DhcpTransport`.
+///
+/// The `IP_PKTINFO` interfaces, the batched receiving and the packet recording
+/// need the tokio socket and are available only in `DhcpFramed`.
+pub struct TransportFramed<T> {
+    transport: T,
+    /// Stores received data and is used for deserialization.
+    buf_read: Vec<u8>,
+    /// Stores pending data and is used for serialization.
+    buf_write: Vec<u8>,
+    /// Stores the destination address and the number of bytes to send.
+    pending: Option<(SocketAddr, usize)>,
+}
+
+impl<T> TransportFramed<T>
+where
+    T: DhcpTransport,
+{
+    /// Wraps a transport into a `Stream+Sink` abstraction.
+    pub fn new(transport: T) -> Self {
+        TransportFramed {
+            transport,
+            buf_read: vec![0u8; BUFFER_READ_CAPACITY],
+            buf_write: vec![0u8; BUFFER_WRITE_CAPACITY],
+            pending: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+}
+
+impl<T> Stream for TransportFramed<T>
+where
+    T: DhcpTransport,
+{
+    type Item = DhcpStreamItem;
+    type Error = io::Error;
+
+    /// Returns `Ok(Async::Ready(Some(_)))` on successful
+    /// both read from the transport and decoding the message.
+    /// The datagrams which cannot be decoded are skipped, so the stream never ends.
+    ///
+    /// # Errors
+    /// `io::Error` on a transport error.
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let (amount, addr) = try_ready!(self.transport.poll_recv_from(&mut self.buf_read));
+            match Message::from_bytes(&self.buf_read[..amount]) {
+                Ok(frame) => return Ok(Async::Ready(Some((addr, frame)))),
+                Err(error) => debug!("Skipped a malformed datagram from {}: {}", addr, error),
+            }
+        }
+    }
+}
+
+impl<T> Sink for TransportFramed<T>
+where
+    T: DhcpTransport,
+{
+    type SinkItem = DhcpSinkItem;
+    type SinkError = io::Error;
+
+    /// Returns `Ok(AsyncSink::NotReady(item))` if there is pending data.
+    ///
+    /// # Errors
+    /// `io::Error` on an encoding error.
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if self.pending.is_some() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+
+        let (addr, (message, max_size)) = item;
+        let amount = message.to_bytes(&mut self.buf_write, max_size)?;
+        self.pending = Some((addr, amount));
+
+        Ok(AsyncSink::Ready)
+    }
+
+    /// Returns `Ok(Async::NotReady)` if the transport is not ready for sending.
+    ///
+    /// # Errors
+    /// `io::Error` on a transport error.
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        if let Some((addr, amount)) = self.pending {
+            let sent = try_ready!(
+                self.transport
+                    .poll_send_to(&self.buf_write[..amount], &addr)
+            );
+            if sent != amount {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "Failed to write entire datagram to socket",
+                ));
+            }
+        }
+        self.pending = None;
+
+        Ok(Async::Ready(()))
+    }
+
+    /// Just a `poll_complete` proxy.
+    ///
+    /// # Errors
+    /// `io::Error` on a transport error.
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.poll_complete()
+    }
+}
+
+/// The framed socket the server runs over, i.e. `DhcpFramed` or `TransportFramed`.
+///
+/// Only `DhcpFramed` learns and chooses the interfaces, binds to the devices and
+/// receives in batches. The other sockets report no packet information and ignore
+/// the interface settings, so they serve a single interface.
+pub trait ServerSocket:
+    Stream<Item = DhcpStreamItem, Error = io::Error>
+    + Sink<SinkItem = DhcpSinkItem, SinkError = io::Error>
+    + Sized
+{
+    /// Wraps a socket bound to a server port.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot be registered or set up.
+    fn from_std(socket: StdUdpSocket) -> io::Result<Self>;
+
+    /// See `DhcpFramed::enable_pktinfo`.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot learn the interfaces.
+    fn enable_pktinfo(&mut self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The transport cannot learn the interfaces",
+        ))
+    }
+
+    /// See `DhcpFramed::enable_batching`, ignored by the other sockets.
+    fn enable_batching(&mut self, _size: usize) {}
+
+    /// See `DhcpFramed::enable_ring`.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot receive with `io_uring`.
+    fn enable_ring(&mut self, _size: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The transport cannot receive with io_uring",
+        ))
+    }
+
+    /// See `DhcpFramed::bind_to_device`.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot be bound to a device.
+    fn bind_to_device(&self, _name: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The transport cannot be bound to a device",
+        ))
+    }
+
+    /// See `DhcpFramed::ingress_interface`.
+    fn ingress_interface(&self) -> Option<u32> {
+        None
+    }
+
+    /// See `DhcpFramed::destination_address`.
+    fn destination_address(&self) -> Option<Ipv4Addr> {
+        None
+    }
+
+    /// See `DhcpFramed::set_egress_interface`, ignored by the other sockets.
+    fn set_egress_interface(&mut self, _interface: Option<u32>) {}
+
+    /// See `DhcpFramed::set_source_address`, ignored by the other sockets.
+    fn set_source_address(&mut self, _address: Option<Ipv4Addr>) {}
+
+    /// The fixed header of the last received message, i.e. the fields before `server_name`.
+    fn received_header(&self) -> &[u8];
+
+    /// See `DhcpFramed::set_reply_header`, the other sockets encode the replies from scratch.
+    fn set_reply_header(&mut self, _header: Option<[u8; OFFSET_SERVER_NAME]>) {}
+}
+
+impl ServerSocket for DhcpFramed {
+    fn from_std(socket: StdUdpSocket) -> io::Result<Self> {
+        DhcpFramed::new(UdpSocket::from_std(socket, &Handle::default())?)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+    fn enable_pktinfo(&mut self) -> io::Result<()> {
+        DhcpFramed::enable_pktinfo(self)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn enable_batching(&mut self, size: usize) {
+        DhcpFramed::enable_batching(self, size)
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring-receive"))]
+    fn enable_ring(&mut self, size: usize) -> io::Result<()> {
+        DhcpFramed::enable_ring(self, size)
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "io-uring-receive")))]
+    fn enable_ring(&mut self, _size: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The io_uring receiving requires the io-uring-receive feature on Linux",
+        ))
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+    fn bind_to_device(&self, name: &str) -> io::Result<()> {
+        DhcpFramed::bind_to_device(self, name)
+    }
+
+    fn ingress_interface(&self) -> Option<u32> {
+        DhcpFramed::ingress_interface(self)
+    }
+
+    fn destination_address(&self) -> Option<Ipv4Addr> {
+        DhcpFramed::destination_address(self)
+    }
+
+    fn set_egress_interface(&mut self, interface: Option<u32>) {
+        DhcpFramed::set_egress_interface(self, interface)
+    }
+
+    fn set_source_address(&mut self, address: Option<Ipv4Addr>) {
+        DhcpFramed::set_source_address(self, address)
+    }
+
+    fn received_header(&self) -> &[u8] {
+        DhcpFramed::received_header(self)
+    }
+
+    fn set_reply_header(&mut self, header: Option<[u8; OFFSET_SERVER_NAME]>) {
+        DhcpFramed::set_reply_header(self, header)
+    }
+}
+
+impl<T> ServerSocket for TransportFramed<T>
+where
+    T: DhcpTransport,
+{
+    fn from_std(socket: StdUdpSocket) -> io::Result<Self> {
+        T::from_std(socket).map(Self::new)
+    }
+
+    fn received_header(&self) -> &[u8] {
+        &self.buf_read[..OFFSET_SERVER_NAME]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Instant;
+
+    use codec::tests::discover;
+
+    #[test]
+    fn receives_past_malformed_datagram() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let receiver = BlockingTransport::bind_broadcast(&address).unwrap();
+        let destination = receiver.get_ref().local_addr().unwrap();
+        let sender = StdUdpSocket::bind(address).unwrap();
+        sender.send_to(&[1, 2, 3], destination).unwrap();
+        sender.send_to(&discover(), destination).unwrap();
+
+        let (received, _) = TransportFramed::new(receiver)
+            .into_future()
+            .map_err(|(error, _)| error)
+            .wait()
+            .unwrap();
+        let (source, message) = received.expect("The stream has ended");
+        assert_eq!(message.transaction_id, 0x1234_5678);
+        assert_eq!(source, sender.local_addr().unwrap());
+    }
+
+    #[test]
+    fn waits_for_datagram_without_blocking() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut receiver = BlockingTransport::bind_broadcast(&address).unwrap();
+        let destination = receiver.get_ref().local_addr().unwrap();
+        let mut buf = vec![0u8; BUFFER_READ_CAPACITY];
+
+        let started = Instant::now();
+        let polled = future::lazy(|| Ok::<_, ()>(receiver.poll_recv_from(&mut buf)))
+            .wait()
+            .unwrap();
+        assert!(polled.unwrap().is_not_ready());
+        assert!(started.elapsed() < Duration::from_millis(BLOCKING_TIMEOUT_MS));
+
+        let sender = StdUdpSocket::bind(address).unwrap();
+        sender.send_to(&[1, 2, 3], destination).unwrap();
+        let (amount, source) = future::poll_fn(|| receiver.poll_recv_from(&mut buf))
+            .wait()
+            .unwrap();
+        assert_eq!(&buf[..amount], &[1, 2, 3]);
+        assert_eq!(source, sender.local_addr().unwrap());
+    }
+}
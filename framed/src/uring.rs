@@ -0,0 +1,290 @@
+//! The receiving with `io_uring`, which keeps a `recvmsg` request in flight for every buffer
+//! of a ring allocated once, so the kernel fills the buffers as the datagrams arrive
+//! and the buffers taken are resubmitted with a single `io_uring_enter` call.
+//!
+//! The tokio reactor is readiness-based, so the completions are signaled through
+//! an `eventfd` registered with both the ring and the reactor.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read},
+    mem,
+    net::{Ipv4Addr, SocketAddr},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+};
+
+use futures::{Async, Poll};
+use io_uring::{opcode, types, IoUring};
+use libc::{self, c_void, sockaddr_in, socklen_t};
+use mio::{self, unix::EventedFd, Evented, PollOpt, Ready, Token};
+use tokio::reactor::PollEvented2;
+
+use pktinfo::{self, CONTROL_SIZE};
+
+/// The `user_data` of the cancellations, which tells them from the buffer indexes.
+const CANCEL: u64 = u64::MAX;
+
+/// The ring of the pre-allocated receive buffers with a request in flight for each.
+pub struct RecvRing {
+    ring: IoUring,
+    /// Becomes readable when some requests complete.
+    event: PollEvented2<EventFd>,
+    /// The socket the datagrams are received from.
+    fd: RawFd,
+    /// Never resized, since the requests point into them.
+    buffers: Vec<Vec<u8>>,
+    names: Vec<sockaddr_in>,
+    controls: Vec<[u64; CONTROL_SIZE]>,
+    iovecs: Vec<libc::iovec>,
+    headers: Vec<libc::msghdr>,
+    /// The indexes of the filled buffers with the received lengths or the negated errors.
+    completed: VecDeque<(usize, i32)>,
+    /// The number of the receive requests the kernel has not completed.
+    in_flight: usize,
+    /// Whether some requests have been queued but not submitted.
+    is_submit_needed: bool,
+}
+
+/// The raw pointers only refer to the heap memory owned by the ring.
+unsafe impl Send for RecvRing {}
+
+impl RecvRing {
+    /// Sets up the ring of `size` buffers of `buffer_size` bytes, one at least,
+    /// and submits a request for every buffer.
+    ///
+    /// # Errors
+    /// `io::Error` if `io_uring` is not supported by the kernel or not permitted.
+    pub fn new(fd: RawFd, size: usize, buffer_size: usize) -> io::Result<Self> {
+        let size = size.max(1);
+        let ring = IoUring::new((size as u32).next_power_of_two())?;
+        let event = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if event < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let event = EventFd(unsafe { File::from_raw_fd(event) });
+        ring.submitter().register_eventfd(event.0.as_raw_fd())?;
+
+        let mut buffers = vec![vec![0u8; buffer_size]; size];
+        let iovecs = buffers
+            .iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr() as *mut c_void,
+                iov_len: buffer.len(),
+            }).collect();
+        let mut recv_ring = RecvRing {
+            ring,
+            event: PollEvented2::new(event),
+            fd,
+            buffers,
+            names: vec![unsafe { mem::zeroed() }; size],
+            controls: vec![[0u64; CONTROL_SIZE]; size],
+            iovecs,
+            headers: vec![unsafe { mem::zeroed() }; size],
+            completed: VecDeque::with_capacity(size),
+            in_flight: 0,
+            is_submit_needed: false,
+        };
+        for index in 0..size {
+            recv_ring.push(index);
+        }
+        recv_ring.submit()?;
+        Ok(recv_ring)
+    }
+
+    /// Takes the next received datagram, copying it into `buf`, with its source,
+    /// the index of the interface it has come from and its destination address,
+    /// which are unknown unless `IP_PKTINFO` is enabled.
+    ///
+    /// # Errors
+    /// `io::Error` if a request has failed or the ring cannot be entered.
+    pub fn poll_recv(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Poll<(usize, SocketAddr, u32, Option<Ipv4Addr>), io::Error> {
+        loop {
+            if let Some((index, result)) = self.next_completion() {
+                return self.take(index, result, buf).map(Async::Ready);
+            }
+            // the buffers taken are resubmitted at once before waiting for the next datagrams
+            self.submit()?;
+            try_ready!(self.event.poll_read_ready(Ready::readable()));
+            let mut counter = [0u8; 8];
+            match (&self.event.get_ref().0).read(&mut counter) {
+                Ok(_) => {}
+                Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    self.event.clear_read_ready(Ready::readable())?;
+                    if self.ring.completion().is_empty() {
+                        return Ok(Async::NotReady);
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Queues the request filling the buffer.
+    fn push(&mut self, index: usize) {
+        let header = &mut self.headers[index];
+        header.msg_name = &mut self.names[index] as *mut sockaddr_in as *mut c_void;
+        header.msg_namelen = mem::size_of::<sockaddr_in>() as socklen_t;
+        header.msg_iov = &mut self.iovecs[index];
+        header.msg_iovlen = 1;
+        header.msg_control = self.controls[index].as_mut_ptr() as *mut c_void;
+        header.msg_controllen = mem::size_of::<[u64; CONTROL_SIZE]>() as _;
+        header.msg_flags = 0;
+        let entry = opcode::RecvMsg::new(types::Fd(self.fd), header)
+            .build()
+            .user_data(index as u64);
+        // the buffers are not freed until the request completes, see `Drop`
+        unsafe { self.ring.submission().push(&entry) }
+            .expect("The submission queue holds a request per buffer");
+        self.in_flight += 1;
+        self.is_submit_needed = true;
+    }
+
+    /// Submits the queued requests if there are any.
+    fn submit(&mut self) -> io::Result<()> {
+        if self.is_submit_needed {
+            self.ring.submit()?;
+            self.is_submit_needed = false;
+        }
+        Ok(())
+    }
+
+    /// Moves the completions from the ring and returns the first one.
+    fn next_completion(&mut self) -> Option<(usize, i32)> {
+        for entry in self.ring.completion() {
+            if entry.user_data() == CANCEL {
+                continue;
+            }
+            self.in_flight -= 1;
+            self.completed
+                .push_back((entry.user_data() as usize, entry.result()));
+        }
+        self.completed.pop_front()
+    }
+
+    /// Copies the datagram out of the filled buffer and queues the buffer again.
+    fn take(
+        &mut self,
+        index: usize,
+        result: i32,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr, u32, Option<Ipv4Addr>)> {
+        if result < 0 {
+            self.push(index);
+            return Err(io::Error::from_raw_os_error(-result));
+        }
+        let amount = (result as usize)
+            .min(self.buffers[index].len())
+            .min(buf.len());
+        buf[..amount].copy_from_slice(&self.buffers[index][..amount]);
+        let (interface, destination) = pktinfo::packet_info(&self.headers[index]);
+        let source = pktinfo::source(&self.names[index]);
+        self.push(index);
+        Ok((amount, source, interface, destination))
+    }
+}
+
+impl Drop for RecvRing {
+    /// Cancels the requests and waits until the kernel gives up the buffers they write into.
+    fn drop(&mut self) {
+        let mut result = self.submit();
+        if result.is_ok() {
+            for index in 0..self.headers.len() {
+                let entry = opcode::AsyncCancel::new(index as u64)
+                    .build()
+                    .user_data(CANCEL);
+                // the queue is empty after the submission and holds a request per buffer
+                let _ = unsafe { self.ring.submission().push(&entry) };
+            }
+        }
+        while result.is_ok() && self.in_flight > 0 {
+            result = match self.ring.submit_and_wait(1) {
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => Ok(()),
+                result => result.map(|_| ()),
+            };
+            while self.next_completion().is_some() {}
+        }
+        if let Err(error) = result {
+            // the kernel may still write into the buffers, so they are never freed
+            error!("Unable to cancel the io_uring requests: {}", error);
+            mem::forget(mem::take(&mut self.buffers));
+            mem::forget(mem::take(&mut self.names));
+            mem::forget(mem::take(&mut self.controls));
+            mem::forget(mem::take(&mut self.iovecs));
+            mem::forget(mem::take(&mut self.headers));
+        }
+    }
+}
+
+/// The completion `eventfd`, closed when dropped.
+struct EventFd(File);
+
+impl Evented for EventFd {
+    fn register(
+        &self,
+        poll: &mio::Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).register(poll, token, interest, opts)
+    }
+
+    fn reregister(
+        &self,
+        poll: &mio::Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).deregister(poll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::UdpSocket;
+
+    use futures::future;
+    use tokio::runtime::current_thread::Runtime;
+
+    #[test]
+    fn receives_into_ring() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let destination = receiver.local_addr().unwrap();
+        let mut ring = match RecvRing::new(receiver.as_raw_fd(), 2, 64) {
+            Ok(ring) => ring,
+            // the kernel or the sandbox may forbid io_uring
+            Err(_) => return,
+        };
+        for data in &[b"first", b"other", b"third"] {
+            sender.send_to(&data[..], destination).unwrap();
+        }
+
+        let mut received = Vec::new();
+        Runtime::new()
+            .unwrap()
+            .block_on(future::poll_fn(|| {
+                let mut buf = [0u8; 64];
+                while received.len() < 3 {
+                    let (amount, source, _, _) = try_ready!(ring.poll_recv(&mut buf));
+                    assert_eq!(source, sender.local_addr().unwrap());
+                    received.push(buf[..amount].to_vec());
+                }
+                Ok::<_, io::Error>(Async::Ready(()))
+            })).unwrap();
+        // the requests in flight may complete in any order
+        received.sort();
+        assert_eq!(received, vec![b"first".to_vec(), b"other".to_vec(), b"third".to_vec()]);
+    }
+}
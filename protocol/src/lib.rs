@@ -2,14 +2,25 @@
 
 extern crate bytes;
 extern crate eui48;
-#[macro_use]
-extern crate failure;
+extern crate md5;
 
 mod v4;
+pub mod v6;
 
 pub use self::v4::{
+    auth,
     constants::*,
-    options::{MessageType, OptionTag, Options, Overload},
+    options::{
+        decode_classless_routes, encode_classless_routes, ClientFqdn, MessageType, OptionTag,
+        Options, Overload, RelayAgentInformation, StatusCode, DHCP_STATE_ABANDONED,
+        DHCP_STATE_ACTIVE, DHCP_STATE_AVAILABLE, DHCP_STATE_EXPIRED, DHCP_STATE_RELEASED,
+        DHCP_STATE_REMOTE, DHCP_STATE_RESET, DHCP_STATE_TRANSITIONING, FQDN_FLAG_E, FQDN_FLAG_N,
+        FQDN_FLAG_O, FQDN_FLAG_S, MICROSOFT_CLASSLESS_STATIC_ROUTES, STATUS_MALFORMED_QUERY,
+        STATUS_NOT_ALLOWED, STATUS_QUERY_TERMINATED, STATUS_SUCCESS, STATUS_UNSPEC_FAIL,
+        SUBOPTION_CIRCUIT_ID, SUBOPTION_LINK_SELECTION, SUBOPTION_REMOTE_ID,
+    },
+    Error as ProtocolError,
+    Fingerprint,
     HardwareType,
     Message,
     OperationCode,
@@ -17,3 +28,5 @@ pub use self::v4::{
 
 pub const DHCP_PORT_SERVER: u16 = 67;
 pub const DHCP_PORT_CLIENT: u16 = 68;
+/// The port of the PXE boot servers and the ProxyDHCP requests.
+pub const DHCP_PORT_PXE: u16 = 4011;
@@ -0,0 +1,191 @@
+//! RFC 3118 message authentication module.
+
+use md5;
+
+use super::constants::*;
+use super::options::OptionTag;
+
+/// The delayed authentication protocol (RFC 3118 §5).
+pub const PROTOCOL_DELAYED: u8 = 1;
+/// The reconfigure key protocol of the FORCERENEW nonces (RFC 6704 §3.3).
+pub const PROTOCOL_RECONFIGURE_KEY: u8 = 3;
+/// The HMAC-MD5 algorithm of the delayed authentication and reconfigure key protocols.
+pub const ALGORITHM_HMAC_MD5: u8 = 1;
+/// The reconfigure key information carrying the nonce (RFC 6704 §3.3.1).
+pub const RECONFIGURE_KEY_VALUE: u8 = 1;
+/// The reconfigure key information carrying the HMAC-MD5 (RFC 6704 §3.3.2).
+pub const RECONFIGURE_KEY_HMAC_MD5: u8 = 2;
+/// The reconfigure key nonce size in bytes.
+pub const SIZE_RECONFIGURE_KEY: usize = 16;
+/// The monotonically increasing replay detection counter (RFC 3118 §2).
+pub const RDM_MONOTONIC: u8 = 0;
+/// The HMAC-MD5 size in bytes.
+pub const SIZE_HMAC_MD5: usize = 16;
+/// The secret ID size in bytes.
+pub const SIZE_SECRET_ID: usize = 4;
+/// The protocol, algorithm, RDM and replay detection fields size in bytes.
+pub const SIZE_AUTHENTICATION_FIXED: usize = 11;
+
+/// The `hops` field offset in bytes.
+const OFFSET_HOPS: usize = 3;
+/// The `giaddr` field offset in bytes.
+const OFFSET_GATEWAY_IP_ADDRESS: usize = 24;
+/// The MD5 block size in bytes.
+const SIZE_MD5_BLOCK: usize = 64;
+
+/// The `Authentication` option value (RFC 3118 §2).
+#[derive(Clone, Default)]
+pub struct Authentication {
+    pub protocol: u8,
+    pub algorithm: u8,
+    pub replay_detection_method: u8,
+    pub replay_detection: u64,
+    /// For the delayed authentication protocol, consists of the DHCP realm,
+    /// the secret ID and the HMAC-MD5. Empty in `DHCPDISCOVER`.
+    pub information: Vec<u8>,
+    /// The received packet prepared for the MAC verification.
+    ///
+    /// Is filled by the deserializer and ignored by the serializer.
+    pub authenticated_data: Option<Vec<u8>>,
+}
+
+impl ::std::fmt::Debug for Authentication {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(
+            f,
+            "protocol {}, algorithm {}, RDM {}, replay {}, information {:?}",
+            self.protocol,
+            self.algorithm,
+            self.replay_detection_method,
+            self.replay_detection,
+            self.information,
+        )
+    }
+}
+
+impl Authentication {
+    /// The secret ID of the delayed authentication protocol.
+    pub fn secret_id(&self) -> Option<u32> {
+        let len = self.information.len();
+        if len < SIZE_SECRET_ID + SIZE_HMAC_MD5 {
+            return None;
+        }
+        let start = len - SIZE_HMAC_MD5 - SIZE_SECRET_ID;
+        Some(
+            self.information[start..start + SIZE_SECRET_ID]
+                .iter()
+                .fold(0u32, |id, byte| (id << 8) | u32::from(*byte)),
+        )
+    }
+
+    /// The DHCP realm of the delayed authentication protocol.
+    pub fn realm(&self) -> Option<&[u8]> {
+        let len = self.information.len();
+        if len < SIZE_SECRET_ID + SIZE_HMAC_MD5 {
+            return None;
+        }
+        Some(&self.information[..len - SIZE_HMAC_MD5 - SIZE_SECRET_ID])
+    }
+
+    /// The MAC of the delayed authentication protocol.
+    pub fn mac(&self) -> Option<&[u8]> {
+        let len = self.information.len();
+        if len < SIZE_SECRET_ID + SIZE_HMAC_MD5 {
+            return None;
+        }
+        Some(&self.information[len - SIZE_HMAC_MD5..])
+    }
+
+    /// Checks the MAC of a received message with the key.
+    ///
+    /// Returns `false` if the message is not authenticated with the delayed protocol.
+    pub fn verify(&self, key: &[u8]) -> bool {
+        if self.protocol != PROTOCOL_DELAYED || self.algorithm != ALGORITHM_HMAC_MD5 {
+            return false;
+        }
+        match (self.mac(), self.authenticated_data.as_ref()) {
+            (Some(mac), Some(data)) => {
+                let expected = hmac_md5(key, data);
+                // the constant-time comparison
+                mac.iter()
+                    .zip(expected.iter())
+                    .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+                    == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Computes the HMAC-MD5 (RFC 2104).
+pub fn hmac_md5(key: &[u8], data: &[u8]) -> [u8; SIZE_HMAC_MD5] {
+    let mut block = [0u8; SIZE_MD5_BLOCK];
+    if key.len() > SIZE_MD5_BLOCK {
+        block[..SIZE_HMAC_MD5].copy_from_slice(&md5::compute(key).0);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = md5::Context::new();
+    inner.consume(&block.iter().map(|byte| byte ^ 0x36).collect::<Vec<u8>>());
+    inner.consume(data);
+    let inner = inner.compute();
+
+    let mut outer = md5::Context::new();
+    outer.consume(&block.iter().map(|byte| byte ^ 0x5c).collect::<Vec<u8>>());
+    outer.consume(&inner.0);
+    outer.compute().0
+}
+
+/// Prepares a packet for the MAC computation.
+///
+/// The `hops` and `giaddr` fields and the MAC are zeroed, since they
+/// may be changed by relay agents or are being computed (RFC 3118 §5.4).
+///
+/// Returns `None` if the packet has no delayed authentication or reconfigure key
+/// HMAC-MD5 information in the main options area.
+pub fn authenticated_data(packet: &[u8]) -> Option<Vec<u8>> {
+    let mac_offset = find_mac(packet)?;
+    let mut data = packet.to_vec();
+    data[OFFSET_HOPS] = 0;
+    for byte in data[OFFSET_GATEWAY_IP_ADDRESS..OFFSET_GATEWAY_IP_ADDRESS + 4].iter_mut() {
+        *byte = 0;
+    }
+    for byte in data[mac_offset..mac_offset + SIZE_HMAC_MD5].iter_mut() {
+        *byte = 0;
+    }
+    Some(data)
+}
+
+/// Finds the MAC offset in the main options area.
+fn find_mac(packet: &[u8]) -> Option<usize> {
+    let mut offset = OFFSET_OPTIONS;
+    while offset < packet.len() {
+        let tag = packet[offset];
+        if tag == OptionTag::Pad as u8 {
+            offset += 1;
+            continue;
+        }
+        if tag == OptionTag::End as u8 || offset + 1 >= packet.len() {
+            return None;
+        }
+        let len = packet[offset + 1] as usize;
+        let end = offset + 2 + len;
+        if end > packet.len() {
+            return None;
+        }
+        if tag == OptionTag::Authentication as u8 {
+            // the secret ID or the reconfigure key information type precedes the MAC
+            let prefix = match packet.get(offset + 2) {
+                Some(&PROTOCOL_RECONFIGURE_KEY) => 1,
+                _ => SIZE_SECRET_ID,
+            };
+            if len < SIZE_AUTHENTICATION_FIXED + prefix + SIZE_HMAC_MD5 {
+                return None;
+            }
+            return Some(end - SIZE_HMAC_MD5);
+        }
+        offset = end;
+    }
+    None
+}
@@ -33,5 +33,10 @@ pub const SIZE_HEADER_IP: usize = 20;
 /// The size of the UDP header the server uses.
 pub const SIZE_HEADER_UDP: usize = 8;
 
+/// The size of a message with the 64-byte BOOTP vendor area (RFC 951).
+///
+/// Some BOOTP clients drop the shorter messages, so the messages are padded to it.
+pub const SIZE_MESSAGE_BOOTP: usize = 300;
+
 /// The minimal message size the client MUST be able to accept.
 pub const SIZE_MESSAGE_MINIMAL: usize = 576;
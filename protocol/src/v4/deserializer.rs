@@ -6,8 +6,11 @@ use bytes::Buf;
 use eui48::{EUI48LEN, MacAddress};
 
 use super::{
+    auth,
     constants::*,
-    options::{OptionTag::*, Options, Overload},
+    options::{
+        ClientFqdn, OptionTag::*, Options, Overload, RelayAgentInformation, StatusCode,
+    },
     Message,
 };
 
@@ -98,8 +101,9 @@ impl Message {
             options: Options::default(),
         };
 
+        // the RFC 951 BOOTP clients may fill the vendor area with anything but the options
         if cursor.get_u32_be() != MAGIC_COOKIE {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "MAGIC_COOKIE"));
+            return Ok(message);
         }
 
         Self::append_options(&mut cursor, &mut message.options)?;
@@ -125,6 +129,10 @@ impl Message {
             _ => {}
         }
 
+        if let Some(ref mut authentication) = message.options.authentication {
+            authentication.authenticated_data = auth::authenticated_data(src);
+        }
+
         Ok(message)
     }
 
@@ -178,11 +186,34 @@ impl Message {
                     options.dhcp_message_type = Some(Self::get_opt_u8(&mut cursor)?.into())
                 }
                 DhcpServerId => options.dhcp_server_id = Some(Self::get_opt_ipv4(&mut cursor)?),
+                SubnetSelection => {
+                    options.subnet_selection = Some(Self::get_opt_ipv4(&mut cursor)?)
+                }
                 DhcpMaxMessageSize => {
                     options.dhcp_max_message_size = Some(Self::get_opt_u16(&mut cursor)?)
                 }
                 RenewalTime => options.renewal_time = Some(Self::get_opt_u32(&mut cursor)?),
                 RebindingTime => options.rebinding_time = Some(Self::get_opt_u32(&mut cursor)?),
+                Ipv6OnlyPreferred => {
+                    options.ipv6_only_preferred = Some(Self::get_opt_u32(&mut cursor)?)
+                }
+                Authentication => {
+                    options.authentication = Some(Self::get_opt_authentication(&mut cursor)?)
+                }
+                ClientLastTransactionTime => {
+                    options.client_last_transaction_time = Some(Self::get_opt_u32(&mut cursor)?)
+                }
+                AssociatedIp => {
+                    options.associated_ip = Some(Self::get_opt_vec_ipv4(
+                        &mut cursor,
+                        &mut options.associated_ip,
+                    )?)
+                }
+                ClientFqdn => options.client_fqdn = Some(Self::get_opt_client_fqdn(&mut cursor)?),
+                RelayAgentInformation => {
+                    options.relay_agent_information =
+                        Some(Self::get_opt_relay_agent_information(&mut cursor)?)
+                }
 
                 // splittable options
                 Routers => {
@@ -344,6 +375,10 @@ impl Message {
                 ClassId => {
                     options.class_id = Some(Self::get_opt_vec(&mut cursor, &mut options.class_id)?)
                 }
+                UserClass => {
+                    options.user_class =
+                        Some(Self::get_opt_vec(&mut cursor, &mut options.user_class)?)
+                }
                 ClientId => {
                     options.client_id =
                         Some(Self::get_opt_vec(&mut cursor, &mut options.client_id)?)
@@ -436,12 +471,54 @@ impl Message {
                         &mut options.stda_servers,
                     )?)
                 }
+                CaptivePortal => {
+                    options.captive_portal = Some(Self::get_opt_string(
+                        &mut cursor,
+                        &mut options.captive_portal,
+                    )?)
+                }
+                WebProxyAutoDiscovery => {
+                    options.web_proxy_auto_discovery = Some(Self::get_opt_string(
+                        &mut cursor,
+                        &mut options.web_proxy_auto_discovery,
+                    )?)
+                }
+                ClientArchitecture => {
+                    options.client_architecture = Some(Self::get_opt_vec_u16(
+                        &mut cursor,
+                        &mut options.client_architecture,
+                    )?)
+                }
+                ClientMachineId => {
+                    options.client_machine_id = Some(Self::get_opt_vec(
+                        &mut cursor,
+                        &mut options.client_machine_id,
+                    )?)
+                }
+                MudUrl => {
+                    options.mud_url = Some(Self::get_opt_string(&mut cursor, &mut options.mud_url)?)
+                }
                 ClasslessStaticRoutes => {
                     options.classless_static_routes = Some(Self::get_opt_classless_static_routes(
                         &mut cursor,
                         &mut options.classless_static_routes,
                     )?)
                 }
+                ForcerenewNonceCapable => {
+                    options.forcerenew_nonce_capable = Some(Self::get_opt_vec(
+                        &mut cursor,
+                        &mut options.forcerenew_nonce_capable,
+                    )?)
+                }
+                StatusCode => options.status_code = Some(Self::get_opt_status_code(&mut cursor)?),
+                BaseTime => options.base_time = Some(Self::get_opt_u32(&mut cursor)?),
+                StartTimeOfState => {
+                    options.start_time_of_state = Some(Self::get_opt_u32(&mut cursor)?)
+                }
+                QueryStartTime => options.query_start_time = Some(Self::get_opt_u32(&mut cursor)?),
+                QueryEndTime => options.query_end_time = Some(Self::get_opt_u32(&mut cursor)?),
+                DhcpState => options.dhcp_state = Some(Self::get_opt_u8(&mut cursor)?),
+                DataSource => options.data_source = Some(Self::get_opt_u8(&mut cursor)?),
 
                 End => break,
                 Pad => continue,
@@ -492,6 +569,74 @@ impl Message {
     }
 
     /// Can be splitted so values are appended if an option already contains some data.
+    fn get_opt_authentication(
+        cursor: &mut io::Cursor<&[u8]>,
+    ) -> io::Result<auth::Authentication> {
+        check_remaining!(cursor, mem::size_of::<u8>());
+        let len = cursor.get_u8() as usize;
+        check_remaining!(cursor, len);
+        if len < auth::SIZE_AUTHENTICATION_FIXED {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Authentication option is too short",
+            ));
+        }
+        let authentication = auth::Authentication {
+            protocol: cursor.get_u8(),
+            algorithm: cursor.get_u8(),
+            replay_detection_method: cursor.get_u8(),
+            replay_detection: cursor.get_u64_be(),
+            information: cursor.bytes()[..len - auth::SIZE_AUTHENTICATION_FIXED].to_vec(),
+            authenticated_data: None,
+        };
+        cursor.advance(len - auth::SIZE_AUTHENTICATION_FIXED);
+        Ok(authentication)
+    }
+
+    /// Cannot be splitted so reassembling not required.
+    fn get_opt_relay_agent_information(
+        cursor: &mut io::Cursor<&[u8]>,
+    ) -> io::Result<RelayAgentInformation> {
+        check_remaining!(cursor, mem::size_of::<u8>());
+        let len = cursor.get_u8() as usize;
+        check_length!(len);
+        check_remaining!(cursor, len);
+        let value = RelayAgentInformation::from_bytes(&cursor.bytes()[..len]).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Relay agent information sub-option exceeds the option",
+            )
+        })?;
+        cursor.advance(len);
+        Ok(value)
+    }
+
+    /// Cannot be splitted so reassembling not required.
+    fn get_opt_client_fqdn(cursor: &mut io::Cursor<&[u8]>) -> io::Result<ClientFqdn> {
+        check_remaining!(cursor, mem::size_of::<u8>());
+        let len = cursor.get_u8() as usize;
+        check_length!(len);
+        check_remaining!(cursor, len);
+        let value = ClientFqdn::from_bytes(&cursor.bytes()[..len]).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Malformed client FQDN option")
+        })?;
+        cursor.advance(len);
+        Ok(value)
+    }
+
+    /// Cannot be splitted so reassembling not required.
+    fn get_opt_status_code(cursor: &mut io::Cursor<&[u8]>) -> io::Result<StatusCode> {
+        check_remaining!(cursor, mem::size_of::<u8>());
+        let len = cursor.get_u8() as usize;
+        check_length!(len);
+        check_remaining!(cursor, len);
+        let value = StatusCode::from_bytes(&cursor.bytes()[..len]).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Malformed status code option")
+        })?;
+        cursor.advance(len);
+        Ok(value)
+    }
+
     fn get_opt_string(
         cursor: &mut io::Cursor<&[u8]>,
         option: &mut Option<String>,
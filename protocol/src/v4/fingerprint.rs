@@ -0,0 +1,41 @@
+//! DHCP fingerprint module.
+
+use std::fmt;
+
+use super::Message;
+
+/// The request options a client operating system or device is recognized by.
+///
+/// Displayed like the Fingerbank fingerprints, e.g. `1,3,6,15,31,33,43,44,46,47,119,121,249,252`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    /// The `Parameter Request List` option codes in the client order.
+    pub parameter_list: Vec<u8>,
+    /// The `Vendor Class Identifier` option, if sent.
+    pub vendor_class: Option<Vec<u8>>,
+}
+
+impl Message {
+    /// Extracts the fingerprint of the client.
+    ///
+    /// Returns `None` if the message has no `Parameter Request List` option.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        let parameter_list = self.options.parameter_list.as_ref()?;
+        Some(Fingerprint {
+            parameter_list: parameter_list.to_owned(),
+            vendor_class: self.options.class_id.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, code) in self.parameter_list.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", code)?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 //! The main DHCP message module.
+pub mod auth;
 pub mod constants;
+pub mod fingerprint;
 pub mod hardware_type;
 pub mod operation_code;
 pub mod options;
@@ -13,9 +15,11 @@ use std::{fmt, net::Ipv4Addr};
 use eui48::MacAddress;
 
 pub use self::{
+    fingerprint::Fingerprint,
     hardware_type::HardwareType,
     operation_code::OperationCode,
     options::{OptionTag, Options},
+    validator::Error,
 };
 
 /// DHCP message.
@@ -198,10 +202,71 @@ impl fmt::Display for Message {
         dbg_opt!(f, self.options.street_talk_servers, iter);
         dbg_opt!(f, self.options.stda_servers, iter);
 
+        let mut iter = (OptionTag::UserClass as u8)..=(OptionTag::UserClass as u8);
+        dbg_opt!(f, self.options.user_class, iter);
+
+        let mut iter = (OptionTag::ClientFqdn as u8)..=(OptionTag::ClientFqdn as u8);
+        dbg_opt!(f, self.options.client_fqdn, iter);
+
+        let mut iter =
+            (OptionTag::RelayAgentInformation as u8)..=(OptionTag::RelayAgentInformation as u8);
+        dbg_opt!(f, self.options.relay_agent_information, iter);
+
+        let mut iter = (OptionTag::Authentication as u8)..=(OptionTag::Authentication as u8);
+        dbg_opt!(f, self.options.authentication, iter);
+
+        let mut iter = (OptionTag::ClientLastTransactionTime as u8)
+            ..=(OptionTag::ClientLastTransactionTime as u8);
+        dbg_opt!(f, self.options.client_last_transaction_time, iter);
+
+        let mut iter = (OptionTag::AssociatedIp as u8)..=(OptionTag::AssociatedIp as u8);
+        dbg_opt!(f, self.options.associated_ip, iter);
+
+        let mut iter =
+            (OptionTag::ClientArchitecture as u8)..=(OptionTag::ClientArchitecture as u8);
+        dbg_opt!(f, self.options.client_architecture, iter);
+
+        let mut iter = (OptionTag::ClientMachineId as u8)..=(OptionTag::ClientMachineId as u8);
+        dbg_opt!(f, self.options.client_machine_id, iter);
+
+        let mut iter =
+            (OptionTag::Ipv6OnlyPreferred as u8)..=(OptionTag::Ipv6OnlyPreferred as u8);
+        dbg_opt!(f, self.options.ipv6_only_preferred, iter);
+
+        let mut iter = (OptionTag::CaptivePortal as u8)..=(OptionTag::CaptivePortal as u8);
+        dbg_opt!(f, self.options.captive_portal, iter);
+
+        let mut iter = (OptionTag::SubnetSelection as u8)..=(OptionTag::SubnetSelection as u8);
+        dbg_opt!(f, self.options.subnet_selection, iter);
+
         let mut iter =
             (OptionTag::ClasslessStaticRoutes as u8)..=(OptionTag::ClasslessStaticRoutes as u8);
         dbg_opt!(f, self.options.classless_static_routes, iter);
 
+        let mut iter =
+            (OptionTag::ForcerenewNonceCapable as u8)..=(OptionTag::ForcerenewNonceCapable as u8);
+        dbg_opt!(f, self.options.forcerenew_nonce_capable, iter);
+
+        let mut iter = (OptionTag::StatusCode as u8)..=(OptionTag::DataSource as u8);
+        dbg_opt!(f, self.options.status_code, iter);
+        dbg_opt!(f, self.options.base_time, iter);
+        dbg_opt!(f, self.options.start_time_of_state, iter);
+        dbg_opt!(f, self.options.query_start_time, iter);
+        dbg_opt!(f, self.options.query_end_time, iter);
+        dbg_opt!(f, self.options.dhcp_state, iter);
+        dbg_opt!(f, self.options.data_source, iter);
+
+        let mut iter = (OptionTag::MudUrl as u8)..=(OptionTag::MudUrl as u8);
+        dbg_opt!(f, self.options.mud_url, iter);
+
+        let mut iter = (OptionTag::WebProxyAutoDiscovery as u8)
+            ..=(OptionTag::WebProxyAutoDiscovery as u8);
+        dbg_opt!(f, self.options.web_proxy_auto_discovery, iter);
+
+        for &(tag, ref data) in self.options.raw.iter() {
+            writeln!(f, "[{:03}] {:027}| {:?}", tag, "raw", data)?;
+        }
+
         writeln!(f, "{}", "_".repeat(75))?;
         Ok(())
     }
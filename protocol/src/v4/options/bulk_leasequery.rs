@@ -0,0 +1,52 @@
+//! DHCP bulk leasequery options module.
+
+/// The query has been processed successfully (RFC 6926 §6.2.2).
+pub const STATUS_SUCCESS: u8 = 0;
+/// The query has failed for an unspecified reason (RFC 6926 §6.2.2).
+pub const STATUS_UNSPEC_FAIL: u8 = 1;
+/// The server has terminated the query (RFC 6926 §6.2.2).
+pub const STATUS_QUERY_TERMINATED: u8 = 2;
+/// The query is malformed or of an unsupported type (RFC 6926 §6.2.2).
+pub const STATUS_MALFORMED_QUERY: u8 = 3;
+/// The requester is not allowed to query the server (RFC 6926 §6.2.2).
+pub const STATUS_NOT_ALLOWED: u8 = 4;
+
+/// The states of the addresses reported in the `dhcp-state` option (RFC 6926 §6.2.8).
+pub const DHCP_STATE_AVAILABLE: u8 = 1;
+pub const DHCP_STATE_ACTIVE: u8 = 2;
+pub const DHCP_STATE_EXPIRED: u8 = 3;
+pub const DHCP_STATE_RELEASED: u8 = 4;
+pub const DHCP_STATE_ABANDONED: u8 = 5;
+pub const DHCP_STATE_RESET: u8 = 6;
+pub const DHCP_STATE_REMOTE: u8 = 7;
+pub const DHCP_STATE_TRANSITIONING: u8 = 8;
+
+/// The `status-code` option value (RFC 6926 §6.2.2).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusCode {
+    pub code: u8,
+    /// A UTF-8 text for the administrators, may be empty.
+    pub message: String,
+}
+
+impl StatusCode {
+    /// Parses the option data.
+    ///
+    /// Returns `None` if the data is empty or the message is not UTF-8.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.is_empty() {
+            return None;
+        }
+        Some(StatusCode {
+            code: data[0],
+            message: String::from_utf8(data[1..].to_vec()).ok()?,
+        })
+    }
+
+    /// Encodes the option data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![self.code];
+        data.extend_from_slice(self.message.as_bytes());
+        data
+    }
+}
@@ -0,0 +1,52 @@
+//! DHCP classless static routes option codec.
+
+use std::net::Ipv4Addr;
+
+/// The pre-standard Microsoft code of the classless static routes option,
+/// requested by the older Windows clients instead of 121.
+pub const MICROSOFT_CLASSLESS_STATIC_ROUTES: u8 = 249;
+
+/// Encodes the `(destination, mask, router)` routes as the option data (RFC 3442 §3).
+///
+/// Only the significant octets of the destination are sent.
+pub fn encode_classless_routes(routes: &[(Ipv4Addr, Ipv4Addr, Ipv4Addr)]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(routes.len() * 9);
+    for &(destination, mask, router) in routes.iter() {
+        let width = u32::from(mask).count_ones() as usize;
+        data.push(width as u8);
+        data.extend_from_slice(&destination.octets()[..(width + 7) / 8]);
+        data.extend_from_slice(&router.octets());
+    }
+    data
+}
+
+/// Decodes the option data into the `(destination, mask, router)` routes.
+///
+/// Returns `None` if the data is truncated or a mask width exceeds 32.
+pub fn decode_classless_routes(data: &[u8]) -> Option<Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>> {
+    let mut routes = Vec::new();
+    let mut rest = data;
+    while let Some(&width) = rest.first() {
+        if width > 32 {
+            return None;
+        }
+        let significant = (width as usize + 7) / 8;
+        let destination = rest.get(1..1 + significant)?;
+        let router = rest.get(1 + significant..5 + significant)?;
+
+        let mut octets = [0u8; 4];
+        octets[..significant].copy_from_slice(destination);
+        let mask = if width == 0 {
+            0
+        } else {
+            u32::max_value() << (32 - u32::from(width))
+        };
+        routes.push((
+            Ipv4Addr::from(octets),
+            Ipv4Addr::from(mask),
+            Ipv4Addr::new(router[0], router[1], router[2], router[3]),
+        ));
+        rest = &rest[5 + significant..];
+    }
+    Some(routes)
+}
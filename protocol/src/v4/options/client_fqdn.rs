@@ -0,0 +1,93 @@
+//! DHCP client FQDN option module.
+
+/// The client asks the server to perform the A RR update (RFC 4702 §2.1).
+pub const FQDN_FLAG_S: u8 = 0x01;
+/// The server has overridden the client `S` flag (RFC 4702 §2.1).
+pub const FQDN_FLAG_O: u8 = 0x02;
+/// The domain name is in the canonical wire format (RFC 4702 §2.1).
+pub const FQDN_FLAG_E: u8 = 0x04;
+/// The server must not perform any DNS updates (RFC 4702 §2.1).
+pub const FQDN_FLAG_N: u8 = 0x08;
+
+/// The `Client FQDN` option value (RFC 4702 §2).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientFqdn {
+    pub flags: u8,
+    /// Deprecated, sent as 255 by the server.
+    pub rcode1: u8,
+    /// Deprecated, sent as 255 by the server.
+    pub rcode2: u8,
+    /// Ends with a dot if the name is fully qualified.
+    pub domain_name: String,
+}
+
+impl ClientFqdn {
+    /// Parses the option data.
+    ///
+    /// Returns `None` if the data is too short or the domain name is malformed.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < 3 {
+            return None;
+        }
+        let flags = data[0];
+        let domain_name = if flags & FQDN_FLAG_E != 0 {
+            decode_name(&data[3..])?
+        } else {
+            String::from_utf8(data[3..].to_vec()).ok()?
+        };
+        Some(ClientFqdn {
+            flags,
+            rcode1: data[1],
+            rcode2: data[2],
+            domain_name,
+        })
+    }
+
+    /// Encodes the option data, the domain name format is chosen by the `E` flag.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![self.flags, self.rcode1, self.rcode2];
+        if self.flags & FQDN_FLAG_E != 0 {
+            data.extend(encode_name(&self.domain_name));
+        } else {
+            data.extend_from_slice(self.domain_name.as_bytes());
+        }
+        data
+    }
+
+    /// Checks whether the flag is set.
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// The partial names lack the terminating zero-length label (RFC 4702 §2.3.1).
+fn decode_name(data: &[u8]) -> Option<String> {
+    let mut name = String::new();
+    let mut i = 0;
+    while i < data.len() {
+        let len = data[i] as usize;
+        if len == 0 {
+            name.push('.');
+            break;
+        }
+        let label = data.get(i + 1..i + 1 + len)?;
+        if !name.is_empty() {
+            name.push('.');
+        }
+        name.push_str(&String::from_utf8(label.to_vec()).ok()?);
+        i += 1 + len;
+    }
+    Some(name)
+}
+
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    for label in name.trim_right_matches('.').split('.') {
+        data.push(label.len() as u8);
+        data.extend_from_slice(label.as_bytes());
+    }
+    if name.ends_with('.') {
+        data.push(0);
+    }
+    data
+}
@@ -2,7 +2,7 @@
 
 use std::fmt;
 
-/// DHCP message type (RFC 2131 only).
+/// DHCP message type (RFC 2131, RFC 3203, RFC 4388 and RFC 6926).
 #[derive(Debug, Clone, Copy)]
 pub enum MessageType {
     Undefined = 0,
@@ -14,6 +14,13 @@ pub enum MessageType {
     DhcpNak,
     DhcpRelease,
     DhcpInform,
+    DhcpForceRenew,
+    DhcpLeaseQuery = 10,
+    DhcpLeaseUnassigned,
+    DhcpLeaseUnknown,
+    DhcpLeaseActive,
+    DhcpBulkLeaseQuery = 14,
+    DhcpLeaseQueryDone,
 }
 
 impl fmt::Display for MessageType {
@@ -28,6 +35,13 @@ impl fmt::Display for MessageType {
             DhcpNak => write!(f, "DHCPNAK"),
             DhcpRelease => write!(f, "DHCPRELEASE"),
             DhcpInform => write!(f, "DHCPINFORM"),
+            DhcpForceRenew => write!(f, "DHCPFORCERENEW"),
+            DhcpLeaseQuery => write!(f, "DHCPLEASEQUERY"),
+            DhcpLeaseUnassigned => write!(f, "DHCPLEASEUNASSIGNED"),
+            DhcpLeaseUnknown => write!(f, "DHCPLEASEUNKNOWN"),
+            DhcpLeaseActive => write!(f, "DHCPLEASEACTIVE"),
+            DhcpBulkLeaseQuery => write!(f, "DHCPBULKLEASEQUERY"),
+            DhcpLeaseQueryDone => write!(f, "DHCPLEASEQUERYDONE"),
 
             Undefined => write!(f, "UNDEFINED"),
         }
@@ -46,6 +60,13 @@ impl From<u8> for MessageType {
             6 => DhcpNak,
             7 => DhcpRelease,
             8 => DhcpInform,
+            9 => DhcpForceRenew,
+            10 => DhcpLeaseQuery,
+            11 => DhcpLeaseUnassigned,
+            12 => DhcpLeaseUnknown,
+            13 => DhcpLeaseActive,
+            14 => DhcpBulkLeaseQuery,
+            15 => DhcpLeaseQueryDone,
 
             _ => Undefined,
         }
@@ -1,19 +1,49 @@
 //! DHCP options module.
 
+mod bulk_leasequery;
+mod classless_routes;
+mod client_fqdn;
 mod message_type;
 mod option_tag;
 mod overload;
+mod relay_agent_information;
 
-pub use self::{message_type::MessageType, option_tag::OptionTag, overload::Overload};
+pub use self::{
+    bulk_leasequery::{
+        StatusCode, DHCP_STATE_ABANDONED, DHCP_STATE_ACTIVE, DHCP_STATE_AVAILABLE,
+        DHCP_STATE_EXPIRED, DHCP_STATE_RELEASED, DHCP_STATE_REMOTE, DHCP_STATE_RESET,
+        DHCP_STATE_TRANSITIONING, STATUS_MALFORMED_QUERY, STATUS_NOT_ALLOWED,
+        STATUS_QUERY_TERMINATED, STATUS_SUCCESS, STATUS_UNSPEC_FAIL,
+    },
+    classless_routes::{
+        decode_classless_routes, encode_classless_routes, MICROSOFT_CLASSLESS_STATIC_ROUTES,
+    },
+    client_fqdn::{ClientFqdn, FQDN_FLAG_E, FQDN_FLAG_N, FQDN_FLAG_O, FQDN_FLAG_S},
+    message_type::MessageType,
+    option_tag::OptionTag,
+    overload::Overload,
+    relay_agent_information::{
+        RelayAgentInformation, SUBOPTION_CIRCUIT_ID, SUBOPTION_LINK_SELECTION,
+        SUBOPTION_REMOTE_ID,
+    },
+};
 
 use std::net::Ipv4Addr;
 
+use super::auth::Authentication;
+
 /// DHCP options.
 ///
 /// Implemented completely with `Option` for better flexibility and polymorphism.
 ///
 /// [RFC 2132](https://tools.ietf.org/html/rfc2132)
+/// [RFC 3004](https://tools.ietf.org/html/rfc3004)
+/// [RFC 3046](https://tools.ietf.org/html/rfc3046)
+/// [RFC 3118](https://tools.ietf.org/html/rfc3118)
 /// [RFC 3442](https://tools.ietf.org/html/rfc3442)
+/// [RFC 8520](https://tools.ietf.org/html/rfc8520)
+/// [RFC 8910](https://tools.ietf.org/html/rfc8910)
+/// [RFC 8925](https://tools.ietf.org/html/rfc8925)
 #[derive(Default)]
 pub struct Options {
     /*
@@ -112,8 +142,91 @@ pub struct Options {
     pub street_talk_servers: Option<Vec<Ipv4Addr>>,
     pub stda_servers: Option<Vec<Ipv4Addr>>,
 
+    /*
+    RFC 3004 (The User Class Option for DHCP)
+    */
+    /// Kept as is, since some clients do not use the RFC 3004 instance encoding.
+    pub user_class: Option<Vec<u8>>,
+
+    /*
+    RFC 4702 (The DHCP Client FQDN Option)
+    */
+    pub client_fqdn: Option<ClientFqdn>,
+
+    /*
+    RFC 3046 (DHCP Relay Agent Information Option)
+    */
+    pub relay_agent_information: Option<RelayAgentInformation>,
+
+    /*
+    RFC 3118 (Authentication for DHCP Messages)
+    */
+    pub authentication: Option<Authentication>,
+
+    /*
+    RFC 4388 (Dynamic Host Configuration Protocol (DHCP) Leasequery)
+    */
+    pub client_last_transaction_time: Option<u32>,
+    pub associated_ip: Option<Vec<Ipv4Addr>>,
+
+    /*
+    RFC 4578 (DHCP Options for the Intel Preboot eXecution Environment)
+    */
+    pub client_architecture: Option<Vec<u16>>,
+    /// The type octet followed by the identifier, usually a 16-octet UUID.
+    pub client_machine_id: Option<Vec<u8>>,
+
+    /*
+    RFC 8925 (IPv6-Only Preferred Option for DHCPv4)
+    */
+    pub ipv6_only_preferred: Option<u32>,
+
+    /*
+    RFC 8910 (Captive-Portal Identification in DHCP and Router Advertisements)
+    */
+    pub captive_portal: Option<String>,
+
+    /*
+    RFC 3011 (The IPv4 Subnet Selection Option for DHCP)
+    */
+    /// The subnet the client wants an address from, echoed back by the server.
+    pub subnet_selection: Option<Ipv4Addr>,
+
     /*
     RFC 3442 (The Classless Static Route Option)
     */
     pub classless_static_routes: Option<Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>>,
+
+    /*
+    RFC 6704 (Forcerenew Nonce Authentication)
+    */
+    /// The nonce algorithms the client supports, like `ALGORITHM_HMAC_MD5`.
+    pub forcerenew_nonce_capable: Option<Vec<u8>>,
+
+    /*
+    RFC 6926 (DHCPv4 Bulk Leasequery)
+    */
+    pub status_code: Option<StatusCode>,
+    /// The server time the other times in the message are relative to.
+    pub base_time: Option<u32>,
+    pub start_time_of_state: Option<u32>,
+    pub query_start_time: Option<u32>,
+    pub query_end_time: Option<u32>,
+    pub dhcp_state: Option<u8>,
+    pub data_source: Option<u8>,
+
+    /*
+    RFC 8520 (Manufacturer Usage Description Specification)
+    */
+    pub mud_url: Option<String>,
+
+    /*
+    Web Proxy Auto-Discovery (private use, draft-ietf-wrec-wpad-01)
+    */
+    pub web_proxy_auto_discovery: Option<String>,
+
+    /// The options without a typed field as `(tag, data)` pairs.
+    ///
+    /// Only serialized, since the deserializer skips the unknown options.
+    pub raw: Vec<(u8, Vec<u8>)>,
 }
@@ -102,11 +102,84 @@ pub enum OptionTag {
     StreetTalkServers,
     StdaServers,
 
+    /*
+    RFC 3004 (The User Class Option for DHCP)
+    */
+    UserClass = 77,
+
+    /*
+    RFC 4702 (The DHCP Client FQDN Option)
+    */
+    ClientFqdn = 81,
+
+    /*
+    RFC 3046 (DHCP Relay Agent Information Option)
+    */
+    RelayAgentInformation = 82,
+
+    /*
+    RFC 3118 (Authentication for DHCP Messages)
+    */
+    Authentication = 90,
+
+    /*
+    RFC 4388 (Dynamic Host Configuration Protocol (DHCP) Leasequery)
+    */
+    ClientLastTransactionTime = 91,
+    AssociatedIp = 92,
+
+    /*
+    RFC 4578 (DHCP Options for the Intel Preboot eXecution Environment)
+    */
+    ClientArchitecture = 93,
+    ClientMachineId = 97,
+
+    /*
+    RFC 8925 (IPv6-Only Preferred Option for DHCPv4)
+    */
+    Ipv6OnlyPreferred = 108,
+
+    /*
+    RFC 8910 (Captive-Portal Identification in DHCP and Router Advertisements)
+    */
+    CaptivePortal = 114,
+
+    /*
+    RFC 3011 (The IPv4 Subnet Selection Option for DHCP)
+    */
+    SubnetSelection = 118,
+
     /*
     RFC 3442 (The Classless Static Route Option)
     */
     ClasslessStaticRoutes = 121,
 
+    /*
+    RFC 6704 (Forcerenew Nonce Authentication)
+    */
+    ForcerenewNonceCapable = 145,
+
+    /*
+    RFC 6926 (DHCPv4 Bulk Leasequery)
+    */
+    StatusCode = 151,
+    BaseTime = 152,
+    StartTimeOfState = 153,
+    QueryStartTime = 154,
+    QueryEndTime = 155,
+    DhcpState = 156,
+    DataSource = 157,
+
+    /*
+    RFC 8520 (Manufacturer Usage Description Specification)
+    */
+    MudUrl = 161,
+
+    /*
+    Web Proxy Auto-Discovery (private use, draft-ietf-wrec-wpad-01)
+    */
+    WebProxyAutoDiscovery = 252,
+
     End = 255,
 }
 
@@ -192,8 +265,42 @@ impl From<u8> for OptionTag {
             75 => StreetTalkServers,
             76 => StdaServers,
 
+            77 => UserClass,
+
+            81 => ClientFqdn,
+
+            82 => RelayAgentInformation,
+
+            90 => Authentication,
+
+            91 => ClientLastTransactionTime,
+            92 => AssociatedIp,
+
+            93 => ClientArchitecture,
+            97 => ClientMachineId,
+
+            108 => Ipv6OnlyPreferred,
+
+            114 => CaptivePortal,
+
+            118 => SubnetSelection,
+
             121 => ClasslessStaticRoutes,
 
+            145 => ForcerenewNonceCapable,
+
+            151 => StatusCode,
+            152 => BaseTime,
+            153 => StartTimeOfState,
+            154 => QueryStartTime,
+            155 => QueryEndTime,
+            156 => DhcpState,
+            157 => DataSource,
+
+            161 => MudUrl,
+
+            252 => WebProxyAutoDiscovery,
+
             255 => End,
             _ => Unknown,
         }
@@ -0,0 +1,83 @@
+//! DHCP relay agent information option module.
+
+use std::net::Ipv4Addr;
+
+/// The `Agent Circuit ID` sub-option code (RFC 3046 §3.1).
+pub const SUBOPTION_CIRCUIT_ID: u8 = 1;
+/// The `Agent Remote ID` sub-option code (RFC 3046 §3.2).
+pub const SUBOPTION_REMOTE_ID: u8 = 2;
+/// The `Link Selection` sub-option code (RFC 3527 §3).
+pub const SUBOPTION_LINK_SELECTION: u8 = 5;
+
+/// The `Relay Agent Information` option value (RFC 3046 §2.0).
+///
+/// The sub-options are opaque to the server and must be echoed back unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayAgentInformation {
+    /// Usually identifies the relay agent port the request came from.
+    pub circuit_id: Option<Vec<u8>>,
+    /// Usually identifies the remote host end of the circuit.
+    pub remote_id: Option<Vec<u8>>,
+    /// The other sub-options as `(code, data)` pairs in the order of appearance.
+    pub other: Vec<(u8, Vec<u8>)>,
+}
+
+impl RelayAgentInformation {
+    /// Parses the option data consisting of the sub-options.
+    ///
+    /// Returns `None` if a sub-option exceeds the option data.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let mut information = RelayAgentInformation::default();
+        let mut i = 0;
+        while i < data.len() {
+            let code = data[i];
+            let len = *data.get(i + 1)? as usize;
+            let value = data.get(i + 2..i + 2 + len)?.to_vec();
+            match code {
+                SUBOPTION_CIRCUIT_ID => information.circuit_id = Some(value),
+                SUBOPTION_REMOTE_ID => information.remote_id = Some(value),
+                code => information.other.push((code, value)),
+            }
+            i += 2 + len;
+        }
+        Some(information)
+    }
+
+    /// The address of the client link, which may differ from `giaddr` (RFC 3527 §3).
+    ///
+    /// Returns `None` if the sub-option is absent or malformed.
+    pub fn link_selection(&self) -> Option<Ipv4Addr> {
+        self.other
+            .iter()
+            .find(|&&(code, _)| code == SUBOPTION_LINK_SELECTION)
+            .and_then(|&(_, ref value)| {
+                if value.len() == 4 {
+                    Some(Ipv4Addr::new(value[0], value[1], value[2], value[3]))
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Encodes the sub-options into the option data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        let known = [
+            (SUBOPTION_CIRCUIT_ID, &self.circuit_id),
+            (SUBOPTION_REMOTE_ID, &self.remote_id),
+        ];
+        for &(code, value) in known.iter() {
+            if let Some(ref value) = value {
+                data.push(code);
+                data.push(value.len() as u8);
+                data.extend_from_slice(value);
+            }
+        }
+        for &(code, ref value) in self.other.iter() {
+            data.push(code);
+            data.push(value.len() as u8);
+            data.extend_from_slice(value);
+        }
+        data
+    }
+}
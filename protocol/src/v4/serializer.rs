@@ -5,6 +5,7 @@ use std::{io, mem, net::Ipv4Addr};
 use bytes::{Buf, BufMut};
 
 use super::{
+    auth::{Authentication, SIZE_AUTHENTICATION_FIXED},
     constants::*,
     options::{OptionTag, Overload as OverloadEnum},
     Message,
@@ -43,6 +44,12 @@ const CURSOR_INDEX_MAIN: usize = 2;
 /// The cursors array size.
 const CURSOR_INDEX_TOTAL: usize = 3;
 
+/// The `client_hardware_address` field offset in bytes.
+const OFFSET_CLIENT_HARDWARE_ADDRESS: usize = OFFSET_SERVER_NAME - SIZE_HARDWARE_ADDRESS;
+
+/// The padding source, as long as the longest padded field.
+const ZEROS: [u8; SIZE_BOOT_FILENAME] = [0u8; SIZE_BOOT_FILENAME];
+
 impl Message {
     /// DHCP message serialization.
     ///
@@ -57,6 +64,39 @@ impl Message {
     /// # Errors
     /// `io::Error` if the buffer is too small.
     pub fn to_bytes(&self, dst: &mut [u8], max_size: Option<u16>) -> io::Result<usize> {
+        self.encode(dst, max_size, None)
+    }
+
+    /// Serializes a reply by patching a copy of the fixed header of the `request` it answers,
+    /// i.e. its first `OFFSET_SERVER_NAME` bytes, instead of encoding every field.
+    ///
+    /// Only the operation code, the hops, the seconds and the addresses assigned by the server
+    /// are patched, so the other fields of the header must be equal to the reply ones,
+    /// like in a `DHCPACK` to a renewing client. Otherwise the reply is serialized
+    /// with `to_bytes`, so the output is the same anyway.
+    ///
+    /// # Errors
+    /// `io::Error` if the buffer is too small.
+    pub fn reply_to_bytes(
+        &self,
+        request: &[u8],
+        dst: &mut [u8],
+        max_size: Option<u16>,
+    ) -> io::Result<usize> {
+        if self.keeps_header(request) {
+            self.encode(dst, max_size, Some(request))
+        } else {
+            self.encode(dst, max_size, None)
+        }
+    }
+
+    /// Serializes the message patching the `request` header if it is set.
+    fn encode(
+        &self,
+        dst: &mut [u8],
+        max_size: Option<u16>,
+        request: Option<&[u8]>,
+    ) -> io::Result<usize> {
         use OptionTag::*;
 
         // the slice is truncated to the maximal client message size
@@ -78,30 +118,14 @@ impl Message {
         ];
 
         check_remaining!(cursors[CURSOR_INDEX_MAIN], OFFSET_OPTIONS);
-        cursors[CURSOR_INDEX_MAIN].put_u8(self.operation_code as u8);
-        cursors[CURSOR_INDEX_MAIN].put_u8(self.hardware_type as u8);
-        cursors[CURSOR_INDEX_MAIN].put_u8(self.hardware_address_length);
-        cursors[CURSOR_INDEX_MAIN].put_u8(self.hardware_options);
-        cursors[CURSOR_INDEX_MAIN].put_u32_be(self.transaction_id);
-        cursors[CURSOR_INDEX_MAIN].put_u16_be(self.seconds);
-        // https://tools.ietf.org/html/rfc2131#section-2
-        // https://tools.ietf.org/html/rfc1700#page-3
-        // Leftmost bit (0 bit) is most significant
-        cursors[CURSOR_INDEX_MAIN].put_u16_be(if self.is_broadcast { 0x8000 } else { 0x0000 });
-        cursors[CURSOR_INDEX_MAIN].put_u32_be(u32::from(self.client_ip_address));
-        cursors[CURSOR_INDEX_MAIN].put_u32_be(u32::from(self.your_ip_address));
-        cursors[CURSOR_INDEX_MAIN].put_u32_be(u32::from(self.server_ip_address));
-        cursors[CURSOR_INDEX_MAIN].put_u32_be(u32::from(self.gateway_ip_address));
-        cursors[CURSOR_INDEX_MAIN].put(self.client_hardware_address.as_bytes()); // 6 byte MAC-48
-        cursors[CURSOR_INDEX_MAIN].put(vec![
-            0u8;
-            SIZE_HARDWARE_ADDRESS
-                - self.client_hardware_address.as_bytes().len()
-        ]); // 10 byte padding
+        match request {
+            Some(request) => self.patch_header(&mut cursors[CURSOR_INDEX_MAIN], request),
+            None => self.put_header(&mut cursors[CURSOR_INDEX_MAIN]),
+        }
         cursors[CURSOR_INDEX_MAIN].put(&self.server_name);
-        cursors[CURSOR_INDEX_MAIN].put(vec![0u8; SIZE_SERVER_NAME - self.server_name.len()]); // (64 - length) byte padding
+        cursors[CURSOR_INDEX_MAIN].put(&ZEROS[..SIZE_SERVER_NAME - self.server_name.len()]); // (64 - length) byte padding
         cursors[CURSOR_INDEX_MAIN].put(&self.boot_filename);
-        cursors[CURSOR_INDEX_MAIN].put(vec![0u8; SIZE_BOOT_FILENAME - self.boot_filename.len()]); // (128 - length) byte padding
+        cursors[CURSOR_INDEX_MAIN].put(&ZEROS[..SIZE_BOOT_FILENAME - self.boot_filename.len()]); // (128 - length) byte padding
         cursors[CURSOR_INDEX_MAIN].put_u32_be(MAGIC_COOKIE);
 
         // the most important and required options are encoded first
@@ -199,6 +223,40 @@ impl Message {
             RebindingTime,
             &self.options.rebinding_time,
         )?;
+        Self::put_opt_u32(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            Ipv6OnlyPreferred,
+            &self.options.ipv6_only_preferred,
+        )?;
+        Self::put_opt_ipv4(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            SubnetSelection,
+            &self.options.subnet_selection,
+        )?;
+        Self::put_opt_authentication(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            &self.options.authentication,
+        )?;
+        Self::put_opt_u32(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            ClientLastTransactionTime,
+            &self.options.client_last_transaction_time,
+        )?;
+        Self::put_opt_vec_ipv4(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            AssociatedIp,
+            &self.options.associated_ip,
+        )?;
+        Self::put_opt_vec_u16(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            ClientArchitecture,
+            &self.options.client_architecture,
+        )?;
+        Self::put_opt_vec(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            ClientMachineId,
+            &self.options.client_machine_id,
+        )?;
         Self::put_opt_string(
             &mut cursors[CURSOR_INDEX_MAIN],
             Hostname,
@@ -209,7 +267,20 @@ impl Message {
             DhcpMessage,
             &self.options.dhcp_message,
         )?;
-
+        Self::put_opt_string(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            CaptivePortal,
+            &self.options.captive_portal,
+        )?;
+        Self::put_opt_string(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            WebProxyAutoDiscovery,
+            &self.options.web_proxy_auto_discovery,
+        )?;
+        Self::put_opt_string(&mut cursors[CURSOR_INDEX_MAIN], MudUrl, &self.options.mud_url)?;
+        for &(tag, ref data) in self.options.raw.iter() {
+            Self::put_opt_raw(&mut cursors[CURSOR_INDEX_MAIN], tag, data)?;
+        }
         // unimplemented options are encoded next
         Self::put_opt_u32(
             &mut cursors[CURSOR_INDEX_MAIN],
@@ -436,6 +507,48 @@ impl Message {
             ClassId,
             &self.options.class_id,
         )?;
+        Self::put_opt_vec(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            UserClass,
+            &self.options.user_class,
+        )?;
+        Self::put_opt_vec(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            ForcerenewNonceCapable,
+            &self.options.forcerenew_nonce_capable,
+        )?;
+        if let Some(ref value) = self.options.client_fqdn {
+            Self::put_opt_raw(
+                &mut cursors[CURSOR_INDEX_MAIN],
+                ClientFqdn as u8,
+                &value.to_bytes(),
+            )?;
+        }
+        if let Some(ref value) = self.options.status_code {
+            Self::put_opt_raw(
+                &mut cursors[CURSOR_INDEX_MAIN],
+                StatusCode as u8,
+                &value.to_bytes(),
+            )?;
+        }
+        Self::put_opt_u32(&mut cursors[CURSOR_INDEX_MAIN], BaseTime, &self.options.base_time)?;
+        Self::put_opt_u32(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            StartTimeOfState,
+            &self.options.start_time_of_state,
+        )?;
+        Self::put_opt_u32(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            QueryStartTime,
+            &self.options.query_start_time,
+        )?;
+        Self::put_opt_u32(
+            &mut cursors[CURSOR_INDEX_MAIN],
+            QueryEndTime,
+            &self.options.query_end_time,
+        )?;
+        Self::put_opt_u8(&mut cursors[CURSOR_INDEX_MAIN], DhcpState, &self.options.dhcp_state)?;
+        Self::put_opt_u8(&mut cursors[CURSOR_INDEX_MAIN], DataSource, &self.options.data_source)?;
         Self::put_opt_vec(
             &mut cursors[CURSOR_INDEX_MAIN],
             NetwareIpDomain,
@@ -512,6 +625,15 @@ impl Message {
             &self.options.stda_servers,
         )?;
 
+        // the relay agent information is expected to be the last option (RFC 3046 §2.1)
+        if let Some(ref value) = self.options.relay_agent_information {
+            Self::put_opt_raw(
+                &mut cursors[CURSOR_INDEX_MAIN],
+                RelayAgentInformation as u8,
+                &value.to_bytes(),
+            )?;
+        }
+
         check_remaining!(cursors[CURSOR_INDEX_MAIN], mem::size_of::<u8>());
         cursors[CURSOR_INDEX_MAIN].put_u8(End as u8);
         if cursors[CURSOR_INDEX_FILE].position() > 0 {
@@ -520,9 +642,87 @@ impl Message {
         if cursors[CURSOR_INDEX_SNAME].position() > 0 {
             cursors[CURSOR_INDEX_SNAME].put_u8(End as u8);
         }
+        let size = cursors[CURSOR_INDEX_MAIN].position() as usize;
+        if size < SIZE_MESSAGE_BOOTP {
+            check_remaining!(cursors[CURSOR_INDEX_MAIN], SIZE_MESSAGE_BOOTP - size);
+            cursors[CURSOR_INDEX_MAIN].put(&ZEROS[..SIZE_MESSAGE_BOOTP - size]); // the pad options
+        }
         Ok(cursors[CURSOR_INDEX_MAIN].position() as usize)
     }
 
+    /// Encodes the header fields before `server_name`.
+    fn put_header(&self, cursor: &mut io::Cursor<&mut [u8]>) {
+        cursor.put_u8(self.operation_code as u8);
+        cursor.put_u8(self.hardware_type as u8);
+        cursor.put_u8(self.hardware_address_length);
+        cursor.put_u8(self.hardware_options);
+        cursor.put_u32_be(self.transaction_id);
+        cursor.put_u16_be(self.seconds);
+        // https://tools.ietf.org/html/rfc2131#section-2
+        // https://tools.ietf.org/html/rfc1700#page-3
+        // Leftmost bit (0 bit) is most significant
+        cursor.put_u16_be(self.flags());
+        cursor.put_u32_be(u32::from(self.client_ip_address));
+        cursor.put_u32_be(u32::from(self.your_ip_address));
+        cursor.put_u32_be(u32::from(self.server_ip_address));
+        cursor.put_u32_be(u32::from(self.gateway_ip_address));
+        let mac = self.client_hardware_address.as_bytes();
+        cursor.put(mac); // 6 byte MAC-48
+        cursor.put(&ZEROS[..SIZE_HARDWARE_ADDRESS - mac.len()]); // 10 byte padding
+    }
+
+    /// Copies the header fields before `server_name` from the request
+    /// and patches the ones set by the server.
+    fn patch_header(&self, cursor: &mut io::Cursor<&mut [u8]>, request: &[u8]) {
+        let start = cursor.position() as usize;
+        cursor.put(&request[..OFFSET_SERVER_NAME]);
+        let header = &mut cursor.get_mut()[start..start + OFFSET_SERVER_NAME];
+        header[0] = self.operation_code as u8;
+        header[3] = self.hardware_options;
+        header[8] = (self.seconds >> 8) as u8;
+        header[9] = self.seconds as u8;
+        header[16..20].copy_from_slice(&self.your_ip_address.octets());
+        header[20..24].copy_from_slice(&self.server_ip_address.octets());
+    }
+
+    /// Checks whether the request header fields which are not patched are equal to
+    /// the ones `put_header` would encode.
+    fn keeps_header(&self, request: &[u8]) -> bool {
+        if request.len() < OFFSET_SERVER_NAME {
+            return false;
+        }
+        let mut header = io::Cursor::new(&request[..OFFSET_CLIENT_HARDWARE_ADDRESS]);
+        header.advance(mem::size_of::<u8>()); // the operation code is patched
+        let hardware_type = header.get_u8();
+        let hardware_address_length = header.get_u8();
+        header.advance(mem::size_of::<u8>()); // the hops are patched
+        let transaction_id = header.get_u32_be();
+        header.advance(mem::size_of::<u16>()); // the seconds are patched
+        let flags = header.get_u16_be();
+        let client_ip_address = Ipv4Addr::from(header.get_u32_be());
+        header.advance(mem::size_of::<u32>() * 2); // the assigned addresses are patched
+        let gateway_ip_address = Ipv4Addr::from(header.get_u32_be());
+
+        let client_hardware_address = &request[OFFSET_CLIENT_HARDWARE_ADDRESS..OFFSET_SERVER_NAME];
+        let mac = self.client_hardware_address.as_bytes();
+        hardware_type == self.hardware_type as u8
+            && hardware_address_length == self.hardware_address_length
+            && transaction_id == self.transaction_id
+            && flags == self.flags()
+            && client_ip_address == self.client_ip_address
+            && gateway_ip_address == self.gateway_ip_address
+            && client_hardware_address.starts_with(mac)
+            && client_hardware_address[mac.len()..].iter().all(|&octet| octet == 0)
+    }
+
+    fn flags(&self) -> u16 {
+        if self.is_broadcast {
+            FLAG_BROADCAST
+        } else {
+            0x0000
+        }
+    }
+
     /// Cannot be splitted.
     fn put_opt_u8(
         cursor: &mut io::Cursor<&mut [u8]>,
@@ -607,6 +807,31 @@ impl Message {
     }
 
     /// Can be splitted.
+    /// Is always put into the main options area, so the MAC can be found by receivers.
+    fn put_opt_authentication(
+        cursor: &mut io::Cursor<&mut [u8]>,
+        value: &Option<Authentication>,
+    ) -> io::Result<()> {
+        if let Some(ref value) = value {
+            let size = SIZE_AUTHENTICATION_FIXED + value.information.len();
+            if size > u8::max_value() as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Authentication information is too long",
+                ));
+            }
+            check_remaining!(cursor, SIZE_OPTION_AFFIXES + size);
+            cursor.put_u8(OptionTag::Authentication as u8);
+            cursor.put_u8(size as u8);
+            cursor.put_u8(value.protocol);
+            cursor.put_u8(value.algorithm);
+            cursor.put_u8(value.replay_detection_method);
+            cursor.put_u64_be(value.replay_detection);
+            cursor.put(&value.information);
+        }
+        Ok(())
+    }
+
     fn put_opt_vec(
         cursor: &mut io::Cursor<&mut [u8]>,
         tag: OptionTag,
@@ -625,6 +850,21 @@ impl Message {
         Ok(())
     }
 
+    /// Cannot be splitted.
+    fn put_opt_raw(cursor: &mut io::Cursor<&mut [u8]>, tag: u8, data: &[u8]) -> io::Result<()> {
+        if data.len() > SIZE_OPTION_MAX {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The option {} is too long: {} bytes", tag, data.len()),
+            ));
+        }
+        check_remaining!(cursor, SIZE_OPTION_AFFIXES + data.len());
+        cursor.put_u8(tag);
+        cursor.put_u8(data.len() as u8);
+        cursor.put(data);
+        Ok(())
+    }
+
     /// Can be splitted.
     fn put_opt_vec_u16(
         cursor: &mut io::Cursor<&mut [u8]>,
@@ -1,14 +1,27 @@
 //! DHCP message validation module.
 
-use super::{constants::SIZE_MESSAGE_MINIMAL, options::MessageType, Message};
+use std::{error, fmt};
+
+use super::{
+    constants::SIZE_MESSAGE_MINIMAL, operation_code::OperationCode, options::MessageType, Message,
+};
 
 /// The error type returned by `Message::validate`.
-#[derive(Fail, Debug)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "Validation error: {}", _0)]
     Validation(&'static str),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Validation(reason) => write!(f, "Validation error: {}", reason),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
 /// Checks if required options are present for each message type.
 macro_rules! must_set_option (
     ($name:expr) => (
@@ -19,6 +32,14 @@ macro_rules! must_set_option (
 );
 
 impl Message {
+    /// Checks whether the message is a plain BOOTP request, which has no DHCP message type.
+    pub fn is_bootp_request(&self) -> bool {
+        match (self.operation_code, self.options.dhcp_message_type) {
+            (OperationCode::BootRequest, None) => true,
+            _ => false,
+        }
+    }
+
     /// DHCP message validation.
     ///
     /// Returns the DHCP message type on successful validation.
@@ -57,6 +78,12 @@ impl Message {
                 must_set_option!(message.options.address_request);
                 must_set_option!(message.options.dhcp_server_id);
             }
+            // the responses are sent to the requester in 'giaddr' (RFC 4388 §6.1)
+            MessageType::DhcpLeaseQuery => if message.gateway_ip_address.is_unspecified() {
+                return Err(Error::Validation("The leasequery requester address is absent"));
+            },
+            // the bulk leasequery connection itself identifies the requester (RFC 6926 §7.2)
+            MessageType::DhcpBulkLeaseQuery => {}
 
             // server generated packets section
             MessageType::DhcpOffer => {
@@ -70,6 +97,17 @@ impl Message {
             MessageType::DhcpNak => {
                 must_set_option!(message.options.dhcp_server_id);
             }
+            MessageType::DhcpForceRenew => {
+                must_set_option!(message.options.dhcp_server_id);
+            }
+            MessageType::DhcpLeaseActive => {
+                must_set_option!(message.options.address_time);
+                must_set_option!(message.options.dhcp_server_id);
+            }
+            MessageType::DhcpLeaseUnassigned | MessageType::DhcpLeaseUnknown => {
+                must_set_option!(message.options.dhcp_server_id);
+            }
+            MessageType::DhcpLeaseQueryDone => {}
 
             _ => return Err(Error::Validation("Unknown DHCP message type")),
         }
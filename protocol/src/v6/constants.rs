@@ -0,0 +1,31 @@
+//! DHCPv6 message constants.
+
+use std::net::Ipv6Addr;
+
+/// The UDP port the clients listen on (RFC 8415 §7.2).
+pub const DHCP6_PORT_CLIENT: u16 = 546;
+
+/// The UDP port the servers and relay agents listen on (RFC 8415 §7.2).
+pub const DHCP6_PORT_SERVER: u16 = 547;
+
+/// The link-scoped multicast address used by clients to reach the servers (RFC 8415 §7.1).
+pub const ALL_DHCP_RELAY_AGENTS_AND_SERVERS: Ipv6Addr =
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 1, 2);
+
+/// The message type and the transaction ID size in bytes.
+pub const SIZE_HEADER: usize = 4;
+
+/// The option code and the option length size in bytes.
+pub const SIZE_OPTION_HEADER: usize = 4;
+
+/// The transaction ID is only 24 bits long.
+pub const TRANSACTION_ID_MASK: u32 = 0x00ff_ffff;
+
+/// The preference value which makes the client stop collecting advertisements.
+pub const PREFERENCE_MAXIMAL: u8 = 255;
+
+/// The lifetime value meaning infinity (RFC 8415 §7.7).
+pub const INFINITY: u32 = 0xffff_ffff;
+
+/// The unicast flag of the `DHCPV4-QUERY` flags field (RFC 7341 §6).
+pub const FLAG_DHCP4O6_UNICAST: u32 = 0x80_0000;
@@ -0,0 +1,458 @@
+//! DHCPv6 message deserialization module.
+
+use std::{
+    io::{self, Cursor},
+    net::Ipv6Addr,
+};
+
+use bytes::Buf;
+
+use super::{
+    constants::*,
+    options::{IaAddress, IaNa, IaPd, IaPrefix, OptionCode, Options, StatusCode},
+    Message,
+};
+
+/// Checks if there is enough space in buffer to get a value.
+macro_rules! check_remaining(
+    ($cursor:expr, $length:expr) => (
+        if $cursor.remaining() < $length {
+            return Err(
+                io::Error::new(io::ErrorKind::UnexpectedEof,
+                "Buffer is too small or packet has invalid length octets",
+            ));
+        }
+    );
+);
+
+/// Checks if the length octets contain correct length for the type.
+macro_rules! check_length(
+    ($len:expr, $correct:expr) => (
+        if $len != $correct {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Length octets are invalid"));
+        }
+    );
+);
+
+/// Checks if the vector size in bytes is divisible by the length of its element.
+macro_rules! check_divisibility(
+    ($len:expr, $divider:expr) => (
+        if $len % $divider != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Divisibility check failed"));
+        }
+    );
+);
+
+/// The IPv6 address size in bytes.
+const SIZE_IPV6_ADDRESS: usize = 16;
+/// The IAID, T1 and T2 fields of the `IA_NA` option.
+const SIZE_IA_NA_FIXED: usize = 12;
+/// The address and the lifetimes of the `IA Address` option.
+const SIZE_IA_ADDRESS_FIXED: usize = SIZE_IPV6_ADDRESS + 8;
+/// The IAID, T1 and T2 fields of the `IA_PD` option.
+const SIZE_IA_PD_FIXED: usize = 12;
+/// The lifetimes, the prefix length and the prefix of the `IA Prefix` option.
+const SIZE_IA_PREFIX_FIXED: usize = 9 + SIZE_IPV6_ADDRESS;
+
+impl Message {
+    /// DHCPv6 message deserialization.
+    ///
+    /// # Errors
+    /// `io::Error` if the packet is abrupted, too small or contains invalid length octets.
+    pub fn from_bytes(src: &[u8]) -> io::Result<Self> {
+        let mut cursor = Cursor::new(src);
+        check_remaining!(cursor, SIZE_HEADER);
+
+        let header = cursor.get_u32_be();
+        let mut message = Message {
+            message_type: ((header >> 24) as u8).into(),
+            transaction_id: header & TRANSACTION_ID_MASK,
+            options: Options::default(),
+        };
+
+        Self::append_options(&src[SIZE_HEADER..], &mut message.options)?;
+        Ok(message)
+    }
+
+    /// Parses the top-level options.
+    fn append_options(src: &[u8], options: &mut Options) -> io::Result<()> {
+        for (code, data) in Self::split_options(src)? {
+            match code {
+                OptionCode::ClientId => options.client_id = Some(data.to_vec()),
+                OptionCode::ServerId => options.server_id = Some(data.to_vec()),
+                OptionCode::IaNa => options.ia_na.push(Self::get_ia_na(data)?),
+                OptionCode::OptionRequest => {
+                    check_divisibility!(data.len(), 2);
+                    let mut cursor = Cursor::new(data);
+                    let mut codes = Vec::with_capacity(data.len() / 2);
+                    while cursor.has_remaining() {
+                        codes.push(cursor.get_u16_be());
+                    }
+                    options.option_request = Some(codes);
+                }
+                OptionCode::Preference => {
+                    check_length!(data.len(), 1);
+                    options.preference = Some(data[0]);
+                }
+                OptionCode::ElapsedTime => {
+                    check_length!(data.len(), 2);
+                    options.elapsed_time = Some(Cursor::new(data).get_u16_be());
+                }
+                OptionCode::StatusCode => options.status_code = Some(Self::get_status_code(data)?),
+                OptionCode::RapidCommit => options.rapid_commit = true,
+                OptionCode::ServerUnicast => options.server_unicast = Some(Self::get_ipv6(data)?),
+                OptionCode::DnsServers => {
+                    check_divisibility!(data.len(), SIZE_IPV6_ADDRESS);
+                    let mut addresses = Vec::with_capacity(data.len() / SIZE_IPV6_ADDRESS);
+                    for chunk in data.chunks(SIZE_IPV6_ADDRESS) {
+                        addresses.push(Self::get_ipv6(chunk)?);
+                    }
+                    options.dns_servers = Some(addresses);
+                }
+                OptionCode::DomainList => options.domain_list = Some(Self::get_domain_list(data)?),
+                OptionCode::IaPd => options.ia_pd.push(Self::get_ia_pd(data)?),
+                OptionCode::Dhcpv4Message => options.dhcpv4_message = Some(data.to_vec()),
+                OptionCode::Dhcp4o6Servers => {
+                    check_divisibility!(data.len(), SIZE_IPV6_ADDRESS);
+                    let mut addresses = Vec::with_capacity(data.len() / SIZE_IPV6_ADDRESS);
+                    for chunk in data.chunks(SIZE_IPV6_ADDRESS) {
+                        addresses.push(Self::get_ipv6(chunk)?);
+                    }
+                    options.dhcp4o6_servers = Some(addresses);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits the options area into the option codes and values.
+    fn split_options(src: &[u8]) -> io::Result<Vec<(OptionCode, &[u8])>> {
+        let mut cursor = Cursor::new(src);
+        let mut result = Vec::new();
+        while cursor.has_remaining() {
+            check_remaining!(cursor, SIZE_OPTION_HEADER);
+            let code = OptionCode::from(cursor.get_u16_be());
+            let len = cursor.get_u16_be() as usize;
+            check_remaining!(cursor, len);
+            let start = cursor.position() as usize;
+            result.push((code, &src[start..start + len]));
+            cursor.advance(len);
+        }
+        Ok(result)
+    }
+
+    fn get_ipv6(data: &[u8]) -> io::Result<Ipv6Addr> {
+        check_length!(data.len(), SIZE_IPV6_ADDRESS);
+        let mut octets = [0u8; SIZE_IPV6_ADDRESS];
+        octets.copy_from_slice(data);
+        Ok(Ipv6Addr::from(octets))
+    }
+
+    fn get_status_code(data: &[u8]) -> io::Result<StatusCode> {
+        let mut cursor = Cursor::new(data);
+        check_remaining!(cursor, 2);
+        Ok(StatusCode {
+            code: cursor.get_u16_be(),
+            message: String::from_utf8_lossy(&data[2..]).into_owned(),
+        })
+    }
+
+    fn get_ia_na(data: &[u8]) -> io::Result<IaNa> {
+        let mut cursor = Cursor::new(data);
+        check_remaining!(cursor, SIZE_IA_NA_FIXED);
+        let mut ia_na = IaNa {
+            iaid: cursor.get_u32_be(),
+            renewal_time: cursor.get_u32_be(),
+            rebinding_time: cursor.get_u32_be(),
+            addresses: Vec::new(),
+            status_code: None,
+        };
+        for (code, data) in Self::split_options(&data[SIZE_IA_NA_FIXED..])? {
+            match code {
+                OptionCode::IaAddr => ia_na.addresses.push(Self::get_ia_address(data)?),
+                OptionCode::StatusCode => ia_na.status_code = Some(Self::get_status_code(data)?),
+                _ => {}
+            }
+        }
+        Ok(ia_na)
+    }
+
+    fn get_ia_address(data: &[u8]) -> io::Result<IaAddress> {
+        let mut cursor = Cursor::new(data);
+        check_remaining!(cursor, SIZE_IA_ADDRESS_FIXED);
+        let address = Self::get_ipv6(&data[..SIZE_IPV6_ADDRESS])?;
+        cursor.advance(SIZE_IPV6_ADDRESS);
+        let mut ia_address = IaAddress {
+            address,
+            preferred_lifetime: cursor.get_u32_be(),
+            valid_lifetime: cursor.get_u32_be(),
+            status_code: None,
+        };
+        for (code, data) in Self::split_options(&data[SIZE_IA_ADDRESS_FIXED..])? {
+            if let OptionCode::StatusCode = code {
+                ia_address.status_code = Some(Self::get_status_code(data)?);
+            }
+        }
+        Ok(ia_address)
+    }
+
+    fn get_ia_pd(data: &[u8]) -> io::Result<IaPd> {
+        let mut cursor = Cursor::new(data);
+        check_remaining!(cursor, SIZE_IA_PD_FIXED);
+        let mut ia_pd = IaPd {
+            iaid: cursor.get_u32_be(),
+            renewal_time: cursor.get_u32_be(),
+            rebinding_time: cursor.get_u32_be(),
+            prefixes: Vec::new(),
+            status_code: None,
+        };
+        for (code, data) in Self::split_options(&data[SIZE_IA_PD_FIXED..])? {
+            match code {
+                OptionCode::IaPrefix => ia_pd.prefixes.push(Self::get_ia_prefix(data)?),
+                OptionCode::StatusCode => ia_pd.status_code = Some(Self::get_status_code(data)?),
+                _ => {}
+            }
+        }
+        Ok(ia_pd)
+    }
+
+    fn get_ia_prefix(data: &[u8]) -> io::Result<IaPrefix> {
+        let mut cursor = Cursor::new(data);
+        check_remaining!(cursor, SIZE_IA_PREFIX_FIXED);
+        let preferred_lifetime = cursor.get_u32_be();
+        let valid_lifetime = cursor.get_u32_be();
+        let prefix_length = cursor.get_u8();
+        if prefix_length > 128 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid prefix length",
+            ));
+        }
+        let mut ia_prefix = IaPrefix {
+            preferred_lifetime,
+            valid_lifetime,
+            prefix_length,
+            prefix: Self::get_ipv6(&data[9..SIZE_IA_PREFIX_FIXED])?,
+            status_code: None,
+        };
+        for (code, data) in Self::split_options(&data[SIZE_IA_PREFIX_FIXED..])? {
+            if let OptionCode::StatusCode = code {
+                ia_prefix.status_code = Some(Self::get_status_code(data)?);
+            }
+        }
+        Ok(ia_prefix)
+    }
+
+    /// Decodes the uncompressed DNS wire format names (RFC 8415 §10).
+    fn get_domain_list(data: &[u8]) -> io::Result<Vec<String>> {
+        let mut cursor = Cursor::new(data);
+        let mut names = Vec::new();
+        let mut labels: Vec<String> = Vec::new();
+        while cursor.has_remaining() {
+            let len = cursor.get_u8() as usize;
+            if len == 0 {
+                names.push(labels.join("."));
+                labels.clear();
+                continue;
+            }
+            check_remaining!(cursor, len);
+            let start = cursor.position() as usize;
+            labels.push(String::from_utf8_lossy(&data[start..start + len]).into_owned());
+            cursor.advance(len);
+        }
+        if !labels.is_empty() {
+            // a partially qualified name
+            names.push(labels.join("."));
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::MessageType;
+
+    /// The `SOLICIT` header with the transaction ID 0x123456.
+    const HEADER: [u8; SIZE_HEADER] = [1, 0x12, 0x34, 0x56];
+
+    fn option(code: u16, data: &[u8]) -> Vec<u8> {
+        let mut option = vec![(code >> 8) as u8, code as u8];
+        option.push((data.len() >> 8) as u8);
+        option.push(data.len() as u8);
+        option.extend_from_slice(data);
+        option
+    }
+
+    fn message(options: &[u8]) -> Vec<u8> {
+        let mut message = HEADER.to_vec();
+        message.extend_from_slice(options);
+        message
+    }
+
+    fn ia_prefix(prefix_length: u8, prefix: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0x0e, 0x10, 0, 0, 0x1c, 0x20, prefix_length];
+        data.extend_from_slice(prefix);
+        option(OptionCode::IaPrefix as u16, &data)
+    }
+
+    fn ia_pd(prefixes: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0x07, 0x08, 0, 0, 0x0b, 0x40];
+        data.extend_from_slice(prefixes);
+        option(OptionCode::IaPd as u16, &data)
+    }
+
+    fn error_kind(data: &[u8]) -> Option<io::ErrorKind> {
+        Message::from_bytes(data).err().map(|error| error.kind())
+    }
+
+    #[test]
+    fn parses_header() {
+        let message = Message::from_bytes(&HEADER).unwrap();
+        assert_eq!(message.message_type, MessageType::Solicit);
+        assert_eq!(message.transaction_id, 0x12_3456);
+
+        for size in 0..SIZE_HEADER {
+            assert_eq!(
+                error_kind(&HEADER[..size]),
+                Some(io::ErrorKind::UnexpectedEof),
+                "{}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn parses_domain_lists() {
+        // the wire format and the names
+        let cases: &[(&[u8], &[&str])] = &[
+            (b"", &[]),
+            (b"\0", &[""]),
+            (b"\x07example\x03com\0", &["example.com"]),
+            (b"\x01a\0\x01b\x01c\0", &["a", "b.c"]),
+            (b"\x04host", &["host"]),
+            (b"\x01a\0\x04host", &["a", "host"]),
+        ];
+        for &(data, names) in cases {
+            let message =
+                Message::from_bytes(&message(&option(OptionCode::DomainList as u16, data)))
+                    .unwrap();
+            assert_eq!(
+                message.options.domain_list,
+                Some(names.iter().map(|name| name.to_string()).collect()),
+                "{:?}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_domain_lists() {
+        let cases: &[&[u8]] = &[b"\x07exampl", b"\x07example\x03co", b"\x01", b"\x01a\0\xff"];
+        for &data in cases {
+            assert_eq!(
+                error_kind(&message(&option(OptionCode::DomainList as u16, data))),
+                Some(io::ErrorKind::UnexpectedEof),
+                "{:?}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn checks_prefix_lengths() {
+        let prefix = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        // the prefix length and the error kind if it is rejected
+        let cases = [
+            (0, None),
+            (48, None),
+            (64, None),
+            (128, None),
+            (129, Some(io::ErrorKind::InvalidData)),
+            (255, Some(io::ErrorKind::InvalidData)),
+        ];
+        for &(prefix_length, kind) in cases.iter() {
+            let data = message(&ia_pd(&ia_prefix(prefix_length, &prefix)));
+            assert_eq!(error_kind(&data), kind, "{}", prefix_length);
+            if kind.is_none() {
+                let message = Message::from_bytes(&data).unwrap();
+                let ia_pd = &message.options.ia_pd[0];
+                assert_eq!(ia_pd.iaid, 1);
+                assert_eq!(ia_pd.prefixes[0].prefix_length, prefix_length);
+                assert_eq!(ia_pd.prefixes[0].prefix, Ipv6Addr::from(prefix));
+                assert_eq!(ia_pd.prefixes[0].valid_lifetime, 7200);
+            }
+        }
+
+        for size in 0..prefix.len() {
+            assert_eq!(
+                error_kind(&message(&ia_pd(&ia_prefix(64, &prefix[..size])))),
+                Some(io::ErrorKind::UnexpectedEof),
+                "{}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_option_lengths() {
+        // the options area and the error kind
+        let cases = [
+            (vec![0, 7], io::ErrorKind::UnexpectedEof),
+            (vec![0, 7, 0, 2, 255], io::ErrorKind::UnexpectedEof),
+            (
+                option(OptionCode::Preference as u16, &[1, 2]),
+                io::ErrorKind::InvalidData,
+            ),
+            (
+                option(OptionCode::ElapsedTime as u16, &[1]),
+                io::ErrorKind::InvalidData,
+            ),
+            (
+                option(OptionCode::OptionRequest as u16, &[0, 23, 0]),
+                io::ErrorKind::InvalidData,
+            ),
+            (
+                option(OptionCode::DnsServers as u16, &[0; 17]),
+                io::ErrorKind::InvalidData,
+            ),
+            (
+                option(OptionCode::StatusCode as u16, &[0]),
+                io::ErrorKind::UnexpectedEof,
+            ),
+            (
+                option(OptionCode::IaNa as u16, &[0; 11]),
+                io::ErrorKind::UnexpectedEof,
+            ),
+            (ia_pd(&[0, 26, 0, 1]), io::ErrorKind::UnexpectedEof),
+        ];
+        for &(ref options, kind) in cases.iter() {
+            assert_eq!(error_kind(&message(options)), Some(kind), "{:?}", options);
+        }
+    }
+
+    #[test]
+    fn parses_options() {
+        let mut options = option(OptionCode::ClientId as u16, &[0, 3, 0, 1, 1, 2, 3, 4, 5, 6]);
+        options.extend(option(OptionCode::OptionRequest as u16, &[0, 23, 0, 24]));
+        options.extend(option(OptionCode::Preference as u16, &[255]));
+        options.extend(option(OptionCode::ElapsedTime as u16, &[0x01, 0x00]));
+        options.extend(option(OptionCode::RapidCommit as u16, &[]));
+        options.extend(option(OptionCode::StatusCode as u16, b"\0\x02no addrs"));
+        options.extend(option(0xfff0, &[1, 2, 3]));
+
+        let message = Message::from_bytes(&message(&options)).unwrap();
+        let options = &message.options;
+
+        assert_eq!(options.client_id, Some(vec![0, 3, 0, 1, 1, 2, 3, 4, 5, 6]));
+        assert_eq!(options.option_request, Some(vec![23, 24]));
+        assert_eq!(options.preference, Some(255));
+        assert_eq!(options.elapsed_time, Some(256));
+        assert!(options.rapid_commit);
+        let status_code = options.status_code.as_ref().unwrap();
+        assert_eq!(status_code.code, 2);
+        assert_eq!(status_code.message, "no addrs");
+    }
+}
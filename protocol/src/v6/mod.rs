@@ -0,0 +1,72 @@
+//! The main DHCPv6 message module.
+pub mod constants;
+pub mod message_type;
+pub mod options;
+
+mod deserializer;
+mod serializer;
+
+use std::fmt;
+
+pub use self::{
+    message_type::MessageType,
+    options::{IaAddress, IaNa, IaPd, IaPrefix, OptionCode, Options, Status, StatusCode},
+};
+
+/// DHCPv6 client/server message (RFC 8415 §8).
+pub struct Message {
+    pub message_type: MessageType,
+    /// Only the lower 24 bits are used.
+    ///
+    /// Contains the flags in `DHCPV4-QUERY` and `DHCPV4-RESPONSE` (RFC 7341 §6).
+    pub transaction_id: u32,
+    pub options: Options,
+}
+
+/// Prints an option with `Debug`.
+macro_rules! dbg_opt (
+    ($f:expr, $option:expr, $code:expr) => (
+        let name = stringify!($option).split(".").collect::<Vec<&str>>().last().cloned().unwrap();
+        if let Some(ref v) = $option {
+            writeln!($f, "[{:03}] {:027}| {:?}", $code as u16, name, v)?;
+        }
+    );
+);
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f)?;
+
+        writeln!(f, "{}HEADER{}", "_".repeat(30), "_".repeat(39))?;
+        writeln!(f, "{:32} | {}", "Message type", self.message_type)?;
+        writeln!(f, "{:32} | {:06x}", "Transaction ID", self.transaction_id)?;
+
+        writeln!(f, "{}OPTIONS{}", "_".repeat(30), "_".repeat(38))?;
+        dbg_opt!(f, self.options.client_id, OptionCode::ClientId);
+        dbg_opt!(f, self.options.server_id, OptionCode::ServerId);
+        for ia_na in self.options.ia_na.iter() {
+            writeln!(f, "[{:03}] {:027}| {:?}", OptionCode::IaNa as u16, "ia_na", ia_na)?;
+        }
+        dbg_opt!(f, self.options.option_request, OptionCode::OptionRequest);
+        dbg_opt!(f, self.options.preference, OptionCode::Preference);
+        dbg_opt!(f, self.options.elapsed_time, OptionCode::ElapsedTime);
+        dbg_opt!(f, self.options.status_code, OptionCode::StatusCode);
+        if self.options.rapid_commit {
+            writeln!(f, "[{:03}] {:027}|", OptionCode::RapidCommit as u16, "rapid_commit")?;
+        }
+        dbg_opt!(f, self.options.server_unicast, OptionCode::ServerUnicast);
+        for ia_pd in self.options.ia_pd.iter() {
+            writeln!(f, "[{:03}] {:027}| {:?}", OptionCode::IaPd as u16, "ia_pd", ia_pd)?;
+        }
+        dbg_opt!(f, self.options.dns_servers, OptionCode::DnsServers);
+        dbg_opt!(f, self.options.domain_list, OptionCode::DomainList);
+        if let Some(ref message) = self.options.dhcpv4_message {
+            let code = OptionCode::Dhcpv4Message as u16;
+            writeln!(f, "[{:03}] {:027}| {} bytes", code, "dhcpv4_message", message.len())?;
+        }
+        dbg_opt!(f, self.options.dhcp4o6_servers, OptionCode::Dhcp4o6Servers);
+
+        writeln!(f, "{}", "_".repeat(75))?;
+        Ok(())
+    }
+}
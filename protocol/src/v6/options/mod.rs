@@ -0,0 +1,136 @@
+//! DHCPv6 options module.
+
+mod option_code;
+
+use std::{fmt, net::Ipv6Addr};
+
+pub use self::option_code::OptionCode;
+
+/// The status code carried in the `Status Code` option (RFC 8415 §21.13).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Success = 0,
+    UnspecFail = 1,
+    NoAddrsAvail = 2,
+    NoBinding = 3,
+    NotOnLink = 4,
+    UseMulticast = 5,
+    NoPrefixAvail = 6,
+    Unknown,
+}
+
+impl From<u16> for Status {
+    fn from(value: u16) -> Self {
+        use self::Status::*;
+        match value {
+            0 => Success,
+            1 => UnspecFail,
+            2 => NoAddrsAvail,
+            3 => NoBinding,
+            4 => NotOnLink,
+            5 => UseMulticast,
+            6 => NoPrefixAvail,
+
+            _ => Unknown,
+        }
+    }
+}
+
+/// The `Status Code` option value.
+#[derive(Debug, Clone)]
+pub struct StatusCode {
+    pub code: u16,
+    pub message: String,
+}
+
+impl StatusCode {
+    /// The status enumeration value.
+    pub fn status(&self) -> Status {
+        Status::from(self.code)
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} ({}) {}", self.status(), self.code, self.message)
+    }
+}
+
+/// The `IA Address` option value (RFC 8415 §21.6).
+#[derive(Debug, Clone)]
+pub struct IaAddress {
+    pub address: Ipv6Addr,
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+    pub status_code: Option<StatusCode>,
+}
+
+/// The `Identity Association for Non-temporary Addresses` option value (RFC 8415 §21.4).
+#[derive(Debug, Clone)]
+pub struct IaNa {
+    pub iaid: u32,
+    /// So called T1.
+    pub renewal_time: u32,
+    /// So called T2.
+    pub rebinding_time: u32,
+    pub addresses: Vec<IaAddress>,
+    pub status_code: Option<StatusCode>,
+}
+
+/// The `IA Prefix` option value (RFC 8415 §21.22).
+#[derive(Debug, Clone)]
+pub struct IaPrefix {
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+    pub prefix_length: u8,
+    pub prefix: Ipv6Addr,
+    pub status_code: Option<StatusCode>,
+}
+
+/// The `Identity Association for Prefix Delegation` option value (RFC 8415 §21.21).
+#[derive(Debug, Clone)]
+pub struct IaPd {
+    pub iaid: u32,
+    /// So called T1.
+    pub renewal_time: u32,
+    /// So called T2.
+    pub rebinding_time: u32,
+    pub prefixes: Vec<IaPrefix>,
+    pub status_code: Option<StatusCode>,
+}
+
+/// DHCPv6 options.
+///
+/// [RFC 8415](https://tools.ietf.org/html/rfc8415)
+/// [RFC 3646](https://tools.ietf.org/html/rfc3646)
+/// [RFC 7341](https://tools.ietf.org/html/rfc7341)
+#[derive(Default)]
+pub struct Options {
+    /*
+    RFC 8415 (Dynamic Host Configuration Protocol for IPv6)
+    */
+    pub client_id: Option<Vec<u8>>,
+    pub server_id: Option<Vec<u8>>,
+    pub ia_na: Vec<IaNa>,
+    pub option_request: Option<Vec<u16>>,
+    pub preference: Option<u8>,
+    /// In hundredths of a second.
+    pub elapsed_time: Option<u16>,
+    pub status_code: Option<StatusCode>,
+    pub rapid_commit: bool,
+    pub server_unicast: Option<Ipv6Addr>,
+    pub ia_pd: Vec<IaPd>,
+
+    /*
+    RFC 3646 (DNS Configuration options for DHCPv6)
+    */
+    pub dns_servers: Option<Vec<Ipv6Addr>>,
+    pub domain_list: Option<Vec<String>>,
+
+    /*
+    RFC 7341 (DHCPv4-over-DHCPv6 Transport)
+    */
+    /// The encapsulated DHCPv4 message.
+    pub dhcpv4_message: Option<Vec<u8>>,
+    pub dhcp4o6_servers: Option<Vec<Ipv6Addr>>,
+}
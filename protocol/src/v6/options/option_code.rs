@@ -0,0 +1,86 @@
+//! DHCPv6 option code module.
+
+/// DHCPv6 option code (RFC 8415 §21).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionCode {
+    Unknown = 0,
+
+    /*
+    RFC 8415 (Dynamic Host Configuration Protocol for IPv6)
+    */
+    ClientId = 1,
+    ServerId = 2,
+    IaNa = 3,
+    IaTa = 4,
+    IaAddr = 5,
+    OptionRequest = 6,
+    Preference = 7,
+    ElapsedTime = 8,
+    RelayMessage = 9,
+    Authentication = 11,
+    ServerUnicast = 12,
+    StatusCode = 13,
+    RapidCommit = 14,
+    UserClass = 15,
+    VendorClass = 16,
+    VendorOpts = 17,
+    InterfaceId = 18,
+    ReconfigureMessage = 19,
+    ReconfigureAccept = 20,
+
+    /*
+    RFC 3646 (DNS Configuration options for DHCPv6)
+    */
+    DnsServers = 23,
+    DomainList = 24,
+
+    /*
+    RFC 8415 (Prefix Delegation)
+    */
+    IaPd = 25,
+    IaPrefix = 26,
+
+    /*
+    RFC 7341 (DHCPv4-over-DHCPv6 Transport)
+    */
+    Dhcpv4Message = 87,
+    Dhcp4o6Servers = 88,
+}
+
+impl From<u16> for OptionCode {
+    fn from(value: u16) -> Self {
+        use self::OptionCode::*;
+        match value {
+            1 => ClientId,
+            2 => ServerId,
+            3 => IaNa,
+            4 => IaTa,
+            5 => IaAddr,
+            6 => OptionRequest,
+            7 => Preference,
+            8 => ElapsedTime,
+            9 => RelayMessage,
+            11 => Authentication,
+            12 => ServerUnicast,
+            13 => StatusCode,
+            14 => RapidCommit,
+            15 => UserClass,
+            16 => VendorClass,
+            17 => VendorOpts,
+            18 => InterfaceId,
+            19 => ReconfigureMessage,
+            20 => ReconfigureAccept,
+
+            23 => DnsServers,
+            24 => DomainList,
+
+            25 => IaPd,
+            26 => IaPrefix,
+
+            87 => Dhcpv4Message,
+            88 => Dhcp4o6Servers,
+
+            _ => Unknown,
+        }
+    }
+}
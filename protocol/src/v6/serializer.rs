@@ -0,0 +1,220 @@
+//! DHCPv6 message serialization module.
+
+use std::{
+    io::{self, Cursor},
+    net::Ipv6Addr,
+};
+
+use bytes::{Buf, BufMut};
+
+use super::{
+    constants::*,
+    options::{IaAddress, IaNa, IaPd, IaPrefix, OptionCode, StatusCode},
+    Message,
+};
+
+/// Checks if there is enough space in buffer to put a value.
+macro_rules! check_remaining(
+    ($cursor:expr, $distance:expr) => (
+        if $cursor.remaining() < $distance {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "No more space left"));
+        }
+    )
+);
+
+/// The maximal option value size.
+const SIZE_OPTION_MAX: usize = 0xffff;
+
+impl Message {
+    /// DHCPv6 message serialization.
+    ///
+    /// Unlike DHCPv4, all the options are mandatory, since there is no size negotiation.
+    ///
+    /// # Errors
+    /// `io::Error` if the buffer is too small.
+    pub fn to_bytes(&self, dst: &mut [u8]) -> io::Result<usize> {
+        let mut cursor = Cursor::new(dst);
+        check_remaining!(cursor, SIZE_HEADER);
+        cursor.put_u32_be(
+            ((self.message_type as u32) << 24) | (self.transaction_id & TRANSACTION_ID_MASK),
+        );
+
+        let options = &self.options;
+        if let Some(ref client_id) = options.client_id {
+            Self::put_opt_bytes(&mut cursor, OptionCode::ClientId, client_id)?;
+        }
+        if let Some(ref server_id) = options.server_id {
+            Self::put_opt_bytes(&mut cursor, OptionCode::ServerId, server_id)?;
+        }
+        for ia_na in options.ia_na.iter() {
+            Self::put_opt_bytes(&mut cursor, OptionCode::IaNa, &Self::ia_na_bytes(ia_na)?)?;
+        }
+        if let Some(ref codes) = options.option_request {
+            let mut data = Vec::with_capacity(codes.len() * 2);
+            for code in codes.iter() {
+                data.put_u16_be(*code);
+            }
+            Self::put_opt_bytes(&mut cursor, OptionCode::OptionRequest, &data)?;
+        }
+        if let Some(preference) = options.preference {
+            Self::put_opt_bytes(&mut cursor, OptionCode::Preference, &[preference])?;
+        }
+        if let Some(elapsed_time) = options.elapsed_time {
+            let data = [(elapsed_time >> 8) as u8, elapsed_time as u8];
+            Self::put_opt_bytes(&mut cursor, OptionCode::ElapsedTime, &data)?;
+        }
+        if let Some(ref status_code) = options.status_code {
+            let data = Self::status_code_bytes(status_code);
+            Self::put_opt_bytes(&mut cursor, OptionCode::StatusCode, &data)?;
+        }
+        if options.rapid_commit {
+            Self::put_opt_bytes(&mut cursor, OptionCode::RapidCommit, &[])?;
+        }
+        if let Some(ref address) = options.server_unicast {
+            Self::put_opt_bytes(&mut cursor, OptionCode::ServerUnicast, &address.octets())?;
+        }
+        for ia_pd in options.ia_pd.iter() {
+            Self::put_opt_bytes(&mut cursor, OptionCode::IaPd, &Self::ia_pd_bytes(ia_pd)?)?;
+        }
+        if let Some(ref message) = options.dhcpv4_message {
+            Self::put_opt_bytes(&mut cursor, OptionCode::Dhcpv4Message, message)?;
+        }
+        if let Some(ref addresses) = options.dhcp4o6_servers {
+            let data = Self::ipv6_list_bytes(addresses);
+            Self::put_opt_bytes(&mut cursor, OptionCode::Dhcp4o6Servers, &data)?;
+        }
+        if let Some(ref addresses) = options.dns_servers {
+            let data = Self::ipv6_list_bytes(addresses);
+            Self::put_opt_bytes(&mut cursor, OptionCode::DnsServers, &data)?;
+        }
+        if let Some(ref names) = options.domain_list {
+            let data = Self::domain_list_bytes(names)?;
+            Self::put_opt_bytes(&mut cursor, OptionCode::DomainList, &data)?;
+        }
+
+        Ok(cursor.position() as usize)
+    }
+
+    fn put_opt_bytes(
+        cursor: &mut Cursor<&mut [u8]>,
+        code: OptionCode,
+        value: &[u8],
+    ) -> io::Result<()> {
+        if value.len() > SIZE_OPTION_MAX {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The option is too big",
+            ));
+        }
+        check_remaining!(cursor, SIZE_OPTION_HEADER + value.len());
+        cursor.put_u16_be(code as u16);
+        cursor.put_u16_be(value.len() as u16);
+        cursor.put_slice(value);
+        Ok(())
+    }
+
+    /// Encodes a nested option into a vector.
+    fn nested_option(data: &mut Vec<u8>, code: OptionCode, value: &[u8]) -> io::Result<()> {
+        if value.len() > SIZE_OPTION_MAX {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The option is too big",
+            ));
+        }
+        data.put_u16_be(code as u16);
+        data.put_u16_be(value.len() as u16);
+        data.put_slice(value);
+        Ok(())
+    }
+
+    fn status_code_bytes(status_code: &StatusCode) -> Vec<u8> {
+        let mut data = Vec::with_capacity(2 + status_code.message.len());
+        data.put_u16_be(status_code.code);
+        data.put_slice(status_code.message.as_bytes());
+        data
+    }
+
+    fn ipv6_list_bytes(addresses: &[Ipv6Addr]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(addresses.len() * 16);
+        for address in addresses.iter() {
+            data.put_slice(&address.octets());
+        }
+        data
+    }
+
+    fn ia_na_bytes(ia_na: &IaNa) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        data.put_u32_be(ia_na.iaid);
+        data.put_u32_be(ia_na.renewal_time);
+        data.put_u32_be(ia_na.rebinding_time);
+        for address in ia_na.addresses.iter() {
+            let value = Self::ia_address_bytes(address)?;
+            Self::nested_option(&mut data, OptionCode::IaAddr, &value)?;
+        }
+        if let Some(ref status_code) = ia_na.status_code {
+            let value = Self::status_code_bytes(status_code);
+            Self::nested_option(&mut data, OptionCode::StatusCode, &value)?;
+        }
+        Ok(data)
+    }
+
+    fn ia_address_bytes(ia_address: &IaAddress) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        data.put_slice(&ia_address.address.octets());
+        data.put_u32_be(ia_address.preferred_lifetime);
+        data.put_u32_be(ia_address.valid_lifetime);
+        if let Some(ref status_code) = ia_address.status_code {
+            let value = Self::status_code_bytes(status_code);
+            Self::nested_option(&mut data, OptionCode::StatusCode, &value)?;
+        }
+        Ok(data)
+    }
+
+    fn ia_pd_bytes(ia_pd: &IaPd) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        data.put_u32_be(ia_pd.iaid);
+        data.put_u32_be(ia_pd.renewal_time);
+        data.put_u32_be(ia_pd.rebinding_time);
+        for prefix in ia_pd.prefixes.iter() {
+            let value = Self::ia_prefix_bytes(prefix)?;
+            Self::nested_option(&mut data, OptionCode::IaPrefix, &value)?;
+        }
+        if let Some(ref status_code) = ia_pd.status_code {
+            let value = Self::status_code_bytes(status_code);
+            Self::nested_option(&mut data, OptionCode::StatusCode, &value)?;
+        }
+        Ok(data)
+    }
+
+    fn ia_prefix_bytes(ia_prefix: &IaPrefix) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        data.put_u32_be(ia_prefix.preferred_lifetime);
+        data.put_u32_be(ia_prefix.valid_lifetime);
+        data.put_u8(ia_prefix.prefix_length);
+        data.put_slice(&ia_prefix.prefix.octets());
+        if let Some(ref status_code) = ia_prefix.status_code {
+            let value = Self::status_code_bytes(status_code);
+            Self::nested_option(&mut data, OptionCode::StatusCode, &value)?;
+        }
+        Ok(data)
+    }
+
+    /// Encodes the names in the uncompressed DNS wire format (RFC 8415 §10).
+    fn domain_list_bytes(names: &[String]) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        for name in names.iter() {
+            for label in name.trim_end_matches('.').split('.') {
+                if label.is_empty() || label.len() > 63 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Invalid domain name label",
+                    ));
+                }
+                data.put_u8(label.len() as u8);
+                data.put_slice(label.as_bytes());
+            }
+            data.put_u8(0);
+        }
+        Ok(data)
+    }
+}
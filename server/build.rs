@@ -0,0 +1,33 @@
+//! Generates the gRPC admin API code if the `grpc-api` feature is enabled.
+
+#[cfg(feature = "grpc-api")]
+extern crate protoc_rust_grpc;
+
+#[cfg(feature = "grpc-api")]
+fn main() {
+    use std::{env, fs, path::Path};
+
+    println!("cargo:rerun-if-changed=proto/dhcp_admin.proto");
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    protoc_rust_grpc::run(protoc_rust_grpc::Args {
+        out_dir: &out_dir,
+        includes: &["proto"],
+        input: &["proto/dhcp_admin.proto"],
+        rust_protobuf: true,
+    }).expect("The gRPC code generation error");
+
+    // the generated files are included as modules, which cannot have the inner attributes
+    for name in ["dhcp_admin.rs", "dhcp_admin_grpc.rs"].iter() {
+        let path = Path::new(&out_dir).join(name);
+        let code = fs::read_to_string(&path).expect("The generated file reading error");
+        let code: String = code
+            .lines()
+            .filter(|line| !line.starts_with("#![") && !line.starts_with("//!"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        fs::write(&path, code).expect("The generated file writing error");
+    }
+}
+
+#[cfg(not(feature = "grpc-api"))]
+fn main() {}
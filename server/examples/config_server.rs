@@ -0,0 +1,72 @@
+//! Run this with administrator privileges where it is required
+//! in order to bind the DHCP server socket to its port 67 or use other OS-specific features.
+//!
+//! The configuration file path is the first argument, `server.toml` by default.
+//! The file is reloaded on `SIGHUP` on Unix systems and on the admin API requests.
+//!
+//! With `--check-config` before the path the configuration is only validated
+//! and printed with the defaults filled in, exiting with 1 if it has problems.
+//! The problems are printed to the standard error, so the standard output
+//! holds only the configuration.
+
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+extern crate tokio;
+
+extern crate dhcp_server;
+
+use tokio::prelude::Future;
+
+fn main() {
+    std::env::set_var("RUST_BACKTRACE", "full");
+    std::env::set_var("RUST_LOG", "config_server=trace,dhcp_server=trace");
+    env_logger::init();
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let is_check = args.first().map_or(false, |arg| arg == "--check-config");
+    if is_check {
+        args.remove(0);
+    }
+    let path = args
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| "server.toml".to_owned());
+    if is_check {
+        match dhcp_server::Config::from_file(&path).and_then(dhcp_server::Config::check) {
+            Ok(report) => {
+                for line in report.view().iter() {
+                    println!("{}", line);
+                }
+                for warning in report.warnings.iter() {
+                    eprintln!("warning: {}", warning);
+                }
+                for conflict in report.conflicts.iter() {
+                    eprintln!("error: {}", conflict);
+                }
+                if !report.is_valid() {
+                    std::process::exit(1);
+                }
+            }
+            Err(error) => {
+                eprintln!("error: {}", error);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let config = dhcp_server::Config::from_file(&path).expect("Configuration loading error");
+    let mut builder = config
+        .into_builder(dhcp_server::RamStorage::new())
+        .expect("Configuration error");
+    builder.with_reloader(dhcp_server::file_reloader(&path));
+    #[cfg(unix)]
+    {
+        builder.with_reload_source(dhcp_server::sighup_reloads(&path));
+    }
+    let server = builder.finish().expect("Server creating error");
+    let future = server.map_err(|error| error!("Error: {}", error));
+
+    info!("DHCP server started with the configuration {}", path);
+    tokio::run(future);
+}
@@ -0,0 +1,32 @@
+//! Sends a request to the control socket of a running server and prints the reply.
+//!
+//! `control /run/rust-dhcp.sock query lease 192.168.0.10`
+//!
+//! Exits with 1 if the request has failed.
+
+extern crate dhcp_server;
+
+#[cfg(unix)]
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("Usage: control <socket> <request>");
+        std::process::exit(2);
+    }
+
+    let mut client =
+        dhcp_server::ControlClient::connect(&args[0]).expect("Control socket connecting error");
+    let reply = client
+        .request(&args[1..].join(" "))
+        .expect("Control socket request error");
+    println!("{}", reply.body);
+    if !reply.is_success() {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("The control socket is supported only on Unix");
+    std::process::exit(2);
+}
@@ -0,0 +1,42 @@
+//! Migrates the bindings between an ISC dhcpd leases file and a lease journal.
+//!
+//! `dhcpd_leases import <dhcpd.leases> <journal>` adds the active leases to the journal.
+//! `dhcpd_leases export <journal> <dhcpd.leases>` writes the active leases for dhcpd.
+//!
+//! Stop the servers using the files before migrating.
+
+extern crate dhcp_server;
+
+use std::{env, fs, io::Write, process};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 3 {
+        eprintln!("Usage: dhcpd_leases import <dhcpd.leases> <journal>");
+        eprintln!("       dhcpd_leases export <journal> <dhcpd.leases>");
+        process::exit(2);
+    }
+
+    match args[0].as_str() {
+        "import" => {
+            let data = fs::read_to_string(&args[1]).expect("Leases file reading error");
+            let mut storage =
+                dhcp_server::FileStorage::open(&args[2]).expect("Journal opening error");
+            let imported = dhcp_server::import_dhcpd_leases(&mut storage, &data)
+                .unwrap_or_else(|error| panic!("Import error: {}", error));
+            println!("Imported {} leases into {}", imported, args[2]);
+        }
+        "export" => {
+            let storage = dhcp_server::FileStorage::open(&args[1]).expect("Journal opening error");
+            let mut file = fs::File::create(&args[2]).expect("Leases file creating error");
+            let exported = dhcp_server::export_dhcpd_leases(&storage, &mut file)
+                .unwrap_or_else(|error| panic!("Export error: {}", error));
+            file.flush().expect("Leases file writing error");
+            println!("Exported {} leases into {}", exported, args[2]);
+        }
+        command => {
+            eprintln!("Unknown command {}", command);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Run this with administrator privileges where it is required
+//! in order to bind the DHCP server socket to its port 67 or use other OS-specific features.
+//!
+//! Runs a worker per thread on Linux, all of them sharing the port, the lease table
+//! and the expirations. The workers may be run as separate processes the same way,
+//! sharing a Redis or SQLite storage.
+//! The number of workers is the first argument, 4 by default.
+
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+extern crate tokio;
+
+extern crate dhcp_server;
+
+use std::{net::Ipv4Addr, thread};
+
+use tokio::prelude::Future;
+
+/// The number of the lease table shards.
+const SHARDS: usize = 64;
+
+fn main() {
+    std::env::set_var("RUST_BACKTRACE", "full");
+    std::env::set_var("RUST_LOG", "workers=trace,dhcp_server=info");
+    env_logger::init();
+
+    let workers: u32 = std::env::args()
+        .nth(1)
+        .map(|count| count.parse().expect("Invalid number of workers"))
+        .unwrap_or(4);
+    let storage = dhcp_server::ShardedStorage::new(
+        (0..SHARDS)
+            .map(|_| dhcp_server::RamStorage::new())
+            .collect(),
+    );
+    let state = dhcp_server::WorkerState::new();
+
+    let threads: Vec<thread::JoinHandle<()>> = (0..workers)
+        .map(|index| {
+            let storage = storage.clone();
+            let state = state.clone();
+            thread::spawn(move || {
+                let mut builder = dhcp_server::ServerBuilder::new(
+                    Ipv4Addr::new(192, 168, 0, 2),
+                    "ens33".to_owned(),
+                    (
+                        Ipv4Addr::new(192, 168, 0, 50),
+                        Ipv4Addr::new(192, 168, 0, 99),
+                    ),
+                    (
+                        Ipv4Addr::new(192, 168, 0, 100),
+                        Ipv4Addr::new(192, 168, 3, 254),
+                    ),
+                    storage,
+                    Ipv4Addr::new(255, 255, 252, 0),
+                    vec![Ipv4Addr::new(192, 168, 0, 1)],
+                    vec![Ipv4Addr::new(192, 168, 0, 1)],
+                    vec![],
+                    vec![],
+                );
+                builder.with_worker(index, workers, state);
+                let server = builder.finish().expect("Server creating error");
+                let future = server.map_err(|error| error!("Error: {}", error));
+
+                info!("DHCP server worker {} started", index);
+                tokio::run(future);
+            })
+        }).collect();
+    for thread in threads.into_iter() {
+        let _ = thread.join();
+    }
+}
@@ -0,0 +1,64 @@
+//! The hardware address allow and deny lists.
+
+use eui48::MacAddress;
+
+/// A hardware address pattern.
+///
+/// Parsed from an exact address like `00:11:22:33:44:55`, an OUI prefix like `00:11:22`
+/// or a wildcard like `00:11:*:33:*:*`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacPattern([Option<u8>; 6]);
+
+impl MacPattern {
+    /// Returns `None` if the pattern is malformed.
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let parts: Vec<&str> = pattern.split(|c| c == ':' || c == '-').collect();
+        if parts.is_empty() || parts.len() > 6 {
+            return None;
+        }
+        let mut octets = [None; 6];
+        for (index, part) in parts.into_iter().enumerate() {
+            octets[index] = match part {
+                "*" => None,
+                part => Some(u8::from_str_radix(part, 16).ok()?),
+            };
+        }
+        Some(MacPattern(octets))
+    }
+
+    /// Checks whether the address matches the pattern.
+    pub fn matches(&self, address: &MacAddress) -> bool {
+        self.0
+            .iter()
+            .zip(address.as_bytes().iter())
+            .all(|(pattern, octet)| pattern.map_or(true, |pattern| pattern == *octet))
+    }
+}
+
+/// What the server does with a denied client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeniedAction {
+    /// The requests are silently dropped.
+    Ignore,
+    /// The `DHCPREQUEST` messages get `DHCPNAK`, so the client gives up its address,
+    /// while the other messages are dropped.
+    Nak,
+}
+
+/// The clients allowed to get addresses.
+#[derive(Debug, Clone)]
+pub struct AccessList {
+    /// If not empty, only the matching clients are allowed.
+    pub allow: Vec<MacPattern>,
+    /// Takes precedence over `allow`.
+    pub deny: Vec<MacPattern>,
+    pub denied_action: DeniedAction,
+}
+
+impl AccessList {
+    /// Checks whether the client is allowed.
+    pub fn permits(&self, address: &MacAddress) -> bool {
+        !self.deny.iter().any(|pattern| pattern.matches(address))
+            && (self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.matches(address)))
+    }
+}
@@ -0,0 +1,292 @@
+//! The HTTP admin API managing a running server.
+//!
+//! A minimal HTTP/1.1 service with the JSON responses:
+//!
+//! * `GET /leases` lists the leases, optionally filtered by the `address`, `client_id`,
+//! `hardware_address`, `state` and `device_type` query parameters.
+//! * `DELETE /leases/<address>` frees the address.
+//! * `POST /leases/<address>/forcerenew` sends a `DHCPFORCERENEW` to the client of the lease.
+//! * `GET /pools` shows the size and the allocated, leased, reserved, abandoned and free
+//! addresses of every pool range with its high-water mark.
+//! * `POST /pools/<subnet>/forcerenew` sends a `DHCPFORCERENEW` to the clients
+//! of the active leases in the subnet, e.g. after changing its DNS servers or routes.
+//! * `GET /audit` shows the squatting and the silent addresses found by the last
+//! conflict audit of the pool addresses.
+//! * `POST /reservations` adds a reservation with the `[[reservations]]` fields,
+//! which is kept until the next configuration reload.
+//! * `POST /reload` reloads the configuration.
+//!
+//! If a token is set, the requests must have the `Authorization: Bearer <token>` header.
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use eui48::MacAddress;
+use tokio::io;
+
+use config::parse_reservation;
+use http::{HttpListener, Request, Response};
+use lease::Lease;
+use reservation::{HostKey, Reservation};
+use storage_file::{hex_decode, hex_encode};
+
+/// The lease states reported and searched by.
+const STATE_OFFERED: &str = "offered";
+const STATE_ACTIVE: &str = "active";
+const STATE_RELEASED: &str = "released";
+const STATE_EXPIRED: &str = "expired";
+
+/// A request validated by the admin API, executed by the server.
+pub enum Command {
+    Leases(LeaseFilter),
+    DeleteLease(Ipv4Addr),
+    Pools,
+    ForceRenew(RenewalTarget),
+    Audit,
+    AddReservation(HostKey, Reservation),
+    Reload,
+    /// Issued only over the control socket.
+    Shutdown,
+}
+
+/// The clients a `DHCPFORCERENEW` is sent to.
+#[derive(Debug, Clone, Copy)]
+pub enum RenewalTarget {
+    /// The client of the lease.
+    Lease(Ipv4Addr),
+    /// The clients of the active leases in the subnet with the address.
+    Subnet(Ipv4Addr),
+}
+
+/// The lease search parameters, all the set ones must match.
+#[derive(Default)]
+pub struct LeaseFilter {
+    address: Option<Ipv4Addr>,
+    client_id: Option<Vec<u8>>,
+    hardware_address: Option<MacAddress>,
+    state: Option<String>,
+    device_type: Option<String>,
+}
+
+impl LeaseFilter {
+    /// Matches the lease of the address.
+    pub fn by_address(address: Ipv4Addr) -> Self {
+        LeaseFilter {
+            address: Some(address),
+            ..LeaseFilter::default()
+        }
+    }
+
+    /// Decodes the `/leases` query parameters.
+    pub fn from_query(query: &[(String, String)]) -> Result<Self, String> {
+        let mut filter = LeaseFilter::default();
+        for &(ref key, ref value) in query.iter() {
+            match key.as_str() {
+                "address" => {
+                    filter.address = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid address {}", value))?,
+                    )
+                }
+                "client_id" => {
+                    filter.client_id = Some(
+                        hex_decode(value).ok_or_else(|| format!("Invalid client ID {}", value))?,
+                    )
+                }
+                "hardware_address" => {
+                    filter.hardware_address = Some(
+                        MacAddress::parse_str(value)
+                            .map_err(|_| format!("Invalid hardware address {}", value))?,
+                    )
+                }
+                "state" => match value.as_str() {
+                    STATE_OFFERED | STATE_ACTIVE | STATE_RELEASED | STATE_EXPIRED => {
+                        filter.state = Some(value.to_owned())
+                    }
+                    _ => return Err(format!("Invalid lease state {}", value)),
+                },
+                "device_type" => filter.device_type = Some(value.to_owned()),
+                _ => return Err(format!("Unknown parameter {}", key)),
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Checks whether the lease matches.
+    ///
+    /// The hardware address is the one the client ID is made of, if any,
+    /// and the device type is the one found by the client fingerprint.
+    pub fn matches(
+        &self,
+        client_id: &[u8],
+        hardware_address: Option<MacAddress>,
+        device_type: Option<&str>,
+        lease: &Lease,
+    ) -> bool {
+        self.address.map_or(true, |address| address == lease.address())
+            && self
+                .client_id
+                .as_ref()
+                .map_or(true, |expected| expected.as_slice() == client_id)
+            && self
+                .hardware_address
+                .map_or(true, |expected| Some(expected) == hardware_address)
+            && self
+                .state
+                .as_ref()
+                .map_or(true, |state| state == lease_state(lease))
+            && self
+                .device_type
+                .as_ref()
+                .map_or(true, |expected| Some(expected.as_str()) == device_type)
+    }
+}
+
+/// A lease in the responses.
+#[derive(Serialize)]
+pub struct LeaseRecord {
+    /// Hexadecimal.
+    client_id: String,
+    hardware_address: Option<String>,
+    address: Ipv4Addr,
+    state: &'static str,
+    lease_time: u32,
+    /// The Unix time, zero for the infinite leases.
+    expires_at: u32,
+    /// Like `printer`, if the client fingerprint is known.
+    device_type: Option<String>,
+}
+
+impl LeaseRecord {
+    pub fn new(
+        client_id: &[u8],
+        hardware_address: Option<MacAddress>,
+        device_type: Option<&str>,
+        lease: &Lease,
+    ) -> Self {
+        LeaseRecord {
+            client_id: hex_encode(client_id),
+            hardware_address: hardware_address.map(|address| address.to_hex_string()),
+            address: lease.address(),
+            state: lease_state(lease),
+            lease_time: lease.lease_time(),
+            expires_at: lease.expires_at(),
+            device_type: device_type.map(str::to_owned),
+        }
+    }
+}
+
+/// The outcome of a `DHCPFORCERENEW` command.
+#[derive(Serialize)]
+pub struct ForceRenewReport {
+    pub sent: usize,
+    /// The clients having neither a subnet key nor a nonce.
+    pub skipped: usize,
+}
+
+/// The admin API on top of the HTTP listener.
+pub struct AdminApi {
+    http: HttpListener,
+    /// The bearer token the requests must have, if any.
+    token: Option<String>,
+}
+
+impl AdminApi {
+    /// Starts listening for the admin clients.
+    ///
+    /// # Errors
+    /// `io::Error` if the address cannot be bound.
+    pub fn new(address: SocketAddr, token: Option<String>) -> io::Result<Self> {
+        Ok(AdminApi {
+            http: HttpListener::new(address)?,
+            token,
+        })
+    }
+
+    /// Returns the next command with the ID of its connection, accepting the new connections.
+    ///
+    /// The unauthorized and invalid requests are answered here.
+    pub fn poll_command(&mut self) -> Option<(usize, Command)> {
+        loop {
+            let (id, peer, request) = self.http.poll_request()?;
+            info!(
+                target: "audit",
+                "Admin API request {} {} from {}",
+                request.method,
+                request.path,
+                peer,
+            );
+            let is_authorized = self.token.as_ref().map_or(true, |token| {
+                request.authorization.as_ref() == Some(&format!("Bearer {}", token))
+            });
+            if !is_authorized {
+                warn!("The admin client {} is not authorized", peer);
+                self.http.respond(id, Response::error(401, "Unauthorized"));
+                continue;
+            }
+            match command(&request) {
+                Ok(command) => return Some((id, command)),
+                Err(response) => self.http.respond(id, response),
+            }
+        }
+    }
+
+    /// Queues the response to a command received on the connection.
+    pub fn respond(&mut self, id: usize, response: Response) {
+        self.http.respond(id, response);
+    }
+}
+
+/// Decodes the command or returns the error response.
+fn command(request: &Request) -> Result<Command, Response> {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let command = match (request.method.as_str(), &segments[..]) {
+        ("GET", ["leases"]) => Command::Leases(
+            LeaseFilter::from_query(&request.query).map_err(|error| Response::error(400, &error))?,
+        ),
+        ("DELETE", ["leases", address]) => Command::DeleteLease(address.parse().map_err(|_| {
+            Response::error(400, &format!("Invalid address {}", address))
+        })?),
+        ("POST", ["leases", address, "forcerenew"]) => Command::ForceRenew(
+            RenewalTarget::Lease(address.parse().map_err(|_| {
+                Response::error(400, &format!("Invalid address {}", address))
+            })?),
+        ),
+        ("GET", ["pools"]) => Command::Pools,
+        ("POST", ["pools", subnet, "forcerenew"]) => Command::ForceRenew(
+            RenewalTarget::Subnet(subnet.parse().map_err(|_| {
+                Response::error(400, &format!("Invalid subnet {}", subnet))
+            })?),
+        ),
+        ("GET", ["audit"]) => Command::Audit,
+        ("POST", ["reservations"]) => {
+            let (key, reservation) = parse_reservation(&request.body)
+                .map_err(|error| Response::error(400, &error.to_string()))?;
+            Command::AddReservation(key, reservation)
+        }
+        ("POST", ["reload"]) => Command::Reload,
+        (_, ["leases"])
+        | (_, ["leases", _])
+        | (_, ["leases", _, "forcerenew"])
+        | (_, ["pools"])
+        | (_, ["pools", _, "forcerenew"])
+        | (_, ["audit"])
+        | (_, ["reservations"])
+        | (_, ["reload"]) => return Err(Response::error(405, "Method not allowed")),
+        _ => return Err(Response::error(404, "Not found")),
+    };
+    Ok(command)
+}
+
+/// The expired offers are reported as expired leases.
+fn lease_state(lease: &Lease) -> &'static str {
+    if lease.is_offered() && !lease.is_offer_expired() {
+        STATE_OFFERED
+    } else if lease.is_active() {
+        STATE_ACTIVE
+    } else if lease.is_released() {
+        STATE_RELEASED
+    } else {
+        STATE_EXPIRED
+    }
+}
@@ -0,0 +1,196 @@
+//! The strategies choosing the dynamic addresses offered to the new clients.
+
+use std::fmt;
+
+use rand::{self, Rng};
+
+/// Orders the addresses of a dynamic pool range for an allocation.
+///
+/// The database offers the first candidate which is available,
+/// so a strategy decides only the order.
+pub trait Allocator: fmt::Debug + Send + Sync {
+    /// Returns every address of the inclusive range `[first, last]` exactly once.
+    fn candidates(&self, client_id: &[u8], first: u32, last: u32) -> Box<Iterator<Item = u32>>;
+
+    /// Returns every address of the inclusive ranges exactly once.
+    ///
+    /// The ranges are searched one after another by default.
+    fn pool_candidates(
+        &self,
+        client_id: &[u8],
+        ranges: &[(u32, u32)],
+    ) -> Box<Iterator<Item = u32>> {
+        let candidates: Vec<Box<Iterator<Item = u32>>> = ranges
+            .iter()
+            .filter(|&&(first, last)| first <= last)
+            .map(|&(first, last)| self.candidates(client_id, first, last))
+            .collect();
+        Box::new(candidates.into_iter().flat_map(|candidates| candidates))
+    }
+}
+
+/// Offers the lowest free address, which keeps the pool compact.
+#[derive(Debug, Default)]
+pub struct SequentialAllocator;
+
+impl Allocator for SequentialAllocator {
+    fn candidates(&self, _client_id: &[u8], first: u32, last: u32) -> Box<Iterator<Item = u32>> {
+        Box::new(first..=last)
+    }
+}
+
+/// Offers a random free address, which makes the addresses unpredictable
+/// and delays their reuse.
+#[derive(Debug, Default)]
+pub struct RandomAllocator;
+
+impl Allocator for RandomAllocator {
+    fn candidates(&self, _client_id: &[u8], first: u32, last: u32) -> Box<Iterator<Item = u32>> {
+        let size = u64::from(last - first) + 1;
+        let mut rng = rand::thread_rng();
+        let start = rng.gen_range(0, size);
+        // a stride coprime to the size visits every address once
+        let stride = loop {
+            let stride = rng.gen_range(1, size.max(2));
+            if gcd(stride, size) == 1 {
+                break stride;
+            }
+        };
+        Box::new((0..size).map(move |step| first + ((start + step * stride % size) % size) as u32))
+    }
+}
+
+/// Starts the search at the address the client ID hashes to and probes the next ones,
+/// so a client whose lease is long gone most likely gets the same address again.
+///
+/// The hash is taken over all the ranges of a pool as a whole and is stable across the
+/// restarts and the builds, so the servers of a stateless or anycast pair with the same
+/// pools offer a client the same address without sharing the leases.
+#[derive(Debug, Default)]
+pub struct HashAllocator;
+
+impl Allocator for HashAllocator {
+    fn candidates(&self, client_id: &[u8], first: u32, last: u32) -> Box<Iterator<Item = u32>> {
+        self.pool_candidates(client_id, &[(first, last)])
+    }
+
+    fn pool_candidates(
+        &self,
+        client_id: &[u8],
+        ranges: &[(u32, u32)],
+    ) -> Box<Iterator<Item = u32>> {
+        let ranges: Vec<(u32, u64)> = ranges
+            .iter()
+            .filter(|&&(first, last)| first <= last)
+            .map(|&(first, last)| (first, u64::from(last - first) + 1))
+            .collect();
+        let size: u64 = ranges.iter().map(|&(_, size)| size).sum();
+        if size == 0 {
+            return Box::new(None.into_iter());
+        }
+        let start = fnv1a(client_id) % size;
+        Box::new((0..size).map(move |step| {
+            // the offset in the pool is mapped to the range it falls into
+            let mut offset = (start + step) % size;
+            for &(first, range_size) in ranges.iter() {
+                if offset < range_size {
+                    return first + offset as u32;
+                }
+                offset -= range_size;
+            }
+            unreachable!("The offset is less than the pool size")
+        }))
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let remainder = a % b;
+        a = b;
+        b = remainder;
+    }
+    a
+}
+
+/// The 64-bit FNV-1a hash, which is stable across the restarts and the builds.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pools searched by every allocator, including an empty and a reversed range.
+    const POOLS: &[&[(u32, u32)]] = &[
+        &[(10, 10)],
+        &[(10, 19)],
+        &[(10, 19), (30, 34)],
+        &[(10, 19), (20, 10), (30, 34)],
+        &[(0, 0), (u32::max_value() - 3, u32::max_value())],
+    ];
+
+    fn allocators() -> Vec<Box<Allocator>> {
+        vec![
+            Box::new(SequentialAllocator),
+            Box::new(RandomAllocator),
+            Box::new(HashAllocator),
+        ]
+    }
+
+    fn expected(ranges: &[(u32, u32)]) -> Vec<u32> {
+        ranges
+            .iter()
+            .filter(|&&(first, last)| first <= last)
+            .flat_map(|&(first, last)| first..=last)
+            .collect()
+    }
+
+    #[test]
+    fn visits_every_address_once() {
+        for allocator in allocators() {
+            for ranges in POOLS {
+                let mut candidates: Vec<u32> =
+                    allocator.pool_candidates(&[1, 2, 3], ranges).collect();
+                candidates.sort();
+                assert_eq!(candidates, expected(ranges), "{:?} {:?}", allocator, ranges);
+            }
+        }
+    }
+
+    #[test]
+    fn sequential_offers_lowest_first() {
+        for ranges in POOLS {
+            let candidates: Vec<u32> = SequentialAllocator.pool_candidates(&[1], ranges).collect();
+            assert_eq!(candidates, expected(ranges), "{:?}", ranges);
+        }
+    }
+
+    #[test]
+    fn hash_is_stable_per_client() {
+        let cases: &[(&[u8], &[u8])] = &[(&[1], &[2]), (&[1, 2, 3], &[3, 2, 1]), (&[], &[0])];
+        for &(client_id, other_id) in cases {
+            for ranges in POOLS {
+                let first = HashAllocator.pool_candidates(client_id, ranges).next();
+                let again = HashAllocator.pool_candidates(client_id, ranges).next();
+                assert_eq!(first, again, "{:?} {:?}", client_id, ranges);
+                let other = HashAllocator.pool_candidates(other_id, ranges).next();
+                assert!(other.is_some(), "{:?} {:?}", other_id, ranges);
+            }
+        }
+    }
+
+    #[test]
+    fn fnv1a_matches_reference() {
+        let cases: &[(&[u8], u64)] = &[
+            (b"", 0xcbf2_9ce4_8422_2325),
+            (b"a", 0xaf63_dc4c_8601_ec8c),
+            (b"foobar", 0x8594_4171_f739_67e8),
+        ];
+        for &(data, hash) in cases {
+            assert_eq!(fnv1a(data), hash, "{:?}", data);
+        }
+    }
+}
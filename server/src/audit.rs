@@ -0,0 +1,133 @@
+//! The periodic audit of the pool addresses looking for the conflicts.
+//!
+//! Every address of the pool ranges is probed in the background with the ICMP echo
+//! probes of the address prober, using at most half of its probes, so the offers
+//! are still probed. The audit finds:
+//!
+//! * the squatters, i.e. the addresses replying without an active lease, like the hosts
+//! configured statically inside the pools;
+//! * the silent addresses, i.e. the leased ones not replying, like the clients gone
+//! without releasing their addresses or blocking the echo requests.
+//!
+//! The abandoned addresses are left to the reclamation. The report of the last finished
+//! audit is shown by the admin API.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    net::Ipv4Addr,
+};
+
+use chrono::Utc;
+
+/// The outcome of an audit.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AuditReport {
+    /// The Unix time.
+    pub started_at: i64,
+    /// The Unix time.
+    pub finished_at: i64,
+    pub probed: usize,
+    /// Replying without an active lease.
+    pub squatters: Vec<Ipv4Addr>,
+    /// Leased and not replying.
+    pub silent: Vec<Ipv4Addr>,
+}
+
+/// The audit in progress and the last finished one.
+#[derive(Default)]
+pub struct Audit {
+    /// The addresses waiting to be probed.
+    pending: VecDeque<Ipv4Addr>,
+    /// The addresses being probed.
+    probing: usize,
+    current: Option<AuditReport>,
+    last: Option<AuditReport>,
+}
+
+impl Audit {
+    pub fn new() -> Self {
+        Audit::default()
+    }
+
+    /// Starts auditing the inclusive ranges without the skipped addresses
+    /// unless the previous audit is in progress.
+    pub fn start(&mut self, ranges: &[(Ipv4Addr, Ipv4Addr)], skipped: &HashSet<Ipv4Addr>) {
+        if self.current.is_some() {
+            warn!("The previous address audit is still in progress");
+            return;
+        }
+        for &(first, last) in ranges.iter() {
+            self.pending.extend(
+                (u32::from(first)..=u32::from(last))
+                    .map(Ipv4Addr::from)
+                    .filter(|address| !skipped.contains(address)),
+            );
+        }
+        info!("Auditing {} addresses", self.pending.len());
+        self.current = Some(AuditReport {
+            started_at: Utc::now().timestamp(),
+            ..AuditReport::default()
+        });
+        self.finish_if_done();
+    }
+
+    /// Takes the next address to probe if the probes of the audit are fewer than `max_probing`.
+    pub fn next_address(&mut self, max_probing: usize) -> Option<Ipv4Addr> {
+        if self.probing >= max_probing {
+            return None;
+        }
+        let address = self.pending.pop_front()?;
+        self.probing += 1;
+        Some(address)
+    }
+
+    /// Returns the address which has not been probed, e.g. if the prober is busy.
+    pub fn postpone(&mut self, address: Ipv4Addr) {
+        self.probing -= 1;
+        self.pending.push_front(address);
+    }
+
+    /// Records the outcome of the address probe.
+    ///
+    /// The addresses whose state is not known are only counted as probed.
+    pub fn record(&mut self, address: Ipv4Addr, is_in_use: bool, is_leased: Option<bool>) {
+        self.probing = self.probing.saturating_sub(1);
+        if let Some(ref mut report) = self.current {
+            report.probed += 1;
+            match (is_in_use, is_leased) {
+                (true, Some(false)) => {
+                    warn!("Address conflict: {} replies without an active lease", address);
+                    report.squatters.push(address);
+                }
+                (false, Some(true)) => {
+                    debug!("The leased address {} does not reply", address);
+                    report.silent.push(address);
+                }
+                _ => {}
+            }
+        }
+        self.finish_if_done();
+    }
+
+    /// The report of the last finished audit, if any.
+    pub fn report(&self) -> Option<&AuditReport> {
+        self.last.as_ref()
+    }
+
+    fn finish_if_done(&mut self) {
+        if !self.pending.is_empty() || self.probing > 0 {
+            return;
+        }
+        if let Some(mut report) = self.current.take() {
+            report.finished_at = Utc::now().timestamp();
+            report.squatters.sort();
+            report.silent.sort();
+            info!(
+                "The address audit has found {} squatters and {} silent leases",
+                report.squatters.len(),
+                report.silent.len(),
+            );
+            self.last = Some(report);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! The RFC 3118 delayed authentication of the subnet clients.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use tokio::io;
+
+use dhcp_protocol::{
+    auth::{self, Authentication},
+    Message, MessageType,
+};
+
+/// The buffer size used to serialize a message before signing it.
+const SIZE_SIGNING_BUFFER: usize = 8192;
+
+/// What is done with a request failing the authentication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    /// The request is ignored.
+    Drop,
+    /// The failure is logged and the request is processed anyway,
+    /// e.g. while the clients are being provisioned with the keys.
+    Log,
+}
+
+/// The key shared by a subnet and its clients.
+#[derive(Debug, Clone)]
+pub struct AuthenticationKey {
+    /// The DHCP realm the key belongs to. May be empty.
+    pub realm: Vec<u8>,
+    /// The key identifier within the realm.
+    pub secret_id: u32,
+    pub key: Vec<u8>,
+    pub failure_action: FailureAction,
+}
+
+impl AuthenticationKey {
+    /// Creates the option with the key identifiers and a zero MAC.
+    pub fn option(&self, replay_detection: u64) -> Authentication {
+        let mut information = self.realm.to_owned();
+        information.push((self.secret_id >> 24) as u8);
+        information.push((self.secret_id >> 16) as u8);
+        information.push((self.secret_id >> 8) as u8);
+        information.push(self.secret_id as u8);
+        information.extend_from_slice(&[0u8; auth::SIZE_HMAC_MD5]);
+        Authentication {
+            protocol: auth::PROTOCOL_DELAYED,
+            algorithm: auth::ALGORITHM_HMAC_MD5,
+            replay_detection_method: auth::RDM_MONOTONIC,
+            replay_detection,
+            information,
+            authenticated_data: None,
+        }
+    }
+
+    /// Checks whether the option refers to the key.
+    pub fn identifies(&self, authentication: &Authentication) -> bool {
+        authentication.protocol == auth::PROTOCOL_DELAYED
+            && authentication.secret_id() == Some(self.secret_id)
+            && authentication.realm() == Some(self.realm.as_slice())
+    }
+}
+
+/// Verifies the requests and signs the responses of the subnets with the keys.
+pub struct Authenticator {
+    /// The last replay detection value sent.
+    replay_sent: u64,
+    /// The last replay detection values received, keyed by the client ID.
+    replay_received: HashMap<Vec<u8>, u64>,
+}
+
+impl Authenticator {
+    /// The replay detection counter starts from the current time,
+    /// so it keeps increasing across restarts.
+    pub fn new() -> Self {
+        Authenticator {
+            replay_sent: (Utc::now().timestamp() as u64) << 32,
+            replay_received: HashMap::new(),
+        }
+    }
+
+    /// Checks the request MAC and replay detection value.
+    ///
+    /// A `DHCPDISCOVER` carries no MAC, so it must only announce the delayed
+    /// authentication support.
+    ///
+    /// # Errors
+    /// The reason of the failure.
+    pub fn verify(
+        &mut self,
+        key: &AuthenticationKey,
+        client_id: &[u8],
+        request: &Message,
+    ) -> Result<(), &'static str> {
+        let authentication = match request.options.authentication {
+            Some(ref authentication) => authentication,
+            None => return Err("the request is not authenticated"),
+        };
+        if authentication.protocol != auth::PROTOCOL_DELAYED
+            || authentication.algorithm != auth::ALGORITHM_HMAC_MD5
+        {
+            return Err("the authentication protocol is not supported");
+        }
+        if let Some(MessageType::DhcpDiscover) = request.options.dhcp_message_type {
+            return Ok(());
+        }
+
+        if !key.identifies(authentication) {
+            return Err("the request is authenticated with an unknown key");
+        }
+        if authentication.replay_detection_method != auth::RDM_MONOTONIC {
+            return Err("the replay detection method is not supported");
+        }
+        if let Some(replay_received) = self.replay_received.get(client_id) {
+            if authentication.replay_detection <= *replay_received {
+                return Err("the request is replayed");
+            }
+        }
+        if !authentication.verify(&key.key) {
+            return Err("the request MAC is invalid");
+        }
+
+        self.replay_received
+            .insert(client_id.to_vec(), authentication.replay_detection);
+        Ok(())
+    }
+
+    /// Adds the authentication option with the MAC computed with the key to the response.
+    ///
+    /// # Errors
+    /// `io::Error` if the response cannot be serialized.
+    pub fn sign(
+        &mut self,
+        key: &AuthenticationKey,
+        response: &mut Message,
+        max_size: Option<u16>,
+    ) -> io::Result<()> {
+        self.replay_sent = self.replay_sent.wrapping_add(1);
+        response.options.authentication = Some(key.option(self.replay_sent));
+
+        // the serializer is deterministic, so the sent message will be the same
+        let mut buffer = vec![0u8; SIZE_SIGNING_BUFFER];
+        let amount = response.to_bytes(&mut buffer, max_size)?;
+        let data = match auth::authenticated_data(&buffer[..amount]) {
+            Some(data) => data,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The authentication option has not been serialized",
+                ))
+            }
+        };
+        let mac = auth::hmac_md5(&key.key, &data);
+
+        if let Some(ref mut authentication) = response.options.authentication {
+            let start = authentication.information.len() - auth::SIZE_HMAC_MD5;
+            authentication.information[start..].copy_from_slice(&mac);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use eui48::MacAddress;
+
+    use dhcp_protocol::{HardwareType, OperationCode, Options};
+
+    const CLIENT_ID: &[u8] = &[1, 0x00, 0x0c, 0x29, 0x13, 0x0e, 0x37];
+
+    fn key(realm: &[u8], secret_id: u32, key: &[u8]) -> AuthenticationKey {
+        AuthenticationKey {
+            realm: realm.to_vec(),
+            secret_id,
+            key: key.to_vec(),
+            failure_action: FailureAction::Drop,
+        }
+    }
+
+    fn request(dhcp_message_type: MessageType) -> Message {
+        let mut options = Options::default();
+        options.dhcp_message_type = Some(dhcp_message_type);
+        options.client_id = Some(CLIENT_ID.to_vec());
+
+        Message {
+            operation_code: OperationCode::BootRequest,
+            hardware_type: HardwareType::Ethernet,
+            hardware_address_length: 6,
+            hardware_options: Default::default(),
+
+            transaction_id: 1,
+            seconds: Default::default(),
+            is_broadcast: false,
+
+            client_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            your_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            server_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            gateway_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+
+            client_hardware_address: MacAddress::new([0x00, 0x0c, 0x29, 0x13, 0x0e, 0x37]),
+            server_name: Default::default(),
+            boot_filename: Default::default(),
+
+            options,
+        }
+    }
+
+    /// Signs the request with the key and parses it back as it is received.
+    fn received(signer: &mut Authenticator, key: &AuthenticationKey, relayed: bool) -> Message {
+        let mut request = request(MessageType::DhcpRequest);
+        signer.sign(key, &mut request, None).unwrap();
+        let mut buffer = vec![0u8; SIZE_SIGNING_BUFFER];
+        let amount = request.to_bytes(&mut buffer, None).unwrap();
+        if relayed {
+            // the hops and giaddr are changed by the relay agents
+            buffer[3] += 1;
+            buffer[24..28].copy_from_slice(&[10, 0, 0, 1]);
+        }
+        Message::from_bytes(&buffer[..amount]).unwrap()
+    }
+
+    #[test]
+    fn verifies_signed_requests() {
+        let subnet = key(b"realm", 42, b"secret");
+        // the key the request is signed with, whether it is relayed, and the result
+        let cases = [
+            (key(b"realm", 42, b"secret"), false, Ok(())),
+            (key(b"realm", 42, b"secret"), true, Ok(())),
+            (
+                key(b"realm", 42, b"wrong"),
+                false,
+                Err("the request MAC is invalid"),
+            ),
+            (
+                key(b"realm", 43, b"secret"),
+                false,
+                Err("the request is authenticated with an unknown key"),
+            ),
+            (
+                key(b"other", 42, b"secret"),
+                false,
+                Err("the request is authenticated with an unknown key"),
+            ),
+        ];
+        for &(ref signing, relayed, expected) in cases.iter() {
+            let request = received(&mut Authenticator::new(), signing, relayed);
+            assert_eq!(
+                Authenticator::new().verify(&subnet, CLIENT_ID, &request),
+                expected,
+                "{:?} relayed {}",
+                signing,
+                relayed
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_replayed_request() {
+        let subnet = key(b"", 1, b"secret");
+        let mut signer = Authenticator::new();
+        let mut verifier = Authenticator::new();
+
+        let first = received(&mut signer, &subnet, false);
+        let second = received(&mut signer, &subnet, false);
+
+        assert_eq!(verifier.verify(&subnet, CLIENT_ID, &second), Ok(()));
+        assert_eq!(
+            verifier.verify(&subnet, CLIENT_ID, &first),
+            Err("the request is replayed")
+        );
+        assert_eq!(
+            verifier.verify(&subnet, CLIENT_ID, &second),
+            Err("the request is replayed")
+        );
+    }
+
+    #[test]
+    fn checks_unsigned_requests() {
+        let subnet = key(b"realm", 42, b"secret");
+        let announcing = Authentication {
+            protocol: auth::PROTOCOL_DELAYED,
+            algorithm: auth::ALGORITHM_HMAC_MD5,
+            ..Default::default()
+        };
+        let unsupported = Authentication {
+            protocol: auth::PROTOCOL_RECONFIGURE_KEY,
+            algorithm: auth::ALGORITHM_HMAC_MD5,
+            ..Default::default()
+        };
+        // the request type, its authentication option, and the result
+        let cases = [
+            (MessageType::DhcpDiscover, Some(announcing.clone()), Ok(())),
+            (
+                MessageType::DhcpDiscover,
+                None,
+                Err("the request is not authenticated"),
+            ),
+            (
+                MessageType::DhcpDiscover,
+                Some(unsupported.clone()),
+                Err("the authentication protocol is not supported"),
+            ),
+            (
+                MessageType::DhcpRequest,
+                Some(announcing),
+                Err("the request is authenticated with an unknown key"),
+            ),
+            (
+                MessageType::DhcpRequest,
+                None,
+                Err("the request is not authenticated"),
+            ),
+            (
+                MessageType::DhcpRequest,
+                Some(unsupported),
+                Err("the authentication protocol is not supported"),
+            ),
+        ];
+        for (dhcp_message_type, authentication, expected) in cases.iter().cloned() {
+            let mut request = request(dhcp_message_type);
+            request.options.authentication = authentication;
+            assert_eq!(
+                Authenticator::new().verify(&subnet, CLIENT_ID, &request),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn computes_hmac_md5() {
+        // RFC 2202 §2
+        let cases: &[(&[u8], &[u8], &str)] = &[
+            (&[0x0b; 16], b"Hi There", "9294727a3638bb1c13f48ef8158bfc9d"),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "750c783e6ab0b503eaa86e310a5db738",
+            ),
+            (&[0xaa; 16], &[0xdd; 50], "56be34521d144c88dbb8c733f0e8b3f6"),
+            (
+                &[0xaa; 80],
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "6b1ab7fe4bd7bf8f0b62e6ce61b9d0cd",
+            ),
+        ];
+        for &(key, data, expected) in cases {
+            let mac: String = auth::hmac_md5(key, data)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            assert_eq!(mac, expected, "{:?}", data);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Manages the leases of a running server over its control socket.
+//!
+//! `dhcp-leasectl [--socket <path>] <command>`, the socket is `/run/rust-dhcp.sock` by default.
+//! The commands are:
+//!
+//! * `list` prints all the leases.
+//! * `search <key>=<value> ...` prints the leases matching the `/leases` admin API parameters,
+//! like `hardware_address=00:11:22:33:44:55` or `state=active`.
+//! * `delete <address>` frees the address.
+//! * `export [json|csv]` prints all the leases as JSON, by default, or CSV.
+//! * `import <file>` adds the reservations from a CSV file with a header naming
+//! the `[[reservations]]` fields, like `hardware_address,address,hostname`.
+//! The reservations are kept until the next configuration reload.
+//!
+//! Exits with 1 if a request has failed.
+
+extern crate serde_json;
+
+extern crate dhcp_server;
+
+#[cfg(unix)]
+use std::{fs, io, net::Ipv4Addr};
+
+#[cfg(unix)]
+use serde_json::{Map, Value};
+
+#[cfg(unix)]
+use dhcp_server::{ControlClient, ControlReply};
+
+#[cfg(unix)]
+const DEFAULT_SOCKET: &str = "/run/rust-dhcp.sock";
+/// The lease record fields in the CSV export order.
+#[cfg(unix)]
+const LEASE_COLUMNS: [&str; 7] = [
+    "address",
+    "state",
+    "hardware_address",
+    "client_id",
+    "lease_time",
+    "expires_at",
+    "device_type",
+];
+/// The reservation fields imported as numbers.
+#[cfg(unix)]
+const NUMERIC_COLUMNS: [&str; 4] = [
+    "default_lease_time",
+    "max_lease_time",
+    "renewal_time",
+    "rebinding_time",
+];
+
+const USAGE: &str = "Usage: dhcp-leasectl [--socket <path>] \
+                     list | search <key>=<value> ... | delete <address> | \
+                     export [json|csv] | import <file>";
+
+#[cfg(unix)]
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut socket = DEFAULT_SOCKET.to_owned();
+    if args.first().map_or(false, |arg| arg == "--socket") && args.len() > 1 {
+        args.remove(0);
+        socket = args.remove(0);
+    }
+    if args.is_empty() {
+        usage();
+    }
+    let command = args.remove(0);
+
+    let mut client = match ControlClient::connect(&socket) {
+        Ok(client) => client,
+        Err(error) => fail(&format!("Control socket {} connecting error: {}", socket, error)),
+    };
+    match (command.as_str(), args.len()) {
+        ("list", 0) => print_leases(&body(client.query_leases(&[]))),
+        ("search", count) if count > 0 => {
+            let mut query = Vec::with_capacity(count);
+            for parameter in args.iter() {
+                let mut parts = parameter.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(value)) => query.push((key, value)),
+                    _ => fail(&format!("Invalid search parameter {}", parameter)),
+                }
+            }
+            print_leases(&body(client.query_leases(&query)));
+        }
+        ("delete", 1) => {
+            let address: Ipv4Addr = match args[0].parse() {
+                Ok(address) => address,
+                Err(_) => fail(&format!("Invalid address {}", args[0])),
+            };
+            println!("{}", body(client.delete_lease(address)));
+        }
+        ("export", count) if count <= 1 => {
+            let leases = body(client.query_leases(&[]));
+            match args.first().map(String::as_str) {
+                None | Some("json") => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&leases).unwrap_or_default()
+                ),
+                Some("csv") => print_csv(&leases),
+                Some(format) => fail(&format!("Unknown export format {}", format)),
+            }
+        }
+        ("import", 1) => import(&mut client, &args[0]),
+        _ => usage(),
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("The control socket is supported only on Unix\n{}", USAGE);
+    std::process::exit(2);
+}
+
+/// Returns the reply body or exits if the request has failed.
+#[cfg(unix)]
+fn body(reply: io::Result<ControlReply>) -> Value {
+    match reply {
+        Ok(ref reply) if reply.is_success() => reply.body.to_owned(),
+        Ok(reply) => fail(&reply.body.to_string()),
+        Err(error) => fail(&format!("Control socket request error: {}", error)),
+    }
+}
+
+/// Prints a lease per line.
+#[cfg(unix)]
+fn print_leases(leases: &Value) {
+    let leases = leases.as_array().map(Vec::as_slice).unwrap_or(&[]);
+    for lease in leases.iter() {
+        println!(
+            "{:<15} {:<8} {:<17} {}",
+            field(lease, "address"),
+            field(lease, "state"),
+            field(lease, "hardware_address"),
+            field(lease, "client_id"),
+        );
+    }
+    println!("{} leases", leases.len());
+}
+
+/// Prints the leases as CSV with a header.
+#[cfg(unix)]
+fn print_csv(leases: &Value) {
+    println!("{}", LEASE_COLUMNS.join(","));
+    for lease in leases.as_array().into_iter().flat_map(|leases| leases.iter()) {
+        let fields: Vec<String> = LEASE_COLUMNS
+            .iter()
+            .map(|column| csv_escape(&field(lease, column)))
+            .collect();
+        println!("{}", fields.join(","));
+    }
+}
+
+/// Adds the reservations from the CSV file, reporting the rejected ones.
+#[cfg(unix)]
+fn import(client: &mut ControlClient, path: &str) {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(error) => fail(&format!("File {} reading error: {}", path, error)),
+    };
+    let mut lines = data
+        .lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty());
+    let header = match lines.next() {
+        Some((_, header)) => csv_split(header),
+        None => fail(&format!("File {} is empty", path)),
+    };
+
+    let (mut imported, mut rejected) = (0, 0);
+    for (index, line) in lines {
+        let mut reservation = Map::new();
+        for (column, value) in header.iter().zip(csv_split(line)) {
+            if value.is_empty() {
+                continue;
+            }
+            let value = if NUMERIC_COLUMNS.contains(&column.as_str()) {
+                match value.parse::<u32>() {
+                    Ok(number) => Value::from(number),
+                    Err(_) => Value::String(value),
+                }
+            } else {
+                Value::String(value)
+            };
+            reservation.insert(column.to_owned(), value);
+        }
+        match client.add_reservation(&Value::Object(reservation)) {
+            Ok(ref reply) if reply.is_success() => imported += 1,
+            Ok(reply) => {
+                eprintln!("Line {}: {}", index + 1, reply.body);
+                rejected += 1;
+            }
+            Err(error) => fail(&format!("Control socket request error: {}", error)),
+        }
+    }
+    println!("{} reservations imported, {} rejected", imported, rejected);
+    if rejected > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// The lease field as text, empty if it is not set.
+#[cfg(unix)]
+fn field(lease: &Value, name: &str) -> String {
+    match lease.get(name) {
+        Some(&Value::String(ref value)) => value.to_owned(),
+        Some(&Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Splits the CSV line into the fields, unquoting them.
+#[cfg(unix)]
+fn csv_split(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut is_quoted = false;
+    let mut chars = line.trim_right_matches('\r').chars().peekable();
+    while let Some(character) = chars.next() {
+        match character {
+            '"' if is_quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => is_quoted = !is_quoted,
+            ',' if !is_quoted => fields.push(field.split_off(0).trim().to_owned()),
+            character => field.push(character),
+        }
+    }
+    fields.push(field.trim().to_owned());
+    fields
+}
+
+/// Quotes the CSV field if it contains a separator or a quote.
+#[cfg(unix)]
+fn csv_escape(field: &str) -> String {
+    if field.contains(',') || field.contains('"') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(unix)]
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+#[cfg(unix)]
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,75 @@
+//! The network boot parameters selected by the client architecture.
+
+use std::net::Ipv4Addr;
+
+use dhcp_protocol::Message;
+
+/// The client system architecture types (RFC 4578 §2.1 and the IANA registry).
+pub const ARCHITECTURE_X86_BIOS: u16 = 0;
+pub const ARCHITECTURE_EFI_IA32: u16 = 6;
+/// Registered as `EFI BC`, but sent by the most x64 UEFI firmwares.
+pub const ARCHITECTURE_EFI_BC: u16 = 7;
+pub const ARCHITECTURE_EFI_X86_64: u16 = 9;
+pub const ARCHITECTURE_EFI_ARM64: u16 = 11;
+
+/// The vendor class identifier prefix of the PXE clients, also sent back by the ProxyDHCP servers.
+pub const PXE_CLIENT_CLASS: &[u8] = b"PXEClient";
+/// The `PXE_DISCOVERY_CONTROL` vendor option making the client download the boot file
+/// from the offer instead of discovering the boot servers.
+pub const PXE_VENDOR_OPTIONS: &[u8] = &[6, 1, 8, 255];
+
+/// The parameters sent to the booting clients.
+#[derive(Debug, Clone, Default)]
+pub struct BootParameters {
+    /// Sent in the `siaddr` field.
+    pub next_server: Option<Ipv4Addr>,
+    /// Sent in the `sname` field and the `server_name` option.
+    pub server_name: Option<String>,
+    /// Sent in the `file` field and the `bootfile_name` option.
+    pub boot_filename: Option<String>,
+}
+
+/// The boot parameters with the overrides for particular client architectures.
+#[derive(Debug, Clone, Default)]
+pub struct Boot {
+    /// Used for the clients of the other architectures and those not sending one.
+    pub default: BootParameters,
+    /// The parameters set in an override replace the default ones.
+    pub overrides: Vec<(u16, BootParameters)>,
+}
+
+impl Boot {
+    /// Selects the parameters for the architectures sent by the client in the order of preference.
+    pub fn select(&self, architectures: Option<&Vec<u16>>) -> BootParameters {
+        let mut parameters = self.default.to_owned();
+        let found = architectures.and_then(|architectures| {
+            architectures.iter().filter_map(|architecture| {
+                self.overrides
+                    .iter()
+                    .find(|&&(code, _)| code == *architecture)
+                    .map(|&(_, ref parameters)| parameters)
+            }).next()
+        });
+        if let Some(found) = found {
+            if found.next_server.is_some() {
+                parameters.next_server = found.next_server;
+            }
+            if found.server_name.is_some() {
+                parameters.server_name = found.server_name.to_owned();
+            }
+            if found.boot_filename.is_some() {
+                parameters.boot_filename = found.boot_filename.to_owned();
+            }
+        }
+        parameters
+    }
+}
+
+/// Checks whether the request is sent by a PXE boot ROM.
+pub fn is_pxe_client(request: &Message) -> bool {
+    request
+        .options
+        .class_id
+        .as_ref()
+        .map_or(false, |class_id| class_id.starts_with(PXE_CLIENT_CLASS))
+}
@@ -6,15 +6,16 @@ use std::{
     net::Ipv4Addr,
 };
 
-use eui48::{EUI48LEN, MacAddress};
+use eui48::MacAddress;
 use futures_cpupool::CpuPool;
 use ifcontrol::{self, Iface};
 use netif_bpf::Bpf;
 
-use dhcp_protocol::{Message, DHCP_PORT_CLIENT, DHCP_PORT_SERVER};
+use dhcp_protocol::Message;
+
+use frame::ethernet_packet;
 
 const DEFAULT_BPF_NUM_THREADS_SIZE: usize = 4;
-const DEFAULT_IP_TTL: u8 = 64;
 const DEFAULT_PACKET_BUFFER_SIZE: usize = 8192;
 
 pub struct BpfData {
@@ -87,7 +88,7 @@ impl BpfData {
 
         let mut payload = vec![0u8; DEFAULT_PACKET_BUFFER_SIZE];
         let amount = message.to_bytes(payload.as_mut(), max_size)?;
-        let packet = Self::ethernet_packet(
+        let packet = ethernet_packet(
             self.iface_hw_addr.to_owned(),
             message.client_hardware_address.to_owned(),
             source.to_owned(),
@@ -110,30 +111,4 @@ impl BpfData {
 
         Ok(())
     }
-
-    /// Constructs a multi-layer DHCP packet for BPF communication.
-    fn ethernet_packet(
-        src_mac: MacAddress,
-        dst_mac: MacAddress,
-        src_ip: Ipv4Addr,
-        dst_ip: Ipv4Addr,
-        payload: &[u8],
-    ) -> io::Result<Vec<u8>> {
-        use etherparse::{PacketBuilder, WriteError};
-
-        let builder = PacketBuilder::ethernet2(
-            *array_ref!(src_mac.as_bytes(), 0, EUI48LEN),
-            *array_ref!(dst_mac.as_bytes(), 0, EUI48LEN),
-        ).ipv4(src_ip.octets(), dst_ip.octets(), DEFAULT_IP_TTL)
-            .udp(DHCP_PORT_SERVER, DHCP_PORT_CLIENT);
-
-        let mut result = Vec::<u8>::with_capacity(builder.size(payload.len()));
-        match builder.write(&mut result, payload) {
-            Ok(_) => Ok(result),
-            Err(WriteError::IoError(error)) => Err(error),
-            Err(WriteError::ValueError(error)) => {
-                Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", error)))
-            }
-        }
-    }
 }
@@ -2,9 +2,14 @@
 
 use std::net::Ipv4Addr;
 
+use eui48::{MacAddress, EUI48LEN};
+
 use dhcp_protocol::*;
 
+use boot::{BootParameters, PXE_CLIENT_CLASS, PXE_VENDOR_OPTIONS};
 use database::{Ack, Error, Offer};
+use reservation::Reservation;
+use subnet::Subnet;
 
 /// Builds common server messages with some parameters.
 pub struct MessageBuilder {
@@ -22,6 +27,8 @@ pub struct MessageBuilder {
     static_routes: Vec<(Ipv4Addr, Ipv4Addr)>,
     /// Sent to clients in options.
     classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    /// Sent to clients in options as is.
+    raw_options: Vec<(u8, Vec<u8>)>,
 }
 
 impl MessageBuilder {
@@ -45,9 +52,31 @@ impl MessageBuilder {
             domain_name_servers,
             static_routes,
             classless_static_routes,
+            raw_options: Vec::new(),
         }
     }
 
+    /// Replaces the network parameters of a running server.
+    pub fn set_parameters(
+        &mut self,
+        subnet_mask: Ipv4Addr,
+        routers: Vec<Ipv4Addr>,
+        domain_name_servers: Vec<Ipv4Addr>,
+        static_routes: Vec<(Ipv4Addr, Ipv4Addr)>,
+        classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    ) {
+        self.subnet_mask = subnet_mask;
+        self.routers = routers;
+        self.domain_name_servers = domain_name_servers;
+        self.static_routes = static_routes;
+        self.classless_static_routes = classless_static_routes;
+    }
+
+    /// Sets the options sent as is if requested.
+    pub fn set_raw_options(&mut self, raw_options: Vec<(u8, Vec<u8>)>) {
+        self.raw_options = raw_options;
+    }
+
     /// Creates a `DHCPOFFER` message from a `DHCPDISCOVER` message.
     pub fn dhcp_discover_to_offer(&self, discover: &Message, offer: &Offer) -> Message {
         let mut options = Options::default();
@@ -59,6 +88,13 @@ impl MessageBuilder {
         options.dhcp_message_type = Some(MessageType::DhcpOffer);
         options.dhcp_message = Some(offer.message.to_owned());
         options.address_time = Some(offer.lease_time);
+        /*
+        RFC 3046 §2.2
+        DHCP servers claiming to support the Relay Agent Information option
+        SHALL echo the entire contents of the Relay Agent Information option
+        in all replies.
+        */
+        options.relay_agent_information = discover.options.relay_agent_information.to_owned();
 
         Message {
             operation_code: OperationCode::BootReply,
@@ -96,6 +132,7 @@ impl MessageBuilder {
         options.address_time = Some(ack.lease_time);
         options.renewal_time = Some(ack.renewal_time);
         options.rebinding_time = Some(ack.rebinding_time);
+        options.relay_agent_information = request.options.relay_agent_information.to_owned();
 
         Message {
             operation_code: OperationCode::BootReply,
@@ -121,6 +158,9 @@ impl MessageBuilder {
     }
 
     /// Creates a `DHCPACK` message from a `DHCPINFORM` message.
+    ///
+    /// Carries only the configuration parameters, since the client
+    /// has obtained its address by other means.
     pub fn dhcp_inform_to_ack(&self, inform: &Message, message: &str) -> Message {
         let mut options = Options::default();
         self.append_default_options(&mut options);
@@ -130,6 +170,7 @@ impl MessageBuilder {
 
         options.dhcp_message_type = Some(MessageType::DhcpAck);
         options.dhcp_message = Some(message.to_owned());
+        options.relay_agent_information = inform.options.relay_agent_information.to_owned();
 
         Message {
             operation_code: OperationCode::BootReply,
@@ -154,6 +195,115 @@ impl MessageBuilder {
         }
     }
 
+    /// Creates a `DHCPLEASEACTIVE`, `DHCPLEASEUNASSIGNED` or `DHCPLEASEUNKNOWN` message
+    /// from a `DHCPLEASEQUERY` message.
+    ///
+    /// The lease parameters are filled by the caller.
+    pub fn dhcp_leasequery_to_response(
+        &self,
+        query: &Message,
+        dhcp_message_type: MessageType,
+    ) -> Message {
+        let mut options = Options::default();
+        options.dhcp_message_type = Some(dhcp_message_type);
+        options.dhcp_server_id = Some(self.server_ip_address);
+        options.client_id = query.options.client_id.to_owned();
+
+        Message {
+            operation_code: OperationCode::BootReply,
+            hardware_type: query.hardware_type,
+            hardware_address_length: query.hardware_address_length,
+            hardware_options: Default::default(),
+
+            transaction_id: query.transaction_id,
+            seconds: Default::default(),
+            is_broadcast: false,
+
+            client_ip_address: query.client_ip_address,
+            your_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            server_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            gateway_ip_address: query.gateway_ip_address,
+
+            client_hardware_address: query.client_hardware_address,
+            server_name: Default::default(),
+            boot_filename: Default::default(),
+
+            options,
+        }
+    }
+
+    /// Creates a `BOOTREPLY` message from a plain BOOTP request.
+    ///
+    /// The BOOTP messages have no DHCP message type, since the BOOTP clients drop
+    /// the unknown ones, and the lease time, since the address is bound forever.
+    pub fn bootp_request_to_reply(&self, request: &Message, address: Ipv4Addr) -> Message {
+        let mut options = Options::default();
+        self.append_default_options(&mut options);
+        if let Some(ref parameter_list) = request.options.parameter_list {
+            self.append_requested_options(&mut options, parameter_list);
+        }
+        options.relay_agent_information = request.options.relay_agent_information.to_owned();
+
+        Message {
+            operation_code: OperationCode::BootReply,
+            hardware_type: HardwareType::Ethernet,
+            hardware_address_length: request.hardware_address_length,
+            hardware_options: Default::default(),
+
+            transaction_id: request.transaction_id,
+            seconds: Default::default(),
+            is_broadcast: request.is_broadcast,
+
+            client_ip_address: request.client_ip_address,
+            your_ip_address: address,
+            server_ip_address: self.server_ip_address,
+            gateway_ip_address: request.gateway_ip_address,
+
+            client_hardware_address: request.client_hardware_address,
+            server_name: Default::default(),
+            boot_filename: Default::default(),
+
+            options,
+        }
+    }
+
+    /// Creates a ProxyDHCP `DHCPOFFER` or `DHCPACK` message without an address.
+    ///
+    /// The address is assigned by another server, so the message carries
+    /// only the boot parameters applied afterwards.
+    pub fn proxy_response(&self, request: &Message, dhcp_message_type: MessageType) -> Message {
+        let mut options = Options::default();
+        options.dhcp_message_type = Some(dhcp_message_type);
+        options.dhcp_server_id = Some(self.server_ip_address);
+        options.class_id = Some(PXE_CLIENT_CLASS.to_vec());
+        options.vendor_specific = Some(PXE_VENDOR_OPTIONS.to_vec());
+        // the PXE clients drop the replies without their machine ID
+        options.client_machine_id = request.options.client_machine_id.to_owned();
+        options.relay_agent_information = request.options.relay_agent_information.to_owned();
+
+        Message {
+            operation_code: OperationCode::BootReply,
+            hardware_type: HardwareType::Ethernet,
+            hardware_address_length: request.hardware_address_length,
+            hardware_options: Default::default(),
+
+            transaction_id: request.transaction_id,
+            seconds: Default::default(),
+            is_broadcast: request.is_broadcast,
+
+            client_ip_address: request.client_ip_address,
+            your_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            server_ip_address: self.server_ip_address,
+            gateway_ip_address: request.gateway_ip_address,
+
+            client_hardware_address: request.client_hardware_address,
+            server_name: Default::default(),
+            boot_filename: Default::default(),
+
+            options,
+        }
+    }
+
     /// Creates a `DHCPNAK` message from a `DHCPREQUEST` message.
     pub fn dhcp_request_to_nak(&self, request: &Message, error: &Error) -> Message {
         let mut options = Options::default();
@@ -161,6 +311,7 @@ impl MessageBuilder {
 
         options.dhcp_message_type = Some(MessageType::DhcpNak);
         options.dhcp_message = Some(error.to_string());
+        options.relay_agent_information = request.options.relay_agent_information.to_owned();
 
         Message {
             operation_code: OperationCode::BootReply,
@@ -185,6 +336,209 @@ impl MessageBuilder {
         }
     }
 
+    /// Creates a `DHCPFORCERENEW` message making the bound client renew its lease (RFC 3203).
+    pub fn dhcp_forcerenew(
+        &self,
+        client_id: &[u8],
+        address: Ipv4Addr,
+        hardware_address: MacAddress,
+        transaction_id: u32,
+    ) -> Message {
+        let mut options = Options::default();
+        self.append_default_options(&mut options);
+
+        options.dhcp_message_type = Some(MessageType::DhcpForceRenew);
+        options.client_id = Some(client_id.to_vec());
+
+        Message {
+            operation_code: OperationCode::BootReply,
+            hardware_type: HardwareType::Ethernet,
+            hardware_address_length: EUI48LEN as u8,
+            hardware_options: Default::default(),
+
+            transaction_id,
+            seconds: Default::default(),
+            is_broadcast: false,
+
+            client_ip_address: address,
+            your_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            server_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            gateway_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+
+            client_hardware_address: hardware_address,
+            server_name: Default::default(),
+            boot_filename: Default::default(),
+
+            options,
+        }
+    }
+
+    /// Fills the `siaddr`, `sname` and `file` fields of a `DHCPOFFER` or `DHCPACK` message.
+    ///
+    /// The `server_name` and `bootfile_name` options are also sent if the client requests them
+    /// or if the values do not fit the fields.
+    pub fn apply_boot(&self, request: &Message, message: &mut Message, boot: &BootParameters) {
+        let is_requested = |tag: OptionTag| {
+            request
+                .options
+                .parameter_list
+                .as_ref()
+                .map_or(false, |parameter_list| parameter_list.contains(&(tag as u8)))
+        };
+        if let Some(next_server) = boot.next_server {
+            message.server_ip_address = next_server;
+        }
+        if let Some(ref server_name) = boot.server_name {
+            let fits = server_name.len() <= SIZE_SERVER_NAME;
+            if fits {
+                message.server_name = server_name.as_bytes().to_vec();
+            }
+            if !fits || is_requested(OptionTag::ServerName) {
+                message.options.server_name = Some(server_name.to_owned());
+            }
+        }
+        if let Some(ref boot_filename) = boot.boot_filename {
+            let fits = boot_filename.len() <= SIZE_BOOT_FILENAME;
+            if fits {
+                message.boot_filename = boot_filename.as_bytes().to_vec();
+            }
+            if !fits || is_requested(OptionTag::BootfileName) {
+                message.options.bootfile_name = Some(boot_filename.to_owned());
+            }
+        }
+    }
+
+    /// Applies the per-host overrides to a `DHCPOFFER` or `DHCPACK` message.
+    pub fn apply_reservation(&self, message: &mut Message, reservation: &Reservation) {
+        if let Some(ref hostname) = reservation.hostname {
+            message.options.hostname = Some(hostname.to_owned());
+        }
+        if let Some(ref boot_filename) = reservation.boot_filename {
+            if boot_filename.len() <= SIZE_BOOT_FILENAME {
+                message.boot_filename = boot_filename.as_bytes().to_vec();
+                // replaces the one set by the boot parameters
+                if message.options.bootfile_name.is_some() {
+                    message.options.bootfile_name = Some(boot_filename.to_owned());
+                }
+            } else {
+                message.boot_filename = Vec::new();
+                message.options.bootfile_name = Some(boot_filename.to_owned());
+            }
+        }
+    }
+
+    /// Replaces the server-wide `subnet_mask` and `routers` options with the subnet ones.
+    pub fn apply_subnet(&self, request: &Message, message: &mut Message, subnet: &Subnet) {
+        let parameter_list = match request.options.parameter_list {
+            Some(ref parameter_list) => parameter_list,
+            None => return,
+        };
+        if parameter_list.contains(&(OptionTag::SubnetMask as u8)) {
+            message.options.subnet_mask = Some(subnet.mask);
+        }
+        if parameter_list.contains(&(OptionTag::Routers as u8))
+            && message.options.classless_static_routes.is_none()
+            && !subnet.routers.is_empty()
+        {
+            message.options.routers = Some(subnet.routers.to_owned());
+        }
+    }
+
+    /// Replaces the routes with the subnet or class ones if there are any.
+    ///
+    /// The routes are sent as the RFC 3442 option or as its pre-standard code 249
+    /// to the clients requesting only the latter, without the `routers`. The clients
+    /// requesting neither get the classful routes in the legacy `static_routes` option.
+    pub fn apply_routes(
+        &self,
+        request: &Message,
+        message: &mut Message,
+        routes: &[(Ipv4Addr, Ipv4Addr, Ipv4Addr)],
+    ) {
+        let parameter_list = match request.options.parameter_list {
+            Some(ref parameter_list) => parameter_list,
+            None => return,
+        };
+        if routes.is_empty() {
+            return;
+        }
+
+        let options = &mut message.options;
+        options.classless_static_routes = None;
+        options.static_routes = None;
+        options
+            .raw
+            .retain(|&(tag, _)| tag != MICROSOFT_CLASSLESS_STATIC_ROUTES);
+        if parameter_list.contains(&(OptionTag::ClasslessStaticRoutes as u8)) {
+            options.classless_static_routes = Some(routes.to_vec());
+            options.routers = None;
+        } else if parameter_list.contains(&MICROSOFT_CLASSLESS_STATIC_ROUTES) {
+            options.raw.push((
+                MICROSOFT_CLASSLESS_STATIC_ROUTES,
+                encode_classless_routes(routes),
+            ));
+            options.routers = None;
+        } else if parameter_list.contains(&(OptionTag::StaticRoutes as u8)) {
+            let classful: Vec<(Ipv4Addr, Ipv4Addr)> = routes
+                .iter()
+                .filter(|&&(destination, mask, _)| is_classful(destination, mask))
+                .map(|&(destination, _, router)| (destination, router))
+                .collect();
+            if !classful.is_empty() {
+                options.static_routes = Some(classful);
+            }
+        }
+    }
+
+    /// The server-wide options the scopes inherit, with the `domain_name_servers`
+    /// encoded like the raw ones.
+    pub fn global_options(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut options = Vec::with_capacity(self.raw_options.len() + 1);
+        if !self.domain_name_servers.is_empty() {
+            let data = self
+                .domain_name_servers
+                .iter()
+                .flat_map(|address| address.octets().to_vec())
+                .collect();
+            options.push((OptionTag::DomainNameServers as u8, data));
+        }
+        options.extend(self.raw_options.iter().cloned());
+        options
+    }
+
+    /// Replaces the options requested by the client with the resolved scope ones.
+    pub fn apply_options(
+        &self,
+        request: &Message,
+        message: &mut Message,
+        options: &[(u8, Vec<u8>)],
+    ) {
+        let parameter_list = match request.options.parameter_list {
+            Some(ref parameter_list) => parameter_list,
+            None => return,
+        };
+        for &(tag, ref data) in options.iter() {
+            if !parameter_list.contains(&tag) {
+                continue;
+            }
+            if tag == OptionTag::DomainNameServers as u8 {
+                let addresses = data
+                    .chunks(4)
+                    .filter(|octets| octets.len() == 4)
+                    .map(|octets| Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+                    .collect();
+                message.options.domain_name_servers = Some(addresses);
+                continue;
+            }
+            if tag == OptionTag::VendorSpecific as u8 {
+                message.options.vendor_specific = Some(data.to_owned());
+                continue;
+            }
+            message.options.raw.retain(|&(raw_tag, _)| raw_tag != tag);
+            message.options.raw.push((tag, data.to_owned()));
+        }
+    }
+
     fn append_default_options(&self, options: &mut Options) {
         options.hostname = self.hostname.to_owned();
         options.dhcp_server_id = Some(self.server_ip_address);
@@ -230,8 +584,26 @@ impl MessageBuilder {
                     options.static_routes = Some(self.static_routes.to_owned())
                 },
 
-                _ => continue,
+                _ => {
+                    for &(raw_tag, ref data) in self.raw_options.iter() {
+                        if raw_tag == *tag {
+                            options.raw.push((raw_tag, data.to_owned()));
+                        }
+                    }
+                }
             }
         }
     }
 }
+
+/// Checks whether the option 33 route is expressible, i.e. the mask is the classful one
+/// of the destination, which is not the default route (RFC 2132 §5.8).
+fn is_classful(destination: Ipv4Addr, mask: Ipv4Addr) -> bool {
+    let classful = match destination.octets()[0] {
+        1...127 => 8,
+        128...191 => 16,
+        192...223 => 24,
+        _ => return false,
+    };
+    u32::from(mask).count_ones() == classful
+}
@@ -0,0 +1,215 @@
+//! The bulk leasequery service streaming the lease database over TCP (RFC 6926).
+//!
+//! Every message is preceded by its length as a 16-bit big-endian integer.
+//! The requesters, usually the relay agents recovering their state after a reboot,
+//! send `DHCPBULKLEASEQUERY` messages and get the matching leases as `DHCPLEASEACTIVE`
+//! and `DHCPLEASEUNASSIGNED` messages followed by a `DHCPLEASEQUERYDONE` one.
+
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
+
+use bytes::{BufMut, BytesMut};
+use tokio::{
+    codec::{Decoder, Encoder, Framed},
+    io,
+    net::{tcp::Incoming, TcpListener, TcpStream},
+    prelude::*,
+};
+
+use dhcp_protocol::Message;
+
+/// The size of the message length prefix in bytes.
+const SIZE_LENGTH: usize = 2;
+/// The maximal message size in bytes, limited by the 16-bit length prefix.
+const SIZE_MESSAGE_MAX: usize = 0xffff;
+
+/// Frames the messages with their length prefixes (RFC 6926 §7.1).
+pub struct MessageCodec;
+
+impl Decoder for MessageCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Message>> {
+        if src.len() < SIZE_LENGTH {
+            return Ok(None);
+        }
+        let len = ((src[0] as usize) << 8) | (src[1] as usize);
+        if src.len() < SIZE_LENGTH + len {
+            src.reserve(SIZE_LENGTH + len - src.len());
+            return Ok(None);
+        }
+        src.advance(SIZE_LENGTH);
+        let data = src.split_to(len);
+        Message::from_bytes(&data).map(Some)
+    }
+}
+
+impl Encoder for MessageCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Message, dst: &mut BytesMut) -> io::Result<()> {
+        let mut buffer = vec![0u8; SIZE_MESSAGE_MAX];
+        let len = item.to_bytes(&mut buffer, None)?;
+        dst.reserve(SIZE_LENGTH + len);
+        dst.put_u16_be(len as u16);
+        dst.put_slice(&buffer[..len]);
+        Ok(())
+    }
+}
+
+/// A connection of a requester.
+struct Connection {
+    id: usize,
+    peer: SocketAddr,
+    stream: Framed<TcpStream, MessageCodec>,
+    /// The responses waiting for the connection to accept them.
+    outgoing: VecDeque<Message>,
+}
+
+/// The listener of the bulk leasequery connections.
+pub struct BulkLeasequery {
+    listener: Incoming,
+    /// The requesters allowed to connect, everyone is allowed if empty.
+    requesters: Vec<Ipv4Addr>,
+    connections: Vec<Connection>,
+    next_id: usize,
+}
+
+impl BulkLeasequery {
+    /// Starts listening for the requesters.
+    ///
+    /// # Errors
+    /// `io::Error` if the address cannot be bound.
+    pub fn new(address: SocketAddr, requesters: Vec<Ipv4Addr>) -> io::Result<Self> {
+        Ok(BulkLeasequery {
+            listener: TcpListener::bind(&address)?.incoming(),
+            requesters,
+            connections: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Returns the next query with the ID of its connection, accepting the new connections.
+    pub fn poll_query(&mut self) -> Option<(usize, Message)> {
+        self.poll_accept();
+        self.flush();
+
+        let mut index = 0;
+        while index < self.connections.len() {
+            let result = self.connections[index].stream.poll();
+            match result {
+                Ok(Async::Ready(Some(query))) => {
+                    let connection = &self.connections[index];
+                    debug!("Received a bulk leasequery from {}", connection.peer);
+                    return Some((connection.id, query));
+                }
+                Ok(Async::Ready(None)) => {
+                    debug!("The requester {} has disconnected", self.connections[index].peer);
+                    self.connections.remove(index);
+                }
+                Ok(Async::NotReady) => index += 1,
+                Err(error) => {
+                    warn!(
+                        "Bulk leasequery receiving error from {}: {}",
+                        self.connections[index].peer, error
+                    );
+                    self.connections.remove(index);
+                }
+            }
+        }
+        None
+    }
+
+    /// Queues the responses to a query received on the connection.
+    ///
+    /// The responses are dropped if the requester has already disconnected.
+    pub fn respond(&mut self, id: usize, responses: Vec<Message>) {
+        if let Some(connection) = self
+            .connections
+            .iter_mut()
+            .find(|connection| connection.id == id)
+        {
+            connection.outgoing.extend(responses);
+        }
+        self.flush();
+    }
+
+    fn poll_accept(&mut self) {
+        loop {
+            let stream = match self.listener.poll() {
+                Ok(Async::Ready(Some(stream))) => stream,
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => return,
+                Err(error) => {
+                    warn!("Bulk leasequery accepting error: {}", error);
+                    return;
+                }
+            };
+            let peer = match stream.peer_addr() {
+                Ok(peer) => peer,
+                Err(error) => {
+                    warn!("Bulk leasequery accepting error: {}", error);
+                    continue;
+                }
+            };
+            let is_allowed = match peer.ip() {
+                IpAddr::V4(ref address) => {
+                    self.requesters.is_empty() || self.requesters.contains(address)
+                }
+                IpAddr::V6(_) => self.requesters.is_empty(),
+            };
+            if !is_allowed {
+                warn!("Rejecting the bulk leasequery requester {}", peer);
+                continue;
+            }
+            info!("The bulk leasequery requester {} has connected", peer);
+            self.connections.push(Connection {
+                id: self.next_id,
+                peer,
+                stream: Framed::new(stream, MessageCodec),
+                outgoing: VecDeque::new(),
+            });
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+    }
+
+    /// Moves the queued responses to the connections until they are full.
+    fn flush(&mut self) {
+        let mut index = 0;
+        while index < self.connections.len() {
+            let result = {
+                let connection = &mut self.connections[index];
+                let stream = &mut connection.stream;
+                let outgoing = &mut connection.outgoing;
+                let mut result = Ok(());
+                while let Some(message) = outgoing.pop_front() {
+                    match stream.start_send(message) {
+                        Ok(AsyncSink::Ready) => {}
+                        Ok(AsyncSink::NotReady(message)) => {
+                            outgoing.push_front(message);
+                            break;
+                        }
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
+                }
+                result.and_then(|_| stream.poll_complete().map(|_| ()))
+            };
+            match result {
+                Ok(()) => index += 1,
+                Err(error) => {
+                    warn!(
+                        "Bulk leasequery sending error to {}: {}",
+                        self.connections[index].peer, error
+                    );
+                    self.connections.remove(index);
+                }
+            }
+        }
+    }
+}
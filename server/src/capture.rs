@@ -0,0 +1,259 @@
+//! The pcapng capture of the DHCP messages received and sent by the server
+//! for the dispute resolution and the interoperability debugging.
+//!
+//! The messages are wrapped into the synthesized IPv4 and UDP headers, so the files
+//! open in Wireshark as the raw IP captures. The received messages are serialized
+//! again after parsing, so their option order and padding may differ from the wire.
+//!
+//! The files are named like `dhcp-20180801T120000.000.pcapng` and rotated when they exceed
+//! the size or the age limit, the oldest ones beyond the file limit are deleted.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use bytes::{BufMut, BytesMut};
+use chrono::Utc;
+
+use dhcp_framed::{BufferPool, PoolStats};
+use dhcp_protocol::Message;
+
+/// The bytes a file is rotated at by default.
+pub const DEFAULT_CAPTURE_MAX_SIZE: u64 = 100 * 1024 * 1024;
+/// The seconds a file is rotated after by default.
+pub const DEFAULT_CAPTURE_MAX_AGE: u64 = 24 * 60 * 60;
+/// The newest files kept by default.
+pub const DEFAULT_CAPTURE_MAX_FILES: usize = 30;
+
+/// The buffer size used to serialize a message.
+const SIZE_MESSAGE_BUFFER: usize = 8192;
+/// The packet and the block buffers are taken for each message.
+const POOL_SIZE: usize = 2;
+/// Holds the largest block with a packet of a message.
+const POOL_BUFFER_CAPACITY: usize = SIZE_MESSAGE_BUFFER + 64;
+/// `LINKTYPE_RAW`, the packets begin with the IPv4 header.
+const LINKTYPE_RAW: u16 = 101;
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const SIZE_IPV4_HEADER: usize = 20;
+const SIZE_UDP_HEADER: usize = 8;
+const PROTOCOL_UDP: u8 = 17;
+const IPV4_TTL: u8 = 64;
+
+/// Writes the messages to the rotated capture files.
+pub struct Capture {
+    directory: PathBuf,
+    /// The size in bytes a file is rotated at.
+    max_size: u64,
+    /// The age a file is rotated at.
+    max_age: Duration,
+    /// The number of the files kept, including the current one.
+    max_files: usize,
+    /// The current file with its size and the time it has been created.
+    file: Option<(BufWriter<File>, u64, Instant)>,
+    /// The messages are serialized into it.
+    payload: Vec<u8>,
+    /// The packets and blocks are built in the reused buffers.
+    pool: BufferPool,
+}
+
+impl Capture {
+    /// Creates the directory if it does not exist, the first file is created
+    /// with the first message.
+    ///
+    /// # Errors
+    /// `io::Error` if the directory cannot be created.
+    pub fn new(
+        directory: PathBuf,
+        max_size: u64,
+        max_age: Duration,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        Ok(Capture {
+            directory,
+            max_size,
+            max_age,
+            max_files,
+            file: None,
+            payload: vec![0u8; SIZE_MESSAGE_BUFFER],
+            pool: BufferPool::new(POOL_BUFFER_CAPACITY, POOL_SIZE),
+        })
+    }
+
+    /// Appends the message sent between the addresses.
+    ///
+    /// # Errors
+    /// `io::Error` if the message cannot be serialized or written.
+    pub fn record(
+        &mut self,
+        source: &SocketAddr,
+        destination: &SocketAddr,
+        message: &Message,
+        max_size: Option<u16>,
+    ) -> io::Result<()> {
+        let amount = message.to_bytes(&mut self.payload, max_size)?;
+
+        let is_rotated = match self.file {
+            Some((_, size, created_at)) => {
+                size >= self.max_size || created_at.elapsed() >= self.max_age
+            }
+            None => true,
+        };
+        if is_rotated {
+            self.rotate()?;
+        }
+        let mut packet = self.pool.get();
+        ipv4_packet(source, destination, &self.payload[..amount], &mut packet);
+        let mut block = self.pool.get();
+        enhanced_packet_block(&packet, &mut block);
+        let result = match self.file {
+            Some((ref mut file, ref mut size, _)) => {
+                let result = file.write_all(&block).and_then(|_| file.flush());
+                if result.is_ok() {
+                    *size += block.len() as u64;
+                }
+                result
+            }
+            None => Ok(()),
+        };
+        self.pool.put(packet);
+        self.pool.put(block);
+        result
+    }
+
+    /// The counters of the packet buffer pool.
+    pub fn buffer_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// Starts a new file and deletes the oldest ones.
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some((mut file, _, _)) = self.file.take() {
+            file.flush()?;
+        }
+        let name = format!("dhcp-{}.pcapng", Utc::now().format("%Y%m%dT%H%M%S%.3f"));
+        let mut file = BufWriter::new(File::create(self.directory.join(name))?);
+        let mut header = section_header_block();
+        header.extend(interface_description_block());
+        file.write_all(&header)?;
+        self.file = Some((file, header.len() as u64, Instant::now()));
+
+        // the names are ordered by the creation time
+        let mut names: Vec<PathBuf> = fs::read_dir(&self.directory)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .map_or(false, |name| {
+                        name.starts_with("dhcp-") && name.ends_with(".pcapng")
+                    })
+            }).collect();
+        names.sort();
+        let excess = names.len().saturating_sub(self.max_files.max(1));
+        for path in names.into_iter().take(excess) {
+            if let Err(error) = fs::remove_file(&path) {
+                warn!("Capture file {} removing error: {}", path.display(), error);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn section_header_block() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.put_u32_le(BYTE_ORDER_MAGIC);
+    body.put_u16_le(1); // major version
+    body.put_u16_le(0); // minor version
+    body.extend_from_slice(&[0xFF; 8]); // the unknown section length
+    block(BLOCK_SECTION_HEADER, &body)
+}
+
+fn interface_description_block() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.put_u16_le(LINKTYPE_RAW);
+    body.put_u16_le(0); // reserved
+    body.put_u32_le(0); // no snapshot length limit
+    block(BLOCK_INTERFACE_DESCRIPTION, &body)
+}
+
+/// Writes the block of the packet with the microsecond timestamp of the interface 0.
+fn enhanced_packet_block(packet: &[u8], dst: &mut BytesMut) {
+    let now = Utc::now();
+    let timestamp =
+        (now.timestamp() as u64) * 1_000_000 + u64::from(now.timestamp_subsec_micros());
+    let padding = (4 - packet.len() % 4) % 4;
+    let length = (12 + 20 + packet.len() + padding) as u32;
+    dst.reserve(length as usize);
+    dst.put_u32_le(BLOCK_ENHANCED_PACKET);
+    dst.put_u32_le(length);
+    dst.put_u32_le(0);
+    dst.put_u32_le((timestamp >> 32) as u32);
+    dst.put_u32_le(timestamp as u32);
+    dst.put_u32_le(packet.len() as u32); // captured
+    dst.put_u32_le(packet.len() as u32); // original
+    dst.put_slice(packet);
+    dst.put_slice(&[0u8; 3][..padding]);
+    dst.put_u32_le(length);
+}
+
+/// Wraps the body padded to 32 bits with the type and the total length.
+///
+/// The blocks are little-endian, as the byte order magic tells the readers.
+fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+    let length = (body.len() + 12) as u32;
+    let mut block = Vec::with_capacity(length as usize);
+    block.put_u32_le(kind);
+    block.put_u32_le(length);
+    block.extend_from_slice(body);
+    block.put_u32_le(length);
+    block
+}
+
+/// Writes the datagram with the IPv4 and UDP headers, the UDP checksum is left out.
+fn ipv4_packet(source: &SocketAddr, destination: &SocketAddr, payload: &[u8], dst: &mut BytesMut) {
+    let address = |addr: &SocketAddr| match *addr {
+        SocketAddr::V4(addr) => *addr.ip(),
+        SocketAddr::V6(_) => Ipv4Addr::new(0, 0, 0, 0),
+    };
+    let udp_length = SIZE_UDP_HEADER + payload.len();
+    let total_length = SIZE_IPV4_HEADER + udp_length;
+
+    dst.reserve(total_length);
+    dst.put_u8(0x45); // version 4, 5 words of the header
+    dst.put_u8(0); // DSCP and ECN
+    dst.put_u16_be(total_length as u16);
+    dst.put_slice(&[0, 0]); // identification
+    dst.put_slice(&[0x40, 0]); // don't fragment
+    dst.put_u8(IPV4_TTL);
+    dst.put_u8(PROTOCOL_UDP);
+    dst.put_slice(&[0, 0]); // the checksum is set below
+    dst.put_slice(&address(source).octets());
+    dst.put_slice(&address(destination).octets());
+    let checksum = ipv4_checksum(&dst[..SIZE_IPV4_HEADER]);
+    dst[10] = (checksum >> 8) as u8;
+    dst[11] = checksum as u8;
+
+    dst.put_u16_be(source.port());
+    dst.put_u16_be(destination.port());
+    dst.put_u16_be(udp_length as u16);
+    dst.put_slice(&[0, 0]);
+    dst.put_slice(payload);
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | u32::from(word[1]))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
@@ -0,0 +1,261 @@
+//! The configuration dry run finding the pool and reservation problems
+//! the server itself tolerates, like the overlapping pools.
+
+use std::{fmt, net::Ipv4Addr};
+
+use reload::Reload;
+use reservation::HostKey;
+use storage_file::hex_encode;
+use subnet::is_in_any;
+
+/// The outcome of `Config::check`.
+pub struct CheckReport {
+    /// The problems making the server misbehave, like the pools allocating the same addresses.
+    pub conflicts: Vec<String>,
+    /// The settings which are likely to be mistakes.
+    pub warnings: Vec<String>,
+    /// The effective settings, one per line, with the defaults filled in.
+    view: Vec<String>,
+}
+
+impl CheckReport {
+    /// Checks the validated settings.
+    pub fn new(reload: &Reload) -> Self {
+        let mut report = CheckReport {
+            conflicts: Vec::new(),
+            warnings: Vec::new(),
+            view: Vec::new(),
+        };
+        report.check_pools(reload);
+        report.check_reservations(reload);
+        report.describe(reload);
+        report
+    }
+
+    /// The effective settings, one per line, with the defaults filled in.
+    pub fn view(&self) -> &[String] {
+        &self.view
+    }
+
+    /// Whether the configuration has no conflicts.
+    pub fn is_valid(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    fn check_pools(&mut self, reload: &Reload) {
+        let mut pools = vec![
+            ("the static range".to_owned(), reload.static_address_range),
+            ("the dynamic range".to_owned(), reload.dynamic_address_range),
+        ];
+        for subnet in reload.subnets.iter() {
+            for &range in subnet.ranges.iter() {
+                if !subnet.contains(&range.0) || !subnet.contains(&range.1) {
+                    self.conflicts.push(format!(
+                        "The range {} is outside the subnet {}",
+                        format_range(range),
+                        subnet.address
+                    ));
+                }
+                pools.push((format!("the subnet {} range", subnet.address), range));
+            }
+        }
+
+        for (index, &(ref name, (first, last))) in pools.iter().enumerate() {
+            if first > last {
+                self.conflicts.push(format!(
+                    "The first address of {} {} is after the last one",
+                    name,
+                    format_range((first, last))
+                ));
+                continue;
+            }
+            for &(ref other_name, (other_first, other_last)) in pools[index + 1..].iter() {
+                if first <= other_last && other_first <= last {
+                    self.conflicts.push(format!(
+                        "{} {} overlaps {} {}",
+                        capitalize(name),
+                        format_range((first, last)),
+                        other_name,
+                        format_range((other_first, other_last))
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_reservations(&mut self, reload: &Reload) {
+        for (index, &(ref key, ref reservation)) in reload.reservations.iter().enumerate() {
+            let address = reservation.address;
+            let is_duplicate = reload.reservations[..index]
+                .iter()
+                .any(|&(_, ref other)| other.address == address);
+            if is_duplicate {
+                self.conflicts.push(format!(
+                    "The address {} is reserved for several hosts, including {}",
+                    address,
+                    format_key(key)
+                ));
+            }
+
+            let is_dynamic = is_in_any(&[reload.dynamic_address_range], &address)
+                && !is_in_any(&reload.dynamic_exclusions, &address);
+            let subnet = reload
+                .subnets
+                .iter()
+                .find(|subnet| subnet.is_in_ranges(&address));
+            if is_dynamic || subnet.is_some() {
+                self.warnings.push(format!(
+                    "The address {} reserved for {} is inside {}",
+                    address,
+                    format_key(key),
+                    subnet.map_or("the dynamic range".to_owned(), |subnet| {
+                        format!("a range of the subnet {}", subnet.address)
+                    })
+                ));
+            }
+        }
+    }
+
+    fn describe(&mut self, reload: &Reload) {
+        let view = &mut self.view;
+        view.push(format!(
+            "server {} on {}, mask {}",
+            reload.server_ip_address, reload.iface_name, reload.subnet_mask
+        ));
+        view.push(format!(
+            "static range {}",
+            format_pool(reload.static_address_range, &[])
+        ));
+        view.push(format!(
+            "dynamic range {}",
+            format_pool(reload.dynamic_address_range, &reload.dynamic_exclusions)
+        ));
+        view.push(format!(
+            "lease times {} s by default, {} s at most, affinity retention {} s, \
+             grace period {} s",
+            reload.lease_times.0,
+            reload.lease_times.1,
+            reload.affinity_retention,
+            reload.grace_period
+        ));
+        view.push(format!(
+            "routers {:?}, domain name servers {:?}",
+            reload.routers, reload.domain_name_servers
+        ));
+        for subnet in reload.subnets.iter() {
+            let ranges: Vec<String> = subnet
+                .ranges
+                .iter()
+                .map(|&range| format_pool(range, &subnet.exclusions))
+                .collect();
+            view.push(format!(
+                "subnet {}/{}{}: {}",
+                subnet.address,
+                u32::from(subnet.mask).count_ones(),
+                if subnet.authoritative {
+                    ", authoritative"
+                } else {
+                    ""
+                },
+                ranges.join(", ")
+            ));
+        }
+        for shared_network in reload.shared_networks.iter() {
+            view.push(format!(
+                "shared network {}: {:?}",
+                shared_network.name, shared_network.subnets
+            ));
+        }
+        for &(ref key, ref reservation) in reload.reservations.iter() {
+            view.push(format!(
+                "reservation {}: {}{}",
+                format_key(key),
+                reservation.address,
+                reservation
+                    .hostname
+                    .as_ref()
+                    .map_or(String::new(), |hostname| format!(" ({})", hostname))
+            ));
+        }
+        for class in reload.classes.iter() {
+            view.push(format!("class {}", class.name));
+        }
+        for vendor_class in reload.vendor_classes.iter() {
+            view.push(format!("vendor class {}", vendor_class.name));
+        }
+        let codes: Vec<String> = reload
+            .raw_options
+            .iter()
+            .map(|&(code, _)| code.to_string())
+            .collect();
+        view.push(format!("options [{}]", codes.join(", ")));
+        view.push(format!(
+            "access list {}, boot {}, BOOTP {}",
+            on_off(reload.access_list.is_some()),
+            on_off(reload.boot.is_some()),
+            on_off(reload.bootp)
+        ));
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.view.iter() {
+            writeln!(f, "{}", line)?;
+        }
+        for warning in self.warnings.iter() {
+            writeln!(f, "warning: {}", warning)?;
+        }
+        for conflict in self.conflicts.iter() {
+            writeln!(f, "error: {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+fn format_range((first, last): (Ipv4Addr, Ipv4Addr)) -> String {
+    format!("{}-{}", first, last)
+}
+
+/// The range with its size and the number of the excluded addresses inside it.
+fn format_pool(range: (Ipv4Addr, Ipv4Addr), exclusions: &[(Ipv4Addr, Ipv4Addr)]) -> String {
+    let size = |(first, last): (Ipv4Addr, Ipv4Addr)| {
+        u64::from(u32::from(last)).saturating_sub(u64::from(u32::from(first))) + 1
+    };
+    let excluded: u64 = exclusions
+        .iter()
+        .filter(|&&(first, last)| range.0 <= first && last <= range.1)
+        .map(|&exclusion| size(exclusion))
+        .sum();
+    format!(
+        "{} ({} addresses, {} excluded)",
+        format_range(range),
+        size(range),
+        excluded
+    )
+}
+
+fn format_key(key: &HostKey) -> String {
+    match *key {
+        HostKey::HardwareAddress(ref address) => address.to_hex_string(),
+        HostKey::ClientId(ref id) => format!("client ID {}", hex_encode(id)),
+        HostKey::CircuitId(ref id) => format!("circuit ID {}", hex_encode(id)),
+        HostKey::RemoteId(ref id) => format!("remote ID {}", hex_encode(id)),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
@@ -0,0 +1,116 @@
+//! Client classes with their own pools, lease times and options.
+
+use std::net::Ipv4Addr;
+
+use dhcp_protocol::Message;
+
+use scope::ScopeOptions;
+
+/// A condition a client is recognized by.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// The `class_id` option starts with the bytes.
+    VendorClass(Vec<u8>),
+    /// The `user_class` option or one of its RFC 3004 instances is equal to the bytes.
+    UserClass(Vec<u8>),
+    /// The first three octets of `chaddr`.
+    Oui([u8; 3]),
+    /// The `parameter_list` option is equal to the codes.
+    Fingerprint(Vec<u8>),
+    /// The circuit ID of the relay agent information.
+    CircuitId(Vec<u8>),
+    /// The remote ID of the relay agent information.
+    RemoteId(Vec<u8>),
+    /// The device type found in the fingerprint database.
+    DeviceType(String),
+}
+
+impl Matcher {
+    /// Checks whether the request of the device type, if it is known, satisfies the condition.
+    pub fn matches(&self, request: &Message, device_type: Option<&str>) -> bool {
+        let options = &request.options;
+        match *self {
+            Matcher::VendorClass(ref prefix) => options
+                .class_id
+                .as_ref()
+                .map_or(false, |class_id| class_id.starts_with(prefix)),
+            Matcher::UserClass(ref class) => options
+                .user_class
+                .as_ref()
+                .map_or(false, |user_class| {
+                    user_class == class
+                        || user_class_instances(user_class)
+                            .into_iter()
+                            .any(|instance| instance == &class[..])
+                }),
+            Matcher::Oui(ref oui) => &request.client_hardware_address.as_bytes()[..3] == oui,
+            Matcher::Fingerprint(ref codes) => options
+                .parameter_list
+                .as_ref()
+                .map_or(false, |parameter_list| parameter_list == codes),
+            Matcher::CircuitId(ref circuit_id) => options
+                .relay_agent_information
+                .as_ref()
+                .and_then(|information| information.circuit_id.as_ref())
+                .map_or(false, |value| value == circuit_id),
+            Matcher::RemoteId(ref remote_id) => options
+                .relay_agent_information
+                .as_ref()
+                .and_then(|information| information.remote_id.as_ref())
+                .map_or(false, |value| value == remote_id),
+            Matcher::DeviceType(ref expected) => device_type == Some(expected.as_str()),
+        }
+    }
+}
+
+/// A client class.
+///
+/// The first class whose conditions are all satisfied by a request is selected.
+#[derive(Debug, Clone)]
+pub struct Class {
+    /// Used only for logging.
+    pub name: String,
+    /// Must not be empty, since the class would match nothing.
+    pub matchers: Vec<Matcher>,
+    /// The network address of the subnet the class addresses are allocated from
+    /// instead of the subnet selected by the link address.
+    pub subnet: Option<Ipv4Addr>,
+    /// The default and maximal lease times in seconds.
+    ///
+    /// The maximal lease time cannot exceed the server-wide one.
+    pub lease_times: Option<(u32, u32)>,
+    /// The renewal (T1) and rebinding (T2) times in seconds.
+    pub renewal_times: Option<(u32, u32)>,
+    /// Sent instead of the subnet routes if not empty.
+    pub classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    /// Applied after the subnet options and before the host ones.
+    pub options: ScopeOptions,
+}
+
+impl Class {
+    /// Checks whether the request of the device type, if it is known, belongs to the class.
+    pub fn matches(&self, request: &Message, device_type: Option<&str>) -> bool {
+        !self.matchers.is_empty()
+            && self
+                .matchers
+                .iter()
+                .all(|matcher| matcher.matches(request, device_type))
+    }
+}
+
+/// Splits the RFC 3004 user class data into the instances.
+///
+/// Stops at the first instance exceeding the data.
+fn user_class_instances(data: &[u8]) -> Vec<&[u8]> {
+    let mut instances = Vec::new();
+    let mut rest = data;
+    while let Some(&len) = rest.first() {
+        let len = len as usize;
+        match rest.get(1..1 + len) {
+            Some(instance) => instances.push(instance),
+            None => break,
+        }
+        rest = &rest[1 + len..];
+    }
+    instances
+}
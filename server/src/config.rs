@@ -0,0 +1,2066 @@
+//! The TOML configuration file of the DHCP server.
+//!
+//! ```toml
+//! [server]
+//! address = "192.168.0.2"
+//! interface = "eth0"
+//! static_range = ["192.168.0.50", "192.168.0.99"]
+//! dynamic_range = ["192.168.0.100", "192.168.0.199"]
+//! subnet_mask = "255.255.255.0"
+//! routers = ["192.168.0.1"]
+//! domain_name_servers = ["192.168.0.1"]
+//! default_lease_time = 86400
+//! max_lease_time = 604800
+//! exclusions = ["192.168.0.100", ["192.168.0.110", "192.168.0.119"]]
+//! allocator = "hash"
+//! affinity_retention = 604800
+//! grace_period = 600
+//! deny = ["00:00:5e:*:*:*"]
+//! bootp = true
+//! fingerprints = "/etc/rust-dhcp/fingerprints"
+//! inbound_queue_size = 4096
+//! receive_batch = 64
+//! receive_ring = 256
+//! bind_to_device = true
+//! systemd = true
+//! user = "dhcp"
+//! chroot = "/var/lib/rust-dhcp"
+//!
+//! [rate_limits]
+//! client = [5, 10]
+//! relay = [200, 400]
+//! global = [1000, 2000]
+//!
+//! [subscriber_limits]
+//! circuit_id = 4
+//! remote_id = 16
+//! denied_action = "nak"
+//!
+//! [failover]
+//! role = "primary"
+//! mode = "standby"
+//! address = "192.168.0.2:647"
+//! mclt = 3600
+//!
+//! [admin]
+//! address = "127.0.0.1:8067"
+//! token = "c2VjcmV0"
+//!
+//! [grpc]
+//! address = "127.0.0.1:8068"
+//! token = "c2VjcmV0"
+//!
+//! [metrics]
+//! address = "127.0.0.1:9267"
+//!
+//! [control]
+//! path = "/run/rust-dhcp.sock"
+//!
+//! [probes]
+//! timeout = 500
+//! max = 16
+//! retries = 1
+//! reclamation_interval = 3600
+//! quiet_period = 86400
+//! audit_interval = 86400
+//!
+//! [bulk_leasequery]
+//! address = "192.168.0.2:67"
+//! requesters = ["10.0.0.1"]
+//!
+//! [ddns]
+//! server = "192.168.0.1:53"
+//! key_name = "dhcp-key"
+//! key_secret = "c2VjcmV0"
+//! zone = "example.com"
+//! reverse_zone = "0.168.192.in-addr.arpa"
+//! override_client_updates = true
+//! synthesized_prefix = "dhcp"
+//!
+//! [event_log]
+//! path = "/var/log/dhcp/events.json"
+//!
+//! [webhook]
+//! url = "http://127.0.0.1:8080/dhcp/events"
+//! authorization = "Bearer c2VjcmV0"
+//! max_batch = 100
+//! delay = 1
+//! retries = 5
+//!
+//! [capture]
+//! directory = "/var/log/dhcp/capture"
+//! max_size = 104857600
+//! max_age = 86400
+//! max_files = 30
+//!
+//! [hook]
+//! command = "/usr/local/bin/dhcp-lease-hook"
+//! args = ["--firewall"]
+//! timeout = 10
+//!
+//! [boot]
+//! next_server = "192.168.0.3"
+//! boot_filename = "pxelinux.0"
+//!
+//! [boot.architectures.uefi_x64]
+//! boot_filename = "grubx64.efi"
+//!
+//! [boot.architectures.uefi_arm64]
+//! boot_filename = "grubaa64.efi"
+//!
+//! [[subnets]]
+//! address = "10.0.0.0"
+//! mask = "255.255.255.0"
+//! ranges = [["10.0.0.100", "10.0.0.199"]]
+//! exclusions = ["10.0.0.150"]
+//! allocator = "random"
+//! routers = ["10.0.0.1"]
+//! classless_static_routes = [
+//!     ["0.0.0.0", "0.0.0.0", "10.0.0.1"],
+//!     ["10.1.0.0", "255.255.0.0", "10.0.0.254"],
+//! ]
+//! remote_ids = ["0a0b0c"]
+//! authoritative = true
+//! default_lease_time = 600
+//! max_lease_time = 1800
+//! renewal_time = 300
+//! rebinding_time = 525
+//! ddns_zone = "lab.example.com"
+//! allow = ["00:40:8c", "00:11:22:33:44:55"]
+//! denied_action = "nak"
+//!
+//! [subnets.authentication]
+//! secret_id = 1
+//! key = "000102030405060708090a0b0c0d0e0f"
+//! failure_action = "log"
+//!
+//! [subnets.boot]
+//! next_server = "10.0.0.3"
+//! boot_filename = "undionly.kpxe"
+//!
+//! [subnets.append_options]
+//! ntp_servers = ["10.0.0.1"]
+//!
+//! [[shared_networks]]
+//! name = "lab"
+//! subnets = ["10.0.0.0"]
+//!
+//! [shared_networks.options]
+//! domain_name = "lab.example.com"
+//! domain_name_servers = ["10.0.0.1"]
+//!
+//! [[interfaces]]
+//! name = "eth0"
+//! address = "192.168.0.2"
+//!
+//! [[interfaces]]
+//! name = "eth1"
+//! address = "10.0.0.2"
+//! subnet = "10.0.0.0"
+//!
+//! [[reservations]]
+//! hardware_address = "00:11:22:33:44:55"
+//! address = "192.168.0.10"
+//! hostname = "printer"
+//! boot_filename = "pxelinux.0"
+//! default_lease_time = 604800
+//!
+//! [reservations.options]
+//! 252 = "http://wpad.example.com/printers.dat"
+//!
+//! [[reservations]]
+//! circuit_id = "0001"
+//! address = "10.0.1.10"
+//!
+//! [[classes]]
+//! name = "cameras"
+//! oui = "00:40:8c"
+//! subnet = "10.0.0.0"
+//! default_lease_time = 3600
+//! max_lease_time = 7200
+//! classless_static_routes = [["0.0.0.0", "0.0.0.0", "10.0.0.1"]]
+//!
+//! [classes.options]
+//! ntp_servers = ["10.0.0.1"]
+//!
+//! [[classes]]
+//! name = "printers"
+//! device_type = "printer"
+//! default_lease_time = 604800
+//!
+//! [[vendor_classes]]
+//! name = "aironet"
+//! vendor_class = "Cisco AP"
+//!
+//! [vendor_classes.suboptions]
+//! 241 = ["10.0.0.5"]
+//!
+//! [[vendor_classes]]
+//! name = "phones"
+//! vendor_class = "Polycom"
+//! data = "0115687474703a2f2f70726f766973696f6e2e6c616e2f"
+//!
+//! [options]
+//! domain_name = "example.com"
+//! ntp_servers = ["192.168.0.1"]
+//! 252 = "http://wpad.example.com/wpad.dat"
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    error, fmt, fs,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use base64;
+use serde_json;
+
+use eui48::MacAddress;
+use toml::{self, Value};
+
+use dhcp_protocol::OptionTag;
+
+use access::{AccessList, DeniedAction, MacPattern};
+use allocator::{Allocator, HashAllocator, RandomAllocator, SequentialAllocator};
+use auth::{AuthenticationKey, FailureAction};
+use boot::{
+    Boot, BootParameters, ARCHITECTURE_EFI_ARM64, ARCHITECTURE_EFI_BC, ARCHITECTURE_EFI_IA32,
+    ARCHITECTURE_EFI_X86_64, ARCHITECTURE_X86_BIOS,
+};
+use capture::{DEFAULT_CAPTURE_MAX_AGE, DEFAULT_CAPTURE_MAX_FILES, DEFAULT_CAPTURE_MAX_SIZE};
+use check::CheckReport;
+use class::{Class, Matcher};
+use database::{DEFAULT_LEASE_TIME, MAX_LEASE_TIME};
+use ddns::{DnsUpdater, DnsZone, TsigKey};
+use event_log::EventLogger;
+use failover::{Mode, Role};
+use fingerprint::FingerprintDatabase;
+use fqdn::FqdnPolicy;
+use hook::{CommandHook, DEFAULT_HOOK_TIMEOUT};
+use inbound::DEFAULT_INBOUND_QUEUE_SIZE;
+use interface::Interface;
+use reload::Reload;
+use reservation::{HostKey, Reservation};
+use scope::{ScopeOptions, SharedNetwork};
+use server::ServerBuilder;
+use storage::Storage;
+use storage_file::hex_decode;
+use subnet::Subnet;
+use vendor::{encode_suboptions, VendorClass};
+use webhook::{
+    WebhookNotifier, DEFAULT_WEBHOOK_DELAY, DEFAULT_WEBHOOK_MAX_BATCH, DEFAULT_WEBHOOK_RETRIES,
+};
+
+/// Errors generated while loading the configuration.
+#[derive(Debug)]
+pub enum Error {
+    Read(String),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Read(ref desc) => write!(f, "Configuration reading error: {}", desc),
+            Error::Parse(ref desc) => write!(f, "Configuration parsing error: {}", desc),
+            Error::Invalid(ref desc) => write!(f, "Invalid configuration: {}", desc),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// The way a named option value is encoded.
+#[derive(Clone, Copy)]
+enum Kind {
+    Address,
+    Addresses,
+    Text,
+    U8,
+    U16,
+    U32,
+}
+
+/// The options which may be set by name in the `[options]` table.
+const NAMED_OPTIONS: &[(&str, OptionTag, Kind)] = &[
+    ("time_offset", OptionTag::TimeOffset, Kind::U32),
+    ("time_servers", OptionTag::TimeServers, Kind::Addresses),
+    ("name_servers", OptionTag::NameServers, Kind::Addresses),
+    ("log_servers", OptionTag::LogServers, Kind::Addresses),
+    ("lpr_servers", OptionTag::LprServers, Kind::Addresses),
+    ("domain_name", OptionTag::DomainName, Kind::Text),
+    ("root_path", OptionTag::RootPath, Kind::Text),
+    ("default_ip_ttl", OptionTag::DefaultIpTtl, Kind::U8),
+    ("mtu_interface", OptionTag::MtuInterface, Kind::U16),
+    ("broadcast_address", OptionTag::BroadcastAddress, Kind::Address),
+    ("arp_timeout", OptionTag::ArpTimeout, Kind::U32),
+    ("default_tcp_ttl", OptionTag::DefaultTcpTtl, Kind::U8),
+    ("nis_domain", OptionTag::NisDomain, Kind::Text),
+    ("nis_servers", OptionTag::NisServers, Kind::Addresses),
+    ("ntp_servers", OptionTag::NtpServers, Kind::Addresses),
+    ("netbios_name_servers", OptionTag::NetbiosNameServers, Kind::Addresses),
+    ("netbios_node_type", OptionTag::NetbiosNodeType, Kind::U8),
+    ("netbios_scope", OptionTag::NetbiosScope, Kind::Text),
+    ("server_name", OptionTag::ServerName, Kind::Text),
+    ("bootfile_name", OptionTag::BootfileName, Kind::Text),
+    ("smtp_servers", OptionTag::SmtpServers, Kind::Addresses),
+    ("pop3_servers", OptionTag::Pop3Servers, Kind::Addresses),
+    ("www_servers", OptionTag::WwwServers, Kind::Addresses),
+    ("ipv6_only_preferred", OptionTag::Ipv6OnlyPreferred, Kind::U32),
+    ("captive_portal", OptionTag::CaptivePortal, Kind::Text),
+    (
+        "web_proxy_auto_discovery",
+        OptionTag::WebProxyAutoDiscovery,
+        Kind::Text,
+    ),
+];
+
+/// The options which are set by the dedicated fields or by the protocol itself.
+const RESERVED_OPTIONS: &[OptionTag] = &[
+    OptionTag::Pad,
+    OptionTag::SubnetMask,
+    OptionTag::Routers,
+    OptionTag::DomainNameServers,
+    OptionTag::Hostname,
+    OptionTag::StaticRoutes,
+    OptionTag::AddressRequest,
+    OptionTag::AddressTime,
+    OptionTag::Overload,
+    OptionTag::DhcpMessageType,
+    OptionTag::DhcpServerId,
+    OptionTag::ParameterList,
+    OptionTag::DhcpMessage,
+    OptionTag::DhcpMaxMessageSize,
+    OptionTag::RenewalTime,
+    OptionTag::RebindingTime,
+    OptionTag::ClientId,
+    OptionTag::RelayAgentInformation,
+    OptionTag::ClasslessStaticRoutes,
+    OptionTag::End,
+];
+
+/// The server configuration loaded at startup.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    server: ServerSection,
+    rate_limits: Option<RateLimitsSection>,
+    subscriber_limits: Option<SubscriberLimitsSection>,
+    failover: Option<FailoverSection>,
+    bulk_leasequery: Option<BulkLeasequerySection>,
+    admin: Option<AdminSection>,
+    /// Served only with the `grpc-api` feature.
+    grpc: Option<AdminSection>,
+    control: Option<ControlSection>,
+    metrics: Option<MetricsSection>,
+    probes: Option<ProbesSection>,
+    ddns: Option<DdnsSection>,
+    event_log: Option<EventLogSection>,
+    webhook: Option<WebhookSection>,
+    capture: Option<CaptureSection>,
+    hook: Option<HookSection>,
+    boot: Option<BootSection>,
+    #[serde(default)]
+    subnets: Vec<SubnetSection>,
+    /// Served with a single socket instead of the `[server]` interface, Linux only.
+    #[serde(default)]
+    interfaces: Vec<InterfaceSection>,
+    #[serde(default)]
+    reservations: Vec<ReservationSection>,
+    /// Checked in the order of appearance.
+    #[serde(default)]
+    classes: Vec<ClassSection>,
+    #[serde(default)]
+    shared_networks: Vec<SharedNetworkSection>,
+    /// Checked in the order of appearance.
+    #[serde(default)]
+    vendor_classes: Vec<VendorClassSection>,
+    /// The outermost option scope, inherited by the shared networks, subnets,
+    /// classes and reservations in this order.
+    ///
+    /// Keyed by an option name from `NAMED_OPTIONS` or by a decimal option code.
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerSection {
+    address: Ipv4Addr,
+    interface: String,
+    static_range: (Ipv4Addr, Ipv4Addr),
+    dynamic_range: (Ipv4Addr, Ipv4Addr),
+    subnet_mask: Ipv4Addr,
+    #[serde(default)]
+    routers: Vec<Ipv4Addr>,
+    #[serde(default)]
+    domain_name_servers: Vec<Ipv4Addr>,
+    #[serde(default)]
+    static_routes: Vec<(Ipv4Addr, Ipv4Addr)>,
+    #[serde(default)]
+    classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    default_lease_time: Option<u32>,
+    max_lease_time: Option<u32>,
+    /// The addresses and the inclusive ranges inside the dynamic range which are never allocated.
+    #[serde(default)]
+    exclusions: Vec<ExclusionSection>,
+    /// `sequential` (the default), `random` or `hash` for the dynamic range.
+    allocator: Option<String>,
+    /// The seconds the addresses of the expired and released leases are kept
+    /// for the returning clients.
+    #[serde(default)]
+    affinity_retention: u32,
+    /// The seconds the addresses of the expired and released leases are not allocated
+    /// to the other clients, even if the pools run out of addresses.
+    #[serde(default)]
+    grace_period: u32,
+    /// Like `00:11:22:33:44:55`, `00:11:22` or `00:11:*:33:*:*`.
+    #[serde(default)]
+    allow: Vec<String>,
+    /// Like `allow`.
+    #[serde(default)]
+    deny: Vec<String>,
+    /// `ignore` or `nak`.
+    denied_action: Option<String>,
+    /// Answers the plain BOOTP requests of the clients with `[[reservations]]`.
+    #[serde(default)]
+    bootp: bool,
+    /// Leaves the addresses to another server and only offers the `[boot]` parameters
+    /// to the PXE clients. Cannot be reloaded, since the port 4011 socket is bound at startup.
+    #[serde(default)]
+    proxy_dhcp: bool,
+    /// The received messages waiting to be processed, beyond which `DHCPDISCOVER` ones
+    /// are dropped. Cannot be reloaded, since the queue lives in the server.
+    inbound_queue_size: Option<usize>,
+    /// The datagrams received with a single system call on Linux, one per call if not set.
+    /// Cannot be reloaded, since the buffers are allocated at startup.
+    receive_batch: Option<usize>,
+    /// The `io_uring` receive requests kept in flight on Linux with the `io-uring-receive`
+    /// feature, which takes precedence over `receive_batch`.
+    /// Cannot be reloaded, since the buffers are allocated at startup.
+    receive_ring: Option<usize>,
+    /// Binds the socket to the `interface`, so the broadcasts leave through it,
+    /// or sends them over the raw socket if it cannot be bound.
+    /// Cannot be reloaded, since the socket is bound at startup.
+    #[serde(default)]
+    bind_to_device: bool,
+    /// Takes the sockets passed by systemd and notifies it on Linux.
+    /// Cannot be reloaded, since the sockets are bound at startup.
+    #[serde(default)]
+    systemd: bool,
+    /// The unprivileged user the server switches to once the sockets are open on Unix.
+    user: Option<String>,
+    /// The group of the `user`, its primary one if not set.
+    group: Option<String>,
+    /// The new root directory the storage and the reloaded configuration paths are inside.
+    chroot: Option<PathBuf>,
+    /// The fingerprint database the device types of the clients are found in.
+    /// Read again on every reload.
+    fingerprints: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ExclusionSection {
+    Address(Ipv4Addr),
+    Range(Ipv4Addr, Ipv4Addr),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InterfaceSection {
+    name: String,
+    /// The server address on the interface.
+    address: Ipv4Addr,
+    /// The network address of the server network or a `[[subnets]]` entry,
+    /// the one containing the address if not set.
+    subnet: Option<Ipv4Addr>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubnetSection {
+    address: Ipv4Addr,
+    mask: Ipv4Addr,
+    ranges: Vec<(Ipv4Addr, Ipv4Addr)>,
+    /// Like the `[server]` ones, inside the `ranges`.
+    #[serde(default)]
+    exclusions: Vec<ExclusionSection>,
+    /// Like the `[server]` one.
+    allocator: Option<String>,
+    #[serde(default)]
+    routers: Vec<Ipv4Addr>,
+    /// As `[destination, mask, router]`, replacing the `[server]` routes.
+    /// Must include the default route, since the `routers` are not sent with them.
+    #[serde(default)]
+    classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    /// Hexadecimal.
+    #[serde(default)]
+    circuit_ids: Vec<String>,
+    /// Hexadecimal.
+    #[serde(default)]
+    remote_ids: Vec<String>,
+    #[serde(default)]
+    authoritative: bool,
+    /// Like the `[server]` ones, replaced by the class and host ones.
+    default_lease_time: Option<u32>,
+    max_lease_time: Option<u32>,
+    /// The renewal (T1) and rebinding (T2) times in seconds, set together.
+    renewal_time: Option<u32>,
+    rebinding_time: Option<u32>,
+    /// Overrides the `[ddns]` zones for the subnet.
+    ddns_zone: Option<String>,
+    ddns_reverse_zone: Option<String>,
+    /// Replace the `[server]` access list for the subnet.
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    denied_action: Option<String>,
+    /// Replaces the `[boot]` section for the subnet.
+    boot: Option<BootSection>,
+    authentication: Option<AuthenticationSection>,
+    /// Like the `[options]` table, replacing the inherited options.
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+    /// Like the `[options]` table, appended to the inherited options.
+    #[serde(default)]
+    append_options: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SharedNetworkSection {
+    name: String,
+    /// The network addresses of the `[[subnets]]`.
+    subnets: Vec<Ipv4Addr>,
+    /// Like the `[options]` table, replacing the inherited options.
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+    /// Like the `[options]` table, appended to the inherited options.
+    #[serde(default)]
+    append_options: BTreeMap<String, Value>,
+}
+
+/// Exactly one of `data` and `suboptions` must be set.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VendorClassSection {
+    name: String,
+    /// The prefix of the vendor class identifier.
+    vendor_class: String,
+    /// The hexadecimal option 43 data.
+    data: Option<String>,
+    /// Keyed by a decimal sub-option code, with the values like the raw `[options]` ones.
+    #[serde(default)]
+    suboptions: BTreeMap<String, Value>,
+}
+
+/// The limits as `[rate, burst]` pairs, where the rate is in requests per second.
+///
+/// Cannot be reloaded, since the limiter state lives in the server.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RateLimitsSection {
+    client: Option<(u32, u32)>,
+    relay: Option<(u32, u32)>,
+    global: Option<(u32, u32)>,
+}
+
+/// The concurrent leases of the clients behind every relay agent circuit and remote ID.
+///
+/// Cannot be reloaded, since the clients of the IDs are tracked by the server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscriberLimitsSection {
+    circuit_id: Option<usize>,
+    remote_id: Option<usize>,
+    /// `ignore` or `nak`.
+    denied_action: Option<String>,
+}
+
+/// Cannot be reloaded, since the peer connection is established at startup.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FailoverSection {
+    /// `primary` or `secondary`.
+    role: String,
+    /// `standby` or `load_balance`.
+    mode: String,
+    /// The address the primary listens on and the secondary connects to.
+    address: SocketAddr,
+    /// The maximum client lead time in seconds.
+    mclt: u32,
+}
+
+/// Cannot be reloaded, since the listener is bound at startup.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BulkLeasequerySection {
+    /// The TCP address the requesters connect to, usually on port 67 (RFC 6926 §7.1).
+    address: SocketAddr,
+    /// The addresses of the requesters allowed to connect, everyone if empty.
+    #[serde(default)]
+    requesters: Vec<Ipv4Addr>,
+}
+
+/// Cannot be reloaded, since the listener is bound at startup.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AdminSection {
+    /// The TCP address of the API, better kept on the loopback interface.
+    address: SocketAddr,
+    /// The bearer token the requests must have, if set.
+    token: Option<String>,
+}
+
+/// Cannot be reloaded, since the listener is bound at startup.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ControlSection {
+    /// The Unix socket file, Unix only.
+    path: PathBuf,
+}
+
+/// Cannot be reloaded, since the listener is bound at startup.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetricsSection {
+    /// The TCP address the Prometheus scrapes `/metrics` at.
+    address: SocketAddr,
+}
+
+/// Cannot be reloaded, since the probes in progress live in the server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbesSection {
+    /// The milliseconds to wait for an ICMP echo reply before offering an address.
+    timeout: u64,
+    /// The probes running at the same time.
+    max: usize,
+    /// The echo requests repeated after the first one has got no reply.
+    #[serde(default)]
+    retries: u32,
+    /// The seconds between probing the abandoned addresses, never if not set.
+    reclamation_interval: Option<u64>,
+    /// The seconds an abandoned address must not reply to be reclaimed.
+    #[serde(default)]
+    quiet_period: u64,
+    /// The seconds between the conflict audits of all the pool addresses, never if not set.
+    audit_interval: Option<u64>,
+}
+
+/// Cannot be reloaded, since the updater is a server observer.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DdnsSection {
+    /// The primary DNS server, like `192.168.0.1:53`.
+    server: SocketAddr,
+    key_name: Option<String>,
+    /// Base64, like the `tsig-keygen` output.
+    key_secret: Option<String>,
+    /// The zones of the server network, the subnets have their own ones.
+    zone: Option<String>,
+    reverse_zone: Option<String>,
+    /// Updates the A records of the clients asking to update them themselves.
+    #[serde(default)]
+    override_client_updates: bool,
+    /// Updates the records of the clients asking for no updates at all.
+    #[serde(default)]
+    override_no_updates: bool,
+    /// The host names of the clients sending none are synthesized from their addresses,
+    /// like `dhcp-192-168-0-10`.
+    synthesized_prefix: Option<String>,
+}
+
+/// Cannot be reloaded, since the logger is a server observer.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventLogSection {
+    /// The file the JSON lines are appended to, the standard output if not set.
+    path: Option<PathBuf>,
+}
+
+/// Cannot be reloaded, since the notifier is a server observer.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WebhookSection {
+    /// Like `http://127.0.0.1:8080/dhcp/events`, the host name is resolved on startup.
+    url: String,
+    /// The `Authorization` header value, like `Bearer <token>`.
+    authorization: Option<String>,
+    /// The events posted at once.
+    max_batch: Option<usize>,
+    /// The seconds the first event of a batch waits for the others.
+    delay: Option<u64>,
+    /// The repeated attempts to post a batch before it is dropped.
+    retries: Option<u32>,
+}
+
+/// Cannot be reloaded, since the files are written by the server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaptureSection {
+    /// The directory the pcapng files are written to.
+    directory: PathBuf,
+    /// The bytes a file is rotated at.
+    max_size: Option<u64>,
+    /// The seconds a file is rotated after.
+    max_age: Option<u64>,
+    /// The newest files kept.
+    max_files: Option<usize>,
+}
+
+/// Cannot be reloaded, since the command is run by a server hook.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HookSection {
+    /// Run with the lease described by the `DHCP_*` environment variables.
+    command: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    /// In seconds, the command is killed afterwards.
+    timeout: Option<u64>,
+}
+
+/// The RFC 3118 delayed authentication key of a subnet.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuthenticationSection {
+    #[serde(default)]
+    realm: String,
+    secret_id: u32,
+    /// Hexadecimal.
+    key: String,
+    /// `drop` (the default) or `log` for the requests failing the authentication.
+    failure_action: Option<String>,
+}
+
+/// The network boot parameters.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BootSection {
+    next_server: Option<Ipv4Addr>,
+    server_name: Option<String>,
+    boot_filename: Option<String>,
+    /// Keyed by `bios`, `uefi_ia32`, `uefi_x64`, `uefi_arm64` or a decimal architecture type.
+    #[serde(default)]
+    architectures: BTreeMap<String, BootParametersSection>,
+}
+
+/// The parameters replacing the `[boot]` ones for an architecture.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BootParametersSection {
+    next_server: Option<Ipv4Addr>,
+    server_name: Option<String>,
+    boot_filename: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReservationSection {
+    /// Like `00:11:22:33:44:55`.
+    hardware_address: Option<String>,
+    /// Hexadecimal, like `01001122334455`.
+    client_id: Option<String>,
+    /// Hexadecimal.
+    circuit_id: Option<String>,
+    /// Hexadecimal.
+    remote_id: Option<String>,
+    address: Ipv4Addr,
+    hostname: Option<String>,
+    boot_filename: Option<String>,
+    /// Replace the class and subnet ones.
+    default_lease_time: Option<u32>,
+    max_lease_time: Option<u32>,
+    renewal_time: Option<u32>,
+    rebinding_time: Option<u32>,
+    /// Like the `[options]` table, replacing the inherited options.
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+    /// Like the `[options]` table, appended to the inherited options.
+    #[serde(default)]
+    append_options: BTreeMap<String, Value>,
+}
+
+/// All the conditions which are set must be satisfied.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClassSection {
+    name: String,
+    /// The prefix of the vendor class identifier.
+    vendor_class: Option<String>,
+    user_class: Option<String>,
+    /// Like `00:11:22`.
+    oui: Option<String>,
+    /// The exact parameter request list.
+    fingerprint: Option<Vec<u8>>,
+    /// Found in the `[server]` fingerprint database.
+    device_type: Option<String>,
+    /// Hexadecimal.
+    circuit_id: Option<String>,
+    /// Hexadecimal.
+    remote_id: Option<String>,
+    /// The network address of one of the `[[subnets]]`.
+    subnet: Option<Ipv4Addr>,
+    /// Replace the subnet ones.
+    default_lease_time: Option<u32>,
+    max_lease_time: Option<u32>,
+    renewal_time: Option<u32>,
+    rebinding_time: Option<u32>,
+    /// Like the `[[subnets]]` ones, replacing the subnet routes.
+    #[serde(default)]
+    classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    /// Like the `[options]` table, replacing the inherited options.
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+    /// Like the `[options]` table, appended to the inherited options.
+    #[serde(default)]
+    append_options: BTreeMap<String, Value>,
+}
+
+impl Config {
+    /// Reads and parses the configuration file.
+    ///
+    /// # Errors
+    /// `self::Error` if the file cannot be read or parsed.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let data = fs::read_to_string(path).map_err(|error| Error::Read(error.to_string()))?;
+        Self::parse(&data)
+    }
+
+    /// Parses the configuration.
+    ///
+    /// # Errors
+    /// `self::Error` if the data is not a valid configuration.
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        toml::from_str(data).map_err(|error| Error::Parse(error.to_string()))
+    }
+
+    /// Validates the configuration and creates a server builder from it.
+    ///
+    /// # Errors
+    /// `self::Error` if a reservation or an option value is invalid.
+    pub fn into_builder<S>(self, storage: S) -> Result<ServerBuilder<S>, Error>
+    where
+        S: Storage,
+    {
+        let dns_updater = self.dns_updater()?;
+        let fqdn_policy = self.fqdn_policy()?;
+        let event_logger = self.event_logger()?;
+        let webhook_notifier = self.webhook_notifier()?;
+        let hook = self.hook.clone();
+        let failover = self.failover()?;
+        let interfaces = self.interfaces()?;
+        let bulk_leasequery = self.bulk_leasequery.clone();
+        let admin = self.admin.clone();
+        let grpc = self.grpc.clone();
+        let control = self.control.clone();
+        let metrics = self.metrics.clone();
+        let capture = self.capture()?;
+        let probes = self.probes()?;
+        let rate_limits = self.rate_limits()?;
+        let subscriber_limits = self.subscriber_limits()?;
+        let inbound_queue_size = self.inbound_queue_size()?;
+        let receive_batch = self.receive_batch()?;
+        let receive_ring = self.receive_ring()?;
+        let proxy_dhcp = self.server.proxy_dhcp;
+        let bind_to_device = self.server.bind_to_device;
+        let systemd = self.server.systemd;
+        let user = self.user()?;
+        let chroot = self.server.chroot.clone();
+        let reload = self.into_reload()?;
+        let mut builder = ServerBuilder::new(
+            reload.server_ip_address,
+            reload.iface_name,
+            reload.static_address_range,
+            reload.dynamic_address_range,
+            storage,
+            reload.subnet_mask,
+            reload.routers,
+            reload.domain_name_servers,
+            reload.static_routes,
+            reload.classless_static_routes,
+        );
+        builder.with_lease_times(reload.lease_times.0, reload.lease_times.1);
+        builder.with_dynamic_exclusions(reload.dynamic_exclusions);
+        builder.with_allocator(reload.allocator);
+        builder.with_affinity_retention(reload.affinity_retention);
+        builder.with_grace_period(reload.grace_period);
+        builder.with_raw_options(reload.raw_options);
+        for subnet in reload.subnets.into_iter() {
+            builder.with_subnet(subnet);
+        }
+        for (key, reservation) in reload.reservations.into_iter() {
+            builder.with_reservation(key, reservation);
+        }
+        for class in reload.classes.into_iter() {
+            builder.with_class(class);
+        }
+        for shared_network in reload.shared_networks.into_iter() {
+            builder.with_shared_network(shared_network);
+        }
+        for vendor_class in reload.vendor_classes.into_iter() {
+            builder.with_vendor_class(vendor_class);
+        }
+        if let Some(access_list) = reload.access_list {
+            builder.with_access_list(access_list);
+        }
+        if let Some(boot) = reload.boot {
+            builder.with_boot(boot);
+        }
+        if reload.bootp {
+            builder.with_bootp();
+        }
+        if let Some(fingerprints) = reload.fingerprints {
+            builder.with_fingerprints(fingerprints);
+        }
+        if let Some(dns_updater) = dns_updater {
+            builder.with_observer(Box::new(dns_updater));
+        }
+        if let Some(fqdn_policy) = fqdn_policy {
+            builder.with_fqdn_policy(fqdn_policy);
+        }
+        if let Some(event_logger) = event_logger {
+            builder.with_observer(Box::new(event_logger));
+        }
+        if let Some(webhook_notifier) = webhook_notifier {
+            builder.with_observer(Box::new(webhook_notifier));
+        }
+        if let Some(hook) = hook {
+            builder.with_hook(Box::new(CommandHook::new(
+                hook.command,
+                hook.args,
+                Duration::from_secs(hook.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT)),
+            )));
+        }
+        for interface in interfaces.into_iter() {
+            builder.with_interface(interface);
+        }
+        if let Some((role, mode, address, mclt)) = failover {
+            builder.with_failover(role, mode, address, mclt);
+        }
+        if let Some(bulk_leasequery) = bulk_leasequery {
+            builder.with_bulk_leasequery(bulk_leasequery.address, bulk_leasequery.requesters);
+        }
+        if let Some(admin) = admin {
+            builder.with_admin_api(admin.address, admin.token);
+        }
+        if let Some(grpc) = grpc {
+            builder.with_grpc_api(grpc.address, grpc.token);
+        }
+        if let Some(control) = control {
+            builder.with_control_socket(control.path);
+        }
+        if let Some(metrics) = metrics {
+            builder.with_metrics(metrics.address);
+        }
+        if let Some(capture) = capture {
+            builder.with_capture(
+                capture.directory,
+                capture.max_size.unwrap_or(DEFAULT_CAPTURE_MAX_SIZE),
+                Duration::from_secs(capture.max_age.unwrap_or(DEFAULT_CAPTURE_MAX_AGE)),
+                capture.max_files.unwrap_or(DEFAULT_CAPTURE_MAX_FILES),
+            );
+        }
+        if let Some(probes) = probes {
+            builder.with_probes(Duration::from_millis(probes.timeout), probes.max);
+            builder.with_probe_retries(probes.retries);
+            if let Some(interval) = probes.reclamation_interval {
+                builder.with_reclamation_interval(Duration::from_secs(interval));
+            }
+            builder.with_reclamation_quiet_period(Duration::from_secs(probes.quiet_period));
+            if let Some(interval) = probes.audit_interval {
+                builder.with_audit_interval(Duration::from_secs(interval));
+            }
+        }
+        if let Some((rate, burst)) = rate_limits.client {
+            builder.with_client_rate_limit(rate, burst);
+        }
+        if let Some((rate, burst)) = rate_limits.relay {
+            builder.with_relay_rate_limit(rate, burst);
+        }
+        if let Some((rate, burst)) = rate_limits.global {
+            builder.with_global_rate_limit(rate, burst);
+        }
+        if let Some((circuit_limit, remote_limit, denied_action)) = subscriber_limits {
+            builder.with_subscriber_limits(circuit_limit, remote_limit, denied_action);
+        }
+        builder.with_inbound_queue_size(inbound_queue_size);
+        if let Some(size) = receive_batch {
+            builder.with_receive_batch(size);
+        }
+        if let Some(size) = receive_ring {
+            builder.with_receive_ring(size);
+        }
+        if proxy_dhcp {
+            builder.with_proxy_dhcp();
+        }
+        if bind_to_device {
+            builder.with_device_binding();
+        }
+        if systemd {
+            builder.with_systemd();
+        }
+        if let Some((user, group)) = user {
+            builder.with_user(user, group);
+        }
+        if let Some(chroot) = chroot {
+            builder.with_chroot(chroot);
+        }
+        Ok(builder)
+    }
+
+    /// Validates the configuration without starting the server, also looking for
+    /// the overlapping pools and the reservations inside them.
+    ///
+    /// The files like the event log are not opened.
+    ///
+    /// # Errors
+    /// `self::Error` if the server would not start with the configuration.
+    pub fn check(self) -> Result<CheckReport, Error> {
+        self.dns_updater()?;
+        self.fqdn_policy()?;
+        self.webhook_notifier()?;
+        self.failover()?;
+        self.interfaces()?;
+        self.capture()?;
+        self.probes()?;
+        self.rate_limits()?;
+        self.subscriber_limits()?;
+        self.inbound_queue_size()?;
+        self.receive_batch()?;
+        self.receive_ring()?;
+        self.user()?;
+        let reload = self.into_reload()?;
+        Ok(CheckReport::new(&reload))
+    }
+
+    /// Validates the `[capture]` section if it is present.
+    fn capture(&self) -> Result<Option<CaptureSection>, Error> {
+        if let Some(ref capture) = self.capture {
+            if capture.max_size == Some(0)
+                || capture.max_age == Some(0)
+                || capture.max_files == Some(0)
+            {
+                return Err(Error::Invalid(
+                    "The capture file size, age and count limits must be positive".to_owned(),
+                ));
+            }
+        }
+        Ok(self.capture.clone())
+    }
+
+    /// Validates the `[probes]` section if it is present.
+    fn probes(&self) -> Result<Option<ProbesSection>, Error> {
+        if let Some(ref probes) = self.probes {
+            if probes.max == 0
+                || probes.reclamation_interval == Some(0)
+                || probes.audit_interval == Some(0)
+            {
+                return Err(Error::Invalid(
+                    "The probe limit and the reclamation and audit intervals must be positive"
+                        .to_owned(),
+                ));
+            }
+        }
+        Ok(self.probes.clone())
+    }
+
+    /// Returns the inbound queue size, the default one if not set.
+    fn inbound_queue_size(&self) -> Result<usize, Error> {
+        match self.server.inbound_queue_size {
+            Some(0) => Err(Error::Invalid(
+                "The inbound queue size must be positive".to_owned(),
+            )),
+            Some(size) => Ok(size),
+            None => Ok(DEFAULT_INBOUND_QUEUE_SIZE),
+        }
+    }
+
+    /// Returns the receive batch size if set.
+    fn receive_batch(&self) -> Result<Option<usize>, Error> {
+        match self.server.receive_batch {
+            Some(0) => Err(Error::Invalid(
+                "The receive batch size must be positive".to_owned(),
+            )),
+            size => Ok(size),
+        }
+    }
+
+    /// Returns the receive ring size if set.
+    fn receive_ring(&self) -> Result<Option<usize>, Error> {
+        match self.server.receive_ring {
+            Some(0) => Err(Error::Invalid(
+                "The receive ring size must be positive".to_owned(),
+            )),
+            size => Ok(size),
+        }
+    }
+
+    /// Returns the unprivileged user with its group if set.
+    fn user(&self) -> Result<Option<(String, Option<String>)>, Error> {
+        match (&self.server.user, &self.server.group) {
+            (&Some(ref user), group) => Ok(Some((user.to_owned(), group.to_owned()))),
+            (&None, &Some(_)) => Err(Error::Invalid("The group requires the user".to_owned())),
+            (&None, &None) => Ok(None),
+        }
+    }
+
+    /// Validates the `[rate_limits]` section, which is empty if absent.
+    fn rate_limits(&self) -> Result<RateLimitsSection, Error> {
+        let rate_limits = self.rate_limits.clone().unwrap_or_default();
+        let limits = [
+            ("client", rate_limits.client),
+            ("relay", rate_limits.relay),
+            ("global", rate_limits.global),
+        ];
+        for &(name, limit) in limits.iter() {
+            if let Some((rate, burst)) = limit {
+                if rate == 0 || burst == 0 {
+                    return Err(Error::Invalid(format!(
+                        "The {} rate limit and burst must be positive",
+                        name
+                    )));
+                }
+            }
+        }
+        Ok(rate_limits)
+    }
+
+    /// Validates the `[subscriber_limits]` section if it is present.
+    fn subscriber_limits(
+        &self,
+    ) -> Result<Option<(Option<usize>, Option<usize>, DeniedAction)>, Error> {
+        let limits = match self.subscriber_limits {
+            Some(ref limits) => limits,
+            None => return Ok(None),
+        };
+        if limits.circuit_id == Some(0) || limits.remote_id == Some(0) {
+            return Err(Error::Invalid(
+                "The subscriber lease limits must be positive".to_owned(),
+            ));
+        }
+        let denied_action = decode_denied_action(limits.denied_action.as_ref())?;
+        Ok(Some((limits.circuit_id, limits.remote_id, denied_action)))
+    }
+
+    /// Validates the `[failover]` section if it is present.
+    /// Checks that the interfaces are bound to the server network or the configured subnets.
+    ///
+    /// Cannot be reloaded, since the interface indexes are looked up at startup.
+    fn interfaces(&self) -> Result<Vec<Interface>, Error> {
+        let contains = |address: Ipv4Addr, mask: Ipv4Addr, subnet: Ipv4Addr| {
+            let mask = u32::from(mask);
+            u32::from(address) & mask == u32::from(subnet) & mask
+        };
+        self.interfaces
+            .iter()
+            .map(|interface| {
+                let subnet = interface.subnet.unwrap_or(interface.address);
+                let is_known = contains(self.server.address, self.server.subnet_mask, subnet)
+                    || self
+                        .subnets
+                        .iter()
+                        .any(|section| contains(section.address, section.mask, subnet));
+                if !is_known {
+                    return Err(Error::Invalid(format!(
+                        "The interface {} is not bound to any subnet",
+                        interface.name
+                    )));
+                }
+                Ok(Interface {
+                    name: interface.name.to_owned(),
+                    address: interface.address,
+                    subnet,
+                })
+            }).collect()
+    }
+
+    fn failover(&self) -> Result<Option<(Role, Mode, SocketAddr, u32)>, Error> {
+        let failover = match self.failover {
+            Some(ref failover) => failover,
+            None => return Ok(None),
+        };
+        let role = match failover.role.as_str() {
+            "primary" => Role::Primary,
+            "secondary" => Role::Secondary,
+            role => return Err(Error::Invalid(format!("Invalid failover role {}", role))),
+        };
+        let mode = match failover.mode.as_str() {
+            "standby" => Mode::Standby,
+            "load_balance" => Mode::LoadBalance,
+            mode => return Err(Error::Invalid(format!("Invalid failover mode {}", mode))),
+        };
+        if failover.mclt == 0 {
+            return Err(Error::Invalid("The failover MCLT must be positive".to_owned()));
+        }
+        Ok(Some((role, mode, failover.address, failover.mclt)))
+    }
+
+    /// Creates the dynamic DNS updater if the `[ddns]` section is present.
+    fn dns_updater(&self) -> Result<Option<DnsUpdater>, Error> {
+        let ddns = match self.ddns {
+            Some(ref ddns) => ddns,
+            None => return Ok(None),
+        };
+
+        let key = match (&ddns.key_name, &ddns.key_secret) {
+            (&Some(ref name), &Some(ref secret)) => Some(TsigKey {
+                name: name.to_owned(),
+                secret: base64::decode(secret)
+                    .map_err(|_| Error::Invalid(format!("Invalid secret of the key {}", name)))?,
+            }),
+            (&None, &None) => None,
+            _ => {
+                return Err(Error::Invalid(
+                    "The DDNS key must have both a name and a secret".to_owned(),
+                ))
+            }
+        };
+
+        let mut zones = Vec::new();
+        for subnet in self.subnets.iter() {
+            if let Some(ref zone) = subnet.ddns_zone {
+                zones.push(DnsZone {
+                    address: subnet.address,
+                    mask: subnet.mask,
+                    forward_zone: zone.to_owned(),
+                    reverse_zone: subnet.ddns_reverse_zone.to_owned(),
+                });
+            }
+        }
+        if let Some(ref zone) = ddns.zone {
+            zones.push(DnsZone {
+                address: self.server.address,
+                mask: self.server.subnet_mask,
+                forward_zone: zone.to_owned(),
+                reverse_zone: ddns.reverse_zone.to_owned(),
+            });
+        }
+
+        Ok(Some(DnsUpdater::new(ddns.server, key, zones)))
+    }
+
+    /// Creates the client FQDN policy of the `[ddns]` section.
+    fn fqdn_policy(&self) -> Result<Option<FqdnPolicy>, Error> {
+        let ddns = match self.ddns {
+            Some(ref ddns) => ddns,
+            None => return Ok(None),
+        };
+        if let Some(ref prefix) = ddns.synthesized_prefix {
+            let is_valid = !prefix.is_empty()
+                && prefix.len() < 48
+                && !prefix.starts_with('-')
+                && prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+            if !is_valid {
+                return Err(Error::Invalid(format!(
+                    "Invalid synthesized host name prefix {}",
+                    prefix
+                )));
+            }
+        }
+        Ok(Some(FqdnPolicy {
+            override_client_updates: ddns.override_client_updates,
+            override_no_updates: ddns.override_no_updates,
+            synthesized_prefix: ddns.synthesized_prefix.to_owned(),
+        }))
+    }
+
+    /// Opens the lease event log if it is configured.
+    fn event_logger(&self) -> Result<Option<EventLogger>, Error> {
+        let event_log = match self.event_log {
+            Some(ref event_log) => event_log,
+            None => return Ok(None),
+        };
+
+        match event_log.path {
+            Some(ref path) => EventLogger::open(path).map(Some).map_err(|error| {
+                Error::Invalid(format!("Event log {} opening error: {}", path.display(), error))
+            }),
+            None => Ok(Some(EventLogger::stdout())),
+        }
+    }
+
+    /// Creates the webhook notifier if the `[webhook]` section is present.
+    fn webhook_notifier(&self) -> Result<Option<WebhookNotifier>, Error> {
+        let webhook = match self.webhook {
+            Some(ref webhook) => webhook,
+            None => return Ok(None),
+        };
+        if webhook.max_batch == Some(0) {
+            return Err(Error::Invalid("The webhook batch must not be empty".to_owned()));
+        }
+
+        WebhookNotifier::new(
+            &webhook.url,
+            webhook.authorization.to_owned(),
+            webhook.max_batch.unwrap_or(DEFAULT_WEBHOOK_MAX_BATCH),
+            Duration::from_secs(webhook.delay.unwrap_or(DEFAULT_WEBHOOK_DELAY)),
+            webhook.retries.unwrap_or(DEFAULT_WEBHOOK_RETRIES),
+        ).map(Some)
+        .map_err(|error| Error::Invalid(format!("Webhook {} error: {}", webhook.url, error)))
+    }
+
+    /// Validates the configuration and extracts the settings for a running server.
+    ///
+    /// # Errors
+    /// `self::Error` if a reservation or an option value is invalid.
+    pub fn into_reload(self) -> Result<Reload, Error> {
+        let raw_options = self
+            .options
+            .iter()
+            .map(|(key, value)| encode_option(key, value))
+            .collect::<Result<Vec<(u8, Vec<u8>)>, Error>>()?;
+
+        let server = self.server;
+        let access_list = decode_access_list(
+            &server.allow,
+            &server.deny,
+            server.denied_action.as_ref(),
+        )?;
+        let default_lease_time = server.default_lease_time.unwrap_or(DEFAULT_LEASE_TIME);
+        let max_lease_time = server.max_lease_time.unwrap_or(MAX_LEASE_TIME);
+        if default_lease_time == 0 || max_lease_time == 0 {
+            return Err(Error::Invalid("The lease times must be positive".to_owned()));
+        }
+
+        let mut subnets = Vec::with_capacity(self.subnets.len());
+        for subnet in self.subnets.into_iter() {
+            let exclusions = decode_exclusions(&subnet.exclusions, &subnet.ranges)?;
+            let scope = format!("the subnet {}", subnet.address);
+            subnets.push(Subnet {
+                address: subnet.address,
+                mask: subnet.mask,
+                ranges: subnet.ranges,
+                exclusions,
+                allocator: decode_allocator(subnet.allocator.as_ref())?,
+                routers: subnet.routers,
+                classless_static_routes: decode_routes(subnet.classless_static_routes)?,
+                circuit_ids: decode_ids(&subnet.circuit_ids, "circuit ID")?,
+                remote_ids: decode_ids(&subnet.remote_ids, "remote ID")?,
+                authoritative: subnet.authoritative,
+                lease_times: decode_lease_times(
+                    &scope,
+                    subnet.default_lease_time,
+                    subnet.max_lease_time,
+                    max_lease_time,
+                )?,
+                renewal_times: decode_renewal_times(
+                    &scope,
+                    subnet.renewal_time,
+                    subnet.rebinding_time,
+                )?,
+                access_list: decode_access_list(
+                    &subnet.allow,
+                    &subnet.deny,
+                    subnet.denied_action.as_ref(),
+                )?,
+                boot: match subnet.boot {
+                    Some(boot) => Some(decode_boot(boot)?),
+                    None => None,
+                },
+                authentication: match subnet.authentication {
+                    Some(ref authentication) => Some(decode_authentication(authentication)?),
+                    None => None,
+                },
+                options: decode_scope(&subnet.options, &subnet.append_options)?,
+            });
+        }
+
+        let mut shared_networks = Vec::with_capacity(self.shared_networks.len());
+        for shared_network in self.shared_networks.into_iter() {
+            for address in shared_network.subnets.iter() {
+                if !subnets.iter().any(|subnet| subnet.address == *address) {
+                    return Err(Error::Invalid(format!(
+                        "The shared network {} refers to the unknown subnet {}",
+                        shared_network.name, address
+                    )));
+                }
+            }
+            shared_networks.push(SharedNetwork {
+                options: decode_scope(&shared_network.options, &shared_network.append_options)?,
+                name: shared_network.name,
+                subnets: shared_network.subnets,
+            });
+        }
+
+        let mut reservations = Vec::with_capacity(self.reservations.len());
+        for reservation in self.reservations.into_iter() {
+            reservations.push(decode_reservation(reservation, max_lease_time)?);
+        }
+
+        let mut classes = Vec::with_capacity(self.classes.len());
+        for class in self.classes.into_iter() {
+            classes.push(decode_class(class, &subnets, max_lease_time)?);
+        }
+
+        let mut vendor_classes = Vec::with_capacity(self.vendor_classes.len());
+        for vendor_class in self.vendor_classes.iter() {
+            vendor_classes.push(decode_vendor_class(vendor_class)?);
+        }
+
+        let boot = match self.boot {
+            Some(boot) => Some(decode_boot(boot)?),
+            None => None,
+        };
+
+        let fingerprints = match server.fingerprints {
+            Some(ref path) => Some(FingerprintDatabase::from_file(path).map_err(|error| {
+                Error::Invalid(format!(
+                    "The fingerprint database {}: {}",
+                    path.display(),
+                    error
+                ))
+            })?),
+            None => None,
+        };
+
+        Ok(Reload {
+            server_ip_address: server.address,
+            iface_name: server.interface,
+            static_address_range: server.static_range,
+            dynamic_address_range: server.dynamic_range,
+            dynamic_exclusions: decode_exclusions(&server.exclusions, &[server.dynamic_range])?,
+            subnet_mask: server.subnet_mask,
+            routers: server.routers,
+            domain_name_servers: server.domain_name_servers,
+            static_routes: server.static_routes,
+            classless_static_routes: server.classless_static_routes,
+            lease_times: (default_lease_time, max_lease_time),
+            allocator: decode_allocator(server.allocator.as_ref())?,
+            affinity_retention: server.affinity_retention,
+            grace_period: server.grace_period,
+            raw_options,
+            subnets,
+            reservations,
+            classes,
+            shared_networks,
+            vendor_classes,
+            access_list,
+            boot,
+            bootp: server.bootp,
+            fingerprints,
+        })
+    }
+}
+
+/// Parses a JSON reservation with the `[[reservations]]` fields, like the one
+/// added over the admin API.
+///
+/// # Errors
+/// `self::Error` if the reservation is malformed or invalid.
+pub fn parse_reservation(data: &[u8]) -> Result<(HostKey, Reservation), Error> {
+    let reservation =
+        serde_json::from_slice(data).map_err(|error| Error::Parse(error.to_string()))?;
+    // the server-wide maximal lease time is applied on leasing
+    decode_reservation(reservation, u32::max_value())
+}
+
+fn decode_reservation(
+    reservation: ReservationSection,
+    max_lease_time: u32,
+) -> Result<(HostKey, Reservation), Error> {
+    let scope = format!("the reservation {}", reservation.address);
+    let key = match (
+        reservation.hardware_address,
+        reservation.client_id,
+        reservation.circuit_id,
+        reservation.remote_id,
+    ) {
+        (Some(hardware_address), None, None, None) => HostKey::HardwareAddress(
+            MacAddress::parse_str(&hardware_address).map_err(|_| {
+                Error::Invalid(format!("Invalid hardware address {}", hardware_address))
+            })?,
+        ),
+        (None, Some(client_id), None, None) => {
+            HostKey::ClientId(decode_id(&client_id, "client ID")?)
+        }
+        (None, None, Some(circuit_id), None) => {
+            HostKey::CircuitId(decode_id(&circuit_id, "circuit ID")?)
+        }
+        (None, None, None, Some(remote_id)) => {
+            HostKey::RemoteId(decode_id(&remote_id, "remote ID")?)
+        }
+        _ => {
+            return Err(Error::Invalid(format!(
+                "The reservation of {} must have exactly one of a hardware address, \
+                 a client ID, a circuit ID or a remote ID",
+                reservation.address
+            )))
+        }
+    };
+    Ok((
+        key,
+        Reservation {
+            address: reservation.address,
+            hostname: reservation.hostname,
+            boot_filename: reservation.boot_filename,
+            lease_times: decode_lease_times(
+                &scope,
+                reservation.default_lease_time,
+                reservation.max_lease_time,
+                max_lease_time,
+            )?,
+            renewal_times: decode_renewal_times(
+                &scope,
+                reservation.renewal_time,
+                reservation.rebinding_time,
+            )?,
+            options: decode_scope(&reservation.options, &reservation.append_options)?,
+        },
+    ))
+}
+
+/// Encodes the option tables of a scope below the global one.
+fn decode_scope(
+    options: &BTreeMap<String, Value>,
+    append_options: &BTreeMap<String, Value>,
+) -> Result<ScopeOptions, Error> {
+    Ok(ScopeOptions {
+        overrides: options
+            .iter()
+            .map(|(key, value)| encode_scope_option(key, value))
+            .collect::<Result<Vec<(u8, Vec<u8>)>, Error>>()?,
+        appends: append_options
+            .iter()
+            .map(|(key, value)| encode_scope_option(key, value))
+            .collect::<Result<Vec<(u8, Vec<u8>)>, Error>>()?,
+    })
+}
+
+/// Unlike the `[options]` table, the scopes may set `domain_name_servers`
+/// to replace or extend the `[server]` ones.
+fn encode_scope_option(key: &str, value: &Value) -> Result<(u8, Vec<u8>), Error> {
+    if key == "domain_name_servers" {
+        let data = encode_addresses(value)
+            .ok_or_else(|| Error::Invalid(format!("Invalid value of the option {}", key)))?;
+        return Ok((OptionTag::DomainNameServers as u8, data));
+    }
+    encode_option(key, value)
+}
+
+/// Encodes a named option according to its kind or a raw one as is.
+///
+/// A raw option value may be a string, an array of addresses or an array of bytes.
+fn encode_option(key: &str, value: &Value) -> Result<(u8, Vec<u8>), Error> {
+    let invalid = || Error::Invalid(format!("Invalid value of the option {}", key));
+
+    if let Some(&(_, tag, kind)) = NAMED_OPTIONS.iter().find(|&&(name, _, _)| name == key) {
+        let data = match kind {
+            Kind::Address => encode_address(value).ok_or_else(invalid)?,
+            Kind::Addresses => encode_addresses(value).ok_or_else(invalid)?,
+            Kind::Text => value.as_str().ok_or_else(invalid)?.as_bytes().to_vec(),
+            Kind::U8 => encode_integer(value, 1).ok_or_else(invalid)?,
+            Kind::U16 => encode_integer(value, 2).ok_or_else(invalid)?,
+            Kind::U32 => encode_integer(value, 4).ok_or_else(invalid)?,
+        };
+        return Ok((tag as u8, data));
+    }
+
+    let tag: u8 = key
+        .parse()
+        .map_err(|_| Error::Invalid(format!("Unknown option {}", key)))?;
+    if RESERVED_OPTIONS
+        .iter()
+        .any(|reserved| *reserved as u8 == tag)
+    {
+        return Err(Error::Invalid(format!(
+            "The option {} cannot be set in the options table",
+            tag
+        )));
+    }
+    Ok((tag, encode_raw(value).ok_or_else(invalid)?))
+}
+
+/// Encodes a string, an array of addresses or an array of bytes.
+fn encode_raw(value: &Value) -> Option<Vec<u8>> {
+    match *value {
+        Value::String(ref string) => Some(string.as_bytes().to_vec()),
+        Value::Array(ref array) if array.iter().all(Value::is_str) => encode_addresses(value),
+        Value::Array(ref array) => array
+            .iter()
+            .map(|byte| match byte.as_integer() {
+                Some(byte) if byte >= 0 && byte <= 0xff => Some(byte as u8),
+                _ => None,
+            }).collect(),
+        _ => None,
+    }
+}
+
+fn decode_vendor_class(vendor_class: &VendorClassSection) -> Result<VendorClass, Error> {
+    let name = &vendor_class.name;
+    let invalid =
+        |what: &str| Error::Invalid(format!("Invalid {} of the vendor class {}", what, name));
+
+    let data = match (vendor_class.data.as_ref(), vendor_class.suboptions.is_empty()) {
+        (Some(data), true) => hex_decode(data).ok_or_else(|| invalid("data"))?,
+        (None, false) => {
+            let mut suboptions = Vec::with_capacity(vendor_class.suboptions.len());
+            for (code, value) in vendor_class.suboptions.iter() {
+                let code = match code.parse::<u8>() {
+                    Ok(code) if code != 0 && code != 0xff => code,
+                    _ => return Err(invalid("sub-option code")),
+                };
+                suboptions.push((code, encode_raw(value).ok_or_else(|| invalid("sub-option"))?));
+            }
+            encode_suboptions(&suboptions).ok_or_else(|| invalid("sub-option length"))?
+        }
+        _ => {
+            return Err(Error::Invalid(format!(
+                "The vendor class {} must have exactly one of data or sub-options",
+                name
+            )))
+        }
+    };
+    if data.len() > 0xff {
+        return Err(invalid("data length"));
+    }
+    Ok(VendorClass {
+        name: name.to_owned(),
+        prefix: vendor_class.vendor_class.as_bytes().to_vec(),
+        data,
+    })
+}
+
+fn decode_class(
+    class: ClassSection,
+    subnets: &[Subnet],
+    max_lease_time: u32,
+) -> Result<Class, Error> {
+    let mut matchers = Vec::new();
+    if let Some(vendor_class) = class.vendor_class {
+        matchers.push(Matcher::VendorClass(vendor_class.into_bytes()));
+    }
+    if let Some(user_class) = class.user_class {
+        matchers.push(Matcher::UserClass(user_class.into_bytes()));
+    }
+    if let Some(oui) = class.oui {
+        let name = &class.name;
+        matchers.push(Matcher::Oui(decode_oui(&oui).ok_or_else(|| {
+            Error::Invalid(format!("Invalid OUI {} of the class {}", oui, name))
+        })?));
+    }
+    if let Some(fingerprint) = class.fingerprint {
+        matchers.push(Matcher::Fingerprint(fingerprint));
+    }
+    if let Some(device_type) = class.device_type {
+        matchers.push(Matcher::DeviceType(device_type));
+    }
+    if let Some(circuit_id) = class.circuit_id {
+        matchers.push(Matcher::CircuitId(decode_id(&circuit_id, "circuit ID")?));
+    }
+    if let Some(remote_id) = class.remote_id {
+        matchers.push(Matcher::RemoteId(decode_id(&remote_id, "remote ID")?));
+    }
+    if matchers.is_empty() {
+        return Err(Error::Invalid(format!(
+            "The class {} must have at least one condition",
+            class.name
+        )));
+    }
+
+    if let Some(address) = class.subnet {
+        if !subnets.iter().any(|subnet| subnet.address == address) {
+            return Err(Error::Invalid(format!(
+                "The class {} refers to the unknown subnet {}",
+                class.name, address
+            )));
+        }
+    }
+
+    let scope = format!("the class {}", class.name);
+    let lease_times = decode_lease_times(
+        &scope,
+        class.default_lease_time,
+        class.max_lease_time,
+        max_lease_time,
+    )?;
+    let renewal_times = decode_renewal_times(&scope, class.renewal_time, class.rebinding_time)?;
+
+    Ok(Class {
+        options: decode_scope(&class.options, &class.append_options)?,
+        name: class.name,
+        matchers,
+        subnet: class.subnet,
+        lease_times,
+        renewal_times,
+        classless_static_routes: decode_routes(class.classless_static_routes)?,
+    })
+}
+
+/// Decodes the default and maximal lease times of a scope, clamped to the server-wide maximum.
+///
+/// The default lease time is the maximal one if only the latter is set.
+fn decode_lease_times(
+    scope: &str,
+    default: Option<u32>,
+    max: Option<u32>,
+    max_lease_time: u32,
+) -> Result<Option<(u32, u32)>, Error> {
+    match (default, max) {
+        (None, None) => Ok(None),
+        (default, max) => {
+            let max = max.unwrap_or(max_lease_time).min(max_lease_time);
+            let default = default.unwrap_or(max).min(max);
+            if default == 0 || max == 0 {
+                return Err(Error::Invalid(format!(
+                    "The lease times of {} must be positive",
+                    scope
+                )));
+            }
+            Ok(Some((default, max)))
+        }
+    }
+}
+
+/// Decodes the renewal (T1) and rebinding (T2) times of a scope.
+fn decode_renewal_times(
+    scope: &str,
+    renewal: Option<u32>,
+    rebinding: Option<u32>,
+) -> Result<Option<(u32, u32)>, Error> {
+    match (renewal, rebinding) {
+        (None, None) => Ok(None),
+        (Some(renewal), Some(rebinding)) if renewal > 0 && renewal < rebinding => {
+            Ok(Some((renewal, rebinding)))
+        }
+        (Some(_), Some(_)) => Err(Error::Invalid(format!(
+            "The renewal time of {} must be positive and less than the rebinding one",
+            scope
+        ))),
+        _ => Err(Error::Invalid(format!(
+            "The renewal and rebinding times of {} must be set together",
+            scope
+        ))),
+    }
+}
+
+fn decode_boot(boot: BootSection) -> Result<Boot, Error> {
+    let mut overrides = Vec::new();
+    for (architecture, parameters) in boot.architectures.into_iter() {
+        let codes = match architecture.as_str() {
+            "bios" => vec![ARCHITECTURE_X86_BIOS],
+            "uefi_ia32" => vec![ARCHITECTURE_EFI_IA32],
+            "uefi_x64" => vec![ARCHITECTURE_EFI_BC, ARCHITECTURE_EFI_X86_64],
+            "uefi_arm64" => vec![ARCHITECTURE_EFI_ARM64],
+            code => vec![code.parse().map_err(|_| {
+                Error::Invalid(format!("Unknown client architecture {}", architecture))
+            })?],
+        };
+        for code in codes.into_iter() {
+            overrides.push((
+                code,
+                BootParameters {
+                    next_server: parameters.next_server,
+                    server_name: parameters.server_name.to_owned(),
+                    boot_filename: parameters.boot_filename.to_owned(),
+                },
+            ));
+        }
+    }
+    Ok(Boot {
+        default: BootParameters {
+            next_server: boot.next_server,
+            server_name: boot.server_name,
+            boot_filename: boot.boot_filename,
+        },
+        overrides,
+    })
+}
+
+fn decode_allocator(allocator: Option<&String>) -> Result<Arc<Allocator>, Error> {
+    Ok(match allocator.map(String::as_str) {
+        None | Some("sequential") => Arc::new(SequentialAllocator),
+        Some("random") => Arc::new(RandomAllocator),
+        Some("hash") => Arc::new(HashAllocator),
+        Some(allocator) => {
+            return Err(Error::Invalid(format!("Unknown allocator {}", allocator)))
+        }
+    })
+}
+
+/// Converts the addresses to single address ranges and checks that
+/// every range lies inside one of the pool `ranges`.
+fn decode_exclusions(
+    exclusions: &[ExclusionSection],
+    ranges: &[(Ipv4Addr, Ipv4Addr)],
+) -> Result<Vec<(Ipv4Addr, Ipv4Addr)>, Error> {
+    exclusions
+        .iter()
+        .map(|exclusion| {
+            let (first, last) = match *exclusion {
+                ExclusionSection::Address(address) => (address, address),
+                ExclusionSection::Range(first, last) => (first, last),
+            };
+            let is_inside = ranges
+                .iter()
+                .any(|&(start, end)| start <= first && first <= last && last <= end);
+            if !is_inside {
+                return Err(Error::Invalid(format!(
+                    "The exclusion {}-{} is not inside a pool range",
+                    first, last
+                )));
+            }
+            Ok((first, last))
+        })
+        .collect()
+}
+
+/// Checks that the masks are contiguous and the destinations have no host bits.
+fn decode_routes(
+    routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+) -> Result<Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>, Error> {
+    for &(destination, mask, _) in routes.iter() {
+        let host_bits = !u32::from(mask);
+        let is_contiguous = host_bits & host_bits.wrapping_add(1) == 0;
+        if !is_contiguous || u32::from(destination) & host_bits != 0 {
+            return Err(Error::Invalid(format!(
+                "Invalid route to {}/{}",
+                destination, mask
+            )));
+        }
+    }
+    Ok(routes)
+}
+
+fn decode_authentication(
+    authentication: &AuthenticationSection,
+) -> Result<AuthenticationKey, Error> {
+    let failure_action = match authentication.failure_action.as_ref().map(String::as_str) {
+        None | Some("drop") => FailureAction::Drop,
+        Some("log") => FailureAction::Log,
+        Some(action) => {
+            return Err(Error::Invalid(format!(
+                "Invalid authentication failure action {}",
+                action
+            )))
+        }
+    };
+    // the key is not quoted in the error, since it is a secret
+    let key = match hex_decode(&authentication.key) {
+        Some(ref key) if !key.is_empty() => key.to_owned(),
+        _ => return Err(Error::Invalid("Invalid authentication key".to_owned())),
+    };
+    Ok(AuthenticationKey {
+        realm: authentication.realm.as_bytes().to_vec(),
+        secret_id: authentication.secret_id,
+        key,
+        failure_action,
+    })
+}
+
+/// Returns `None` if there are no patterns.
+fn decode_access_list(
+    allow: &[String],
+    deny: &[String],
+    denied_action: Option<&String>,
+) -> Result<Option<AccessList>, Error> {
+    let denied_action = decode_denied_action(denied_action)?;
+    if allow.is_empty() && deny.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(AccessList {
+        allow: decode_mac_patterns(allow)?,
+        deny: decode_mac_patterns(deny)?,
+        denied_action,
+    }))
+}
+
+/// `ignore` by default.
+fn decode_denied_action(denied_action: Option<&String>) -> Result<DeniedAction, Error> {
+    match denied_action.map(String::as_str) {
+        None | Some("ignore") => Ok(DeniedAction::Ignore),
+        Some("nak") => Ok(DeniedAction::Nak),
+        Some(action) => Err(Error::Invalid(format!(
+            "Invalid denied client action {}",
+            action
+        ))),
+    }
+}
+
+fn decode_mac_patterns(patterns: &[String]) -> Result<Vec<MacPattern>, Error> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            MacPattern::parse(pattern).ok_or_else(|| {
+                Error::Invalid(format!("Invalid hardware address pattern {}", pattern))
+            })
+        }).collect()
+}
+
+/// Like `00:11:22`.
+fn decode_oui(oui: &str) -> Option<[u8; 3]> {
+    let octets = oui
+        .split(':')
+        .map(|octet| u8::from_str_radix(octet, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    if octets.len() != 3 {
+        return None;
+    }
+    Some([octets[0], octets[1], octets[2]])
+}
+
+fn decode_id(id: &str, kind: &str) -> Result<Vec<u8>, Error> {
+    hex_decode(id).ok_or_else(|| Error::Invalid(format!("Invalid {} {}", kind, id)))
+}
+
+fn decode_ids(ids: &[String], kind: &str) -> Result<Vec<Vec<u8>>, Error> {
+    ids.iter().map(|id| decode_id(id, kind)).collect()
+}
+
+fn encode_address(value: &Value) -> Option<Vec<u8>> {
+    let address: Ipv4Addr = value.as_str()?.parse().ok()?;
+    Some(address.octets().to_vec())
+}
+
+fn encode_addresses(value: &Value) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    for address in value.as_array()?.iter() {
+        data.extend(encode_address(address)?);
+    }
+    Some(data)
+}
+
+/// Big-endian, checking the value fits the size.
+fn encode_integer(value: &Value, size: usize) -> Option<Vec<u8>> {
+    let value = value.as_integer()?;
+    if value < 0 || (value as u64) >> (size * 8) != 0 {
+        return None;
+    }
+    Some(
+        (0..size)
+            .rev()
+            .map(|index| (value >> (index * 8)) as u8)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(data: &str) -> Result<Class, Error> {
+        let section: ClassSection = toml::from_str(data).unwrap();
+        decode_class(section, &[], 86400)
+    }
+
+    fn invalid(result: Result<Class, Error>) -> String {
+        match result {
+            Err(Error::Invalid(desc)) => desc,
+            Err(error) => panic!("unexpected {}", error),
+            Ok(class) => panic!("unexpected class {}", class.name),
+        }
+    }
+
+    #[test]
+    fn decodes_class_matchers() {
+        // the class section and the debug output of its matchers
+        let cases = [
+            (
+                "name = \"phones\"\nvendor_class = \"android\"",
+                "[VendorClass([97, 110, 100, 114, 111, 105, 100])]",
+            ),
+            ("name = \"x\"\nuser_class = \"ab\"", "[UserClass([97, 98])]"),
+            ("name = \"x\"\noui = \"00:11:a2\"", "[Oui([0, 17, 162])]"),
+            (
+                "name = \"x\"\nfingerprint = [1, 3, 6]",
+                "[Fingerprint([1, 3, 6])]",
+            ),
+            (
+                "name = \"x\"\ndevice_type = \"printer\"",
+                "[DeviceType(\"printer\")]",
+            ),
+            (
+                "name = \"x\"\ncircuit_id = \"0a0b\"\nremote_id = \"ff\"",
+                "[CircuitId([10, 11]), RemoteId([255])]",
+            ),
+            (
+                "name = \"x\"\nvendor_class = \"a\"\noui = \"00:00:5e\"",
+                "[VendorClass([97]), Oui([0, 0, 94])]",
+            ),
+        ];
+        for &(data, matchers) in cases.iter() {
+            let class = class(data).unwrap_or_else(|error| panic!("{}: {}", data, error));
+            assert_eq!(format!("{:?}", class.matchers), matchers, "{}", data);
+        }
+    }
+
+    #[test]
+    fn decodes_class_times() {
+        // the class section, and the lease and renewal times
+        let cases = [
+            ("", None, None),
+            ("default_lease_time = 3600", Some((3600, 86400)), None),
+            ("max_lease_time = 7200", Some((7200, 7200)), None),
+            ("max_lease_time = 604800", Some((86400, 86400)), None),
+            (
+                "default_lease_time = 7200\nmax_lease_time = 3600",
+                Some((3600, 3600)),
+                None,
+            ),
+            (
+                "renewal_time = 1800\nrebinding_time = 3150",
+                None,
+                Some((1800, 3150)),
+            ),
+        ];
+        for &(times, lease_times, renewal_times) in cases.iter() {
+            let data = format!("name = \"x\"\nuser_class = \"a\"\n{}", times);
+            let class = class(&data).unwrap_or_else(|error| panic!("{}: {}", data, error));
+            assert_eq!(class.lease_times, lease_times, "{}", times);
+            assert_eq!(class.renewal_times, renewal_times, "{}", times);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_classes() {
+        // the class section and the expected error description
+        let cases = [
+            (
+                "name = \"empty\"",
+                "The class empty must have at least one condition",
+            ),
+            (
+                "name = \"x\"\noui = \"00:11\"",
+                "Invalid OUI 00:11 of the class x",
+            ),
+            (
+                "name = \"x\"\noui = \"00:11:22:33\"",
+                "Invalid OUI 00:11:22:33 of the class x",
+            ),
+            (
+                "name = \"x\"\noui = \"zz:11:22\"",
+                "Invalid OUI zz:11:22 of the class x",
+            ),
+            ("name = \"x\"\ncircuit_id = \"0g\"", "Invalid circuit ID 0g"),
+            ("name = \"x\"\nremote_id = \"abc\"", "Invalid remote ID abc"),
+            (
+                "name = \"x\"\nuser_class = \"a\"\nsubnet = \"10.0.0.0\"",
+                "The class x refers to the unknown subnet 10.0.0.0",
+            ),
+            (
+                "name = \"x\"\nuser_class = \"a\"\nrenewal_time = 1800",
+                "The renewal and rebinding times of the class x must be set together",
+            ),
+            (
+                "name = \"x\"\nuser_class = \"a\"\nrenewal_time = 3600\nrebinding_time = 1800",
+                "The renewal time of the class x must be positive and less than the rebinding one",
+            ),
+        ];
+        for &(data, expected) in cases.iter() {
+            assert_eq!(invalid(class(data)), expected, "{}", data);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_class_fields() {
+        let result = toml::from_str::<ClassSection>("name = \"x\"\nvendor = \"a\"");
+        assert!(result.is_err());
+    }
+}
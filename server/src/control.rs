@@ -0,0 +1,350 @@
+//! The local control socket for scripting the server without the HTTP API.
+//!
+//! Every request and reply is a single line. The requests are:
+//!
+//! * `query lease <address>` lists the lease of the address, if any.
+//! * `query leases [<key>=<value> ...]` lists the leases matching the `/leases` admin API
+//! parameters, like `query leases state=active`, or all the leases without them.
+//! * `del lease <address>` frees the address.
+//! * `forcerenew lease <address>` sends a `DHCPFORCERENEW` to the client of the lease.
+//! * `forcerenew pool <subnet>` sends a `DHCPFORCERENEW` to the clients of the subnet.
+//! * `add reservation <json>` adds a reservation with the `[[reservations]]` fields,
+//! which is kept until the next configuration reload.
+//! * `stats` shows the address counters and the high-water marks of the pool ranges.
+//! * `shutdown` stops the server.
+//!
+//! A reply is an HTTP-like status followed by the admin API JSON body,
+//! like `200 {"message":"The lease has been deleted"}`.
+//!
+//! The socket file is accessible only by its owner.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::Ipv4Addr,
+    os::unix::{fs::PermissionsExt, net::UnixStream as StdUnixStream},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{self, Value};
+use tokio::{
+    codec::{Framed, LinesCodec},
+    io,
+    prelude::*,
+};
+use tokio_uds::{Incoming, UnixListener, UnixStream};
+
+use admin::{Command, LeaseFilter, RenewalTarget};
+use config::parse_reservation;
+use http::Response;
+
+/// The permissions of the socket file.
+const SOCKET_MODE: u32 = 0o600;
+
+/// A connection of a control client.
+struct Connection {
+    id: usize,
+    stream: Framed<UnixStream, LinesCodec>,
+    /// The replies waiting for the connection to accept them.
+    outgoing: VecDeque<String>,
+}
+
+/// The listener of the control connections.
+pub struct ControlSocket {
+    path: PathBuf,
+    listener: Incoming,
+    connections: Vec<Connection>,
+    next_id: usize,
+}
+
+impl ControlSocket {
+    /// Starts listening at the `path`, replacing the socket file left by a previous run.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot be bound.
+    pub fn new(path: PathBuf) -> io::Result<Self> {
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        let listener = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(SOCKET_MODE))?;
+        Ok(ControlSocket {
+            path,
+            listener: listener.incoming(),
+            connections: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Returns the next command with the ID of its connection, accepting the new connections.
+    ///
+    /// The invalid requests are answered here.
+    pub fn poll_command(&mut self) -> Option<(usize, Command)> {
+        self.poll_accept();
+        self.flush();
+
+        let mut index = 0;
+        while index < self.connections.len() {
+            let result = self.connections[index].stream.poll();
+            match result {
+                Ok(Async::Ready(Some(line))) => {
+                    let id = self.connections[index].id;
+                    info!(target: "audit", "Control socket request {}", line);
+                    match command(&line) {
+                        Ok(command) => return Some((id, command)),
+                        Err(response) => self.respond(id, response),
+                    }
+                }
+                Ok(Async::Ready(None)) => {
+                    trace!("A control client has disconnected");
+                    self.connections.remove(index);
+                }
+                Ok(Async::NotReady) => index += 1,
+                Err(error) => {
+                    warn!("Control socket receiving error: {}", error);
+                    self.connections.remove(index);
+                }
+            }
+        }
+        None
+    }
+
+    /// Queues the reply to a command received on the connection.
+    ///
+    /// The reply is dropped if the client has already disconnected.
+    pub fn respond(&mut self, id: usize, response: Response) {
+        if let Some(connection) = self
+            .connections
+            .iter_mut()
+            .find(|connection| connection.id == id)
+        {
+            connection
+                .outgoing
+                .push_back(format!("{} {}", response.status(), response.body()));
+        }
+        self.flush();
+    }
+
+    fn poll_accept(&mut self) {
+        loop {
+            let stream = match self.listener.poll() {
+                Ok(Async::Ready(Some(stream))) => stream,
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => return,
+                Err(error) => {
+                    warn!("Control socket accepting error: {}", error);
+                    return;
+                }
+            };
+            trace!("A control client has connected");
+            self.connections.push(Connection {
+                id: self.next_id,
+                stream: Framed::new(stream, LinesCodec::new()),
+                outgoing: VecDeque::new(),
+            });
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+    }
+
+    /// Moves the queued replies to the connections until they are full.
+    fn flush(&mut self) {
+        let mut index = 0;
+        while index < self.connections.len() {
+            let result = {
+                let connection = &mut self.connections[index];
+                let stream = &mut connection.stream;
+                let outgoing = &mut connection.outgoing;
+                let mut result = Ok(());
+                while let Some(reply) = outgoing.pop_front() {
+                    match stream.start_send(reply) {
+                        Ok(AsyncSink::Ready) => {}
+                        Ok(AsyncSink::NotReady(reply)) => {
+                            outgoing.push_front(reply);
+                            break;
+                        }
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
+                }
+                result.and_then(|_| stream.poll_complete().map(|_| ()))
+            };
+            match result {
+                Ok(()) => index += 1,
+                Err(error) => {
+                    warn!("Control socket sending error: {}", error);
+                    self.connections.remove(index);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            warn!("Control socket removing error: {}", error);
+        }
+    }
+}
+
+/// Decodes the command or returns the error reply.
+fn command(line: &str) -> Result<Command, Response> {
+    let line = line.trim();
+    let (verb, argument) = match line.find(' ') {
+        Some(position) => (&line[..position], line[position + 1..].trim()),
+        None => (line, ""),
+    };
+    let parse_address = |address: &str| -> Result<Ipv4Addr, Response> {
+        address
+            .parse()
+            .map_err(|_| Response::error(400, &format!("Invalid address {}", address)))
+    };
+
+    let command = match (verb, argument) {
+        ("query", argument) if argument.starts_with("lease ") => Command::Leases(
+            LeaseFilter::by_address(parse_address(argument["lease ".len()..].trim())?),
+        ),
+        ("query", argument) if argument == "leases" || argument.starts_with("leases ") => {
+            let query: Vec<(String, String)> = argument["leases".len()..]
+                .split_whitespace()
+                .map(|parameter| {
+                    let mut parts = parameter.splitn(2, '=');
+                    let key = parts.next().unwrap_or("").to_owned();
+                    (key, parts.next().unwrap_or("").to_owned())
+                }).collect();
+            Command::Leases(
+                LeaseFilter::from_query(&query).map_err(|error| Response::error(400, &error))?,
+            )
+        }
+        ("del", argument) if argument.starts_with("lease ") => {
+            Command::DeleteLease(parse_address(argument["lease ".len()..].trim())?)
+        }
+        ("forcerenew", argument) if argument.starts_with("lease ") => Command::ForceRenew(
+            RenewalTarget::Lease(parse_address(argument["lease ".len()..].trim())?),
+        ),
+        ("forcerenew", argument) if argument.starts_with("pool ") => Command::ForceRenew(
+            RenewalTarget::Subnet(parse_address(argument["pool ".len()..].trim())?),
+        ),
+        ("add", argument) if argument.starts_with("reservation ") => {
+            let (key, reservation) = parse_reservation(argument["reservation ".len()..].as_bytes())
+                .map_err(|error| Response::error(400, &error.to_string()))?;
+            Command::AddReservation(key, reservation)
+        }
+        ("stats", "") => Command::Pools,
+        ("shutdown", "") => Command::Shutdown,
+        _ => return Err(Response::error(400, &format!("Unknown request {}", line))),
+    };
+    Ok(command)
+}
+
+/// A reply of the server.
+#[derive(Debug)]
+pub struct ControlReply {
+    /// HTTP-like, e.g. 200 or 404.
+    pub status: u16,
+    pub body: Value,
+}
+
+impl ControlReply {
+    /// Checks whether the request has succeeded.
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+}
+
+/// A blocking client of the control socket for the scripts and the tools.
+pub struct ControlClient {
+    reader: BufReader<StdUnixStream>,
+    writer: StdUnixStream,
+}
+
+impl ControlClient {
+    /// Connects to the socket of a running server.
+    ///
+    /// # Errors
+    /// `io::Error` if the socket cannot be connected.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let writer = StdUnixStream::connect(path)?;
+        Ok(ControlClient {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+        })
+    }
+
+    pub fn query_lease(&mut self, address: Ipv4Addr) -> io::Result<ControlReply> {
+        self.request(&format!("query lease {}", address))
+    }
+
+    /// The `query` holds the `/leases` admin API parameters, like `("state", "active")`,
+    /// and all the leases are listed if it is empty.
+    pub fn query_leases(&mut self, query: &[(&str, &str)]) -> io::Result<ControlReply> {
+        let mut request = "query leases".to_owned();
+        for &(key, value) in query.iter() {
+            request.push_str(&format!(" {}={}", key, value));
+        }
+        self.request(&request)
+    }
+
+    pub fn delete_lease(&mut self, address: Ipv4Addr) -> io::Result<ControlReply> {
+        self.request(&format!("del lease {}", address))
+    }
+
+    /// The `reservation` is a JSON object with the `[[reservations]]` fields.
+    pub fn force_renew_lease(&mut self, address: Ipv4Addr) -> io::Result<ControlReply> {
+        self.request(&format!("forcerenew lease {}", address))
+    }
+
+    pub fn force_renew_pool(&mut self, subnet: Ipv4Addr) -> io::Result<ControlReply> {
+        self.request(&format!("forcerenew pool {}", subnet))
+    }
+
+    pub fn add_reservation(&mut self, reservation: &Value) -> io::Result<ControlReply> {
+        self.request(&format!("add reservation {}", reservation))
+    }
+
+    pub fn stats(&mut self) -> io::Result<ControlReply> {
+        self.request("stats")
+    }
+
+    pub fn shutdown(&mut self) -> io::Result<ControlReply> {
+        self.request("shutdown")
+    }
+
+    /// Sends a raw request line and waits for the reply.
+    ///
+    /// # Errors
+    /// `io::Error` if the request contains a line break, the connection fails
+    /// or the reply is malformed.
+    pub fn request(&mut self, request: &str) -> io::Result<ControlReply> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        if request.contains('\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The request must be a single line",
+            ));
+        }
+        self.writer.write_all(format!("{}\n", request).as_bytes())?;
+        self.writer.flush()?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The server has closed the connection",
+            ));
+        }
+        let mut parts = line.trim_right().splitn(2, ' ');
+        let status = parts
+            .next()
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| invalid("Malformed reply status"))?;
+        let body = serde_json::from_str(parts.next().unwrap_or("null"))
+            .map_err(|_| invalid("Malformed reply body"))?;
+        Ok(ControlReply { status, body })
+    }
+}
@@ -1,38 +1,88 @@
 //! The DHCP database implementation with address allocating algorithms.
 
-use std::{cmp, net::Ipv4Addr};
+use std::{
+    cmp,
+    collections::{HashMap, HashSet},
+    error, fmt,
+    net::Ipv4Addr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
 
-use lease::Lease;
+use chrono::prelude::*;
+
+use dhcp_protocol::RelayAgentInformation;
+
+use allocator::{Allocator, SequentialAllocator};
+use lease::{Lease, INFINITE_LEASE_TIME};
 use storage::{self, Storage};
+use subnet::{count_covered, is_in_any, Subnet};
+use wheel::TimerWheel;
 
 /// T1 RFC 2131 suggestion.
 const RENEWAL_TIME_FACTOR: f64 = 0.5;
 /// T2 RFC 2131 suggestion.
 const REBINDING_TIME_FACTOR: f64 = 0.875;
 /// 24 hours.
-const DEFAULT_LEASE_TIME: u32 = 60 * 60 * 24;
+pub const DEFAULT_LEASE_TIME: u32 = 60 * 60 * 24;
 /// 1 week.
-const MAX_LEASE_TIME: u32 = 60 * 60 * 24 * 7;
+pub const MAX_LEASE_TIME: u32 = 60 * 60 * 24 * 7;
+/// Outlasts the client retransmissions of a transaction.
+const TRANSACTION_CLAIM_TIMEOUT: u32 = 120;
+
+/// A pool range with the subnet it belongs to and its exclusions.
+type PoolRange<'a> = (Option<Ipv4Addr>, (Ipv4Addr, Ipv4Addr), &'a [(Ipv4Addr, Ipv4Addr)]);
 
 /// Errors generated by `Database` methods.
-#[derive(Fail, Debug)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "Database error")]
     Storage(storage::Error),
-    #[fail(display = "The dynamic pool has been exhausted")]
     DynamicPoolExhausted,
+    ReservedAddressAllocated,
 
-    #[fail(display = "The requested address is not offered")]
     OfferNotFound,
-    #[fail(display = "The offer is invalid")]
     OfferInvalid,
-    #[fail(display = "The offer is expired")]
     OfferExpired,
 
-    #[fail(display = "Lease not found")]
     LeaseNotFound,
-    #[fail(display = "The lease is invalid")]
     LeaseInvalid,
+    WrongNetwork,
+
+    DeclineInvalid,
+
+    ClientDenied,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Error::Storage(_) => "Database error",
+            Error::DynamicPoolExhausted => "The dynamic pool has been exhausted",
+            Error::ReservedAddressAllocated => {
+                "The reserved address is allocated by another client"
+            }
+
+            Error::OfferNotFound => "The requested address is not offered",
+            Error::OfferInvalid => "The offer is invalid",
+            Error::OfferExpired => "The offer is expired",
+
+            Error::LeaseNotFound => "Lease not found",
+            Error::LeaseInvalid => "The lease is invalid",
+            Error::WrongNetwork => "The address is wrong for the network",
+
+            Error::DeclineInvalid => "The declined address is not allocated by the client",
+
+            Error::ClientDenied => "The client is not allowed",
+        })
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Storage(ref error) => Some(error),
+            _ => None,
+        }
+    }
 }
 
 impl From<storage::Error> for Error {
@@ -63,6 +113,127 @@ pub struct Ack {
     pub message: String,
 }
 
+/// The address counters of a pool range.
+#[derive(Debug, Serialize)]
+pub struct Utilization {
+    /// The network address of the subnet the range belongs to, if any.
+    pub subnet: Option<Ipv4Addr>,
+    pub first: Ipv4Addr,
+    pub last: Ipv4Addr,
+    /// Without the excluded addresses.
+    pub size: u32,
+    /// Offered or leased.
+    pub allocated: u32,
+    pub leased: u32,
+    /// Reserved for the hosts, whether allocated or not.
+    pub reserved: u32,
+    pub abandoned: u32,
+    /// Neither allocated, nor reserved, nor abandoned.
+    pub free: u32,
+    /// The most addresses allocated at once since the start.
+    pub high_water: u32,
+}
+
+/// The allocation state kept beside the lease storage.
+struct State {
+    /// The offer and lease expirations by client IDs.
+    expirations: TimerWheel<Vec<u8>>,
+    /// The former clients of the retained addresses and the times the addresses were freed.
+    affinity: HashMap<Ipv4Addr, (Vec<u8>, u32)>,
+    /// The offered or assigned addresses by client IDs and whether they are assigned.
+    allocations: HashMap<Vec<u8>, (Ipv4Addr, bool)>,
+    /// The addresses allocated by the pool ranges.
+    allocated: HashMap<(Ipv4Addr, Ipv4Addr), u32>,
+    /// The addresses assigned by the pool ranges.
+    leased: HashMap<(Ipv4Addr, Ipv4Addr), u32>,
+    /// The most addresses allocated at once by the pool ranges, raised on each allocation.
+    high_water: HashMap<(Ipv4Addr, Ipv4Addr), u32>,
+    /// If the stored leases have been scheduled by a database sharing the state.
+    is_loaded: bool,
+}
+
+impl State {
+    /// Counts the allocation of the client in the pool ranges containing it
+    /// instead of the former one and raises their high-water marks.
+    fn count_allocation(
+        &mut self,
+        ranges: &[PoolRange],
+        client_id: &[u8],
+        allocation: (Ipv4Addr, bool),
+    ) {
+        let former = self.allocations.insert(client_id.to_vec(), allocation);
+        if former == Some(allocation) {
+            return;
+        }
+        let (address, is_leased) = allocation;
+        for &(_, range, exclusions) in ranges.iter() {
+            if let Some((former, was_leased)) = former {
+                if is_in_pool(range, exclusions, &former) {
+                    uncount(&mut self.allocated, range);
+                    if was_leased {
+                        uncount(&mut self.leased, range);
+                    }
+                }
+            }
+            if is_in_pool(range, exclusions, &address) {
+                let allocated = {
+                    let allocated = self.allocated.entry(range).or_insert(0);
+                    *allocated += 1;
+                    *allocated
+                };
+                if is_leased {
+                    *self.leased.entry(range).or_insert(0) += 1;
+                }
+                let high_water = self.high_water.entry(range).or_insert(0);
+                *high_water = cmp::max(*high_water, allocated);
+            }
+        }
+    }
+
+    /// Uncounts the allocation of the client in the pool ranges containing it.
+    fn count_deallocation(&mut self, ranges: &[PoolRange], client_id: &[u8]) {
+        let (address, was_leased) = match self.allocations.remove(client_id) {
+            Some(allocation) => allocation,
+            None => return,
+        };
+        for &(_, range, exclusions) in ranges.iter() {
+            if is_in_pool(range, exclusions, &address) {
+                uncount(&mut self.allocated, range);
+                if was_leased {
+                    uncount(&mut self.leased, range);
+                }
+            }
+        }
+    }
+}
+
+/// The lease expirations, the retained addresses and the high-water marks.
+///
+/// The server workers sharing a storage within a process must share the state as well,
+/// so any worker reaps the leases allocated by the others. The clones share the state.
+#[derive(Clone)]
+pub struct WorkerState(Arc<Mutex<State>>);
+
+impl WorkerState {
+    pub fn new() -> Self {
+        WorkerState(Arc::new(Mutex::new(State {
+            expirations: TimerWheel::new(Utc::now().timestamp() as u32),
+            affinity: HashMap::new(),
+            allocations: HashMap::new(),
+            allocated: HashMap::new(),
+            leased: HashMap::new(),
+            high_water: HashMap::new(),
+            is_loaded: false,
+        })))
+    }
+}
+
+impl Default for WorkerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// DHCP persistent lease database.
 pub struct Database<S>
 where
@@ -72,6 +243,29 @@ where
     static_address_range: (Ipv4Addr, Ipv4Addr),
     /// The inclusive dynamic address range.
     dynamic_address_range: (Ipv4Addr, Ipv4Addr),
+    /// The inclusive ranges inside the dynamic one which are never allocated.
+    dynamic_exclusions: Vec<(Ipv4Addr, Ipv4Addr)>,
+    /// The addresses reserved for particular hosts.
+    reserved_addresses: HashSet<Ipv4Addr>,
+    /// The subnets with their own pools, used instead of the ranges above if selected.
+    subnets: Vec<Subnet>,
+    /// The network addresses of the subnets sharing a link, allocated from as a whole.
+    shared_networks: Vec<Vec<Ipv4Addr>>,
+    /// The lease time used if the client does not request one.
+    default_lease_time: u32,
+    /// The lease time requested by clients is clamped to this value.
+    max_lease_time: u32,
+    /// The `[from, to)` percent of every range the addresses are allocated from.
+    pool_share: (u32, u32),
+    /// Orders the dynamic range addresses outside the subnets.
+    allocator: Arc<Allocator>,
+    /// How long the address of an expired or released lease is kept for its client in seconds.
+    affinity_retention: u32,
+    /// How long the address of an expired or released lease is not allocated
+    /// to the other clients in seconds, even if the pools run out of addresses.
+    grace_period: u32,
+    /// The expirations, the retained addresses and the high-water marks.
+    state: WorkerState,
     /// A user defined persistent DHCP database.
     storage: S,
 }
@@ -89,10 +283,517 @@ where
         Database {
             static_address_range,
             dynamic_address_range,
+            dynamic_exclusions: Vec::new(),
+            reserved_addresses: HashSet::new(),
+            subnets: Vec::new(),
+            shared_networks: Vec::new(),
+            default_lease_time: DEFAULT_LEASE_TIME,
+            max_lease_time: MAX_LEASE_TIME,
+            pool_share: (0, 100),
+            allocator: Arc::new(SequentialAllocator),
+            affinity_retention: 0,
+            grace_period: 0,
+            state: WorkerState::new(),
             storage,
         }
     }
 
+    /// Shares the expirations, the retained addresses and the high-water marks
+    /// with the databases of the other workers.
+    ///
+    /// Must be called before `load_expirations`.
+    pub fn share_state(&mut self, state: WorkerState) {
+        self.state = state;
+    }
+
+    /// Schedules the expirations of the offers and leases found in the storage
+    /// and retains the addresses of the former leases for their clients.
+    ///
+    /// Does nothing if a database sharing the state has done it.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn load_expirations(&mut self) -> Result<(), Error> {
+        if self.state().is_loaded {
+            return Ok(());
+        }
+        for (client_id, lease) in self.storage.get_leases()?.into_iter() {
+            if lease.is_released() {
+                let released_at = lease.released_at();
+                self.retain(client_id.to_owned(), &lease, released_at);
+            } else if !lease.is_offered() && !lease.is_assigned() && lease.expires_at() != 0 {
+                let expires_at = lease.expires_at();
+                self.retain(client_id.to_owned(), &lease, expires_at);
+            }
+            if lease.is_allocated() {
+                self.count_allocation(&client_id, &lease.address(), lease.is_active());
+            }
+            self.schedule(client_id, &lease);
+        }
+        self.state().is_loaded = true;
+        Ok(())
+    }
+
+    /// Expires the offers and leases whose time has come and frees their addresses.
+    ///
+    /// Returns the client IDs and the addresses of the expired leases.
+    /// The expired offers are not returned, since they have not been committed.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn reap(&mut self) -> Result<Vec<(Vec<u8>, Ipv4Addr)>, Error> {
+        let now = Utc::now().timestamp() as u32;
+        let mut expired = Vec::new();
+        let client_ids = self.state().expirations.expire(now);
+        for client_id in client_ids.into_iter() {
+            let lease = match self.storage.get_lease(&client_id)? {
+                Some(lease) => lease,
+                None => continue,
+            };
+            let is_committed = if lease.is_assigned() && lease.is_expired() {
+                true
+            } else if lease.is_offered() && lease.is_offer_expired() {
+                false
+            } else {
+                // renewed, reassigned or released since scheduled
+                continue;
+            };
+
+            // another worker may have reassigned the lease since it has been read
+            let mut is_lapsed = false;
+            self.storage
+                .update_lease(&client_id, &mut |lease: &mut Lease| {
+                    if (lease.is_assigned() && lease.is_expired())
+                        || (lease.is_offered() && lease.is_offer_expired())
+                    {
+                        lease.expire();
+                        is_lapsed = true;
+                    }
+                })?;
+            if !is_lapsed {
+                continue;
+            }
+            self.count_deallocation(&client_id);
+            let address = lease.address();
+            if self.storage.get_client(&address)?.as_ref() == Some(&client_id) {
+                self.storage.delete_client(&address)?;
+            }
+            if is_committed {
+                trace!("The lease of {} by client {:?} has expired", address, client_id);
+                self.retain(client_id.to_owned(), &lease, now);
+                expired.push((client_id, address));
+            }
+        }
+        let retention = cmp::max(self.affinity_retention, self.grace_period);
+        self.state()
+            .affinity
+            .retain(|_, &mut (_, freed_at)| freed_at.saturating_add(retention) > now);
+        Ok(expired)
+    }
+
+    /// The Unix time the next offer or lease may expire at, which is when `reap` must be called.
+    pub fn next_expiration(&self) -> Option<u32> {
+        self.state().expirations.next_deadline()
+    }
+
+    /// Reclaims the space taken by the outdated storage records.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        Ok(self.storage.compact()?)
+    }
+
+    /// Sets the default and maximal lease times in seconds.
+    pub fn set_lease_times(&mut self, default_lease_time: u32, max_lease_time: u32) {
+        self.default_lease_time = cmp::min(default_lease_time, max_lease_time);
+        self.max_lease_time = max_lease_time;
+    }
+
+    /// Restricts the allocations to a part of every range, so the failover peer
+    /// may allocate from the rest.
+    pub fn set_pool_share(&mut self, from: u32, to: u32) {
+        self.pool_share = (from, to);
+    }
+
+    /// Claims the transaction of the client for the worker `owner`,
+    /// returning `false` if another worker sharing the storage has claimed it.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn claim_transaction(
+        &mut self,
+        client_id: &[u8],
+        transaction_id: u32,
+        owner: u32,
+    ) -> Result<bool, Error> {
+        Ok(self.storage.claim_transaction(
+            client_id,
+            transaction_id,
+            owner,
+            TRANSACTION_CLAIM_TIMEOUT,
+        )?)
+    }
+
+    /// Returns the lease of the client.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn lease(&self, client_id: &[u8]) -> Result<Option<Lease>, Error> {
+        Ok(self.storage.get_lease(client_id)?)
+    }
+
+    /// Returns the client the address is bound to and its lease.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn lease_by_address(&self, address: &Ipv4Addr) -> Result<Option<(Vec<u8>, Lease)>, Error> {
+        if let Some(client_id) = self.storage.get_client(address)? {
+            if let Some(lease) = self.storage.get_lease(&client_id)? {
+                if lease.address() == *address {
+                    return Ok(Some((client_id, lease)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks whether the address is in the pools or reserved, so the server may lease it.
+    pub fn is_address_managed(&self, address: &Ipv4Addr) -> bool {
+        self.is_address_in_pools(address) || self.reserved_addresses.contains(address)
+    }
+
+    /// Returns all the leases with their client IDs.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn leases(&self) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        Ok(self.storage.get_leases()?)
+    }
+
+    /// Returns the utilization of the static and dynamic ranges and the subnet ones
+    /// with their high-water marks.
+    ///
+    /// The allocations are taken from the counters, so the leases are not read.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn utilization(&self) -> Result<Vec<Utilization>, Error> {
+        let abandoned = self.storage.get_frozen()?;
+        let mut utilization = Vec::new();
+        for (subnet, (first, last), exclusions) in self.pool_ranges().into_iter() {
+            let contains = |address: &Ipv4Addr| is_in_pool((first, last), exclusions, address);
+            let size = if first <= last {
+                (u32::from(last) - u32::from(first))
+                    .saturating_add(1)
+                    .saturating_sub(count_covered((first, last), exclusions))
+            } else {
+                0
+            };
+            let reserved: Vec<Ipv4Addr> = self
+                .reserved_addresses
+                .iter()
+                .cloned()
+                .filter(|address| contains(address))
+                .collect();
+            let abandoned: Vec<Ipv4Addr> = abandoned
+                .iter()
+                .cloned()
+                .filter(|address| contains(address))
+                .collect();
+
+            // the allocated ones are already counted
+            let mut unavailable = HashSet::new();
+            for address in reserved.iter().chain(abandoned.iter()) {
+                if !self.is_address_allocated(address)? {
+                    unavailable.insert(*address);
+                }
+            }
+            let (allocated, leased, high_water) = {
+                let state = self.state();
+                let count = |counters: &HashMap<(Ipv4Addr, Ipv4Addr), u32>| {
+                    counters.get(&(first, last)).cloned().unwrap_or(0)
+                };
+                (
+                    count(&state.allocated),
+                    count(&state.leased),
+                    count(&state.high_water),
+                )
+            };
+            utilization.push(Utilization {
+                subnet,
+                first,
+                last,
+                size,
+                allocated,
+                leased,
+                reserved: reserved.len() as u32,
+                abandoned: abandoned.len() as u32,
+                free: size
+                    .saturating_sub(allocated)
+                    .saturating_sub(unavailable.len() as u32),
+                high_water,
+            });
+        }
+        Ok(utilization)
+    }
+
+    /// Stores the lease received from the failover peer, replacing the local one.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn apply_lease(&mut self, client_id: &[u8], lease: Lease) -> Result<(), Error> {
+        let address = lease.address();
+        if let Some(current) = self.storage.get_lease(client_id)? {
+            if current.address() != address
+                && self.is_address_allocated_by(&current.address(), client_id)?
+            {
+                self.storage.delete_client(&current.address())?;
+            }
+            let mut replacement = Some(lease.to_owned());
+            self.storage.update_lease(client_id, &mut |current: &mut Lease| {
+                if let Some(lease) = replacement.take() {
+                    *current = lease;
+                }
+            })?;
+        } else {
+            self.storage.add_lease(client_id, lease.to_owned())?;
+        }
+
+        let owner = self.storage.get_client(&address)?;
+        let is_owned_by_client = owner.as_ref().map_or(false, |owner| owner == client_id);
+        if lease.is_allocated() {
+            if let Some(ref owner) = owner {
+                if !is_owned_by_client {
+                    warn!(
+                        "The address {} of the client {:?} is taken over by the peer client {:?}",
+                        address, owner, client_id
+                    );
+                }
+            }
+            self.storage.add_client(&address, client_id)?;
+            self.count_allocation(client_id, &address, lease.is_active());
+        } else {
+            if is_owned_by_client {
+                self.storage.delete_client(&address)?;
+            }
+            self.count_deallocation(client_id);
+        }
+
+        self.schedule(client_id.to_vec(), &lease);
+        Ok(())
+    }
+
+    /// Adds a subnet selected by the link address.
+    pub fn add_subnet(&mut self, subnet: Subnet) {
+        self.subnets.push(subnet);
+    }
+
+    /// Replaces the pools and the reserved addresses of a running server.
+    ///
+    /// The existing leases are kept, so the allocated addresses left outside
+    /// the new pools or reserved for another host are only reported.
+    /// The allocations are counted anew in the new pool ranges.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn reconfigure(
+        &mut self,
+        static_address_range: (Ipv4Addr, Ipv4Addr),
+        dynamic_address_range: (Ipv4Addr, Ipv4Addr),
+        subnets: Vec<Subnet>,
+        reserved_addresses: HashSet<Ipv4Addr>,
+    ) -> Result<(), Error> {
+        let old_ranges = self.ranges();
+        let old_reserved_addresses = self.reserved_addresses.to_owned();
+
+        self.static_address_range = static_address_range;
+        self.dynamic_address_range = dynamic_address_range;
+        self.subnets = subnets;
+        self.reserved_addresses = reserved_addresses;
+
+        let leases = self.storage.get_leases()?;
+        for &(_, ref lease) in leases.iter() {
+            if !lease.is_allocated() {
+                continue;
+            }
+            let address = lease.address();
+            let was_in_pools = old_ranges
+                .iter()
+                .any(|&(first, last)| first <= address && address <= last);
+            if was_in_pools && !self.is_address_in_pools(&address) {
+                warn!("The allocated address {} is no longer in the pools", address);
+            }
+            if self.reserved_addresses.contains(&address)
+                && !old_reserved_addresses.contains(&address)
+            {
+                warn!("The newly reserved address {} is allocated", address);
+            }
+        }
+        self.recount(&leases);
+        Ok(())
+    }
+
+    /// Groups the subnets on the same links by their network addresses.
+    ///
+    /// The addresses are allocated from the pools of the selected subnet first
+    /// and then from the ones of the other subnets of its group in their order,
+    /// and the addresses of the whole group are valid for its clients.
+    pub fn set_shared_networks(&mut self, shared_networks: Vec<Vec<Ipv4Addr>>) {
+        self.shared_networks = shared_networks;
+    }
+
+    /// Returns the subnet followed by the other subnets of its shared network, if any.
+    pub fn link_subnets<'a>(&'a self, subnet: &'a Subnet) -> Vec<&'a Subnet> {
+        let mut subnets = vec![subnet];
+        if let Some(members) = self
+            .shared_networks
+            .iter()
+            .find(|members| members.contains(&subnet.address))
+        {
+            subnets.extend(members.iter().filter_map(|address| {
+                self.subnets
+                    .iter()
+                    .find(|member| member.address == *address && member.address != subnet.address)
+            }));
+        }
+        subnets
+    }
+
+    /// Checks whether the address belongs to the subnet or another subnet of its shared network.
+    pub fn is_on_link(&self, subnet: &Subnet, address: &Ipv4Addr) -> bool {
+        self.link_subnets(subnet)
+            .iter()
+            .any(|member| member.contains(address))
+    }
+
+    /// Sets the ranges inside the dynamic range outside the subnets which are never allocated.
+    pub fn set_dynamic_exclusions(&mut self, exclusions: Vec<(Ipv4Addr, Ipv4Addr)>) {
+        self.dynamic_exclusions = exclusions;
+    }
+
+    /// Keeps the addresses of the expired and released leases for their clients
+    /// for `seconds`, unless the pools run out of the other addresses.
+    pub fn set_affinity_retention(&mut self, seconds: u32) {
+        self.affinity_retention = seconds;
+        if self.affinity_retention == 0 && self.grace_period == 0 {
+            self.state().affinity.clear();
+        }
+    }
+
+    /// Keeps the addresses of the expired and released leases from the other clients
+    /// for `seconds`, so the clients still using them do not clash with the new ones.
+    pub fn set_grace_period(&mut self, seconds: u32) {
+        self.grace_period = seconds;
+        if self.affinity_retention == 0 && self.grace_period == 0 {
+            self.state().affinity.clear();
+        }
+    }
+
+    /// Replaces the strategy of the dynamic range outside the subnets.
+    pub fn set_allocator(&mut self, allocator: Arc<Allocator>) {
+        self.allocator = allocator;
+    }
+
+    /// Selects the subnet for a request.
+    ///
+    /// A subnet matching the relay agent information is preferred over the one
+    /// containing the link address (`giaddr` or the receiving interface address).
+    pub fn subnet(
+        &self,
+        link_address: &Ipv4Addr,
+        relay_agent_information: Option<&RelayAgentInformation>,
+    ) -> Option<&Subnet> {
+        if let Some(information) = relay_agent_information {
+            if let Some(subnet) = self
+                .subnets
+                .iter()
+                .find(|subnet| subnet.matches_relay_agent(information))
+            {
+                return Some(subnet);
+            }
+        }
+        self.subnets
+            .iter()
+            .find(|subnet| subnet.contains(link_address))
+    }
+
+    pub fn subnets(&self) -> &[Subnet] {
+        &self.subnets
+    }
+
+    /// Returns the subnet with the network address.
+    pub fn subnet_by_address(&self, address: &Ipv4Addr) -> Option<&Subnet> {
+        self.subnets.iter().find(|subnet| subnet.address == *address)
+    }
+
+    /// Sets the addresses which are never allocated from the pools.
+    pub fn set_reserved_addresses(&mut self, reserved_addresses: HashSet<Ipv4Addr>) {
+        self.reserved_addresses = reserved_addresses;
+    }
+
+    /// Allocates the address reserved for the client, bypassing the pools.
+    ///
+    /// The lease time is chosen as in `allocate`.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    /// `self::Error` if the address is still allocated by another client.
+    pub fn allocate_reserved(
+        &mut self,
+        client_id: &[u8],
+        address: &Ipv4Addr,
+        lease_time: Option<u32>,
+    ) -> Result<Offer, Error> {
+        let reuse_lease_time = lease_time.is_none();
+        let lease_time = self.lease_time(lease_time);
+
+        let is_current = self.is_address_allocated_by(address, client_id)?;
+        if !is_current && self.is_address_allocated(address)? {
+            return Err(Error::ReservedAddressAllocated);
+        }
+        let lease_time = self
+            .offer(address, client_id, lease_time, reuse_lease_time && is_current)?
+            .ok_or(Error::ReservedAddressAllocated)?;
+        let offer = Offer {
+            address: address.to_owned(),
+            lease_time,
+            message: "Offering the reserved address".to_owned(),
+        };
+        trace!(
+            "Offering to the client {:?} the reserved address {}",
+            client_id,
+            offer.address
+        );
+        Ok(offer)
+    }
+
+    /// Binds the address reserved for the BOOTP client for an infinite time.
+    ///
+    /// The BOOTP clients never renew nor release their addresses,
+    /// so the lease is assigned at once and never expires.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    /// `self::Error` if the address is still allocated by another client.
+    pub fn bind_bootp(&mut self, client_id: &[u8], address: &Ipv4Addr) -> Result<(), Error> {
+        let is_current = self.is_address_allocated_by(address, client_id)?;
+        if !is_current && self.is_address_allocated(address)? {
+            return Err(Error::ReservedAddressAllocated);
+        }
+        self.storage.add_client(address, client_id)?;
+        let mut lease = Lease::new(address.to_owned(), INFINITE_LEASE_TIME);
+        lease.assign(INFINITE_LEASE_TIME);
+        self.storage.add_lease(client_id, lease)?;
+        self.count_allocation(client_id, address, true);
+        trace!(
+            "Binding the address {} to the BOOTP client {:?}",
+            address,
+            client_id
+        );
+        Ok(())
+    }
+
     /// Allocates an address.
     ///
     /// Address allocation algorithm:
@@ -110,6 +811,8 @@ where
     /// the message was received (if 'giaddr' is 0) or on the address of
     /// the relay agent that forwarded the message ('giaddr' when not 0).
     ///
+    /// The static and dynamic ranges are used if no subnet has been selected for the request.
+    ///
     /// Lease time calculation algorithm:
     /// RFC 2132 §4.3.1
     /// The server must also choose an expiration time for the lease, as follows:
@@ -137,30 +840,39 @@ where
         client_id: &[u8],
         lease_time: Option<u32>,
         requested_address: Option<Ipv4Addr>,
+        subnet: Option<&Subnet>,
     ) -> Result<Offer, Error> {
+        if let Some(subnet) = subnet {
+            trace!("Allocating in the subnet {}", subnet.address);
+        }
+
         // for lease time case 1
         let reuse_lease_time = lease_time.is_none();
         // lease time case 2 or 3
-        let lease_time = cmp::min(lease_time.unwrap_or(DEFAULT_LEASE_TIME), MAX_LEASE_TIME);
+        let lease_time = self.lease_time(lease_time);
 
         // address allocation case 1
         if let Some(address) = self.client_current_address(client_id)? {
             if self.is_address_allocated_by(&address, client_id)?
                 && !self.is_address_frozen(&address)?
+                && subnet.map_or(true, |subnet| self.is_on_link(subnet, &address))
             {
                 // lease time case 1
-                let lease_time = self.offer(&address, client_id, lease_time, reuse_lease_time)?;
-                let offer = Offer {
-                    address,
-                    lease_time,
-                    message: "Offering the current address".to_owned(),
-                };
-                trace!(
-                    "Offering to the client {:?} the current address {}",
-                    client_id,
-                    offer.address
-                );
-                return Ok(offer);
+                if let Some(lease_time) =
+                    self.offer(&address, client_id, lease_time, reuse_lease_time)?
+                {
+                    let offer = Offer {
+                        address,
+                        lease_time,
+                        message: "Offering the current address".to_owned(),
+                    };
+                    trace!(
+                        "Offering to the client {:?} the current address {}",
+                        client_id,
+                        offer.address
+                    );
+                    return Ok(offer);
+                }
             } else {
                 trace!("Client {:?} has no current address", client_id);
             }
@@ -170,19 +882,20 @@ where
 
         // address allocation case 2
         if let Some(address) = self.client_last_address(client_id)? {
-            if self.is_address_available(&address)? {
-                let lease_time = self.offer(&address, client_id, lease_time, false)?;
-                let offer = Offer {
-                    address,
-                    lease_time,
-                    message: "Offering the previous address".to_owned(),
-                };
-                trace!(
-                    "Offering to the client {:?} the previous address {}",
-                    client_id,
-                    offer.address
-                );
-                return Ok(offer);
+            if self.is_address_available(&address, subnet)? {
+                if let Some(lease_time) = self.offer(&address, client_id, lease_time, false)? {
+                    let offer = Offer {
+                        address,
+                        lease_time,
+                        message: "Offering the previous address".to_owned(),
+                    };
+                    trace!(
+                        "Offering to the client {:?} the previous address {}",
+                        client_id,
+                        offer.address
+                    );
+                    return Ok(offer);
+                }
             } else {
                 trace!("The previous address {} is not available", address);
             }
@@ -192,19 +905,23 @@ where
 
         // address allocation case 3
         if let Some(address) = requested_address {
-            if self.is_address_available(&address)? {
-                let lease_time = self.offer(&address, client_id, lease_time, false)?;
-                let offer = Offer {
-                    address,
-                    lease_time,
-                    message: "Offering the requested address".to_owned(),
-                };
-                trace!(
-                    "Offering to the client {:?} the requested address {}",
-                    client_id,
-                    offer.address
-                );
-                return Ok(offer);
+            let now = Utc::now().timestamp() as u32;
+            if self.is_address_available(&address, subnet)?
+                && !self.is_address_quarantined(&address, client_id, now)
+            {
+                if let Some(lease_time) = self.offer(&address, client_id, lease_time, false)? {
+                    let offer = Offer {
+                        address,
+                        lease_time,
+                        message: "Offering the requested address".to_owned(),
+                    };
+                    trace!(
+                        "Offering to the client {:?} the requested address {}",
+                        client_id,
+                        offer.address
+                    );
+                    return Ok(offer);
+                }
             } else {
                 trace!("The requested address {} is not available", address);
             }
@@ -212,21 +929,31 @@ where
             trace!("Client {:?} does not request an address", client_id);
         }
 
-        // address allocation case 4, giaddr stuff not implemented
-        let address = self.get_dynamic_available()?
-            .ok_or(Error::DynamicPoolExhausted)?;
-        let lease_time = self.offer(&address, client_id, lease_time, false)?;
-        let offer = Offer {
-            address,
-            lease_time,
-            message: "Offering an address from the dynamic pool".to_owned(),
-        };
-        trace!(
-            "Offering to the client {:?} the address {} from the dynamic pool",
-            client_id,
-            offer.address
-        );
-        Ok(offer)
+        // address allocation case 4
+        let mut claimed = Vec::new();
+        loop {
+            let address = self
+                .get_dynamic_available(client_id, subnet, &claimed)?
+                .ok_or(Error::DynamicPoolExhausted)?;
+            let lease_time = match self.offer(&address, client_id, lease_time, false)? {
+                Some(lease_time) => lease_time,
+                None => {
+                    claimed.push(address);
+                    continue;
+                }
+            };
+            let offer = Offer {
+                address,
+                lease_time,
+                message: "Offering an address from the dynamic pool".to_owned(),
+            };
+            trace!(
+                "Offering to the client {:?} the address {} from the dynamic pool",
+                client_id,
+                offer.address
+            );
+            return Ok(offer);
+        }
     }
 
     /// Assigns a previously offered address.
@@ -250,13 +977,17 @@ where
                 }
                 let lease_time =
                     cmp::min(lease_time.unwrap_or(lease.lease_time()), lease.lease_time());
+                let mut updated = lease.to_owned();
+                updated.assign(lease_time);
+                self.schedule(client_id.to_vec(), &updated);
                 self.storage
                     .update_lease(client_id, &mut |lease: &mut Lease| lease.assign(lease_time))?;
+                self.count_allocation(client_id, address, true);
                 let ack = Ack {
                     address: Ipv4Addr::from(lease.address()),
-                    lease_time: lease.lease_time(),
-                    renewal_time: ((lease.lease_time() as f64) * RENEWAL_TIME_FACTOR) as u32,
-                    rebinding_time: ((lease.lease_time() as f64) * REBINDING_TIME_FACTOR) as u32,
+                    lease_time,
+                    renewal_time: ((lease_time as f64) * RENEWAL_TIME_FACTOR) as u32,
+                    rebinding_time: ((lease_time as f64) * REBINDING_TIME_FACTOR) as u32,
                     message: "Successfully assigned".to_owned(),
                 };
                 trace!(
@@ -274,6 +1005,9 @@ where
 
     /// Renewes a previously assigned address.
     ///
+    /// A lease already reaped, released or never assigned is not renewed,
+    /// since its address may have been allocated to another client.
+    ///
     /// # Errors
     /// `self::Error` on internal storage error.
     /// `self::Error` on address renewal error.
@@ -283,16 +1017,19 @@ where
         address: &Ipv4Addr,
         lease_time: Option<u32>,
     ) -> Result<Ack, Error> {
-        let lease_time = cmp::min(lease_time.unwrap_or(DEFAULT_LEASE_TIME), MAX_LEASE_TIME);
+        let lease_time = self.lease_time(lease_time);
         if let Some(lease) = self.storage.get_lease(&client_id)? {
-            if lease.address() == *address {
+            if lease.address() == *address && lease.is_assigned() {
+                let mut updated = lease.to_owned();
+                updated.renew(lease_time);
+                self.schedule(client_id.to_vec(), &updated);
                 self.storage
                     .update_lease(client_id, &mut |lease: &mut Lease| lease.renew(lease_time))?;
                 let ack = Ack {
                     address: Ipv4Addr::from(lease.address()),
-                    lease_time: lease.lease_time(),
-                    renewal_time: ((lease.lease_time() as f64) * RENEWAL_TIME_FACTOR) as u32,
-                    rebinding_time: ((lease.lease_time() as f64) * REBINDING_TIME_FACTOR) as u32,
+                    lease_time,
+                    renewal_time: ((lease_time as f64) * RENEWAL_TIME_FACTOR) as u32,
+                    rebinding_time: ((lease_time as f64) * REBINDING_TIME_FACTOR) as u32,
                     message: "Your lease has been renewed".to_owned(),
                 };
                 trace!(
@@ -318,10 +1055,52 @@ where
         self.storage.delete_client(address)?;
         self.storage
             .update_lease(client_id, &mut |lease: &mut Lease| lease.release())?;
+        self.count_deallocation(client_id);
         Ok(())
     }
 
-    /// Freezes an address due to a `DHCPDECLINE` message.
+    /// Releases an address due to a `DHCPRELEASE` message.
+    ///
+    /// The client must hold an active lease of the address, so other hosts
+    /// cannot release it on behalf of the client.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    /// `self::Error` if the address is not leased to the client.
+    pub fn release(&mut self, client_id: &[u8], address: &Ipv4Addr) -> Result<(), Error> {
+        match self.storage.get_lease(client_id)? {
+            Some(ref lease) if lease.address() == *address && lease.is_active() => {}
+            Some(_) => return Err(Error::LeaseInvalid),
+            None => return Err(Error::LeaseNotFound),
+        }
+        if self.storage.get_client(address)?.as_ref().map(Vec::as_slice) != Some(client_id) {
+            return Err(Error::LeaseInvalid);
+        }
+        self.deallocate(client_id, address)?;
+        if let Some(lease) = self.storage.get_lease(client_id)? {
+            let released_at = lease.released_at();
+            self.retain(client_id.to_vec(), &lease, released_at);
+        }
+        Ok(())
+    }
+
+    /// Abandons an address declined by the client in a `DHCPDECLINE` message.
+    ///
+    /// The client binding is released, so the client gets another address.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    /// `self::Error` if the address is not offered or assigned to the client.
+    pub fn decline(&mut self, client_id: &[u8], address: &Ipv4Addr) -> Result<(), Error> {
+        if !self.is_address_allocated_by(address, client_id)? {
+            return Err(Error::DeclineInvalid);
+        }
+        self.freeze(address)?;
+        self.deallocate(client_id, address)
+    }
+
+    /// Abandons an address found to be in use, so it is not allocated
+    /// until it is reclaimed.
     ///
     /// # Errors
     /// `self::Error` on internal storage error.
@@ -330,6 +1109,23 @@ where
         Ok(())
     }
 
+    /// Returns an abandoned address to the pools.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn reclaim(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        self.storage.delete_frozen(address)?;
+        Ok(())
+    }
+
+    /// The abandoned addresses waiting for the reclamation.
+    ///
+    /// # Errors
+    /// `self::Error` on internal storage error.
+    pub fn abandoned(&self) -> Result<Vec<Ipv4Addr>, Error> {
+        Ok(self.storage.get_frozen()?)
+    }
+
     /// Checks the address of a client in the `INIT-REBOOT` state.
     ///
     /// # Errors
@@ -353,14 +1149,26 @@ where
         }
     }
 
+    fn lease_time(&self, requested: Option<u32>) -> u32 {
+        cmp::min(
+            requested.unwrap_or(self.default_lease_time),
+            self.max_lease_time,
+        )
+    }
+
+    /// Offers the address to the client and returns the lease time,
+    /// or `None` if another client has claimed the address since it has been checked.
     fn offer(
         &mut self,
         address: &Ipv4Addr,
         client_id: &[u8],
         lease_time: u32,
         reuse_lease_time: bool,
-    ) -> Result<u32, Error> {
-        self.storage.add_client(address, client_id)?;
+    ) -> Result<Option<u32>, Error> {
+        if !self.claim(address, client_id)? {
+            trace!("The address {} has been claimed by another client", address);
+            return Ok(None);
+        }
 
         let mut lease_time = lease_time;
         if reuse_lease_time {
@@ -371,9 +1179,135 @@ where
             });
         }
 
-        self.storage
-            .add_lease(client_id, Lease::new(address.to_owned(), lease_time))?;
-        Ok(lease_time)
+        let lease = Lease::new(address.to_owned(), lease_time);
+        self.schedule(client_id.to_vec(), &lease);
+        self.storage.add_lease(client_id, lease)?;
+        self.count_allocation(client_id, address, false);
+        Ok(Some(lease_time))
+    }
+
+    /// Associates the address with the client unless another client holds it.
+    ///
+    /// The association is conditional on the client read here, so of the workers
+    /// sharing the storage and racing for the address only one gets it.
+    /// The address of a client without a lease is being claimed by another worker.
+    fn claim(&mut self, address: &Ipv4Addr, client_id: &[u8]) -> Result<bool, Error> {
+        let owner = self.storage.get_client(address)?;
+        if let Some(ref owner) = owner {
+            if owner.as_slice() != client_id {
+                match self.storage.get_lease(owner)? {
+                    Some(ref lease) if lease.address() != *address || !lease.is_allocated() => {}
+                    _ => return Ok(false),
+                }
+            }
+        }
+        Ok(self
+            .storage
+            .claim_address(address, client_id, owner.as_ref().map(Vec::as_slice))?)
+    }
+
+    /// The pool ranges with the subnets they belong to and their exclusions.
+    fn pool_ranges<'a>(&'a self) -> Vec<PoolRange<'a>> {
+        let mut ranges = vec![
+            (None, self.static_address_range, &[][..]),
+            (
+                None,
+                self.dynamic_address_range,
+                &self.dynamic_exclusions[..],
+            ),
+        ];
+        for subnet in self.subnets.iter() {
+            ranges.extend(
+                subnet
+                    .ranges
+                    .iter()
+                    .map(|range| (Some(subnet.address), *range, &subnet.exclusions[..])),
+            );
+        }
+        ranges
+    }
+
+    /// Counts the address allocated to the client in the pool ranges containing it
+    /// instead of the former address of the client and raises their high-water marks.
+    fn count_allocation(&self, client_id: &[u8], address: &Ipv4Addr, is_leased: bool) {
+        let ranges = self.pool_ranges();
+        self.state().count_allocation(&ranges, client_id, (*address, is_leased));
+    }
+
+    /// Uncounts the address allocated to the client in the pool ranges containing it.
+    fn count_deallocation(&self, client_id: &[u8]) {
+        let ranges = self.pool_ranges();
+        self.state().count_deallocation(&ranges, client_id);
+    }
+
+    /// Counts the allocated leases in the pool ranges anew,
+    /// keeping the high-water marks of the ranges.
+    fn recount(&self, leases: &[(Vec<u8>, Lease)]) {
+        let ranges = self.pool_ranges();
+        let mut state = self.state();
+        state.allocations.clear();
+        state.allocated.clear();
+        state.leased.clear();
+        for (client_id, lease) in leases.iter() {
+            if lease.is_allocated() {
+                state.count_allocation(&ranges, client_id, (lease.address(), lease.is_active()));
+            }
+        }
+    }
+
+    /// Schedules the expiration of the offered or assigned lease.
+    /// Keeps the address of a former lease for the client until the retention
+    /// and the grace period are over.
+    fn retain(&mut self, client_id: Vec<u8>, lease: &Lease, freed_at: u32) {
+        let retention = cmp::max(self.affinity_retention, self.grace_period);
+        if retention == 0 {
+            return;
+        }
+        if freed_at.saturating_add(retention) > Utc::now().timestamp() as u32 {
+            self.state()
+                .affinity
+                .insert(lease.address(), (client_id, freed_at));
+        }
+    }
+
+    /// Checks whether the address is kept for another client.
+    fn is_address_retained(&self, address: &Ipv4Addr, client_id: &[u8], now: u32) -> bool {
+        self.is_address_kept(address, client_id, now, self.affinity_retention)
+    }
+
+    /// Checks whether the address is in the grace period of another client.
+    fn is_address_quarantined(&self, address: &Ipv4Addr, client_id: &[u8], now: u32) -> bool {
+        self.is_address_kept(address, client_id, now, self.grace_period)
+    }
+
+    fn is_address_kept(
+        &self,
+        address: &Ipv4Addr,
+        client_id: &[u8],
+        now: u32,
+        retention: u32,
+    ) -> bool {
+        match self.state().affinity.get(address) {
+            Some(&(ref former, freed_at)) => {
+                freed_at.saturating_add(retention) > now && former.as_slice() != client_id
+            }
+            None => false,
+        }
+    }
+
+    fn schedule(&mut self, client_id: Vec<u8>, lease: &Lease) {
+        if lease.is_offered() {
+            self.state()
+                .expirations
+                .insert(lease.offer_expires_at(), client_id);
+        } else if lease.is_assigned() && lease.lease_time() != INFINITE_LEASE_TIME {
+            self.state().expirations.insert(lease.expires_at(), client_id);
+        }
+    }
+
+    /// Locks the state shared with the other workers.
+    fn state(&self) -> MutexGuard<State> {
+        self.state.0.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
     fn client_current_address(&self, client_id: &[u8]) -> Result<Option<Ipv4Addr>, Error> {
@@ -392,11 +1326,24 @@ where
         Ok(None)
     }
 
-    fn is_address_available(&self, address: &Ipv4Addr) -> Result<bool, Error> {
+    fn is_address_available(
+        &self,
+        address: &Ipv4Addr,
+        subnet: Option<&Subnet>,
+    ) -> Result<bool, Error> {
+        let is_in_pool = match subnet {
+            Some(subnet) => self
+                .link_subnets(subnet)
+                .iter()
+                .any(|member| member.is_in_ranges(address)),
+            None => {
+                self.is_address_in_static_pool(address) || self.is_address_in_dynamic_pool(address)
+            }
+        };
         Ok(
-            !self.is_address_allocated(address)? && !self.is_address_frozen(address)?
-                && (self.is_address_in_static_pool(address)
-                    || self.is_address_in_dynamic_pool(address)),
+            is_in_pool && self.is_address_owned(address) && !self.is_address_allocated(address)?
+                && !self.is_address_frozen(address)?
+                && !self.reserved_addresses.contains(address),
         )
     }
 
@@ -424,16 +1371,79 @@ where
         Ok(self.storage.check_frozen(address)?)
     }
 
-    fn get_dynamic_available(&self) -> Result<Option<Ipv4Addr>, Error> {
-        for address in
-            u32::from(self.dynamic_address_range.0)..=u32::from(self.dynamic_address_range.1)
-        {
-            let address = Ipv4Addr::from(address);
-            if self.is_address_available(&address)? {
+    /// The first available address of the pools, except the `claimed` ones.
+    fn get_dynamic_available(
+        &self,
+        client_id: &[u8],
+        subnet: Option<&Subnet>,
+        claimed: &[Ipv4Addr],
+    ) -> Result<Option<Ipv4Addr>, Error> {
+        let pools: Vec<(Vec<(Ipv4Addr, Ipv4Addr)>, &Arc<Allocator>)> = match subnet {
+            Some(subnet) => self
+                .link_subnets(subnet)
+                .into_iter()
+                .map(|member| (member.ranges.to_owned(), &member.allocator))
+                .collect(),
+            None => vec![(vec![self.dynamic_address_range], &self.allocator)],
+        };
+        let now = Utc::now().timestamp() as u32;
+        let mut retained = None;
+        for (ranges, allocator) in pools.into_iter() {
+            let ranges: Vec<(u32, u32)> = ranges
+                .into_iter()
+                .map(|(first, last)| (u32::from(first), u32::from(last)))
+                .collect();
+            for address in allocator.pool_candidates(client_id, &ranges) {
+                let address = Ipv4Addr::from(address);
+                if claimed.contains(&address)
+                    || !self.is_address_available(&address, subnet)?
+                    || self.is_address_quarantined(&address, client_id, now)
+                {
+                    continue;
+                }
+                if self.is_address_retained(&address, client_id, now) {
+                    retained = retained.or(Some(address));
+                    continue;
+                }
                 return Ok(Some(address));
             }
         }
-        Ok(None)
+        if let Some(address) = retained {
+            trace!("Taking the address {} retained for another client", address);
+        }
+        Ok(retained)
+    }
+
+    fn ranges(&self) -> Vec<(Ipv4Addr, Ipv4Addr)> {
+        let mut ranges = vec![self.static_address_range, self.dynamic_address_range];
+        for subnet in self.subnets.iter() {
+            ranges.extend(subnet.ranges.iter().cloned());
+        }
+        ranges
+    }
+
+    /// Checks whether the address is in the pool share of its range.
+    fn is_address_owned(&self, address: &Ipv4Addr) -> bool {
+        let (from, to) = self.pool_share;
+        if (from, to) == (0, 100) {
+            return true;
+        }
+        let address = u32::from(*address);
+        self.ranges()
+            .into_iter()
+            .map(|(first, last)| (u32::from(first), u32::from(last)))
+            .find(|&(first, last)| first <= address && address <= last)
+            .map_or(true, |(first, last)| {
+                let size = u64::from(last - first) + 1;
+                let percent = (u64::from(address - first) * 100 / size) as u32;
+                from <= percent && percent < to
+            })
+    }
+
+    fn is_address_in_pools(&self, address: &Ipv4Addr) -> bool {
+        self.is_address_in_static_pool(address)
+            || self.is_address_in_dynamic_pool(address)
+            || self.subnets.iter().any(|subnet| subnet.is_in_ranges(address))
     }
 
     fn is_address_in_static_pool(&self, address: &Ipv4Addr) -> bool {
@@ -441,14 +1451,34 @@ where
     }
 
     fn is_address_in_dynamic_pool(&self, address: &Ipv4Addr) -> bool {
-        self.dynamic_address_range.0 <= *address && *address <= self.dynamic_address_range.1
+        self.dynamic_address_range.0 <= *address
+            && *address <= self.dynamic_address_range.1
+            && !is_in_any(&self.dynamic_exclusions, address)
     }
 }
 
+/// Checks whether the address is in the inclusive range and not excluded from it.
+/// Decrements the counter of the pool range.
+fn uncount(counters: &mut HashMap<(Ipv4Addr, Ipv4Addr), u32>, range: (Ipv4Addr, Ipv4Addr)) {
+    if let Some(count) = counters.get_mut(&range) {
+        *count = count.saturating_sub(1);
+    }
+}
+
+fn is_in_pool(
+    range: (Ipv4Addr, Ipv4Addr),
+    exclusions: &[(Ipv4Addr, Ipv4Addr)],
+    address: &Ipv4Addr,
+) -> bool {
+    range.0 <= *address && *address <= range.1 && !is_in_any(exclusions, address)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use scope::clamp_lease_time;
     use storage_ram::RamStorage;
+    use storage_sharded::ShardedStorage;
 
     #[test]
     fn reuses_client_current_address() {
@@ -470,6 +1500,7 @@ mod tests {
                 client_id.as_ref(),
                 Some(1000),
                 Some(Ipv4Addr::new(192, 168, 0, 11)),
+                None,
             )
             .unwrap();
         let ack1 = storage
@@ -481,6 +1512,7 @@ mod tests {
                 client_id.as_ref(),
                 Some(1000),
                 Some(Ipv4Addr::new(192, 168, 0, 12)),
+                None,
             )
             .unwrap();
         let ack2 = storage
@@ -506,7 +1538,7 @@ mod tests {
         let client_id = vec![1u8];
 
         let offer1 = storage
-            .allocate(client_id.as_ref(), Some(1000), None)
+            .allocate(client_id.as_ref(), Some(1000), None, None)
             .unwrap();
         let ack1 = storage
             .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
@@ -520,6 +1552,7 @@ mod tests {
                 client_id.as_ref(),
                 Some(1000),
                 Some(Ipv4Addr::new(192, 168, 0, 166)),
+                None,
             )
             .unwrap();
         let ack2 = storage
@@ -548,7 +1581,7 @@ mod tests {
         let current = Ipv4Addr::new(192, 168, 0, 166);
 
         let offer1 = storage
-            .allocate(client_id.as_ref(), Some(1000), Some(current))
+            .allocate(client_id.as_ref(), Some(1000), Some(current), None)
             .unwrap();
         let ack1 = storage
             .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
@@ -558,7 +1591,7 @@ mod tests {
             .unwrap();
 
         let offer2 = storage
-            .allocate(another_client_id.as_ref(), Some(1000), Some(current))
+            .allocate(another_client_id.as_ref(), Some(1000), Some(current), None)
             .unwrap();
         let ack2 = storage
             .assign(
@@ -591,7 +1624,7 @@ mod tests {
         let requested = Ipv4Addr::new(192, 168, 0, 77);
 
         let offer1 = storage
-            .allocate(client_id.as_ref(), Some(1000), Some(current))
+            .allocate(client_id.as_ref(), Some(1000), Some(current), None)
             .unwrap();
         let ack1 = storage
             .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
@@ -601,7 +1634,7 @@ mod tests {
             .unwrap();
 
         let offer2 = storage
-            .allocate(another_client_id.as_ref(), Some(1000), Some(current))
+            .allocate(another_client_id.as_ref(), Some(1000), Some(current), None)
             .unwrap();
         let _ack2 = storage
             .assign(
@@ -612,7 +1645,7 @@ mod tests {
             .unwrap();
 
         let offer3 = storage
-            .allocate(client_id.as_ref(), Some(1000), Some(requested))
+            .allocate(client_id.as_ref(), Some(1000), Some(requested), None)
             .unwrap();
         let ack3 = storage
             .assign(client_id.as_ref(), &offer3.address, Some(offer3.lease_time))
@@ -642,7 +1675,7 @@ mod tests {
         let requested = Ipv4Addr::new(192, 168, 0, 77);
 
         let offer1 = storage
-            .allocate(client_id.as_ref(), Some(1000), Some(current))
+            .allocate(client_id.as_ref(), Some(1000), Some(current), None)
             .unwrap();
         let ack1 = storage
             .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
@@ -652,7 +1685,7 @@ mod tests {
             .unwrap();
 
         let offer2 = storage
-            .allocate(another_client_id.as_ref(), Some(1000), Some(current))
+            .allocate(another_client_id.as_ref(), Some(1000), Some(current), None)
             .unwrap();
         let _ack2 = storage
             .assign(
@@ -663,7 +1696,12 @@ mod tests {
             .unwrap();
 
         let offer3 = storage
-            .allocate(yet_another_client_id.as_ref(), Some(1000), Some(requested))
+            .allocate(
+                yet_another_client_id.as_ref(),
+                Some(1000),
+                Some(requested),
+                None,
+            )
             .unwrap();
         let _ack3 = storage
             .assign(
@@ -674,7 +1712,7 @@ mod tests {
             .unwrap();
 
         let offer4 = storage
-            .allocate(client_id.as_ref(), Some(1000), Some(requested))
+            .allocate(client_id.as_ref(), Some(1000), Some(requested), None)
             .unwrap();
         let ack4 = storage
             .assign(client_id.as_ref(), &offer4.address, Some(offer4.lease_time))
@@ -682,4 +1720,497 @@ mod tests {
 
         assert_ne!(ack4.address, requested);
     }
+
+    #[test]
+    fn schedules_far_lease_expiration() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 101),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        let client_id = vec![1u8];
+        let now = Utc::now().timestamp() as u32;
+
+        let offer = storage
+            .allocate(client_id.as_ref(), Some(1_000_000), None, None)
+            .unwrap();
+        storage
+            .assign(client_id.as_ref(), &offer.address, Some(offer.lease_time))
+            .unwrap();
+
+        let next_expiration = storage.next_expiration().unwrap();
+        assert!(next_expiration >= now && next_expiration <= now + 1_000_000);
+        assert!(storage.reap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_renewal_after_reaping() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 101),
+            ),
+            RamStorage::new(),
+        );
+        let client_id = vec![1u8];
+        let another_client_id = vec![2u8];
+
+        let offer1 = storage
+            .allocate(client_id.as_ref(), Some(0), None, None)
+            .unwrap();
+        let ack1 = storage
+            .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
+            .unwrap();
+        assert_eq!(storage.reap().unwrap().len(), 1);
+
+        assert!(storage
+            .renew(client_id.as_ref(), &ack1.address, Some(1000))
+            .is_err());
+        let offer2 = storage
+            .allocate(another_client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        assert_eq!(offer2.address, ack1.address);
+    }
+
+    #[test]
+    fn reaps_lease_allocated_by_another_worker() {
+        let storage = ShardedStorage::new(vec![RamStorage::new(), RamStorage::new()]);
+        let state = WorkerState::new();
+        let mut worker1 = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            storage.clone(),
+        );
+        worker1.share_state(state.clone());
+        let mut worker2 = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            storage,
+        );
+        worker2.share_state(state);
+        let client_id = vec![1u8];
+
+        let offer = worker1
+            .allocate(client_id.as_ref(), Some(0), None, None)
+            .unwrap();
+        worker1
+            .assign(client_id.as_ref(), &offer.address, Some(offer.lease_time))
+            .unwrap();
+
+        assert_eq!(worker2.reap().unwrap(), vec![(client_id, offer.address)]);
+        assert!(worker1.reap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn skips_address_being_claimed_by_another_worker() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        let client_id = vec![1u8];
+        let claiming_client_id = vec![2u8];
+        let requested = Ipv4Addr::new(192, 168, 0, 150);
+
+        // the other worker has claimed the address and not stored its offer yet
+        assert!(storage
+            .storage
+            .claim_address(&requested, claiming_client_id.as_ref(), None)
+            .unwrap());
+        assert!(!storage
+            .storage
+            .claim_address(&requested, client_id.as_ref(), None)
+            .unwrap());
+
+        let offer = storage
+            .allocate(client_id.as_ref(), Some(1000), Some(requested), None)
+            .unwrap();
+        assert_ne!(offer.address, requested);
+    }
+
+    #[test]
+    fn claims_transaction_for_single_worker() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 101),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        let client_id = vec![1u8];
+
+        assert!(storage.claim_transaction(&client_id, 1, 0).unwrap());
+        assert!(storage.claim_transaction(&client_id, 1, 0).unwrap());
+        assert!(!storage.claim_transaction(&client_id, 1, 1).unwrap());
+        assert!(storage.claim_transaction(&client_id, 2, 1).unwrap());
+        assert!(!storage.claim_transaction(&client_id, 2, 0).unwrap());
+    }
+
+    #[test]
+    fn keeps_released_address_for_returning_client() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 101),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        storage.set_affinity_retention(3600);
+        let client_id = vec![1u8];
+        let another_client_id = vec![2u8];
+
+        let offer1 = storage
+            .allocate(client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        let ack1 = storage
+            .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
+            .unwrap();
+        storage.release(client_id.as_ref(), &ack1.address).unwrap();
+
+        let offer2 = storage
+            .allocate(another_client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        let offer3 = storage
+            .allocate(client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+
+        assert_ne!(offer2.address, ack1.address);
+        assert_eq!(offer3.address, ack1.address);
+    }
+
+    #[test]
+    fn frees_released_address_after_retention() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 101),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        storage.set_affinity_retention(3600);
+        let client_id = vec![1u8];
+        let another_client_id = vec![2u8];
+
+        let offer1 = storage
+            .allocate(client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        let ack1 = storage
+            .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
+            .unwrap();
+        storage.release(client_id.as_ref(), &ack1.address).unwrap();
+
+        // moves the release to just before the retention window
+        if let Some(&mut (_, ref mut freed_at)) = storage.state().affinity.get_mut(&ack1.address) {
+            *freed_at -= 3600;
+        }
+        storage.reap().unwrap();
+        assert!(storage.state().affinity.is_empty());
+
+        let offer2 = storage
+            .allocate(another_client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+
+        assert_eq!(offer2.address, ack1.address);
+    }
+
+    #[test]
+    fn keeps_released_address_from_another_client_in_grace_period() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 101),
+            ),
+            RamStorage::new(),
+        );
+        storage.set_grace_period(3600);
+        let client_id = vec![1u8];
+        let another_client_id = vec![2u8];
+
+        let offer1 = storage
+            .allocate(client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        let ack1 = storage
+            .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
+            .unwrap();
+        storage.release(client_id.as_ref(), &ack1.address).unwrap();
+
+        assert!(storage
+            .allocate(another_client_id.as_ref(), Some(1000), Some(ack1.address), None)
+            .is_err());
+    }
+
+    #[test]
+    fn keeps_high_water_after_release() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        let client_id = vec![1u8];
+        let another_client_id = vec![2u8];
+
+        let offer1 = storage
+            .allocate(client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        let ack1 = storage
+            .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
+            .unwrap();
+        storage
+            .allocate(another_client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        storage.release(client_id.as_ref(), &ack1.address).unwrap();
+
+        let dynamic = storage
+            .utilization()
+            .unwrap()
+            .into_iter()
+            .find(|pool| pool.first == Ipv4Addr::new(192, 168, 0, 101))
+            .unwrap();
+        assert_eq!(dynamic.allocated, 1);
+        assert_eq!(dynamic.high_water, 2);
+    }
+
+    #[test]
+    fn counts_client_once_in_high_water() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        let client_id = vec![1u8];
+
+        storage
+            .allocate(client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        storage
+            .allocate(
+                client_id.as_ref(),
+                Some(1000),
+                Some(Ipv4Addr::new(192, 168, 0, 150)),
+                None,
+            ).unwrap();
+
+        let dynamic = storage
+            .utilization()
+            .unwrap()
+            .into_iter()
+            .find(|pool| pool.first == Ipv4Addr::new(192, 168, 0, 101))
+            .unwrap();
+        assert_eq!(dynamic.allocated, 1);
+        assert_eq!(dynamic.high_water, 1);
+    }
+
+    #[test]
+    fn counts_leased_and_reserved_addresses() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 110),
+            ),
+            RamStorage::new(),
+        );
+        let reserved = Ipv4Addr::new(192, 168, 0, 110);
+        storage.set_reserved_addresses(vec![reserved].into_iter().collect());
+        let client_id = vec![1u8];
+        let another_client_id = vec![2u8];
+        let host_id = vec![3u8];
+
+        let offer1 = storage
+            .allocate(client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+        storage
+            .assign(client_id.as_ref(), &offer1.address, Some(offer1.lease_time))
+            .unwrap();
+        storage
+            .allocate(another_client_id.as_ref(), Some(1000), None, None)
+            .unwrap();
+
+        let dynamic = |storage: &Database<RamStorage>| {
+            storage
+                .utilization()
+                .unwrap()
+                .into_iter()
+                .find(|pool| pool.first == Ipv4Addr::new(192, 168, 0, 101))
+                .unwrap()
+        };
+        let pool = dynamic(&storage);
+        assert_eq!(pool.size, 10);
+        assert_eq!(pool.allocated, 2);
+        assert_eq!(pool.leased, 1);
+        assert_eq!(pool.reserved, 1);
+        assert_eq!(pool.free, 7);
+
+        // the allocated reserved address is not counted twice
+        storage
+            .allocate_reserved(host_id.as_ref(), &reserved, Some(1000))
+            .unwrap();
+        let pool = dynamic(&storage);
+        assert_eq!(pool.allocated, 3);
+        assert_eq!(pool.free, 7);
+    }
+
+    #[test]
+    fn recounts_allocations_on_reconfigure() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        for client_id in 1..4u8 {
+            storage
+                .allocate(&[client_id], Some(1000), None, None)
+                .unwrap();
+        }
+
+        storage
+            .reconfigure(
+                (
+                    Ipv4Addr::new(192, 168, 0, 2),
+                    Ipv4Addr::new(192, 168, 0, 100),
+                ),
+                (
+                    Ipv4Addr::new(192, 168, 0, 102),
+                    Ipv4Addr::new(192, 168, 0, 200),
+                ),
+                Vec::new(),
+                HashSet::new(),
+            ).unwrap();
+
+        let dynamic = storage
+            .utilization()
+            .unwrap()
+            .into_iter()
+            .find(|pool| pool.first == Ipv4Addr::new(192, 168, 0, 102))
+            .unwrap();
+        assert_eq!(dynamic.allocated, 2);
+        assert_eq!(dynamic.high_water, 2);
+        assert_eq!(dynamic.free, 97);
+
+        // freeing the address left outside the pools changes nothing
+        storage
+            .deallocate(&[1], &Ipv4Addr::new(192, 168, 0, 101))
+            .unwrap();
+        storage
+            .deallocate(&[2], &Ipv4Addr::new(192, 168, 0, 102))
+            .unwrap();
+        let dynamic = storage
+            .utilization()
+            .unwrap()
+            .into_iter()
+            .find(|pool| pool.first == Ipv4Addr::new(192, 168, 0, 102))
+            .unwrap();
+        assert_eq!(dynamic.allocated, 1);
+        assert_eq!(dynamic.high_water, 2);
+    }
+
+    #[test]
+    fn acknowledges_applied_lease_time() {
+        let mut storage = Database::new(
+            (
+                Ipv4Addr::new(192, 168, 0, 2),
+                Ipv4Addr::new(192, 168, 0, 100),
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 101),
+                Ipv4Addr::new(192, 168, 0, 200),
+            ),
+            RamStorage::new(),
+        );
+        let client_id = vec![1u8];
+        // the class lease times the server clamps the requested ones to
+        let lease_times = Some((600, 1200));
+
+        let offer = storage
+            .allocate(client_id.as_ref(), Some(3600), None, None)
+            .unwrap();
+        let ack = storage
+            .assign(
+                client_id.as_ref(),
+                &offer.address,
+                clamp_lease_time(lease_times, None),
+            ).unwrap();
+        assert_eq!(
+            (ack.lease_time, ack.renewal_time, ack.rebinding_time),
+            (600, 300, 525)
+        );
+
+        let ack = storage
+            .renew(
+                client_id.as_ref(),
+                &offer.address,
+                clamp_lease_time(lease_times, Some(3600)),
+            ).unwrap();
+        assert_eq!(
+            (ack.lease_time, ack.renewal_time, ack.rebinding_time),
+            (1200, 600, 1050)
+        );
+        let lease = storage.leases().unwrap().remove(0).1;
+        assert_eq!(lease.lease_time(), 1200);
+    }
 }
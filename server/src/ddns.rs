@@ -0,0 +1,571 @@
+//! The dynamic DNS updates (RFC 2136) of the leased addresses.
+//!
+//! The A and DHCID records are added to the forward zone with the conflict detection
+//! described in RFC 4703, the PTR records are added to the reverse zone afterwards.
+//! The updates are signed with TSIG (RFC 8945) using HMAC-SHA256.
+//! An update the server has not responded to is sent again after the doubling delays.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::BufMut;
+use chrono::Utc;
+use futures::future::{self, Either};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use tokio::{self, io, net::UdpSocket, prelude::*, timer::Deadline};
+
+use dhcp_backoff::{Exponential, Retry};
+
+use fqdn::DnsUpdate;
+use observer::{LeaseEvent, Observer};
+
+/// How long to wait for a DNS server response in seconds.
+const RESPONSE_TIMEOUT: u64 = 5;
+/// The repeated attempts to send an update.
+const RETRIES: u32 = 3;
+/// The delay before the first retry in seconds.
+const RETRY_DELAY: u64 = 1;
+/// The retry delay stops growing at it in seconds.
+const RETRY_DELAY_MAX: u64 = 8;
+/// Enough for the update responses, which echo the zone section only.
+const RESPONSE_SIZE: usize = 512;
+
+const TYPE_A: u16 = 1;
+const TYPE_SOA: u16 = 6;
+const TYPE_PTR: u16 = 12;
+const TYPE_DHCID: u16 = 49;
+const TYPE_TSIG: u16 = 250;
+const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+const CLASS_NONE: u16 = 254;
+const CLASS_ANY: u16 = 255;
+
+const OPCODE_UPDATE: u16 = 5 << 11;
+const RCODE_NOERROR: u8 = 0;
+const RCODE_YXDOMAIN: u8 = 6;
+
+const TSIG_ALGORITHM: &str = "hmac-sha256.";
+const TSIG_FUDGE: u16 = 300;
+
+/// The DHCID identifier type of the client identifier option contents (RFC 4701 §3.3).
+const DHCID_IDENTIFIER_CLIENT_ID: u16 = 1;
+/// The DHCID digest type of SHA-256 (RFC 4701 §3.4).
+const DHCID_DIGEST_SHA256: u8 = 1;
+
+/// A shared secret the updates are signed with.
+#[derive(Debug, Clone)]
+pub struct TsigKey {
+    /// Must be equal to the key name known to the DNS server.
+    pub name: String,
+    /// The decoded HMAC-SHA256 secret.
+    pub secret: Vec<u8>,
+}
+
+/// The zones the addresses of a network are registered in.
+#[derive(Debug, Clone)]
+pub struct DnsZone {
+    /// The network address.
+    pub address: Ipv4Addr,
+    pub mask: Ipv4Addr,
+    /// The client host names are registered as its subdomains.
+    pub forward_zone: String,
+    /// Like `0.168.192.in-addr.arpa`, the PTR records are not updated if not set.
+    pub reverse_zone: Option<String>,
+}
+
+impl DnsZone {
+    fn contains(&self, address: &Ipv4Addr) -> bool {
+        let mask = u32::from(self.mask);
+        u32::from(*address) & mask == u32::from(self.address) & mask
+    }
+}
+
+/// Updates the DNS records when the leases are committed, released or expired.
+///
+/// The names registered for the clients are kept in memory, so the records
+/// of the leases committed before a restart are not removed.
+pub struct DnsUpdater {
+    server: SocketAddr,
+    key: Option<TsigKey>,
+    zones: Vec<DnsZone>,
+    /// The client IDs mapped to the registered names and addresses.
+    names: Mutex<HashMap<Vec<u8>, (String, Ipv4Addr)>>,
+    next_id: AtomicUsize,
+}
+
+impl DnsUpdater {
+    /// Creates an updater sending the updates to the primary DNS server.
+    pub fn new(server: SocketAddr, key: Option<TsigKey>, zones: Vec<DnsZone>) -> Self {
+        DnsUpdater {
+            server,
+            key,
+            zones,
+            names: Mutex::new(HashMap::new()),
+            next_id: AtomicUsize::new(Utc::now().timestamp_subsec_nanos() as usize),
+        }
+    }
+
+    /// Registers the name, replacing the records of the same client.
+    ///
+    /// Only the PTR record is added if the client updates its A record itself.
+    fn add(
+        &self,
+        client_id: &[u8],
+        address: Ipv4Addr,
+        hostname: &str,
+        dns_update: DnsUpdate,
+        lease_time: u32,
+    ) {
+        let zone = match self.zones.iter().find(|zone| zone.contains(&address)) {
+            Some(zone) => zone,
+            None => return,
+        };
+        let label = match host_label(hostname) {
+            Some(label) => label,
+            None => {
+                warn!("The host name {} cannot be registered in DNS", hostname);
+                return;
+            }
+        };
+        let name = format!("{}.{}", label, zone.forward_zone.trim_right_matches('.'));
+
+        let entry = (name.to_owned(), address);
+        {
+            let mut names = self.names.lock().expect("Poisoned DNS names");
+            if names.get(client_id) == Some(&entry) {
+                return;
+            }
+            names.insert(client_id.to_vec(), entry);
+        }
+
+        // the records must not outlive the lease for long (RFC 4702 §5)
+        let ttl = (lease_time / 3).max(1);
+        let dhcid = dhcid(client_id, &name);
+        let a = Record::new(&name, TYPE_A, CLASS_IN, ttl, address.octets().to_vec());
+
+        // the name must not exist at the first attempt (RFC 4703 §5.3.1)
+        let initial = self.message(
+            &zone.forward_zone,
+            vec![Record::empty(&name, TYPE_ANY, CLASS_NONE)],
+            vec![
+                a.clone(),
+                Record::new(&name, TYPE_DHCID, CLASS_IN, ttl, dhcid.to_owned()),
+            ],
+        );
+
+        // the existing name is taken over only if it belongs to the client (RFC 4703 §5.3.2)
+        let replacement = self.message(
+            &zone.forward_zone,
+            vec![Record::new(&name, TYPE_DHCID, CLASS_IN, 0, dhcid)],
+            vec![Record::empty(&name, TYPE_A, CLASS_ANY), a],
+        );
+
+        let pointer = zone.reverse_zone.as_ref().map(|reverse_zone| {
+            let pointer_name = pointer_name(&address);
+            self.message(
+                reverse_zone,
+                Vec::new(),
+                vec![
+                    Record::empty(&pointer_name, TYPE_PTR, CLASS_ANY),
+                    Record::new(&pointer_name, TYPE_PTR, CLASS_IN, ttl, encode_name(&name)),
+                ],
+            )
+        });
+
+        if dns_update == DnsUpdate::Reverse {
+            if let Some(pointer) = pointer {
+                let future = exchange(self.server, pointer)
+                    .map(move |rcode| match rcode {
+                        RCODE_NOERROR => info!("The PTR of {} has been set to {}", address, name),
+                        rcode => warn!(
+                            "The PTR of {} has not been updated (DNS code {})",
+                            address, rcode
+                        ),
+                    }).map_err(|error| warn!("DNS update error: {}", error));
+                tokio::spawn(future);
+            }
+            return;
+        }
+
+        let server = self.server;
+        let future = exchange(server, initial)
+            .and_then(move |rcode| match rcode {
+                RCODE_YXDOMAIN => Either::A(exchange(server, replacement)),
+                rcode => Either::B(future::ok(rcode)),
+            }).and_then(move |rcode| {
+                if rcode != RCODE_NOERROR {
+                    warn!(
+                        "The name {} is in use by another client (DNS code {})",
+                        name, rcode
+                    );
+                    return Either::A(future::ok(()));
+                }
+                info!("The name {} has been registered for {}", name, address);
+                match pointer {
+                    Some(pointer) => Either::B(exchange(server, pointer).map(move |rcode| {
+                        if rcode != RCODE_NOERROR {
+                            warn!(
+                                "The PTR of {} has not been updated (DNS code {})",
+                                address, rcode
+                            );
+                        }
+                    })),
+                    None => Either::A(future::ok(())),
+                }
+            }).map_err(|error| warn!("DNS update error: {}", error));
+        tokio::spawn(future);
+    }
+
+    /// Removes the records registered for the client, if there are any.
+    fn remove(&self, client_id: &[u8]) {
+        let (name, address) = match self
+            .names
+            .lock()
+            .expect("Poisoned DNS names")
+            .remove(client_id)
+        {
+            Some(entry) => entry,
+            None => return,
+        };
+        let zone = match self.zones.iter().find(|zone| zone.contains(&address)) {
+            Some(zone) => zone,
+            None => return,
+        };
+
+        // the records are deleted only if they still belong to the client (RFC 4703 §5.5)
+        let forward = self.message(
+            &zone.forward_zone,
+            vec![Record::new(&name, TYPE_DHCID, CLASS_IN, 0, dhcid(client_id, &name))],
+            vec![
+                Record::empty(&name, TYPE_A, CLASS_ANY),
+                Record::empty(&name, TYPE_DHCID, CLASS_ANY),
+            ],
+        );
+        let future = exchange(self.server, forward)
+            .map(move |rcode| match rcode {
+                RCODE_NOERROR => info!("The name {} has been unregistered", name),
+                rcode => warn!("The name {} has not been unregistered (DNS code {})", name, rcode),
+            }).map_err(|error| warn!("DNS update error: {}", error));
+        tokio::spawn(future);
+
+        if let Some(ref reverse_zone) = zone.reverse_zone {
+            let pointer_name = pointer_name(&address);
+            let pointer = self.message(
+                reverse_zone,
+                Vec::new(),
+                vec![Record::empty(&pointer_name, TYPE_PTR, CLASS_ANY)],
+            );
+            let future = exchange(self.server, pointer)
+                .map(|_| ())
+                .map_err(|error| warn!("DNS update error: {}", error));
+            tokio::spawn(future);
+        }
+    }
+
+    /// Encodes and signs an update message.
+    fn message(&self, zone: &str, prerequisites: Vec<Record>, updates: Vec<Record>) -> Vec<u8> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) as u16;
+        let mut message = Vec::new();
+        message.put_u16_be(id);
+        message.put_u16_be(OPCODE_UPDATE);
+        message.put_u16_be(1);
+        message.put_u16_be(prerequisites.len() as u16);
+        message.put_u16_be(updates.len() as u16);
+        message.put_u16_be(0);
+
+        message.extend(encode_name(zone));
+        message.put_u16_be(TYPE_SOA);
+        message.put_u16_be(CLASS_IN);
+        for record in prerequisites.iter().chain(updates.iter()) {
+            record.put(&mut message);
+        }
+
+        if let Some(ref key) = self.key {
+            sign(&mut message, id, key, Utc::now().timestamp() as u64);
+        }
+        message
+    }
+}
+
+impl Observer for DnsUpdater {
+    fn on_lease_event(&self, event: &LeaseEvent) {
+        match *event {
+            LeaseEvent::Committed {
+                ref client_id,
+                address,
+                hostname: Some(ref hostname),
+                dns_update,
+                lease_time,
+            }
+            | LeaseEvent::Renewed {
+                ref client_id,
+                address,
+                hostname: Some(ref hostname),
+                dns_update,
+                lease_time,
+            } => self.add(client_id, address, hostname, dns_update, lease_time),
+            LeaseEvent::Released { ref client_id, .. }
+            | LeaseEvent::Expired { ref client_id, .. } => self.remove(client_id),
+            _ => {}
+        }
+    }
+}
+
+/// A resource record of the prerequisite or update sections.
+#[derive(Clone)]
+struct Record {
+    name: Vec<u8>,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+impl Record {
+    fn new(name: &str, rtype: u16, class: u16, ttl: u32, data: Vec<u8>) -> Self {
+        Record {
+            name: encode_name(name),
+            rtype,
+            class,
+            ttl,
+            data,
+        }
+    }
+
+    /// The RRset deletions and the name prerequisites have neither TTL nor data (RFC 2136 §2.4).
+    fn empty(name: &str, rtype: u16, class: u16) -> Self {
+        Self::new(name, rtype, class, 0, Vec::new())
+    }
+
+    fn put(&self, message: &mut Vec<u8>) {
+        message.extend_from_slice(&self.name);
+        message.put_u16_be(self.rtype);
+        message.put_u16_be(self.class);
+        message.put_u32_be(self.ttl);
+        message.put_u16_be(self.data.len() as u16);
+        message.extend_from_slice(&self.data);
+    }
+}
+
+/// Appends the TSIG record and increments the additional records count (RFC 8945 §4.3).
+///
+/// `time_signed` is in seconds since the epoch.
+fn sign(message: &mut Vec<u8>, id: u16, key: &TsigKey, time_signed: u64) {
+    let key_name = encode_name(&key.name.to_lowercase());
+    let algorithm = encode_name(TSIG_ALGORITHM);
+
+    let mut variables = Vec::new();
+    variables.extend_from_slice(&key_name);
+    variables.put_u16_be(CLASS_ANY);
+    variables.put_u32_be(0);
+    variables.extend_from_slice(&algorithm);
+    variables.put_uint_be(time_signed, 6);
+    variables.put_u16_be(TSIG_FUDGE);
+    variables.put_u16_be(0);
+    variables.put_u16_be(0);
+
+    let mut mac = Hmac::<Sha256>::new_varkey(&key.secret).expect("HMAC accepts keys of any size");
+    mac.input(message);
+    mac.input(&variables);
+    let mac = mac.result().code();
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&algorithm);
+    data.put_uint_be(time_signed, 6);
+    data.put_u16_be(TSIG_FUDGE);
+    data.put_u16_be(mac.len() as u16);
+    data.extend_from_slice(&mac);
+    data.put_u16_be(id);
+    data.put_u16_be(0);
+    data.put_u16_be(0);
+
+    message.extend_from_slice(&key_name);
+    message.put_u16_be(TYPE_TSIG);
+    message.put_u16_be(CLASS_ANY);
+    message.put_u32_be(0);
+    message.put_u16_be(data.len() as u16);
+    message.extend_from_slice(&data);
+
+    let additional = ((message[10] as u16) << 8 | message[11] as u16) + 1;
+    message[10] = (additional >> 8) as u8;
+    message[11] = additional as u8;
+}
+
+/// Sends the update until a response arrives or the retries are over
+/// and returns the response code.
+///
+/// The message is sent again as it is, so the TSIG time stays within the fudge.
+fn exchange(
+    server: SocketAddr,
+    message: Vec<u8>,
+) -> Box<Future<Item = u8, Error = io::Error> + Send> {
+    let mut strategy = Exponential::new(
+        Duration::from_secs(RETRY_DELAY),
+        Duration::from_secs(RETRY_DELAY_MAX),
+    );
+    strategy.with_retries(RETRIES);
+    let future = Retry::new(strategy, move || {
+        exchange_once(server, message.clone()).map_err(|error| {
+            debug!("DNS update attempt error: {}", error);
+            error
+        })
+    });
+    Box::new(future)
+}
+
+/// Sends the update and returns the response code.
+///
+/// The response signature is not verified, since only the code is used.
+fn exchange_once(
+    server: SocketAddr,
+    message: Vec<u8>,
+) -> Box<Future<Item = u8, Error = io::Error> + Send> {
+    let local = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
+    let socket = match UdpSocket::bind(&local) {
+        Ok(socket) => socket,
+        Err(error) => return Box::new(future::err(error)),
+    };
+    let id = [message[0], message[1]];
+    let future = socket
+        .send_dgram(message, &server)
+        .and_then(|(socket, _)| socket.recv_dgram(vec![0u8; RESPONSE_SIZE]))
+        .and_then(move |(_, response, len, _)| {
+            if len < 4 || response[..2] != id {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid DNS update response",
+                ));
+            }
+            Ok(response[3] & 0x0f)
+        });
+    let future = Deadline::new(future, Instant::now() + Duration::from_secs(RESPONSE_TIMEOUT))
+        .map_err(|error| {
+            if error.is_elapsed() {
+                return io::Error::new(io::ErrorKind::TimedOut, "DNS update timeout");
+            }
+            match error.into_inner() {
+                Some(error) => error,
+                None => io::Error::new(io::ErrorKind::Other, "DNS update timer error"),
+            }
+        });
+    Box::new(future)
+}
+
+/// The DHCID RDATA (RFC 4701 §3.3) computed from the identifier the server keys leases by.
+fn dhcid(client_id: &[u8], name: &str) -> Vec<u8> {
+    let mut identity = client_id.to_vec();
+    identity.extend(encode_name(&name.to_lowercase()));
+    let mut data = Vec::new();
+    data.put_u16_be(DHCID_IDENTIFIER_CLIENT_ID);
+    data.put_u8(DHCID_DIGEST_SHA256);
+    data.extend_from_slice(&Sha256::digest(&identity));
+    data
+}
+
+/// Like `4.3.2.1.in-addr.arpa` for `1.2.3.4`.
+fn pointer_name(address: &Ipv4Addr) -> String {
+    let octets = address.octets();
+    format!(
+        "{}.{}.{}.{}.in-addr.arpa",
+        octets[3], octets[2], octets[1], octets[0]
+    )
+}
+
+/// The first label of the host name, which must be a valid DNS label.
+///
+/// The rest of the name is ignored, so the clients cannot update other zones.
+fn host_label(hostname: &str) -> Option<String> {
+    let label = hostname.split('.').next()?.to_lowercase();
+    let is_valid = !label.is_empty()
+        && label.len() < 64
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if is_valid {
+        Some(label)
+    } else {
+        None
+    }
+}
+
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    for label in name.trim_right_matches('.').split('.') {
+        data.push(label.len() as u8);
+        data.extend_from_slice(label.as_bytes());
+    }
+    data.push(0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_with_reference_mac() {
+        // an update of the example.com zone without records, with the ID 0x1234
+        let mut message = vec![
+            0x12, 0x34, 0x28, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        message.extend_from_slice(b"\x07example\x03com\x00\x00\x06\x00\x01");
+        let key = TsigKey {
+            name: "Update-Key.".to_owned(),
+            secret: b"0123456789abcdef0123456789abcdef".to_vec(),
+        };
+        let mut expected = message.clone();
+
+        sign(&mut message, 0x1234, &key, 1_700_000_000);
+
+        // HMAC-SHA256 of the message followed by the TSIG variables of RFC 8945 §4.3.3:
+        // the lowercase key name, ANY, TTL 0, the algorithm name, the time signed,
+        // the fudge, no error and no other data
+        let mac = [
+            0xe5, 0x94, 0xd9, 0x93, 0x8d, 0xd2, 0x66, 0x24,
+            0x14, 0x06, 0x43, 0x83, 0xbb, 0x78, 0x36, 0xfa,
+            0x70, 0x5a, 0x03, 0xde, 0xe3, 0x38, 0x21, 0xa8,
+            0x48, 0x63, 0xc0, 0xae, 0xf6, 0x5f, 0x2e, 0xde,
+        ];
+        // the additional records count
+        expected[11] = 1;
+        // the key name, TSIG, ANY, TTL 0 and the RDATA length
+        expected.extend_from_slice(b"\x0aupdate-key\x00");
+        expected.extend_from_slice(b"\x00\xfa\x00\xff\x00\x00\x00\x00\x00\x3d");
+        // the algorithm name, the time signed, the fudge of 300 seconds and the MAC size
+        expected.extend_from_slice(b"\x0bhmac-sha256\x00");
+        expected.extend_from_slice(b"\x00\x00\x65\x53\xf1\x00\x01\x2c\x00\x20");
+        expected.extend_from_slice(&mac);
+        // the original ID, no error and no other data
+        expected.extend_from_slice(b"\x12\x34\x00\x00\x00\x00");
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn signs_over_time_signed() {
+        let key = TsigKey {
+            name: "key".to_owned(),
+            secret: vec![1, 2, 3],
+        };
+        let mut first = vec![
+            0x00, 0x01, 0x28, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut second = first.clone();
+        sign(&mut first, 1, &key, 1_700_000_000);
+        sign(&mut second, 1, &key, 1_700_000_001);
+        // the MAC follows the key name, the record header, the algorithm name,
+        // the time signed, the fudge and the MAC size
+        let offset = 12 + 5 + 10 + 13 + 6 + 2 + 2;
+        assert_ne!(first[offset..offset + 32], second[offset..offset + 32]);
+    }
+}
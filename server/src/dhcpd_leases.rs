@@ -0,0 +1,645 @@
+//! The ISC dhcpd leases file format, for migrating the bindings between the servers.
+//!
+//! Only the active and abandoned leases are carried over, like the wrapped one:
+//!
+//! ```text
+//! lease 192.168.0.10 {
+//!   starts 3 2018/08/01 12:00:00;
+//!   ends 4 2018/08/02 12:00:00;
+//!   binding state active;
+//!   hardware ethernet 00:11:22:33:44:55;
+//!   uid "\001\000\021\"3DU";
+//! }
+//! ```
+//!
+//! The file is a journal, so the last declaration of an address supersedes the previous ones.
+
+use std::{
+    collections::BTreeMap,
+    error, fmt,
+    io::{self, Write},
+    net::Ipv4Addr,
+};
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use eui48::MacAddress;
+
+use lease::{Lease, INFINITE_LEASE_TIME};
+use server::client_hardware_address;
+use storage::{self, Storage};
+
+/// Errors generated by the leases file import and export.
+#[derive(Debug)]
+pub enum Error {
+    Parse(usize, String),
+    Write(io::Error),
+    Storage(storage::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Parse(line, ref desc) => {
+                write!(f, "Leases file parsing error at line {}: {}", line, desc)
+            }
+            Error::Write(_) => f.write_str("Leases file writing error"),
+            Error::Storage(_) => f.write_str("Leases file storage error"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Parse(..) => None,
+            Error::Write(ref error) => Some(error),
+            Error::Storage(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<storage::Error> for Error {
+    fn from(error: storage::Error) -> Self {
+        Error::Storage(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Write(error)
+    }
+}
+
+/// A lexical token.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(Vec<u8>),
+    Open,
+    Close,
+    /// The statement terminator.
+    End,
+}
+
+/// The binding state of a lease declaration.
+#[derive(PartialEq)]
+enum Binding {
+    Active,
+    Abandoned,
+    Other,
+}
+
+/// The fields of a lease declaration the server keeps.
+struct Declaration {
+    starts: u32,
+    /// Zero for the infinite leases.
+    ends: u32,
+    binding: Binding,
+    hardware_address: Option<MacAddress>,
+    uid: Option<Vec<u8>>,
+}
+
+/// Imports the active leases and the abandoned addresses into the storage.
+///
+/// The client ID is the `uid` or the hardware address, like the one the server
+/// uses for the clients without the `client_id` option. The expired leases are skipped.
+///
+/// Returns the number of the imported leases and addresses.
+///
+/// # Errors
+/// `self::Error` if the file is malformed or the storage fails.
+pub fn import<S: Storage + ?Sized>(storage: &mut S, data: &str) -> Result<usize, Error> {
+    let now = Utc::now().timestamp() as u32;
+    let mut imported = 0;
+    for (address, declaration) in parse(data)?.into_iter() {
+        match declaration.binding {
+            Binding::Active => {
+                if declaration.ends != 0 && declaration.ends <= now {
+                    continue;
+                }
+                let client_id = match (declaration.uid, declaration.hardware_address) {
+                    (Some(uid), _) => uid,
+                    (None, Some(hardware_address)) => hardware_address.as_bytes().to_vec(),
+                    (None, None) => {
+                        warn!("Skipping the lease of {} without a client", address);
+                        continue;
+                    }
+                };
+                storage.add_client(&address, &client_id)?;
+                storage.add_lease(
+                    &client_id,
+                    Lease::migrated(address, declaration.starts, declaration.ends),
+                )?;
+            }
+            Binding::Abandoned => storage.add_frozen(&address)?,
+            Binding::Other => continue,
+        }
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Exports the active leases and the frozen addresses, the latter as abandoned.
+///
+/// Returns the number of the exported leases and addresses.
+///
+/// # Errors
+/// `self::Error` if the storage fails or the sink cannot be written.
+pub fn export<S: Storage + ?Sized>(storage: &S, sink: &mut Write) -> Result<usize, Error> {
+    writeln!(
+        sink,
+        "# The format of this file is documented in the dhcpd.leases(5) manual page."
+    )?;
+    writeln!(sink, "# This lease file was written by rust-dhcp\n")?;
+
+    let mut exported = 0;
+    for (client_id, lease) in storage.get_leases()?.into_iter() {
+        if !lease.is_active() {
+            continue;
+        }
+        writeln!(sink, "lease {} {{", lease.address())?;
+        writeln!(sink, "  starts {};", format_time(lease.updated_at()))?;
+        if lease.lease_time() == INFINITE_LEASE_TIME {
+            writeln!(sink, "  ends never;")?;
+        } else {
+            writeln!(sink, "  ends {};", format_time(lease.expires_at()))?;
+        }
+        writeln!(sink, "  cltt {};", format_time(lease.updated_at()))?;
+        writeln!(sink, "  binding state active;")?;
+        writeln!(sink, "  next binding state free;")?;
+        if let Some(hardware_address) = client_hardware_address(&client_id) {
+            writeln!(
+                sink,
+                "  hardware ethernet {};",
+                hardware_address.to_hex_string()
+            )?;
+        }
+        writeln!(sink, "  uid \"{}\";", escape(&client_id))?;
+        writeln!(sink, "}}")?;
+        exported += 1;
+    }
+    for address in storage.get_frozen()?.into_iter() {
+        writeln!(sink, "lease {} {{", address)?;
+        writeln!(sink, "  binding state abandoned;")?;
+        writeln!(sink, "}}")?;
+        exported += 1;
+    }
+    Ok(exported)
+}
+
+/// Parses the lease declarations, skipping the other statements and blocks.
+fn parse(data: &str) -> Result<BTreeMap<Ipv4Addr, Declaration>, Error> {
+    let tokens = tokenize(data)?;
+    let mut declarations = BTreeMap::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let (line, ref token) = tokens[index];
+        index += 1;
+        match *token {
+            Token::Word(ref word) if word == "lease" => {
+                let address = match tokens.get(index) {
+                    Some(&(_, Token::Word(ref address))) => address.parse().map_err(|_| {
+                        Error::Parse(line, format!("Invalid lease address {}", address))
+                    })?,
+                    _ => return Err(Error::Parse(line, "Expected a lease address".to_owned())),
+                };
+                if tokens.get(index + 1).map(|&(_, ref token)| token) != Some(&Token::Open) {
+                    return Err(Error::Parse(line, "Expected {".to_owned()));
+                }
+                index += 2;
+                let declaration = parse_declaration(&tokens, &mut index)?;
+                declarations.insert(address, declaration);
+            }
+            Token::Open => skip_block(&tokens, &mut index),
+            _ => {}
+        }
+    }
+    Ok(declarations)
+}
+
+/// Parses the statements of a lease declaration up to its closing brace.
+fn parse_declaration(tokens: &[(usize, Token)], index: &mut usize) -> Result<Declaration, Error> {
+    let mut declaration = Declaration {
+        starts: 0,
+        ends: 0,
+        binding: Binding::Other,
+        hardware_address: None,
+        uid: None,
+    };
+    loop {
+        let mut statement = Vec::new();
+        loop {
+            match tokens.get(*index) {
+                Some(&(_, Token::End)) => {
+                    *index += 1;
+                    break;
+                }
+                Some(&(_, Token::Close)) if statement.is_empty() => {
+                    *index += 1;
+                    return Ok(declaration);
+                }
+                Some(&(_, Token::Open)) => {
+                    *index += 1;
+                    skip_block(tokens, index);
+                    break;
+                }
+                Some(&(line, ref token)) => {
+                    statement.push((line, token));
+                    *index += 1;
+                }
+                None => {
+                    let line = tokens.last().map_or(1, |&(line, _)| line);
+                    return Err(Error::Parse(
+                        line,
+                        "Unterminated lease declaration".to_owned(),
+                    ));
+                }
+            }
+        }
+        if let Some(&(line, _)) = statement.first() {
+            apply_statement(&mut declaration, line, &statement)?;
+        }
+    }
+}
+
+fn apply_statement(
+    declaration: &mut Declaration,
+    line: usize,
+    statement: &[(usize, &Token)],
+) -> Result<(), Error> {
+    let words: Vec<&str> = statement
+        .iter()
+        .filter_map(|&(_, token)| match *token {
+            Token::Word(ref word) => Some(word.as_str()),
+            _ => None,
+        })
+        .collect();
+    let invalid = |what: &str| Error::Parse(line, format!("Invalid {}", what));
+
+    match words.first().cloned() {
+        Some("starts") => {
+            declaration.starts = parse_time(&words[1..]).ok_or_else(|| invalid("start time"))?;
+        }
+        Some("ends") => {
+            declaration.ends = parse_time(&words[1..]).ok_or_else(|| invalid("end time"))?;
+        }
+        Some("binding") if words.get(1) == Some(&"state") => {
+            declaration.binding = match words.get(2) {
+                Some(&"active") => Binding::Active,
+                Some(&"abandoned") => Binding::Abandoned,
+                _ => Binding::Other,
+            };
+        }
+        Some("hardware") if words.get(1) == Some(&"ethernet") => {
+            let hardware_address = words.get(2).ok_or_else(|| invalid("hardware address"))?;
+            declaration.hardware_address = Some(
+                MacAddress::parse_str(hardware_address).map_err(|_| invalid("hardware address"))?,
+            );
+        }
+        Some("uid") => {
+            declaration.uid = Some(match statement.get(1) {
+                Some(&(_, &Token::Text(ref uid))) => uid.to_owned(),
+                Some(&(_, &Token::Word(ref uid))) => uid
+                    .split(':')
+                    .map(|octet| u8::from_str_radix(octet, 16).ok())
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(|| invalid("uid"))?,
+                _ => return Err(invalid("uid")),
+            });
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Skips the tokens up to the closing brace of a block, including the nested blocks.
+fn skip_block(tokens: &[(usize, Token)], index: &mut usize) {
+    let mut depth = 1;
+    while depth > 0 && *index < tokens.len() {
+        match tokens[*index].1 {
+            Token::Open => depth += 1,
+            Token::Close => depth -= 1,
+            _ => {}
+        }
+        *index += 1;
+    }
+}
+
+/// Splits the data into words, quoted strings and punctuation, dropping the comments.
+fn tokenize(data: &str) -> Result<Vec<(usize, Token)>, Error> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut bytes = data.bytes().peekable();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'\n' => line += 1,
+            b'#' => {
+                while bytes.peek().map_or(false, |&byte| byte != b'\n') {
+                    bytes.next();
+                }
+            }
+            b'{' => tokens.push((line, Token::Open)),
+            b'}' => tokens.push((line, Token::Close)),
+            b';' => tokens.push((line, Token::End)),
+            b'"' => {
+                let start = line;
+                let mut text = Vec::new();
+                loop {
+                    match bytes.next() {
+                        Some(b'"') => break,
+                        Some(b'\\') => match bytes.next() {
+                            Some(digit @ b'0'...b'7') => {
+                                let mut value = u32::from(digit - b'0');
+                                for _ in 0..2 {
+                                    match bytes.peek().cloned() {
+                                        Some(digit @ b'0'...b'7') => {
+                                            value = value * 8 + u32::from(digit - b'0');
+                                            bytes.next();
+                                        }
+                                        _ => break,
+                                    }
+                                }
+                                text.push(value as u8);
+                            }
+                            Some(b'n') => text.push(b'\n'),
+                            Some(b't') => text.push(b'\t'),
+                            Some(b'r') => text.push(b'\r'),
+                            Some(escaped) => text.push(escaped),
+                            None => break,
+                        },
+                        Some(byte) => {
+                            if byte == b'\n' {
+                                line += 1;
+                            }
+                            text.push(byte);
+                        }
+                        None => return Err(Error::Parse(start, "Unterminated string".to_owned())),
+                    }
+                }
+                tokens.push((start, Token::Text(text)));
+            }
+            byte if (byte as char).is_ascii_whitespace() => {}
+            byte => {
+                let mut word = vec![byte];
+                while let Some(&byte) = bytes.peek() {
+                    if (byte as char).is_ascii_whitespace() || b"{};\"#".contains(&byte) {
+                        break;
+                    }
+                    word.push(byte);
+                    bytes.next();
+                }
+                tokens.push((
+                    line,
+                    Token::Word(String::from_utf8_lossy(&word).into_owned()),
+                ));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses `never`, `epoch <seconds>` or `<weekday> <YYYY/MM/DD> <HH:MM:SS>` in UTC.
+///
+/// Returns zero for `never`.
+fn parse_time(words: &[&str]) -> Option<u32> {
+    match (words.get(0), words.get(1), words.get(2)) {
+        (Some(&"never"), _, _) => Some(0),
+        (Some(&"epoch"), Some(seconds), _) => seconds.parse().ok(),
+        (Some(_), Some(date), Some(time)) => {
+            NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y/%m/%d %H:%M:%S")
+                .ok()
+                .map(|time| time.timestamp() as u32)
+        }
+        _ => None,
+    }
+}
+
+/// Formats the Unix time like `3 2018/08/01 12:00:00`, where the first number is the weekday.
+fn format_time(timestamp: u32) -> String {
+    Utc.timestamp(i64::from(timestamp), 0)
+        .format("%w %Y/%m/%d %H:%M:%S")
+        .to_string()
+}
+
+/// Escapes the bytes for a quoted string, the non-printable ones as octal.
+fn escape(data: &[u8]) -> String {
+    let mut escaped = String::with_capacity(data.len() * 4);
+    for &byte in data.iter() {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            0x20...0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use storage_ram::RamStorage;
+
+    /// The addresses and the IDs of the clients.
+    type Clients = Vec<(Ipv4Addr, Vec<u8>)>;
+
+    /// Ends long after the tests are run.
+    const FUTURE: &str = "epoch 4000000000";
+
+    fn clients(storage: &RamStorage) -> Clients {
+        let mut clients: Clients = storage
+            .clients()
+            .map(|(address, client_id)| (*address, client_id.to_owned()))
+            .collect();
+        clients.sort();
+        clients
+    }
+
+    #[test]
+    fn parses_times() {
+        let cases: &[(&[&str], Option<u32>)] = &[
+            (&["never"], Some(0)),
+            (&["epoch", "1533124800"], Some(1_533_124_800)),
+            (&["3", "2018/08/01", "12:00:00"], Some(1_533_124_800)),
+            (&["epoch", "soon"], None),
+            (&["3", "2018/13/01", "12:00:00"], None),
+            (&["3", "2018/08/01"], None),
+            (&[], None),
+        ];
+        for &(words, expected) in cases {
+            assert_eq!(parse_time(words), expected, "{:?}", words);
+        }
+        assert_eq!(format_time(1_533_124_800), "3 2018/08/01 12:00:00");
+    }
+
+    #[test]
+    fn escapes_uids() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"abc", "abc"),
+            (
+                &[1, 0, 0x11, b'"', b'3', b'D', b'U'],
+                r#"\001\000\021\"3DU"#,
+            ),
+            (b"a\\b", r#"a\\b"#),
+            (&[0x7f, 0xff], r#"\177\377"#),
+        ];
+        for &(data, escaped) in cases {
+            assert_eq!(escape(data), escaped, "{:?}", data);
+            let tokens = tokenize(&format!("\"{}\"", escaped)).unwrap();
+            assert_eq!(tokens, vec![(1, Token::Text(data.to_vec()))], "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn imports_leases() {
+        // the leases file, the imported number, clients and frozen addresses
+        let cases: Vec<(String, usize, Clients, Vec<Ipv4Addr>)> = vec![
+            (String::new(), 0, vec![], vec![]),
+            (
+                format!(
+                    "lease 192.168.0.10 {{ starts {0}; ends {0}; binding state active; \
+                     uid \"\\001\\000\\021\\\"3DU\"; }}",
+                    FUTURE
+                ),
+                1,
+                vec![(
+                    Ipv4Addr::new(192, 168, 0, 10),
+                    vec![1, 0, 0x11, 0x22, 0x33, 0x44, 0x55],
+                )],
+                vec![],
+            ),
+            (
+                format!(
+                    "lease 192.168.0.11 {{\n  ends {};\n  binding state active;\n  \
+                     hardware ethernet 00:11:22:33:44:55;\n}}\n",
+                    FUTURE
+                ),
+                1,
+                vec![(
+                    Ipv4Addr::new(192, 168, 0, 11),
+                    vec![0, 0x11, 0x22, 0x33, 0x44, 0x55],
+                )],
+                vec![],
+            ),
+            (
+                format!(
+                    "lease 192.168.0.12 {{ ends {}; binding state active; uid 01:02:03; }}",
+                    FUTURE
+                ),
+                1,
+                vec![(Ipv4Addr::new(192, 168, 0, 12), vec![1, 2, 3])],
+                vec![],
+            ),
+            (
+                "lease 192.168.0.13 { ends epoch 1; binding state active; uid 01; }".to_owned(),
+                0,
+                vec![],
+                vec![],
+            ),
+            (
+                format!(
+                    "lease 192.168.0.14 {{ ends {}; binding state active; }}",
+                    FUTURE
+                ),
+                0,
+                vec![],
+                vec![],
+            ),
+            (
+                "lease 192.168.0.15 { binding state free; uid 01; }\n\
+                 lease 192.168.0.16 { binding state abandoned; }"
+                    .to_owned(),
+                1,
+                vec![],
+                vec![Ipv4Addr::new(192, 168, 0, 16)],
+            ),
+            (
+                format!(
+                    "# a comment\nserver-duid \"\\000\\001\";\nfailover peer \"peer\" state {{ \
+                     my state normal; }}\nlease 192.168.0.17 {{ binding state abandoned; }}\n\
+                     lease 192.168.0.17 {{ ends {}; binding state active; uid 01; \
+                     on expiry {{ set a = 1; }} }}",
+                    FUTURE
+                ),
+                1,
+                vec![(Ipv4Addr::new(192, 168, 0, 17), vec![1])],
+                vec![],
+            ),
+        ];
+        for (data, imported, expected_clients, frozen) in cases {
+            let mut storage = RamStorage::new();
+            assert_eq!(import(&mut storage, &data).unwrap(), imported, "{}", data);
+            assert_eq!(clients(&storage), expected_clients, "{}", data);
+            assert_eq!(storage.get_frozen().unwrap(), frozen, "{}", data);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        // the leases file and the line of the error
+        let cases: &[(&str, usize)] = &[
+            ("lease 192.168.0.256 { }", 1),
+            ("lease {", 1),
+            ("lease 192.168.0.10 binding state active;", 1),
+            ("lease 192.168.0.10 {\n  binding state active;\n", 2),
+            ("lease 192.168.0.10 {\n  starts 3 2018/08/01;\n}", 2),
+            ("lease 192.168.0.10 {\n\n  hardware ethernet 00:11;\n}", 3),
+            ("lease 192.168.0.10 {\n  uid 01:xx;\n}", 2),
+            ("lease 192.168.0.10 {\n  uid \"\\001;\n}", 2),
+        ];
+        for &(data, line) in cases {
+            match import(&mut RamStorage::new(), data) {
+                Err(Error::Parse(error_line, _)) => assert_eq!(error_line, line, "{}", data),
+                result => panic!("{}: unexpected {:?}", data, result),
+            }
+        }
+    }
+
+    #[test]
+    fn exports_and_imports_back() {
+        let mut storage = RamStorage::new();
+        let clients_added = vec![
+            (
+                Ipv4Addr::new(192, 168, 0, 10),
+                vec![1, 0, 0x11, 0x22, 0x33, 0x44, 0x55],
+            ),
+            (
+                Ipv4Addr::new(192, 168, 0, 11),
+                b"client \"quoted\"".to_vec(),
+            ),
+        ];
+        for &(address, ref client_id) in clients_added.iter() {
+            let mut lease = Lease::new(address, 3600);
+            lease.assign(3600);
+            storage.add_client(&address, client_id).unwrap();
+            storage.add_lease(client_id, lease).unwrap();
+        }
+        let mut released = Lease::new(Ipv4Addr::new(192, 168, 0, 12), 3600);
+        released.release();
+        storage.add_lease(&[9], released).unwrap();
+        storage.add_frozen(&Ipv4Addr::new(192, 168, 0, 13)).unwrap();
+
+        let mut data = Vec::new();
+        assert_eq!(export(&storage, &mut data).unwrap(), 3);
+        let data = String::from_utf8(data).unwrap();
+        assert!(data.contains("hardware ethernet 00:11:22:33:44:55;"));
+
+        let mut imported = RamStorage::new();
+        assert_eq!(import(&mut imported, &data).unwrap(), 3);
+        assert_eq!(clients(&imported), clients_added);
+        assert_eq!(
+            imported.get_frozen().unwrap(),
+            vec![Ipv4Addr::new(192, 168, 0, 13)]
+        );
+        for client in clients_added.iter() {
+            let client_id = &client.1;
+            let original = storage.get_lease(client_id).unwrap().unwrap();
+            let migrated = imported.get_lease(client_id).unwrap().unwrap();
+            assert_eq!(migrated.address(), original.address());
+            assert_eq!(migrated.expires_at(), original.expires_at());
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! The error type of the server crate, so the applications may handle the errors
+//! of the configuration, the storages, the sockets and the server itself with a single `?`.
+//!
+//! The causes are kept as the sources, so the whole chain can be reported.
+
+use std::{error, fmt, io};
+
+use dhcp_protocol::ProtocolError;
+
+use config;
+use dhcpd_leases;
+use storage;
+
+/// Any error of the server crate.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Config(config::Error),
+    Storage(storage::Error),
+    DhcpdLeases(dhcpd_leases::Error),
+    Protocol(ProtocolError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Error::Io(_) => "Server I/O error",
+            Error::Config(_) => "Configuration error",
+            Error::Storage(_) => "Storage error",
+            Error::DhcpdLeases(_) => "Leases file error",
+            Error::Protocol(_) => "Protocol error",
+        })
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Io(ref error) => Some(error),
+            Error::Config(ref error) => Some(error),
+            Error::Storage(ref error) => Some(error),
+            Error::DhcpdLeases(ref error) => Some(error),
+            Error::Protocol(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(error: config::Error) -> Self {
+        Error::Config(error)
+    }
+}
+
+impl From<storage::Error> for Error {
+    fn from(error: storage::Error) -> Self {
+        Error::Storage(error)
+    }
+}
+
+impl From<dhcpd_leases::Error> for Error {
+    fn from(error: dhcpd_leases::Error) -> Self {
+        Error::DhcpdLeases(error)
+    }
+}
+
+impl From<ProtocolError> for Error {
+    fn from(error: ProtocolError) -> Self {
+        Error::Protocol(error)
+    }
+}
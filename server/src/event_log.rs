@@ -0,0 +1,170 @@
+//! The machine-parseable lease event log for the SIEM systems.
+//!
+//! Every event is written as a JSON object on its own line, like the wrapped one:
+//!
+//! ```text
+//! {"timestamp":"2018-08-01T12:00:00Z","event":"renewed","client_id":"01a1b2c3d4e5f6",
+//! "address":"192.168.0.10","hostname":"laptop","lease_time":3600}
+//! ```
+//!
+//! The records are kept apart from the human-oriented `log` output.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    net::Ipv4Addr,
+    path::Path,
+    sync::Mutex,
+};
+
+use chrono::{SecondsFormat, Utc};
+use serde_json;
+
+use observer::{LeaseEvent, Observer};
+use storage_file::hex_encode;
+
+/// A line of the event log, also posted by the webhooks.
+#[derive(Serialize)]
+pub(crate) struct Record<'a> {
+    /// RFC 3339 in UTC.
+    timestamp: String,
+    event: &'static str,
+    /// Hexadecimal, absent for the address conflicts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+    address: Ipv4Addr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lease_time: Option<u32>,
+    /// The error the `DHCPNAK` has been sent for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+}
+
+impl<'a> Record<'a> {
+    pub(crate) fn new(event: &'a LeaseEvent) -> Self {
+        let mut record = Record {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            event: "",
+            client_id: None,
+            address: Ipv4Addr::new(0, 0, 0, 0),
+            hostname: None,
+            lease_time: None,
+            reason: None,
+        };
+        match *event {
+            LeaseEvent::Committed {
+                ref client_id,
+                address,
+                ref hostname,
+                lease_time,
+                ..
+            } => {
+                record.event = "committed";
+                record.client_id = Some(hex_encode(client_id));
+                record.address = address;
+                record.hostname = hostname.as_ref().map(String::as_str);
+                record.lease_time = Some(lease_time);
+            }
+            LeaseEvent::Renewed {
+                ref client_id,
+                address,
+                ref hostname,
+                lease_time,
+                ..
+            } => {
+                record.event = "renewed";
+                record.client_id = Some(hex_encode(client_id));
+                record.address = address;
+                record.hostname = hostname.as_ref().map(String::as_str);
+                record.lease_time = Some(lease_time);
+            }
+            LeaseEvent::Released {
+                ref client_id,
+                address,
+            } => {
+                record.event = "released";
+                record.client_id = Some(hex_encode(client_id));
+                record.address = address;
+            }
+            LeaseEvent::Expired {
+                ref client_id,
+                address,
+            } => {
+                record.event = "expired";
+                record.client_id = Some(hex_encode(client_id));
+                record.address = address;
+            }
+            LeaseEvent::Declined {
+                ref client_id,
+                address,
+            } => {
+                record.event = "declined";
+                record.client_id = Some(hex_encode(client_id));
+                record.address = address;
+            }
+            LeaseEvent::Rejected {
+                ref client_id,
+                address,
+                ref reason,
+            } => {
+                record.event = "nak";
+                record.client_id = Some(hex_encode(client_id));
+                record.address = address;
+                record.reason = Some(reason.as_str());
+            }
+            LeaseEvent::Conflict { address } => {
+                record.event = "conflict";
+                record.address = address;
+            }
+        }
+        record
+    }
+}
+
+/// Writes the lease events as JSON lines to a sink.
+pub struct EventLogger {
+    sink: Mutex<Box<Write + Send>>,
+}
+
+impl EventLogger {
+    pub fn new(sink: Box<Write + Send>) -> Self {
+        EventLogger {
+            sink: Mutex::new(sink),
+        }
+    }
+
+    /// Writes the events to the standard output.
+    pub fn stdout() -> Self {
+        EventLogger::new(Box::new(io::stdout()))
+    }
+
+    /// Appends the events to the file, creating it if it does not exist.
+    ///
+    /// # Errors
+    /// `io::Error` if the file cannot be opened.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(EventLogger::new(Box::new(file)))
+    }
+
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut sink = self
+            .sink
+            .lock()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "The sink is poisoned"))?;
+        sink.write_all(&line)?;
+        sink.flush()
+    }
+}
+
+impl Observer for EventLogger {
+    fn on_lease_event(&self, event: &LeaseEvent) {
+        if let Err(error) = self.write(&Record::new(event)) {
+            warn!("Event logging error: {}", error);
+        }
+    }
+}
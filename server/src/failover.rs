@@ -0,0 +1,605 @@
+//! The failover pair of two servers sharing the address pools.
+//!
+//! The servers exchange the lease updates over a TCP connection as the lines
+//! of the file storage journal format, and acknowledge every received update.
+//! The primary accepts the connection and the secondary keeps connecting to it.
+//!
+//! Each server allocates the new addresses only from its own share of every pool range,
+//! so the pair never allocates an address twice even if the peer is unreachable.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use tokio::{
+    codec::{Framed, LinesCodec},
+    io,
+    net::{
+        tcp::{ConnectFuture, Incoming},
+        TcpListener, TcpStream,
+    },
+    prelude::*,
+};
+
+use dhcp_backoff::{Backoff, Exponential};
+use dhcp_protocol::Message;
+
+use lease::Lease;
+use storage_file::{hex_decode, hex_encode};
+
+/// How long the secondary waits after the first failed connection in seconds.
+const RECONNECT_INTERVAL: u64 = 5;
+/// The reconnection delay stops growing at it in seconds.
+const RECONNECT_INTERVAL_MAX: u64 = 60;
+/// The reconnection delays are randomized by up to it in seconds, so the retries spread out.
+const RECONNECT_JITTER: u64 = 1;
+/// The share of every pool range the primary allocates from in the standby mode in percent.
+const STANDBY_PRIMARY_SHARE: u32 = 90;
+/// The share of every pool range the primary allocates from in the load balancing mode in percent.
+const LOAD_BALANCE_PRIMARY_SHARE: u32 = 50;
+
+/// The server role in the pair.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Primary,
+    Secondary,
+}
+
+/// The way the clients are split between the servers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// The primary serves all the clients and the secondary takes over
+    /// only while the primary is unreachable.
+    Standby,
+    /// The clients are split by a hash of the client ID, so both servers agree
+    /// on the split without talking to each other.
+    LoadBalance,
+}
+
+/// A change reported by the failover peer.
+pub enum PeerEvent {
+    /// The connection has been established, so the peer needs all the leases.
+    Connected,
+    /// The peer has changed the lease of the client.
+    Update(Vec<u8>, Lease),
+}
+
+/// The connection to the failover peer.
+pub struct Failover {
+    role: Role,
+    mode: Mode,
+    /// The address the primary listens on and the secondary connects to.
+    address: SocketAddr,
+    /// The maximum client lead time in seconds.
+    mclt: u32,
+    /// The incoming connections of the primary.
+    listener: Option<Incoming>,
+    /// Delays the connection attempts of the secondary after a failure.
+    ///
+    /// Not set until the first failure, so the secondary connects at once.
+    reconnect: Option<Backoff>,
+    connecting: Option<ConnectFuture>,
+    peer: Option<Framed<TcpStream, LinesCodec>>,
+    /// The updates and acknowledgements waiting for the connection to accept them.
+    outgoing: VecDeque<String>,
+    /// The clients whose leases the peer has acknowledged over the current connection.
+    acknowledged: HashSet<Vec<u8>>,
+}
+
+impl Failover {
+    /// Starts listening if the server is the primary.
+    ///
+    /// # Errors
+    /// `io::Error` if the address cannot be bound.
+    pub fn new(role: Role, mode: Mode, address: SocketAddr, mclt: u32) -> io::Result<Self> {
+        let listener = match role {
+            Role::Primary => Some(TcpListener::bind(&address)?.incoming()),
+            Role::Secondary => None,
+        };
+        Ok(Failover {
+            role,
+            mode,
+            address,
+            mclt,
+            listener,
+            reconnect: None,
+            connecting: None,
+            peer: None,
+            outgoing: VecDeque::new(),
+            acknowledged: HashSet::new(),
+        })
+    }
+
+    /// The `[from, to)` percent of every pool range the server allocates from.
+    pub fn pool_share(&self) -> (u32, u32) {
+        let primary_share = match self.mode {
+            Mode::Standby => STANDBY_PRIMARY_SHARE,
+            Mode::LoadBalance => LOAD_BALANCE_PRIMARY_SHARE,
+        };
+        match self.role {
+            Role::Primary => (0, primary_share),
+            Role::Secondary => (primary_share, 100),
+        }
+    }
+
+    /// Checks whether the server must answer the request.
+    ///
+    /// The messages with the server identifier have already been filtered,
+    /// so only the broadcast ones are split, and only while the peer is reachable.
+    pub fn serves(&self, request: &Message, client_id: &[u8]) -> bool {
+        if self.peer.is_none() || request.options.dhcp_server_id.is_some() {
+            return true;
+        }
+        match self.mode {
+            Mode::Standby => self.role == Role::Primary,
+            Mode::LoadBalance => (fnv1a(client_id) % 2 == 0) == (self.role == Role::Primary),
+        }
+    }
+
+    /// Clamps the lease time to the MCLT until the peer acknowledges a lease of the client,
+    /// so the peer learns about the lease before it may take the address over.
+    pub fn lease_time(&self, client_id: &[u8], requested: Option<u32>) -> Option<u32> {
+        if self.peer.is_some() && self.acknowledged.contains(client_id) {
+            return requested;
+        }
+        Some(requested.map_or(self.mclt, |requested| requested.min(self.mclt)))
+    }
+
+    /// Sends the lease of the client to the peer if it is connected.
+    ///
+    /// The peer gets all the leases on reconnection, so nothing is queued while disconnected.
+    pub fn send(&mut self, client_id: &[u8], lease: &Lease) {
+        if self.peer.is_none() {
+            return;
+        }
+        self.outgoing.push_back(format!(
+            "lease {} {}",
+            hex_encode(client_id),
+            lease.to_record()
+        ));
+        self.flush();
+    }
+
+    /// Returns the next peer event, maintaining the connection.
+    pub fn poll_peer(&mut self) -> Option<PeerEvent> {
+        if self.poll_connection() {
+            return Some(PeerEvent::Connected);
+        }
+
+        self.flush();
+        loop {
+            let result = match self.peer {
+                Some(ref mut peer) => peer.poll(),
+                None => return None,
+            };
+            match result {
+                Ok(Async::Ready(Some(line))) => {
+                    if let Some(client_id) = parse_ack(&line) {
+                        self.acknowledged.insert(client_id);
+                        continue;
+                    }
+                    match parse_update(&line) {
+                        Some((client_id, lease)) => {
+                            self.outgoing.push_back(format!("ack {}", hex_encode(&client_id)));
+                            self.flush();
+                            return Some(PeerEvent::Update(client_id, lease));
+                        }
+                        None => warn!("Ignoring the malformed failover update {}", line),
+                    }
+                }
+                Ok(Async::Ready(None)) => {
+                    warn!("The failover peer has disconnected");
+                    self.disconnect();
+                    return None;
+                }
+                Ok(Async::NotReady) => return None,
+                Err(error) => {
+                    warn!("Failover peer receiving error: {}", error);
+                    self.disconnect();
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Moves the queued updates to the connection until it is full.
+    fn flush(&mut self) {
+        let result = match self.peer {
+            Some(ref mut peer) => {
+                let outgoing = &mut self.outgoing;
+                let mut result = Ok(());
+                while let Some(line) = outgoing.pop_front() {
+                    match peer.start_send(line) {
+                        Ok(AsyncSink::Ready) => {}
+                        Ok(AsyncSink::NotReady(line)) => {
+                            outgoing.push_front(line);
+                            break;
+                        }
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
+                }
+                result.and_then(|_| peer.poll_complete().map(|_| ()))
+            }
+            None => return,
+        };
+        if let Err(error) = result {
+            warn!("Failover peer sending error: {}", error);
+            self.disconnect();
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.peer = None;
+        self.outgoing.clear();
+        self.acknowledged.clear();
+    }
+
+    /// Accepts or establishes the connection.
+    ///
+    /// Returns `true` if a new connection has been established.
+    fn poll_connection(&mut self) -> bool {
+        if let Some(ref mut listener) = self.listener {
+            match listener.poll() {
+                Ok(Async::Ready(Some(stream))) => {
+                    if self.peer.is_some() {
+                        warn!("Replacing the failover peer connection");
+                    }
+                    info!("The failover peer has connected");
+                    self.outgoing.clear();
+                    self.acknowledged.clear();
+                    self.peer = Some(Framed::new(stream, LinesCodec::new()));
+                    return true;
+                }
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => {}
+                Err(error) => warn!("Failover peer accepting error: {}", error),
+            }
+            return false;
+        }
+
+        if self.peer.is_some() {
+            return false;
+        }
+        if self.connecting.is_none() {
+            if let Some(ref mut reconnect) = self.reconnect {
+                match reconnect.poll() {
+                    Ok(Async::Ready(Some(_))) => {}
+                    Ok(Async::Ready(None)) | Ok(Async::NotReady) => return false,
+                    Err(error) => {
+                        warn!("Failover reconnection timer error: {}", error);
+                        return false;
+                    }
+                }
+            }
+            trace!("Connecting to the failover peer {}", self.address);
+            self.connecting = Some(TcpStream::connect(&self.address));
+        }
+
+        let result = match self.connecting {
+            Some(ref mut connecting) => connecting.poll(),
+            None => return false,
+        };
+        match result {
+            Ok(Async::Ready(stream)) => {
+                info!("Connected to the failover peer {}", self.address);
+                self.connecting = None;
+                self.reconnect = None;
+                self.peer = Some(Framed::new(stream, LinesCodec::new()));
+                true
+            }
+            Ok(Async::NotReady) => false,
+            Err(error) => {
+                debug!("Failover peer connection error: {}", error);
+                self.connecting = None;
+                if self.reconnect.is_none() {
+                    self.reconnect = Some(Backoff::new(reconnect_strategy()));
+                }
+                false
+            }
+        }
+    }
+}
+
+/// Grows the delay between the connection attempts of the secondary, retrying forever.
+fn reconnect_strategy() -> Exponential {
+    let mut strategy = Exponential::new(
+        Duration::from_secs(RECONNECT_INTERVAL),
+        Duration::from_secs(RECONNECT_INTERVAL_MAX),
+    );
+    strategy.with_jitter(Duration::from_secs(RECONNECT_JITTER));
+    strategy
+}
+
+/// Parses the acknowledgement of an update written by `Failover::poll_peer`.
+fn parse_ack(line: &str) -> Option<Vec<u8>> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 2 || fields[0] != "ack" {
+        return None;
+    }
+    hex_decode(fields[1])
+}
+
+/// Parses the line written by `Failover::send`.
+fn parse_update(line: &str) -> Option<(Vec<u8>, Lease)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 2 || fields[0] != "lease" {
+        return None;
+    }
+    Some((hex_decode(fields[1])?, Lease::from_record(&fields[2..])?))
+}
+
+/// The 32-bit FNV-1a hash.
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{self, Ipv4Addr};
+
+    use eui48::MacAddress;
+    use tokio::{runtime::current_thread::Runtime, timer::Timeout};
+
+    use dhcp_protocol::{HardwareType, OperationCode, Options};
+
+    const CLIENT_ID: &[u8] = &[1, 0x00, 0x0c, 0x29, 0x13, 0x0e, 0x37];
+    const MCLT: u32 = 60;
+    const LEASE_TIME: u32 = 3600;
+    /// How long the servers may take to exchange the messages in seconds.
+    const TIMEOUT: u64 = 5;
+
+    /// A broadcast request without the server identifier.
+    fn request() -> Message {
+        Message {
+            operation_code: OperationCode::BootRequest,
+            hardware_type: HardwareType::Ethernet,
+            hardware_address_length: 6,
+            hardware_options: Default::default(),
+
+            transaction_id: 1,
+            seconds: Default::default(),
+            is_broadcast: true,
+
+            client_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            your_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            server_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+            gateway_ip_address: Ipv4Addr::new(0, 0, 0, 0),
+
+            client_hardware_address: MacAddress::new([0x00, 0x0c, 0x29, 0x13, 0x0e, 0x37]),
+            server_name: Default::default(),
+            boot_filename: Default::default(),
+
+            options: Options::default(),
+        }
+    }
+
+    /// A local address nothing listens on.
+    fn unused_address() -> SocketAddr {
+        net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+    }
+
+    /// Polls the servers until the `done` returns `true`, collecting their events.
+    ///
+    /// `None` stands for a server which has been shut down.
+    fn drive<F>(
+        runtime: &mut Runtime,
+        servers: &mut [Option<&mut Failover>],
+        events: &mut [Vec<PeerEvent>],
+        mut done: F,
+    ) where
+        F: FnMut(&[Option<&mut Failover>], &[Vec<PeerEvent>]) -> bool,
+    {
+        let future = future::poll_fn(|| -> Poll<(), ()> {
+            for (server, events) in servers.iter_mut().zip(events.iter_mut()) {
+                if let Some(ref mut server) = *server {
+                    while let Some(event) = server.poll_peer() {
+                        events.push(event);
+                    }
+                }
+            }
+            if done(servers, events) {
+                Ok(Async::Ready(()))
+            } else {
+                Ok(Async::NotReady)
+            }
+        });
+        runtime
+            .block_on(Timeout::new(future, Duration::from_secs(TIMEOUT)))
+            .expect("The servers have not reached the state in time");
+    }
+
+    /// Sends the lease of the client within a task, since the connection may register it.
+    fn send(runtime: &mut Runtime, server: &mut Failover, lease: &Lease) {
+        runtime
+            .block_on(future::lazy(|| -> Result<(), ()> {
+                server.send(CLIENT_ID, lease);
+                Ok(())
+            })).unwrap();
+    }
+
+    fn connected(events: &[PeerEvent]) -> bool {
+        for event in events {
+            if let PeerEvent::Connected = *event {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn splits_pools_and_clients() {
+        let address = unused_address();
+        // the role, the mode, the pool share and whether the client is served with the peer up;
+        // the client ID hash is odd, so the secondary gets the client in the load balancing mode
+        let cases = [
+            (Role::Primary, Mode::Standby, (0, 90), true),
+            (Role::Secondary, Mode::Standby, (90, 100), false),
+            (Role::Primary, Mode::LoadBalance, (0, 50), false),
+            (Role::Secondary, Mode::LoadBalance, (50, 100), true),
+        ];
+        assert_eq!(fnv1a(CLIENT_ID), 0x4e8e_8d17);
+        for &(role, mode, pool_share, _) in cases.iter() {
+            let failover = Failover::new(role, mode, unused_address(), MCLT).unwrap();
+            assert_eq!(failover.pool_share(), pool_share);
+            // nobody is connected, so the server takes all the clients
+            assert!(failover.serves(&request(), CLIENT_ID));
+        }
+
+        for &mode in [Mode::Standby, Mode::LoadBalance].iter() {
+            let mut runtime = Runtime::new().unwrap();
+            let mut primary = Failover::new(Role::Primary, mode, address, MCLT).unwrap();
+            let mut secondary = Failover::new(Role::Secondary, mode, address, MCLT).unwrap();
+            let mut events = vec![Vec::new(), Vec::new()];
+            drive(
+                &mut runtime,
+                &mut [Some(&mut primary), Some(&mut secondary)],
+                &mut events,
+                |_, events| events.iter().all(|events| connected(events)),
+            );
+
+            for &(role, _, _, serves) in cases.iter().filter(|case| case.1 == mode) {
+                let failover = if role == Role::Primary { &primary } else { &secondary };
+                assert_eq!(failover.serves(&request(), CLIENT_ID), serves);
+            }
+
+            // the messages to a server are answered by it
+            let mut unicast = request();
+            unicast.options.dhcp_server_id = Some(Ipv4Addr::new(192, 168, 0, 1));
+            assert!(primary.serves(&unicast, CLIENT_ID));
+            assert!(secondary.serves(&unicast, CLIENT_ID));
+        }
+    }
+
+    #[test]
+    fn exchanges_binding_updates() {
+        let address = unused_address();
+        let mut runtime = Runtime::new().unwrap();
+        let mut primary = Failover::new(Role::Primary, Mode::Standby, address, MCLT).unwrap();
+        let mut secondary = Failover::new(Role::Secondary, Mode::Standby, address, MCLT).unwrap();
+        let mut events = vec![Vec::new(), Vec::new()];
+
+        // the peer is unreachable, so the lease time is clamped
+        assert_eq!(primary.lease_time(CLIENT_ID, Some(LEASE_TIME)), Some(MCLT));
+        assert_eq!(primary.lease_time(CLIENT_ID, None), Some(MCLT));
+        assert_eq!(primary.lease_time(CLIENT_ID, Some(MCLT / 2)), Some(MCLT / 2));
+
+        drive(
+            &mut runtime,
+            &mut [Some(&mut primary), Some(&mut secondary)],
+            &mut events,
+            |_, events| events.iter().all(|events| connected(events)),
+        );
+        // the peer has not acknowledged any lease of the client yet
+        assert_eq!(primary.lease_time(CLIENT_ID, Some(LEASE_TIME)), Some(MCLT));
+
+        let mut lease = Lease::new(Ipv4Addr::new(192, 168, 0, 10), MCLT);
+        lease.assign(MCLT);
+        send(&mut runtime, &mut primary, &lease);
+        drive(
+            &mut runtime,
+            &mut [Some(&mut primary), Some(&mut secondary)],
+            &mut events,
+            |servers, _| match servers[0] {
+                Some(ref primary) => {
+                    primary.lease_time(CLIENT_ID, Some(LEASE_TIME)) == Some(LEASE_TIME)
+                }
+                None => false,
+            },
+        );
+        match events[1].last() {
+            Some(PeerEvent::Update(client_id, update)) => {
+                assert_eq!(client_id.as_slice(), CLIENT_ID);
+                assert_eq!(update.to_record(), lease.to_record());
+            }
+            _ => panic!("The secondary has not received the update"),
+        }
+        // the acknowledgements are not reported as events
+        assert_eq!(events[0].len(), 1);
+        assert_eq!(primary.lease_time(CLIENT_ID, None), None);
+        // the secondary has not sent anything, so it still clamps the lease time
+        assert_eq!(secondary.lease_time(CLIENT_ID, Some(LEASE_TIME)), Some(MCLT));
+        // other clients are not acknowledged
+        assert_eq!(primary.lease_time(&[1, 2, 3], Some(LEASE_TIME)), Some(MCLT));
+    }
+
+    #[test]
+    fn takes_over_when_partner_is_down() {
+        let address = unused_address();
+        let mut runtime = Runtime::new().unwrap();
+        let mut primary = Failover::new(Role::Primary, Mode::Standby, address, MCLT).unwrap();
+        let mut secondary = Failover::new(Role::Secondary, Mode::Standby, address, MCLT).unwrap();
+        let mut events = vec![Vec::new(), Vec::new()];
+        let lease = Lease::new(Ipv4Addr::new(192, 168, 0, 10), MCLT);
+
+        drive(
+            &mut runtime,
+            &mut [Some(&mut primary), Some(&mut secondary)],
+            &mut events,
+            |_, events| events.iter().all(|events| connected(events)),
+        );
+        send(&mut runtime, &mut secondary, &lease);
+        drive(
+            &mut runtime,
+            &mut [Some(&mut primary), Some(&mut secondary)],
+            &mut events,
+            |servers, _| match servers[1] {
+                Some(ref secondary) => secondary.lease_time(CLIENT_ID, None).is_none(),
+                None => false,
+            },
+        );
+        assert!(!secondary.serves(&request(), CLIENT_ID));
+
+        // the primary goes down, so the secondary serves all the clients with the MCLT
+        drop(primary);
+        drive(
+            &mut runtime,
+            &mut [None, Some(&mut secondary)],
+            &mut events,
+            |servers, _| match servers[1] {
+                Some(ref secondary) => secondary.peer.is_none(),
+                None => false,
+            },
+        );
+        assert!(secondary.serves(&request(), CLIENT_ID));
+        assert_eq!(secondary.lease_time(CLIENT_ID, Some(LEASE_TIME)), Some(MCLT));
+        // the updates are not queued while the peer is down
+        send(&mut runtime, &mut secondary, &lease);
+        assert!(secondary.outgoing.is_empty());
+
+        // the primary comes back, and the acknowledgements must be collected anew
+        let mut primary = Failover::new(Role::Primary, Mode::Standby, address, MCLT).unwrap();
+        let mut events = vec![Vec::new(), Vec::new()];
+        drive(
+            &mut runtime,
+            &mut [Some(&mut primary), Some(&mut secondary)],
+            &mut events,
+            |_, events| events.iter().all(|events| connected(events)),
+        );
+        assert!(!secondary.serves(&request(), CLIENT_ID));
+        assert_eq!(secondary.lease_time(CLIENT_ID, Some(LEASE_TIME)), Some(MCLT));
+
+        // the secondary goes down, so the primary keeps serving with the MCLT
+        drop(secondary);
+        drive(
+            &mut runtime,
+            &mut [Some(&mut primary), None],
+            &mut events,
+            |servers, _| match servers[0] {
+                Some(ref primary) => primary.peer.is_none(),
+                None => false,
+            },
+        );
+        assert!(primary.serves(&request(), CLIENT_ID));
+        assert_eq!(primary.lease_time(CLIENT_ID, Some(LEASE_TIME)), Some(MCLT));
+    }
+}
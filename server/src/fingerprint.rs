@@ -0,0 +1,92 @@
+//! The device classification by the DHCP fingerprints.
+//!
+//! The fingerprint database is a text file with a fingerprint per line, followed by
+//! the device type and optionally by the vendor class identifier prefix:
+//!
+//! ```text
+//! # the comments and the empty lines are skipped
+//! 1,3,6,15,44,47            printer
+//! 1,3,6,15,119,252          phone
+//! 1,3,6,12,15,28,42         camera   udhcp
+//! ```
+//!
+//! An entry with a vendor class prefix is preferred to the one without it.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use dhcp_protocol::Fingerprint;
+use tokio::io;
+
+/// A device type recognized by a parameter request list.
+#[derive(Debug, Clone)]
+struct Entry {
+    device_type: String,
+    /// The prefix of the vendor class identifier, any one if not set.
+    vendor_class: Option<Vec<u8>>,
+}
+
+/// The device types by the parameter request lists.
+#[derive(Debug, Clone)]
+pub struct FingerprintDatabase {
+    entries: HashMap<Vec<u8>, Vec<Entry>>,
+}
+
+impl FingerprintDatabase {
+    /// Reads and parses the database file.
+    ///
+    /// # Errors
+    /// `io::Error` if the file cannot be read or has an invalid line.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        Self::parse(&data).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Parses the database.
+    ///
+    /// # Errors
+    /// The description of the first invalid line.
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let mut entries: HashMap<Vec<u8>, Vec<Entry>> = HashMap::new();
+        for (index, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("Invalid fingerprint on the line {}", index + 1);
+            let mut fields = line.split_whitespace();
+            let parameter_list = fields
+                .next()
+                .ok_or_else(invalid)?
+                .split(',')
+                .map(|code| code.parse::<u8>())
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| invalid())?;
+            let device_type = fields.next().ok_or_else(invalid)?.to_owned();
+            let vendor_class = fields.next().map(|prefix| prefix.as_bytes().to_vec());
+            if fields.next().is_some() {
+                return Err(invalid());
+            }
+            entries.entry(parameter_list).or_insert_with(Vec::new).push(Entry {
+                device_type,
+                vendor_class,
+            });
+        }
+        for entries in entries.values_mut() {
+            // the stable sort keeps the file order otherwise
+            entries.sort_by_key(|entry| entry.vendor_class.is_none());
+        }
+        Ok(FingerprintDatabase { entries })
+    }
+
+    /// Returns the device type of the fingerprint if it is known.
+    pub fn device_type(&self, fingerprint: &Fingerprint) -> Option<&str> {
+        let vendor_class = fingerprint.vendor_class.as_ref();
+        self.entries
+            .get(&fingerprint.parameter_list)?
+            .iter()
+            .find(|entry| match entry.vendor_class {
+                Some(ref prefix) => vendor_class.map_or(false, |class| class.starts_with(prefix)),
+                None => true,
+            }).map(|entry| entry.device_type.as_str())
+    }
+}
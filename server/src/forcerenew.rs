@@ -0,0 +1,112 @@
+//! The RFC 6704 nonces authenticating the `DHCPFORCERENEW` messages to the clients
+//! of the subnets without the RFC 3118 keys.
+//!
+//! The nonces are kept in memory, so the clients bound before a restart cannot be
+//! forced to renew until they get a new nonce in a `DHCPACK`.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rand::{self, Rng};
+use tokio::io;
+
+use dhcp_protocol::{
+    auth::{self, Authentication},
+    Message,
+};
+
+/// The buffer size used to serialize a message before signing it.
+const SIZE_SIGNING_BUFFER: usize = 8192;
+
+/// The nonces given to the clients, keyed by the client ID.
+pub struct Nonces {
+    nonces: HashMap<Vec<u8>, [u8; auth::SIZE_RECONFIGURE_KEY]>,
+    /// The last replay detection value sent.
+    replay_sent: u64,
+}
+
+impl Nonces {
+    /// The replay detection counter starts from the current time,
+    /// so it keeps increasing across restarts.
+    pub fn new() -> Self {
+        Nonces {
+            nonces: HashMap::new(),
+            replay_sent: (Utc::now().timestamp() as u64) << 32,
+        }
+    }
+
+    /// Adds the client nonce to a `DHCPACK` if the client supports them (RFC 6704 §3.3.1).
+    ///
+    /// The response authenticated with a subnet key is left as is, since the key
+    /// authenticates the `DHCPFORCERENEW` messages as well.
+    pub fn apply(&mut self, client_id: &[u8], request: &Message, response: &mut Message) {
+        let is_capable = request
+            .options
+            .forcerenew_nonce_capable
+            .as_ref()
+            .map_or(false, |algorithms| algorithms.contains(&auth::ALGORITHM_HMAC_MD5));
+        if !is_capable || response.options.authentication.is_some() {
+            return;
+        }
+
+        let nonce = *self.nonces.entry(client_id.to_vec()).or_insert_with(|| {
+            let mut nonce = [0u8; auth::SIZE_RECONFIGURE_KEY];
+            rand::thread_rng().fill(&mut nonce);
+            nonce
+        });
+        let mut information = vec![auth::RECONFIGURE_KEY_VALUE];
+        information.extend_from_slice(&nonce);
+        response.options.authentication = Some(self.option(information));
+    }
+
+    /// Forgets the nonce of the client whose lease is gone.
+    pub fn remove(&mut self, client_id: &[u8]) {
+        self.nonces.remove(client_id);
+    }
+
+    /// Authenticates the `DHCPFORCERENEW` with the client nonce (RFC 6704 §3.3.2).
+    ///
+    /// Returns `false` if the client has not got a nonce.
+    ///
+    /// # Errors
+    /// `io::Error` if the message cannot be serialized.
+    pub fn sign(&mut self, client_id: &[u8], message: &mut Message) -> io::Result<bool> {
+        let nonce = match self.nonces.get(client_id) {
+            Some(nonce) => *nonce,
+            None => return Ok(false),
+        };
+        let mut information = vec![auth::RECONFIGURE_KEY_HMAC_MD5];
+        information.extend_from_slice(&[0u8; auth::SIZE_HMAC_MD5]);
+        message.options.authentication = Some(self.option(information));
+
+        let mut buffer = vec![0u8; SIZE_SIGNING_BUFFER];
+        let amount = message.to_bytes(&mut buffer, None)?;
+        let data = match auth::authenticated_data(&buffer[..amount]) {
+            Some(data) => data,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The authentication option has not been serialized",
+                ))
+            }
+        };
+        let mac = auth::hmac_md5(&nonce, &data);
+
+        if let Some(ref mut authentication) = message.options.authentication {
+            authentication.information[1..].copy_from_slice(&mac);
+        }
+        Ok(true)
+    }
+
+    fn option(&mut self, information: Vec<u8>) -> Authentication {
+        self.replay_sent = self.replay_sent.wrapping_add(1);
+        Authentication {
+            protocol: auth::PROTOCOL_RECONFIGURE_KEY,
+            algorithm: auth::ALGORITHM_HMAC_MD5,
+            replay_detection_method: auth::RDM_MONOTONIC,
+            replay_detection: self.replay_sent,
+            information,
+            authenticated_data: None,
+        }
+    }
+}
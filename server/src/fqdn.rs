@@ -0,0 +1,124 @@
+//! The client FQDN option negotiation (RFC 4702) deciding which DNS records
+//! the server updates for a client.
+
+use std::net::Ipv4Addr;
+
+use dhcp_protocol::{ClientFqdn, Message, FQDN_FLAG_E, FQDN_FLAG_N, FQDN_FLAG_O, FQDN_FLAG_S};
+
+/// The deprecated RCODE fields value sent by the server (RFC 4702 §2.2).
+const RCODE_SERVER: u8 = 255;
+
+/// The DNS records the server updates for a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsUpdate {
+    /// The A and PTR records.
+    Both,
+    /// Only the PTR record, since the client updates its A record itself.
+    Reverse,
+}
+
+/// How the server answers the client FQDN option and names the clients.
+#[derive(Debug, Clone, Default)]
+pub struct FqdnPolicy {
+    /// Updates the A records of the clients asking to update them themselves (RFC 4702 §3.3).
+    pub override_client_updates: bool,
+    /// Updates the records of the clients asking for no updates at all.
+    pub override_no_updates: bool,
+    /// The prefix of the host names synthesized from the addresses of the clients
+    /// sending none, like `dhcp` for `dhcp-192-168-0-10`.
+    pub synthesized_prefix: Option<String>,
+}
+
+impl FqdnPolicy {
+    /// Returns the host name the client is registered in DNS with and the records updated,
+    /// or `None` if the server performs no updates.
+    ///
+    /// The reserved host name takes precedence over the client FQDN and host name options.
+    pub fn registration(
+        &self,
+        request: &Message,
+        reserved: Option<&str>,
+        address: &Ipv4Addr,
+    ) -> Option<(String, DnsUpdate)> {
+        let update = self.update(request.options.client_fqdn.as_ref())?;
+        let hostname = self.hostname(request, reserved, address)?;
+        Some((hostname, update))
+    }
+
+    /// Creates the option answering the one of the client, if it has been sent (RFC 4702 §3.2).
+    pub fn reply(
+        &self,
+        request: &Message,
+        reserved: Option<&str>,
+        address: &Ipv4Addr,
+    ) -> Option<ClientFqdn> {
+        let fqdn = request.options.client_fqdn.as_ref()?;
+        let hostname = self.hostname(request, reserved, address);
+        let mut flags = fqdn.flags & FQDN_FLAG_E;
+        flags |= match (self.update(Some(fqdn)), hostname.is_some()) {
+            (Some(DnsUpdate::Both), true) if fqdn.has_flag(FQDN_FLAG_S) => FQDN_FLAG_S,
+            (Some(DnsUpdate::Both), true) => FQDN_FLAG_S | FQDN_FLAG_O,
+            (Some(DnsUpdate::Reverse), true) => 0,
+            _ => FQDN_FLAG_N,
+        };
+        Some(ClientFqdn {
+            flags,
+            rcode1: RCODE_SERVER,
+            rcode2: RCODE_SERVER,
+            domain_name: hostname.unwrap_or_else(|| fqdn.domain_name.to_owned()),
+        })
+    }
+
+    /// The records updated according to the client flags and the policy.
+    fn update(&self, fqdn: Option<&ClientFqdn>) -> Option<DnsUpdate> {
+        let fqdn = match fqdn {
+            Some(fqdn) => fqdn,
+            None => return Some(DnsUpdate::Both),
+        };
+        if fqdn.has_flag(FQDN_FLAG_N) {
+            // the client has asked the server not to perform any DNS updates (RFC 4702 §2.1)
+            return if self.override_no_updates {
+                Some(DnsUpdate::Both)
+            } else {
+                None
+            };
+        }
+        if fqdn.has_flag(FQDN_FLAG_S) || self.override_client_updates {
+            Some(DnsUpdate::Both)
+        } else {
+            Some(DnsUpdate::Reverse)
+        }
+    }
+
+    fn hostname(
+        &self,
+        request: &Message,
+        reserved: Option<&str>,
+        address: &Ipv4Addr,
+    ) -> Option<String> {
+        reserved
+            .map(str::to_owned)
+            .or_else(|| {
+                request
+                    .options
+                    .client_fqdn
+                    .as_ref()
+                    .map(|fqdn| fqdn.domain_name.to_owned())
+                    .filter(|name| !name.is_empty())
+            }).or_else(|| {
+                request
+                    .options
+                    .hostname
+                    .to_owned()
+                    .filter(|name| !name.is_empty())
+            }).or_else(|| {
+                self.synthesized_prefix.as_ref().map(|prefix| {
+                    let octets = address.octets();
+                    format!(
+                        "{}-{}-{}-{}-{}",
+                        prefix, octets[0], octets[1], octets[2], octets[3]
+                    )
+                })
+            })
+    }
+}
@@ -0,0 +1,43 @@
+//! The Ethernet frames of the hardware unicast responses.
+//!
+//! Used to reach the clients which have no address yet and do not answer ARP requests,
+//! so the link-layer destination is taken from `chaddr` instead.
+
+use std::{io, net::Ipv4Addr};
+
+use etherparse::{PacketBuilder, WriteError};
+use eui48::{MacAddress, EUI48LEN};
+
+use dhcp_protocol::{DHCP_PORT_CLIENT, DHCP_PORT_SERVER};
+
+const DEFAULT_IP_TTL: u8 = 64;
+
+/// Constructs the Ethernet, IPv4 and UDP headers around the DHCP payload.
+///
+/// # Errors
+/// `io::Error` if the payload is too big for a packet.
+pub fn ethernet_packet(
+    src_mac: MacAddress,
+    dst_mac: MacAddress,
+    src_ip: Ipv4Addr,
+    dst_ip: Ipv4Addr,
+    payload: &[u8],
+) -> io::Result<Vec<u8>> {
+    let mut source = [0u8; EUI48LEN];
+    source.copy_from_slice(src_mac.as_bytes());
+    let mut destination = [0u8; EUI48LEN];
+    destination.copy_from_slice(dst_mac.as_bytes());
+
+    let builder = PacketBuilder::ethernet2(source, destination)
+        .ipv4(src_ip.octets(), dst_ip.octets(), DEFAULT_IP_TTL)
+        .udp(DHCP_PORT_SERVER, DHCP_PORT_CLIENT);
+
+    let mut result = Vec::<u8>::with_capacity(builder.size(payload.len()));
+    match builder.write(&mut result, payload) {
+        Ok(_) => Ok(result),
+        Err(WriteError::IoError(error)) => Err(error),
+        Err(WriteError::ValueError(error)) => {
+            Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", error)))
+        }
+    }
+}
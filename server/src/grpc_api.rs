@@ -0,0 +1,353 @@
+//! The gRPC admin API for the orchestration platforms, served with the `grpc-api` feature.
+//!
+//! The `dhcp.Admin` service of `proto/dhcp_admin.proto` executes the admin API commands:
+//! listing and deleting the leases, adding the reservations and reloading the configuration.
+//! `WatchLeases` streams the lease events of the event log from the moment of the call.
+//!
+//! The HTTP/2 listener runs on its own thread and passes the calls to the server,
+//! which executes them like the HTTP API requests.
+//!
+//! If a token is set, the calls must have the `authorization: Bearer <token>` metadata.
+
+mod dhcp_admin {
+    include!(concat!(env!("OUT_DIR"), "/dhcp_admin.rs"));
+}
+mod dhcp_admin_grpc {
+    include!(concat!(env!("OUT_DIR"), "/dhcp_admin_grpc.rs"));
+}
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use futures::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use grpc::{
+    self, GrpcMessageError, RequestOptions, ServerBuilder, SingleResponse, StreamingResponse,
+};
+use serde_json::{self, Map, Value};
+use tokio::{io, prelude::*};
+
+use self::{
+    dhcp_admin::{
+        Lease, LeaseAddress, LeaseEvent as EventMessage, LeaseFilter, LeaseList, ReloadRequest,
+        Reply, ReservationRequest, WatchRequest,
+    },
+    dhcp_admin_grpc::{Admin, AdminServer},
+};
+use admin::{Command, LeaseFilter as CommandFilter};
+use config::parse_reservation;
+use event_log::Record;
+use http::Response;
+use observer::LeaseEvent;
+
+/// The gRPC status codes the HTTP API ones are mapped to.
+const STATUS_INVALID_ARGUMENT: i32 = 3;
+const STATUS_NOT_FOUND: i32 = 5;
+const STATUS_UNIMPLEMENTED: i32 = 12;
+const STATUS_INTERNAL: i32 = 13;
+const STATUS_UNAVAILABLE: i32 = 14;
+const STATUS_UNAUTHENTICATED: i32 = 16;
+
+/// A command with the sender of its response.
+type Call = (Command, oneshot::Sender<Response>);
+/// The streams of the `WatchLeases` calls.
+type Watchers = Arc<Mutex<Vec<UnboundedSender<EventMessage>>>>;
+
+/// The gRPC listener and the calls passed to the server.
+pub struct GrpcApi {
+    /// Stops the listener thread when dropped.
+    _server: grpc::Server,
+    calls: UnboundedReceiver<Call>,
+    /// The calls being executed by their IDs.
+    pending: HashMap<usize, oneshot::Sender<Response>>,
+    next_id: usize,
+    watchers: Watchers,
+}
+
+impl GrpcApi {
+    /// Starts listening for the gRPC clients.
+    ///
+    /// # Errors
+    /// `io::Error` if the address cannot be bound.
+    pub fn new(address: SocketAddr, token: Option<String>) -> io::Result<Self> {
+        let (sender, calls) = mpsc::unbounded();
+        let watchers = Arc::new(Mutex::new(Vec::new()));
+        let service = AdminService {
+            calls: Mutex::new(sender),
+            watchers: watchers.clone(),
+            token,
+        };
+
+        let mut builder = ServerBuilder::new_plain();
+        builder
+            .http
+            .set_addr(address)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+        builder.add_service(AdminServer::new_service_def(service));
+        let server = builder
+            .build()
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+        Ok(GrpcApi {
+            _server: server,
+            calls,
+            pending: HashMap::new(),
+            next_id: 0,
+            watchers,
+        })
+    }
+
+    /// Returns the next command with the ID of its call.
+    pub fn poll_command(&mut self) -> Option<(usize, Command)> {
+        match self.calls.poll() {
+            Ok(Async::Ready(Some((command, sender)))) => {
+                let id = self.next_id;
+                self.next_id = self.next_id.wrapping_add(1);
+                self.pending.insert(id, sender);
+                Some((id, command))
+            }
+            Ok(Async::Ready(None)) | Ok(Async::NotReady) | Err(()) => None,
+        }
+    }
+
+    /// Completes the call, which may have been cancelled by the client.
+    pub fn respond(&mut self, id: usize, response: Response) {
+        if let Some(sender) = self.pending.remove(&id) {
+            let _ = sender.send(response);
+        }
+    }
+
+    /// Streams the event to the watching clients, forgetting the disconnected ones.
+    pub fn watch(&self, event: &LeaseEvent) {
+        let value = match serde_json::to_value(&Record::new(event)) {
+            Ok(value) => value,
+            Err(error) => {
+                warn!("gRPC event serialization error: {}", error);
+                return;
+            }
+        };
+        let mut message = EventMessage::new();
+        message.set_timestamp(string(&value, "timestamp"));
+        message.set_event(string(&value, "event"));
+        message.set_client_id(string(&value, "client_id"));
+        message.set_address(string(&value, "address"));
+        message.set_hostname(string(&value, "hostname"));
+        message.set_lease_time(number(&value, "lease_time"));
+        message.set_reason(string(&value, "reason"));
+        if let Ok(mut watchers) = self.watchers.lock() {
+            watchers.retain(|watcher| watcher.unbounded_send(message.clone()).is_ok());
+        }
+    }
+}
+
+/// The service handlers running on the listener thread.
+struct AdminService {
+    calls: Mutex<UnboundedSender<Call>>,
+    watchers: Watchers,
+    /// The bearer token the calls must have, if any.
+    token: Option<String>,
+}
+
+impl AdminService {
+    fn authorize(&self, method: &str, options: &RequestOptions) -> Result<(), grpc::Error> {
+        info!(target: "audit", "gRPC API call {}", method);
+        let is_authorized = self.token.as_ref().map_or(true, |token| {
+            options.metadata.get("authorization") == Some(format!("Bearer {}", token).as_bytes())
+        });
+        if is_authorized {
+            Ok(())
+        } else {
+            warn!("The gRPC client is not authorized");
+            Err(status_error(STATUS_UNAUTHENTICATED, "Unauthorized"))
+        }
+    }
+
+    /// Passes the command to the server and converts the JSON body of its response.
+    fn call<T, F>(
+        &self,
+        method: &str,
+        options: &RequestOptions,
+        command: Result<Command, Response>,
+        convert: F,
+    ) -> SingleResponse<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(Value) -> T + Send + 'static,
+    {
+        if let Err(error) = self.authorize(method, options) {
+            return SingleResponse::err(error);
+        }
+        let command = match command {
+            Ok(command) => command,
+            Err(response) => return SingleResponse::err(response_error(&response)),
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        let is_sent = self
+            .calls
+            .lock()
+            .ok()
+            .map_or(false, |calls| calls.unbounded_send((command, sender)).is_ok());
+        if !is_sent {
+            return SingleResponse::err(status_error(STATUS_UNAVAILABLE, "The server has stopped"));
+        }
+        let future = receiver
+            .map_err(|_| status_error(STATUS_UNAVAILABLE, "The server has stopped"))
+            .and_then(|response| {
+                if response.status() >= 300 {
+                    return Err(response_error(&response));
+                }
+                serde_json::from_str(response.body())
+                    .map(convert)
+                    .map_err(|error| status_error(STATUS_INTERNAL, &error.to_string()))
+            });
+        SingleResponse::no_metadata(future)
+    }
+}
+
+impl Admin for AdminService {
+    fn list_leases(
+        &self,
+        options: RequestOptions,
+        filter: LeaseFilter,
+    ) -> SingleResponse<LeaseList> {
+        let query: Vec<(String, String)> = [
+            ("address", filter.get_address()),
+            ("client_id", filter.get_client_id()),
+            ("hardware_address", filter.get_hardware_address()),
+            ("state", filter.get_state()),
+            ("device_type", filter.get_device_type()),
+        ]
+            .iter()
+            .filter(|&&(_, value)| !value.is_empty())
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        let command = CommandFilter::from_query(&query)
+            .map(Command::Leases)
+            .map_err(|error| Response::error(400, &error));
+        self.call("ListLeases", &options, command, |value| {
+            let mut list = LeaseList::new();
+            for record in value.as_array().into_iter().flat_map(|records| records.iter()) {
+                let mut lease = Lease::new();
+                lease.set_client_id(string(record, "client_id"));
+                lease.set_hardware_address(string(record, "hardware_address"));
+                lease.set_address(string(record, "address"));
+                lease.set_state(string(record, "state"));
+                lease.set_lease_time(number(record, "lease_time"));
+                lease.set_expires_at(number(record, "expires_at"));
+                lease.set_device_type(string(record, "device_type"));
+                list.mut_leases().push(lease);
+            }
+            list
+        })
+    }
+
+    fn delete_lease(&self, options: RequestOptions, lease: LeaseAddress) -> SingleResponse<Reply> {
+        let command = lease
+            .get_address()
+            .parse()
+            .map(Command::DeleteLease)
+            .map_err(|_| {
+                Response::error(400, &format!("Invalid address {}", lease.get_address()))
+            });
+        self.call("DeleteLease", &options, command, reply)
+    }
+
+    fn add_reservation(
+        &self,
+        options: RequestOptions,
+        request: ReservationRequest,
+    ) -> SingleResponse<Reply> {
+        let mut fields = Map::new();
+        for &(name, value) in [
+            ("hardware_address", request.get_hardware_address()),
+            ("client_id", request.get_client_id()),
+            ("circuit_id", request.get_circuit_id()),
+            ("remote_id", request.get_remote_id()),
+            ("address", request.get_address()),
+            ("hostname", request.get_hostname()),
+            ("boot_filename", request.get_boot_filename()),
+        ]
+            .iter()
+        {
+            if !value.is_empty() {
+                fields.insert(name.to_owned(), Value::String(value.to_owned()));
+            }
+        }
+        let command = serde_json::to_vec(&Value::Object(fields))
+            .map_err(|error| error.to_string())
+            .and_then(|data| parse_reservation(&data).map_err(|error| error.to_string()))
+            .map(|(key, reservation)| Command::AddReservation(key, reservation))
+            .map_err(|error| Response::error(400, &error));
+        self.call("AddReservation", &options, command, reply)
+    }
+
+    fn reload(&self, options: RequestOptions, _: ReloadRequest) -> SingleResponse<Reply> {
+        self.call("Reload", &options, Ok(Command::Reload), reply)
+    }
+
+    fn watch_leases(
+        &self,
+        options: RequestOptions,
+        _: WatchRequest,
+    ) -> StreamingResponse<EventMessage> {
+        if let Err(error) = self.authorize("WatchLeases", &options) {
+            return StreamingResponse::err(error);
+        }
+        let (sender, receiver) = mpsc::unbounded();
+        match self.watchers.lock() {
+            Ok(mut watchers) => watchers.push(sender),
+            Err(_) => return StreamingResponse::err(status_error(STATUS_INTERNAL, "Poisoned")),
+        }
+        StreamingResponse::no_metadata(
+            receiver.map_err(|_| status_error(STATUS_UNAVAILABLE, "The server has stopped")),
+        )
+    }
+}
+
+/// The reply of the commands answering with a message.
+fn reply(value: Value) -> Reply {
+    let mut reply = Reply::new();
+    reply.set_message(string(&value, "message"));
+    reply
+}
+
+/// The error of the HTTP API response.
+fn response_error(response: &Response) -> grpc::Error {
+    let status = match response.status() {
+        400 => STATUS_INVALID_ARGUMENT,
+        401 => STATUS_UNAUTHENTICATED,
+        404 => STATUS_NOT_FOUND,
+        501 => STATUS_UNIMPLEMENTED,
+        _ => STATUS_INTERNAL,
+    };
+    let message = serde_json::from_str::<Value>(response.body())
+        .ok()
+        .map_or(response.body().to_owned(), |value| string(&value, "error"));
+    status_error(status, &message)
+}
+
+fn status_error(status: i32, message: &str) -> grpc::Error {
+    grpc::Error::GrpcMessage(GrpcMessageError {
+        grpc_status: status,
+        grpc_message: message.to_owned(),
+    })
+}
+
+/// The string field of the JSON object, empty if it is absent.
+fn string(value: &Value, name: &str) -> String {
+    value
+        .get(name)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// The number field of the JSON object, zero if it is absent.
+fn number(value: &Value, name: &str) -> u32 {
+    value.get(name).and_then(Value::as_u64).unwrap_or(0) as u32
+}
@@ -0,0 +1,163 @@
+//! The asynchronous lease lifecycle hooks.
+//!
+//! Unlike the observers, the hooks may do slow work like calling other systems,
+//! since their futures are spawned on the runtime instead of blocking the server.
+
+use std::{
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use futures::future;
+use tokio::{io, prelude::*, timer::Deadline};
+use tokio_process::CommandExt;
+
+use lease::INFINITE_LEASE_TIME;
+use observer::LeaseEvent;
+use server::client_hardware_address;
+use storage_file::hex_encode;
+
+/// The default time limit of the hook commands in seconds.
+pub const DEFAULT_HOOK_TIMEOUT: u64 = 10;
+
+/// The background work of a hook.
+pub type HookFuture = Box<Future<Item = (), Error = io::Error> + Send>;
+
+/// Is called on the lease lifecycle events, e.g. to update firewall rules,
+/// inventory systems or captive portals.
+///
+/// The futures are spawned on the runtime and their errors are logged.
+pub trait Hook: Send + Sync {
+    /// Called after the change has been stored, if any.
+    fn on_lease_event(&self, event: &LeaseEvent) -> HookFuture;
+}
+
+/// Runs an external command on the lease commits, renewals, releases and expirations.
+///
+/// The lease is described by the environment variables:
+///
+/// * `DHCP_EVENT` is `commit`, `renew`, `release` or `expire`.
+/// * `DHCP_CLIENT_ID` is hexadecimal.
+/// * `DHCP_HARDWARE_ADDRESS` is set if the client ID is made of one.
+/// * `DHCP_ADDRESS` is the leased address.
+/// * `DHCP_HOSTNAME` is set if the client has sent one.
+/// * `DHCP_LEASE_TIME` and `DHCP_EXPIRES_AT` are set on the commits and renewals,
+/// the latter is the Unix time or zero for the infinite leases.
+///
+/// The command is killed if it does not exit in time.
+pub struct CommandHook {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandHook {
+    pub fn new(program: PathBuf, args: Vec<String>, timeout: Duration) -> Self {
+        CommandHook {
+            program,
+            args,
+            timeout,
+        }
+    }
+
+    /// Runs the command with the environment variables describing a lease.
+    ///
+    /// Lets the servers of other protocol versions describe their leases
+    /// with the same variables.
+    pub fn run(&self, variables: Vec<(&'static str, String)>) -> HookFuture {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .envs(variables);
+        let child = match command.spawn_async() {
+            Ok(child) => child,
+            Err(error) => return Box::new(future::err(error)),
+        };
+
+        let program = self.program.display().to_string();
+        let future = Deadline::new(child, Instant::now() + self.timeout)
+            .map_err(|error| {
+                if error.is_elapsed() {
+                    return io::Error::new(io::ErrorKind::TimedOut, "Hook command timeout");
+                }
+                match error.into_inner() {
+                    Some(error) => error,
+                    None => io::Error::new(io::ErrorKind::Other, "Hook command timer error"),
+                }
+            }).and_then(move |status| {
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("{} has exited with {}", program, status),
+                    ))
+                }
+            });
+        Box::new(future)
+    }
+
+    /// Returns `None` for the events the command is not run on.
+    fn variables(event: &LeaseEvent) -> Option<Vec<(&'static str, String)>> {
+        let (name, client_id, address, lease) = match *event {
+            LeaseEvent::Committed {
+                ref client_id,
+                address,
+                ref hostname,
+                lease_time,
+                ..
+            } => ("commit", client_id, address, Some((hostname, lease_time))),
+            LeaseEvent::Renewed {
+                ref client_id,
+                address,
+                ref hostname,
+                lease_time,
+                ..
+            } => ("renew", client_id, address, Some((hostname, lease_time))),
+            LeaseEvent::Released {
+                ref client_id,
+                address,
+            } => ("release", client_id, address, None),
+            LeaseEvent::Expired {
+                ref client_id,
+                address,
+            } => ("expire", client_id, address, None),
+            _ => return None,
+        };
+        let mut variables = vec![
+            ("DHCP_EVENT", name.to_owned()),
+            ("DHCP_CLIENT_ID", hex_encode(client_id)),
+            ("DHCP_ADDRESS", address.to_string()),
+        ];
+        if let Some(hardware_address) = client_hardware_address(client_id) {
+            variables.push(("DHCP_HARDWARE_ADDRESS", hardware_address.to_hex_string()));
+        }
+        if let Some((hostname, lease_time)) = lease {
+            if let Some(ref hostname) = *hostname {
+                variables.push(("DHCP_HOSTNAME", hostname.to_owned()));
+            }
+            let expires_at = if lease_time == INFINITE_LEASE_TIME {
+                0
+            } else {
+                (Utc::now().timestamp() as u32).saturating_add(lease_time)
+            };
+            variables.push(("DHCP_LEASE_TIME", lease_time.to_string()));
+            variables.push(("DHCP_EXPIRES_AT", expires_at.to_string()));
+        }
+        Some(variables)
+    }
+}
+
+impl Hook for CommandHook {
+    fn on_lease_event(&self, event: &LeaseEvent) -> HookFuture {
+        match Self::variables(event) {
+            Some(variables) => self.run(variables),
+            None => Box::new(future::ok(())),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! A minimal HTTP/1.1 listener serving the admin API and the metrics.
+//!
+//! The requests are answered by the server in the order of arrival
+//! and the connections are kept alive until the clients close them.
+
+use std::{collections::VecDeque, net::SocketAddr, str};
+
+use bytes::{BufMut, BytesMut};
+use serde::Serialize;
+use serde_json;
+use tokio::{
+    codec::{Decoder, Encoder, Framed},
+    io,
+    net::{tcp::Incoming, TcpListener, TcpStream},
+    prelude::*,
+};
+
+/// The maximal size of the request line and the headers in bytes.
+const SIZE_HEAD_MAX: usize = 8192;
+/// The maximal size of the request body in bytes.
+const SIZE_BODY_MAX: usize = 65536;
+
+const CONTENT_TYPE_JSON: &str = "application/json";
+/// The Prometheus text exposition format.
+const CONTENT_TYPE_TEXT: &str = "text/plain; version=0.0.4";
+
+/// An HTTP request.
+pub struct Request {
+    pub method: String,
+    /// Percent-decoded.
+    pub path: String,
+    /// Percent-decoded in the order of appearance.
+    pub query: Vec<(String, String)>,
+    pub authorization: Option<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct MessageBody<'a> {
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+/// An HTTP response.
+pub struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    /// Serializes the value as the response body.
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => Response {
+                status,
+                content_type: CONTENT_TYPE_JSON,
+                body,
+            },
+            Err(error) => Response::error(500, &error.to_string()),
+        }
+    }
+
+    /// Reports the successful change.
+    pub fn message(status: u16, message: &str) -> Self {
+        Response::json(status, &MessageBody { message })
+    }
+
+    /// Reports the error.
+    pub fn error(status: u16, error: &str) -> Self {
+        Response {
+            status,
+            content_type: CONTENT_TYPE_JSON,
+            body: serde_json::to_string(&ErrorBody { error }).unwrap_or_default(),
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// Sends the plain text body.
+    pub fn text(status: u16, body: String) -> Self {
+        Response {
+            status,
+            content_type: CONTENT_TYPE_TEXT,
+            body,
+        }
+    }
+}
+
+/// Frames the HTTP/1.1 requests and responses.
+struct HttpCodec;
+
+impl Decoder for HttpCodec {
+    type Item = Request;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Request>> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let head_len = match src.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(position) => position + 4,
+            None if src.len() > SIZE_HEAD_MAX => {
+                return Err(invalid("The request head is too long"))
+            }
+            None => return Ok(None),
+        };
+        let (mut request, content_length) =
+            parse_head(&src[..head_len]).ok_or_else(|| invalid("Malformed request head"))?;
+        if content_length > SIZE_BODY_MAX {
+            return Err(invalid("The request body is too long"));
+        }
+        if src.len() < head_len + content_length {
+            return Ok(None);
+        }
+        src.advance(head_len);
+        request.body = src.split_to(content_length).to_vec();
+        Ok(Some(request))
+    }
+}
+
+impl Encoder for HttpCodec {
+    type Item = Response;
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Response, dst: &mut BytesMut) -> io::Result<()> {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            item.status,
+            reason_phrase(item.status),
+            item.content_type,
+            item.body.len(),
+        );
+        dst.reserve(head.len() + item.body.len());
+        dst.put_slice(head.as_bytes());
+        dst.put_slice(item.body.as_bytes());
+        Ok(())
+    }
+}
+
+/// A connection of an HTTP client.
+struct Connection {
+    id: usize,
+    peer: SocketAddr,
+    stream: Framed<TcpStream, HttpCodec>,
+    /// The responses waiting for the connection to accept them.
+    outgoing: VecDeque<Response>,
+}
+
+/// The listener of the HTTP connections.
+pub struct HttpListener {
+    listener: Incoming,
+    connections: Vec<Connection>,
+    next_id: usize,
+}
+
+impl HttpListener {
+    /// Starts listening for the clients.
+    ///
+    /// # Errors
+    /// `io::Error` if the address cannot be bound.
+    pub fn new(address: SocketAddr) -> io::Result<Self> {
+        Ok(HttpListener {
+            listener: TcpListener::bind(&address)?.incoming(),
+            connections: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Returns the next request with the ID and the address of its connection,
+    /// accepting the new connections.
+    pub fn poll_request(&mut self) -> Option<(usize, SocketAddr, Request)> {
+        self.poll_accept();
+        self.flush();
+
+        let mut index = 0;
+        while index < self.connections.len() {
+            let result = self.connections[index].stream.poll();
+            match result {
+                Ok(Async::Ready(Some(request))) => {
+                    let connection = &self.connections[index];
+                    return Some((connection.id, connection.peer, request));
+                }
+                Ok(Async::Ready(None)) => {
+                    trace!("The HTTP client {} has disconnected", self.connections[index].peer);
+                    self.connections.remove(index);
+                }
+                Ok(Async::NotReady) => index += 1,
+                Err(error) => {
+                    warn!(
+                        "HTTP receiving error from {}: {}",
+                        self.connections[index].peer, error
+                    );
+                    self.connections.remove(index);
+                }
+            }
+        }
+        None
+    }
+
+    /// Queues the response to a request received on the connection.
+    ///
+    /// The response is dropped if the client has already disconnected.
+    pub fn respond(&mut self, id: usize, response: Response) {
+        if let Some(connection) = self
+            .connections
+            .iter_mut()
+            .find(|connection| connection.id == id)
+        {
+            connection.outgoing.push_back(response);
+        }
+        self.flush();
+    }
+
+    fn poll_accept(&mut self) {
+        loop {
+            let stream = match self.listener.poll() {
+                Ok(Async::Ready(Some(stream))) => stream,
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => return,
+                Err(error) => {
+                    warn!("HTTP accepting error: {}", error);
+                    return;
+                }
+            };
+            let peer = match stream.peer_addr() {
+                Ok(peer) => peer,
+                Err(error) => {
+                    warn!("HTTP accepting error: {}", error);
+                    continue;
+                }
+            };
+            trace!("The HTTP client {} has connected", peer);
+            self.connections.push(Connection {
+                id: self.next_id,
+                peer,
+                stream: Framed::new(stream, HttpCodec),
+                outgoing: VecDeque::new(),
+            });
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+    }
+
+    /// Moves the queued responses to the connections until they are full.
+    fn flush(&mut self) {
+        let mut index = 0;
+        while index < self.connections.len() {
+            let result = {
+                let connection = &mut self.connections[index];
+                let stream = &mut connection.stream;
+                let outgoing = &mut connection.outgoing;
+                let mut result = Ok(());
+                while let Some(response) = outgoing.pop_front() {
+                    match stream.start_send(response) {
+                        Ok(AsyncSink::Ready) => {}
+                        Ok(AsyncSink::NotReady(response)) => {
+                            outgoing.push_front(response);
+                            break;
+                        }
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
+                }
+                result.and_then(|_| stream.poll_complete().map(|_| ()))
+            };
+            match result {
+                Ok(()) => index += 1,
+                Err(error) => {
+                    warn!(
+                        "HTTP sending error to {}: {}",
+                        self.connections[index].peer, error
+                    );
+                    self.connections.remove(index);
+                }
+            }
+        }
+    }
+}
+
+/// Parses the request line and the headers, returning the request without the body
+/// and the body length.
+fn parse_head(head: &[u8]) -> Option<(Request, usize)> {
+    let head = str::from_utf8(head).ok()?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_owned();
+    let target = request_line.next()?;
+    let (path, query) = match target.find('?') {
+        Some(position) => (&target[..position], &target[position + 1..]),
+        None => (target, ""),
+    };
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            let key = percent_decode(pair.next().unwrap_or_default())?;
+            let value = percent_decode(pair.next().unwrap_or_default())?;
+            Some((key, value))
+        }).collect::<Option<Vec<(String, String)>>>()?;
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for line in lines.filter(|line| !line.is_empty()) {
+        let mut header = line.splitn(2, ':');
+        let name = header.next()?.trim().to_lowercase();
+        let value = header.next()?.trim();
+        match name.as_str() {
+            "content-length" => content_length = value.parse().ok()?,
+            "authorization" => authorization = Some(value.to_owned()),
+            _ => {}
+        }
+    }
+
+    let request = Request {
+        method,
+        path: percent_decode(path)?,
+        query,
+        authorization,
+        body: Vec::new(),
+    };
+    Some((request, content_length))
+}
+
+fn percent_decode(data: &str) -> Option<String> {
+    let bytes = data.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                decoded.push(u8::from_str_radix(data.get(i + 1..i + 3)?, 16).ok()?);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        _ => "Unknown",
+    }
+}
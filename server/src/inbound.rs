@@ -0,0 +1,85 @@
+//! The bounded queue of the received messages shedding the load of the boot storms.
+//!
+//! The socket is drained into the queue before every message is processed, and the messages
+//! of the clients having addresses, like `DHCPREQUEST`, are processed before `DHCPDISCOVER`,
+//! so the existing leases are renewed while the new clients wait.
+//!
+//! Once the queue is full, the received `DHCPDISCOVER` messages are dropped, and the other
+//! ones replace the latest queued `DHCPDISCOVER`, or are dropped if there is none.
+
+use std::{
+    collections::VecDeque,
+    net::{Ipv4Addr, SocketAddr},
+};
+
+use dhcp_protocol::{Message, MessageType, OFFSET_SERVER_NAME};
+
+/// The received messages waiting to be processed by default.
+pub const DEFAULT_INBOUND_QUEUE_SIZE: usize = 1024;
+
+/// A message with its source, the index of the interface it was received on
+/// and the address it was sent to.
+pub struct Received {
+    pub source: SocketAddr,
+    pub message: Message,
+    pub ingress_interface: Option<u32>,
+    pub destination_address: Option<Ipv4Addr>,
+    /// The fixed header of the message the replies are patched into.
+    pub header: [u8; OFFSET_SERVER_NAME],
+}
+
+/// The received messages in the processing order.
+pub struct InboundQueue {
+    size_max: usize,
+    /// The `DHCPDISCOVER` messages processed when there are no other ones.
+    discovers: VecDeque<Received>,
+    others: VecDeque<Received>,
+}
+
+impl InboundQueue {
+    /// Holds at most `size_max` messages, one at least.
+    pub fn new(size_max: usize) -> Self {
+        InboundQueue {
+            size_max: size_max.max(1),
+            discovers: VecDeque::new(),
+            others: VecDeque::new(),
+        }
+    }
+
+    /// Queues the message, returning the one dropped if the queue is full.
+    pub fn push(&mut self, received: Received) -> Option<Received> {
+        let is_discover = match received.message.options.dhcp_message_type {
+            Some(MessageType::DhcpDiscover) => true,
+            _ => false,
+        };
+        if self.len() < self.size_max {
+            if is_discover {
+                self.discovers.push_back(received);
+            } else {
+                self.others.push_back(received);
+            }
+            return None;
+        }
+        if is_discover {
+            return Some(received);
+        }
+        match self.discovers.pop_back() {
+            Some(dropped) => {
+                self.others.push_back(received);
+                Some(dropped)
+            }
+            None => Some(received),
+        }
+    }
+
+    /// Takes the next message to process.
+    pub fn pop(&mut self) -> Option<Received> {
+        self.others
+            .pop_front()
+            .or_else(|| self.discovers.pop_front())
+    }
+
+    fn len(&self) -> usize {
+        self.discovers.len() + self.others.len()
+    }
+}
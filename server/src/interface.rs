@@ -0,0 +1,16 @@
+//! The network interfaces served at once.
+
+use std::net::Ipv4Addr;
+
+/// A network interface with the subnet of its directly connected clients.
+///
+/// The interface the request has come from is learned with `IP_PKTINFO` (Linux only).
+#[derive(Debug, Clone)]
+pub struct Interface {
+    /// The system name, like `eth1`.
+    pub name: String,
+    /// The server address on the interface, sent to the clients as the server identifier.
+    pub address: Ipv4Addr,
+    /// The network address of the subnet the directly connected clients belong to.
+    pub subnet: Ipv4Addr,
+}
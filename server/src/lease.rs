@@ -1,22 +1,26 @@
 //! Address lease implementation.
 
-use std::net::Ipv4Addr;
+use std::{cmp, net::Ipv4Addr};
 
 use chrono::prelude::*;
 
 /// The state of the `Lease`.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 enum State {
     Offered,
     Assigned,
     Released,
+    Expired,
 }
 
 /// A client has only `OFFER_TIMEOUT` seconds to accept a `DHCPOFFER`.
 const OFFER_TIMEOUT: u32 = 60;
 
+/// The lease time representing infinity (RFC 2131 §3.3).
+pub const INFINITE_LEASE_TIME: u32 = 0xffff_ffff;
+
 /// A lease record of the DHCP server lease database.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Lease {
     address: Ipv4Addr,
     state: State,
@@ -46,6 +50,28 @@ impl Lease {
         }
     }
 
+    /// Creates an `Assigned` lease migrated from another server.
+    ///
+    /// The zero expiration timestamp means the infinite lease.
+    pub fn migrated(address: Ipv4Addr, assigned_at: u32, expires_at: u32) -> Self {
+        let lease_time = if expires_at == 0 {
+            INFINITE_LEASE_TIME
+        } else {
+            expires_at.saturating_sub(assigned_at)
+        };
+
+        Lease {
+            address,
+            state: State::Assigned,
+            lease_time,
+            offered_at: assigned_at,
+            assigned_at,
+            renewed_at: 0,
+            released_at: 0,
+            expires_at,
+        }
+    }
+
     /// `IPv4` lease address.
     pub fn address(&self) -> Ipv4Addr {
         self.address.to_owned()
@@ -63,7 +89,7 @@ impl Lease {
         self.state = State::Assigned;
         self.assigned_at = Utc::now().timestamp() as u32;
         self.lease_time = lease_time;
-        self.expires_at = self.assigned_at + self.lease_time;
+        self.expires_at = self.expiration(self.assigned_at);
     }
 
     /// Renewes the expiration time if the lease is in the `Assigned` state.
@@ -72,7 +98,7 @@ impl Lease {
     pub fn renew(&mut self, lease_time: u32) {
         self.lease_time = lease_time;
         self.renewed_at = Utc::now().timestamp() as u32;
-        self.expires_at = self.renewed_at + self.lease_time;
+        self.expires_at = self.expiration(self.renewed_at);
     }
 
     /// Releases the address and moves the lease to `Released` state.
@@ -81,6 +107,35 @@ impl Lease {
         self.released_at = Utc::now().timestamp() as u32;
     }
 
+    /// Moves the offered or assigned lease to the `Expired` state.
+    pub fn expire(&mut self) {
+        self.state = State::Expired;
+    }
+
+    /// The expiration timestamp of the lease started at `from`, which is 0 for the infinite leases.
+    fn expiration(&self, from: u32) -> u32 {
+        if self.lease_time == INFINITE_LEASE_TIME {
+            0
+        } else {
+            from + self.lease_time
+        }
+    }
+
+    /// The timestamp of the last assignment or renewal in seconds.
+    pub fn updated_at(&self) -> u32 {
+        cmp::max(self.assigned_at, self.renewed_at)
+    }
+
+    /// The timestamp of the release in seconds.
+    pub fn released_at(&self) -> u32 {
+        self.released_at
+    }
+
+    /// The timestamp when the offer is expired in seconds.
+    pub fn offer_expires_at(&self) -> u32 {
+        self.offered_at + OFFER_TIMEOUT
+    }
+
     /// The timestamp when the lease is expired in milliseconds.
     pub fn expires_at(&self) -> u32 {
         self.expires_at
@@ -93,6 +148,9 @@ impl Lease {
         if self.is_expired() {
             return 0;
         }
+        if self.expires_at == 0 {
+            return self.lease_time;
+        }
         self.expires_at - (Utc::now().timestamp() as u32)
     }
 
@@ -159,4 +217,51 @@ impl Lease {
         }
         (Utc::now().timestamp() as u32) >= self.expires_at
     }
+
+    /// Formats the lease as space separated fields of a journal record.
+    pub fn to_record(&self) -> String {
+        let state = match self.state {
+            State::Offered => "offered",
+            State::Assigned => "assigned",
+            State::Released => "released",
+            State::Expired => "expired",
+        };
+        format!(
+            "{} {} {} {} {} {} {} {}",
+            self.address,
+            state,
+            self.lease_time,
+            self.offered_at,
+            self.assigned_at,
+            self.renewed_at,
+            self.released_at,
+            self.expires_at,
+        )
+    }
+
+    /// Parses the lease from the fields written by `to_record`.
+    ///
+    /// Returns `None` if the fields are malformed.
+    pub fn from_record(fields: &[&str]) -> Option<Self> {
+        if fields.len() != 8 {
+            return None;
+        }
+        let state = match fields[1] {
+            "offered" => State::Offered,
+            "assigned" => State::Assigned,
+            "released" => State::Released,
+            "expired" => State::Expired,
+            _ => return None,
+        };
+        Some(Lease {
+            address: fields[0].parse().ok()?,
+            state,
+            lease_time: fields[2].parse().ok()?,
+            offered_at: fields[3].parse().ok()?,
+            assigned_at: fields[4].parse().ok()?,
+            renewed_at: fields[5].parse().ok()?,
+            released_at: fields[6].parse().ok()?,
+            expires_at: fields[7].parse().ok()?,
+        })
+    }
 }
@@ -0,0 +1,138 @@
+//! The asynchronous lease storage trait for the backends with non-blocking I/O.
+
+use std::net::Ipv4Addr;
+
+use futures::{future, stream, Future, Stream};
+
+use lease::Lease;
+use storage::{Error, Storage};
+use storage_ram::RamStorage;
+
+/// The future returned by the `LeaseStorage` methods.
+pub type LeaseFuture<T> = Box<Future<Item = T, Error = Error> + Send>;
+/// The stream of the `client_id` and `Lease` pairs returned by `LeaseStorage::iterate`.
+pub type LeaseStream = Box<Stream<Item = (Vec<u8>, Lease), Error = Error> + Send>;
+
+/// May be implemented by the DHCP server crate user instead of blocking on the storage I/O.
+///
+/// A lease is keyed by the client ID, and its address is associated with the client.
+/// `RamStorage` is the default implementation. The server runs on a backend wrapped
+/// into `AsyncStorage`.
+pub trait LeaseStorage
+where
+    Self: Sync + Send,
+{
+    /// Must resolve to the lease of the given client if the lease exists.
+    ///
+    /// # Errors
+    /// Must resolve to `Error::GetLease(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn get(&self, client_id: &[u8]) -> LeaseFuture<Option<Lease>>;
+
+    /// Must resolve to the client ID and the lease the given address is associated with.
+    ///
+    /// # Errors
+    /// Must resolve to `Error::GetClient(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn get_by_address(&self, address: &Ipv4Addr) -> LeaseFuture<Option<(Vec<u8>, Lease)>>;
+
+    /// Must store the lease and associate its address with the client.
+    ///
+    /// # Errors
+    /// Must resolve to `Error::AddLease(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn put(&mut self, client_id: &[u8], lease: Lease) -> LeaseFuture<()>;
+
+    /// Must store the lease only if the current one is equal to `expected`,
+    /// where `None` means there must be no lease. Resolves to `true` if the lease is stored.
+    ///
+    /// # Errors
+    /// Must resolve to `Error::UpdateLease(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn compare_and_put(
+        &mut self,
+        client_id: &[u8],
+        expected: Option<Lease>,
+        lease: Lease,
+    ) -> LeaseFuture<bool>;
+
+    /// Must delete the leases which addresses are available again (see `Lease::is_available`)
+    /// and resolve to the deleted ones.
+    ///
+    /// # Errors
+    /// Must resolve to `Error::UpdateLease(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn expire(&mut self) -> LeaseFuture<Vec<(Vec<u8>, Lease)>>;
+
+    /// Must yield all the stored leases in an arbitrary order.
+    ///
+    /// # Errors
+    /// Must yield `Error::GetLease(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn iterate(&self) -> LeaseStream;
+}
+
+impl LeaseStorage for RamStorage {
+    fn get(&self, client_id: &[u8]) -> LeaseFuture<Option<Lease>> {
+        Box::new(future::result(self.get_lease(client_id)))
+    }
+
+    fn get_by_address(&self, address: &Ipv4Addr) -> LeaseFuture<Option<(Vec<u8>, Lease)>> {
+        let result = self.get_client(address).and_then(|client_id| match client_id {
+            Some(client_id) => Ok(self
+                .get_lease(&client_id)?
+                .map(|lease| (client_id, lease))),
+            None => Ok(None),
+        });
+        Box::new(future::result(result))
+    }
+
+    fn put(&mut self, client_id: &[u8], lease: Lease) -> LeaseFuture<()> {
+        let result = self
+            .add_client(&lease.address(), client_id)
+            .and_then(|_| self.add_lease(client_id, lease));
+        Box::new(future::result(result))
+    }
+
+    fn compare_and_put(
+        &mut self,
+        client_id: &[u8],
+        expected: Option<Lease>,
+        lease: Lease,
+    ) -> LeaseFuture<bool> {
+        let result = self.get_lease(client_id).and_then(|current| {
+            if current != expected {
+                return Ok(false);
+            }
+            self.add_client(&lease.address(), client_id)?;
+            self.add_lease(client_id, lease)?;
+            Ok(true)
+        });
+        Box::new(future::result(result))
+    }
+
+    fn expire(&mut self) -> LeaseFuture<Vec<(Vec<u8>, Lease)>> {
+        let expired: Vec<(Vec<u8>, Lease)> = self
+            .leases()
+            .filter(|(_, lease)| lease.is_available())
+            .map(|(client_id, lease)| (client_id.to_owned(), lease.to_owned()))
+            .collect();
+        for (client_id, lease) in expired.iter() {
+            self.remove_lease(client_id);
+            if let Ok(Some(owner)) = self.get_client(&lease.address()) {
+                if owner == *client_id {
+                    let _ = self.delete_client(&lease.address());
+                }
+            }
+        }
+        Box::new(future::ok(expired))
+    }
+
+    fn iterate(&self) -> LeaseStream {
+        let leases: Vec<(Vec<u8>, Lease)> = self
+            .leases()
+            .map(|(client_id, lease)| (client_id.to_owned(), lease.to_owned()))
+            .collect();
+        Box::new(stream::iter_ok(leases))
+    }
+}
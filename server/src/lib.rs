@@ -2,14 +2,69 @@
 
 #[macro_use]
 mod macros;
+mod access;
+mod admin;
+mod allocator;
+mod audit;
+mod auth;
 #[cfg(any(target_os = "freebsd", target_os = "macos"))]
 mod bpf;
+mod boot;
 mod builder;
+mod bulk_leasequery;
+mod capture;
+mod check;
+mod class;
+mod config;
+#[cfg(unix)]
+mod control;
 mod database;
+mod ddns;
+mod dhcpd_leases;
+mod error;
+mod event_log;
+mod failover;
+mod fingerprint;
+mod forcerenew;
+mod fqdn;
+#[cfg(feature = "grpc-api")]
+mod grpc_api;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+mod frame;
+mod hook;
+mod http;
+mod inbound;
+mod interface;
 mod lease;
+mod lease_storage;
+mod limiter;
+mod metrics;
+mod observer;
+#[cfg(target_os = "linux")]
+mod packet;
+#[cfg(unix)]
+mod privileges;
+mod probe;
+mod reload;
+mod reservation;
+mod scope;
 mod server;
 mod storage;
+mod storage_async;
+mod storage_file;
 mod storage_ram;
+#[cfg(feature = "redis-storage")]
+mod storage_redis;
+mod storage_sharded;
+#[cfg(feature = "sqlite")]
+mod storage_sqlite;
+mod subnet;
+mod subscriber;
+#[cfg(target_os = "linux")]
+mod systemd;
+mod vendor;
+mod webhook;
+mod wheel;
 
 #[macro_use]
 extern crate log;
@@ -18,31 +73,91 @@ extern crate chrono;
 extern crate futures;
 extern crate hostname;
 extern crate tokio;
+extern crate serde;
 #[macro_use]
-extern crate failure;
-#[cfg(any(target_os = "freebsd", target_os = "macos"))]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
 extern crate etherparse;
-#[cfg(any(target_os = "freebsd", target_os = "macos"))]
 extern crate eui48;
+extern crate base64;
+extern crate rand;
+extern crate hmac;
+extern crate sha2;
 #[cfg(any(target_os = "freebsd", target_os = "macos"))]
 extern crate futures_cpupool;
-#[cfg(target_os = "windows")]
 extern crate tokio_process;
-#[cfg(any(target_os = "freebsd", target_os = "macos"))]
-#[macro_use]
-extern crate arrayref;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(feature = "redis-storage")]
+extern crate redis;
+#[cfg(feature = "grpc-api")]
+extern crate grpc;
+#[cfg(feature = "grpc-api")]
+extern crate protobuf;
+#[cfg(unix)]
+extern crate tokio_signal;
+#[cfg(unix)]
+extern crate tokio_uds;
+#[cfg(unix)]
+extern crate libc;
 #[cfg(any(target_os = "freebsd", target_os = "macos"))]
 extern crate ifcontrol;
 #[cfg(any(target_os = "freebsd", target_os = "macos"))]
 extern crate netif_bpf;
 
-#[cfg(any(target_os = "linux", target_os = "windows"))]
+#[cfg(target_os = "windows")]
 extern crate dhcp_arp;
+extern crate dhcp_backoff;
 extern crate dhcp_framed;
 extern crate dhcp_protocol;
 
 pub use self::{
+    access::{AccessList, DeniedAction, MacPattern},
+    allocator::{Allocator, HashAllocator, RandomAllocator, SequentialAllocator},
+    auth::{AuthenticationKey, FailureAction},
+    boot::{Boot, BootParameters},
+    check::CheckReport,
+    class::{Class, Matcher},
+    config::{Config, Error as ConfigError},
+    database::WorkerState,
+    ddns::{DnsUpdater, DnsZone, TsigKey},
+    dhcpd_leases::{
+        export as export_dhcpd_leases, import as import_dhcpd_leases,
+        Error as DhcpdLeasesError,
+    },
+    error::Error as ServerError,
+    event_log::EventLogger,
+    failover::{Mode as FailoverMode, Role as FailoverRole},
+    fingerprint::FingerprintDatabase,
+    fqdn::{DnsUpdate, FqdnPolicy},
+    hook::{CommandHook, Hook, HookFuture},
+    http::{HttpListener, Request as HttpRequest, Response as HttpResponse},
+    interface::Interface,
+    lease::Lease,
+    lease_storage::{LeaseFuture, LeaseStorage, LeaseStream},
+    metrics::{Metrics, ERROR_ALLOCATION, ERROR_RENEWAL, ERROR_STORAGE, ERROR_VALIDATION},
+    observer::{LeaseEvent, Observer},
+    reload::{file_reloader, Reload, ReloadSource, Reloader},
+    reservation::{HostKey, Reservation},
+    scope::{ScopeOptions, SharedNetwork},
     server::{Server, ServerBuilder},
-    storage::Storage,
+    storage::{Error as StorageError, Storage},
+    storage_async::AsyncStorage,
+    storage_file::FileStorage,
     storage_ram::RamStorage,
+    storage_sharded::ShardedStorage,
+    subnet::Subnet,
+    vendor::VendorClass,
+    webhook::WebhookNotifier,
 };
+
+#[cfg(unix)]
+pub use self::control::{ControlClient, ControlReply};
+#[cfg(unix)]
+pub use self::reload::sighup_reloads;
+#[cfg(feature = "redis-storage")]
+pub use self::storage_redis::RedisStorage;
+#[cfg(feature = "sqlite")]
+pub use self::storage_sqlite::SqliteStorage;
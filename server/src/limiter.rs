@@ -0,0 +1,314 @@
+//! The token bucket rate limiting of the incoming requests.
+
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, net::Ipv4Addr, time::Instant};
+
+/// The number of the tracked clients or relays the idle buckets are dropped after.
+const MAX_BUCKETS: usize = 16384;
+
+/// A bucket of `burst` tokens refilled by `rate` tokens per second.
+#[derive(Clone)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn new(burst: u32, now: Instant) -> Self {
+        Bucket {
+            tokens: f64::from(burst),
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant, (rate, burst): (u32, u32)) {
+        let elapsed = now.duration_since(self.updated_at);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+        self.tokens = (self.tokens + elapsed * f64::from(rate)).min(f64::from(burst));
+        self.updated_at = now;
+    }
+
+    fn is_full(&self, now: Instant, limit: (u32, u32)) -> bool {
+        let mut bucket = self.clone();
+        bucket.refill(now, limit);
+        bucket.tokens >= f64::from(limit.1)
+    }
+}
+
+/// The buckets of the clients or relays sharing the same limit.
+struct Buckets<K> {
+    /// The rate in requests per second and the burst size.
+    limit: (u32, u32),
+    buckets: HashMap<K, Bucket>,
+}
+
+impl<K> Buckets<K>
+where
+    K: Hash + Eq,
+{
+    fn new(limit: (u32, u32)) -> Self {
+        Buckets {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn has_token(&mut self, key: K, now: Instant) -> bool {
+        if self.buckets.len() >= MAX_BUCKETS {
+            let limit = self.limit;
+            self.buckets.retain(|_, bucket| !bucket.is_full(now, limit));
+        }
+        let limit = self.limit;
+        let bucket = self
+            .buckets
+            .entry(key)
+            .or_insert_with(|| Bucket::new(limit.1, now));
+        bucket.refill(now, limit);
+        bucket.tokens >= 1.0
+    }
+
+    fn take<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(bucket) = self.buckets.get_mut(key) {
+            bucket.tokens -= 1.0;
+        }
+    }
+}
+
+/// Drops the requests exceeding the per-client, per-relay or global rates,
+/// so a misbehaving client or a `DHCPDISCOVER` storm cannot starve the others.
+///
+/// The global limit bounds the number of the requests processed per second,
+/// so the requests exceeding it are dropped instead of being queued.
+#[derive(Default)]
+pub struct RateLimiter {
+    clients: Option<Buckets<Vec<u8>>>,
+    relays: Option<Buckets<Ipv4Addr>>,
+    global: Option<((u32, u32), Bucket)>,
+}
+
+impl RateLimiter {
+    /// Limits the requests of every client, identified by the client ID or the hardware address.
+    pub fn set_client_limit(&mut self, rate: u32, burst: u32) {
+        self.clients = Some(Buckets::new((rate, burst)));
+    }
+
+    /// Limits the requests relayed by every relay agent, identified by `giaddr`.
+    pub fn set_relay_limit(&mut self, rate: u32, burst: u32) {
+        self.relays = Some(Buckets::new((rate, burst)));
+    }
+
+    /// Limits all the requests together.
+    pub fn set_global_limit(&mut self, rate: u32, burst: u32) {
+        self.global = Some(((rate, burst), Bucket::new(burst, Instant::now())));
+    }
+
+    /// Checks whether the request may be processed and takes the tokens if so.
+    ///
+    /// The tokens are taken only if all the limits are satisfied,
+    /// so the dropped requests of a flooding client do not consume the global rate.
+    pub fn allows(&mut self, client_id: &[u8], relay: Option<Ipv4Addr>) -> bool {
+        self.allows_at(client_id, relay, Instant::now())
+    }
+
+    fn allows_at(&mut self, client_id: &[u8], relay: Option<Ipv4Addr>, now: Instant) -> bool {
+        if let Some(ref mut clients) = self.clients {
+            if !clients.has_token(client_id.to_vec(), now) {
+                return false;
+            }
+        }
+        if let (Some(relays), Some(relay)) = (self.relays.as_mut(), relay) {
+            if !relays.has_token(relay, now) {
+                return false;
+            }
+        }
+        if let Some((ref limit, ref mut bucket)) = self.global {
+            bucket.refill(now, *limit);
+            if bucket.tokens < 1.0 {
+                return false;
+            }
+            bucket.tokens -= 1.0;
+        }
+
+        if let Some(ref mut clients) = self.clients {
+            clients.take(client_id);
+        }
+        if let (Some(relays), Some(relay)) = (self.relays.as_mut(), relay) {
+            relays.take(&relay);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    const CLIENT: &[u8] = &[1, 2, 3];
+    const OTHER: &[u8] = &[4, 5, 6];
+    const RELAY: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+
+    /// The milliseconds since the start, the client ID and the relay.
+    type Request = (u64, &'static [u8], Option<Ipv4Addr>);
+
+    fn at(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    /// Feeds the requests to the limiter and returns which of them are allowed.
+    fn feed(limiter: &mut RateLimiter, start: Instant, requests: &[Request]) -> Vec<bool> {
+        requests
+            .iter()
+            .map(|&(millis, client_id, relay)| {
+                limiter.allows_at(client_id, relay, at(start, millis))
+            }).collect()
+    }
+
+    #[test]
+    fn allows_everything_without_limits() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+        let requests: Vec<Request> = (0..100).map(|_| (0, CLIENT, Some(RELAY))).collect();
+        assert!(feed(&mut limiter, start, &requests).iter().all(|&allowed| allowed));
+    }
+
+    #[test]
+    fn limits_clients() {
+        // the requests at the milliseconds since the start, and whether they are allowed
+        let cases: &[(&[Request], &[bool])] = &[
+            // the burst is allowed at once and the next request is rejected
+            (
+                &[(0, CLIENT, None), (0, CLIENT, None), (0, CLIENT, None), (0, CLIENT, None)],
+                &[true, true, true, false],
+            ),
+            // the other clients have their own buckets
+            (
+                &[(0, CLIENT, None), (0, CLIENT, None), (0, CLIENT, None), (0, OTHER, None)],
+                &[true, true, true, true],
+            ),
+            // a token is refilled every 500 ms at 2 per second
+            (
+                &[
+                    (0, CLIENT, None),
+                    (0, CLIENT, None),
+                    (0, CLIENT, None),
+                    (250, CLIENT, None),
+                    (500, CLIENT, None),
+                    (500, CLIENT, None),
+                    (1000, CLIENT, None),
+                ],
+                &[true, true, true, false, true, false, true],
+            ),
+            // the bucket is not refilled beyond the burst
+            (
+                &[
+                    (0, CLIENT, None),
+                    (60_000, CLIENT, None),
+                    (60_000, CLIENT, None),
+                    (60_000, CLIENT, None),
+                    (60_000, CLIENT, None),
+                ],
+                &[true, true, true, true, false],
+            ),
+        ];
+        for &(requests, expected) in cases {
+            let mut limiter = RateLimiter::default();
+            limiter.set_client_limit(2, 3);
+            let start = Instant::now();
+            assert_eq!(feed(&mut limiter, start, requests), expected, "{:?}", requests);
+        }
+    }
+
+    #[test]
+    fn limits_relays() {
+        let cases: &[(&[Request], &[bool])] = &[
+            // the clients behind a relay share its bucket
+            (
+                &[(0, CLIENT, Some(RELAY)), (0, OTHER, Some(RELAY)), (0, CLIENT, Some(RELAY))],
+                &[true, true, false],
+            ),
+            // the other relays and the local clients are not limited by it
+            (
+                &[
+                    (0, CLIENT, Some(RELAY)),
+                    (0, CLIENT, Some(RELAY)),
+                    (0, CLIENT, Some(Ipv4Addr::new(10, 0, 0, 2))),
+                    (0, CLIENT, None),
+                ],
+                &[true, true, true, true],
+            ),
+            // a token is refilled every second
+            (
+                &[
+                    (0, CLIENT, Some(RELAY)),
+                    (0, CLIENT, Some(RELAY)),
+                    (500, CLIENT, Some(RELAY)),
+                    (1000, CLIENT, Some(RELAY)),
+                ],
+                &[true, true, false, true],
+            ),
+        ];
+        for &(requests, expected) in cases {
+            let mut limiter = RateLimiter::default();
+            limiter.set_relay_limit(1, 2);
+            let start = Instant::now();
+            assert_eq!(feed(&mut limiter, start, requests), expected, "{:?}", requests);
+        }
+    }
+
+    #[test]
+    fn limits_all_requests() {
+        let mut limiter = RateLimiter::default();
+        limiter.set_global_limit(10, 2);
+        let start = Instant::now();
+        let requests: &[Request] = &[
+            (0, CLIENT, None),
+            (0, OTHER, Some(RELAY)),
+            (0, &[7], None),
+            (100, &[8], None),
+            (100, &[9], None),
+        ];
+        assert_eq!(
+            feed(&mut limiter, start, requests),
+            &[true, true, false, true, false]
+        );
+    }
+
+    #[test]
+    fn keeps_tokens_of_rejected_requests() {
+        let mut limiter = RateLimiter::default();
+        limiter.set_client_limit(1, 2);
+        limiter.set_global_limit(1, 1);
+        let start = Instant::now();
+        // the global limit rejects the second request, which must not take the client token
+        let requests: &[Request] = &[
+            (0, CLIENT, None),
+            (0, CLIENT, None),
+            (1000, CLIENT, None),
+            (1000, CLIENT, None),
+        ];
+        assert_eq!(
+            feed(&mut limiter, start, requests),
+            &[true, false, true, false]
+        );
+        // the client has a token left, but the global bucket is empty until refilled
+        assert!(limiter.clients.as_ref().unwrap().buckets[CLIENT].tokens >= 1.0);
+    }
+
+    #[test]
+    fn drops_full_buckets_at_capacity() {
+        let mut limiter = RateLimiter::default();
+        limiter.set_client_limit(1, 1);
+        let start = Instant::now();
+        for index in 0..MAX_BUCKETS as u32 {
+            assert!(limiter.allows_at(&index.to_be_bytes(), None, start));
+        }
+        // all the buckets are full again a second later, so they are dropped
+        assert!(limiter.allows_at(CLIENT, None, at(start, 1000)));
+        assert_eq!(limiter.clients.as_ref().unwrap().buckets.len(), 1);
+    }
+}
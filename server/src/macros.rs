@@ -10,7 +10,10 @@ macro_rules! expect (
 /// Just to move some code from the overwhelmed `poll` method.
 macro_rules! log_receive(
     ($message:expr, $source:expr) => (
-        info!("Received {} from {}", expect!($message.options.dhcp_message_type), $source);
+        match $message.options.dhcp_message_type {
+            Some(dhcp_message_type) => info!("Received {} from {}", dhcp_message_type, $source),
+            None => info!("Received {} from {}", $message.operation_code, $source),
+        }
         debug!("{}", $message);
     );
 );
@@ -18,36 +21,22 @@ macro_rules! log_receive(
 /// Just to move some code from the overwhelmed `poll` method.
 macro_rules! log_send(
     ($message:expr, $destination:expr) => (
-        info!("Sending {} to {}", expect!($message.options.dhcp_message_type), $destination);
+        match $message.options.dhcp_message_type {
+            Some(dhcp_message_type) => info!("Sending {} to {}", dhcp_message_type, $destination),
+            None => info!("Sending {} to {}", $message.operation_code, $destination),
+        }
         debug!("{}", $message);
     );
 );
 
-/// Just to move some code from the overwhelmed `poll` method.
-macro_rules! poll (
-    ($socket:expr) => (
-        match $socket.poll() {
-            Ok(Async::Ready(Some(data))) => data,
-            Ok(Async::Ready(None)) => {
-                warn!("Received an invalid packet");
-                continue;
-            }
-            Ok(Async::NotReady) => return Ok(Async::NotReady),
-            Err(error) => {
-                warn!("Socket error: {}", error);
-                return Err(error);
-            },
-        };
-    );
-);
-
 /// Just to move some code from the overwhelmed `poll` method.
 macro_rules! validate (
-    ($message:expr, $address:expr) => (
+    ($message:expr, $address:expr, $metrics:expr) => (
         match $message.validate() {
             Ok(dhcp_message_type) => dhcp_message_type,
             Err(error) => {
                 warn!("The request from {} is invalid: {}", $address, error);
+                $metrics.error(::metrics::ERROR_VALIDATION);
                 continue;
             },
         };
@@ -0,0 +1,249 @@
+//! The server metrics in the Prometheus text exposition format.
+
+use std::{collections::BTreeMap, fmt::Write, time::Duration};
+
+use dhcp_framed::PoolStats;
+use dhcp_protocol::Message;
+
+use database::Utilization;
+
+/// The kinds of the counted errors.
+pub const ERROR_VALIDATION: &str = "validation";
+pub const ERROR_ALLOCATION: &str = "allocation";
+pub const ERROR_ASSIGNMENT: &str = "assignment";
+pub const ERROR_RENEWAL: &str = "renewal";
+pub const ERROR_CONFLICT: &str = "conflict";
+pub const ERROR_STORAGE: &str = "storage";
+
+/// The database operations the latency is measured of.
+pub const OPERATION_OFFER: &str = "offer";
+pub const OPERATION_ASSIGN: &str = "assign";
+pub const OPERATION_RENEW: &str = "renew";
+
+/// The outcomes of the abandoned address probes.
+pub const RECLAMATION_RECLAIMED: &str = "reclaimed";
+pub const RECLAMATION_SILENT: &str = "silent";
+pub const RECLAMATION_IN_USE: &str = "in_use";
+
+/// The upper bounds of the latency histogram buckets in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+
+/// A cumulative histogram of durations.
+#[derive(Default)]
+struct Histogram {
+    /// The observations in each bucket of `LATENCY_BUCKETS`.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9;
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// The counters and histograms updated while serving.
+///
+/// The pool gauges are computed from the database on each scrape.
+#[derive(Default)]
+pub struct Metrics {
+    /// Keyed by the message type name.
+    received: BTreeMap<String, u64>,
+    /// Keyed by the message type name.
+    sent: BTreeMap<String, u64>,
+    /// The received messages shed by the full inbound queue, keyed by the message type name.
+    dropped: BTreeMap<String, u64>,
+    /// Keyed by the `ERROR_*` kind.
+    errors: BTreeMap<&'static str, u64>,
+    /// Keyed by the `OPERATION_*` name.
+    latencies: BTreeMap<&'static str, Histogram>,
+    /// Keyed by the `RECLAMATION_*` outcome.
+    reclamations: BTreeMap<&'static str, u64>,
+    /// The counters of the packet capture buffer pool, updated on each scrape.
+    buffer_pool: Option<PoolStats>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn received(&mut self, message: &Message) {
+        self.count_received(message_type(message));
+    }
+
+    pub fn sent(&mut self, message: &Message) {
+        self.count_sent(message_type(message));
+    }
+
+    /// Counts a received message by its type name, e.g. of another protocol version.
+    pub fn count_received(&mut self, message_type: String) {
+        *self.received.entry(message_type).or_insert(0) += 1;
+    }
+
+    /// Counts a sent message by its type name, e.g. of another protocol version.
+    pub fn count_sent(&mut self, message_type: String) {
+        *self.sent.entry(message_type).or_insert(0) += 1;
+    }
+
+    pub fn dropped(&mut self, message: &Message) {
+        *self.dropped.entry(message_type(message)).or_insert(0) += 1;
+    }
+
+    pub fn error(&mut self, kind: &'static str) {
+        *self.errors.entry(kind).or_insert(0) += 1;
+    }
+
+    pub fn reclamation(&mut self, outcome: &'static str) {
+        *self.reclamations.entry(outcome).or_insert(0) += 1;
+    }
+
+    pub fn buffer_pool(&mut self, stats: PoolStats) {
+        self.buffer_pool = Some(stats);
+    }
+
+    pub fn observe(&mut self, operation: &'static str, duration: Duration) {
+        self.latencies
+            .entry(operation)
+            .or_insert_with(Histogram::default)
+            .observe(duration);
+    }
+
+    /// Formats the metrics with the pool utilization gauges.
+    pub fn render(&self, utilization: &[Utilization]) -> String {
+        let mut text = String::new();
+
+        header(&mut text, "dhcp_messages_received_total", "counter", "The received messages.");
+        for (message_type, count) in self.received.iter() {
+            let _ = writeln!(
+                text,
+                "dhcp_messages_received_total{{type=\"{}\"}} {}",
+                message_type, count
+            );
+        }
+        header(&mut text, "dhcp_messages_sent_total", "counter", "The sent messages.");
+        for (message_type, count) in self.sent.iter() {
+            let _ = writeln!(
+                text,
+                "dhcp_messages_sent_total{{type=\"{}\"}} {}",
+                message_type, count
+            );
+        }
+        let name = "dhcp_messages_dropped_total";
+        header(&mut text, name, "counter", "The messages dropped by the full inbound queue.");
+        for (message_type, count) in self.dropped.iter() {
+            let _ = writeln!(text, "{}{{type=\"{}\"}} {}", name, message_type, count);
+        }
+        header(&mut text, "dhcp_errors_total", "counter", "The request processing errors.");
+        for (kind, count) in self.errors.iter() {
+            let _ = writeln!(text, "dhcp_errors_total{{kind=\"{}\"}} {}", kind, count);
+        }
+        let name = "dhcp_reclamation_probes_total";
+        header(&mut text, name, "counter", "The finished abandoned address probes.");
+        for (outcome, count) in self.reclamations.iter() {
+            let _ = writeln!(text, "{}{{outcome=\"{}\"}} {}", name, outcome, count);
+        }
+        if let Some(stats) = self.buffer_pool {
+            let counters = [
+                (
+                    "dhcp_buffers_allocated_total",
+                    "The buffers allocated for the empty pool.",
+                    stats.allocated,
+                ),
+                ("dhcp_buffers_reused_total", "The buffers taken from the pool.", stats.reused),
+                (
+                    "dhcp_buffers_discarded_total",
+                    "The buffers dropped by the full pool.",
+                    stats.discarded,
+                ),
+            ];
+            for &(name, help, value) in counters.iter() {
+                header(&mut text, name, "counter", help);
+                let _ = writeln!(text, "{} {}", name, value);
+            }
+            header(&mut text, "dhcp_buffers_idle", "gauge", "The buffers in the pool.");
+            let _ = writeln!(text, "dhcp_buffers_idle {}", stats.idle);
+        }
+
+        let name = "dhcp_allocation_duration_seconds";
+        header(&mut text, name, "histogram", "The address database operation latency.");
+        for (operation, histogram) in self.latencies.iter() {
+            for (count, bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+                let _ = writeln!(
+                    text,
+                    "{}_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                    name, operation, bound, count
+                );
+            }
+            let _ = writeln!(
+                text,
+                "{}_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+                name, operation, histogram.count
+            );
+            let _ = writeln!(text, "{}_sum{{operation=\"{}\"}} {}", name, operation, histogram.sum);
+            let _ = writeln!(
+                text,
+                "{}_count{{operation=\"{}\"}} {}",
+                name, operation, histogram.count
+            );
+        }
+
+        let gauges: [(&str, &str, fn(&Utilization) -> u32); 7] = [
+            ("dhcp_pool_size", "The addresses in the pool range.", |pool| pool.size),
+            ("dhcp_pool_allocated", "The offered and assigned addresses.", |pool| pool.allocated),
+            ("dhcp_pool_leased", "The assigned addresses.", |pool| pool.leased),
+            ("dhcp_pool_reserved", "The addresses reserved for the hosts.", |pool| pool.reserved),
+            ("dhcp_pool_abandoned", "The addresses found in use.", |pool| pool.abandoned),
+            ("dhcp_pool_free", "The addresses available for allocation.", |pool| pool.free),
+            (
+                "dhcp_pool_high_water",
+                "The most addresses allocated at once.",
+                |pool| pool.high_water,
+            ),
+        ];
+        for &(name, help, value) in gauges.iter() {
+            header(&mut text, name, "gauge", help);
+            for pool in utilization.iter() {
+                let subnet = pool
+                    .subnet
+                    .map_or_else(String::new, |subnet| subnet.to_string());
+                let _ = writeln!(
+                    text,
+                    "{}{{subnet=\"{}\",range=\"{}-{}\"}} {}",
+                    name,
+                    subnet,
+                    pool.first,
+                    pool.last,
+                    value(pool)
+                );
+            }
+        }
+        text
+    }
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+/// The plain BOOTP messages are counted by the operation code.
+fn message_type(message: &Message) -> String {
+    match message.options.dhcp_message_type {
+        Some(dhcp_message_type) => dhcp_message_type.to_string(),
+        None => message.operation_code.to_string(),
+    }
+}
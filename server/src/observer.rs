@@ -0,0 +1,62 @@
+//! The lease lifecycle observer module.
+
+use std::net::Ipv4Addr;
+
+use fqdn::DnsUpdate;
+
+/// A change of a client binding.
+#[derive(Debug, Clone)]
+pub enum LeaseEvent {
+    /// The client has been acknowledged a new lease or has verified its lease after a reboot.
+    Committed {
+        client_id: Vec<u8>,
+        address: Ipv4Addr,
+        /// `None` if the client has not sent one or has forbidden the DNS updates.
+        hostname: Option<String>,
+        /// The DNS records of the host name updated by the server.
+        dns_update: DnsUpdate,
+        lease_time: u32,
+    },
+    /// The client has extended its lease in the RENEWING or REBINDING state.
+    Renewed {
+        client_id: Vec<u8>,
+        address: Ipv4Addr,
+        /// `None` if the client has not sent one or has forbidden the DNS updates.
+        hostname: Option<String>,
+        /// The DNS records of the host name updated by the server.
+        dns_update: DnsUpdate,
+        lease_time: u32,
+    },
+    /// The client has released the address with `DHCPRELEASE`.
+    Released {
+        client_id: Vec<u8>,
+        address: Ipv4Addr,
+    },
+    /// The lease has not been renewed in time and the address has been freed.
+    Expired {
+        client_id: Vec<u8>,
+        address: Ipv4Addr,
+    },
+    /// The client has found the address in use with `DHCPDECLINE`, so it has been abandoned.
+    Declined {
+        client_id: Vec<u8>,
+        address: Ipv4Addr,
+    },
+    /// The client has been sent a `DHCPNAK` for the requested address.
+    Rejected {
+        client_id: Vec<u8>,
+        address: Ipv4Addr,
+        reason: String,
+    },
+    /// The address probe has found the address in use by an unknown host.
+    Conflict { address: Ipv4Addr },
+}
+
+/// Is notified about the lease lifecycle events.
+///
+/// May be used for custom logging, metrics or integration with other systems.
+/// Is called synchronously, so it must not block the server.
+pub trait Observer: Send + Sync {
+    /// Called after the change has been stored, if any.
+    fn on_lease_event(&self, _event: &LeaseEvent) {}
+}
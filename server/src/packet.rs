@@ -0,0 +1,124 @@
+//! The `AF_PACKET` socket sending the hardware unicast responses on Linux.
+//!
+//! RFC 2131 §4.1 requires unicasting to the offered address and the client hardware
+//! address if the broadcast bit is not set, although the client cannot answer ARP yet.
+//! The frames are built by the server, so the kernel ARP cache is left intact.
+
+use std::{fs, io, mem, net::Ipv4Addr, os::unix::io::RawFd};
+
+use eui48::{MacAddress, EUI48LEN};
+use libc::{self, c_int, c_void, sockaddr_ll, socklen_t};
+
+use dhcp_protocol::Message;
+
+use frame::ethernet_packet;
+
+const DEFAULT_PACKET_BUFFER_SIZE: usize = 8192;
+/// `ETH_P_IP` in the network byte order, as `AF_PACKET` expects.
+const PROTOCOL_IP: u16 = 0x0800;
+
+/// A raw link-layer socket bound to an interface.
+pub struct PacketSocket {
+    fd: RawFd,
+    /// The interface index.
+    index: c_int,
+    /// The interface MAC address.
+    hardware_address: MacAddress,
+}
+
+impl PacketSocket {
+    /// Opens the socket on the interface, which requires `CAP_NET_RAW`.
+    ///
+    /// # Errors
+    /// `io::Error` if the interface is not found or the socket cannot be opened.
+    pub fn new(iface_name: &str) -> io::Result<Self> {
+        let read = |attribute: &str| {
+            fs::read_to_string(format!("/sys/class/net/{}/{}", iface_name, attribute))
+                .map(|value| value.trim().to_owned())
+        };
+        let index = read("ifindex")?
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid interface index"))?;
+        let hardware_address = MacAddress::parse_str(&read("address")?).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "Invalid interface hardware address")
+        })?;
+
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                c_int::from(PROTOCOL_IP.to_be()),
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PacketSocket {
+            fd,
+            index,
+            hardware_address,
+        })
+    }
+
+    /// Sends a DHCP `message` from `source` to `destination` at the client hardware address.
+    ///
+    /// The message is dropped if the socket buffer is full, so the client retransmits.
+    ///
+    /// # Errors
+    /// `io::Error` on a message serializing or sending error.
+    pub fn send(
+        &self,
+        source: &Ipv4Addr,
+        destination: &Ipv4Addr,
+        message: Message,
+        max_size: Option<u16>,
+    ) -> io::Result<()> {
+        trace!("Sending to {} via AF_PACKET", destination);
+
+        let mut payload = vec![0u8; DEFAULT_PACKET_BUFFER_SIZE];
+        let amount = message.to_bytes(payload.as_mut(), max_size)?;
+        let packet = ethernet_packet(
+            self.hardware_address,
+            message.client_hardware_address,
+            *source,
+            *destination,
+            &payload[..amount],
+        )?;
+
+        let mut address: sockaddr_ll = unsafe { mem::zeroed() };
+        address.sll_family = libc::AF_PACKET as u16;
+        address.sll_protocol = PROTOCOL_IP.to_be();
+        address.sll_ifindex = self.index;
+        address.sll_halen = EUI48LEN as u8;
+        address.sll_addr[..EUI48LEN].copy_from_slice(message.client_hardware_address.as_bytes());
+
+        let sent = unsafe {
+            libc::sendto(
+                self.fd,
+                packet.as_ptr() as *const c_void,
+                packet.len(),
+                0,
+                &address as *const sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<sockaddr_ll>() as socklen_t,
+            )
+        };
+        if sent < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::WouldBlock {
+                warn!("The packet socket buffer is full, dropping the response");
+                return Ok(());
+            }
+            return Err(error);
+        }
+        trace!("Response has been sent via AF_PACKET");
+        Ok(())
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Dropping the root privileges once the privileged ports are bound.
+//!
+//! The DHCP, PXE and admin sockets and the raw sockets sending the hardware unicasts
+//! are opened beforehand and keep working, so no capabilities are kept at all.
+//! The user must be able to write the storage directory, which is compacted by renaming
+//! a new journal, the control socket directory and the event log. After a `chroot`
+//! the paths of the storage, the reloaded configuration and the hook commands
+//! are resolved inside the new root, and the address probes need `ping` there.
+
+use std::{ffi::CString, io, os::unix::ffi::OsStrExt, path::PathBuf};
+
+use libc::{self, gid_t, uid_t};
+
+/// Changes the root directory and then switches to the user and its or the given group.
+///
+/// # Errors
+/// `io::Error` if the user or the group is not found or the privileges cannot be dropped.
+pub fn drop_privileges(
+    user: Option<&(String, Option<String>)>,
+    chroot: Option<&PathBuf>,
+) -> io::Result<()> {
+    // the user database is read before it is out of the new root
+    let ids = match user {
+        Some(&(ref user, ref group)) => Some(lookup(user, group.as_ref().map(String::as_str))?),
+        None => None,
+    };
+
+    if let Some(path) = chroot {
+        let path = c_string(path.as_os_str().as_bytes())?;
+        if unsafe { libc::chroot(path.as_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let root = c_string("/")?;
+        if unsafe { libc::chdir(root.as_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    if let Some((uid, gid)) = ids {
+        // the group must be changed while the server is still root
+        unsafe {
+            if libc::setgroups(1, &gid) < 0 || libc::setgid(gid) < 0 || libc::setuid(uid) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::setuid(0) == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The root privileges have not been dropped",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the user ID and the ID of the group or the primary group of the user.
+fn lookup(user: &str, group: Option<&str>) -> io::Result<(uid_t, gid_t)> {
+    let name = c_string(user)?;
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if passwd.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("User {} not found", user),
+        ));
+    }
+    let (uid, primary_gid) = unsafe { ((*passwd).pw_uid, (*passwd).pw_gid) };
+
+    let gid = match group {
+        Some(group) => {
+            let name = c_string(group)?;
+            let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+            if entry.is_null() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Group {} not found", group),
+                ));
+            }
+            unsafe { (*entry).gr_gid }
+        }
+        None => primary_gid,
+    };
+    Ok((uid, gid))
+}
+
+fn c_string<T: Into<Vec<u8>>>(value: T) -> io::Result<CString> {
+    CString::new(value).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+}
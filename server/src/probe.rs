@@ -0,0 +1,138 @@
+//! The ICMP echo probing of the addresses before offering them.
+//!
+//! The system `ping` utility is used, since raw sockets require the privileges
+//! the server may not have. An echo request without a reply is repeated
+//! on a constant backoff, each one getting the same timeout.
+
+use std::{
+    net::Ipv4Addr,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use tokio::prelude::*;
+use tokio_process::{CommandExt, OutputAsync};
+
+use dhcp_backoff::{Backoff, Constant};
+
+/// A probe in progress with the data to be used when it finishes.
+struct Probe<T> {
+    address: Ipv4Addr,
+    /// Not set after an echo request has failed until the next one is sent.
+    process: Option<OutputAsync>,
+    /// Ticks at the timeout of each echo request, the last tick ends the probe.
+    timeouts: Backoff<Constant>,
+    data: T,
+}
+
+impl<T> Probe<T> {
+    /// Returns whether a host has replied once the probe is over.
+    ///
+    /// The running echo request is killed when the next one is sent.
+    fn poll(&mut self) -> Option<bool> {
+        loop {
+            let polled = match self.process {
+                Some(ref mut process) => process.poll(),
+                None => Ok(Async::NotReady),
+            };
+            match polled {
+                Ok(Async::Ready(ref output)) if output.status.success() => return Some(true),
+                Ok(Async::Ready(_)) => self.process = None,
+                Ok(Async::NotReady) => {}
+                Err(error) => {
+                    warn!("Probe process error: {}", error);
+                    return Some(false);
+                }
+            }
+
+            match self.timeouts.poll() {
+                Ok(Async::NotReady) => return None,
+                Ok(Async::Ready(Some((_, false)))) => {
+                    trace!("Probing the address {} again", self.address);
+                    self.process = Some(ping(&self.address).output_async());
+                }
+                Ok(Async::Ready(_)) => return Some(false),
+                Err(error) => {
+                    warn!("Probe timer error: {}", error);
+                    return Some(false);
+                }
+            }
+        }
+    }
+}
+
+/// Runs the bounded number of the probes concurrently.
+pub struct Prober<T> {
+    timeout: Duration,
+    max_probes: usize,
+    /// The echo requests repeated after the first one has got no reply.
+    retries: u32,
+    probes: Vec<Probe<T>>,
+}
+
+impl<T> Prober<T> {
+    pub fn new(timeout: Duration, max_probes: usize, retries: u32) -> Self {
+        Prober {
+            timeout,
+            max_probes,
+            retries,
+            probes: Vec::with_capacity(max_probes),
+        }
+    }
+
+    /// The probes which may run at the same time.
+    pub fn max_probes(&self) -> usize {
+        self.max_probes
+    }
+
+    /// Starts probing the address.
+    ///
+    /// The data is returned back if the probe cannot be started,
+    /// so the caller may proceed without probing.
+    pub fn start(&mut self, address: Ipv4Addr, data: T) -> Result<(), T> {
+        if self.probes.len() >= self.max_probes {
+            debug!("Too many probes in progress, not probing {}", address);
+            return Err(data);
+        }
+
+        trace!("Probing the address {}", address);
+        let mut timeouts = Constant::new(self.timeout);
+        timeouts.with_retries(self.retries + 1);
+        self.probes.push(Probe {
+            address,
+            process: Some(ping(&address).output_async()),
+            timeouts: Backoff::new(timeouts),
+            data,
+        });
+        Ok(())
+    }
+
+    /// Returns a finished probe as the address, the data and
+    /// whether a host has replied, i.e. the address is in use.
+    ///
+    /// The probe process is killed on timeout.
+    pub fn poll_finished(&mut self) -> Option<(Ipv4Addr, T, bool)> {
+        for index in 0..self.probes.len() {
+            if let Some(is_in_use) = self.probes[index].poll() {
+                let probe = self.probes.swap_remove(index);
+                return Some((probe.address, probe.data, is_in_use));
+            }
+        }
+        None
+    }
+}
+
+/// A single echo request, the timeout is enforced by the `Prober`.
+fn ping(address: &Ipv4Addr) -> Command {
+    let mut command = Command::new("ping");
+    #[cfg(target_os = "windows")]
+    command.arg("-n").arg("1");
+    #[cfg(not(target_os = "windows"))]
+    command.arg("-c").arg("1");
+    command
+        .arg(address.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
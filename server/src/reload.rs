@@ -0,0 +1,101 @@
+//! The configuration reloading module.
+
+use std::{net::Ipv4Addr, path::Path, sync::Arc};
+
+use tokio::{io, prelude::*};
+#[cfg(unix)]
+use tokio_signal::unix::{Signal, SIGHUP};
+
+use access::AccessList;
+use allocator::Allocator;
+use boot::Boot;
+use class::Class;
+use config::{Config, Error as ConfigError};
+use fingerprint::FingerprintDatabase;
+use reservation::{HostKey, Reservation};
+use scope::SharedNetwork;
+use subnet::Subnet;
+use vendor::VendorClass;
+
+/// The server settings applied to a running server.
+///
+/// The leases and the offers in progress are kept, since they live in the storage.
+pub struct Reload {
+    /// Cannot be changed without a restart, since the socket is bound to it.
+    pub server_ip_address: Ipv4Addr,
+    /// Cannot be changed without a restart, since the socket is bound to it.
+    pub iface_name: String,
+    pub static_address_range: (Ipv4Addr, Ipv4Addr),
+    pub dynamic_address_range: (Ipv4Addr, Ipv4Addr),
+    /// The ranges inside the dynamic one which are never allocated.
+    pub dynamic_exclusions: Vec<(Ipv4Addr, Ipv4Addr)>,
+    pub subnet_mask: Ipv4Addr,
+    pub routers: Vec<Ipv4Addr>,
+    pub domain_name_servers: Vec<Ipv4Addr>,
+    pub static_routes: Vec<(Ipv4Addr, Ipv4Addr)>,
+    pub classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    /// The default and maximal lease times in seconds.
+    pub lease_times: (u32, u32),
+    /// The strategy of the dynamic range outside the subnets.
+    pub allocator: Arc<Allocator>,
+    /// In seconds, zero if disabled.
+    pub affinity_retention: u32,
+    /// In seconds, zero if disabled.
+    pub grace_period: u32,
+    pub raw_options: Vec<(u8, Vec<u8>)>,
+    pub subnets: Vec<Subnet>,
+    pub reservations: Vec<(HostKey, Reservation)>,
+    pub classes: Vec<Class>,
+    pub shared_networks: Vec<SharedNetwork>,
+    pub vendor_classes: Vec<VendorClass>,
+    pub access_list: Option<AccessList>,
+    pub boot: Option<Boot>,
+    /// Whether the plain BOOTP requests are answered.
+    pub bootp: bool,
+    /// The device types of the clients are found in it.
+    pub fingerprints: Option<FingerprintDatabase>,
+}
+
+/// A source of the configuration changes.
+///
+/// Implemented for any `Stream` of `Reload`, so an admin API may feed
+/// the server through a `futures::sync::mpsc` channel.
+pub trait ReloadSource: Stream<Item = Reload, Error = io::Error> + Send {}
+
+impl<S> ReloadSource for S where S: Stream<Item = Reload, Error = io::Error> + Send {}
+
+/// Loads the configuration on demand, like on an admin API request.
+pub trait Reloader: Fn() -> Result<Reload, ConfigError> + Send {}
+
+impl<F> Reloader for F where F: Fn() -> Result<Reload, ConfigError> + Send {}
+
+/// Reads the configuration file each time it is called.
+pub fn file_reloader<P: AsRef<Path>>(path: P) -> Box<Reloader> {
+    let path = path.as_ref().to_owned();
+    Box::new(move || {
+        info!("Reloading the configuration {}", path.display());
+        Config::from_file(&path).and_then(Config::into_reload)
+    })
+}
+
+/// Reads the configuration file each time the process receives `SIGHUP`.
+///
+/// A configuration which cannot be loaded is logged and skipped,
+/// so the server keeps working with the previous one.
+#[cfg(unix)]
+pub fn sighup_reloads<P: AsRef<Path>>(path: P) -> Box<ReloadSource> {
+    let path = path.as_ref().to_owned();
+    let stream = Signal::new(SIGHUP)
+        .flatten_stream()
+        .filter_map(move |_| {
+            info!("Reloading the configuration {}", path.display());
+            match Config::from_file(&path).and_then(Config::into_reload) {
+                Ok(reload) => Some(reload),
+                Err(error) => {
+                    error!("Configuration reloading error: {}", error);
+                    None
+                }
+            }
+        });
+    Box::new(stream)
+}
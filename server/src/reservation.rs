@@ -0,0 +1,98 @@
+//! Static host reservations taking precedence over the address pools.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::Ipv4Addr,
+};
+
+use eui48::MacAddress;
+
+use dhcp_protocol::Message;
+
+use scope::ScopeOptions;
+
+/// The key a reserved host is recognized by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HostKey {
+    /// The `chaddr` field.
+    HardwareAddress(MacAddress),
+    /// The `client_id` option.
+    ClientId(Vec<u8>),
+    /// The circuit ID of the relay agent information, e.g. a DSLAM port.
+    CircuitId(Vec<u8>),
+    /// The remote ID of the relay agent information.
+    RemoteId(Vec<u8>),
+}
+
+/// A fixed address assignment with the per-host option overrides.
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    /// May be outside the static and dynamic pools.
+    pub address: Ipv4Addr,
+    /// Sent in the `hostname` option instead of the server hostname.
+    pub hostname: Option<String>,
+    /// Sent in the `file` field or in the `bootfile_name` option if it does not fit.
+    pub boot_filename: Option<String>,
+    /// The default and maximal lease times in seconds, replacing the class and subnet ones.
+    ///
+    /// The server-wide maximal lease time is still applied.
+    pub lease_times: Option<(u32, u32)>,
+    /// The renewal (T1) and rebinding (T2) times in seconds,
+    /// replacing the class and subnet ones.
+    pub renewal_times: Option<(u32, u32)>,
+    /// The innermost option scope, applied after the class one.
+    pub options: ScopeOptions,
+}
+
+/// The host reservation table.
+#[derive(Default)]
+pub struct Reservations {
+    hosts: HashMap<HostKey, Reservation>,
+}
+
+impl Reservations {
+    pub fn new() -> Self {
+        Reservations::default()
+    }
+
+    /// Adds or replaces the reservation of the host.
+    pub fn insert(&mut self, key: HostKey, reservation: Reservation) {
+        self.hosts.insert(key, reservation);
+    }
+
+    /// Finds the reservation of the requesting host.
+    ///
+    /// The `client_id` option is preferred over the hardware address,
+    /// since it is used as the client identifier if present.
+    /// The relay agent circuit and remote IDs are checked last,
+    /// so a host reservation takes precedence over a port one.
+    pub fn find(&self, request: &Message) -> Option<&Reservation> {
+        if let Some(ref client_id) = request.options.client_id {
+            if let Some(reservation) = self.hosts.get(&HostKey::ClientId(client_id.to_owned())) {
+                return Some(reservation);
+            }
+        }
+        if let Some(reservation) = self
+            .hosts
+            .get(&HostKey::HardwareAddress(request.client_hardware_address))
+        {
+            return Some(reservation);
+        }
+        let information = request.options.relay_agent_information.as_ref()?;
+        if let Some(ref circuit_id) = information.circuit_id {
+            if let Some(reservation) = self.hosts.get(&HostKey::CircuitId(circuit_id.to_owned())) {
+                return Some(reservation);
+            }
+        }
+        let remote_id = information.remote_id.as_ref()?;
+        self.hosts.get(&HostKey::RemoteId(remote_id.to_owned()))
+    }
+
+    /// The reserved addresses which must not be allocated to other clients.
+    pub fn addresses(&self) -> HashSet<Ipv4Addr> {
+        self.hosts
+            .values()
+            .map(|reservation| reservation.address)
+            .collect()
+    }
+}
@@ -0,0 +1,62 @@
+//! The option scopes resolved in the order global → shared network → subnet → class → host.
+
+use std::net::Ipv4Addr;
+
+/// Clamps the lease time requested by the client to the `(default, maximal)` lease times
+/// of the most specific scope setting them, if any.
+pub fn clamp_lease_time(lease_times: Option<(u32, u32)>, requested: Option<u32>) -> Option<u32> {
+    match lease_times {
+        Some((default, max)) => Some(requested.unwrap_or(default).min(max)),
+        None => requested,
+    }
+}
+
+/// The options of a configuration scope as `(tag, data)` pairs.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeOptions {
+    /// Replace the inherited options with the same tags.
+    pub overrides: Vec<(u8, Vec<u8>)>,
+    /// Appended to the data of the inherited options with the same tags,
+    /// e.g. to add a server to the inherited `ntp_servers`.
+    pub appends: Vec<(u8, Vec<u8>)>,
+}
+
+impl ScopeOptions {
+    /// Checks whether the scope changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty() && self.appends.is_empty()
+    }
+
+    /// Applies the scope on top of the options inherited from the outer scopes.
+    pub fn apply(&self, options: &mut Vec<(u8, Vec<u8>)>) {
+        for &(tag, ref data) in self.overrides.iter() {
+            options.retain(|&(inherited, _)| inherited != tag);
+            options.push((tag, data.to_owned()));
+        }
+        for &(tag, ref data) in self.appends.iter() {
+            match options.iter_mut().find(|&&mut (inherited, _)| inherited == tag) {
+                Some(&mut (_, ref mut inherited)) => inherited.extend_from_slice(data),
+                None => options.push((tag, data.to_owned())),
+            }
+        }
+    }
+}
+
+/// Several subnets sharing the options, e.g. the ones on the same link or site.
+///
+/// As in ISC dhcpd, the clients of any subnet are allocated from the pools of all of them.
+#[derive(Debug, Clone)]
+pub struct SharedNetwork {
+    /// Used only for logging.
+    pub name: String,
+    /// The network addresses of the subnets.
+    pub subnets: Vec<Ipv4Addr>,
+    pub options: ScopeOptions,
+}
+
+impl SharedNetwork {
+    /// Checks whether the subnet with the network address belongs to the shared network.
+    pub fn contains(&self, subnet: &Ipv4Addr) -> bool {
+        self.subnets.contains(subnet)
+    }
+}
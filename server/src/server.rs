@@ -1,21 +1,104 @@
 //! The main DHCP server module.
 
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use chrono::Utc;
+use eui48::MacAddress;
 use hostname;
-use tokio::{io, prelude::*};
+use rand::{self, Rng};
+use tokio::{
+    self, io,
+    prelude::*,
+    timer::{Delay, Interval},
+};
 
-#[cfg(any(target_os = "linux", target_os = "windows"))]
+#[cfg(target_os = "windows")]
 use dhcp_arp;
-use dhcp_framed::DhcpFramed;
-use dhcp_protocol::{Message, MessageType, DHCP_PORT_CLIENT, DHCP_PORT_SERVER};
+#[cfg(target_os = "linux")]
+use dhcp_framed::interface_index;
+use dhcp_framed::{DhcpFramed, DhcpTransport, ServerSocket, TransportFramed};
+use dhcp_protocol::{
+    HardwareType, Message, MessageType, OptionTag, StatusCode, DHCP_PORT_CLIENT, DHCP_PORT_PXE,
+    DHCP_PORT_SERVER, DHCP_STATE_ACTIVE, DHCP_STATE_EXPIRED, DHCP_STATE_RELEASED,
+    OFFSET_SERVER_NAME, STATUS_MALFORMED_QUERY, STATUS_UNSPEC_FAIL,
+};
 
+use access::{AccessList, DeniedAction};
+use admin::{AdminApi, Command, ForceRenewReport, LeaseRecord, RenewalTarget};
+use allocator::{Allocator, SequentialAllocator};
+use audit::Audit;
+use auth::{Authenticator, FailureAction};
 #[cfg(any(target_os = "freebsd", target_os = "macos"))]
 use bpf::BpfData;
+use boot::{is_pxe_client, Boot, BootParameters};
 use builder::MessageBuilder;
-use database::{Database, Error::LeaseInvalid};
+use bulk_leasequery::BulkLeasequery;
+use capture::Capture;
+#[cfg(unix)]
+use control::ControlSocket;
+use class::Class;
+use database::{
+    Database,
+    Error as DatabaseError,
+    Error::{ClientDenied, LeaseInvalid, LeaseNotFound, WrongNetwork},
+    WorkerState,
+    DEFAULT_LEASE_TIME,
+    MAX_LEASE_TIME,
+};
+use error::Error as ServerError;
+use failover::{Failover, Mode, PeerEvent, Role};
+use fingerprint::FingerprintDatabase;
+use forcerenew::Nonces;
+use fqdn::{DnsUpdate, FqdnPolicy};
+#[cfg(feature = "grpc-api")]
+use grpc_api::GrpcApi;
+use hook::Hook;
+use inbound::{InboundQueue, Received, DEFAULT_INBOUND_QUEUE_SIZE};
+use interface::Interface;
+use http::{HttpListener, Response};
+use lease::{Lease, INFINITE_LEASE_TIME};
+use limiter::RateLimiter;
+use metrics::{
+    Metrics, ERROR_ALLOCATION, ERROR_ASSIGNMENT, ERROR_CONFLICT, ERROR_RENEWAL, ERROR_STORAGE,
+    OPERATION_ASSIGN, OPERATION_OFFER, OPERATION_RENEW, RECLAMATION_IN_USE, RECLAMATION_RECLAIMED,
+    RECLAMATION_SILENT,
+};
+use observer::{LeaseEvent, Observer};
+#[cfg(target_os = "linux")]
+use packet::PacketSocket;
+#[cfg(unix)]
+use privileges::drop_privileges;
+use probe::Prober;
+use reload::{Reload, ReloadSource, Reloader};
+use reservation::{HostKey, Reservation, Reservations};
+use scope::{clamp_lease_time, SharedNetwork};
+use subnet::Subnet;
 use storage::Storage;
-use tokio::net::UdpSocket;
+use subscriber::SubscriberLimits;
+#[cfg(target_os = "linux")]
+use systemd::Systemd;
+use vendor::VendorClass;
+
+/// How long the reaper sleeps in seconds if nothing is scheduled to expire.
+const REAPER_IDLE_INTERVAL: u32 = 3600;
+/// How often the storage is compacted if some leases have expired in seconds.
+const COMPACTION_INTERVAL: u64 = 60 * 60;
+/// The messages read from the socket at once, so the timers are polled during a storm.
+const RECEIVE_BATCH: usize = 256;
+/// The options sent to the BOOTP clients, which cannot request them.
+const BOOTP_PARAMETER_LIST: &[u8] = &[
+    OptionTag::SubnetMask as u8,
+    OptionTag::Routers as u8,
+    OptionTag::DomainNameServers as u8,
+    OptionTag::Hostname as u8,
+    OptionTag::DomainName as u8,
+];
 
 /// Some options like `cpu_pool_size` are OS-specific, so the builder pattern is required.
 pub struct ServerBuilder<S>
@@ -32,6 +115,50 @@ where
     domain_name_servers: Vec<Ipv4Addr>,
     static_routes: Vec<(Ipv4Addr, Ipv4Addr)>,
     classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    reservations: Reservations,
+    subnets: Vec<Subnet>,
+    classes: Vec<Class>,
+    shared_networks: Vec<SharedNetwork>,
+    vendor_classes: Vec<VendorClass>,
+    access_list: Option<AccessList>,
+    boot: Option<Boot>,
+    bootp: bool,
+    fingerprints: Option<FingerprintDatabase>,
+    lease_times: (u32, u32),
+    dynamic_exclusions: Vec<(Ipv4Addr, Ipv4Addr)>,
+    allocator: Arc<Allocator>,
+    affinity_retention: u32,
+    grace_period: u32,
+    raw_options: Vec<(u8, Vec<u8>)>,
+    reloads: Option<Box<ReloadSource>>,
+    reloader: Option<Box<Reloader>>,
+    probes: Option<(Duration, usize)>,
+    probe_retries: u32,
+    reclamation_interval: Option<Duration>,
+    reclamation_quiet_period: Duration,
+    audit_interval: Option<Duration>,
+    observers: Vec<Box<Observer>>,
+    hooks: Vec<Box<Hook>>,
+    interfaces: Vec<Interface>,
+    failover: Option<(Role, Mode, SocketAddr, u32)>,
+    bulk_leasequery: Option<(SocketAddr, Vec<Ipv4Addr>)>,
+    admin_api: Option<(SocketAddr, Option<String>)>,
+    grpc_api: Option<(SocketAddr, Option<String>)>,
+    control_socket: Option<PathBuf>,
+    metrics: Option<SocketAddr>,
+    capture: Option<(PathBuf, u64, Duration, usize)>,
+    rate_limiter: RateLimiter,
+    subscriber_limits: SubscriberLimits,
+    inbound_queue_size: usize,
+    receive_batch: Option<usize>,
+    receive_ring: Option<usize>,
+    proxy_dhcp: bool,
+    device_binding: bool,
+    worker: Option<(u32, u32, WorkerState)>,
+    fqdn_policy: FqdnPolicy,
+    systemd: bool,
+    user: Option<(String, Option<String>)>,
+    chroot: Option<PathBuf>,
     #[allow(unused)]
     bpf_num_threads_size: Option<usize>,
 }
@@ -57,7 +184,7 @@ where
     /// An inclusive IPv4 address range. Gaps may be implemented later.
     ///
     /// * `storage`
-    /// The `Storage` trait object. The trait must be implemented by a crate user.
+    /// The `Storage` trait object. A `LeaseStorage` backend may be wrapped into `AsyncStorage`.
     ///
     /// * `subnet_mask`
     /// Static data for client configuration.
@@ -97,10 +224,465 @@ where
             domain_name_servers,
             static_routes,
             classless_static_routes,
+            reservations: Reservations::new(),
+            subnets: Vec::new(),
+            classes: Vec::new(),
+            shared_networks: Vec::new(),
+            vendor_classes: Vec::new(),
+            access_list: None,
+            boot: None,
+            bootp: false,
+            fingerprints: None,
+            lease_times: (DEFAULT_LEASE_TIME, MAX_LEASE_TIME),
+            dynamic_exclusions: Vec::new(),
+            allocator: Arc::new(SequentialAllocator),
+            affinity_retention: 0,
+            grace_period: 0,
+            raw_options: Vec::new(),
+            reloads: None,
+            reloader: None,
+            probes: None,
+            probe_retries: 0,
+            reclamation_interval: None,
+            reclamation_quiet_period: Duration::from_secs(0),
+            audit_interval: None,
+            observers: Vec::new(),
+            hooks: Vec::new(),
+            interfaces: Vec::new(),
+            failover: None,
+            bulk_leasequery: None,
+            admin_api: None,
+            grpc_api: None,
+            control_socket: None,
+            metrics: None,
+            capture: None,
+            rate_limiter: RateLimiter::default(),
+            subscriber_limits: SubscriberLimits::default(),
+            inbound_queue_size: DEFAULT_INBOUND_QUEUE_SIZE,
+            receive_batch: None,
+            receive_ring: None,
+            proxy_dhcp: false,
+            device_binding: false,
+            worker: None,
+            fqdn_policy: FqdnPolicy::default(),
+            systemd: false,
+            user: None,
+            chroot: None,
             bpf_num_threads_size: None,
         }
     }
 
+    /// Reserves a fixed address for the host.
+    ///
+    /// The reserved address is never allocated to other clients
+    /// and may be outside the static and dynamic ranges.
+    pub fn with_reservation(&mut self, key: HostKey, reservation: Reservation) -> &mut Self {
+        self.reservations.insert(key, reservation);
+        self
+    }
+
+    /// Adds a subnet with its own dynamic pools.
+    ///
+    /// The subnet is selected if the request relay agent information contains
+    /// one of its circuit or remote IDs, or if it contains `giaddr` of a relayed request
+    /// or the server address. The ranges passed to `new` are used otherwise.
+    pub fn with_subnet(&mut self, subnet: Subnet) -> &mut Self {
+        self.subnets.push(subnet);
+        self
+    }
+
+    /// Adds a client class.
+    ///
+    /// The classes are checked in the order they are added.
+    pub fn with_class(&mut self, class: Class) -> &mut Self {
+        self.classes.push(class);
+        self
+    }
+
+    /// Adds a shared network, whose options are inherited by its subnets.
+    pub fn with_shared_network(&mut self, shared_network: SharedNetwork) -> &mut Self {
+        self.shared_networks.push(shared_network);
+        self
+    }
+
+    /// Adds the option 43 payload sent to the clients of a vendor class.
+    ///
+    /// The first vendor class matching a request is used. Applied after the subnet
+    /// options, so a client class or a host may override it.
+    pub fn with_vendor_class(&mut self, vendor_class: VendorClass) -> &mut Self {
+        self.vendor_classes.push(vendor_class);
+        self
+    }
+
+    /// Restricts the clients allowed to get addresses outside the subnets with their own lists.
+    pub fn with_access_list(&mut self, access_list: AccessList) -> &mut Self {
+        self.access_list = Some(access_list);
+        self
+    }
+
+    /// Sets the network boot parameters of the clients outside the subnets with their own ones.
+    pub fn with_boot(&mut self, boot: Boot) -> &mut Self {
+        self.boot = Some(boot);
+        self
+    }
+
+    /// Makes the server answer the plain BOOTP requests of the clients with reservations.
+    ///
+    /// The reserved addresses are bound to the BOOTP clients forever.
+    pub fn with_bootp(&mut self) -> &mut Self {
+        self.bootp = true;
+        self
+    }
+
+    /// Finds the device types of the clients by their fingerprints, so the classes
+    /// may select them and the admin API may show them with the leases.
+    pub fn with_fingerprints(&mut self, fingerprints: FingerprintDatabase) -> &mut Self {
+        self.fingerprints = Some(fingerprints);
+        self
+    }
+
+    /// Sets the lease time used if a client does not request one
+    /// and the maximal lease time, both in seconds.
+    ///
+    /// The defaults are 24 hours and 1 week.
+    pub fn with_lease_times(&mut self, default_lease_time: u32, max_lease_time: u32) -> &mut Self {
+        self.lease_times = (default_lease_time, max_lease_time);
+        self
+    }
+
+    /// Excludes the inclusive ranges inside the dynamic range from the allocation,
+    /// e.g. for the printers and the gateways.
+    pub fn with_dynamic_exclusions(&mut self, exclusions: Vec<(Ipv4Addr, Ipv4Addr)>) -> &mut Self {
+        self.dynamic_exclusions = exclusions;
+        self
+    }
+
+    /// Sets the strategy of the dynamic range outside the subnets.
+    ///
+    /// The lowest free address is offered by default.
+    pub fn with_allocator(&mut self, allocator: Arc<Allocator>) -> &mut Self {
+        self.allocator = allocator;
+        self
+    }
+
+    /// Keeps the addresses of the expired and released leases for the returning clients
+    /// for `seconds`, unless the pools run out of the other addresses.
+    ///
+    /// Disabled by default.
+    pub fn with_affinity_retention(&mut self, seconds: u32) -> &mut Self {
+        self.affinity_retention = seconds;
+        self
+    }
+
+    /// Keeps the addresses of the expired and released leases from the other clients
+    /// for `seconds`, even if the pools run out of the other addresses, so a client
+    /// still using its expired lease does not clash with a new one.
+    ///
+    /// Disabled by default.
+    pub fn with_grace_period(&mut self, seconds: u32) -> &mut Self {
+        self.grace_period = seconds;
+        self
+    }
+
+    /// Sets the options without a typed field as `(tag, data)` pairs.
+    ///
+    /// An option is sent only if the client requests it.
+    pub fn with_raw_options(&mut self, raw_options: Vec<(u8, Vec<u8>)>) -> &mut Self {
+        self.raw_options = raw_options;
+        self
+    }
+
+    /// Sets the source of the configuration changes, e.g. `sighup_reloads`.
+    ///
+    /// The leases and the exchanges in progress survive the changes.
+    pub fn with_reload_source(&mut self, reloads: Box<ReloadSource>) -> &mut Self {
+        self.reloads = Some(reloads);
+        self
+    }
+
+    /// Sets the configuration loader used by the admin API, e.g. `file_reloader`.
+    pub fn with_reloader(&mut self, reloader: Box<Reloader>) -> &mut Self {
+        self.reloader = Some(reloader);
+        self
+    }
+
+    /// Enables probing the addresses with ICMP echo requests before offering them.
+    ///
+    /// The offer is sent if there is no reply in `timeout`. Otherwise the address
+    /// is marked as unavailable and the client gets another one when it retries.
+    /// At most `max_probes` probes run at the same time, the addresses are offered
+    /// without probing if the limit is reached.
+    pub fn with_probes(&mut self, timeout: Duration, max_probes: usize) -> &mut Self {
+        self.probes = Some((timeout, max_probes));
+        self
+    }
+
+    /// Sets how many times an echo request without a reply is repeated,
+    /// each one waiting for the probe timeout. No requests are repeated by default.
+    ///
+    /// Has no effect unless probing is enabled with `with_probes`.
+    pub fn with_probe_retries(&mut self, retries: u32) -> &mut Self {
+        self.probe_retries = retries;
+        self
+    }
+
+    /// Enables probing the abandoned addresses every `interval`.
+    ///
+    /// An address is abandoned if it has been declined by a client or found to be
+    /// in use by a probe. It returns to the pools once the probes have got no reply
+    /// for the quiet period set with `with_reclamation_quiet_period`.
+    /// Has no effect unless probing is enabled with `with_probes`.
+    pub fn with_reclamation_interval(&mut self, interval: Duration) -> &mut Self {
+        self.reclamation_interval = Some(interval);
+        self
+    }
+
+    /// Sets how long an abandoned address must stay silent before it is reclaimed.
+    ///
+    /// A reply to any probe during the period starts it over. The default zero
+    /// period reclaims an address after the first probe without a reply.
+    pub fn with_reclamation_quiet_period(&mut self, quiet_period: Duration) -> &mut Self {
+        self.reclamation_quiet_period = quiet_period;
+        self
+    }
+
+    /// Enables probing all the pool addresses every `interval`, looking for the hosts
+    /// using the addresses without leases and the leased addresses not replying.
+    ///
+    /// The report of the last audit is shown by the admin API.
+    /// Has no effect unless probing is enabled with `with_probes`.
+    pub fn with_audit_interval(&mut self, interval: Duration) -> &mut Self {
+        self.audit_interval = Some(interval);
+        self
+    }
+
+    /// Registers an observer notified about the lease lifecycle events.
+    pub fn with_observer(&mut self, observer: Box<Observer>) -> &mut Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Registers a hook run in the background on the lease lifecycle events.
+    pub fn with_hook(&mut self, hook: Box<Hook>) -> &mut Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Serves the interface along with the others with the same socket (Linux only).
+    ///
+    /// The requests from the interfaces which are not added are ignored.
+    pub fn with_interface(&mut self, interface: Interface) -> &mut Self {
+        self.interfaces.push(interface);
+        self
+    }
+
+    /// Pairs the server with a failover peer.
+    ///
+    /// The primary listens on the `address` and the secondary connects to it.
+    /// Until the peer acknowledges a lease of the client, and while the peer is unreachable,
+    /// the leases are granted for at most `mclt` seconds.
+    pub fn with_failover(
+        &mut self,
+        role: Role,
+        mode: Mode,
+        address: SocketAddr,
+        mclt: u32,
+    ) -> &mut Self {
+        self.failover = Some((role, mode, address, mclt));
+        self
+    }
+
+    /// Streams the leases over TCP to the bulk leasequery requesters (RFC 6926).
+    ///
+    /// Only the `requesters` may connect to the `address`, or everyone if the list is empty.
+    pub fn with_bulk_leasequery(
+        &mut self,
+        address: SocketAddr,
+        requesters: Vec<Ipv4Addr>,
+    ) -> &mut Self {
+        self.bulk_leasequery = Some((address, requesters));
+        self
+    }
+
+    /// Serves the HTTP admin API on the `address`.
+    ///
+    /// The requests must have the `token` as the bearer one if it is set.
+    /// The configuration is reloaded over the API only if a reloader is set.
+    pub fn with_admin_api(&mut self, address: SocketAddr, token: Option<String>) -> &mut Self {
+        self.admin_api = Some((address, token));
+        self
+    }
+
+    /// Serves the gRPC admin API on the `address`, only with the `grpc-api` feature.
+    ///
+    /// The calls must have the `token` as the bearer one if it is set.
+    pub fn with_grpc_api(&mut self, address: SocketAddr, token: Option<String>) -> &mut Self {
+        self.grpc_api = Some((address, token));
+        self
+    }
+
+    /// Serves the control socket at the `path`, Unix only.
+    pub fn with_control_socket(&mut self, path: PathBuf) -> &mut Self {
+        self.control_socket = Some(path);
+        self
+    }
+
+    /// Serves the Prometheus metrics on the `address` at `/metrics`.
+    pub fn with_metrics(&mut self, address: SocketAddr) -> &mut Self {
+        self.metrics = Some(address);
+        self
+    }
+
+    /// Records the received and sent DHCP messages to the pcapng files in the `directory`.
+    ///
+    /// A file is rotated when it exceeds `max_size` bytes or `max_age`,
+    /// and at most `max_files` newest files are kept.
+    pub fn with_capture(
+        &mut self,
+        directory: PathBuf,
+        max_size: u64,
+        max_age: Duration,
+        max_files: usize,
+    ) -> &mut Self {
+        self.capture = Some((directory, max_size, max_age, max_files));
+        self
+    }
+
+    /// Limits the requests of every client to `rate` per second with bursts of `burst`.
+    pub fn with_client_rate_limit(&mut self, rate: u32, burst: u32) -> &mut Self {
+        self.rate_limiter.set_client_limit(rate, burst);
+        self
+    }
+
+    /// Limits the requests relayed by every relay agent to `rate` per second
+    /// with bursts of `burst`.
+    pub fn with_relay_rate_limit(&mut self, rate: u32, burst: u32) -> &mut Self {
+        self.rate_limiter.set_relay_limit(rate, burst);
+        self
+    }
+
+    /// Limits all the requests to `rate` per second with bursts of `burst`.
+    ///
+    /// The requests exceeding the limit are dropped, bounding the work queued by a storm.
+    pub fn with_global_rate_limit(&mut self, rate: u32, burst: u32) -> &mut Self {
+        self.rate_limiter.set_global_limit(rate, burst);
+        self
+    }
+
+    /// Limits the clients leasing addresses behind every relay agent circuit ID
+    /// and every remote ID, e.g. the devices of an ISP subscriber.
+    ///
+    /// The `DHCPDISCOVER` messages of the other clients are dropped, while their
+    /// `DHCPREQUEST` messages are dropped or get `DHCPNAK` according to the `denied_action`.
+    pub fn with_subscriber_limits(
+        &mut self,
+        circuit_limit: Option<usize>,
+        remote_limit: Option<usize>,
+        denied_action: DeniedAction,
+    ) -> &mut Self {
+        self.subscriber_limits = SubscriberLimits::new(circuit_limit, remote_limit, denied_action);
+        self
+    }
+
+    /// Sets how many received messages may wait to be processed.
+    ///
+    /// Under load the `DHCPREQUEST` and the other messages of the clients having
+    /// addresses are processed first, and the excess `DHCPDISCOVER` messages are dropped.
+    pub fn with_inbound_queue_size(&mut self, size: usize) -> &mut Self {
+        self.inbound_queue_size = size;
+        self
+    }
+
+    /// Makes the server receive up to `size` datagrams with a single `recvmmsg` call
+    /// on Linux instead of a system call per datagram.
+    ///
+    /// Pays off under a heavy load like the boot storms and is ignored on other systems.
+    pub fn with_receive_batch(&mut self, size: usize) -> &mut Self {
+        self.receive_batch = Some(size);
+        self
+    }
+
+    /// Makes the server keep a `recvmsg` request in flight with `io_uring` for each of
+    /// the `size` receive buffers, which the kernel fills as the datagrams arrive.
+    ///
+    /// Requires the `io-uring-receive` feature and Linux. Takes precedence over
+    /// `with_receive_batch`, which is used instead if the kernel does not permit `io_uring`.
+    pub fn with_receive_ring(&mut self, size: usize) -> &mut Self {
+        self.receive_ring = Some(size);
+        self
+    }
+
+    /// Makes the server a ProxyDHCP one, which leaves the addresses to another DHCP server
+    /// and only offers the boot parameters to the PXE clients.
+    ///
+    /// The server also answers the PXE boot requests on port 4011.
+    pub fn with_proxy_dhcp(&mut self) -> &mut Self {
+        self.proxy_dhcp = true;
+        self
+    }
+
+    /// Binds the socket to the server interface, so the broadcasts leave through it
+    /// instead of the default route interface of a multi-homed host.
+    ///
+    /// The socket receives only through the interface then, so the relays must reach it there.
+    /// If the binding is not permitted or supported, the broadcasts are sent
+    /// over the raw socket of the interface.
+    pub fn with_device_binding(&mut self) -> &mut Self {
+        self.device_binding = true;
+        self
+    }
+
+    /// Makes the server the worker `index` of `count` ones sharing the port on Linux.
+    ///
+    /// The kernel spreads the requests across the workers by their sources, so every worker
+    /// may run on its own thread or in its own process. The workers must share the storage,
+    /// a `ShardedStorage` within a process or a `RedisStorage` or `SqliteStorage` across
+    /// the processes, and every one allocates only from its part of every range, like
+    /// a failover peer does, so they never offer the same address. Any worker renews any lease.
+    ///
+    /// The broadcast requests reach every worker, so each transaction is claimed in the storage
+    /// by the first one to get it, and the others drop the request.
+    ///
+    /// The workers of a process must be given the clones of one `state`, so any of them
+    /// reaps the leases allocated by the others. The worker processes have their own states.
+    ///
+    /// The admin API, the control socket and the metrics must be served by a single worker.
+    pub fn with_worker(&mut self, index: u32, count: u32, state: WorkerState) -> &mut Self {
+        self.worker = Some((index, count, state));
+        self
+    }
+
+    /// Sets how the client FQDN option is answered and which DNS records are updated.
+    pub fn with_fqdn_policy(&mut self, fqdn_policy: FqdnPolicy) -> &mut Self {
+        self.fqdn_policy = fqdn_policy;
+        self
+    }
+
+    /// Integrates the server with systemd on Linux.
+    ///
+    /// The DHCP and PXE sockets passed by the socket activation are used instead of binding
+    /// the ports, the readiness is reported once the pools are loaded and the server serves,
+    /// and the watchdog is pinged if `WatchdogSec` is set. Nothing is reported if the server
+    /// has not been started by systemd.
+    pub fn with_systemd(&mut self) -> &mut Self {
+        self.systemd = true;
+        self
+    }
+
+    /// Switches to the unprivileged user and its or the given group on Unix
+    /// once all the sockets are open, so the server is run by root only at startup.
+    pub fn with_user(&mut self, user: String, group: Option<String>) -> &mut Self {
+        self.user = Some((user, group));
+        self
+    }
+
+    /// Changes the root directory on Unix once all the sockets are open,
+    /// before switching to the unprivileged user.
+    pub fn with_chroot(&mut self, path: PathBuf) -> &mut Self {
+        self.chroot = Some(path);
+        self
+    }
+
     /// Sets the CPU pool size used for BPF communication.
     ///
     /// If not called during building, the BPF object will use its default pool size.
@@ -111,8 +693,34 @@ where
     }
 
     /// Consumes the builder and returns the built server.
-    pub fn finish(self) -> io::Result<Server<S>> {
-        Server::new(
+    ///
+    /// # Errors
+    /// `ServerError::Io` if the sockets or the listeners cannot be set up.
+    pub fn finish(self) -> Result<Server<S>, ServerError> {
+        self.finish_with_socket()
+    }
+
+    /// Consumes the builder and returns the server running over the transport,
+    /// e.g. `AsyncStdTransport` to be driven by the async-std reactor.
+    ///
+    /// The transports serve a single interface, since they cannot learn the interfaces
+    /// of the messages, and the broadcasts are sent raw if device binding is enabled.
+    ///
+    /// # Errors
+    /// `ServerError::Io` if the sockets or the listeners cannot be set up.
+    pub fn finish_with_transport<T>(self) -> Result<Server<S, TransportFramed<T>>, ServerError>
+    where
+        T: DhcpTransport,
+    {
+        self.finish_with_socket()
+    }
+
+    fn finish_with_socket<F>(self) -> Result<Server<S, F>, ServerError>
+    where
+        F: ServerSocket,
+    {
+        let probe_retries = self.probe_retries;
+        Ok(Server::new(
             self.server_ip_address,
             self.iface_name,
             self.static_address_range,
@@ -123,18 +731,83 @@ where
             self.domain_name_servers,
             self.static_routes,
             self.classless_static_routes,
+            self.reservations,
+            self.subnets,
+            self.classes,
+            self.shared_networks,
+            self.vendor_classes,
+            self.access_list,
+            self.boot,
+            self.bootp,
+            self.fingerprints,
+            self.lease_times,
+            self.dynamic_exclusions,
+            self.allocator,
+            self.affinity_retention,
+            self.grace_period,
+            self.raw_options,
+            self.reloads,
+            self.reloader,
+            self.probes.map(|(timeout, max_probes)| (timeout, max_probes, probe_retries)),
+            self.reclamation_interval,
+            self.reclamation_quiet_period,
+            self.audit_interval,
+            self.observers,
+            self.hooks,
+            self.interfaces,
+            self.failover,
+            self.bulk_leasequery,
+            self.admin_api,
+            self.grpc_api,
+            self.control_socket,
+            self.metrics,
+            self.capture,
+            self.rate_limiter,
+            self.subscriber_limits,
+            self.inbound_queue_size,
+            self.receive_batch,
+            self.receive_ring,
+            self.proxy_dhcp,
+            self.device_binding,
+            self.worker,
+            self.fqdn_policy,
+            self.systemd,
+            self.user,
+            self.chroot,
             self.bpf_num_threads_size,
-        )
+        )?)
     }
 }
 
+/// A response which is sent when the address probe finishes.
+struct PendingResponse {
+    response: Message,
+    destination: Ipv4Addr,
+    hw_unicast: bool,
+    max_size: Option<u16>,
+    ingress: Option<(u32, Interface)>,
+}
+
+/// The reason an address is probed for.
+enum Probed {
+    /// The address is about to be offered.
+    Offer(PendingResponse),
+    /// The address has been abandoned and is probed to be reclaimed.
+    Abandoned,
+    /// The address is probed by the conflict audit.
+    Audit,
+}
+
 /// The struct implementing the `Future` trait.
-pub struct Server<S>
+///
+/// Runs over `DhcpFramed` unless built with `ServerBuilder::finish_with_transport`.
+pub struct Server<S, F = DhcpFramed>
 where
     S: Storage,
+    F: ServerSocket,
 {
     /// The server UDP socket.
-    socket: DhcpFramed,
+    socket: F,
     /// The IP address the server is hosted on.
     server_ip_address: Ipv4Addr,
     /// The interface the server works on.
@@ -144,17 +817,112 @@ where
     builder: MessageBuilder,
     /// The DHCP database using a persistent storage object.
     database: Database<S>,
+    /// The static host reservations.
+    reservations: Reservations,
+    /// The client classes in the order of precedence.
+    classes: Vec<Class>,
+    /// The option scopes between the global and the subnet ones.
+    shared_networks: Vec<SharedNetwork>,
+    /// The option 43 payloads in the order of precedence.
+    vendor_classes: Vec<VendorClass>,
+    /// The clients allowed outside the subnets with their own lists.
+    access_list: Option<AccessList>,
+    /// The network boot parameters outside the subnets with their own ones.
+    boot: Option<Boot>,
+    /// Whether the plain BOOTP requests are answered.
+    bootp: bool,
+    fingerprints: Option<FingerprintDatabase>,
+    /// The device types of the clients found in the fingerprint database by the client IDs.
+    device_types: HashMap<Vec<u8>, String>,
+    /// The source of the configuration changes.
+    reloads: Option<Box<ReloadSource>>,
+    /// Loads the configuration on the admin API requests.
+    reloader: Option<Box<Reloader>>,
+    /// The offers and the abandoned addresses waiting for the address probes.
+    prober: Option<Prober<Probed>>,
+    /// Triggers probing the abandoned addresses.
+    reclamation: Option<Interval>,
+    /// How long an abandoned address must stay silent before it is reclaimed.
+    reclamation_quiet_period: Duration,
+    /// The abandoned addresses with the time of the first probe without a reply
+    /// since the last one with a reply.
+    silent_since: HashMap<Ipv4Addr, Instant>,
+    /// Triggers the conflict audit of the pool addresses.
+    audit_timer: Option<Interval>,
+    audit: Audit,
+    /// Notified about the lease lifecycle events.
+    observers: Vec<Box<Observer>>,
+    /// Run in the background on the lease lifecycle events.
+    hooks: Vec<Box<Hook>>,
+    /// The served interfaces with their indexes, the server address is used if empty.
+    interfaces: Vec<(u32, Interface)>,
+    /// The interface the request being processed has come from.
+    ingress: Option<(u32, Interface)>,
+    /// The connection to the failover peer.
+    failover: Option<Failover>,
+    /// The index of the worker if the server shares the port with the other ones.
+    worker: Option<u32>,
+    /// The connections of the bulk leasequery requesters.
+    bulk_leasequery: Option<BulkLeasequery>,
+    /// The connections of the admin API clients.
+    admin: Option<AdminApi>,
+    /// The listener of the gRPC admin API clients.
+    #[cfg(feature = "grpc-api")]
+    grpc: Option<GrpcApi>,
+    /// The connections of the control socket clients.
+    #[cfg(unix)]
+    control: Option<ControlSocket>,
+    /// Set by the `shutdown` command to stop the server.
+    is_shutdown_requested: bool,
+    /// Verifies the requests and signs the responses of the subnets with the keys.
+    authenticator: Authenticator,
+    /// Answers the client FQDN option and names the clients in DNS.
+    fqdn_policy: FqdnPolicy,
+    /// Authenticates the `DHCPFORCERENEW` messages to the clients of the subnets without the keys.
+    nonces: Nonces,
+    /// The counters and histograms exported to Prometheus.
+    metrics: Metrics,
+    /// The connections of the Prometheus scrapers.
+    metrics_listener: Option<HttpListener>,
+    /// Records the received and sent messages.
+    capture: Option<Capture>,
+    /// Drops the requests of the flooding clients and relays.
+    rate_limiter: RateLimiter,
+    /// Refuses the new clients behind the relay agent IDs having too many.
+    subscriber_limits: SubscriberLimits,
+    /// The received messages waiting to be processed.
+    inbound: InboundQueue,
+    /// Receives the PXE boot requests if the addresses are assigned by another server.
+    proxy: Option<F>,
+    /// Triggers expiring the offers and leases.
+    /// Fires at the next scheduled expiration.
+    reaper: Delay,
+    /// Triggers compacting the storage.
+    compaction: Interval,
+    /// Whether some leases have expired since the last compaction.
+    is_compaction_needed: bool,
     /// The asynchronous `netsh` processes used to work with ARP entries.
     #[cfg(target_os = "windows")]
     arp: Option<dhcp_arp::Arp>,
     /// The object encapsulating BPF functionality.
     #[cfg(any(target_os = "freebsd", target_os = "macos"))]
     bpf_data: BpfData,
+    /// The raw sockets sending the hardware unicasts with their interface names.
+    #[cfg(target_os = "linux")]
+    packet_sockets: Vec<(String, PacketSocket)>,
+    /// Whether the broadcasts are sent over the raw socket, since the socket
+    /// could not be bound to the interface.
+    #[allow(unused)]
+    broadcast_raw: bool,
+    /// Notifies the service manager about the server state.
+    #[cfg(target_os = "linux")]
+    systemd: Option<Systemd>,
 }
 
-impl<S> Server<S>
+impl<S, F> Server<S, F>
 where
     S: Storage,
+    F: ServerSocket,
 {
     /// Creates a server future.
     #[allow(unused_variables)]
@@ -169,98 +937,1565 @@ where
         domain_name_servers: Vec<Ipv4Addr>,
         static_routes: Vec<(Ipv4Addr, Ipv4Addr)>,
         classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+        reservations: Reservations,
+        subnets: Vec<Subnet>,
+        classes: Vec<Class>,
+        shared_networks: Vec<SharedNetwork>,
+        vendor_classes: Vec<VendorClass>,
+        access_list: Option<AccessList>,
+        boot: Option<Boot>,
+        bootp: bool,
+        fingerprints: Option<FingerprintDatabase>,
+        lease_times: (u32, u32),
+        dynamic_exclusions: Vec<(Ipv4Addr, Ipv4Addr)>,
+        allocator: Arc<Allocator>,
+        affinity_retention: u32,
+        grace_period: u32,
+        raw_options: Vec<(u8, Vec<u8>)>,
+        reloads: Option<Box<ReloadSource>>,
+        reloader: Option<Box<Reloader>>,
+        probes: Option<(Duration, usize, u32)>,
+        reclamation_interval: Option<Duration>,
+        reclamation_quiet_period: Duration,
+        audit_interval: Option<Duration>,
+        observers: Vec<Box<Observer>>,
+        hooks: Vec<Box<Hook>>,
+        interfaces: Vec<Interface>,
+        failover: Option<(Role, Mode, SocketAddr, u32)>,
+        bulk_leasequery: Option<(SocketAddr, Vec<Ipv4Addr>)>,
+        admin_api: Option<(SocketAddr, Option<String>)>,
+        grpc_api: Option<(SocketAddr, Option<String>)>,
+        control_socket: Option<PathBuf>,
+        metrics: Option<SocketAddr>,
+        capture: Option<(PathBuf, u64, Duration, usize)>,
+        rate_limiter: RateLimiter,
+        subscriber_limits: SubscriberLimits,
+        inbound_queue_size: usize,
+        receive_batch: Option<usize>,
+        receive_ring: Option<usize>,
+        proxy_dhcp: bool,
+        device_binding: bool,
+        worker: Option<(u32, u32, WorkerState)>,
+        fqdn_policy: FqdnPolicy,
+        systemd: bool,
+        user: Option<(String, Option<String>)>,
+        chroot: Option<PathBuf>,
         bpf_num_threads_size: Option<usize>,
     ) -> io::Result<Self> {
-        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), DHCP_PORT_SERVER);
-        let socket = UdpSocket::bind(&addr)?;
+        if let Some((index, count, _)) = worker {
+            if count == 0 || count > 100 || index >= count {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The worker index must be less than the count of at most 100 workers",
+                ));
+            }
+            if failover.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The workers cannot share the pools with a failover peer",
+                ));
+            }
+        }
+        #[cfg(target_os = "linux")]
+        let mut systemd = if systemd {
+            Some(Systemd::from_env())
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "linux"))]
+        {
+            if systemd {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The systemd integration is supported only on Linux",
+                ));
+            }
+        }
+        let (socket, proxy) = {
+            #[cfg(target_os = "linux")]
+            let mut open_socket = |addr: &SocketAddr| match systemd {
+                Some(ref mut systemd) => match systemd.take_socket(addr) {
+                    Some(socket) => Ok(socket),
+                    None => bind_socket(addr, worker.is_some()),
+                },
+                None => bind_socket(addr, worker.is_some()),
+            };
+            #[cfg(not(target_os = "linux"))]
+            let open_socket = |addr: &SocketAddr| bind_socket(addr, worker.is_some());
+
+            let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), DHCP_PORT_SERVER);
+            let socket = open_socket(&addr)?;
+            let proxy = if proxy_dhcp {
+                let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), DHCP_PORT_PXE);
+                Some(open_socket(&addr)?)
+            } else {
+                None
+            };
+            (socket, proxy)
+        };
         socket.set_broadcast(true)?;
 
-        let socket = DhcpFramed::new(socket)?;
+        let mut socket = F::from_std(socket)?;
+        let broadcast_raw = device_binding && !bind_to_device(&socket, &iface_name);
+        let interfaces = resolve_interfaces(&mut socket, interfaces)?;
+        if let Some(size) = receive_batch {
+            #[cfg(target_os = "linux")]
+            socket.enable_batching(size);
+            #[cfg(not(target_os = "linux"))]
+            warn!("The batched receiving of {} datagrams is supported only on Linux", size);
+        }
+        if let Some(size) = receive_ring {
+            if let Err(error) = socket.enable_ring(size) {
+                warn!("Unable to receive with io_uring: {}", error);
+            }
+        }
+        #[cfg(target_os = "linux")]
+        let packet_sockets = {
+            let mut names = vec![iface_name.to_owned()];
+            for &(_, ref interface) in interfaces.iter() {
+                if !names.contains(&interface.name) {
+                    names.push(interface.name.to_owned());
+                }
+            }
+            names
+                .into_iter()
+                .map(|name| {
+                    let socket = PacketSocket::new(&name)?;
+                    Ok((name, socket))
+                }).collect::<io::Result<Vec<(String, PacketSocket)>>>()?
+        };
         let hostname = hostname::get_hostname();
 
-        let builder = MessageBuilder::new(
-            server_ip_address,
-            hostname,
-            subnet_mask,
-            routers,
-            domain_name_servers,
-            static_routes,
-            classless_static_routes,
-        );
+        let mut builder = MessageBuilder::new(
+            server_ip_address,
+            hostname,
+            subnet_mask,
+            routers,
+            domain_name_servers,
+            static_routes,
+            classless_static_routes,
+        );
+        builder.set_raw_options(raw_options);
+
+        let mut database = Database::new(static_address_range, dynamic_address_range, storage);
+        database.set_reserved_addresses(reservations.addresses());
+        database.set_lease_times(lease_times.0, lease_times.1);
+        database.set_dynamic_exclusions(dynamic_exclusions);
+        database.set_allocator(allocator);
+        database.set_affinity_retention(affinity_retention);
+        database.set_grace_period(grace_period);
+        for subnet in subnets.into_iter() {
+            database.add_subnet(subnet);
+        }
+        database.set_shared_networks(
+            shared_networks
+                .iter()
+                .map(|network| network.subnets.to_owned())
+                .collect(),
+        );
+        if let Some((_, _, ref state)) = worker {
+            database.share_state(state.to_owned());
+        }
+        if let Err(error) = database.load_expirations() {
+            warn!("Lease expirations loading error: {}", error.to_string());
+        }
+        let proxy = match proxy {
+            Some(socket) => Some(F::from_std(socket)?),
+            None => None,
+        };
+        let failover = match failover {
+            Some((role, mode, address, mclt)) => {
+                let failover = Failover::new(role, mode, address, mclt)?;
+                let (from, to) = failover.pool_share();
+                database.set_pool_share(from, to);
+                Some(failover)
+            }
+            None => None,
+        };
+        if let Some((index, count, _)) = worker {
+            database.set_pool_share(index * 100 / count, (index + 1) * 100 / count);
+        }
+        let bulk_leasequery = match bulk_leasequery {
+            Some((address, requesters)) => Some(BulkLeasequery::new(address, requesters)?),
+            None => None,
+        };
+        let admin = match admin_api {
+            Some((address, token)) => Some(AdminApi::new(address, token)?),
+            None => None,
+        };
+        #[cfg(feature = "grpc-api")]
+        let grpc = match grpc_api {
+            Some((address, token)) => Some(GrpcApi::new(address, token)?),
+            None => None,
+        };
+        #[cfg(not(feature = "grpc-api"))]
+        {
+            if grpc_api.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The gRPC API requires the grpc-api feature",
+                ));
+            }
+        }
+        #[cfg(unix)]
+        let control = match control_socket {
+            Some(path) => Some(ControlSocket::new(path)?),
+            None => None,
+        };
+        #[cfg(not(unix))]
+        {
+            if control_socket.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The control socket is supported only on Unix",
+                ));
+            }
+        }
+        let metrics_listener = match metrics {
+            Some(address) => Some(HttpListener::new(address)?),
+            None => None,
+        };
+        let capture = match capture {
+            Some((directory, max_size, max_age, max_files)) => {
+                Some(Capture::new(directory, max_size, max_age, max_files)?)
+            }
+            None => None,
+        };
+        #[cfg(any(target_os = "freebsd", target_os = "macos"))]
+        let bpf_data = BpfData::new(&iface_name, bpf_num_threads_size)?;
+
+        #[cfg(unix)]
+        {
+            if user.is_some() || chroot.is_some() {
+                drop_privileges(user.as_ref(), chroot.as_ref())?;
+                info!(
+                    target: "audit",
+                    "The server runs as the user {} in the root {}",
+                    user.as_ref().map_or("root", |&(ref user, _)| user.as_str()),
+                    chroot.as_ref().map_or("/".into(), |path| path.to_string_lossy()),
+                );
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            if user.is_some() || chroot.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "Dropping the privileges is supported only on Unix",
+                ));
+            }
+        }
+
+        Ok(Server {
+            socket,
+            server_ip_address,
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
+            iface_name: iface_name.to_owned(),
+            builder,
+            database,
+            reservations,
+            classes,
+            shared_networks,
+            vendor_classes,
+            access_list,
+            boot,
+            bootp,
+            fingerprints,
+            device_types: HashMap::new(),
+            reloads,
+            reloader,
+            prober: probes.map(|(timeout, max_probes, retries)| {
+                Prober::new(timeout, max_probes, retries)
+            }),
+            reclamation: match (probes, reclamation_interval) {
+                (Some(_), Some(interval)) => Some(Interval::new(Instant::now() + interval, interval)),
+                _ => None,
+            },
+            reclamation_quiet_period,
+            silent_since: HashMap::new(),
+            audit_timer: match (probes, audit_interval) {
+                (Some(_), Some(interval)) => Some(Interval::new(Instant::now() + interval, interval)),
+                _ => None,
+            },
+            audit: Audit::new(),
+            observers,
+            hooks,
+            interfaces,
+            ingress: None,
+            failover,
+            worker: worker.map(|(index, _, _)| index),
+            bulk_leasequery,
+            admin,
+            #[cfg(feature = "grpc-api")]
+            grpc,
+            #[cfg(unix)]
+            control,
+            is_shutdown_requested: false,
+            authenticator: Authenticator::new(),
+            nonces: Nonces::new(),
+            fqdn_policy,
+            metrics: Metrics::new(),
+            metrics_listener,
+            capture,
+            rate_limiter,
+            subscriber_limits,
+            inbound: InboundQueue::new(inbound_queue_size),
+            proxy,
+            reaper: Delay::new(Instant::now()),
+            compaction: Interval::new(
+                Instant::now() + Duration::from_secs(COMPACTION_INTERVAL),
+                Duration::from_secs(COMPACTION_INTERVAL),
+            ),
+            is_compaction_needed: false,
+            #[cfg(target_os = "windows")]
+            arp: None,
+            #[cfg(any(target_os = "freebsd", target_os = "macos"))]
+            bpf_data,
+            #[cfg(target_os = "linux")]
+            packet_sockets,
+            broadcast_raw,
+            #[cfg(target_os = "linux")]
+            systemd,
+        })
+    }
+
+    /// The server address on the interface the request has come from.
+    fn local_address(&self) -> Ipv4Addr {
+        self.ingress
+            .as_ref()
+            .map_or(self.server_ip_address, |&(_, ref interface)| interface.address)
+    }
+
+    /// The subnet hint of the requests which have not been relayed.
+    fn local_subnet(&self) -> Ipv4Addr {
+        self.ingress
+            .as_ref()
+            .map_or(self.server_ip_address, |&(_, ref interface)| interface.subnet)
+    }
+
+    /// Finds the device type of the client in the fingerprint database.
+    ///
+    /// The type is remembered for the admin API and kept if a later request
+    /// has no fingerprint or an unknown one.
+    fn device_type(&mut self, request: &Message, client_id: &[u8]) -> Option<String> {
+        let found = self.fingerprints.as_ref().and_then(|fingerprints| {
+            request
+                .fingerprint()
+                .and_then(|fingerprint| fingerprints.device_type(&fingerprint).map(str::to_owned))
+        });
+        if let Some(device_type) = found {
+            if self.device_types.get(client_id) != Some(&device_type) {
+                debug!("The client {:?} is a {} device", client_id, device_type);
+                self.device_types.insert(client_id.to_vec(), device_type);
+            }
+        }
+        self.device_types.get(client_id).cloned()
+    }
+
+    /// The address of the client link the subnet is selected by.
+    ///
+    /// The relay link selection sub-option (RFC 3527) takes precedence over the subnet
+    /// selection option (RFC 3011), which takes precedence over `giaddr`. The responses
+    /// are still sent to `giaddr`.
+    fn link_address(&self, request: &Message) -> Ipv4Addr {
+        let link_selection = request
+            .options
+            .relay_agent_information
+            .as_ref()
+            .and_then(|information| information.link_selection())
+            .filter(|_| !request.gateway_ip_address.is_unspecified());
+        if let Some(address) = link_selection.or(request.options.subnet_selection) {
+            return address;
+        }
+        if request.gateway_ip_address.is_unspecified() {
+            self.local_subnet()
+        } else {
+            request.gateway_ip_address
+        }
+    }
+
+    /// Records the message if the capture is enabled.
+    fn capture(
+        &mut self,
+        source: SocketAddr,
+        destination: SocketAddr,
+        message: &Message,
+        max_size: Option<u16>,
+    ) {
+        if let Some(ref mut capture) = self.capture {
+            if let Err(error) = capture.record(&source, &destination, message, max_size) {
+                warn!("Capture error: {}", error);
+            }
+        }
+    }
+
+    /// Chooses the destination IP according to RFC 2131 rules.
+    ///
+    /// Injects an ARP entry in hardware unicast cases on Windows and sets the `arp` field
+    /// if ARP processing is expected to be too long for the tokio reactor.
+    /// The other systems send such responses with the client hardware address themselves.
+    /// The bool flag is `true` if hardware unicast is required.
+    fn destination(&mut self, request: &Message, response: &Message) -> (Ipv4Addr, bool) {
+        if !request.client_ip_address.is_unspecified() {
+            return (request.client_ip_address, false);
+        }
+
+        if request.is_broadcast {
+            return (Ipv4Addr::new(255, 255, 255, 255), false);
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            info!(
+                "Injecting an ARP entry {} -> {}",
+                request.client_hardware_address, response.your_ip_address,
+            );
+            match dhcp_arp::add(
+                request.client_hardware_address,
+                response.your_ip_address,
+                self.iface_name.to_owned(),
+            ) {
+                Ok(result) => {
+                    self.arp = Some(result);
+                }
+                Err(error) => error!("ARP error: {:?}", error),
+            }
+        }
+
+        (response.your_ip_address, true)
+
+        /*
+        RFC 2131 §4.1
+        If unicasting is not possible, the message
+        MAY be sent as an IP broadcast using an IP broadcast address
+        (preferably 0xffffffff) as the IP destination address and the link-
+        layer broadcast address as the link-layer destination address.
+
+        Note: I don't know yet when unicasting is not possible.
+        */
+    }
+
+    /// Applies the pending configuration changes.
+    ///
+    /// # Errors
+    /// `io::Error` if the reload source fails.
+    fn poll_reloads(&mut self) -> io::Result<()> {
+        loop {
+            let poll = match self.reloads {
+                Some(ref mut reloads) => reloads.poll()?,
+                None => return Ok(()),
+            };
+            match poll {
+                Async::Ready(Some(reload)) => self.reload(reload),
+                Async::Ready(None) => {
+                    self.reloads = None;
+                    return Ok(());
+                }
+                Async::NotReady => return Ok(()),
+            }
+        }
+    }
+
+    /// Replaces the settings, keeping the leases and the offers in the storage.
+    fn reload(&mut self, reload: Reload) {
+        if reload.server_ip_address != self.server_ip_address {
+            warn!(
+                "The server address change to {} requires a restart",
+                reload.server_ip_address
+            );
+        }
+
+        let mut reservations = Reservations::new();
+        for (key, reservation) in reload.reservations.into_iter() {
+            reservations.insert(key, reservation);
+        }
+        self.database.set_dynamic_exclusions(reload.dynamic_exclusions);
+        if let Err(error) = self.database.reconfigure(
+            reload.static_address_range,
+            reload.dynamic_address_range,
+            reload.subnets,
+            reservations.addresses(),
+        ) {
+            warn!("Lease checking error: {}", error.to_string());
+        }
+        self.database
+            .set_lease_times(reload.lease_times.0, reload.lease_times.1);
+        self.database.set_allocator(reload.allocator);
+        self.database
+            .set_affinity_retention(reload.affinity_retention);
+        self.database.set_grace_period(reload.grace_period);
+        self.builder.set_parameters(
+            reload.subnet_mask,
+            reload.routers,
+            reload.domain_name_servers,
+            reload.static_routes,
+            reload.classless_static_routes,
+        );
+        self.builder.set_raw_options(reload.raw_options);
+        self.reservations = reservations;
+        self.classes = reload.classes;
+        self.database.set_shared_networks(
+            reload
+                .shared_networks
+                .iter()
+                .map(|network| network.subnets.to_owned())
+                .collect(),
+        );
+        self.shared_networks = reload.shared_networks;
+        self.vendor_classes = reload.vendor_classes;
+        self.access_list = reload.access_list;
+        self.boot = reload.boot;
+        self.bootp = reload.bootp;
+        self.fingerprints = reload.fingerprints;
+        info!("The configuration has been reloaded");
+    }
+
+    /// Drains the socket into the inbound queue, dropping the messages the full queue sheds.
+    ///
+    /// Returns whether the socket has been drained, so the next message wakes the task up.
+    fn receive(&mut self) -> io::Result<bool> {
+        for _ in 0..RECEIVE_BATCH {
+            let (source, message) = match self.socket.poll() {
+                Ok(Async::Ready(Some(data))) => data,
+                Ok(Async::Ready(None)) => {
+                    warn!("Received an invalid packet");
+                    continue;
+                }
+                Ok(Async::NotReady) => return Ok(true),
+                Err(error) => {
+                    warn!("Socket error: {}", error);
+                    return Err(error);
+                }
+            };
+            log_receive!(message, source.ip());
+            self.metrics.received(&message);
+            let mut header = [0u8; OFFSET_SERVER_NAME];
+            header.copy_from_slice(self.socket.received_header());
+            let received = Received {
+                source,
+                message,
+                ingress_interface: self.socket.ingress_interface(),
+                destination_address: self.socket.destination_address(),
+                header,
+            };
+            if let Some(dropped) = self.inbound.push(received) {
+                debug!("Dropping a message from {} over the inbound queue size", dropped.source);
+                self.metrics.dropped(&dropped.message);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Starts probing the abandoned addresses on each reclamation timer tick.
+    ///
+    /// The addresses which are still in use wait for the next tick.
+    fn poll_reclamation(&mut self) {
+        loop {
+            match self.reclamation {
+                Some(ref mut reclamation) => match reclamation.poll() {
+                    Ok(Async::Ready(Some(_))) => {}
+                    Ok(Async::Ready(None)) | Ok(Async::NotReady) => return,
+                    Err(error) => {
+                        warn!("Reclamation timer error: {}", error);
+                        return;
+                    }
+                },
+                None => return,
+            }
+
+            let abandoned = match self.database.abandoned() {
+                Ok(abandoned) => abandoned,
+                Err(error) => {
+                    warn!("Abandoned addresses getting error: {}", error.to_string());
+                    continue;
+                }
+            };
+            // forget the addresses freed by other means
+            self.silent_since.retain(|address, _| abandoned.contains(address));
+            if let Some(ref mut prober) = self.prober {
+                for address in abandoned.into_iter() {
+                    if prober.start(address, Probed::Abandoned).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Starts the conflict audit on each audit timer tick and probes
+    /// the addresses of the audit in progress.
+    fn poll_audit(&mut self) {
+        loop {
+            match self.audit_timer {
+                Some(ref mut audit_timer) => match audit_timer.poll() {
+                    Ok(Async::Ready(Some(_))) => {}
+                    Ok(Async::Ready(None)) | Ok(Async::NotReady) => break,
+                    Err(error) => {
+                        warn!("Audit timer error: {}", error);
+                        break;
+                    }
+                },
+                None => return,
+            }
+
+            let ranges: Vec<(Ipv4Addr, Ipv4Addr)> = match self.database.utilization() {
+                Ok(utilization) => utilization
+                    .iter()
+                    .map(|pool| (pool.first, pool.last))
+                    .collect(),
+                Err(error) => {
+                    warn!("Pool utilization error: {}", error.to_string());
+                    continue;
+                }
+            };
+            match self.database.abandoned() {
+                Ok(abandoned) => self
+                    .audit
+                    .start(&ranges, &abandoned.into_iter().collect()),
+                Err(error) => {
+                    warn!("Abandoned addresses getting error: {}", error.to_string())
+                }
+            }
+        }
+
+        if let Some(ref mut prober) = self.prober {
+            // the other half is left to the offers
+            let max_probing = (prober.max_probes() / 2).max(1);
+            while let Some(address) = self.audit.next_address(max_probing) {
+                if prober.start(address, Probed::Audit).is_err() {
+                    self.audit.postpone(address);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Expires the offers and leases when the reaper timer fires at the next expiration
+    /// and compacts the storage on each compaction timer tick.
+    fn poll_reaper(&mut self) {
+        loop {
+            // the offers and leases made since the last call may expire earlier
+            let deadline = self.reaper_deadline();
+            if deadline < Delay::deadline(&self.reaper) {
+                self.reaper.reset(deadline);
+            }
+            match self.reaper.poll() {
+                Ok(Async::Ready(())) => {}
+                Ok(Async::NotReady) => break,
+                Err(error) => {
+                    warn!("Reaper timer error: {}", error);
+                    let deadline = self.reaper_deadline();
+                    self.reaper.reset(deadline);
+                    break;
+                }
+            }
+            match self.database.reap() {
+                Ok(expired) => for (client_id, address) in expired.into_iter() {
+                    info!("The lease of address {} has expired", address);
+                    self.is_compaction_needed = true;
+                    self.notify(LeaseEvent::Expired { client_id, address });
+                },
+                Err(error) => warn!("Lease reaping error: {}", error.to_string()),
+            }
+            let deadline = self.reaper_deadline();
+            self.reaper.reset(deadline);
+        }
+
+        loop {
+            match self.compaction.poll() {
+                Ok(Async::Ready(Some(_))) => {}
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => break,
+                Err(error) => {
+                    warn!("Compaction timer error: {}", error);
+                    break;
+                }
+            }
+            if !self.is_compaction_needed {
+                continue;
+            }
+            match self.database.compact() {
+                Ok(_) => {
+                    debug!("The storage has been compacted");
+                    self.is_compaction_needed = false;
+                }
+                Err(error) => warn!("Storage compaction error: {}", error.to_string()),
+            }
+        }
+    }
+
+    /// The instant of the next scheduled expiration, so the reaper wakes up only when needed.
+    fn reaper_deadline(&self) -> Instant {
+        let now = Utc::now().timestamp() as u32;
+        let delay = match self.database.next_expiration() {
+            Some(expires_at) => expires_at.saturating_sub(now).min(REAPER_IDLE_INTERVAL),
+            None => REAPER_IDLE_INTERVAL,
+        };
+        Instant::now() + Duration::from_secs(u64::from(delay))
+    }
+
+    /// Returns an offer whose address probe has got no reply.
+    ///
+    /// The offered addresses which are found to be in use are abandoned.
+    /// The abandoned addresses which are not in use anymore are reclaimed.
+    fn poll_probes(&mut self) -> Option<PendingResponse> {
+        loop {
+            let (address, probed, is_in_use) = match self.prober {
+                Some(ref mut prober) => prober.poll_finished()?,
+                None => return None,
+            };
+            match (probed, is_in_use) {
+                (Probed::Offer(pending), false) => return Some(pending),
+                (Probed::Offer(_), true) => {
+                    error!(
+                        "Address conflict: {} is in use by an unknown host and abandoned",
+                        address
+                    );
+                    self.metrics.error(ERROR_CONFLICT);
+                    match self.database.freeze(&address) {
+                        Ok(_) => self.notify(LeaseEvent::Conflict { address }),
+                        Err(error) => warn!("Address freezing error: {}", error.to_string()),
+                    }
+                }
+                (Probed::Abandoned, false) => {
+                    let now = Instant::now();
+                    let silent_since = *self.silent_since.entry(address).or_insert(now);
+                    if now.duration_since(silent_since) < self.reclamation_quiet_period {
+                        debug!("Abandoned address {} is silent", address);
+                        self.metrics.reclamation(RECLAMATION_SILENT);
+                        continue;
+                    }
+                    self.silent_since.remove(&address);
+                    match self.database.reclaim(&address) {
+                        Ok(_) => {
+                            info!("Abandoned address {} has been reclaimed", address);
+                            self.metrics.reclamation(RECLAMATION_RECLAIMED);
+                        }
+                        Err(error) => warn!("Address reclaiming error: {}", error.to_string()),
+                    }
+                }
+                (Probed::Abandoned, true) => {
+                    debug!("Abandoned address {} is still in use", address);
+                    self.silent_since.remove(&address);
+                    self.metrics.reclamation(RECLAMATION_IN_USE);
+                }
+                (Probed::Audit, is_in_use) => {
+                    let is_leased = match self.database.lease_by_address(&address) {
+                        Ok(lease) => Some(lease.map_or(false, |(_, lease)| lease.is_active())),
+                        Err(error) => {
+                            warn!("Lease getting error: {}", error.to_string());
+                            None
+                        }
+                    };
+                    self.audit.record(address, is_in_use, is_leased);
+                }
+            }
+        }
+    }
+
+    /// Notifies the observers about a lease lifecycle event.
+    /// Exchanges the lease updates with the failover peer.
+    fn poll_failover(&mut self) {
+        loop {
+            let event = match self.failover {
+                Some(ref mut failover) => failover.poll_peer(),
+                None => return,
+            };
+            match event {
+                Some(PeerEvent::Connected) => match self.database.leases() {
+                    Ok(leases) => if let Some(ref mut failover) = self.failover {
+                        for (client_id, lease) in leases.iter() {
+                            failover.send(client_id, lease);
+                        }
+                    },
+                    Err(error) => warn!("Lease getting error: {}", error.to_string()),
+                },
+                Some(PeerEvent::Update(client_id, lease)) => {
+                    trace!("The failover peer has updated the client {:?}", client_id);
+                    if let Err(error) = self.database.apply_lease(&client_id, lease) {
+                        warn!("Failover update applying error: {}", error.to_string());
+                    }
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Answers the bulk leasequeries received over TCP.
+    fn poll_bulk_leasequery(&mut self) {
+        loop {
+            let (id, query) = match self.bulk_leasequery {
+                Some(ref mut bulk_leasequery) => match bulk_leasequery.poll_query() {
+                    Some(query) => query,
+                    None => return,
+                },
+                None => return,
+            };
+            debug!("{}", query);
+            let responses = self.bulk_leasequery(&query);
+            trace!("Sending {} bulk leasequery responses", responses.len());
+            if let Some(ref mut bulk_leasequery) = self.bulk_leasequery {
+                bulk_leasequery.respond(id, responses);
+            }
+        }
+    }
+
+    /// Executes the admin API commands.
+    fn poll_admin(&mut self) {
+        loop {
+            let (id, command) = match self.admin {
+                Some(ref mut admin) => match admin.poll_command() {
+                    Some(command) => command,
+                    None => return,
+                },
+                None => return,
+            };
+            let response = self.execute(command);
+            if let Some(ref mut admin) = self.admin {
+                admin.respond(id, response);
+            }
+        }
+    }
+
+    /// Executes the gRPC API commands like the HTTP API ones.
+    #[cfg(feature = "grpc-api")]
+    fn poll_grpc(&mut self) {
+        loop {
+            let (id, command) = match self.grpc {
+                Some(ref mut grpc) => match grpc.poll_command() {
+                    Some(command) => command,
+                    None => return,
+                },
+                None => return,
+            };
+            let response = self.execute(command);
+            if let Some(ref mut grpc) = self.grpc {
+                grpc.respond(id, response);
+            }
+        }
+    }
+
+    /// Executes the control socket commands like the admin API ones.
+    #[cfg(unix)]
+    fn poll_control(&mut self) {
+        loop {
+            let (id, command) = match self.control {
+                Some(ref mut control) => match control.poll_command() {
+                    Some(command) => command,
+                    None => return,
+                },
+                None => return,
+            };
+            let response = self.execute(command);
+            if let Some(ref mut control) = self.control {
+                control.respond(id, response);
+            }
+        }
+    }
+
+    /// Answers the Prometheus scrapes.
+    fn poll_metrics(&mut self) {
+        loop {
+            let (id, request) = match self.metrics_listener {
+                Some(ref mut listener) => match listener.poll_request() {
+                    Some((id, _, request)) => (id, request),
+                    None => return,
+                },
+                None => return,
+            };
+            if let Some(ref capture) = self.capture {
+                self.metrics.buffer_pool(capture.buffer_stats());
+            }
+            let response = match (request.method.as_str(), request.path.as_str()) {
+                ("GET", "/metrics") => match self.database.utilization() {
+                    Ok(utilization) => Response::text(200, self.metrics.render(&utilization)),
+                    Err(error) => {
+                        warn!("Pool utilization error: {}", error.to_string());
+                        self.metrics.error(ERROR_STORAGE);
+                        Response::error(500, &error.to_string())
+                    }
+                },
+                (_, "/metrics") => Response::error(405, "Method not allowed"),
+                _ => Response::error(404, "Not found"),
+            };
+            if let Some(ref mut listener) = self.metrics_listener {
+                listener.respond(id, response);
+            }
+        }
+    }
+
+    /// Executes an admin API command.
+    ///
+    /// The deleted leases are released, so the failover peer and the observers learn about it.
+    fn execute(&mut self, command: Command) -> Response {
+        match command {
+            Command::Leases(filter) => match self.database.leases() {
+                Ok(leases) => {
+                    let device_types = &self.device_types;
+                    let records: Vec<LeaseRecord> = leases
+                        .iter()
+                        .filter_map(|&(ref client_id, ref lease)| {
+                            let hardware_address = client_hardware_address(client_id);
+                            let device_type = device_types.get(client_id).map(String::as_str);
+                            if filter.matches(client_id, hardware_address, device_type, lease) {
+                                Some(LeaseRecord::new(
+                                    client_id,
+                                    hardware_address,
+                                    device_type,
+                                    lease,
+                                ))
+                            } else {
+                                None
+                            }
+                        }).collect();
+                    Response::json(200, &records)
+                }
+                Err(error) => Response::error(500, &error.to_string()),
+            },
+            Command::DeleteLease(address) => {
+                let (client_id, lease) = match self.database.lease_by_address(&address) {
+                    Ok(Some(found)) => found,
+                    Ok(None) => return Response::error(404, "Lease not found"),
+                    Err(error) => return Response::error(500, &error.to_string()),
+                };
+                if let Err(error) = self.database.deallocate(&client_id, &address) {
+                    return Response::error(500, &error.to_string());
+                }
+                info!(target: "audit", "The lease of {} has been deleted", address);
+                self.nonces.remove(&client_id);
+                self.replicate(&client_id);
+                if lease.is_allocated() {
+                    self.notify(LeaseEvent::Released {
+                        client_id: client_id.to_owned(),
+                        address,
+                    });
+                }
+                Response::message(200, "The lease has been deleted")
+            }
+            Command::Pools => match self.database.utilization() {
+                Ok(utilization) => Response::json(200, &utilization),
+                Err(error) => Response::error(500, &error.to_string()),
+            },
+            Command::Audit => match self.audit.report() {
+                Some(report) => Response::json(200, report),
+                None => Response::error(404, "No address audit has finished"),
+            },
+            Command::ForceRenew(target) => self.force_renew(target),
+            Command::AddReservation(key, reservation) => {
+                info!(
+                    target: "audit",
+                    "The address {} has been reserved for {:?}",
+                    reservation.address,
+                    key,
+                );
+                self.reservations.insert(key, reservation);
+                self.database
+                    .set_reserved_addresses(self.reservations.addresses());
+                Response::message(201, "The reservation has been added")
+            }
+            Command::Reload => {
+                let result = match self.reloader {
+                    Some(ref reloader) => reloader(),
+                    None => return Response::error(501, "No configuration source is set"),
+                };
+                match result {
+                    Ok(reload) => {
+                        self.reload(reload);
+                        Response::message(200, "The configuration has been reloaded")
+                    }
+                    Err(error) => {
+                        error!("Configuration reloading error: {}", error);
+                        Response::error(500, &error.to_string())
+                    }
+                }
+            }
+            Command::Shutdown => {
+                info!(target: "audit", "The server shutdown has been requested");
+                self.is_shutdown_requested = true;
+                Response::message(200, "The server is shutting down")
+            }
+        }
+    }
+
+    /// Sends a `DHCPFORCERENEW` to the clients of the active leases of the target.
+    ///
+    /// The message must be authenticated (RFC 3203 §6), so it is signed with the subnet key
+    /// or the client nonce. The clients having neither are skipped.
+    fn force_renew(&mut self, target: RenewalTarget) -> Response {
+        let leases = match self.database.leases() {
+            Ok(leases) => leases,
+            Err(error) => return Response::error(500, &error.to_string()),
+        };
+        let targeted: Vec<(Vec<u8>, Ipv4Addr)> = match target {
+            RenewalTarget::Lease(address) => leases
+                .into_iter()
+                .filter(|&(_, ref lease)| lease.is_active() && lease.address() == address)
+                .map(|(client_id, lease)| (client_id, lease.address()))
+                .collect(),
+            RenewalTarget::Subnet(address) => {
+                let subnet = match self.database.subnet_by_address(&address) {
+                    Some(subnet) => subnet.to_owned(),
+                    None => return Response::error(404, "Subnet not found"),
+                };
+                leases
+                    .into_iter()
+                    .filter(|&(_, ref lease)| {
+                        lease.is_active() && subnet.contains(&lease.address())
+                    }).map(|(client_id, lease)| (client_id, lease.address()))
+                    .collect()
+            }
+        };
+        if let RenewalTarget::Lease(_) = target {
+            if targeted.is_empty() {
+                return Response::error(404, "Lease not found");
+            }
+        }
+
+        // the clients are unicasted from the main socket
+        self.ingress = None;
+        let mut report = ForceRenewReport {
+            sent: 0,
+            skipped: 0,
+        };
+        for (client_id, address) in targeted.into_iter() {
+            let hardware_address =
+                client_hardware_address(&client_id).unwrap_or_else(MacAddress::nil);
+            let mut message = self.builder.dhcp_forcerenew(
+                &client_id,
+                address,
+                hardware_address,
+                rand::thread_rng().gen(),
+            );
+            // the placeholder is signed with the subnet key while being sent
+            let key_option = self
+                .database
+                .subnets()
+                .iter()
+                .find(|subnet| subnet.contains(&address))
+                .and_then(|subnet| subnet.authentication.as_ref())
+                .map(|key| key.option(0));
+            let is_authenticated = match key_option {
+                Some(option) => {
+                    message.options.authentication = Some(option);
+                    true
+                }
+                None => match self.nonces.sign(&client_id, &mut message) {
+                    Ok(is_signed) => is_signed,
+                    Err(error) => {
+                        warn!("Forcerenew signing error: {}", error);
+                        false
+                    }
+                },
+            };
+            if !is_authenticated {
+                report.skipped += 1;
+                continue;
+            }
+            if let Err(error) = self.send_response(message, address, false, None) {
+                warn!("Forcerenew sending error: {}", error);
+                report.skipped += 1;
+                continue;
+            }
+            report.sent += 1;
+        }
+
+        info!(
+            target: "audit",
+            "The forcerenew has been sent to {} clients, {} skipped",
+            report.sent,
+            report.skipped,
+        );
+        Response::json(200, &report)
+    }
+
+    /// Sends the current lease of the client to the failover peer.
+    fn replicate(&mut self, client_id: &[u8]) {
+        let failover = match self.failover {
+            Some(ref mut failover) => failover,
+            None => return,
+        };
+        match self.database.lease(client_id) {
+            Ok(Some(lease)) => failover.send(client_id, &lease),
+            Ok(None) => {}
+            Err(error) => warn!("Lease getting error: {}", error.to_string()),
+        }
+    }
+
+    /// Answers a plain BOOTP request with the address reserved for the client.
+    ///
+    /// The clients without reservations are ignored, since the addresses bound to them
+    /// could never be reclaimed.
+    fn process_bootp(&mut self, request: Message) -> io::Result<()> {
+        if !self.bootp || self.proxy.is_some() {
+            return Ok(());
+        }
+
+        // the BOOTP clients cannot request options, so they get the usual ones
+        let mut request = request;
+        if request.options.parameter_list.is_none() {
+            request.options.parameter_list = Some(BOOTP_PARAMETER_LIST.to_vec());
+        }
+        let client_id = match request.options.client_id {
+            Some(ref client_id) => client_id.to_owned(),
+            None => request.client_hardware_address.as_bytes().to_vec(),
+        };
+        let relay = if request.gateway_ip_address.is_unspecified() {
+            None
+        } else {
+            Some(request.gateway_ip_address)
+        };
+        if !self.rate_limiter.allows(&client_id, relay) {
+            debug!("Dropping a request of the client {:?} over the rate limit", client_id);
+            return Ok(());
+        }
+        let reservation = match self.reservations.find(&request).cloned() {
+            Some(reservation) => reservation,
+            None => {
+                debug!(
+                    "Ignoring the BOOTP client {} without a reservation",
+                    request.client_hardware_address
+                );
+                return Ok(());
+            }
+        };
+        let subnet = self
+            .database
+            .subnet(
+                &self.link_address(&request),
+                request.options.relay_agent_information.as_ref(),
+            ).cloned();
+        let is_denied = subnet
+            .as_ref()
+            .and_then(|subnet| subnet.access_list.as_ref())
+            .or_else(|| self.access_list.as_ref())
+            .map_or(false, |access_list| {
+                !access_list.permits(&request.client_hardware_address)
+            });
+        if is_denied {
+            info!(
+                target: "audit",
+                "The client {} has been denied: BOOTREQUEST",
+                request.client_hardware_address,
+            );
+            return Ok(());
+        }
+
+        if let Err(error) = self.database.bind_bootp(&client_id, &reservation.address) {
+            warn!("BOOTP binding error: {}", error.to_string());
+            return Ok(());
+        }
+        self.replicate(&client_id);
+        self.notify(LeaseEvent::Committed {
+            client_id: client_id.to_owned(),
+            address: reservation.address,
+            hostname: reservation.hostname.to_owned(),
+            dns_update: DnsUpdate::Both,
+            lease_time: INFINITE_LEASE_TIME,
+        });
+        let mut response = self
+            .builder
+            .bootp_request_to_reply(&request, reservation.address);
+        self.apply_parameters(&request, &mut response, &Some(reservation), &subnet, &None);
+        let (destination, hw_unicast) = self.destination(&request, &response);
+        self.send_response(response, destination, hw_unicast, None)
+    }
+
+    /// Answers a `DHCPLEASEQUERY` from the lease database (RFC 4388).
+    ///
+    /// The query by `ciaddr` is preferred over the one by the client ID,
+    /// which is preferred over the one by the hardware address. The latter also finds
+    /// the clients identified by the hardware type and address, which is the usual client ID.
+    fn leasequery(&self, query: &Message) -> Result<Message, DatabaseError> {
+        let by_address = !query.client_ip_address.is_unspecified();
+        let found = if by_address {
+            self.database.lease_by_address(&query.client_ip_address)?
+        } else {
+            let mut found = None;
+            for client_id in queried_client_ids(query).into_iter() {
+                if let Some(lease) = self.database.lease(&client_id)? {
+                    found = Some((client_id, lease));
+                    break;
+                }
+            }
+            found
+        };
+
+        let (client_id, lease) = match found {
+            Some((ref client_id, ref lease)) if lease.is_active() => (client_id, lease),
+            Some((_, ref lease)) => {
+                let mut response = self
+                    .builder
+                    .dhcp_leasequery_to_response(query, MessageType::DhcpLeaseUnassigned);
+                response.client_ip_address = lease.address();
+                return Ok(response);
+            }
+            None => {
+                let dhcp_message_type =
+                    if by_address && self.database.is_address_managed(&query.client_ip_address) {
+                        MessageType::DhcpLeaseUnassigned
+                    } else {
+                        MessageType::DhcpLeaseUnknown
+                    };
+                return Ok(self
+                    .builder
+                    .dhcp_leasequery_to_response(query, dhcp_message_type));
+            }
+        };
+
+        let mut response = self
+            .builder
+            .dhcp_leasequery_to_response(query, MessageType::DhcpLeaseActive);
+        apply_lease(&mut response, client_id, lease);
+        if !by_address {
+            response.options.associated_ip = Some(vec![lease.address()]);
+        }
+        Ok(response)
+    }
+
+    /// Answers a `DHCPBULKLEASEQUERY` with the matching leases followed by
+    /// a `DHCPLEASEQUERYDONE` (RFC 6926).
+    ///
+    /// The leases are queried by the client ID or the hardware address like in `leasequery`,
+    /// or all of them if neither is set. The relay agent queries are not supported,
+    /// since the relay agent information is not stored with the leases.
+    fn bulk_leasequery(&self, query: &Message) -> Vec<Message> {
+        let now = Utc::now().timestamp() as u32;
+        let mut done = self
+            .builder
+            .dhcp_leasequery_to_response(query, MessageType::DhcpLeaseQueryDone);
+        done.options.base_time = Some(now);
+
+        let status = match query.validate() {
+            Ok(MessageType::DhcpBulkLeaseQuery) => {
+                if query.options.relay_agent_information.is_some() {
+                    Some((STATUS_MALFORMED_QUERY, "Relay agent queries are not supported"))
+                } else {
+                    None
+                }
+            }
+            _ => Some((STATUS_MALFORMED_QUERY, "Not a bulk leasequery")),
+        };
+        if let Some((code, message)) = status {
+            done.options.status_code = Some(StatusCode {
+                code,
+                message: message.to_owned(),
+            });
+            return vec![done];
+        }
+        let leases = match self.database.leases() {
+            Ok(leases) => leases,
+            Err(error) => {
+                warn!("Lease getting error: {}", error.to_string());
+                done.options.status_code = Some(StatusCode {
+                    code: STATUS_UNSPEC_FAIL,
+                    message: error.to_string(),
+                });
+                return vec![done];
+            }
+        };
+
+        let client_ids = queried_client_ids(query);
+        let mut responses = Vec::new();
+        for (client_id, lease) in leases.iter() {
+            if lease.is_offered() || !(client_ids.is_empty() || client_ids.contains(client_id)) {
+                continue;
+            }
+            let updated_at = lease.updated_at();
+            let is_queried = query
+                .options
+                .query_start_time
+                .map_or(true, |start_time| updated_at >= start_time)
+                && query
+                    .options
+                    .query_end_time
+                    .map_or(true, |end_time| updated_at <= end_time);
+            if !is_queried {
+                continue;
+            }
 
-        let database = Database::new(static_address_range, dynamic_address_range, storage);
+            let mut response = if lease.is_active() {
+                let mut response = self
+                    .builder
+                    .dhcp_leasequery_to_response(query, MessageType::DhcpLeaseActive);
+                apply_lease(&mut response, client_id, lease);
+                response.options.dhcp_state = Some(DHCP_STATE_ACTIVE);
+                response.options.start_time_of_state = Some(now.saturating_sub(updated_at));
+                response
+            } else {
+                let mut response = self
+                    .builder
+                    .dhcp_leasequery_to_response(query, MessageType::DhcpLeaseUnassigned);
+                apply_client(&mut response, client_id, lease);
+                response.options.dhcp_state = Some(if lease.is_released() {
+                    DHCP_STATE_RELEASED
+                } else {
+                    DHCP_STATE_EXPIRED
+                });
+                response
+            };
+            response.options.base_time = Some(now);
+            responses.push(response);
+        }
+        responses.push(done);
+        responses
+    }
 
-        Ok(Server {
-            socket,
-            server_ip_address,
-            #[cfg(any(target_os = "windows", target_os = "linux"))]
-            iface_name: iface_name.to_owned(),
-            builder,
-            database,
-            #[cfg(target_os = "windows")]
-            arp: None,
-            #[cfg(any(target_os = "freebsd", target_os = "macos"))]
-            bpf_data: BpfData::new(&iface_name, bpf_num_threads_size)?,
-        })
+    /// Selects the boot parameters of the subnet or the server-wide ones.
+    fn boot_parameters(
+        &self,
+        request: &Message,
+        subnet: &Option<Subnet>,
+    ) -> Option<BootParameters> {
+        subnet
+            .as_ref()
+            .and_then(|subnet| subnet.boot.as_ref())
+            .or_else(|| self.boot.as_ref())
+            .map(|boot| boot.select(request.options.client_architecture.as_ref()))
     }
 
-    /// Chooses the destination IP according to RFC 2131 rules.
+    /// Answers the PXE boot requests sent to the ProxyDHCP port.
     ///
-    /// Performs the ARP query in hardware unicast cases and sets the `arp` field
-    /// if ARP processing is expected to be too long for the tokio reactor.
-    /// The bool flag is `true` if hardware unicast is required.
-    fn destination(&mut self, request: &Message, response: &Message) -> (Ipv4Addr, bool) {
-        if !request.client_ip_address.is_unspecified() {
-            return (request.client_ip_address, false);
+    /// The clients send them with their addresses already assigned, so the replies are unicast.
+    fn poll_proxy(&mut self) -> io::Result<()> {
+        loop {
+            let (addr, request) = {
+                let proxy = match self.proxy {
+                    Some(ref mut proxy) => proxy,
+                    None => return Ok(()),
+                };
+                if let Async::NotReady = proxy.poll_complete()? {
+                    return Ok(());
+                }
+                match proxy.poll()? {
+                    Async::Ready(Some(data)) => data,
+                    Async::Ready(None) => {
+                        warn!("Received an invalid packet");
+                        continue;
+                    }
+                    Async::NotReady => return Ok(()),
+                }
+            };
+            log_receive!(request, addr);
+            self.metrics.received(&request);
+            let local = SocketAddr::new(IpAddr::V4(self.server_ip_address), DHCP_PORT_PXE);
+            self.capture(addr, local, &request, None);
+            match validate!(request, addr.ip(), self.metrics) {
+                MessageType::DhcpRequest | MessageType::DhcpInform => {}
+                _ => continue,
+            }
+            if !is_pxe_client(&request) {
+                continue;
+            }
+            if let Some(dhcp_server_id) = request.options.dhcp_server_id {
+                if dhcp_server_id != self.server_ip_address {
+                    continue;
+                }
+            }
+
+            let subnet = self
+                .database
+                .subnet(
+                    &request.client_ip_address,
+                    request.options.relay_agent_information.as_ref(),
+                ).cloned();
+            let parameters = match self.boot_parameters(&request, &subnet) {
+                Some(parameters) => parameters,
+                None => {
+                    debug!("No boot parameters for the client {}", addr);
+                    continue;
+                }
+            };
+            let mut response = self.builder.proxy_response(&request, MessageType::DhcpAck);
+            self.builder.apply_boot(&request, &mut response, &parameters);
+            log_send!(response, addr);
+            self.metrics.sent(&response);
+            let max_size = request.options.dhcp_max_message_size;
+            self.capture(local, addr, &response, max_size);
+            if let Some(ref mut proxy) = self.proxy {
+                start_send!(proxy, addr, response, max_size);
+            }
         }
+    }
 
-        if request.is_broadcast {
-            return (Ipv4Addr::new(255, 255, 255, 255), false);
+    /// Applies the boot, reservation and subnet parameters in the order of precedence
+    /// and the options resolved from the global scope down to the host one.
+    fn apply_parameters(
+        &self,
+        request: &Message,
+        response: &mut Message,
+        reservation: &Option<Reservation>,
+        subnet: &Option<Subnet>,
+        class: &Option<Class>,
+    ) {
+        if let Some(parameters) = self.boot_parameters(request, subnet) {
+            self.builder.apply_boot(request, response, &parameters);
+        }
+        if let Some(ref reservation) = *reservation {
+            self.builder.apply_reservation(response, reservation);
+        }
+        if let Some(ref subnet) = *subnet {
+            // the option identifies the key the response is signed with when sent
+            if let (Some(key), Some(_)) =
+                (subnet.authentication.as_ref(), response.options.dhcp_message_type)
+            {
+                response.options.authentication = Some(key.option(0));
+            }
         }
+        // the address may have been allocated in another subnet of the shared network
+        let address = response.your_ip_address;
+        let subnet = subnet.as_ref().map(|subnet| {
+            self.database
+                .link_subnets(subnet)
+                .into_iter()
+                .find(|member| member.contains(&address))
+                .unwrap_or(subnet)
+        });
+        if let Some(subnet) = subnet {
+            self.builder.apply_subnet(request, response, subnet);
+        }
+        let renewal_times = reservation
+            .as_ref()
+            .and_then(|reservation| reservation.renewal_times)
+            .or_else(|| class.as_ref().and_then(|class| class.renewal_times))
+            .or_else(|| subnet.and_then(|subnet| subnet.renewal_times));
+        if let (Some((renewal_time, rebinding_time)), Some(lease_time), Some(_)) = (
+            renewal_times,
+            response.options.address_time,
+            response.options.renewal_time,
+        ) {
+            // the times proportional to the lease are kept if it is too short
+            if rebinding_time < lease_time {
+                response.options.renewal_time = Some(renewal_time);
+                response.options.rebinding_time = Some(rebinding_time);
+            }
+        }
+        let routes = match (class, subnet) {
+            (&Some(ref class), _) if !class.classless_static_routes.is_empty() => {
+                &class.classless_static_routes[..]
+            }
+            (_, Some(subnet)) => &subnet.classless_static_routes[..],
+            _ => &[][..],
+        };
+        self.builder.apply_routes(request, response, routes);
 
-        #[cfg(any(target_os = "linux", target_os = "windows"))]
-        {
-            info!(
-                "Injecting an ARP entry {} -> {}",
-                request.client_hardware_address, response.your_ip_address,
-            );
-            match dhcp_arp::add(
-                request.client_hardware_address,
-                response.your_ip_address,
-                self.iface_name.to_owned(),
-            ) {
-                #[cfg(target_os = "windows")]
-                Ok(result) => {
-                    self.arp = Some(result);
-                }
-                Err(error) => error!("ARP error: {:?}", error),
-                _ => {}
+        let mut options = self.builder.global_options();
+        if let Some(subnet) = subnet {
+            if let Some(network) = self
+                .shared_networks
+                .iter()
+                .find(|network| network.contains(&subnet.address))
+            {
+                network.options.apply(&mut options);
             }
+            subnet.options.apply(&mut options);
+        }
+        if let Some(vendor_class) = self
+            .vendor_classes
+            .iter()
+            .find(|vendor_class| vendor_class.matches(request))
+        {
+            vendor_class.apply(&mut options);
+        }
+        if let Some(ref class) = *class {
+            class.options.apply(&mut options);
+        }
+        if let Some(ref reservation) = *reservation {
+            reservation.options.apply(&mut options);
+        }
+        self.builder.apply_options(request, response, &options);
+        if response.options.dhcp_message_type.is_some() {
+            // the option must be echoed to the clients sending it (RFC 3011 §3)
+            response.options.subnet_selection = request.options.subnet_selection;
         }
 
-        (response.your_ip_address, true)
+        match response.options.dhcp_message_type {
+            Some(MessageType::DhcpOffer) | Some(MessageType::DhcpAck)
+                if !response.your_ip_address.is_unspecified() =>
+            {
+                response.options.client_fqdn = self.fqdn_policy.reply(
+                    request,
+                    reserved_hostname(reservation),
+                    &response.your_ip_address,
+                );
+            }
+            _ => {}
+        }
+    }
 
-        /*
-        RFC 2131 §4.1
-        If unicasting is not possible, the message
-        MAY be sent as an IP broadcast using an IP broadcast address
-        (preferably 0xffffffff) as the IP destination address and the link-
-        layer broadcast address as the link-layer destination address.
+    /// Returns the host name the client is registered in DNS with and the records updated.
+    fn registration(
+        &self,
+        request: &Message,
+        reservation: &Option<Reservation>,
+        address: &Ipv4Addr,
+    ) -> (Option<String>, DnsUpdate) {
+        match self
+            .fqdn_policy
+            .registration(request, reserved_hostname(reservation), address)
+        {
+            Some((hostname, dns_update)) => (Some(hostname), dns_update),
+            None => (None, DnsUpdate::Both),
+        }
+    }
 
-        Note: I don't know yet when unicasting is not possible.
-        */
+    fn notify(&self, event: LeaseEvent) {
+        for observer in self.observers.iter() {
+            observer.on_lease_event(&event);
+        }
+        #[cfg(feature = "grpc-api")]
+        {
+            if let Some(ref grpc) = self.grpc {
+                grpc.watch(&event);
+            }
+        }
+        for hook in self.hooks.iter() {
+            tokio::spawn(
+                hook.on_lease_event(&event)
+                    .map_err(|error| warn!("Lease hook error: {}", error)),
+            );
+        }
     }
 
     /// Sends a response using OS-specific features.
     #[allow(unused)]
     fn send_response(
         &mut self,
-        response: Message,
+        mut response: Message,
         destination: Ipv4Addr,
         hw_unicast: bool,
         max_size: Option<u16>,
     ) -> io::Result<()> {
+        if let Some((index, ref interface)) = self.ingress {
+            if response.options.dhcp_server_id.is_some() {
+                response.options.dhcp_server_id = Some(interface.address);
+            }
+            self.socket.set_egress_interface(Some(index));
+            // the clients send the unicast requests to the address the replies come from
+            self.socket.set_source_address(Some(interface.address));
+        }
+        let key = response.options.authentication.as_ref().and_then(|authentication| {
+            self.database
+                .subnets()
+                .iter()
+                .filter_map(|subnet| subnet.authentication.as_ref())
+                .find(|key| key.identifies(authentication))
+                .cloned()
+        });
+        if let Some(key) = key {
+            if let Err(error) = self.authenticator.sign(&key, &mut response, max_size) {
+                warn!("Response signing error: {}", error);
+            }
+        }
         log_send!(response, destination);
+        self.metrics.sent(&response);
+        self.capture(
+            SocketAddr::new(IpAddr::V4(self.local_address()), DHCP_PORT_SERVER),
+            SocketAddr::new(IpAddr::V4(destination), DHCP_PORT_CLIENT),
+            &response,
+            max_size,
+        );
+
+        // the raw broadcasts are sent to the client hardware address, which it accepts as well
+        let hw_unicast = hw_unicast || (self.broadcast_raw && destination.is_broadcast());
 
         #[cfg(any(target_os = "freebsd", target_os = "macos"))]
         {
@@ -274,20 +2509,38 @@ where
             }
         }
 
+        #[cfg(target_os = "linux")]
+        {
+            if hw_unicast {
+                let iface_name = self
+                    .ingress
+                    .as_ref()
+                    .map_or(&self.iface_name, |&(_, ref interface)| &interface.name);
+                if let Some(&(_, ref socket)) = self
+                    .packet_sockets
+                    .iter()
+                    .find(|&&(ref name, _)| name == iface_name)
+                {
+                    return socket.send(&self.local_address(), &destination, response, max_size);
+                }
+            }
+        }
+
         let destination = SocketAddr::new(IpAddr::V4(destination), DHCP_PORT_CLIENT);
         start_send!(self.socket, destination, response, max_size);
         Ok(())
     }
 }
 
-impl<S> Future for Server<S>
+impl<S, F> Future for Server<S, F>
 where
     S: Storage,
+    F: ServerSocket,
 {
     type Item = ();
     type Error = io::Error;
 
-    /// Works infinite time.
+    /// Works until the shutdown is requested over the control socket.
     ///
     /// [RFC 2131](https://tools.ietf.org/html/rfc2131)
     fn poll(&mut self) -> Poll<(), io::Error> {
@@ -296,13 +2549,88 @@ where
             {
                 poll_arp!(self.arp);
             }
+            self.poll_reloads()?;
+            self.poll_reclamation();
+            self.poll_audit();
+            self.poll_reaper();
+            self.poll_failover();
+            self.poll_bulk_leasequery();
+            self.poll_admin();
+            #[cfg(feature = "grpc-api")]
+            {
+                self.poll_grpc();
+            }
+            #[cfg(unix)]
+            {
+                self.poll_control();
+            }
+            #[cfg(target_os = "linux")]
+            {
+                if let Some(ref mut systemd) = self.systemd {
+                    systemd.ready();
+                    systemd.poll_watchdog();
+                }
+            }
+            if self.is_shutdown_requested {
+                #[cfg(target_os = "linux")]
+                {
+                    if let Some(ref systemd) = self.systemd {
+                        systemd.stopping();
+                    }
+                }
+                info!("The server has been shut down");
+                return Ok(Async::Ready(()));
+            }
+            self.poll_metrics();
+            self.poll_proxy()?;
             poll_complete!(self.socket);
-            let (addr, request) = poll!(self.socket);
-            log_receive!(request, addr.ip());
-            let dhcp_message_type = validate!(request, addr.ip());
+            if let Some(pending) = self.poll_probes() {
+                self.ingress = pending.ingress;
+                self.send_response(
+                    pending.response,
+                    pending.destination,
+                    pending.hw_unicast,
+                    pending.max_size,
+                )?;
+                continue;
+            }
+            let is_drained = self.receive()?;
+            let Received {
+                source: addr,
+                message: request,
+                ingress_interface: index,
+                destination_address: destination,
+                header,
+            } = match self.inbound.pop() {
+                Some(received) => received,
+                None if is_drained => return Ok(Async::NotReady),
+                None => continue,
+            };
+            // the replies like the renewal DHCPACK are patched into the request header
+            self.socket.set_reply_header(Some(header));
+            if !self.interfaces.is_empty() {
+                self.ingress = self
+                    .interfaces
+                    .iter()
+                    .find(|&&(interface, _)| Some(interface) == index)
+                    .cloned();
+                if self.ingress.is_none() {
+                    debug!("Ignoring a request from an unserved interface {:?}", index);
+                    continue;
+                }
+            }
+            // the broadcast requests are captured with their real destination if it is known
+            let local = destination.unwrap_or_else(|| self.local_address());
+            let local = SocketAddr::new(IpAddr::V4(local), DHCP_PORT_SERVER);
+            self.capture(addr, local, &request, None);
+            if request.is_bootp_request() {
+                self.process_bootp(request)?;
+                continue;
+            }
+            let dhcp_message_type = validate!(request, addr.ip(), self.metrics);
 
             if let Some(dhcp_server_id) = request.options.dhcp_server_id {
-                if dhcp_server_id != self.server_ip_address {
+                if dhcp_server_id != self.local_address() {
                     warn!("Ignoring a message destined for server {}", dhcp_server_id);
                     continue;
                 }
@@ -324,7 +2652,189 @@ where
                 Some(ref client_id) => client_id.as_ref(),
                 None => request.client_hardware_address.as_bytes(),
             };
+            let relay = if request.gateway_ip_address.is_unspecified() {
+                None
+            } else {
+                Some(request.gateway_ip_address)
+            };
+            if !self.rate_limiter.allows(client_id, relay) {
+                debug!("Dropping a request of the client {:?} over the rate limit", client_id);
+                continue;
+            }
+            if let Some(ref failover) = self.failover {
+                if !failover.serves(&request, client_id) {
+                    trace!("Leaving the client {:?} to the failover peer", client_id);
+                    continue;
+                }
+            }
+            // the broadcast requests are delivered to every worker sharing the port
+            if let Some(worker) = self.worker {
+                match self
+                    .database
+                    .claim_transaction(client_id, request.transaction_id, worker)
+                {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        trace!(
+                            "Leaving the transaction {:#x} of the client {:?} to another worker",
+                            request.transaction_id,
+                            client_id
+                        );
+                        continue;
+                    }
+                    Err(error) => warn!("Transaction claiming error: {}", error),
+                }
+            }
             let max_size = request.options.dhcp_max_message_size;
+            let reservation = self.reservations.find(&request).cloned();
+            let link_address = self.link_address(&request);
+            let device_type = self.device_type(&request, client_id);
+            let class = self
+                .classes
+                .iter()
+                .find(|class| class.matches(&request, device_type.as_ref().map(String::as_str)))
+                .cloned();
+            if let Some(ref class) = class {
+                debug!("The client belongs to the class {}", class.name);
+            }
+            let subnet = class
+                .as_ref()
+                .and_then(|class| class.subnet)
+                .and_then(|address| self.database.subnet_by_address(&address))
+                .or_else(|| {
+                    self.database.subnet(
+                        &link_address,
+                        request.options.relay_agent_information.as_ref(),
+                    )
+                }).cloned();
+            // the host lease times replace the class ones, which replace the pool ones
+            let lease_times = reservation
+                .as_ref()
+                .and_then(|reservation| reservation.lease_times)
+                .or_else(|| class.as_ref().and_then(|class| class.lease_times))
+                .or_else(|| subnet.as_ref().and_then(|subnet| subnet.lease_times));
+            let lease_time = clamp_lease_time(lease_times, request.options.address_time);
+            let lease_time = match self.failover {
+                Some(ref failover) => failover.lease_time(client_id, lease_time),
+                None => lease_time,
+            };
+
+            /*
+            RFC 3118 §5.3
+            If the server fails to authenticate a message, the server
+            MUST discard the incoming message.
+            */
+            let is_client_message = match dhcp_message_type {
+                MessageType::DhcpDiscover
+                | MessageType::DhcpRequest
+                | MessageType::DhcpDecline
+                | MessageType::DhcpRelease
+                | MessageType::DhcpInform => true,
+                _ => false,
+            };
+            if let (Some(key), true) = (
+                subnet
+                    .as_ref()
+                    .and_then(|subnet| subnet.authentication.as_ref()),
+                is_client_message,
+            ) {
+                if let Err(reason) = self.authenticator.verify(key, client_id, &request) {
+                    info!(
+                        target: "audit",
+                        "The client {} has failed the authentication: {}",
+                        request.client_hardware_address, reason,
+                    );
+                    if key.failure_action == FailureAction::Drop {
+                        continue;
+                    }
+                }
+            }
+
+            // the releases and declines are processed anyway, since they only free the addresses
+            let denied_action = {
+                let access_list = subnet
+                    .as_ref()
+                    .and_then(|subnet| subnet.access_list.as_ref())
+                    .or_else(|| self.access_list.as_ref());
+                match access_list {
+                    Some(access_list) if !access_list.permits(&request.client_hardware_address) => {
+                        match dhcp_message_type {
+                            MessageType::DhcpDiscover
+                            | MessageType::DhcpRequest
+                            | MessageType::DhcpInform => Some(access_list.denied_action),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            };
+            if let Some(denied_action) = denied_action {
+                info!(
+                    target: "audit",
+                    "The client {} has been denied: {}",
+                    request.client_hardware_address, dhcp_message_type,
+                );
+                if let (DeniedAction::Nak, MessageType::DhcpRequest, None) =
+                    (denied_action, dhcp_message_type, self.proxy.as_ref())
+                {
+                    let response = self.builder.dhcp_request_to_nak(&request, &ClientDenied);
+                    let destination = Ipv4Addr::new(255, 255, 255, 255);
+                    self.send_response(response, destination, false, max_size)?;
+                }
+                continue;
+            }
+
+            // the addresses are assigned by another server, so only the PXE clients are offered
+            if self.proxy.is_some() {
+                if let (MessageType::DhcpDiscover, true) =
+                    (dhcp_message_type, is_pxe_client(&request))
+                {
+                    let parameters = match self.boot_parameters(&request, &subnet) {
+                        Some(parameters) => parameters,
+                        None => continue,
+                    };
+                    let mut response =
+                        self.builder.proxy_response(&request, MessageType::DhcpOffer);
+                    self.builder.apply_boot(&request, &mut response, &parameters);
+                    let destination = Ipv4Addr::new(255, 255, 255, 255);
+                    self.send_response(response, destination, false, max_size)?;
+                }
+                continue;
+            }
+
+            // the new clients behind a full relay agent circuit are refused like the denied ones
+            let is_over_limit = match dhcp_message_type {
+                MessageType::DhcpDiscover | MessageType::DhcpRequest => {
+                    let database = &self.database;
+                    !self.subscriber_limits.permits(
+                        request.options.relay_agent_information.as_ref(),
+                        client_id,
+                        |client_id| {
+                            database
+                                .lease(client_id)
+                                .ok()
+                                .and_then(|lease| lease)
+                                .map_or(false, |lease| lease.is_active())
+                        },
+                    )
+                }
+                _ => false,
+            };
+            if is_over_limit {
+                info!(
+                    target: "audit",
+                    "The client {} has exceeded the relay agent lease limit: {}",
+                    request.client_hardware_address, dhcp_message_type,
+                );
+                if let (DeniedAction::Nak, MessageType::DhcpRequest) =
+                    (self.subscriber_limits.denied_action, dhcp_message_type)
+                {
+                    let response = self.builder.dhcp_request_to_nak(&request, &ClientDenied);
+                    let destination = Ipv4Addr::new(255, 255, 255, 255);
+                    self.send_response(response, destination, false, max_size)?;
+                }
+                continue;
+            }
 
             match dhcp_message_type {
                 MessageType::DhcpDiscover => {
@@ -336,17 +2846,70 @@ where
                     the system administrator.
                     */
 
-                    match self.database.allocate(
-                        client_id,
-                        request.options.address_time,
-                        request.options.address_request,
-                    ) {
+                    let started = Instant::now();
+                    let result = match reservation {
+                        Some(ref reservation) => self.database.allocate_reserved(
+                            client_id,
+                            &reservation.address,
+                            lease_time,
+                        ),
+                        None => self.database.allocate(
+                            client_id,
+                            lease_time,
+                            request.options.address_request,
+                            subnet.as_ref(),
+                        ),
+                    };
+                    self.metrics.observe(OPERATION_OFFER, started.elapsed());
+                    match result {
                         Ok(offer) => {
-                            let response = self.builder.dhcp_discover_to_offer(&request, &offer);
+                            let mut response =
+                                self.builder.dhcp_discover_to_offer(&request, &offer);
+                            self.apply_parameters(
+                                &request,
+                                &mut response,
+                                &reservation,
+                                &subnet,
+                                &class,
+                            );
                             let (destination, hw_unicast) = self.destination(&request, &response);
-                            self.send_response(response, destination, hw_unicast, max_size)?;
+
+                            /*
+                            RFC 2131 §2.2
+                            As a consistency check, the allocating
+                            server SHOULD probe the reused address before allocating the address,
+                            e.g., with an ICMP echo request, and the client SHOULD probe the newly
+                            received address, e.g., with ARP.
+                            */
+                            let pending = PendingResponse {
+                                response,
+                                destination,
+                                hw_unicast,
+                                max_size,
+                                ingress: self.ingress.clone(),
+                            };
+                            let pending = match self.prober {
+                                Some(ref mut prober) => {
+                                    match prober.start(offer.address, Probed::Offer(pending)) {
+                                        Err(Probed::Offer(pending)) => Some(pending),
+                                        _ => None,
+                                    }
+                                }
+                                None => Some(pending),
+                            };
+                            if let Some(pending) = pending {
+                                self.send_response(
+                                    pending.response,
+                                    pending.destination,
+                                    pending.hw_unicast,
+                                    pending.max_size,
+                                )?;
+                            }
+                        }
+                        Err(error) => {
+                            warn!("Address allocation error: {}", error.to_string());
+                            self.metrics.error(ERROR_ALLOCATION);
                         }
-                        Err(error) => warn!("Address allocation error: {}", error.to_string()),
                     };
                 }
                 MessageType::DhcpRequest => {
@@ -378,17 +2941,47 @@ where
                     // the client is in the SELECTING state
                     if request.options.dhcp_server_id.is_some() {
                         let address = expect!(request.options.address_request);
-                        let lease_time = request.options.address_time;
 
-                        match self.database.assign(client_id, &address, lease_time) {
+                        let started = Instant::now();
+                        let result = self.database.assign(client_id, &address, lease_time);
+                        self.metrics.observe(OPERATION_ASSIGN, started.elapsed());
+                        match result {
                             Ok(ack) => {
-                                let response = self.builder.dhcp_request_to_ack(&request, &ack);
+                                self.replicate(client_id);
+                                self.subscriber_limits.bind(
+                                    request.options.relay_agent_information.as_ref(),
+                                    client_id,
+                                );
+                                let (hostname, dns_update) =
+                                    self.registration(&request, &reservation, &ack.address);
+                                self.notify(LeaseEvent::Committed {
+                                    client_id: client_id.to_vec(),
+                                    address: ack.address,
+                                    hostname,
+                                    dns_update,
+                                    lease_time: ack.lease_time,
+                                });
+                                let mut response = self.builder.dhcp_request_to_ack(&request, &ack);
+                                self.apply_parameters(
+                                    &request,
+                                    &mut response,
+                                    &reservation,
+                                    &subnet,
+                                    &class,
+                                );
+                                self.nonces.apply(client_id, &request, &mut response);
                                 let (destination, hw_unicast) =
                                     self.destination(&request, &response);
                                 self.send_response(response, destination, hw_unicast, max_size)?;
                             }
                             Err(error) => {
                                 warn!("Address assignment error: {}", error.to_string());
+                                self.metrics.error(ERROR_ASSIGNMENT);
+                                self.notify(LeaseEvent::Rejected {
+                                    client_id: client_id.to_vec(),
+                                    address,
+                                    reason: error.to_string(),
+                                });
                                 let response = self.builder.dhcp_request_to_nak(&request, &error);
                                 let destination = Ipv4Addr::new(255, 255, 255, 255);
                                 self.send_response(response, destination, false, max_size)?;
@@ -397,47 +2990,163 @@ where
                         continue;
                     }
 
+                    let is_authoritative = subnet
+                        .as_ref()
+                        .map_or(false, |subnet| subnet.authoritative);
+
                     // the client is in the INIT-REBOOT state
                     if request.client_ip_address.is_unspecified() {
                         let address = expect!(request.options.address_request);
 
+                        /*
+                        RFC 2131 §4.3.2
+                        If the DHCP server detects that the client is on the wrong network
+                        (i.e., the result of applying the local subnet mask or remote subnet
+                        mask (if 'giaddr' is not zero) to 'requested IP address' option value
+                        doesn't match reality), then the server SHOULD send a DHCPNAK message
+                        to the client.
+                        */
+                        if is_wrong_network(&self.database, &subnet, &address) {
+                            let error = WrongNetwork;
+                            warn!("Address checking error: {}", error.to_string());
+                            self.metrics.error(ERROR_RENEWAL);
+                            self.notify(LeaseEvent::Rejected {
+                                client_id: client_id.to_vec(),
+                                address,
+                                reason: error.to_string(),
+                            });
+                            let response = self.builder.dhcp_request_to_nak(&request, &error);
+                            let destination = Ipv4Addr::new(255, 255, 255, 255);
+                            self.send_response(response, destination, false, max_size)?;
+                            continue;
+                        }
+
                         match self.database.check(client_id, &address) {
                             Ok(ack) => {
-                                let response = self.builder.dhcp_request_to_ack(&request, &ack);
+                                self.subscriber_limits.bind(
+                                    request.options.relay_agent_information.as_ref(),
+                                    client_id,
+                                );
+                                let (hostname, dns_update) =
+                                    self.registration(&request, &reservation, &ack.address);
+                                self.notify(LeaseEvent::Committed {
+                                    client_id: client_id.to_vec(),
+                                    address: ack.address,
+                                    hostname,
+                                    dns_update,
+                                    lease_time: ack.lease_time,
+                                });
+                                let mut response = self.builder.dhcp_request_to_ack(&request, &ack);
+                                self.apply_parameters(
+                                    &request,
+                                    &mut response,
+                                    &reservation,
+                                    &subnet,
+                                    &class,
+                                );
+                                self.nonces.apply(client_id, &request, &mut response);
                                 let (destination, hw_unicast) =
                                     self.destination(&request, &response);
                                 self.send_response(response, destination, hw_unicast, max_size)?;
                             }
                             Err(error) => {
                                 warn!("Address checking error: {}", error.to_string());
-                                if let LeaseInvalid = error {
-                                    let response =
-                                        self.builder.dhcp_request_to_nak(&request, &error);
-                                    let destination = Ipv4Addr::new(255, 255, 255, 255);
-                                    self.send_response(response, destination, false, max_size)?;
-                                }
+                                self.metrics.error(ERROR_RENEWAL);
                                 /*
                                 RFC 2131 §4.3.2
                                 If the DHCP server has no record of this client, then it MUST
                                 remain silent, and MAY output a warning to the network administrator.
+
+                                Note: an authoritative server owns all the records of the subnet,
+                                so the absent record means the address is wrong.
                                 */
+                                let is_nak_required = match error {
+                                    LeaseInvalid => true,
+                                    LeaseNotFound => is_authoritative,
+                                    _ => false,
+                                };
+                                if is_nak_required {
+                                    self.notify(LeaseEvent::Rejected {
+                                        client_id: client_id.to_vec(),
+                                        address,
+                                        reason: error.to_string(),
+                                    });
+                                    let response =
+                                        self.builder.dhcp_request_to_nak(&request, &error);
+                                    let destination = Ipv4Addr::new(255, 255, 255, 255);
+                                    self.send_response(response, destination, false, max_size)?;
+                                }
                             }
                         }
                         continue;
                     }
 
                     // the client is in the RENEWING or REBINDING state
-                    let lease_time = request.options.address_time;
-                    match self
+                    if is_wrong_network(&self.database, &subnet, &request.client_ip_address) {
+                        let error = WrongNetwork;
+                        warn!("Address checking error: {}", error.to_string());
+                        self.metrics.error(ERROR_RENEWAL);
+                        self.notify(LeaseEvent::Rejected {
+                            client_id: client_id.to_vec(),
+                            address: request.client_ip_address,
+                            reason: error.to_string(),
+                        });
+                        let response = self.builder.dhcp_request_to_nak(&request, &error);
+                        let destination = Ipv4Addr::new(255, 255, 255, 255);
+                        self.send_response(response, destination, false, max_size)?;
+                        continue;
+                    }
+                    let started = Instant::now();
+                    let result = self
                         .database
-                        .renew(client_id, &request.client_ip_address, lease_time)
-                    {
+                        .renew(client_id, &request.client_ip_address, lease_time);
+                    self.metrics.observe(OPERATION_RENEW, started.elapsed());
+                    match result {
                         Ok(ack) => {
-                            let response = self.builder.dhcp_request_to_ack(&request, &ack);
+                            self.replicate(client_id);
+                            self.subscriber_limits.bind(
+                                request.options.relay_agent_information.as_ref(),
+                                client_id,
+                            );
+                            let (hostname, dns_update) =
+                                self.registration(&request, &reservation, &ack.address);
+                            self.notify(LeaseEvent::Renewed {
+                                client_id: client_id.to_vec(),
+                                address: ack.address,
+                                hostname,
+                                dns_update,
+                                lease_time: ack.lease_time,
+                            });
+                            let mut response = self.builder.dhcp_request_to_ack(&request, &ack);
+                            self.apply_parameters(
+                                &request,
+                                &mut response,
+                                &reservation,
+                                &subnet,
+                                &class,
+                            );
+                            self.nonces.apply(client_id, &request, &mut response);
                             let (destination, hw_unicast) = self.destination(&request, &response);
                             self.send_response(response, destination, hw_unicast, max_size)?;
                         }
-                        Err(error) => warn!("Address checking error: {}", error.to_string()),
+                        Err(error) => {
+                            warn!("Address checking error: {}", error.to_string());
+                            self.metrics.error(ERROR_RENEWAL);
+                            let is_nak_required = match error {
+                                LeaseInvalid | LeaseNotFound => is_authoritative,
+                                _ => false,
+                            };
+                            if is_nak_required {
+                                self.notify(LeaseEvent::Rejected {
+                                    client_id: client_id.to_vec(),
+                                    address: request.client_ip_address,
+                                    reason: error.to_string(),
+                                });
+                                let response = self.builder.dhcp_request_to_nak(&request, &error);
+                                let destination = Ipv4Addr::new(255, 255, 255, 255);
+                                self.send_response(response, destination, false, max_size)?;
+                            }
+                        }
                     }
                 }
                 MessageType::DhcpDecline => {
@@ -451,9 +3160,21 @@ where
                     */
 
                     let address = expect!(request.options.address_request);
-                    match self.database.freeze(&address) {
-                        Ok(_) => info!("Address {} has been marked as unavailable", address),
-                        Err(error) => warn!("Address freezing error: {}", error.to_string()),
+                    match self.database.decline(client_id, &address) {
+                        Ok(_) => {
+                            error!(
+                                "Address conflict: {} has been declined by the client {} \
+                                 and abandoned",
+                                address, request.client_hardware_address,
+                            );
+                            self.metrics.error(ERROR_CONFLICT);
+                            self.replicate(client_id);
+                            self.notify(LeaseEvent::Declined {
+                                client_id: client_id.to_vec(),
+                                address,
+                            });
+                        }
+                        Err(error) => warn!("Address declining error: {}", error.to_string()),
                     };
                 }
                 MessageType::DhcpRelease => {
@@ -466,8 +3187,16 @@ where
                     */
 
                     let address = request.client_ip_address;
-                    match self.database.deallocate(client_id, &address) {
-                        Ok(_) => info!("Address {} has been released", address),
+                    match self.database.release(client_id, &address) {
+                        Ok(_) => {
+                            info!("Address {} has been released", address);
+                            self.nonces.remove(client_id);
+                            self.replicate(client_id);
+                            self.notify(LeaseEvent::Released {
+                                client_id: client_id.to_vec(),
+                                address,
+                            });
+                        }
                         Err(error) => warn!("Address releasing error: {}", error.to_string()),
                     };
                 }
@@ -480,16 +3209,235 @@ where
                     to the client and SHOULD NOT fill in 'yiaddr'.
                     */
 
+                    if request.client_ip_address.is_unspecified() {
+                        warn!("Ignoring a DHCPINFORM without the client address");
+                        continue;
+                    }
                     info!(
                         "Address {} has been taken by some client manually",
                         request.client_ip_address
                     );
-                    let response = self.builder.dhcp_inform_to_ack(&request, "Accepted");
+
+                    // the client address is a better subnet hint than the link address
+                    let subnet = self
+                        .database
+                        .subnet(
+                            &request.client_ip_address,
+                            request.options.relay_agent_information.as_ref(),
+                        ).cloned()
+                        .or(subnet);
+                    let mut response = self.builder.dhcp_inform_to_ack(&request, "Accepted");
+                    self.apply_parameters(&request, &mut response, &reservation, &subnet, &class);
                     let (destination, hw_unicast) = self.destination(&request, &response);
                     self.send_response(response, destination, hw_unicast, max_size)?;
                 }
+                MessageType::DhcpLeaseQuery => {
+                    let response = match self.leasequery(&request) {
+                        Ok(response) => response,
+                        Err(error) => {
+                            warn!("Leasequery error: {}", error.to_string());
+                            self.metrics.error(ERROR_STORAGE);
+                            continue;
+                        }
+                    };
+                    // the requester is usually a relay agent listening on the server port
+                    log_send!(response, request.gateway_ip_address);
+                    self.metrics.sent(&response);
+                    let destination =
+                        SocketAddr::new(IpAddr::V4(request.gateway_ip_address), DHCP_PORT_SERVER);
+                    self.capture(local, destination, &response, max_size);
+                    start_send!(self.socket, destination, response, max_size);
+                }
                 _ => {}
             }
         }
     }
 }
+
+/// The client IDs a leasequery is made by, which are either the client ID itself
+/// or both the plain hardware address and the one prefixed with the hardware type.
+///
+/// Empty if the query is made by neither.
+fn queried_client_ids(query: &Message) -> Vec<Vec<u8>> {
+    let mut client_ids = Vec::new();
+    match query.options.client_id {
+        Some(ref client_id) => client_ids.push(client_id.to_owned()),
+        None if !query.client_hardware_address.is_nil() => {
+            let hardware_address = query.client_hardware_address.as_bytes();
+            client_ids.push(hardware_address.to_vec());
+            let mut client_id = vec![query.hardware_type as u8];
+            client_id.extend_from_slice(hardware_address);
+            client_ids.push(client_id);
+        }
+        None => {}
+    }
+    client_ids
+}
+
+/// Fills the leasequery response with the address and the client of the lease.
+fn apply_client(response: &mut Message, client_id: &[u8], lease: &Lease) {
+    response.client_ip_address = lease.address();
+    if let Some(hardware_address) = client_hardware_address(client_id) {
+        response.hardware_type = HardwareType::Ethernet;
+        response.hardware_address_length = hardware_address.as_bytes().len() as u8;
+        response.client_hardware_address = hardware_address;
+    }
+    response.options.client_id = Some(client_id.to_vec());
+}
+
+/// Fills the `DHCPLEASEACTIVE` message with the lease of the client.
+fn apply_lease(response: &mut Message, client_id: &[u8], lease: &Lease) {
+    apply_client(response, client_id, lease);
+    response.options.address_time = Some(lease.expires_after());
+    let now = Utc::now().timestamp() as u32;
+    response.options.client_last_transaction_time =
+        Some(now.saturating_sub(lease.updated_at()));
+}
+
+/// Extracts the hardware address from the client ID, which is either the hardware address
+/// or the hardware type followed by the hardware address (RFC 2132 §9.14).
+pub fn client_hardware_address(client_id: &[u8]) -> Option<MacAddress> {
+    let address = match client_id.len() {
+        6 => client_id,
+        7 if client_id[0] == 1 => &client_id[1..],
+        _ => return None,
+    };
+    MacAddress::from_bytes(address).ok()
+}
+
+/// Binds the UDP socket, letting the other workers bind the same port if `reuse_port` is set.
+#[cfg(target_os = "linux")]
+fn bind_socket(addr: &SocketAddr, reuse_port: bool) -> io::Result<UdpSocket> {
+    use std::{mem, os::unix::io::FromRawFd};
+
+    use libc::{self, c_int, c_void, sockaddr, sockaddr_in, socklen_t};
+
+    if !reuse_port {
+        return UdpSocket::bind(addr);
+    }
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(_) => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+    };
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // closes the descriptor on an error
+    let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+
+    let enable: c_int = 1;
+    for &option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT].iter() {
+        let result = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                option,
+                &enable as *const c_int as *const c_void,
+                mem::size_of::<c_int>() as socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    let mut name: sockaddr_in = unsafe { mem::zeroed() };
+    name.sin_family = libc::AF_INET as libc::sa_family_t;
+    name.sin_port = addr.port().to_be();
+    name.sin_addr.s_addr = u32::from(ip).to_be();
+    let result = unsafe {
+        libc::bind(
+            fd,
+            &name as *const sockaddr_in as *const sockaddr,
+            mem::size_of::<sockaddr_in>() as socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_socket(addr: &SocketAddr, reuse_port: bool) -> io::Result<UdpSocket> {
+    if reuse_port {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "The workers sharing the port are supported only on Linux",
+        ));
+    }
+    UdpSocket::bind(addr)
+}
+
+/// Binds the socket to the interface, returning `false` if the broadcasts
+/// must be sent over the raw socket instead.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "macos"))]
+fn bind_to_device<F: ServerSocket>(socket: &F, iface_name: &str) -> bool {
+    match socket.bind_to_device(iface_name) {
+        Ok(()) => true,
+        Err(error) => {
+            warn!(
+                "Unable to bind the socket to {}, the broadcasts are sent raw: {}",
+                iface_name, error
+            );
+            false
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "macos")))]
+fn bind_to_device<F: ServerSocket>(_socket: &F, iface_name: &str) -> bool {
+    warn!("The socket cannot be bound to {} on this platform", iface_name);
+    true
+}
+
+/// Makes the socket learn the ingress interfaces and looks up the interface indexes.
+#[cfg(target_os = "linux")]
+fn resolve_interfaces<F: ServerSocket>(
+    socket: &mut F,
+    interfaces: Vec<Interface>,
+) -> io::Result<Vec<(u32, Interface)>> {
+    if interfaces.is_empty() {
+        return Ok(Vec::new());
+    }
+    socket.enable_pktinfo()?;
+    interfaces
+        .into_iter()
+        .map(|interface| Ok((interface_index(&interface.name)?, interface)))
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resolve_interfaces<F: ServerSocket>(
+    _socket: &mut F,
+    interfaces: Vec<Interface>,
+) -> io::Result<Vec<(u32, Interface)>> {
+    if interfaces.is_empty() {
+        return Ok(Vec::new());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Serving several interfaces is supported on Linux only",
+    ))
+}
+
+/// Checks whether the address is known to be wrong for the authoritative subnet of the request.
+///
+/// The addresses of the other subnets of its shared network are on the right link.
+fn is_wrong_network<S: Storage>(
+    database: &Database<S>,
+    subnet: &Option<Subnet>,
+    address: &Ipv4Addr,
+) -> bool {
+    subnet.as_ref().map_or(false, |subnet| {
+        subnet.authoritative && !database.is_on_link(subnet, address)
+    })
+}
+
+/// The host name reserved for the client, if any.
+fn reserved_hostname(reservation: &Option<Reservation>) -> Option<&str> {
+    reservation
+        .as_ref()
+        .and_then(|reservation| reservation.hostname.as_ref())
+        .map(String::as_str)
+}
@@ -1,35 +1,55 @@
 //! The trait user must implement to provide a persistent lease storage for the DHCP server.
 
-use std::net::Ipv4Addr;
+use std::{error, fmt, net::Ipv4Addr};
 
 use lease::Lease;
 
 /// Errors generated by the `Storage` trait methods.
-#[derive(Fail, Debug)]
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "Client getting error: {}", _0)]
     GetClient(String),
-    #[fail(display = "Client adding error: {}", _0)]
     AddClient(String),
-    #[fail(display = "Client deleting error: {}", _0)]
     DeleteClient(String),
 
-    #[fail(display = "Lease getting error: {}", _0)]
     GetLease(String),
-    #[fail(display = "Lease adding error: {}", _0)]
     AddLease(String),
-    #[fail(display = "Lease updating error: {}", _0)]
     UpdateLease(String),
 
-    #[fail(display = "Frozen address checking error: {}", _0)]
     CheckFrozen(String),
-    #[fail(display = "Frozen address adding error: {}", _0)]
     AddFrozen(String),
+    DeleteFrozen(String),
+    GetFrozen(String),
+
+    ClaimTransaction(String),
 
-    #[fail(display = "Another error: {}", _0)]
     Other(String),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::GetClient(ref desc) => write!(f, "Client getting error: {}", desc),
+            Error::AddClient(ref desc) => write!(f, "Client adding error: {}", desc),
+            Error::DeleteClient(ref desc) => write!(f, "Client deleting error: {}", desc),
+
+            Error::GetLease(ref desc) => write!(f, "Lease getting error: {}", desc),
+            Error::AddLease(ref desc) => write!(f, "Lease adding error: {}", desc),
+            Error::UpdateLease(ref desc) => write!(f, "Lease updating error: {}", desc),
+
+            Error::CheckFrozen(ref desc) => write!(f, "Frozen address checking error: {}", desc),
+            Error::AddFrozen(ref desc) => write!(f, "Frozen address adding error: {}", desc),
+            Error::DeleteFrozen(ref desc) => write!(f, "Frozen address deleting error: {}", desc),
+            Error::GetFrozen(ref desc) => write!(f, "Frozen addresses getting error: {}", desc),
+
+            Error::ClaimTransaction(ref desc) => write!(f, "Transaction claiming error: {}", desc),
+
+            Error::Other(ref desc) => write!(f, "Another error: {}", desc),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
 /// Must be implemented by the DHCP server crate user.
 ///
 /// Be sure your storage is `ACID`.
@@ -51,6 +71,28 @@ where
     /// or `Error::Other(desc)` on another error.
     fn add_client(&mut self, address: &Ipv4Addr, client_id: &[u8]) -> Result<(), Error>;
 
+    /// Must associate the client with the given address only if the address is associated
+    /// with `owner` now, where `None` means no client, and return `true` if it is done.
+    ///
+    /// The storage shared by the server workers or processes must do it atomically across them,
+    /// so only one of the workers racing for the address gets it. Is not atomic by default.
+    ///
+    /// # Errors
+    /// Must return `Error::AddClient(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn claim_address(
+        &mut self,
+        address: &Ipv4Addr,
+        client_id: &[u8],
+        owner: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        if self.get_client(address)?.as_ref().map(Vec::as_slice) != owner {
+            return Ok(false);
+        }
+        self.add_client(address, client_id)?;
+        Ok(true)
+    }
+
     /// Must disassociate the client ID from the given address.
     ///
     /// # Errors
@@ -83,6 +125,18 @@ where
         action: &mut FnMut(&mut Lease) -> (),
     ) -> Result<(), Error>;
 
+    /// Must return all the leases with their client IDs.
+    ///
+    /// Is called once at startup to schedule the lease expirations.
+    /// Returns nothing by default, so the leases expire only when they are requested again.
+    ///
+    /// # Errors
+    /// Must return `Error::GetLease(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn get_leases(&self) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        Ok(Vec::new())
+    }
+
     /// Must return `true` if the given address has been frozen, `false` otherwise.
     ///
     /// # Errors
@@ -96,4 +150,60 @@ where
     /// Must return `Error::AddFrozen(desc)` if there is a database I/O error
     /// or `Error::Other(desc)` on another error.
     fn add_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error>;
+
+    /// Must unmark the frozen address, so it can be allocated again.
+    ///
+    /// Does nothing by default, so the frozen addresses stay frozen.
+    ///
+    /// # Errors
+    /// Must return `Error::DeleteFrozen(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    #[allow(unused_variables)]
+    fn delete_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Must return all the frozen addresses.
+    ///
+    /// Returns nothing by default, so the frozen addresses are never reclaimed.
+    ///
+    /// # Errors
+    /// Must return `Error::GetFrozen(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    fn get_frozen(&self) -> Result<Vec<Ipv4Addr>, Error> {
+        Ok(Vec::new())
+    }
+
+    /// May reclaim the space taken by the outdated records.
+    ///
+    /// Is called periodically after some leases have expired. Does nothing by default.
+    ///
+    /// # Errors
+    /// Must return `Error::Other(desc)` on an error.
+    fn compact(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Must claim the transaction of the client for the server worker `owner`, so a broadcast
+    /// request, which the kernel delivers to every worker sharing the port, is answered once.
+    ///
+    /// Must return `false` if another worker has claimed the same `transaction_id` of the client
+    /// within the last `timeout` seconds, or replace the claim of the client and return `true`.
+    /// The storage shared by the server processes must do it atomically across them.
+    ///
+    /// Is called only by the workers. Always returns `true` by default.
+    ///
+    /// # Errors
+    /// Must return `Error::ClaimTransaction(desc)` if there is a database I/O error
+    /// or `Error::Other(desc)` on another error.
+    #[allow(unused_variables)]
+    fn claim_transaction(
+        &mut self,
+        client_id: &[u8],
+        transaction_id: u32,
+        owner: u32,
+        timeout: u32,
+    ) -> Result<bool, Error> {
+        Ok(true)
+    }
 }
@@ -0,0 +1,402 @@
+//! The storage the server runs on over an asynchronous `LeaseStorage` backend.
+//!
+//! The leases are loaded from the backend once and mirrored in memory, so the requests
+//! are answered without waiting for the backend I/O. The lease writes reach the backend
+//! in order on a writer thread, each one conditional on the lease the backend is known
+//! to store. If another server has changed the lease meanwhile, the write is refused
+//! and the mirror takes the stored lease instead.
+//!
+//! `LeaseStorage` does not store the frozen addresses and the transaction claims,
+//! so they are kept in the mirror only.
+
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    sync::{mpsc, Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    thread,
+};
+
+use futures::{Future, Stream};
+
+use lease::Lease;
+use lease_storage::LeaseStorage;
+use storage::{Error, Storage};
+use storage_ram::RamStorage;
+
+/// A write queued for the backend.
+enum Write {
+    /// Stores the lease of the client.
+    Put(Vec<u8>, Lease),
+    /// Deletes the leases which addresses are available again.
+    Expire,
+}
+
+pub struct AsyncStorage {
+    /// The leases mirrored from the backend.
+    mirror: Arc<RwLock<RamStorage>>,
+    /// The writes queued for the writer thread.
+    writes: Option<mpsc::Sender<Write>>,
+    /// The writer thread owning the backend.
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl AsyncStorage {
+    /// Loads the leases from `backend` and starts the writer thread.
+    ///
+    /// Blocks until the leases are loaded, so it must be called before the server is run.
+    /// The writes queued when the storage is dropped are completed before it returns.
+    ///
+    /// # Errors
+    /// `storage::Error` if the backend fails to yield the leases.
+    pub fn new<L>(backend: L) -> Result<Self, Error>
+    where
+        L: LeaseStorage + 'static,
+    {
+        let leases = backend.iterate().collect().wait()?;
+        let mut mirror = RamStorage::new();
+        let mut stored = HashMap::with_capacity(leases.len());
+        for (client_id, lease) in leases.into_iter() {
+            mirror.add_client(&lease.address(), &client_id)?;
+            mirror.add_lease(&client_id, lease.to_owned())?;
+            stored.insert(client_id, lease);
+        }
+        let mirror = Arc::new(RwLock::new(mirror));
+
+        let (sender, receiver) = mpsc::channel();
+        let writer = Writer {
+            backend,
+            stored,
+            mirror: mirror.clone(),
+        };
+        let writer = thread::Builder::new()
+            .name("dhcp-lease-writer".to_owned())
+            .spawn(move || writer.run(receiver))
+            .map_err(|error| Error::Other(error.to_string()))?;
+
+        Ok(AsyncStorage {
+            mirror,
+            writes: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// Queues the write for the backend.
+    fn queue(&self, write: Write) -> Result<(), Error> {
+        self.writes
+            .as_ref()
+            .and_then(|writes| writes.send(write).ok())
+            .ok_or_else(|| Error::Other("The lease writer has stopped".to_owned()))
+    }
+}
+
+impl Drop for AsyncStorage {
+    fn drop(&mut self) {
+        // the writer drains the queue and stops once the sender is gone
+        self.writes.take();
+        if let Some(writer) = self.writer.take() {
+            if writer.join().is_err() {
+                error!("The lease writer has panicked");
+            }
+        }
+    }
+}
+
+impl Storage for AsyncStorage {
+    fn get_client(&self, address: &Ipv4Addr) -> Result<Option<Vec<u8>>, Error> {
+        read(&self.mirror)?.get_client(address)
+    }
+
+    fn add_client(&mut self, address: &Ipv4Addr, client_id: &[u8]) -> Result<(), Error> {
+        write(&self.mirror)?.add_client(address, client_id)
+    }
+
+    fn claim_address(
+        &mut self,
+        address: &Ipv4Addr,
+        client_id: &[u8],
+        owner: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        write(&self.mirror)?.claim_address(address, client_id, owner)
+    }
+
+    fn delete_client(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        write(&self.mirror)?.delete_client(address)
+    }
+
+    fn get_lease(&self, client_id: &[u8]) -> Result<Option<Lease>, Error> {
+        read(&self.mirror)?.get_lease(client_id)
+    }
+
+    fn add_lease(&mut self, client_id: &[u8], lease: Lease) -> Result<(), Error> {
+        write(&self.mirror)?.add_lease(client_id, lease.to_owned())?;
+        self.queue(Write::Put(client_id.to_vec(), lease))
+    }
+
+    fn update_lease(
+        &mut self,
+        client_id: &[u8],
+        action: &mut FnMut(&mut Lease) -> (),
+    ) -> Result<(), Error> {
+        let mut updated = None;
+        write(&self.mirror)?.update_lease(client_id, &mut |lease: &mut Lease| {
+            action(lease);
+            updated = Some(lease.to_owned());
+        })?;
+        match updated {
+            Some(lease) => self.queue(Write::Put(client_id.to_vec(), lease)),
+            None => Ok(()),
+        }
+    }
+
+    fn get_leases(&self) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        read(&self.mirror)?.get_leases()
+    }
+
+    fn check_frozen(&self, address: &Ipv4Addr) -> Result<bool, Error> {
+        read(&self.mirror)?.check_frozen(address)
+    }
+
+    fn add_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        write(&self.mirror)?.add_frozen(address)
+    }
+
+    fn delete_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        write(&self.mirror)?.delete_frozen(address)
+    }
+
+    fn get_frozen(&self) -> Result<Vec<Ipv4Addr>, Error> {
+        read(&self.mirror)?.get_frozen()
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.queue(Write::Expire)
+    }
+
+    fn claim_transaction(
+        &mut self,
+        client_id: &[u8],
+        transaction_id: u32,
+        owner: u32,
+        timeout: u32,
+    ) -> Result<bool, Error> {
+        write(&self.mirror)?.claim_transaction(client_id, transaction_id, owner, timeout)
+    }
+}
+
+/// The backend with the leases it is known to store.
+struct Writer<L> {
+    backend: L,
+    /// The leases the conditional writes expect to replace.
+    stored: HashMap<Vec<u8>, Lease>,
+    mirror: Arc<RwLock<RamStorage>>,
+}
+
+impl<L> Writer<L>
+where
+    L: LeaseStorage,
+{
+    /// Applies the queued writes one by one until the storage is dropped.
+    fn run(mut self, writes: mpsc::Receiver<Write>) {
+        for write in writes.iter() {
+            let result = match write {
+                Write::Put(client_id, lease) => self.put(client_id, lease),
+                Write::Expire => self.expire(),
+            };
+            if let Err(error) = result {
+                warn!("Lease storage error: {}", error);
+            }
+        }
+    }
+
+    /// Stores the lease if the backend has not been changed by another server.
+    ///
+    /// Otherwise, the mirror takes the stored lease unless a newer lease is queued,
+    /// which is conditional on the stored one as well.
+    fn put(&mut self, client_id: Vec<u8>, lease: Lease) -> Result<(), Error> {
+        let expected = self.stored.get(&client_id).cloned();
+        let is_stored = self
+            .backend
+            .compare_and_put(&client_id, expected, lease.to_owned())
+            .wait()?;
+        if is_stored {
+            self.stored.insert(client_id, lease);
+            return Ok(());
+        }
+
+        warn!("The lease of client {:?} has been changed by another server", client_id);
+        let current = self.backend.get(&client_id).wait()?;
+        {
+            let mut mirror = write(&self.mirror)?;
+            if mirror.get_lease(&client_id)?.as_ref() == Some(&lease) {
+                if mirror.get_client(&lease.address())?.as_ref() == Some(&client_id) {
+                    mirror.delete_client(&lease.address())?;
+                }
+                match current {
+                    Some(ref current) => {
+                        mirror.add_client(&current.address(), &client_id)?;
+                        mirror.add_lease(&client_id, current.to_owned())?;
+                    }
+                    None => mirror.remove_lease(&client_id),
+                }
+            }
+        }
+        match current {
+            Some(current) => self.stored.insert(client_id, current),
+            None => self.stored.remove(&client_id),
+        };
+        Ok(())
+    }
+
+    /// Deletes the expired leases from the backend and the mirror.
+    ///
+    /// A lease renewed since it is deleted is kept in the mirror and stored again.
+    fn expire(&mut self) -> Result<(), Error> {
+        let expired = self.backend.expire().wait()?;
+        let mut mirror = write(&self.mirror)?;
+        for (client_id, lease) in expired.into_iter() {
+            self.stored.remove(&client_id);
+            if mirror.get_lease(&client_id)?.as_ref() != Some(&lease) {
+                continue;
+            }
+            mirror.remove_lease(&client_id);
+            if mirror.get_client(&lease.address())?.as_ref() == Some(&client_id) {
+                mirror.delete_client(&lease.address())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read(mirror: &RwLock<RamStorage>) -> Result<RwLockReadGuard<RamStorage>, Error> {
+    mirror
+        .read()
+        .map_err(|_| Error::Other("The lease mirror is poisoned".to_owned()))
+}
+
+fn write(mirror: &RwLock<RamStorage>) -> Result<RwLockWriteGuard<RamStorage>, Error> {
+    mirror
+        .write()
+        .map_err(|_| Error::Other("The lease mirror is poisoned".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use lease_storage::{LeaseFuture, LeaseStream};
+
+    /// A backend shared with the test, so the stored leases can be checked.
+    #[derive(Clone)]
+    struct Shared(Arc<Mutex<RamStorage>>);
+
+    impl Shared {
+        fn new() -> Self {
+            Shared(Arc::new(Mutex::new(RamStorage::new())))
+        }
+
+        fn lease(&self, client_id: &[u8]) -> Option<Lease> {
+            self.0.lock().unwrap().get_lease(client_id).unwrap()
+        }
+    }
+
+    impl LeaseStorage for Shared {
+        fn get(&self, client_id: &[u8]) -> LeaseFuture<Option<Lease>> {
+            self.0.lock().unwrap().get(client_id)
+        }
+
+        fn get_by_address(&self, address: &Ipv4Addr) -> LeaseFuture<Option<(Vec<u8>, Lease)>> {
+            self.0.lock().unwrap().get_by_address(address)
+        }
+
+        fn put(&mut self, client_id: &[u8], lease: Lease) -> LeaseFuture<()> {
+            self.0.lock().unwrap().put(client_id, lease)
+        }
+
+        fn compare_and_put(
+            &mut self,
+            client_id: &[u8],
+            expected: Option<Lease>,
+            lease: Lease,
+        ) -> LeaseFuture<bool> {
+            self.0
+                .lock()
+                .unwrap()
+                .compare_and_put(client_id, expected, lease)
+        }
+
+        fn expire(&mut self) -> LeaseFuture<Vec<(Vec<u8>, Lease)>> {
+            self.0.lock().unwrap().expire()
+        }
+
+        fn iterate(&self) -> LeaseStream {
+            self.0.lock().unwrap().iterate()
+        }
+    }
+
+    fn address(last: u8) -> Ipv4Addr {
+        Ipv4Addr::new(192, 168, 0, last)
+    }
+
+    #[test]
+    fn writes_leases_through_to_backend() {
+        let mut backend = Shared::new();
+        backend.put(&[1], Lease::new(address(2), 3600)).wait().unwrap();
+
+        let mut storage = AsyncStorage::new(backend.clone()).unwrap();
+        assert_eq!(storage.get_client(&address(2)).unwrap(), Some(vec![1]));
+        storage
+            .update_lease(&[1], &mut |lease: &mut Lease| lease.assign(7200))
+            .unwrap();
+        storage.add_lease(&[3], Lease::new(address(4), 3600)).unwrap();
+        let expected = storage.get_lease(&[1]).unwrap();
+        drop(storage);
+
+        assert!(backend.lease(&[1]) == expected);
+        assert!(backend.lease(&[3]).is_some());
+    }
+
+    #[test]
+    fn takes_lease_changed_by_another_server() {
+        let mut backend = Shared::new();
+        backend.put(&[1], Lease::new(address(2), 3600)).wait().unwrap();
+
+        let mut storage = AsyncStorage::new(backend.clone()).unwrap();
+        let mut other = Lease::new(address(5), 3600);
+        other.assign(3600);
+        backend.put(&[1], other.to_owned()).wait().unwrap();
+        storage
+            .update_lease(&[1], &mut |lease: &mut Lease| lease.assign(7200))
+            .unwrap();
+        let mirror = storage.mirror.clone();
+        drop(storage);
+
+        let mirror = mirror.read().unwrap();
+        assert!(backend.lease(&[1]) == Some(other.to_owned()));
+        assert!(mirror.get_lease(&[1]).unwrap() == Some(other));
+        assert_eq!(mirror.get_client(&address(5)).unwrap(), Some(vec![1]));
+        assert_eq!(mirror.get_client(&address(2)).unwrap(), None);
+    }
+
+    #[test]
+    fn expires_released_leases() {
+        let backend = Shared::new();
+        let mut storage = AsyncStorage::new(backend.clone()).unwrap();
+        let mut released = Lease::new(address(2), 3600);
+        released.assign(3600);
+        released.release();
+        storage.add_client(&address(2), &[1]).unwrap();
+        storage.add_lease(&[1], released).unwrap();
+        storage.add_lease(&[3], Lease::new(address(4), 3600)).unwrap();
+        storage.compact().unwrap();
+        let mirror = storage.mirror.clone();
+        drop(storage);
+
+        let mirror = mirror.read().unwrap();
+        assert!(backend.lease(&[1]).is_none());
+        assert!(backend.lease(&[3]).is_some());
+        assert!(mirror.get_lease(&[1]).unwrap().is_none());
+        assert_eq!(mirror.get_client(&address(2)).unwrap(), None);
+    }
+}
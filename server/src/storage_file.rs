@@ -0,0 +1,505 @@
+//! A journaled file implementation of the persistent lease storage of the DHCP server.
+//!
+//! Every change is appended to the journal as a single line and synced before
+//! it is applied to the memory, so a crash may lose only the change being written.
+//! A torn last line is ignored during the recovery.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+
+use futures::future;
+
+use lease::Lease;
+use lease_storage::{LeaseFuture, LeaseStorage, LeaseStream};
+use storage::{Error, Storage};
+use storage_ram::RamStorage;
+
+/// The client association record tag.
+const RECORD_CLIENT: &str = "client";
+/// The client disassociation record tag.
+const RECORD_DELETE: &str = "delete";
+/// The lease record tag. The last lease record of a client supersedes the previous ones.
+const RECORD_LEASE: &str = "lease";
+/// The lease deletion record tag.
+const RECORD_EXPIRE: &str = "expire";
+/// The frozen address record tag.
+const RECORD_FROZEN: &str = "frozen";
+/// The frozen address deletion record tag.
+const RECORD_UNFROZEN: &str = "unfrozen";
+
+pub struct FileStorage {
+    /// The journal path.
+    path: PathBuf,
+    /// The state recovered from the journal.
+    ram: RamStorage,
+    /// The journal opened for appending.
+    journal: File,
+}
+
+impl FileStorage {
+    /// Opens the journal at `path` or creates it if it does not exist.
+    ///
+    /// The journal is replayed and compacted. The compacted journal is written
+    /// to a temporary file which then atomically replaces the original one.
+    ///
+    /// # Errors
+    /// `io::Error` if the journal cannot be read or written.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let mut ram = RamStorage::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                Self::replay(&mut ram, &data);
+            }
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+
+        Self::rewrite(&ram, path)?;
+        let journal = OpenOptions::new().append(true).open(path)?;
+        Ok(FileStorage {
+            path: path.to_owned(),
+            ram,
+            journal,
+        })
+    }
+
+    /// Applies the journal records to the empty storage.
+    fn replay(ram: &mut RamStorage, data: &[u8]) {
+        let mut lines: Vec<&[u8]> = data.split(|byte| *byte == b'\n').collect();
+        // the last chunk is either empty or a record torn by a crash
+        if let Some(torn) = lines.pop() {
+            if !torn.is_empty() {
+                warn!("Ignoring the incomplete last journal record");
+            }
+        }
+
+        for (number, line) in lines.into_iter().enumerate() {
+            let applied = String::from_utf8(line.to_vec())
+                .ok()
+                .and_then(|line| Self::apply(ram, &line));
+            if applied.is_none() {
+                warn!("Ignoring the malformed journal record #{}", number + 1);
+            }
+        }
+    }
+
+    /// Applies a single record. Returns `None` if the record is malformed.
+    fn apply(ram: &mut RamStorage, line: &str) -> Option<()> {
+        let fields: Vec<&str> = line.split(' ').collect();
+        match (fields[0], fields.len()) {
+            (RECORD_CLIENT, 3) => {
+                let address: Ipv4Addr = fields[1].parse().ok()?;
+                ram.add_client(&address, &hex_decode(fields[2])?).ok()
+            }
+            (RECORD_DELETE, 2) => {
+                let address: Ipv4Addr = fields[1].parse().ok()?;
+                ram.delete_client(&address).ok()
+            }
+            (RECORD_LEASE, _) if fields.len() > 2 => {
+                let lease = Lease::from_record(&fields[2..])?;
+                ram.add_lease(&hex_decode(fields[1])?, lease).ok()
+            }
+            (RECORD_EXPIRE, 2) => {
+                ram.remove_lease(&hex_decode(fields[1])?);
+                Some(())
+            }
+            (RECORD_FROZEN, 2) => {
+                let address: Ipv4Addr = fields[1].parse().ok()?;
+                ram.add_frozen(&address).ok()
+            }
+            (RECORD_UNFROZEN, 2) => {
+                let address: Ipv4Addr = fields[1].parse().ok()?;
+                ram.delete_frozen(&address).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes the current state as the new journal.
+    fn rewrite(ram: &RamStorage, path: &Path) -> io::Result<()> {
+        let mut data = String::new();
+        for (address, client_id) in ram.clients() {
+            data.push_str(&client_record(address, client_id));
+        }
+        for (client_id, lease) in ram.leases() {
+            data.push_str(&lease_record(client_id, lease));
+        }
+        for address in ram.frozen() {
+            data.push_str(&frozen_record(address));
+        }
+
+        let mut temporary = PathBuf::from(path);
+        temporary.set_extension("tmp");
+        {
+            let mut file = File::create(&temporary)?;
+            file.write_all(data.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temporary, path)?;
+
+        // the rename itself is durable only after the directory is synced
+        #[cfg(unix)]
+        {
+            if let Some(directory) = path.parent() {
+                let directory = if directory.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    directory
+                };
+                File::open(directory)?.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the record to the journal and waits until it reaches the disk.
+    fn append(&mut self, record: &str) -> io::Result<()> {
+        self.journal.write_all(record.as_bytes())?;
+        self.journal.sync_data()
+    }
+}
+
+impl Storage for FileStorage {
+    fn get_client(&self, address: &Ipv4Addr) -> Result<Option<Vec<u8>>, Error> {
+        self.ram.get_client(address)
+    }
+
+    fn add_client(&mut self, address: &Ipv4Addr, client_id: &[u8]) -> Result<(), Error> {
+        self.append(&client_record(address, client_id))
+            .map_err(|error| Error::AddClient(error.to_string()))?;
+        self.ram.add_client(address, client_id)
+    }
+
+    fn delete_client(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        self.append(&format!("{} {}\n", RECORD_DELETE, address))
+            .map_err(|error| Error::DeleteClient(error.to_string()))?;
+        self.ram.delete_client(address)
+    }
+
+    fn get_lease(&self, client_id: &[u8]) -> Result<Option<Lease>, Error> {
+        self.ram.get_lease(client_id)
+    }
+
+    fn add_lease(&mut self, client_id: &[u8], lease: Lease) -> Result<(), Error> {
+        self.append(&lease_record(client_id, &lease))
+            .map_err(|error| Error::AddLease(error.to_string()))?;
+        self.ram.add_lease(client_id, lease)
+    }
+
+    fn update_lease(
+        &mut self,
+        client_id: &[u8],
+        action: &mut FnMut(&mut Lease) -> (),
+    ) -> Result<(), Error> {
+        let mut lease = match self.ram.get_lease(client_id)? {
+            Some(lease) => lease,
+            None => return Ok(()),
+        };
+        action(&mut lease);
+        self.append(&lease_record(client_id, &lease))
+            .map_err(|error| Error::UpdateLease(error.to_string()))?;
+        self.ram.add_lease(client_id, lease)
+    }
+
+    fn get_leases(&self) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        self.ram.get_leases()
+    }
+
+    fn check_frozen(&self, address: &Ipv4Addr) -> Result<bool, Error> {
+        self.ram.check_frozen(address)
+    }
+
+    fn add_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        self.append(&frozen_record(address))
+            .map_err(|error| Error::AddFrozen(error.to_string()))?;
+        self.ram.add_frozen(address)
+    }
+
+    fn delete_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        self.append(&format!("{} {}\n", RECORD_UNFROZEN, address))
+            .map_err(|error| Error::DeleteFrozen(error.to_string()))?;
+        self.ram.delete_frozen(address)
+    }
+
+    fn get_frozen(&self) -> Result<Vec<Ipv4Addr>, Error> {
+        self.ram.get_frozen()
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        Self::rewrite(&self.ram, &self.path)
+            .and_then(|_| OpenOptions::new().append(true).open(&self.path))
+            .map(|journal| self.journal = journal)
+            .map_err(|error| Error::Other(error.to_string()))
+    }
+}
+
+impl LeaseStorage for FileStorage {
+    fn get(&self, client_id: &[u8]) -> LeaseFuture<Option<Lease>> {
+        self.ram.get(client_id)
+    }
+
+    fn get_by_address(&self, address: &Ipv4Addr) -> LeaseFuture<Option<(Vec<u8>, Lease)>> {
+        self.ram.get_by_address(address)
+    }
+
+    fn put(&mut self, client_id: &[u8], lease: Lease) -> LeaseFuture<()> {
+        let result = self
+            .add_client(&lease.address(), client_id)
+            .and_then(|_| self.add_lease(client_id, lease));
+        Box::new(future::result(result))
+    }
+
+    fn compare_and_put(
+        &mut self,
+        client_id: &[u8],
+        expected: Option<Lease>,
+        lease: Lease,
+    ) -> LeaseFuture<bool> {
+        let result = self.ram.get_lease(client_id).and_then(|current| {
+            if current != expected {
+                return Ok(false);
+            }
+            self.add_client(&lease.address(), client_id)?;
+            self.add_lease(client_id, lease)?;
+            Ok(true)
+        });
+        Box::new(future::result(result))
+    }
+
+    fn expire(&mut self) -> LeaseFuture<Vec<(Vec<u8>, Lease)>> {
+        let expired: Vec<(Vec<u8>, Lease)> = self
+            .ram
+            .leases()
+            .filter(|(_, lease)| lease.is_available())
+            .map(|(client_id, lease)| (client_id.to_owned(), lease.to_owned()))
+            .collect();
+        for (client_id, lease) in expired.iter() {
+            let record = format!("{} {}\n", RECORD_EXPIRE, hex_encode(client_id));
+            if let Err(error) = self.append(&record) {
+                return Box::new(future::err(Error::UpdateLease(error.to_string())));
+            }
+            self.ram.remove_lease(client_id);
+            if let Ok(Some(owner)) = self.ram.get_client(&lease.address()) {
+                if owner == *client_id {
+                    if let Err(error) = self.delete_client(&lease.address()) {
+                        return Box::new(future::err(error));
+                    }
+                }
+            }
+        }
+        Box::new(future::ok(expired))
+    }
+
+    fn iterate(&self) -> LeaseStream {
+        self.ram.iterate()
+    }
+}
+
+fn client_record(address: &Ipv4Addr, client_id: &[u8]) -> String {
+    format!("{} {} {}\n", RECORD_CLIENT, address, hex_encode(client_id))
+}
+
+fn lease_record(client_id: &[u8], lease: &Lease) -> String {
+    format!(
+        "{} {} {}\n",
+        RECORD_LEASE,
+        hex_encode(client_id),
+        lease.to_record()
+    )
+}
+
+fn frozen_record(address: &Ipv4Addr) -> String {
+    format!("{} {}\n", RECORD_FROZEN, address)
+}
+
+pub(crate) fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn hex_decode(data: &str) -> Option<Vec<u8>> {
+    if data.len() % 2 != 0 {
+        return None;
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    use futures::Future;
+
+    /// The addresses and the IDs of the clients.
+    type Clients = Vec<(Ipv4Addr, Vec<u8>)>;
+
+    /// A journal path unique to the test, removed with its temporary file when dropped.
+    struct Journal(PathBuf);
+
+    impl Journal {
+        fn new(name: &str, data: &[u8]) -> Self {
+            let path = env::temp_dir().join(format!("dhcp-journal-{}-{}", process::id(), name));
+            fs::write(&path, data).unwrap();
+            Journal(path)
+        }
+    }
+
+    impl Drop for Journal {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(self.0.with_extension("tmp"));
+        }
+    }
+
+    fn address(last: u8) -> Ipv4Addr {
+        Ipv4Addr::new(192, 168, 0, last)
+    }
+
+    #[test]
+    fn recovers_from_journal() {
+        let lease = Lease::new(address(2), 3600);
+        let lease_line = format!("lease 0102 {}\n", lease.to_record());
+        // the journal, and the clients and the frozen addresses recovered from it
+        let cases: Vec<(&str, String, Clients, Vec<Ipv4Addr>)> = vec![
+            ("empty", String::new(), vec![], vec![]),
+            (
+                "clients",
+                "client 192.168.0.2 0102\nclient 192.168.0.3 0a0b\n".to_owned(),
+                vec![(address(2), vec![1, 2]), (address(3), vec![10, 11])],
+                vec![],
+            ),
+            (
+                "deleted",
+                "client 192.168.0.2 0102\ndelete 192.168.0.2\n".to_owned(),
+                vec![],
+                vec![],
+            ),
+            (
+                "frozen",
+                "frozen 192.168.0.4\nfrozen 192.168.0.5\nunfrozen 192.168.0.4\n".to_owned(),
+                vec![],
+                vec![address(5)],
+            ),
+            (
+                "torn",
+                "client 192.168.0.2 0102\nclient 192.168.0.3 0a".to_owned(),
+                vec![(address(2), vec![1, 2])],
+                vec![],
+            ),
+            (
+                "malformed",
+                "client 192.168.0.256 0102\nclient 192.168.0.3 0a0\nbogus\n\nfrozen 192.168.0.4\n"
+                    .to_owned(),
+                vec![],
+                vec![address(4)],
+            ),
+            (
+                "leased",
+                format!("client 192.168.0.2 0102\n{}", lease_line),
+                vec![(address(2), vec![1, 2])],
+                vec![],
+            ),
+        ];
+        for (name, data, clients, frozen) in cases {
+            let journal = Journal::new(name, data.as_bytes());
+            let storage = FileStorage::open(&journal.0).unwrap();
+
+            let mut recovered: Clients = storage
+                .ram
+                .clients()
+                .map(|(address, client_id)| (*address, client_id.to_owned()))
+                .collect();
+            recovered.sort();
+            assert_eq!(recovered, clients, "{}", name);
+            let mut recovered = storage.get_frozen().unwrap();
+            recovered.sort();
+            assert_eq!(recovered, frozen, "{}", name);
+        }
+    }
+
+    #[test]
+    fn recovers_last_lease_record() {
+        let mut lease = Lease::new(address(2), 3600);
+        let first = format!("lease 0102 {}\n", lease.to_record());
+        lease.assign(7200);
+        let last = format!("lease 0102 {}\n", lease.to_record());
+        let journal = Journal::new("lease", format!("{}{}", first, last).as_bytes());
+
+        let storage = FileStorage::open(&journal.0).unwrap();
+        let recovered = storage.get_lease(&[1, 2]).unwrap().unwrap();
+
+        assert_eq!(recovered.to_record(), lease.to_record());
+        assert!(storage.get_lease(&[3, 4]).unwrap().is_none());
+    }
+
+    #[test]
+    fn survives_reopening() {
+        let journal = Journal::new("reopen", b"");
+        {
+            let mut storage = FileStorage::open(&journal.0).unwrap();
+            storage.add_client(&address(2), &[1, 2]).unwrap();
+            storage
+                .add_lease(&[1, 2], Lease::new(address(2), 3600))
+                .unwrap();
+            storage.add_frozen(&address(3)).unwrap();
+            storage.add_client(&address(4), &[3, 4]).unwrap();
+            storage.delete_client(&address(4)).unwrap();
+        }
+
+        let storage = FileStorage::open(&journal.0).unwrap();
+
+        assert_eq!(storage.get_client(&address(2)).unwrap(), Some(vec![1, 2]));
+        assert_eq!(storage.get_client(&address(4)).unwrap(), None);
+        assert!(storage.get_lease(&[1, 2]).unwrap().is_some());
+        assert!(storage.check_frozen(&address(3)).unwrap());
+        // the recovered journal is compacted
+        let data = fs::read_to_string(&journal.0).unwrap();
+        assert_eq!(data.lines().count(), 3);
+    }
+
+    #[test]
+    fn replays_expired_leases() {
+        let journal = Journal::new("expire", b"");
+        {
+            let mut storage = FileStorage::open(&journal.0).unwrap();
+            let mut released = Lease::new(address(2), 3600);
+            released.release();
+            storage.put(&[1, 2], released).wait().unwrap();
+            storage.put(&[3, 4], Lease::new(address(4), 3600)).wait().unwrap();
+            let expired = storage.expire().wait().unwrap();
+            assert_eq!(expired.len(), 1);
+            assert_eq!(expired[0].0, vec![1, 2]);
+        }
+
+        let storage = FileStorage::open(&journal.0).unwrap();
+
+        assert!(storage.get_lease(&[1, 2]).unwrap().is_none());
+        assert_eq!(storage.get_client(&address(2)).unwrap(), None);
+        assert!(storage.get_lease(&[3, 4]).unwrap().is_some());
+    }
+
+    #[test]
+    fn encodes_hex() {
+        let cases: &[(&[u8], &str)] =
+            &[(&[], ""), (&[0x00, 0xff], "00ff"), (&[0x0a, 0xbc], "0abc")];
+        for &(data, hex) in cases {
+            assert_eq!(hex_encode(data), hex);
+            assert_eq!(hex_decode(hex), Some(data.to_vec()));
+        }
+        for hex in &["0", "0g", "abc", "é0"] {
+            assert_eq!(hex_decode(hex), None, "{}", hex);
+        }
+    }
+}
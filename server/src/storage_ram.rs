@@ -3,6 +3,8 @@
 
 use std::{collections::HashMap, net::Ipv4Addr};
 
+use chrono::Utc;
+
 use lease::Lease;
 use storage::{Error, Storage};
 
@@ -13,6 +15,8 @@ pub struct RamStorage {
     client_lease_map: HashMap<Vec<u8>, Lease>,
     /// `IPv4` addresses reported by `DHCPDECLINE`.
     frozen_addresses: Vec<Ipv4Addr>,
+    /// `client_id` to the last claimed transaction ID, its owner and the claim time.
+    transactions: HashMap<Vec<u8>, (u32, u32, u32)>,
 }
 
 impl RamStorage {
@@ -21,8 +25,29 @@ impl RamStorage {
             address_client_map: HashMap::new(),
             client_lease_map: HashMap::new(),
             frozen_addresses: Vec::new(),
+            transactions: HashMap::new(),
         }
     }
+
+    /// The `IPv4` to `client_id` mapping.
+    pub(crate) fn clients(&self) -> impl Iterator<Item = (&Ipv4Addr, &Vec<u8>)> {
+        self.address_client_map.iter()
+    }
+
+    /// The `client_id` to `Lease` mapping.
+    pub(crate) fn leases(&self) -> impl Iterator<Item = (&Vec<u8>, &Lease)> {
+        self.client_lease_map.iter()
+    }
+
+    /// Deletes the lease of the client.
+    pub(crate) fn remove_lease(&mut self, client_id: &[u8]) {
+        self.client_lease_map.remove(client_id);
+    }
+
+    /// The addresses reported by `DHCPDECLINE`.
+    pub(crate) fn frozen(&self) -> impl Iterator<Item = &Ipv4Addr> {
+        self.frozen_addresses.iter()
+    }
 }
 
 impl Storage for RamStorage {
@@ -69,12 +94,49 @@ impl Storage for RamStorage {
         Ok(())
     }
 
+    fn get_leases(&self) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        Ok(self
+            .client_lease_map
+            .iter()
+            .map(|(client_id, lease)| (client_id.to_owned(), lease.to_owned()))
+            .collect())
+    }
+
     fn check_frozen(&self, address: &Ipv4Addr) -> Result<bool, Error> {
         Ok(self.frozen_addresses.contains(address))
     }
 
     fn add_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
-        self.frozen_addresses.push(address.to_owned());
+        if !self.frozen_addresses.contains(address) {
+            self.frozen_addresses.push(address.to_owned());
+        }
+        Ok(())
+    }
+
+    fn delete_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        self.frozen_addresses.retain(|frozen| frozen != address);
         Ok(())
     }
+
+    fn get_frozen(&self) -> Result<Vec<Ipv4Addr>, Error> {
+        Ok(self.frozen_addresses.to_owned())
+    }
+
+    fn claim_transaction(
+        &mut self,
+        client_id: &[u8],
+        transaction_id: u32,
+        owner: u32,
+        timeout: u32,
+    ) -> Result<bool, Error> {
+        let now = Utc::now().timestamp() as u32;
+        if let Some(&(claimed, claimer, claimed_at)) = self.transactions.get(client_id) {
+            if claimed == transaction_id && claimer != owner && now < claimed_at + timeout {
+                return Ok(false);
+            }
+        }
+        self.transactions
+            .insert(client_id.to_vec(), (transaction_id, owner, now));
+        Ok(true)
+    }
 }
@@ -0,0 +1,386 @@
+//! A Redis implementation of the lease storage shared by several DHCP servers.
+//!
+//! An anycast or load-balanced pair of servers may use one Redis database as a single
+//! consistent lease table. Every read-modify-write is a conditional one: the lease key
+//! is watched, so a write racing with the other server's one is retried with the new lease
+//! by `update_lease` or refused by `compare_and_put`. An address is claimed with `SETNX` or,
+//! if it is taken over from a former client, with the address key watched, so only one
+//! of the racing servers gets it.
+//!
+//! The keys are prefixed, so several server groups may share a database:
+//!
+//! * `<prefix>:lease:<hex client ID>` is the lease record like the file storage ones;
+//! * `<prefix>:client:<address>` is the client ID the address is associated with;
+//! * `<prefix>:leases` is the set of the hex client IDs having leases;
+//! * `<prefix>:frozen` is the set of the frozen addresses;
+//! * `<prefix>:transaction:<hex client ID>` is the last claimed transaction ID of the client
+//!   and its owner worker, expiring after the claim timeout.
+
+use std::{
+    net::Ipv4Addr,
+    sync::{Mutex, MutexGuard},
+};
+
+use futures::{future, stream};
+use redis::{self, Commands, Connection, ErrorKind, PipelineCommands, RedisError, RedisResult};
+
+use lease::Lease;
+use lease_storage::{LeaseFuture, LeaseStorage, LeaseStream};
+use storage::{Error, Storage};
+use storage_file::{hex_decode, hex_encode};
+
+pub struct RedisStorage {
+    /// The connection is not `Sync`, but the `Storage` trait requires it.
+    connection: Mutex<Connection>,
+    prefix: String,
+}
+
+impl RedisStorage {
+    /// Connects to the database at `url`, like `redis://127.0.0.1:6379/0`,
+    /// with the keys prefixed by `prefix`.
+    ///
+    /// # Errors
+    /// `redis::RedisError` if the URL is invalid or the connection fails.
+    pub fn open(url: &str, prefix: &str) -> RedisResult<Self> {
+        let connection = redis::Client::open(url)?.get_connection()?;
+        Ok(RedisStorage {
+            connection: Mutex::new(connection),
+            prefix: prefix.to_owned(),
+        })
+    }
+
+    /// The connection is locked for a single storage call.
+    fn lock(&self) -> Result<MutexGuard<Connection>, Error> {
+        self.connection
+            .lock()
+            .map_err(|_| Error::Other("The database connection is poisoned".to_owned()))
+    }
+
+    fn lease_key(&self, client_id: &[u8]) -> String {
+        format!("{}:lease:{}", self.prefix, hex_encode(client_id))
+    }
+
+    fn client_key(&self, address: &Ipv4Addr) -> String {
+        format!("{}:client:{}", self.prefix, address)
+    }
+
+    fn leases_key(&self) -> String {
+        format!("{}:leases", self.prefix)
+    }
+
+    fn frozen_key(&self) -> String {
+        format!("{}:frozen", self.prefix)
+    }
+
+    fn transaction_key(&self, client_id: &[u8]) -> String {
+        format!("{}:transaction:{}", self.prefix, hex_encode(client_id))
+    }
+
+    /// Reads the lease record back into a `Lease`.
+    fn query_lease(connection: &Connection, key: &str) -> RedisResult<Option<Lease>> {
+        let record: Option<String> = connection.get(key)?;
+        match record {
+            Some(record) => {
+                let fields: Vec<&str> = record.split(' ').collect();
+                Lease::from_record(&fields)
+                    .map(Some)
+                    .ok_or_else(|| RedisError::from((ErrorKind::TypeError, "Malformed lease")))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the lease if the current one is equal to `expected`, retrying on the races
+    /// with the other writers. Resolves to `true` if the lease is stored.
+    fn compare_and_put_lease(
+        &self,
+        client_id: &[u8],
+        expected: &Option<Lease>,
+        lease: &Lease,
+    ) -> Result<bool, Error> {
+        let connection = self.lock()?;
+        let key = self.lease_key(client_id);
+        let client_key = self.client_key(&lease.address());
+        let leases_key = self.leases_key();
+        redis::transaction(&*connection, &[&key], |pipe| {
+            if &Self::query_lease(&connection, &key)? != expected {
+                return Ok(Some(false));
+            }
+            pipe.set(&key, lease.to_record())
+                .ignore()
+                .set(&client_key, client_id)
+                .ignore()
+                .sadd(&leases_key, hex_encode(client_id))
+                .ignore()
+                .query::<Option<()>>(&*connection)
+                .map(|stored| stored.map(|_| true))
+        }).map_err(|error| Error::UpdateLease(error.to_string()))
+    }
+}
+
+impl Storage for RedisStorage {
+    fn get_client(&self, address: &Ipv4Addr) -> Result<Option<Vec<u8>>, Error> {
+        let connection = self.lock()?;
+        connection
+            .get(self.client_key(address))
+            .map_err(|error| Error::GetClient(error.to_string()))
+    }
+
+    fn add_client(&mut self, address: &Ipv4Addr, client_id: &[u8]) -> Result<(), Error> {
+        let connection = self.lock()?;
+        connection
+            .set(self.client_key(address), client_id)
+            .map_err(|error| Error::AddClient(error.to_string()))
+    }
+
+    fn claim_address(
+        &mut self,
+        address: &Ipv4Addr,
+        client_id: &[u8],
+        owner: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let connection = self.lock()?;
+        let key = self.client_key(address);
+        let owner = match owner {
+            Some(owner) => owner,
+            None => {
+                return connection
+                    .set_nx(&key, client_id)
+                    .map_err(|error| Error::AddClient(error.to_string()))
+            }
+        };
+        // the owner is compared again after a race
+        redis::transaction(&*connection, &[&key], |pipe| {
+            let current: Option<Vec<u8>> = connection.get(&key)?;
+            if current.as_ref().map(Vec::as_slice) != Some(owner) {
+                return Ok(Some(false));
+            }
+            pipe.set(&key, client_id)
+                .ignore()
+                .query(&*connection)
+                .map(|result: Option<()>| result.map(|()| true))
+        }).map_err(|error| Error::AddClient(error.to_string()))
+    }
+
+    fn delete_client(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        let connection = self.lock()?;
+        connection
+            .del(self.client_key(address))
+            .map_err(|error| Error::DeleteClient(error.to_string()))
+    }
+
+    fn get_lease(&self, client_id: &[u8]) -> Result<Option<Lease>, Error> {
+        let connection = self.lock()?;
+        Self::query_lease(&connection, &self.lease_key(client_id))
+            .map_err(|error| Error::GetLease(error.to_string()))
+    }
+
+    fn add_lease(&mut self, client_id: &[u8], lease: Lease) -> Result<(), Error> {
+        let connection = self.lock()?;
+        redis::pipe()
+            .atomic()
+            .set(self.lease_key(client_id), lease.to_record())
+            .ignore()
+            .sadd(self.leases_key(), hex_encode(client_id))
+            .ignore()
+            .query(&*connection)
+            .map_err(|error| Error::AddLease(error.to_string()))
+    }
+
+    fn update_lease(
+        &mut self,
+        client_id: &[u8],
+        action: &mut FnMut(&mut Lease) -> (),
+    ) -> Result<(), Error> {
+        let connection = self.lock()?;
+        let key = self.lease_key(client_id);
+        // the action is applied to the lease read again after a race
+        redis::transaction(&*connection, &[&key], |pipe| {
+            let mut lease = match Self::query_lease(&connection, &key)? {
+                Some(lease) => lease,
+                None => return Ok(Some(())),
+            };
+            action(&mut lease);
+            pipe.set(&key, lease.to_record())
+                .ignore()
+                .query(&*connection)
+        }).map_err(|error| Error::UpdateLease(error.to_string()))
+    }
+
+    fn get_leases(&self) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        let connection = self.lock()?;
+        let client_ids: Vec<String> = connection
+            .smembers(self.leases_key())
+            .map_err(|error| Error::GetLease(error.to_string()))?;
+        let mut leases = Vec::with_capacity(client_ids.len());
+        for hex_client_id in client_ids.into_iter() {
+            let client_id = hex_decode(&hex_client_id)
+                .ok_or_else(|| Error::GetLease(format!("Invalid client ID {}", hex_client_id)))?;
+            // the lease may have been expired by another server meanwhile
+            if let Some(lease) = Self::query_lease(&connection, &self.lease_key(&client_id))
+                .map_err(|error| Error::GetLease(error.to_string()))?
+            {
+                leases.push((client_id, lease));
+            }
+        }
+        Ok(leases)
+    }
+
+    fn check_frozen(&self, address: &Ipv4Addr) -> Result<bool, Error> {
+        let connection = self.lock()?;
+        connection
+            .sismember(self.frozen_key(), address.to_string())
+            .map_err(|error| Error::CheckFrozen(error.to_string()))
+    }
+
+    fn add_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        let connection = self.lock()?;
+        connection
+            .sadd(self.frozen_key(), address.to_string())
+            .map_err(|error| Error::AddFrozen(error.to_string()))
+    }
+
+    fn delete_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        let connection = self.lock()?;
+        connection
+            .srem(self.frozen_key(), address.to_string())
+            .map_err(|error| Error::DeleteFrozen(error.to_string()))
+    }
+
+    fn get_frozen(&self) -> Result<Vec<Ipv4Addr>, Error> {
+        let connection = self.lock()?;
+        let addresses: Vec<String> = connection
+            .smembers(self.frozen_key())
+            .map_err(|error| Error::GetFrozen(error.to_string()))?;
+        let mut frozen = Vec::with_capacity(addresses.len());
+        for address in addresses.into_iter() {
+            frozen.push(
+                address
+                    .parse()
+                    .map_err(|_| Error::GetFrozen(format!("Invalid address {}", address)))?,
+            );
+        }
+        Ok(frozen)
+    }
+
+    fn claim_transaction(
+        &mut self,
+        client_id: &[u8],
+        transaction_id: u32,
+        owner: u32,
+        timeout: u32,
+    ) -> Result<bool, Error> {
+        let connection = self.lock()?;
+        let key = self.transaction_key(client_id);
+        let claim = format!("{} {}", transaction_id, owner);
+        redis::transaction(&*connection, &[&key], |pipe| {
+            let current: Option<String> = connection.get(&key)?;
+            if let Some(current) = current {
+                let claimed = current.split(' ').next();
+                if claimed == Some(transaction_id.to_string().as_str()) && current != claim {
+                    return Ok(Some(false));
+                }
+            }
+            pipe.set_ex(&key, &claim, timeout as usize)
+                .ignore()
+                .query::<Option<()>>(&*connection)
+                .map(|stored| stored.map(|_| true))
+        }).map_err(|error| Error::ClaimTransaction(error.to_string()))
+    }
+}
+
+impl LeaseStorage for RedisStorage {
+    fn get(&self, client_id: &[u8]) -> LeaseFuture<Option<Lease>> {
+        Box::new(future::result(self.get_lease(client_id)))
+    }
+
+    fn get_by_address(&self, address: &Ipv4Addr) -> LeaseFuture<Option<(Vec<u8>, Lease)>> {
+        let result = self.get_client(address).and_then(|client_id| match client_id {
+            Some(client_id) => Ok(self
+                .get_lease(&client_id)?
+                .map(|lease| (client_id, lease))),
+            None => Ok(None),
+        });
+        Box::new(future::result(result))
+    }
+
+    fn put(&mut self, client_id: &[u8], lease: Lease) -> LeaseFuture<()> {
+        let result = self.lock().and_then(|connection| {
+            redis::pipe()
+                .atomic()
+                .set(self.lease_key(client_id), lease.to_record())
+                .ignore()
+                .set(self.client_key(&lease.address()), client_id)
+                .ignore()
+                .sadd(self.leases_key(), hex_encode(client_id))
+                .ignore()
+                .query(&*connection)
+                .map_err(|error| Error::AddLease(error.to_string()))
+        });
+        Box::new(future::result(result))
+    }
+
+    fn compare_and_put(
+        &mut self,
+        client_id: &[u8],
+        expected: Option<Lease>,
+        lease: Lease,
+    ) -> LeaseFuture<bool> {
+        Box::new(future::result(
+            self.compare_and_put_lease(client_id, &expected, &lease),
+        ))
+    }
+
+    fn expire(&mut self) -> LeaseFuture<Vec<(Vec<u8>, Lease)>> {
+        let leases = match self.get_leases() {
+            Ok(leases) => leases,
+            Err(error) => return Box::new(future::err(error)),
+        };
+        let mut expired = Vec::new();
+        for (client_id, lease) in leases.into_iter() {
+            if !lease.is_available() {
+                continue;
+            }
+            let connection = match self.lock() {
+                Ok(connection) => connection,
+                Err(error) => return Box::new(future::err(error)),
+            };
+            let key = self.lease_key(&client_id);
+            let client_key = self.client_key(&lease.address());
+            let leases_key = self.leases_key();
+            // the lease may have been renewed by another server meanwhile
+            let mut current = None;
+            let result = redis::transaction(&*connection, &[&key, &client_key], |pipe| {
+                current = Self::query_lease(&connection, &key)?;
+                if current.as_ref().map_or(true, |current| !current.is_available()) {
+                    return Ok(Some(false));
+                }
+                let owner: Option<Vec<u8>> = connection.get(&client_key)?;
+                pipe.del(&key)
+                    .ignore()
+                    .srem(&leases_key, hex_encode(&client_id))
+                    .ignore();
+                if owner.as_ref() == Some(&client_id) {
+                    pipe.del(&client_key).ignore();
+                }
+                pipe.query::<Option<()>>(&*connection)
+                    .map(|deleted| deleted.map(|_| true))
+            });
+            match (result, current) {
+                (Ok(true), Some(lease)) => expired.push((client_id, lease)),
+                (Ok(_), _) => {}
+                (Err(error), _) => {
+                    return Box::new(future::err(Error::UpdateLease(error.to_string())))
+                }
+            }
+        }
+        Box::new(future::ok(expired))
+    }
+
+    fn iterate(&self) -> LeaseStream {
+        match self.get_leases() {
+            Ok(leases) => Box::new(stream::iter_ok(leases)),
+            Err(error) => Box::new(stream::once(Err(error))),
+        }
+    }
+}
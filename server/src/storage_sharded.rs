@@ -0,0 +1,168 @@
+//! The lease table split across the independently locked shards, so several server
+//! workers on their own threads may share it.
+//!
+//! The leases are sharded by the hash of the client ID, the address associations and
+//! the frozen addresses by the hash of the address. Every operation locks a single shard,
+//! and the reads of the renewal path take only a shared lock of it. An address is claimed
+//! under the lock of its shard, so the workers never associate it with two clients.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    net::Ipv4Addr,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use lease::Lease;
+use storage::{Error, Storage};
+
+/// A storage sharing the inner storages between its clones.
+pub struct ShardedStorage<S>
+where
+    S: Storage,
+{
+    shards: Arc<Vec<RwLock<S>>>,
+}
+
+impl<S> ShardedStorage<S>
+where
+    S: Storage,
+{
+    /// Wraps the shards, which must be empty or sharded by a storage with the same number of them.
+    ///
+    /// # Panics
+    /// If there are no shards.
+    pub fn new(shards: Vec<S>) -> Self {
+        assert!(!shards.is_empty(), "At least one shard is required");
+        ShardedStorage {
+            shards: Arc::new(shards.into_iter().map(RwLock::new).collect()),
+        }
+    }
+
+    fn read<K: Hash + ?Sized>(&self, key: &K) -> Result<RwLockReadGuard<S>, Error> {
+        self.shards[self.index(key)]
+            .read()
+            .map_err(|_| Error::Other("The shard lock is poisoned".to_owned()))
+    }
+
+    fn write<K: Hash + ?Sized>(&self, key: &K) -> Result<RwLockWriteGuard<S>, Error> {
+        self.shards[self.index(key)]
+            .write()
+            .map_err(|_| Error::Other("The shard lock is poisoned".to_owned()))
+    }
+
+    fn index<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+}
+
+impl<S> Clone for ShardedStorage<S>
+where
+    S: Storage,
+{
+    fn clone(&self) -> Self {
+        ShardedStorage {
+            shards: self.shards.clone(),
+        }
+    }
+}
+
+impl<S> Storage for ShardedStorage<S>
+where
+    S: Storage,
+{
+    fn get_client(&self, address: &Ipv4Addr) -> Result<Option<Vec<u8>>, Error> {
+        self.read(address)?.get_client(address)
+    }
+
+    fn add_client(&mut self, address: &Ipv4Addr, client_id: &[u8]) -> Result<(), Error> {
+        self.write(address)?.add_client(address, client_id)
+    }
+
+    fn claim_address(
+        &mut self,
+        address: &Ipv4Addr,
+        client_id: &[u8],
+        owner: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        // the shard is locked for both the comparison and the association
+        self.write(address)?.claim_address(address, client_id, owner)
+    }
+
+    fn delete_client(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        self.write(address)?.delete_client(address)
+    }
+
+    fn get_lease(&self, client_id: &[u8]) -> Result<Option<Lease>, Error> {
+        self.read(client_id)?.get_lease(client_id)
+    }
+
+    fn add_lease(&mut self, client_id: &[u8], lease: Lease) -> Result<(), Error> {
+        self.write(client_id)?.add_lease(client_id, lease)
+    }
+
+    fn update_lease(
+        &mut self,
+        client_id: &[u8],
+        action: &mut FnMut(&mut Lease) -> (),
+    ) -> Result<(), Error> {
+        self.write(client_id)?.update_lease(client_id, action)
+    }
+
+    fn get_leases(&self) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        let mut leases = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard
+                .read()
+                .map_err(|_| Error::Other("The shard lock is poisoned".to_owned()))?;
+            leases.extend(shard.get_leases()?);
+        }
+        Ok(leases)
+    }
+
+    fn check_frozen(&self, address: &Ipv4Addr) -> Result<bool, Error> {
+        self.read(address)?.check_frozen(address)
+    }
+
+    fn add_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        self.write(address)?.add_frozen(address)
+    }
+
+    fn delete_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        self.write(address)?.delete_frozen(address)
+    }
+
+    fn get_frozen(&self) -> Result<Vec<Ipv4Addr>, Error> {
+        let mut frozen = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard
+                .read()
+                .map_err(|_| Error::Other("The shard lock is poisoned".to_owned()))?;
+            frozen.extend(shard.get_frozen()?);
+        }
+        Ok(frozen)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        for shard in self.shards.iter() {
+            shard
+                .write()
+                .map_err(|_| Error::Other("The shard lock is poisoned".to_owned()))?
+                .compact()?;
+        }
+        Ok(())
+    }
+
+    fn claim_transaction(
+        &mut self,
+        client_id: &[u8],
+        transaction_id: u32,
+        owner: u32,
+        timeout: u32,
+    ) -> Result<bool, Error> {
+        self.write(client_id)?
+            .claim_transaction(client_id, transaction_id, owner, timeout)
+    }
+}
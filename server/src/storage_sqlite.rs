@@ -0,0 +1,451 @@
+//! An SQLite implementation of the persistent lease storage of the DHCP server.
+//!
+//! The tables can be queried with any SQLite tool while the server is running.
+
+use std::{
+    net::Ipv4Addr,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use chrono::Utc;
+use futures::{future, stream};
+use rusqlite::{self, types::ToSql, Connection, TransactionBehavior};
+
+use lease::Lease;
+use lease_storage::{LeaseFuture, LeaseStorage, LeaseStream};
+use storage::{Error, Storage};
+
+/// The schema migrations. The schema version is stored in `PRAGMA user_version`,
+/// which is the number of the migrations applied.
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE clients (
+        address TEXT PRIMARY KEY NOT NULL,
+        client_id BLOB NOT NULL
+    );
+    CREATE TABLE leases (
+        client_id BLOB PRIMARY KEY NOT NULL,
+        address TEXT NOT NULL,
+        state TEXT NOT NULL,
+        lease_time INTEGER NOT NULL,
+        offered_at INTEGER NOT NULL,
+        assigned_at INTEGER NOT NULL,
+        renewed_at INTEGER NOT NULL,
+        released_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX leases_address ON leases (address);
+    CREATE TABLE frozen (
+        address TEXT PRIMARY KEY NOT NULL
+    );
+", "
+    CREATE TABLE transactions (
+        client_id BLOB PRIMARY KEY NOT NULL,
+        transaction_id INTEGER NOT NULL,
+        owner INTEGER NOT NULL,
+        claimed_at INTEGER NOT NULL
+    );
+"];
+
+pub struct SqliteStorage {
+    /// The connection is not `Sync`, but the `Storage` trait requires it.
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path` or creates it if it does not exist.
+    ///
+    /// The pending schema migrations are applied in a single transaction.
+    ///
+    /// # Errors
+    /// `rusqlite::Error` if the database cannot be opened or migrated.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, rusqlite::Error> {
+        let mut connection = Connection::open(path)?;
+        Self::migrate(&mut connection)?;
+        Ok(SqliteStorage {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Brings the schema up to the latest version.
+    fn migrate(connection: &mut Connection) -> Result<(), rusqlite::Error> {
+        let transaction = connection.transaction()?;
+        let version: i64 = transaction.query_row("PRAGMA user_version", &[], |row| row.get(0))?;
+        if version as usize > MIGRATIONS.len() {
+            warn!(
+                "The database schema version {} is newer than the supported {}",
+                version,
+                MIGRATIONS.len()
+            );
+        }
+        for (number, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            info!("Applying the database migration #{}", number + 1);
+            transaction.execute_batch(migration)?;
+            transaction.execute_batch(&format!("PRAGMA user_version = {}", number + 1))?;
+        }
+        transaction.commit()
+    }
+
+    /// The connection is locked for a single storage call.
+    fn lock(&self) -> Result<MutexGuard<Connection>, Error> {
+        self.connection
+            .lock()
+            .map_err(|_| Error::Other("The database connection is poisoned".to_owned()))
+    }
+
+    /// Writes the lease columns, which follow the `Lease::to_record` fields.
+    fn put_lease(
+        connection: &Connection,
+        client_id: &[u8],
+        lease: &Lease,
+    ) -> Result<(), rusqlite::Error> {
+        let record = lease.to_record();
+        let fields: Vec<&str> = record.split(' ').collect();
+        let mut values: Vec<&ToSql> = Vec::with_capacity(fields.len() + 1);
+        values.push(&client_id);
+        for field in fields.iter() {
+            values.push(field);
+        }
+        connection.execute(
+            "INSERT OR REPLACE INTO leases (client_id, address, state, lease_time, offered_at, \
+             assigned_at, renewed_at, released_at, expires_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            &values,
+        )?;
+        Ok(())
+    }
+
+    /// Reads the lease columns back into a `Lease`.
+    fn query_lease(
+        connection: &Connection,
+        client_id: &[u8],
+    ) -> Result<Option<Lease>, rusqlite::Error> {
+        let result = connection.query_row(
+            "SELECT address, state, lease_time, offered_at, assigned_at, renewed_at, \
+             released_at, expires_at FROM leases WHERE client_id = ?1",
+            &[&client_id],
+            |row| {
+                let mut fields: Vec<String> = vec![row.get(0), row.get(1)];
+                for index in 2..8 {
+                    fields.push(row.get::<_, i64>(index).to_string());
+                }
+                fields
+            },
+        );
+        let fields = match result {
+            Ok(fields) => fields,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        match Lease::from_record(&fields) {
+            Some(lease) => Ok(Some(lease)),
+            None => Err(rusqlite::Error::InvalidQuery),
+        }
+    }
+
+    /// Reads all the leases with their client IDs.
+    fn query_leases(connection: &Connection) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        let mut statement = connection
+            .prepare(
+                "SELECT client_id, address, state, lease_time, offered_at, assigned_at, \
+                 renewed_at, released_at, expires_at FROM leases",
+            ).map_err(|error| Error::GetLease(error.to_string()))?;
+        let rows = statement
+            .query_map(&[], |row| {
+                let client_id: Vec<u8> = row.get(0);
+                let mut fields: Vec<String> = vec![row.get(1), row.get(2)];
+                for index in 3..9 {
+                    fields.push(row.get::<_, i64>(index).to_string());
+                }
+                (client_id, fields)
+            }).map_err(|error| Error::GetLease(error.to_string()))?;
+        let mut leases = Vec::new();
+        for row in rows {
+            let (client_id, fields) = row.map_err(|error| Error::GetLease(error.to_string()))?;
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            let lease = Lease::from_record(&fields)
+                .ok_or_else(|| Error::GetLease(format!("Malformed lease of {:?}", client_id)))?;
+            leases.push((client_id, lease));
+        }
+        Ok(leases)
+    }
+
+    /// Writes the lease and associates its address with the client.
+    fn store_lease(
+        connection: &Connection,
+        client_id: &[u8],
+        lease: &Lease,
+    ) -> Result<(), rusqlite::Error> {
+        Self::put_lease(connection, client_id, lease)?;
+        connection.execute(
+            "INSERT OR REPLACE INTO clients (address, client_id) VALUES (?1, ?2)",
+            &[&lease.address().to_string() as &ToSql, &client_id],
+        )?;
+        Ok(())
+    }
+
+    /// Stores the lease if the current one is equal to `expected` or unconditionally
+    /// if nothing is expected. Returns `true` if the lease is stored.
+    fn compare_and_store(
+        connection: &mut Connection,
+        client_id: &[u8],
+        expected: Option<&Option<Lease>>,
+        lease: &Lease,
+    ) -> Result<bool, rusqlite::Error> {
+        // the write lock is taken before the read, so the other processes cannot interleave
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if let Some(expected) = expected {
+            if &Self::query_lease(&transaction, client_id)? != expected {
+                return Ok(false);
+            }
+        }
+        Self::store_lease(&transaction, client_id, lease)?;
+        transaction.commit()?;
+        Ok(true)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get_client(&self, address: &Ipv4Addr) -> Result<Option<Vec<u8>>, Error> {
+        let connection = self.lock()?;
+        let result = connection.query_row(
+            "SELECT client_id FROM clients WHERE address = ?1",
+            &[&address.to_string()],
+            |row| row.get(0),
+        );
+        match result {
+            Ok(client_id) => Ok(Some(client_id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(error) => Err(Error::GetClient(error.to_string())),
+        }
+    }
+
+    fn add_client(&mut self, address: &Ipv4Addr, client_id: &[u8]) -> Result<(), Error> {
+        let connection = self.lock()?;
+        connection
+            .execute(
+                "INSERT OR REPLACE INTO clients (address, client_id) VALUES (?1, ?2)",
+                &[&address.to_string() as &ToSql, &client_id],
+            ).map_err(|error| Error::AddClient(error.to_string()))?;
+        Ok(())
+    }
+
+    fn claim_address(
+        &mut self,
+        address: &Ipv4Addr,
+        client_id: &[u8],
+        owner: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let connection = self.lock()?;
+        // a single statement, so the processes sharing the database cannot both claim
+        let claimed = match owner {
+            Some(owner) => connection.execute(
+                "UPDATE clients SET client_id = ?2 WHERE address = ?1 AND client_id = ?3",
+                &[&address.to_string() as &ToSql, &client_id, &owner],
+            ),
+            None => connection.execute(
+                "INSERT OR IGNORE INTO clients (address, client_id) VALUES (?1, ?2)",
+                &[&address.to_string() as &ToSql, &client_id],
+            ),
+        }.map_err(|error| Error::AddClient(error.to_string()))?;
+        Ok(claimed > 0)
+    }
+
+    fn delete_client(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        let connection = self.lock()?;
+        connection
+            .execute(
+                "DELETE FROM clients WHERE address = ?1",
+                &[&address.to_string()],
+            ).map_err(|error| Error::DeleteClient(error.to_string()))?;
+        Ok(())
+    }
+
+    fn get_lease(&self, client_id: &[u8]) -> Result<Option<Lease>, Error> {
+        let connection = self.lock()?;
+        Self::query_lease(&connection, client_id).map_err(|error| Error::GetLease(error.to_string()))
+    }
+
+    fn add_lease(&mut self, client_id: &[u8], lease: Lease) -> Result<(), Error> {
+        let connection = self.lock()?;
+        Self::put_lease(&connection, client_id, &lease)
+            .map_err(|error| Error::AddLease(error.to_string()))
+    }
+
+    fn update_lease(
+        &mut self,
+        client_id: &[u8],
+        action: &mut FnMut(&mut Lease) -> (),
+    ) -> Result<(), Error> {
+        let mut connection = self.lock()?;
+        let transaction = connection
+            .transaction()
+            .map_err(|error| Error::UpdateLease(error.to_string()))?;
+        let mut lease = match Self::query_lease(&transaction, client_id)
+            .map_err(|error| Error::UpdateLease(error.to_string()))?
+        {
+            Some(lease) => lease,
+            None => return Ok(()),
+        };
+        action(&mut lease);
+        Self::put_lease(&transaction, client_id, &lease)
+            .and_then(|_| transaction.commit())
+            .map_err(|error| Error::UpdateLease(error.to_string()))
+    }
+
+    fn get_leases(&self) -> Result<Vec<(Vec<u8>, Lease)>, Error> {
+        let connection = self.lock()?;
+        Self::query_leases(&connection)
+    }
+
+    fn check_frozen(&self, address: &Ipv4Addr) -> Result<bool, Error> {
+        let connection = self.lock()?;
+        let count: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM frozen WHERE address = ?1",
+                &[&address.to_string()],
+                |row| row.get(0),
+            ).map_err(|error| Error::CheckFrozen(error.to_string()))?;
+        Ok(count > 0)
+    }
+
+    fn add_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        let connection = self.lock()?;
+        connection
+            .execute(
+                "INSERT OR IGNORE INTO frozen (address) VALUES (?1)",
+                &[&address.to_string()],
+            ).map_err(|error| Error::AddFrozen(error.to_string()))?;
+        Ok(())
+    }
+
+    fn delete_frozen(&mut self, address: &Ipv4Addr) -> Result<(), Error> {
+        let connection = self.lock()?;
+        connection
+            .execute(
+                "DELETE FROM frozen WHERE address = ?1",
+                &[&address.to_string()],
+            ).map_err(|error| Error::DeleteFrozen(error.to_string()))?;
+        Ok(())
+    }
+
+    fn get_frozen(&self) -> Result<Vec<Ipv4Addr>, Error> {
+        let connection = self.lock()?;
+        let mut statement = connection
+            .prepare("SELECT address FROM frozen")
+            .map_err(|error| Error::GetFrozen(error.to_string()))?;
+        let addresses = statement
+            .query_map(&[], |row| row.get::<_, String>(0))
+            .map_err(|error| Error::GetFrozen(error.to_string()))?;
+        let mut frozen = Vec::new();
+        for address in addresses {
+            let address = address.map_err(|error| Error::GetFrozen(error.to_string()))?;
+            frozen.push(
+                address
+                    .parse()
+                    .map_err(|_| Error::GetFrozen(format!("Invalid address {}", address)))?,
+            );
+        }
+        Ok(frozen)
+    }
+
+    fn claim_transaction(
+        &mut self,
+        client_id: &[u8],
+        transaction_id: u32,
+        owner: u32,
+        timeout: u32,
+    ) -> Result<bool, Error> {
+        let connection = self.lock()?;
+        // a single statement, so the processes sharing the database cannot both claim
+        let claimed = connection
+            .execute(
+                "INSERT OR REPLACE INTO transactions \
+                 (client_id, transaction_id, owner, claimed_at) \
+                 SELECT ?1, ?2, ?3, ?4 WHERE NOT EXISTS (SELECT 1 FROM transactions \
+                 WHERE client_id = ?1 AND transaction_id = ?2 AND owner != ?3 \
+                 AND claimed_at + ?5 > ?4)",
+                &[
+                    &client_id,
+                    &i64::from(transaction_id),
+                    &i64::from(owner),
+                    &Utc::now().timestamp(),
+                    &i64::from(timeout),
+                ],
+            ).map_err(|error| Error::ClaimTransaction(error.to_string()))?;
+        Ok(claimed > 0)
+    }
+}
+
+impl LeaseStorage for SqliteStorage {
+    fn get(&self, client_id: &[u8]) -> LeaseFuture<Option<Lease>> {
+        Box::new(future::result(self.get_lease(client_id)))
+    }
+
+    fn get_by_address(&self, address: &Ipv4Addr) -> LeaseFuture<Option<(Vec<u8>, Lease)>> {
+        let result = self.get_client(address).and_then(|client_id| match client_id {
+            Some(client_id) => Ok(self
+                .get_lease(&client_id)?
+                .map(|lease| (client_id, lease))),
+            None => Ok(None),
+        });
+        Box::new(future::result(result))
+    }
+
+    fn put(&mut self, client_id: &[u8], lease: Lease) -> LeaseFuture<()> {
+        let result = self.lock().and_then(|mut connection| {
+            Self::compare_and_store(&mut connection, client_id, None, &lease)
+                .map(|_| ())
+                .map_err(|error| Error::AddLease(error.to_string()))
+        });
+        Box::new(future::result(result))
+    }
+
+    fn compare_and_put(
+        &mut self,
+        client_id: &[u8],
+        expected: Option<Lease>,
+        lease: Lease,
+    ) -> LeaseFuture<bool> {
+        let result = self.lock().and_then(|mut connection| {
+            Self::compare_and_store(&mut connection, client_id, Some(&expected), &lease)
+                .map_err(|error| Error::UpdateLease(error.to_string()))
+        });
+        Box::new(future::result(result))
+    }
+
+    fn expire(&mut self) -> LeaseFuture<Vec<(Vec<u8>, Lease)>> {
+        let result = self.lock().and_then(|mut connection| {
+            let transaction = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(|error| Error::UpdateLease(error.to_string()))?;
+            let expired: Vec<(Vec<u8>, Lease)> = Self::query_leases(&transaction)?
+                .into_iter()
+                .filter(|(_, lease)| lease.is_available())
+                .collect();
+            for (client_id, lease) in expired.iter() {
+                transaction
+                    .execute("DELETE FROM leases WHERE client_id = ?1", &[client_id as &ToSql])
+                    .and_then(|_| {
+                        transaction.execute(
+                            "DELETE FROM clients WHERE address = ?1 AND client_id = ?2",
+                            &[&lease.address().to_string() as &ToSql, client_id as &ToSql],
+                        )
+                    }).map_err(|error| Error::UpdateLease(error.to_string()))?;
+            }
+            transaction
+                .commit()
+                .map_err(|error| Error::UpdateLease(error.to_string()))?;
+            Ok(expired)
+        });
+        Box::new(future::result(result))
+    }
+
+    fn iterate(&self) -> LeaseStream {
+        match self.get_leases() {
+            Ok(leases) => Box::new(stream::iter_ok(leases)),
+            Err(error) => Box::new(stream::once(Err(error))),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Subnets with their own dynamic address pools.
+
+use std::{cmp, net::Ipv4Addr, sync::Arc};
+
+use dhcp_protocol::RelayAgentInformation;
+
+use access::AccessList;
+use allocator::Allocator;
+use auth::AuthenticationKey;
+use boot::Boot;
+use scope::ScopeOptions;
+
+/// A subnet served by the server directly or via relay agents.
+///
+/// The subnet is selected by the relay agent information if the request
+/// contains a matching one, by `giaddr` if the request is relayed
+/// or by the server address otherwise.
+#[derive(Debug, Clone)]
+pub struct Subnet {
+    /// The network address.
+    pub address: Ipv4Addr,
+    /// Also sent to clients in the `subnet_mask` option.
+    pub mask: Ipv4Addr,
+    /// The inclusive dynamic address ranges, allocated in the order of appearance.
+    pub ranges: Vec<(Ipv4Addr, Ipv4Addr)>,
+    /// The inclusive ranges inside `ranges` which are never allocated, e.g. for printers.
+    pub exclusions: Vec<(Ipv4Addr, Ipv4Addr)>,
+    /// Orders the addresses of each range.
+    pub allocator: Arc<Allocator>,
+    /// Sent to clients in the `routers` option instead of the server-wide ones if not empty.
+    pub routers: Vec<Ipv4Addr>,
+    /// The `(destination, mask, router)` routes sent instead of the server-wide ones
+    /// if not empty.
+    pub classless_static_routes: Vec<(Ipv4Addr, Ipv4Addr, Ipv4Addr)>,
+    /// The relay agent circuit IDs the subnet is selected by regardless of the link address.
+    pub circuit_ids: Vec<Vec<u8>>,
+    /// The relay agent remote IDs the subnet is selected by regardless of the link address.
+    pub remote_ids: Vec<Vec<u8>>,
+    /// If set, the requests for the addresses which are wrong for the subnet
+    /// or unknown to the server get `DHCPNAK` instead of silence.
+    pub authoritative: bool,
+    /// The default and maximal lease times in seconds, replaced by the class and host ones.
+    ///
+    /// The maximal lease time cannot exceed the server-wide one.
+    pub lease_times: Option<(u32, u32)>,
+    /// The renewal (T1) and rebinding (T2) times in seconds,
+    /// replaced by the class and host ones.
+    pub renewal_times: Option<(u32, u32)>,
+    /// Replaces the server-wide access list for the subnet clients.
+    pub access_list: Option<AccessList>,
+    /// Replaces the server-wide network boot parameters for the subnet clients.
+    pub boot: Option<Boot>,
+    /// Enables the RFC 3118 authentication of the requests and responses.
+    pub authentication: Option<AuthenticationKey>,
+    /// Applied after the server-wide and shared network options.
+    pub options: ScopeOptions,
+}
+
+impl Subnet {
+    /// Checks whether the address belongs to the subnet.
+    pub fn contains(&self, address: &Ipv4Addr) -> bool {
+        let mask = u32::from(self.mask);
+        u32::from(*address) & mask == u32::from(self.address) & mask
+    }
+
+    /// Checks whether the relay agent information contains one of the subnet circuit or remote IDs.
+    pub fn matches_relay_agent(&self, information: &RelayAgentInformation) -> bool {
+        let circuit_id_matches = information
+            .circuit_id
+            .as_ref()
+            .map_or(false, |circuit_id| self.circuit_ids.contains(circuit_id));
+        let remote_id_matches = information
+            .remote_id
+            .as_ref()
+            .map_or(false, |remote_id| self.remote_ids.contains(remote_id));
+        circuit_id_matches || remote_id_matches
+    }
+
+    /// Checks whether the address belongs to one of the dynamic ranges and is not excluded.
+    pub fn is_in_ranges(&self, address: &Ipv4Addr) -> bool {
+        is_in_any(&self.ranges, address) && !is_in_any(&self.exclusions, address)
+    }
+}
+
+/// Checks whether the address belongs to one of the inclusive ranges.
+pub fn is_in_any(ranges: &[(Ipv4Addr, Ipv4Addr)], address: &Ipv4Addr) -> bool {
+    ranges
+        .iter()
+        .any(|&(first, last)| first <= *address && *address <= last)
+}
+
+/// Counts the addresses of the inclusive range covered by the other ranges,
+/// which must not overlap.
+pub fn count_covered(range: (Ipv4Addr, Ipv4Addr), ranges: &[(Ipv4Addr, Ipv4Addr)]) -> u32 {
+    let (first, last) = (u32::from(range.0), u32::from(range.1));
+    ranges
+        .iter()
+        .map(|&(other_first, other_last)| {
+            let from = cmp::max(first, u32::from(other_first));
+            let to = cmp::min(last, u32::from(other_last));
+            if from <= to {
+                (to - from).saturating_add(1)
+            } else {
+                0
+            }
+        }).fold(0, u32::saturating_add)
+}
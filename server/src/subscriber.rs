@@ -0,0 +1,146 @@
+//! The per-subscriber lease limits of the ISP deployments.
+//!
+//! The subscribers are recognized by the relay agent circuit or remote ID. The clients
+//! acknowledged behind an ID are remembered, and a new client is refused once the number
+//! of the remembered ones still having active leases reaches the limit.
+//!
+//! The clients are remembered only while the server runs, so after a restart
+//! the existing leases are counted as their clients renew them.
+
+use std::collections::{HashMap, HashSet};
+
+use dhcp_protocol::RelayAgentInformation;
+
+use access::DeniedAction;
+
+/// The clients acknowledged behind every relay agent ID.
+#[derive(Default)]
+struct Subscribers {
+    limit: Option<usize>,
+    clients: HashMap<Vec<u8>, HashSet<Vec<u8>>>,
+    /// The ID every remembered client is behind.
+    ids: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Subscribers {
+    fn permits<F>(&mut self, id: Option<&Vec<u8>>, client_id: &[u8], is_active: &F) -> bool
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let (limit, id) = match (self.limit, id) {
+            (Some(limit), Some(id)) => (limit, id),
+            _ => return true,
+        };
+        let clients = match self.clients.get_mut(id) {
+            Some(clients) => clients,
+            None => return true,
+        };
+        if clients.contains(client_id) {
+            return true;
+        }
+        let ids = &mut self.ids;
+        clients.retain(|client_id| {
+            let is_kept = is_active(&client_id[..]);
+            if !is_kept {
+                ids.remove(client_id);
+            }
+            is_kept
+        });
+        clients.len() < limit
+    }
+
+    fn bind(&mut self, id: Option<&Vec<u8>>, client_id: &[u8]) {
+        if let (Some(_), Some(id)) = (self.limit, id) {
+            // the client may have moved from another port
+            if let Some(previous) = self.ids.insert(client_id.to_vec(), id.to_owned()) {
+                let is_empty = match self.clients.get_mut(&previous) {
+                    Some(clients) => {
+                        clients.remove(client_id);
+                        clients.is_empty()
+                    }
+                    None => false,
+                };
+                if is_empty {
+                    self.clients.remove(&previous);
+                }
+            }
+            self.clients
+                .entry(id.to_owned())
+                .or_insert_with(HashSet::new)
+                .insert(client_id.to_vec());
+        }
+    }
+}
+
+/// The limits of the clients leasing addresses behind a relay agent circuit or remote ID.
+pub struct SubscriberLimits {
+    circuits: Subscribers,
+    remotes: Subscribers,
+    /// Applied to the refused clients.
+    pub denied_action: DeniedAction,
+}
+
+impl Default for SubscriberLimits {
+    fn default() -> Self {
+        SubscriberLimits {
+            circuits: Subscribers::default(),
+            remotes: Subscribers::default(),
+            denied_action: DeniedAction::Ignore,
+        }
+    }
+}
+
+impl SubscriberLimits {
+    /// Limits the clients of every circuit ID and every remote ID, if the limits are set.
+    pub fn new(
+        circuit_limit: Option<usize>,
+        remote_limit: Option<usize>,
+        denied_action: DeniedAction,
+    ) -> Self {
+        SubscriberLimits {
+            circuits: Subscribers {
+                limit: circuit_limit,
+                ..Subscribers::default()
+            },
+            remotes: Subscribers {
+                limit: remote_limit,
+                ..Subscribers::default()
+            },
+            denied_action,
+        }
+    }
+
+    /// Checks whether the client may lease an address behind the relay agent,
+    /// forgetting the remembered clients whose leases are not active anymore.
+    pub fn permits<F>(
+        &mut self,
+        information: Option<&RelayAgentInformation>,
+        client_id: &[u8],
+        is_active: F,
+    ) -> bool
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let information = match information {
+            Some(information) => information,
+            None => return true,
+        };
+        // the clients remembered before a restart are not refused their leases
+        if is_active(client_id) {
+            return true;
+        }
+        self.circuits
+            .permits(information.circuit_id.as_ref(), client_id, &is_active)
+            && self
+                .remotes
+                .permits(information.remote_id.as_ref(), client_id, &is_active)
+    }
+
+    /// Remembers the client acknowledged behind the relay agent.
+    pub fn bind(&mut self, information: Option<&RelayAgentInformation>, client_id: &[u8]) {
+        if let Some(information) = information {
+            self.circuits.bind(information.circuit_id.as_ref(), client_id);
+            self.remotes.bind(information.remote_id.as_ref(), client_id);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! The systemd integration on Linux: the socket activation, the readiness notification
+//! and the watchdog pings.
+//!
+//! A unit starting the server by a socket may look like:
+//!
+//! ```ini
+//! # rust-dhcp.socket
+//! [Socket]
+//! ListenDatagram=0.0.0.0:67
+//! Broadcast=true
+//!
+//! # rust-dhcp.service
+//! [Service]
+//! Type=notify
+//! WatchdogSec=30
+//! ExecStart=/usr/local/bin/rust-dhcp /etc/rust-dhcp.toml
+//! ```
+//!
+//! The sockets not passed by systemd are bound by the server itself.
+
+use std::{
+    env, mem,
+    net::{self, SocketAddr},
+    os::unix::{
+        ffi::OsStringExt,
+        io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+        net::UnixDatagram,
+    },
+    time::{Duration, Instant},
+};
+
+use libc::{self, c_int, c_void, sockaddr, sockaddr_un, socklen_t};
+use tokio::{io, prelude::*, timer::Interval};
+
+/// The first descriptor passed by systemd (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// The state of the service reported to systemd.
+pub struct Systemd {
+    /// The passed sockets not taken yet.
+    listen_fds: Vec<RawFd>,
+    /// Connected to `NOTIFY_SOCKET` in advance, so it is reachable after a `chroot`.
+    notify_socket: Option<UnixDatagram>,
+    /// Triggers the watchdog pings at the half of the timeout.
+    watchdog: Option<Interval>,
+    is_ready: bool,
+}
+
+impl Systemd {
+    /// Reads the variables set by systemd, removing the socket activation ones,
+    /// so they are not inherited by the hook processes.
+    ///
+    /// Only one server in a process gets the passed sockets.
+    pub fn from_env() -> Self {
+        let pid = unsafe { libc::getpid() }.to_string();
+        let is_own = |name: &str| env::var(name).ok().map_or(true, |value| value == pid);
+
+        let listen_fds = match (is_own("LISTEN_PID"), env::var("LISTEN_FDS")) {
+            (true, Ok(count)) => match count.parse::<RawFd>() {
+                Ok(count) => (LISTEN_FDS_START..LISTEN_FDS_START + count)
+                    .filter(|&fd| {
+                        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) == 0 }
+                    }).collect(),
+                Err(_) => {
+                    warn!("Invalid LISTEN_FDS value {}", count);
+                    Vec::new()
+                }
+            },
+            _ => Vec::new(),
+        };
+        for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"].iter() {
+            env::remove_var(name);
+        }
+
+        let notify_socket = env::var_os("NOTIFY_SOCKET").and_then(|path| {
+            let mut path = path.into_vec();
+            // an abstract socket name
+            if path.first() == Some(&b'@') {
+                path[0] = 0;
+            }
+            connect(&path)
+                .map_err(|error| warn!("The systemd notification socket error: {}", error))
+                .ok()
+        });
+        let watchdog = match env::var("WATCHDOG_USEC").map(|usec| usec.parse::<u64>()) {
+            Ok(Ok(usec)) if usec > 0 && is_own("WATCHDOG_PID") => {
+                let interval = Duration::from_micros(usec / 2);
+                Some(Interval::new(Instant::now() + interval, interval))
+            }
+            _ => None,
+        };
+        Systemd {
+            listen_fds,
+            notify_socket,
+            watchdog,
+            is_ready: false,
+        }
+    }
+
+    /// Takes the passed UDP socket bound to the address, if any.
+    ///
+    /// The socket is registered in the reactor by the server socket wrapping it.
+    pub fn take_socket(&mut self, addr: &SocketAddr) -> Option<net::UdpSocket> {
+        let position = self.listen_fds.iter().position(|&fd| {
+            let mut kind: c_int = 0;
+            let mut length = mem::size_of::<c_int>() as socklen_t;
+            let result = unsafe {
+                libc::getsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_TYPE,
+                    &mut kind as *mut c_int as *mut c_void,
+                    &mut length,
+                )
+            };
+            if result < 0 || kind != libc::SOCK_DGRAM {
+                return false;
+            }
+            let socket = unsafe { net::UdpSocket::from_raw_fd(fd) };
+            let is_bound = socket.local_addr().ok() == Some(*addr);
+            // the descriptor is deliberately leaked, since it is still owned by the systemd list
+            let _ = socket.into_raw_fd();
+            is_bound
+        });
+        match position {
+            Some(position) => {
+                let fd = self.listen_fds.remove(position);
+                info!("Using the socket {} passed by systemd", addr);
+                Some(unsafe { net::UdpSocket::from_raw_fd(fd) })
+            }
+            None => None,
+        }
+    }
+
+    /// Reports the server has loaded the pools and started serving.
+    pub fn ready(&mut self) {
+        if !self.is_ready {
+            self.is_ready = true;
+            self.notify("READY=1");
+        }
+    }
+
+    /// Reports the server is shutting down.
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// Pings the watchdog on each watchdog timer tick.
+    pub fn poll_watchdog(&mut self) {
+        loop {
+            match self.watchdog {
+                Some(ref mut watchdog) => match watchdog.poll() {
+                    Ok(Async::Ready(Some(_))) => {}
+                    Ok(Async::Ready(None)) | Ok(Async::NotReady) => return,
+                    Err(error) => {
+                        warn!("Watchdog timer error: {}", error);
+                        return;
+                    }
+                },
+                None => return,
+            }
+            self.notify("WATCHDOG=1");
+        }
+    }
+
+    /// Sends the state to the service manager, if the server is run by one.
+    fn notify(&self, state: &str) {
+        let socket = match self.notify_socket {
+            Some(ref socket) => socket,
+            None => return,
+        };
+        if let Err(error) = socket.send(state.as_bytes()) {
+            warn!("The systemd notification {} error: {}", state, error);
+        }
+    }
+}
+
+/// Connects the notification socket, the `std` addresses do not allow the abstract names.
+fn connect(path: &[u8]) -> io::Result<UnixDatagram> {
+    let mut name: sockaddr_un = unsafe { mem::zeroed() };
+    if path.is_empty() || path.len() >= name.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid NOTIFY_SOCKET path",
+        ));
+    }
+    name.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    for (destination, &byte) in name.sun_path.iter_mut().zip(path.iter()) {
+        *destination = byte as libc::c_char;
+    }
+    let length = mem::size_of::<libc::sa_family_t>() + path.len();
+
+    let socket = UnixDatagram::unbound()?;
+    let result = unsafe {
+        libc::connect(
+            socket.as_raw_fd(),
+            &name as *const sockaddr_un as *const sockaddr,
+            length as socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
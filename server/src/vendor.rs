@@ -0,0 +1,49 @@
+//! The vendor-specific information (option 43) sent to the clients of a vendor class,
+//! e.g. the controller addresses of the access points or the provisioning servers of the phones.
+
+use dhcp_protocol::{Message, OptionTag};
+
+/// The option 43 payload of a vendor class.
+#[derive(Debug, Clone)]
+pub struct VendorClass {
+    /// Used only for logging.
+    pub name: String,
+    /// The prefix of the `class_id` option.
+    pub prefix: Vec<u8>,
+    /// The encapsulated sub-options or the raw data.
+    pub data: Vec<u8>,
+}
+
+impl VendorClass {
+    /// Checks whether the request comes from a client of the vendor class.
+    pub fn matches(&self, request: &Message) -> bool {
+        request
+            .options
+            .class_id
+            .as_ref()
+            .map_or(false, |class_id| class_id.starts_with(&self.prefix))
+    }
+
+    /// Replaces the option 43 inherited from the outer scopes.
+    pub fn apply(&self, options: &mut Vec<(u8, Vec<u8>)>) {
+        let tag = OptionTag::VendorSpecific as u8;
+        options.retain(|&(inherited, _)| inherited != tag);
+        options.push((tag, self.data.to_owned()));
+    }
+}
+
+/// Encodes the sub-options as the RFC 2132 encapsulated vendor-specific information.
+///
+/// Returns `None` if a sub-option is longer than 255 bytes.
+pub fn encode_suboptions(suboptions: &[(u8, Vec<u8>)]) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    for &(code, ref value) in suboptions.iter() {
+        if value.len() > 0xff {
+            return None;
+        }
+        data.push(code);
+        data.push(value.len() as u8);
+        data.extend_from_slice(value);
+    }
+    Some(data)
+}
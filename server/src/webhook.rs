@@ -0,0 +1,317 @@
+//! The webhook notifications of the lease events for the NAC and IPAM systems.
+//!
+//! The events of the event log are posted in batches to an `http://` endpoint, like:
+//!
+//! ```text
+//! POST /dhcp/events HTTP/1.1
+//! Content-Type: application/json
+//!
+//! {"events":[{"timestamp":"2018-08-01T12:00:00Z","event":"committed",
+//! "client_id":"01a1b2c3d4e5f6","address":"192.168.0.10","lease_time":3600}]}
+//! ```
+//!
+//! A batch is posted when it is full or the delay since its first event has elapsed.
+//! The batches are posted one at a time, so the events coming meanwhile make the next
+//! ones bigger. A failed batch is retried after the doubling delays and dropped after
+//! the last attempt. HTTPS is not supported, a local TLS proxy may be used instead.
+
+use std::{
+    mem,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+use serde_json::{self, Value};
+use tokio::{
+    self, io,
+    net::TcpStream,
+    prelude::*,
+    timer::{Deadline, Delay},
+};
+
+use dhcp_backoff::{Exponential, Retry};
+
+use event_log::Record;
+use observer::{LeaseEvent, Observer};
+
+/// The events posted at once by default.
+pub const DEFAULT_WEBHOOK_MAX_BATCH: usize = 100;
+/// The seconds the first event of a batch waits for the others by default.
+pub const DEFAULT_WEBHOOK_DELAY: u64 = 1;
+/// The repeated attempts to post a batch by default.
+pub const DEFAULT_WEBHOOK_RETRIES: u32 = 5;
+
+/// How long to wait for an endpoint response in seconds.
+const RESPONSE_TIMEOUT: u64 = 10;
+/// The delay before the first retry in seconds.
+const RETRY_DELAY: u64 = 1;
+/// The retry delay stops growing at it in seconds.
+const RETRY_DELAY_MAX: u64 = 64;
+/// The events are dropped beyond it while the endpoint is unavailable.
+const SIZE_QUEUE_MAX: usize = 10000;
+
+/// The endpoint the events are posted to.
+struct Endpoint {
+    /// Resolved once on creation.
+    address: SocketAddr,
+    /// The `Host` header value.
+    host: String,
+    path: String,
+    /// The `Authorization` header value, like `Bearer <token>`.
+    authorization: Option<String>,
+}
+
+type Worker = Box<Future<Item = (), Error = ()> + Send>;
+
+/// Posts the lease events as JSON to an HTTP endpoint.
+pub struct WebhookNotifier {
+    sender: Mutex<UnboundedSender<Value>>,
+    /// Spawned with the first event, since the notifier is created outside the runtime.
+    worker: Mutex<Option<Worker>>,
+    /// The events not taken by the worker yet.
+    queued: Arc<AtomicUsize>,
+}
+
+impl WebhookNotifier {
+    /// Resolves the `http://host[:port]/path` URL.
+    ///
+    /// # Errors
+    /// `io::Error` if the URL is invalid or the host is not resolved.
+    pub fn new(
+        url: &str,
+        authorization: Option<String>,
+        max_batch: usize,
+        delay: Duration,
+        retries: u32,
+    ) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "Invalid URL");
+        if !url.starts_with("http://") {
+            return Err(invalid());
+        }
+        let rest = &url["http://".len()..];
+        let (host, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() || path.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+        let mut addresses = if host.contains(':') {
+            host.to_socket_addrs()?
+        } else {
+            (host, 80).to_socket_addrs()?
+        };
+        let address = addresses.next().ok_or_else(invalid)?;
+        if let Some(ref authorization) = authorization {
+            if authorization.contains(|c: char| c == '\r' || c == '\n') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid webhook authorization",
+                ));
+            }
+        }
+        let endpoint = Arc::new(Endpoint {
+            address,
+            host: host.to_owned(),
+            path: path.to_owned(),
+            authorization,
+        });
+
+        let (sender, receiver) = mpsc::unbounded();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let batches = Batches {
+            receiver,
+            queued: queued.clone(),
+            max_batch: max_batch.max(1),
+            delay,
+            batch: Vec::new(),
+            timer: None,
+        };
+        let worker = batches.for_each(move |events| post_batch(endpoint.clone(), events, retries));
+        Ok(WebhookNotifier {
+            sender: Mutex::new(sender),
+            worker: Mutex::new(Some(Box::new(worker))),
+            queued,
+        })
+    }
+}
+
+impl Observer for WebhookNotifier {
+    fn on_lease_event(&self, event: &LeaseEvent) {
+        if let Some(worker) = self.worker.lock().ok().and_then(|mut worker| worker.take()) {
+            tokio::spawn(worker);
+        }
+        if self.queued.load(Ordering::Relaxed) >= SIZE_QUEUE_MAX {
+            warn!("The webhook queue is full, the event is dropped");
+            return;
+        }
+        let value = match serde_json::to_value(&Record::new(event)) {
+            Ok(value) => value,
+            Err(error) => {
+                warn!("Webhook event serialization error: {}", error);
+                return;
+            }
+        };
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let is_sent = self
+            .sender
+            .lock()
+            .ok()
+            .map_or(false, |sender| sender.unbounded_send(value).is_ok());
+        if !is_sent {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Groups the queued events into the batches.
+struct Batches {
+    receiver: UnboundedReceiver<Value>,
+    queued: Arc<AtomicUsize>,
+    max_batch: usize,
+    delay: Duration,
+    batch: Vec<Value>,
+    /// Started with the first event of the batch.
+    timer: Option<Delay>,
+}
+
+impl Batches {
+    fn take(&mut self) -> Vec<Value> {
+        self.timer = None;
+        mem::replace(&mut self.batch, Vec::new())
+    }
+}
+
+impl Stream for Batches {
+    type Item = Vec<Value>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        while self.batch.len() < self.max_batch {
+            match self.receiver.poll()? {
+                Async::Ready(Some(event)) => {
+                    self.queued.fetch_sub(1, Ordering::Relaxed);
+                    if self.batch.is_empty() {
+                        self.timer = Some(Delay::new(Instant::now() + self.delay));
+                    }
+                    self.batch.push(event);
+                }
+                // the notifier has been dropped
+                Async::Ready(None) if self.batch.is_empty() => return Ok(Async::Ready(None)),
+                Async::Ready(None) => return Ok(Async::Ready(Some(self.take()))),
+                Async::NotReady => break,
+            }
+        }
+        if self.batch.len() >= self.max_batch {
+            return Ok(Async::Ready(Some(self.take())));
+        }
+        let is_elapsed = match self.timer {
+            Some(ref mut timer) => match timer.poll() {
+                Ok(Async::NotReady) => false,
+                Ok(Async::Ready(())) => true,
+                Err(error) => {
+                    warn!("Webhook timer error: {}", error);
+                    true
+                }
+            },
+            None => false,
+        };
+        if is_elapsed {
+            Ok(Async::Ready(Some(self.take())))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Batch {
+    events: Vec<Value>,
+}
+
+/// Posts the batch until it is accepted or the attempts are over, never fails.
+fn post_batch(
+    endpoint: Arc<Endpoint>,
+    events: Vec<Value>,
+    retries: u32,
+) -> Box<Future<Item = (), Error = ()> + Send> {
+    let count = events.len();
+    let body = match serde_json::to_vec(&Batch { events }) {
+        Ok(body) => body,
+        Err(error) => {
+            warn!("Webhook batch serialization error: {}", error);
+            return Box::new(future::ok(()));
+        }
+    };
+
+    let mut strategy = Exponential::new(
+        Duration::from_secs(RETRY_DELAY),
+        Duration::from_secs(RETRY_DELAY_MAX),
+    );
+    strategy.with_retries(retries);
+    let future = Retry::new(strategy, move || {
+        post(&endpoint, body.clone()).map_err(|error| warn!("Webhook error: {}", error))
+    }).or_else(move |()| {
+        warn!("The webhook has failed, {} events are dropped", count);
+        Ok(())
+    });
+    Box::new(future)
+}
+
+/// Sends the request on a new connection and checks the response status.
+fn post(endpoint: &Endpoint, body: Vec<u8>) -> Box<Future<Item = (), Error = io::Error> + Send> {
+    let mut head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n",
+        endpoint.path,
+        endpoint.host,
+        body.len()
+    );
+    if let Some(ref authorization) = endpoint.authorization {
+        head.push_str(&format!("Authorization: {}\r\n", authorization));
+    }
+    head.push_str("\r\n");
+    let mut request = head.into_bytes();
+    request.extend(body);
+
+    let future = TcpStream::connect(&endpoint.address)
+        .and_then(move |stream| io::write_all(stream, request))
+        .and_then(|(stream, _)| io::read_to_end(stream, Vec::new()))
+        .and_then(|(_, response)| {
+            // like `HTTP/1.1 204 No Content`
+            let status = response
+                .split(|&byte| byte == b' ')
+                .nth(1)
+                .and_then(|status| String::from_utf8_lossy(status).parse::<u16>().ok());
+            match status {
+                Some(status) if status >= 200 && status < 300 => Ok(()),
+                Some(status) => Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("HTTP status {}", status),
+                )),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid HTTP response",
+                )),
+            }
+        });
+    let future = Deadline::new(future, Instant::now() + Duration::from_secs(RESPONSE_TIMEOUT))
+        .map_err(|error| {
+            if error.is_elapsed() {
+                return io::Error::new(io::ErrorKind::TimedOut, "Webhook response timeout");
+            }
+            match error.into_inner() {
+                Some(error) => error,
+                None => io::Error::new(io::ErrorKind::Other, "Webhook timer error"),
+            }
+        });
+    Box::new(future)
+}
@@ -0,0 +1,286 @@
+//! The hierarchical timer wheel used to schedule the lease expirations.
+
+use std::mem;
+
+/// The slots of a level are indexed by `BITS` bits of the deadline.
+const BITS: u32 = 6;
+const SLOTS: usize = 1 << BITS;
+/// The levels of one second, 64 seconds, ~68 minutes, ~73 hours, ~194 days and ~34 years slots,
+/// so any `u32` deadline fits.
+const LEVELS: usize = 6;
+
+/// Keeps the items in the slots of the levels by how far their deadlines are.
+///
+/// An item is inserted in O(1) into the level whose slots are as long as
+/// the distance to its deadline, and is moved a level down each time its slot comes,
+/// so an item is touched at most `LEVELS` times however far its deadline is.
+pub struct TimerWheel<T> {
+    levels: Vec<Level<T>>,
+    /// The second the wheel has been turned to.
+    elapsed: u64,
+}
+
+struct Level<T> {
+    slots: Vec<Vec<(u32, T)>>,
+    /// The bit of each non-empty slot.
+    occupied: u64,
+}
+
+impl<T> TimerWheel<T> {
+    /// Creates an empty wheel starting at the `now` timestamp in seconds.
+    pub fn new(now: u32) -> Self {
+        TimerWheel {
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+                    occupied: 0,
+                }).collect(),
+            elapsed: u64::from(now),
+        }
+    }
+
+    /// Schedules the item at the `deadline` timestamp in seconds.
+    ///
+    /// An item with a past deadline is returned by the next `expire` call.
+    pub fn insert(&mut self, deadline: u32, item: T) {
+        let deadline = u64::from(deadline).max(self.elapsed);
+        // the levels below the highest bit differing from the current time are passed already
+        let significant = 63 - ((self.elapsed ^ deadline) | (SLOTS as u64 - 1)).leading_zeros();
+        let level = (significant / BITS) as usize;
+        let slot = ((deadline >> (BITS * level as u32)) as usize) & (SLOTS - 1);
+
+        let level = &mut self.levels[level];
+        level.slots[slot].push((deadline as u32, item));
+        level.occupied |= 1 << slot;
+    }
+
+    /// Removes and returns the items whose deadlines have come by `now`.
+    ///
+    /// Only the non-empty slots are visited, so the wheel may be turned rarely.
+    pub fn expire(&mut self, now: u32) -> Vec<T> {
+        let now = u64::from(now);
+        let mut expired = Vec::new();
+        while let Some((level, slot, start)) = self.next_slot() {
+            if start > now {
+                break;
+            }
+            self.elapsed = start;
+            let items = {
+                let level = &mut self.levels[level];
+                level.occupied &= !(1 << slot);
+                mem::replace(&mut level.slots[slot], Vec::new())
+            };
+            for (deadline, item) in items.into_iter() {
+                if u64::from(deadline) <= now {
+                    expired.push(item);
+                } else {
+                    self.insert(deadline, item);
+                }
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+        expired
+    }
+
+    /// The timestamp the earliest non-empty slot starts at.
+    ///
+    /// No item expires before it, so it is the time the wheel must be turned at.
+    pub fn next_deadline(&self) -> Option<u32> {
+        self.next_slot().map(|(_, _, start)| start as u32)
+    }
+
+    /// Returns the level, the index and the start of the earliest non-empty slot.
+    ///
+    /// The slots of a lower level always precede the ones of a higher level,
+    /// and the slots of a level before the current one are always empty,
+    /// since they are expired before the wheel is turned past them.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        for (index, level) in self.levels.iter().enumerate() {
+            if level.occupied == 0 {
+                continue;
+            }
+            let slot_range = 1u64 << (BITS * index as u32);
+            let level_range = slot_range << BITS;
+            let current = ((self.elapsed / slot_range) as usize) & (SLOTS - 1);
+            debug_assert_eq!(level.occupied & ((1 << current) - 1), 0);
+            let slot = (level.occupied >> current).trailing_zeros() as usize + current;
+
+            let start = (self.elapsed & !(level_range - 1)) + slot as u64 * slot_range;
+            return Some((index, slot, start));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The latest deadline a `u32` timestamp allows.
+    const LAST_SECOND: u32 = 0xffff_ffff;
+
+    /// Turns the wheel to every `next_deadline` until it is empty,
+    /// returning the times it has been turned at and the items expired at them.
+    fn drain(wheel: &mut TimerWheel<u32>) -> Vec<(u32, Vec<u32>)> {
+        let mut turns = Vec::new();
+        while let Some(now) = wheel.next_deadline() {
+            let mut expired = wheel.expire(now);
+            expired.sort();
+            turns.push((now, expired));
+        }
+        turns
+    }
+
+    /// The items expired at the turns, which must be the ones due exactly then.
+    fn expired_at_deadlines(turns: &[(u32, Vec<u32>)]) -> Vec<u32> {
+        let mut expired = Vec::new();
+        for &(now, ref items) in turns.iter() {
+            assert!(items.iter().all(|deadline| *deadline == now));
+            expired.extend_from_slice(items);
+        }
+        expired
+    }
+
+    #[test]
+    fn expires_at_deadlines_of_every_level() {
+        let now = 1_000_000;
+        let mut deadlines: Vec<u32> = (0..LEVELS as u32)
+            .flat_map(|level| {
+                let distance = 1u32 << (BITS * level);
+                vec![now + distance, now + distance * 3 + 1]
+            }).collect();
+        deadlines.push(now);
+        deadlines.push(LAST_SECOND);
+
+        let mut wheel = TimerWheel::new(now);
+        for deadline in deadlines.iter().rev() {
+            wheel.insert(*deadline, *deadline);
+        }
+        deadlines.sort();
+
+        let turns = drain(&mut wheel);
+        assert_eq!(expired_at_deadlines(&turns), deadlines);
+        assert!(wheel.expire(LAST_SECOND).is_empty());
+    }
+
+    #[test]
+    fn starts_next_deadline_at_slot() {
+        let now = 0x1000;
+        // the deadline and the start of its slot
+        let cases = [
+            (0x1000, 0x1000),
+            (0x103f, 0x103f),
+            (0x1040, 0x1040),
+            (0x1041, 0x1040),
+            (0x10ff, 0x10c0),
+            (0x2345, 0x2000),
+            (0x0004_5678, 0x0004_0000),
+            (0x0123_4567, 0x0100_0000),
+            (0x8765_4321, 0x8000_0000),
+        ];
+        for &(deadline, start) in cases.iter() {
+            let mut wheel = TimerWheel::new(now);
+            assert_eq!(wheel.next_deadline(), None);
+            wheel.insert(deadline, ());
+            assert_eq!(wheel.next_deadline(), Some(start));
+            // nothing expires before the deadline, however the wheel is turned
+            if deadline > start {
+                assert!(wheel.expire(start).is_empty());
+                assert!(wheel.expire(deadline - 1).is_empty());
+            }
+            assert_eq!(wheel.expire(deadline), vec![()]);
+            assert_eq!(wheel.next_deadline(), None);
+        }
+    }
+
+    #[test]
+    fn expires_past_deadlines_at_once() {
+        let mut wheel = TimerWheel::new(1000);
+        wheel.insert(0, 0);
+        wheel.insert(999, 999);
+        wheel.insert(1000, 1000);
+        wheel.insert(1001, 1001);
+        assert_eq!(wheel.next_deadline(), Some(1000));
+
+        let mut expired = wheel.expire(1000);
+        expired.sort();
+        assert_eq!(expired, vec![0, 999, 1000]);
+
+        // the wheel does not go back
+        wheel.expire(5000);
+        wheel.insert(4000, 4000);
+        assert_eq!(wheel.next_deadline(), Some(5000));
+        assert_eq!(wheel.expire(5000), vec![4000]);
+    }
+
+    #[test]
+    fn cascades_far_future_deadlines() {
+        let mut wheel = TimerWheel::new(0);
+        wheel.insert(LAST_SECOND - 1, LAST_SECOND - 1);
+        wheel.insert(LAST_SECOND, LAST_SECOND);
+        assert_eq!(wheel.next_deadline(), Some(0xc000_0000));
+
+        // every turn moves the items a level down until they reach the one-second slots
+        let turns = drain(&mut wheel);
+        assert!(turns.len() <= LEVELS + 1);
+        assert_eq!(
+            expired_at_deadlines(&turns),
+            vec![LAST_SECOND - 1, LAST_SECOND]
+        );
+    }
+
+    /// The items just before and after the end of a revolution of every level
+    /// never fall behind the current slot.
+    #[test]
+    fn turns_across_revolutions() {
+        for level in 0..LEVELS as u32 - 1 {
+            // the slots of the level run out right after the `now`
+            let boundary = 1u32 << (BITS * (level + 1));
+            let now = boundary - 2;
+            let deadlines = vec![boundary - 1, boundary, boundary + 1, boundary * 2 - 1];
+
+            let mut wheel = TimerWheel::new(now);
+            for deadline in deadlines.iter() {
+                wheel.insert(*deadline, *deadline);
+            }
+            assert_eq!(wheel.next_deadline(), Some(boundary - 1));
+
+            let turns = drain(&mut wheel);
+            assert_eq!(expired_at_deadlines(&turns), deadlines);
+        }
+    }
+
+    #[test]
+    fn expires_like_sorted_list() {
+        let mut wheel = TimerWheel::new(0);
+        let mut pending: Vec<u32> = Vec::new();
+        let mut now = 0u32;
+        // a xorshift generator, so the sequence is the same on every run
+        let mut state = 0x2545_f491u32;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        for _ in 0..10_000 {
+            let distance = random() >> (random() % 32);
+            let deadline = now.saturating_add(distance).saturating_sub(random() % 4);
+            wheel.insert(deadline, deadline);
+            pending.push(deadline);
+
+            if random() % 4 == 0 {
+                now = now.saturating_add(random() >> (random() % 32));
+                let mut items = wheel.expire(now);
+                items.sort();
+                let (mut due, rest): (Vec<u32>, Vec<u32>) =
+                    pending.iter().partition(|deadline| **deadline <= now);
+                due.sort();
+                assert_eq!(items, due);
+                pending = rest;
+            }
+        }
+        assert_eq!(wheel.expire(LAST_SECOND).len(), pending.len());
+    }
+}
@@ -0,0 +1,48 @@
+//! Run this with administrator privileges where it is required
+//! in order to bind the DHCPv6 server socket to its port 547.
+//!
+//! The interface name is the first argument, `eth0` by default.
+
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+extern crate tokio;
+
+extern crate dhcp_server6;
+
+use tokio::prelude::Future;
+
+fn main() {
+    std::env::set_var("RUST_BACKTRACE", "full");
+    std::env::set_var("RUST_LOG", "server=trace,dhcp_server6=trace");
+    env_logger::init();
+
+    let iface_name = std::env::args().nth(1).unwrap_or_else(|| "eth0".to_owned());
+    // DUID-LL of the Ethernet address 02:00:00:00:00:01 (RFC 8415 §11.4)
+    let server_duid = vec![0x00, 0x03, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+    let mut builder = dhcp_server6::ServerBuilder::new(
+        server_duid,
+        iface_name.to_owned(),
+        vec![(
+            "2001:db8::1000".parse().unwrap(),
+            "2001:db8::ffff".parse().unwrap(),
+        )],
+        dhcp_server6::RamStorage::new(),
+    );
+    builder
+        .with_lifetimes(3000, 4000)
+        .with_dns_servers(vec!["2001:db8::53".parse().unwrap()])
+        .with_domain_list(vec!["example.org".to_owned()])
+        .with_preference(10)
+        .with_prefix_pool(dhcp_server6::PrefixPool::new(
+            "2001:db8:100::".parse().unwrap(),
+            40,
+            56,
+        ));
+    let server = builder.finish().expect("Server creating error");
+    let future = server.map_err(|error| error!("Error: {}", error));
+
+    info!("DHCPv6 server started on {}", iface_name);
+    tokio::run(future);
+}
@@ -0,0 +1,540 @@
+//! The DHCPv6 lease database.
+//!
+//! The addresses are chosen by the hash of the DUID and the IAID, so a client is likely
+//! to get the same address after its lease is lost, and the huge pools are not scanned.
+//! The delegated prefixes are chosen the same way.
+
+use std::{collections::HashMap, error, fmt, net::Ipv6Addr};
+
+use dhcp_server::StorageError;
+
+use lease::{Lease, PrefixLease};
+use prefix::{overlaps, PrefixPool};
+use storage::Storage;
+
+/// The addresses tried in the pools before they are considered exhausted.
+const MAX_ALLOCATION_PROBES: u128 = 1024;
+
+/// Errors generated by `Database` methods.
+#[derive(Debug)]
+pub enum Error {
+    Storage(StorageError),
+    PoolsExhausted,
+    BindingNotFound,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Error::Storage(_) => "Database error",
+            Error::PoolsExhausted => "The pools have been exhausted",
+            Error::BindingNotFound => "Binding not found",
+        })
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Storage(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<StorageError> for Error {
+    fn from(error: StorageError) -> Self {
+        Error::Storage(error)
+    }
+}
+
+/// The address pools and the leases of the identity associations.
+pub struct Database<S: Storage> {
+    /// The inclusive address ranges.
+    pools: Vec<(u128, u128)>,
+    prefix_pools: Vec<PrefixPool>,
+    /// The prefixes always delegated to the routers by their DUID.
+    prefix_reservations: HashMap<Vec<u8>, (Ipv6Addr, u8)>,
+    /// The delegated prefix lengths of the routers by their DUID.
+    prefix_lengths: HashMap<Vec<u8>, u8>,
+    storage: S,
+    /// In seconds.
+    preferred_lifetime: u32,
+    /// In seconds.
+    valid_lifetime: u32,
+}
+
+impl<S> Database<S>
+where
+    S: Storage,
+{
+    /// The reversed ranges and the prefix pools longer than their delegated prefixes are skipped.
+    pub fn new(
+        pools: Vec<(Ipv6Addr, Ipv6Addr)>,
+        prefix_pools: Vec<PrefixPool>,
+        prefix_reservations: HashMap<Vec<u8>, (Ipv6Addr, u8)>,
+        prefix_lengths: HashMap<Vec<u8>, u8>,
+        storage: S,
+        preferred_lifetime: u32,
+        valid_lifetime: u32,
+    ) -> Self {
+        let pools = pools
+            .into_iter()
+            .map(|(first, last)| (u128::from(first), u128::from(last)))
+            .filter(|&(first, last)| first <= last)
+            .collect();
+        let prefix_pools = prefix_pools
+            .into_iter()
+            .filter(PrefixPool::is_valid)
+            .collect();
+        Database {
+            pools,
+            prefix_pools,
+            prefix_reservations,
+            prefix_lengths,
+            storage,
+            preferred_lifetime,
+            valid_lifetime,
+        }
+    }
+
+    /// The preferred and valid lifetimes of the assigned leases.
+    pub fn lifetimes(&self) -> (u32, u32) {
+        (self.preferred_lifetime, self.valid_lifetime)
+    }
+
+    /// Chooses an address for the identity association and holds it for a while.
+    ///
+    /// The current address of the binding is preferred, then the one hinted by the client.
+    pub fn advertise(
+        &mut self,
+        duid: &[u8],
+        iaid: u32,
+        hint: Option<Ipv6Addr>,
+    ) -> Result<Lease, Error> {
+        let mut hint = hint;
+        if let Some(lease) = self.storage.get_lease(duid, iaid)? {
+            if !lease.is_expired() {
+                return Ok(lease);
+            }
+            // the expired address is reused unless it has been taken
+            hint = Some(lease.address);
+        }
+        let address = self.choose(duid, iaid, hint)?;
+        let lease = Lease::advertised(duid, iaid, address);
+        self.storage.add_lease(lease.to_owned())?;
+        Ok(lease)
+    }
+
+    /// Assigns an address to the identity association like `advertise` does,
+    /// e.g. on `REQUEST` or on `SOLICIT` with the rapid commit.
+    pub fn assign(
+        &mut self,
+        duid: &[u8],
+        iaid: u32,
+        hint: Option<Ipv6Addr>,
+    ) -> Result<Lease, Error> {
+        let mut lease = self.advertise(duid, iaid, hint)?;
+        lease.renew(self.preferred_lifetime, self.valid_lifetime);
+        self.storage.add_lease(lease.to_owned())?;
+        Ok(lease)
+    }
+
+    /// Extends the lease of the address bound to the identity association.
+    pub fn renew(&mut self, duid: &[u8], iaid: u32, address: &Ipv6Addr) -> Result<Lease, Error> {
+        let mut lease = self.bound_lease(duid, iaid, address)?;
+        if lease.is_advertised {
+            return Err(Error::BindingNotFound);
+        }
+        lease.renew(self.preferred_lifetime, self.valid_lifetime);
+        self.storage.add_lease(lease.to_owned())?;
+        Ok(lease)
+    }
+
+    /// Deletes the lease of the address bound to the identity association.
+    pub fn release(&mut self, duid: &[u8], iaid: u32, address: &Ipv6Addr) -> Result<Lease, Error> {
+        let lease = self.bound_lease(duid, iaid, address)?;
+        self.storage.delete_lease(duid, iaid)?;
+        Ok(lease)
+    }
+
+    /// Deletes the lease of the address bound to the identity association
+    /// and never leases the address again.
+    pub fn decline(&mut self, duid: &[u8], iaid: u32, address: &Ipv6Addr) -> Result<Lease, Error> {
+        let lease = self.release(duid, iaid, address)?;
+        self.storage.add_frozen(address)?;
+        Ok(lease)
+    }
+
+    /// Deletes the expired leases and returns the ones which have been assigned.
+    pub fn expire(&mut self) -> Result<Vec<Lease>, Error> {
+        let mut expired = Vec::new();
+        for lease in self.storage.get_leases()?.into_iter() {
+            if !lease.is_expired() {
+                continue;
+            }
+            self.storage.delete_lease(&lease.duid, lease.iaid)?;
+            if !lease.is_advertised {
+                expired.push(lease);
+            }
+        }
+        Ok(expired)
+    }
+
+    /// All the leases including the advertised ones.
+    pub fn leases(&self) -> Result<Vec<Lease>, Error> {
+        Ok(self.storage.get_leases()?)
+    }
+
+    /// Chooses a prefix for the identity association and holds it for a while.
+    ///
+    /// The reserved prefix is preferred, then the current prefix of the binding,
+    /// then the one hinted by the router if it has the configured length.
+    pub fn advertise_prefix(
+        &mut self,
+        duid: &[u8],
+        iaid: u32,
+        hint: Option<(Ipv6Addr, u8)>,
+    ) -> Result<PrefixLease, Error> {
+        let mut hint = hint;
+        if let Some(lease) = self.storage.get_prefix_lease(duid, iaid)? {
+            if !lease.is_expired() {
+                return Ok(lease);
+            }
+            hint = Some((lease.prefix, lease.prefix_length));
+        }
+        let (prefix, prefix_length) = self.choose_prefix(duid, iaid, hint)?;
+        let lease = PrefixLease::advertised(duid, iaid, prefix, prefix_length);
+        self.storage.add_prefix_lease(lease.to_owned())?;
+        Ok(lease)
+    }
+
+    /// Delegates a prefix to the identity association like `advertise_prefix` does.
+    pub fn assign_prefix(
+        &mut self,
+        duid: &[u8],
+        iaid: u32,
+        hint: Option<(Ipv6Addr, u8)>,
+    ) -> Result<PrefixLease, Error> {
+        let mut lease = self.advertise_prefix(duid, iaid, hint)?;
+        lease.renew(self.preferred_lifetime, self.valid_lifetime);
+        self.storage.add_prefix_lease(lease.to_owned())?;
+        Ok(lease)
+    }
+
+    /// Extends the lease of the prefix delegated to the identity association.
+    pub fn renew_prefix(
+        &mut self,
+        duid: &[u8],
+        iaid: u32,
+        prefix: &Ipv6Addr,
+    ) -> Result<PrefixLease, Error> {
+        let mut lease = self.bound_prefix(duid, iaid, prefix)?;
+        if lease.is_advertised {
+            return Err(Error::BindingNotFound);
+        }
+        lease.renew(self.preferred_lifetime, self.valid_lifetime);
+        self.storage.add_prefix_lease(lease.to_owned())?;
+        Ok(lease)
+    }
+
+    /// Deletes the lease of the prefix delegated to the identity association.
+    pub fn release_prefix(
+        &mut self,
+        duid: &[u8],
+        iaid: u32,
+        prefix: &Ipv6Addr,
+    ) -> Result<PrefixLease, Error> {
+        let lease = self.bound_prefix(duid, iaid, prefix)?;
+        self.storage.delete_prefix_lease(duid, iaid)?;
+        Ok(lease)
+    }
+
+    /// Deletes the expired prefix leases and returns the ones which have been delegated.
+    pub fn expire_prefixes(&mut self) -> Result<Vec<PrefixLease>, Error> {
+        let mut expired = Vec::new();
+        for lease in self.storage.get_prefix_leases()?.into_iter() {
+            if !lease.is_expired() {
+                continue;
+            }
+            self.storage.delete_prefix_lease(&lease.duid, lease.iaid)?;
+            if !lease.is_advertised {
+                expired.push(lease);
+            }
+        }
+        Ok(expired)
+    }
+
+    /// All the prefix leases including the advertised ones.
+    pub fn prefix_leases(&self) -> Result<Vec<PrefixLease>, Error> {
+        Ok(self.storage.get_prefix_leases()?)
+    }
+
+    /// Whether the prefix may be delegated by this server.
+    pub fn is_delegable(&self, prefix: &Ipv6Addr, prefix_length: u8) -> bool {
+        self.prefix_pools
+            .iter()
+            .any(|pool| pool.contains(prefix, prefix_length))
+            || self
+                .prefix_reservations
+                .values()
+                .any(|&reserved| reserved == (*prefix, prefix_length))
+    }
+
+    /// Whether the address belongs to one of the pools.
+    pub fn is_on_link(&self, address: &Ipv6Addr) -> bool {
+        let address = u128::from(*address);
+        self.pools
+            .iter()
+            .any(|&(first, last)| first <= address && address <= last)
+    }
+
+    /// Returns the lease if the address is bound to the identity association.
+    fn bound_lease(&self, duid: &[u8], iaid: u32, address: &Ipv6Addr) -> Result<Lease, Error> {
+        match self.storage.get_lease(duid, iaid)? {
+            Some(ref lease) if lease.address == *address && !lease.is_expired() => {
+                Ok(lease.to_owned())
+            }
+            _ => Err(Error::BindingNotFound),
+        }
+    }
+
+    /// Returns the prefix lease if the prefix is delegated to the identity association.
+    fn bound_prefix(
+        &self,
+        duid: &[u8],
+        iaid: u32,
+        prefix: &Ipv6Addr,
+    ) -> Result<PrefixLease, Error> {
+        match self.storage.get_prefix_lease(duid, iaid)? {
+            Some(ref lease) if lease.prefix == *prefix && !lease.is_expired() => {
+                Ok(lease.to_owned())
+            }
+            _ => Err(Error::BindingNotFound),
+        }
+    }
+
+    /// Checks whether the address may be leased to the identity association.
+    fn is_available(&self, duid: &[u8], iaid: u32, address: &Ipv6Addr) -> Result<bool, Error> {
+        if !self.is_on_link(address) || self.storage.check_frozen(address)? {
+            return Ok(false);
+        }
+        Ok(match self.storage.get_binding(address)? {
+            Some((ref bound_duid, bound_iaid)) if bound_duid == duid && bound_iaid == iaid => true,
+            Some((ref bound_duid, bound_iaid)) => self
+                .storage
+                .get_lease(bound_duid, bound_iaid)?
+                .map_or(true, |lease| {
+                    lease.address != *address || lease.is_expired()
+                }),
+            None => true,
+        })
+    }
+
+    /// Chooses a free address, starting at the hinted one and then at the hash of the binding.
+    fn choose(&self, duid: &[u8], iaid: u32, hint: Option<Ipv6Addr>) -> Result<Ipv6Addr, Error> {
+        if let Some(hint) = hint {
+            if self.is_available(duid, iaid, &hint)? {
+                return Ok(hint);
+            }
+        }
+
+        let size: u128 = self
+            .pools
+            .iter()
+            .map(|&(first, last)| (last - first).saturating_add(1))
+            .fold(0, |total: u128, size| total.saturating_add(size));
+        if size == 0 {
+            return Err(Error::PoolsExhausted);
+        }
+        let start = u128::from(binding_hash(duid, iaid)) % size;
+        for step in 0..MAX_ALLOCATION_PROBES.min(size) {
+            // the offset in the pools is mapped to the pool it falls into
+            let mut offset = (start + step) % size;
+            for &(first, last) in self.pools.iter() {
+                let pool_size = (last - first).saturating_add(1);
+                if offset < pool_size {
+                    let address = Ipv6Addr::from(first + offset);
+                    if self.is_available(duid, iaid, &address)? {
+                        return Ok(address);
+                    }
+                    break;
+                }
+                offset -= pool_size;
+            }
+        }
+        Err(Error::PoolsExhausted)
+    }
+
+    /// Chooses a prefix not overlapping the ones delegated to or reserved for the other routers.
+    fn choose_prefix(
+        &self,
+        duid: &[u8],
+        iaid: u32,
+        hint: Option<(Ipv6Addr, u8)>,
+    ) -> Result<(Ipv6Addr, u8), Error> {
+        let delegated: Vec<(Ipv6Addr, u8)> = self
+            .storage
+            .get_prefix_leases()?
+            .into_iter()
+            .filter(|lease| !lease.is_expired() && (lease.duid != duid || lease.iaid != iaid))
+            .map(|lease| (lease.prefix, lease.prefix_length))
+            .collect();
+        let reservations = &self.prefix_reservations;
+        let is_free = |prefix: (Ipv6Addr, u8)| {
+            !delegated.iter().any(|&other| overlaps(other, prefix))
+                && !reservations.iter().any(|(owner, &reserved)| {
+                    owner.as_slice() != duid && overlaps(reserved, prefix)
+                })
+        };
+
+        // the reservation may be taken by another IA of the same router
+        if let Some(&reserved) = self.prefix_reservations.get(duid) {
+            if is_free(reserved) {
+                return Ok(reserved);
+            }
+        }
+        let configured_length = self.prefix_lengths.get(duid).cloned();
+        if let Some((prefix, prefix_length)) = hint {
+            let is_suitable = self.prefix_pools.iter().any(|pool| {
+                pool.contains(&prefix, prefix_length)
+                    && configured_length.unwrap_or(pool.delegated_length) == prefix_length
+            });
+            if is_suitable && is_free((prefix, prefix_length)) {
+                return Ok((prefix, prefix_length));
+            }
+        }
+
+        let hash = u128::from(binding_hash(duid, iaid));
+        for pool in self.prefix_pools.iter() {
+            let prefix_length = configured_length.unwrap_or(pool.delegated_length);
+            if prefix_length < pool.prefix_length || prefix_length > 128 {
+                continue;
+            }
+            let size = pool.size(prefix_length);
+            let start = hash % size;
+            for step in 0..MAX_ALLOCATION_PROBES.min(size) {
+                let prefix = pool.nth(prefix_length, (start + step) % size);
+                if is_free((prefix, prefix_length)) {
+                    return Ok((prefix, prefix_length));
+                }
+            }
+        }
+        Err(Error::PoolsExhausted)
+    }
+}
+
+/// The hash of the binding the allocations start at.
+fn binding_hash(duid: &[u8], iaid: u32) -> u64 {
+    let mut key = duid.to_vec();
+    key.extend_from_slice(&[
+        (iaid >> 24) as u8,
+        (iaid >> 16) as u8,
+        (iaid >> 8) as u8,
+        iaid as u8,
+    ]);
+    fnv1a(&key)
+}
+
+/// The 64-bit FNV-1a hash, which is stable across the restarts and the builds.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage_ram::RamStorage;
+
+    const ROUTER_DUID: &[u8] = &[0, 3, 0, 1, 2, 0, 0, 0, 0, 2];
+    const SIZED_ROUTER_DUID: &[u8] = &[0, 3, 0, 1, 2, 0, 0, 0, 0, 3];
+
+    fn database() -> Database<RamStorage> {
+        let mut prefix_reservations = HashMap::new();
+        prefix_reservations.insert(
+            ROUTER_DUID.to_vec(),
+            ("2001:db8:100:ff00::".parse().unwrap(), 56),
+        );
+        let mut prefix_lengths = HashMap::new();
+        prefix_lengths.insert(SIZED_ROUTER_DUID.to_vec(), 48);
+        Database::new(
+            vec![(
+                "2001:db8::1000".parse().unwrap(),
+                "2001:db8::1fff".parse().unwrap(),
+            )],
+            vec![PrefixPool::new("2001:db8:100::".parse().unwrap(), 40, 56)],
+            prefix_reservations,
+            prefix_lengths,
+            RamStorage::new(),
+            3600,
+            7200,
+        )
+    }
+
+    #[test]
+    fn assigns_advertised_address() {
+        let mut database = database();
+        let duid = vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 1];
+
+        let advertised = database.advertise(&duid, 1, None).unwrap();
+        assert!(advertised.is_advertised);
+        assert!(database.is_on_link(&advertised.address));
+
+        let assigned = database.assign(&duid, 1, None).unwrap();
+        assert!(!assigned.is_advertised);
+        assert_eq!(advertised.address, assigned.address);
+        assert_eq!(
+            (3600, 7200),
+            (assigned.preferred_lifetime, assigned.valid_lifetime)
+        );
+    }
+
+    #[test]
+    fn leases_different_addresses_to_different_ias() {
+        let mut database = database();
+        let duid = vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 1];
+        let hint = "2001:db8::1234".parse().unwrap();
+
+        let first = database.assign(&duid, 1, Some(hint)).unwrap();
+        let second = database.assign(&duid, 2, Some(hint)).unwrap();
+        assert_eq!(hint, first.address);
+        assert_ne!(first.address, second.address);
+    }
+
+    #[test]
+    fn never_leases_declined_address() {
+        let mut database = database();
+        let duid = vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 1];
+
+        let lease = database.assign(&duid, 1, None).unwrap();
+        database.decline(&duid, 1, &lease.address).unwrap();
+        let renewed = database.renew(&duid, 1, &lease.address);
+        assert!(renewed.is_err());
+
+        let another = database.assign(&duid, 1, Some(lease.address)).unwrap();
+        assert_ne!(lease.address, another.address);
+    }
+
+    #[test]
+    fn delegates_reserved_and_sized_prefixes() {
+        let mut database = database();
+
+        let reserved = database.assign_prefix(ROUTER_DUID, 1, None).unwrap();
+        assert_eq!(
+            ("2001:db8:100:ff00::".parse().unwrap(), 56),
+            (reserved.prefix, reserved.prefix_length)
+        );
+        let another = database.assign_prefix(ROUTER_DUID, 2, None).unwrap();
+        assert_ne!(reserved.prefix, another.prefix);
+
+        let sized = database.assign_prefix(SIZED_ROUTER_DUID, 1, None).unwrap();
+        assert_eq!(48, sized.prefix_length);
+        assert!(!overlaps(
+            (sized.prefix, sized.prefix_length),
+            (reserved.prefix, reserved.prefix_length)
+        ));
+        assert!(database.is_delegable(&sized.prefix, sized.prefix_length));
+    }
+}
@@ -0,0 +1,160 @@
+//! The DHCPv6 address and prefix lease implementation.
+
+use std::net::Ipv6Addr;
+
+use chrono::prelude::*;
+use dhcp_protocol::v6::constants::INFINITY;
+
+/// A client has only `ADVERTISE_TIMEOUT` seconds to request an advertised address.
+pub const ADVERTISE_TIMEOUT: u32 = 60;
+
+/// A non-temporary address bound to an identity association of a client (RFC 8415 §12.1).
+///
+/// The binding is identified by the client DUID and the IAID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lease {
+    pub duid: Vec<u8>,
+    pub iaid: u32,
+    pub address: Ipv6Addr,
+    /// In seconds.
+    pub preferred_lifetime: u32,
+    /// In seconds.
+    pub valid_lifetime: u32,
+    /// The Unix time the address has been advertised, assigned or renewed at.
+    pub updated_at: u32,
+    /// Whether the address has been only advertised and not requested yet.
+    pub is_advertised: bool,
+}
+
+impl Lease {
+    /// Creates a lease of an advertised address, which expires in `ADVERTISE_TIMEOUT`.
+    pub fn advertised(duid: &[u8], iaid: u32, address: Ipv6Addr) -> Self {
+        Lease {
+            duid: duid.to_vec(),
+            iaid,
+            address,
+            preferred_lifetime: ADVERTISE_TIMEOUT,
+            valid_lifetime: ADVERTISE_TIMEOUT,
+            updated_at: Utc::now().timestamp() as u32,
+            is_advertised: true,
+        }
+    }
+
+    /// Assigns the address or extends the lease with the lifetimes.
+    pub fn renew(&mut self, preferred_lifetime: u32, valid_lifetime: u32) {
+        self.preferred_lifetime = preferred_lifetime;
+        self.valid_lifetime = valid_lifetime;
+        self.updated_at = Utc::now().timestamp() as u32;
+        self.is_advertised = false;
+    }
+
+    /// The Unix time the lease expires at, which is 0 for the infinite leases.
+    pub fn expires_at(&self) -> u32 {
+        expires_at(self.updated_at, self.valid_lifetime)
+    }
+
+    /// Whether the valid lifetime has passed.
+    pub fn is_expired(&self) -> bool {
+        is_expired(self.updated_at, self.valid_lifetime)
+    }
+
+    /// The lifetimes left until now, which are sent in the replies.
+    pub fn remaining_lifetimes(&self) -> (u32, u32) {
+        remaining_lifetimes(
+            self.updated_at,
+            self.preferred_lifetime,
+            self.valid_lifetime,
+        )
+    }
+}
+
+/// A prefix delegated to an identity association of a requesting router (RFC 8415 §6.3).
+///
+/// The binding is identified by the client DUID and the IAID of the `IA_PD`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefixLease {
+    pub duid: Vec<u8>,
+    pub iaid: u32,
+    pub prefix: Ipv6Addr,
+    pub prefix_length: u8,
+    /// In seconds.
+    pub preferred_lifetime: u32,
+    /// In seconds.
+    pub valid_lifetime: u32,
+    /// The Unix time the prefix has been advertised, delegated or renewed at.
+    pub updated_at: u32,
+    /// Whether the prefix has been only advertised and not requested yet.
+    pub is_advertised: bool,
+}
+
+impl PrefixLease {
+    /// Creates a lease of an advertised prefix, which expires in `ADVERTISE_TIMEOUT`.
+    pub fn advertised(duid: &[u8], iaid: u32, prefix: Ipv6Addr, prefix_length: u8) -> Self {
+        PrefixLease {
+            duid: duid.to_vec(),
+            iaid,
+            prefix,
+            prefix_length,
+            preferred_lifetime: ADVERTISE_TIMEOUT,
+            valid_lifetime: ADVERTISE_TIMEOUT,
+            updated_at: Utc::now().timestamp() as u32,
+            is_advertised: true,
+        }
+    }
+
+    /// Delegates the prefix or extends the lease with the lifetimes.
+    pub fn renew(&mut self, preferred_lifetime: u32, valid_lifetime: u32) {
+        self.preferred_lifetime = preferred_lifetime;
+        self.valid_lifetime = valid_lifetime;
+        self.updated_at = Utc::now().timestamp() as u32;
+        self.is_advertised = false;
+    }
+
+    /// The Unix time the lease expires at, which is 0 for the infinite leases.
+    pub fn expires_at(&self) -> u32 {
+        expires_at(self.updated_at, self.valid_lifetime)
+    }
+
+    /// Whether the valid lifetime has passed.
+    pub fn is_expired(&self) -> bool {
+        is_expired(self.updated_at, self.valid_lifetime)
+    }
+
+    /// The lifetimes left until now, which are sent in the replies.
+    pub fn remaining_lifetimes(&self) -> (u32, u32) {
+        remaining_lifetimes(
+            self.updated_at,
+            self.preferred_lifetime,
+            self.valid_lifetime,
+        )
+    }
+}
+
+fn expires_at(updated_at: u32, valid_lifetime: u32) -> u32 {
+    if valid_lifetime == INFINITY {
+        0
+    } else {
+        updated_at.saturating_add(valid_lifetime)
+    }
+}
+
+fn is_expired(updated_at: u32, valid_lifetime: u32) -> bool {
+    let expires_at = expires_at(updated_at, valid_lifetime);
+    expires_at != 0 && expires_at <= Utc::now().timestamp() as u32
+}
+
+fn remaining_lifetimes(
+    updated_at: u32,
+    preferred_lifetime: u32,
+    valid_lifetime: u32,
+) -> (u32, u32) {
+    let elapsed = (Utc::now().timestamp() as u32).saturating_sub(updated_at);
+    let remaining = |lifetime: u32| {
+        if lifetime == INFINITY {
+            INFINITY
+        } else {
+            lifetime.saturating_sub(elapsed)
+        }
+    };
+    (remaining(preferred_lifetime), remaining(valid_lifetime))
+}
@@ -0,0 +1,37 @@
+//! The Rust DHCPv6 stateful server implementation.
+//!
+//! Leases the non-temporary addresses (`IA_NA`) and delegates the prefixes (`IA_PD`)
+//! to the directly connected clients.
+//! The relayed messages are not served yet.
+//!
+//! The admin API, the metrics and the hook commands are the ones of the DHCPv4 server.
+
+mod database;
+mod lease;
+mod prefix;
+mod server;
+mod storage;
+mod storage_ram;
+
+#[macro_use]
+extern crate log;
+extern crate chrono;
+#[macro_use]
+extern crate futures;
+extern crate tokio;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+extern crate dhcp_framed;
+extern crate dhcp_protocol;
+extern crate dhcp_server;
+
+pub use self::{
+    database::Error as DatabaseError,
+    lease::{Lease, PrefixLease},
+    prefix::PrefixPool,
+    server::{Server, ServerBuilder},
+    storage::Storage,
+    storage_ram::RamStorage,
+};
@@ -0,0 +1,66 @@
+//! The delegated prefix arithmetic.
+
+use std::net::Ipv6Addr;
+
+/// The prefixes are delegated from the pool with `delegated_length`
+/// unless another length is configured for the requesting router.
+#[derive(Debug, Clone)]
+pub struct PrefixPool {
+    pub prefix: Ipv6Addr,
+    pub prefix_length: u8,
+    pub delegated_length: u8,
+}
+
+impl PrefixPool {
+    /// The host bits of the prefix are cleared.
+    pub fn new(prefix: Ipv6Addr, prefix_length: u8, delegated_length: u8) -> Self {
+        PrefixPool {
+            prefix: Ipv6Addr::from(mask(&prefix, prefix_length)),
+            prefix_length,
+            delegated_length,
+        }
+    }
+
+    /// Whether the pool is not longer than the delegated prefixes.
+    pub fn is_valid(&self) -> bool {
+        self.prefix_length <= self.delegated_length && self.delegated_length <= 128
+    }
+
+    /// Whether the prefix lies within the pool.
+    pub fn contains(&self, prefix: &Ipv6Addr, prefix_length: u8) -> bool {
+        prefix_length >= self.prefix_length
+            && prefix_length <= 128
+            && mask(prefix, self.prefix_length) == u128::from(self.prefix)
+    }
+
+    /// The number of the prefixes of the length in the pool, saturated.
+    pub(crate) fn size(&self, prefix_length: u8) -> u128 {
+        match prefix_length - self.prefix_length {
+            bits if bits >= 128 => u128::max_value(),
+            bits => 1 << bits,
+        }
+    }
+
+    /// The prefix of the length at the index.
+    pub(crate) fn nth(&self, prefix_length: u8, index: u128) -> Ipv6Addr {
+        if prefix_length == 0 {
+            return self.prefix;
+        }
+        Ipv6Addr::from(u128::from(self.prefix) | (index << (128 - u32::from(prefix_length))))
+    }
+}
+
+/// Clears the bits after the prefix length.
+pub(crate) fn mask(prefix: &Ipv6Addr, prefix_length: u8) -> u128 {
+    match prefix_length {
+        0 => 0,
+        length if length >= 128 => u128::from(*prefix),
+        length => u128::from(*prefix) & (u128::max_value() << (128 - u32::from(length))),
+    }
+}
+
+/// Whether one of the prefixes contains the other one.
+pub(crate) fn overlaps(first: (Ipv6Addr, u8), second: (Ipv6Addr, u8)) -> bool {
+    let length = first.1.min(second.1);
+    mask(&first.0, length) == mask(&second.0, length)
+}
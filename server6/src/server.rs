@@ -0,0 +1,956 @@
+//! The main DHCPv6 server module.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use tokio::{io, net::UdpSocket, prelude::*, timer::Interval};
+
+#[cfg(target_os = "linux")]
+use dhcp_framed::interface_index;
+use dhcp_framed::Dhcp6Framed;
+use dhcp_protocol::v6::{
+    constants::*, IaAddress, IaNa, IaPd, IaPrefix, Message, MessageType, OptionCode, Options,
+    Status, StatusCode,
+};
+use dhcp_server::{
+    CommandHook, HttpListener, HttpResponse as Response, Metrics, ERROR_ALLOCATION, ERROR_RENEWAL,
+    ERROR_STORAGE, ERROR_VALIDATION,
+};
+
+use database::{Database, Error};
+use lease::{Lease, PrefixLease};
+use prefix::PrefixPool;
+use storage::Storage;
+
+/// The default preferred lifetime of the assigned addresses in seconds.
+const DEFAULT_PREFERRED_LIFETIME: u32 = 43200;
+/// The default valid lifetime of the assigned addresses in seconds.
+const DEFAULT_VALID_LIFETIME: u32 = 86400;
+/// How often the expired leases are deleted in seconds.
+const EXPIRATION_INTERVAL: u64 = 60;
+
+/// Builds the `Server` on the interface the clients are connected to.
+pub struct ServerBuilder<S: Storage> {
+    server_duid: Vec<u8>,
+    iface_name: String,
+    pools: Vec<(Ipv6Addr, Ipv6Addr)>,
+    prefix_pools: Vec<PrefixPool>,
+    prefix_reservations: HashMap<Vec<u8>, (Ipv6Addr, u8)>,
+    prefix_lengths: HashMap<Vec<u8>, u8>,
+    storage: S,
+    preferred_lifetime: u32,
+    valid_lifetime: u32,
+    dns_servers: Vec<Ipv6Addr>,
+    domain_list: Vec<String>,
+    rapid_commit: bool,
+    preference: Option<u8>,
+    admin_api: Option<(SocketAddr, Option<String>)>,
+    metrics: Option<SocketAddr>,
+    hooks: Vec<CommandHook>,
+}
+
+impl<S> ServerBuilder<S>
+where
+    S: Storage,
+{
+    /// Creates a builder with the mandatory parameters.
+    ///
+    /// `server_duid` is sent in the `Server Identifier` option, so it must be stable
+    /// across the restarts (RFC 8415 §11).
+    pub fn new(
+        server_duid: Vec<u8>,
+        iface_name: String,
+        pools: Vec<(Ipv6Addr, Ipv6Addr)>,
+        storage: S,
+    ) -> Self {
+        ServerBuilder {
+            server_duid,
+            iface_name,
+            pools,
+            prefix_pools: Vec::new(),
+            prefix_reservations: HashMap::new(),
+            prefix_lengths: HashMap::new(),
+            storage,
+            preferred_lifetime: DEFAULT_PREFERRED_LIFETIME,
+            valid_lifetime: DEFAULT_VALID_LIFETIME,
+            dns_servers: Vec::new(),
+            domain_list: Vec::new(),
+            rapid_commit: false,
+            preference: None,
+            admin_api: None,
+            metrics: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// The lifetimes of the assigned addresses in seconds.
+    pub fn with_lifetimes(&mut self, preferred_lifetime: u32, valid_lifetime: u32) -> &mut Self {
+        self.preferred_lifetime = preferred_lifetime;
+        self.valid_lifetime = valid_lifetime;
+        self
+    }
+
+    /// Delegates the prefixes to the requesting routers in `IA_PD` (RFC 8415 §6.3).
+    pub fn with_prefix_pool(&mut self, pool: PrefixPool) -> &mut Self {
+        self.prefix_pools.push(pool);
+        self
+    }
+
+    /// Always delegates the prefix to the router with the DUID.
+    ///
+    /// The prefix need not belong to a pool, but must not overlap the other reservations.
+    pub fn with_prefix_reservation(
+        &mut self,
+        duid: Vec<u8>,
+        prefix: Ipv6Addr,
+        prefix_length: u8,
+    ) -> &mut Self {
+        self.prefix_reservations
+            .insert(duid, (prefix, prefix_length));
+        self
+    }
+
+    /// Delegates the prefixes of the length instead of the pool one to the router with the DUID,
+    /// e.g. a `/48` to a business customer in a pool of the residential `/56` prefixes.
+    pub fn with_prefix_length(&mut self, duid: Vec<u8>, prefix_length: u8) -> &mut Self {
+        self.prefix_lengths.insert(duid, prefix_length);
+        self
+    }
+
+    /// Sent to the clients requesting the `DNS Recursive Name Server` option.
+    pub fn with_dns_servers(&mut self, dns_servers: Vec<Ipv6Addr>) -> &mut Self {
+        self.dns_servers = dns_servers;
+        self
+    }
+
+    /// Sent to the clients requesting the `Domain Search List` option.
+    pub fn with_domain_list(&mut self, domain_list: Vec<String>) -> &mut Self {
+        self.domain_list = domain_list;
+        self
+    }
+
+    /// Assigns the addresses right on `SOLICIT` with the `Rapid Commit` option.
+    ///
+    /// Enable it only if this server is the only one on the link (RFC 8415 §18.3.1).
+    pub fn with_rapid_commit(&mut self) -> &mut Self {
+        self.rapid_commit = true;
+        self
+    }
+
+    /// Sent in `ADVERTISE`. `PREFERENCE_MAXIMAL` makes the clients request this server at once.
+    pub fn with_preference(&mut self, preference: u8) -> &mut Self {
+        self.preference = Some(preference);
+        self
+    }
+
+    /// Serves `GET /leases`. The token is required in the `Authorization: Bearer` header.
+    pub fn with_admin_api(&mut self, address: SocketAddr, token: Option<String>) -> &mut Self {
+        self.admin_api = Some((address, token));
+        self
+    }
+
+    /// Serves the Prometheus metrics at `GET /metrics`.
+    pub fn with_metrics(&mut self, address: SocketAddr) -> &mut Self {
+        self.metrics = Some(address);
+        self
+    }
+
+    /// Runs the command on the lease commits, renewals, releases and expirations.
+    pub fn with_hook(&mut self, hook: CommandHook) -> &mut Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Binds the socket and the listeners.
+    ///
+    /// # Errors
+    /// `io::Error` on unsuccessful socket binding or multicast group joining.
+    pub fn finish(self) -> io::Result<Server<S>> {
+        let address = SocketAddr::new(
+            IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+            DHCP6_PORT_SERVER,
+        );
+        let socket = UdpSocket::bind(&address)?;
+        #[cfg(target_os = "linux")]
+        let interface = interface_index(&self.iface_name)?;
+        #[cfg(not(target_os = "linux"))]
+        let interface = 0;
+        socket.join_multicast_v6(&ALL_DHCP_RELAY_AGENTS_AND_SERVERS, interface)?;
+        info!(
+            "Listening on {} at {}",
+            self.iface_name, ALL_DHCP_RELAY_AGENTS_AND_SERVERS
+        );
+
+        let admin_api = match self.admin_api {
+            Some((address, token)) => Some((HttpListener::new(address)?, token)),
+            None => None,
+        };
+        let metrics_listener = match self.metrics {
+            Some(address) => Some(HttpListener::new(address)?),
+            None => None,
+        };
+        let interval = Duration::from_secs(EXPIRATION_INTERVAL);
+
+        Ok(Server {
+            socket: Dhcp6Framed::new(socket)?,
+            server_duid: self.server_duid,
+            database: Database::new(
+                self.pools,
+                self.prefix_pools,
+                self.prefix_reservations,
+                self.prefix_lengths,
+                self.storage,
+                self.preferred_lifetime,
+                self.valid_lifetime,
+            ),
+            dns_servers: self.dns_servers,
+            domain_list: self.domain_list,
+            rapid_commit: self.rapid_commit,
+            preference: self.preference,
+            admin_api,
+            metrics_listener,
+            metrics: Metrics::new(),
+            hooks: self.hooks,
+            expiration: Interval::new(Instant::now() + interval, interval),
+        })
+    }
+}
+
+/// A lease in the admin API responses.
+#[derive(Serialize)]
+struct LeaseRecord {
+    /// The hexadecimal client DUID.
+    duid: String,
+    iaid: u32,
+    address: Ipv6Addr,
+    preferred_lifetime: u32,
+    valid_lifetime: u32,
+    /// The Unix time, 0 for the infinite leases.
+    expires_at: u32,
+    /// `advertised` or `assigned`.
+    state: &'static str,
+}
+
+/// A delegated prefix in the admin API responses.
+#[derive(Serialize)]
+struct PrefixRecord {
+    /// The hexadecimal router DUID.
+    duid: String,
+    iaid: u32,
+    /// In the `prefix/length` notation.
+    prefix: String,
+    preferred_lifetime: u32,
+    valid_lifetime: u32,
+    /// The Unix time, 0 for the infinite leases.
+    expires_at: u32,
+    /// `advertised` or `delegated`.
+    state: &'static str,
+}
+
+/// The DHCPv6 server future.
+///
+/// Only the directly connected clients are served, so the replies are sent
+/// to the link-local addresses the requests have come from.
+pub struct Server<S: Storage> {
+    socket: Dhcp6Framed,
+    server_duid: Vec<u8>,
+    database: Database<S>,
+    dns_servers: Vec<Ipv6Addr>,
+    domain_list: Vec<String>,
+    rapid_commit: bool,
+    preference: Option<u8>,
+    /// The listener and the token.
+    admin_api: Option<(HttpListener, Option<String>)>,
+    metrics_listener: Option<HttpListener>,
+    metrics: Metrics,
+    hooks: Vec<CommandHook>,
+    /// Deletes the expired leases.
+    expiration: Interval,
+}
+
+impl<S> Future for Server<S>
+where
+    S: Storage,
+{
+    type Item = ();
+    type Error = io::Error;
+
+    /// Works infinite time.
+    fn poll(&mut self) -> Poll<(), io::Error> {
+        loop {
+            self.poll_admin();
+            self.poll_metrics();
+            self.poll_expiration();
+            try_ready!(self.socket.poll_complete());
+
+            let (source, request) = match try_ready!(self.socket.poll()) {
+                Some(item) => item,
+                None => {
+                    warn!("Received an invalid packet");
+                    self.metrics.error(ERROR_VALIDATION);
+                    continue;
+                }
+            };
+            self.metrics
+                .count_received(request.message_type.to_string());
+            debug!("Received {} from {}", request.message_type, source);
+            trace!("{}", request);
+
+            if !self.is_valid(&request) {
+                warn!(
+                    "Discarding an invalid {} from {}",
+                    request.message_type, source
+                );
+                self.metrics.error(ERROR_VALIDATION);
+                continue;
+            }
+            let reply = match self.process(&request) {
+                Some(reply) => reply,
+                None => continue,
+            };
+
+            self.metrics.count_sent(reply.message_type.to_string());
+            debug!("Sending {} to {}", reply.message_type, source);
+            trace!("{}", reply);
+            match self.socket.start_send((source, reply)) {
+                Ok(AsyncSink::Ready) => {}
+                Ok(AsyncSink::NotReady(_)) => panic!("Must wait for poll_complete first"),
+                Err(error) => warn!("Reply encoding error: {}", error),
+            }
+        }
+    }
+}
+
+impl<S> Server<S>
+where
+    S: Storage,
+{
+    /// Checks the identifiers the message must or must not contain (RFC 8415 §16).
+    fn is_valid(&self, request: &Message) -> bool {
+        let has_client_id = request.options.client_id.is_some();
+        let server_id = request.options.server_id.as_ref();
+        let is_ours = server_id.map_or(false, |id| *id == self.server_duid);
+        match request.message_type {
+            MessageType::Solicit | MessageType::Rebind | MessageType::Confirm => {
+                has_client_id && server_id.is_none()
+            }
+            MessageType::Request
+            | MessageType::Renew
+            | MessageType::Release
+            | MessageType::Decline => has_client_id && is_ours,
+            MessageType::InformationRequest => {
+                server_id.map_or(true, |_| is_ours)
+                    && request.options.ia_na.is_empty()
+                    && request.options.ia_pd.is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `None` if the message must not be answered.
+    fn process(&mut self, request: &Message) -> Option<Message> {
+        let duid = request.options.client_id.to_owned().unwrap_or_default();
+        match request.message_type {
+            MessageType::Solicit => {
+                let is_rapid = self.rapid_commit && request.options.rapid_commit;
+                let message_type = if is_rapid {
+                    MessageType::Reply
+                } else {
+                    MessageType::Advertise
+                };
+                let mut reply = self.reply(request, message_type);
+                for ia in request.options.ia_na.iter() {
+                    let hint = ia.addresses.first().map(|address| address.address);
+                    let result = if is_rapid {
+                        self.database.assign(&duid, ia.iaid, hint)
+                    } else {
+                        self.database.advertise(&duid, ia.iaid, hint)
+                    };
+                    reply.options.ia_na.push(match result {
+                        Ok(lease) => {
+                            if is_rapid {
+                                self.run_hooks(lease_variables("commit", &lease));
+                            }
+                            ia_na(&lease)
+                        }
+                        Err(error) => {
+                            ia_na_status(ia.iaid, self.failure(&error, Status::NoAddrsAvail))
+                        }
+                    });
+                }
+                reply.options.ia_pd = self.delegate_prefixes(&duid, request, is_rapid);
+                if is_rapid {
+                    reply.options.rapid_commit = true;
+                } else {
+                    reply.options.preference = self.preference;
+                }
+                Some(reply)
+            }
+            MessageType::Request => {
+                let mut reply = self.reply(request, MessageType::Reply);
+                for ia in request.options.ia_na.iter() {
+                    let hint = ia.addresses.first().map(|address| address.address);
+                    reply
+                        .options
+                        .ia_na
+                        .push(match self.database.assign(&duid, ia.iaid, hint) {
+                            Ok(lease) => {
+                                self.run_hooks(lease_variables("commit", &lease));
+                                ia_na(&lease)
+                            }
+                            Err(error) => {
+                                ia_na_status(ia.iaid, self.failure(&error, Status::NoAddrsAvail))
+                            }
+                        });
+                }
+                reply.options.ia_pd = self.delegate_prefixes(&duid, request, true);
+                Some(reply)
+            }
+            MessageType::Renew | MessageType::Rebind => {
+                let is_rebind = request.message_type == MessageType::Rebind;
+                let mut reply = self.reply(request, MessageType::Reply);
+                for ia in request.options.ia_na.iter() {
+                    let address = match ia.addresses.first() {
+                        Some(address) => address.address,
+                        None => {
+                            reply.options.ia_na.push(ia_na_status(
+                                ia.iaid,
+                                status_code(Status::NoBinding, "No address to renew"),
+                            ));
+                            continue;
+                        }
+                    };
+                    reply
+                        .options
+                        .ia_na
+                        .push(match self.database.renew(&duid, ia.iaid, &address) {
+                            Ok(lease) => {
+                                self.run_hooks(lease_variables("renew", &lease));
+                                ia_na(&lease)
+                            }
+                            // the client is told to stop using an address of another link
+                            Err(Error::BindingNotFound)
+                                if is_rebind && !self.database.is_on_link(&address) =>
+                            {
+                                ia_na_invalid(ia.iaid, address)
+                            }
+                            Err(error) => {
+                                ia_na_status(ia.iaid, self.failure(&error, Status::NoBinding))
+                            }
+                        });
+                }
+                reply.options.ia_pd = self.renew_prefixes(&duid, request, is_rebind);
+                Some(reply)
+            }
+            MessageType::Release | MessageType::Decline => {
+                let is_decline = request.message_type == MessageType::Decline;
+                let mut reply = self.reply(request, MessageType::Reply);
+                for ia in request.options.ia_na.iter() {
+                    let mut is_bound = true;
+                    for address in ia.addresses.iter() {
+                        let result = if is_decline {
+                            self.database.decline(&duid, ia.iaid, &address.address)
+                        } else {
+                            self.database.release(&duid, ia.iaid, &address.address)
+                        };
+                        match result {
+                            Ok(lease) => {
+                                if is_decline {
+                                    warn!("The address {} has been declined", lease.address);
+                                }
+                                self.run_hooks(lease_variables("release", &lease));
+                            }
+                            Err(Error::BindingNotFound) => is_bound = false,
+                            Err(error) => {
+                                warn!("Release error: {}", error);
+                                self.metrics.error(ERROR_STORAGE);
+                            }
+                        }
+                    }
+                    if !is_bound {
+                        reply.options.ia_na.push(ia_na_status(
+                            ia.iaid,
+                            status_code(Status::NoBinding, "Binding not found"),
+                        ));
+                    }
+                }
+                // the prefixes are not declined (RFC 8415 §18.2.8)
+                if !is_decline {
+                    reply.options.ia_pd = self.release_prefixes(&duid, request);
+                }
+                reply.options.status_code = Some(status_code(Status::Success, "Done"));
+                Some(reply)
+            }
+            MessageType::Confirm => {
+                let mut addresses = request
+                    .options
+                    .ia_na
+                    .iter()
+                    .flat_map(|ia| ia.addresses.iter())
+                    .peekable();
+                // a `CONFIRM` without addresses is not answered (RFC 8415 §18.3.3)
+                if addresses.peek().is_none() {
+                    return None;
+                }
+                let database = &self.database;
+                let is_on_link = addresses.all(|address| database.is_on_link(&address.address));
+                let mut reply = self.reply(request, MessageType::Reply);
+                reply.options.status_code = Some(if is_on_link {
+                    status_code(Status::Success, "All addresses are on link")
+                } else {
+                    status_code(Status::NotOnLink, "Some addresses are not on link")
+                });
+                Some(reply)
+            }
+            MessageType::InformationRequest => Some(self.reply(request, MessageType::Reply)),
+            _ => None,
+        }
+    }
+
+    /// Advertises or delegates the prefixes to the `IA_PD` of the request.
+    fn delegate_prefixes(
+        &mut self,
+        duid: &[u8],
+        request: &Message,
+        is_assigned: bool,
+    ) -> Vec<IaPd> {
+        let mut ia_pds = Vec::with_capacity(request.options.ia_pd.len());
+        for ia in request.options.ia_pd.iter() {
+            let hint = ia
+                .prefixes
+                .first()
+                .map(|prefix| (prefix.prefix, prefix.prefix_length));
+            let result = if is_assigned {
+                self.database.assign_prefix(duid, ia.iaid, hint)
+            } else {
+                self.database.advertise_prefix(duid, ia.iaid, hint)
+            };
+            ia_pds.push(match result {
+                Ok(lease) => {
+                    if is_assigned {
+                        self.run_hooks(prefix_variables("commit", &lease));
+                    }
+                    ia_pd(&lease)
+                }
+                Err(error) => ia_pd_status(ia.iaid, self.failure(&error, Status::NoPrefixAvail)),
+            });
+        }
+        ia_pds
+    }
+
+    /// Renews the prefixes of the `IA_PD` of the request.
+    fn renew_prefixes(&mut self, duid: &[u8], request: &Message, is_rebind: bool) -> Vec<IaPd> {
+        let mut ia_pds = Vec::with_capacity(request.options.ia_pd.len());
+        for ia in request.options.ia_pd.iter() {
+            let (prefix, prefix_length) = match ia.prefixes.first() {
+                Some(prefix) => (prefix.prefix, prefix.prefix_length),
+                None => {
+                    ia_pds.push(ia_pd_status(
+                        ia.iaid,
+                        status_code(Status::NoBinding, "No prefix to renew"),
+                    ));
+                    continue;
+                }
+            };
+            ia_pds.push(match self.database.renew_prefix(duid, ia.iaid, &prefix) {
+                Ok(lease) => {
+                    self.run_hooks(prefix_variables("renew", &lease));
+                    ia_pd(&lease)
+                }
+                // the router is told to stop using a prefix this server does not delegate
+                Err(Error::BindingNotFound)
+                    if is_rebind && !self.database.is_delegable(&prefix, prefix_length) =>
+                {
+                    ia_pd_invalid(ia.iaid, prefix, prefix_length)
+                }
+                Err(error) => ia_pd_status(ia.iaid, self.failure(&error, Status::NoBinding)),
+            });
+        }
+        ia_pds
+    }
+
+    /// Releases the prefixes of the `IA_PD` of the request,
+    /// returning the ones without the binding.
+    fn release_prefixes(&mut self, duid: &[u8], request: &Message) -> Vec<IaPd> {
+        let mut ia_pds = Vec::new();
+        for ia in request.options.ia_pd.iter() {
+            let mut is_bound = true;
+            for prefix in ia.prefixes.iter() {
+                match self.database.release_prefix(duid, ia.iaid, &prefix.prefix) {
+                    Ok(lease) => self.run_hooks(prefix_variables("release", &lease)),
+                    Err(Error::BindingNotFound) => is_bound = false,
+                    Err(error) => {
+                        warn!("Release error: {}", error);
+                        self.metrics.error(ERROR_STORAGE);
+                    }
+                }
+            }
+            if !is_bound {
+                ia_pds.push(ia_pd_status(
+                    ia.iaid,
+                    status_code(Status::NoBinding, "Binding not found"),
+                ));
+            }
+        }
+        ia_pds
+    }
+
+    /// Creates a reply with the identifiers and the configuration options requested.
+    fn reply(&self, request: &Message, message_type: MessageType) -> Message {
+        let mut options = Options::default();
+        options.server_id = Some(self.server_duid.to_owned());
+        options.client_id = request.options.client_id.to_owned();
+        if let Some(ref option_request) = request.options.option_request {
+            if option_request.contains(&(OptionCode::DnsServers as u16))
+                && !self.dns_servers.is_empty()
+            {
+                options.dns_servers = Some(self.dns_servers.to_owned());
+            }
+            if option_request.contains(&(OptionCode::DomainList as u16))
+                && !self.domain_list.is_empty()
+            {
+                options.domain_list = Some(self.domain_list.to_owned());
+            }
+        }
+        Message {
+            message_type,
+            transaction_id: request.transaction_id,
+            options,
+        }
+    }
+
+    /// Logs the error and returns the status of the IA for the client.
+    fn failure(&mut self, error: &Error, status: Status) -> StatusCode {
+        match *error {
+            Error::Storage(_) => {
+                warn!("Lease error: {}", error);
+                self.metrics.error(ERROR_STORAGE);
+                status_code(Status::UnspecFail, &error.to_string())
+            }
+            Error::PoolsExhausted => {
+                warn!("Allocation error: {}", error);
+                self.metrics.error(ERROR_ALLOCATION);
+                status_code(status, &error.to_string())
+            }
+            Error::BindingNotFound => {
+                self.metrics.error(ERROR_RENEWAL);
+                status_code(status, &error.to_string())
+            }
+        }
+    }
+
+    /// Runs the hook commands with the lease variables.
+    fn run_hooks(&self, variables: Vec<(&'static str, String)>) {
+        for hook in self.hooks.iter() {
+            tokio::spawn(
+                hook.run(variables.to_owned())
+                    .map_err(|error| warn!("Hook error: {}", error)),
+            );
+        }
+    }
+
+    /// Answers the admin API requests.
+    fn poll_admin(&mut self) {
+        loop {
+            let (id, request, is_authorized) = match self.admin_api {
+                Some((ref mut listener, ref token)) => match listener.poll_request() {
+                    Some((id, peer, request)) => {
+                        info!(
+                            target: "audit",
+                            "Admin API request {} {} from {}",
+                            request.method,
+                            request.path,
+                            peer,
+                        );
+                        let is_authorized = token.as_ref().map_or(true, |token| {
+                            request.authorization.as_ref() == Some(&format!("Bearer {}", token))
+                        });
+                        (id, request, is_authorized)
+                    }
+                    None => return,
+                },
+                None => return,
+            };
+            let response = if !is_authorized {
+                Response::error(401, "Unauthorized")
+            } else {
+                match (request.method.as_str(), request.path.as_str()) {
+                    ("GET", "/leases") => match self.database.leases() {
+                        Ok(leases) => {
+                            let records: Vec<LeaseRecord> =
+                                leases.iter().map(lease_record).collect();
+                            Response::json(200, &records)
+                        }
+                        Err(error) => {
+                            warn!("Leases getting error: {}", error);
+                            self.metrics.error(ERROR_STORAGE);
+                            Response::error(500, &error.to_string())
+                        }
+                    },
+                    ("GET", "/prefixes") => match self.database.prefix_leases() {
+                        Ok(leases) => {
+                            let records: Vec<PrefixRecord> =
+                                leases.iter().map(prefix_record).collect();
+                            Response::json(200, &records)
+                        }
+                        Err(error) => {
+                            warn!("Prefixes getting error: {}", error);
+                            self.metrics.error(ERROR_STORAGE);
+                            Response::error(500, &error.to_string())
+                        }
+                    },
+                    (_, "/leases") | (_, "/prefixes") => Response::error(405, "Method not allowed"),
+                    _ => Response::error(404, "Not found"),
+                }
+            };
+            if let Some((ref mut listener, _)) = self.admin_api {
+                listener.respond(id, response);
+            }
+        }
+    }
+
+    /// Answers the metrics scrapes.
+    fn poll_metrics(&mut self) {
+        loop {
+            let (id, request) = match self.metrics_listener {
+                Some(ref mut listener) => match listener.poll_request() {
+                    Some((id, _, request)) => (id, request),
+                    None => return,
+                },
+                None => return,
+            };
+            let response = match (request.method.as_str(), request.path.as_str()) {
+                ("GET", "/metrics") => Response::text(200, self.metrics.render(&[])),
+                (_, "/metrics") => Response::error(405, "Method not allowed"),
+                _ => Response::error(404, "Not found"),
+            };
+            if let Some(ref mut listener) = self.metrics_listener {
+                listener.respond(id, response);
+            }
+        }
+    }
+
+    /// Deletes the expired leases and runs the hooks on the assigned ones.
+    fn poll_expiration(&mut self) {
+        loop {
+            match self.expiration.poll() {
+                Ok(Async::Ready(Some(_))) => {}
+                Ok(_) => return,
+                Err(error) => {
+                    warn!("Expiration timer error: {}", error);
+                    return;
+                }
+            }
+            match self.database.expire() {
+                Ok(expired) => {
+                    for lease in expired.iter() {
+                        info!("The lease of {} has expired", lease.address);
+                        self.run_hooks(lease_variables("expire", lease));
+                    }
+                }
+                Err(error) => {
+                    warn!("Expiration error: {}", error);
+                    self.metrics.error(ERROR_STORAGE);
+                }
+            }
+            match self.database.expire_prefixes() {
+                Ok(expired) => {
+                    for lease in expired.iter() {
+                        info!(
+                            "The lease of {}/{} has expired",
+                            lease.prefix, lease.prefix_length
+                        );
+                        self.run_hooks(prefix_variables("expire", lease));
+                    }
+                }
+                Err(error) => {
+                    warn!("Expiration error: {}", error);
+                    self.metrics.error(ERROR_STORAGE);
+                }
+            }
+        }
+    }
+}
+
+/// The IA with the leased address and the T1 and T2 recommended by RFC 8415 §21.4.
+fn ia_na(lease: &Lease) -> IaNa {
+    let (preferred_lifetime, valid_lifetime) = lease.remaining_lifetimes();
+    let (renewal_time, rebinding_time) = if preferred_lifetime == INFINITY {
+        (INFINITY, INFINITY)
+    } else {
+        (preferred_lifetime / 2, preferred_lifetime / 5 * 4)
+    };
+    IaNa {
+        iaid: lease.iaid,
+        renewal_time,
+        rebinding_time,
+        addresses: vec![IaAddress {
+            address: lease.address,
+            preferred_lifetime,
+            valid_lifetime,
+            status_code: None,
+        }],
+        status_code: None,
+    }
+}
+
+/// The IA with the delegated prefix and the T1 and T2 like the ones of `ia_na`.
+fn ia_pd(lease: &PrefixLease) -> IaPd {
+    let (preferred_lifetime, valid_lifetime) = lease.remaining_lifetimes();
+    let (renewal_time, rebinding_time) = if preferred_lifetime == INFINITY {
+        (INFINITY, INFINITY)
+    } else {
+        (preferred_lifetime / 2, preferred_lifetime / 5 * 4)
+    };
+    IaPd {
+        iaid: lease.iaid,
+        renewal_time,
+        rebinding_time,
+        prefixes: vec![IaPrefix {
+            preferred_lifetime,
+            valid_lifetime,
+            prefix_length: lease.prefix_length,
+            prefix: lease.prefix,
+            status_code: None,
+        }],
+        status_code: None,
+    }
+}
+
+/// The IA with the prefix the router must stop using (RFC 8415 §18.3.5).
+fn ia_pd_invalid(iaid: u32, prefix: Ipv6Addr, prefix_length: u8) -> IaPd {
+    IaPd {
+        iaid,
+        renewal_time: 0,
+        rebinding_time: 0,
+        prefixes: vec![IaPrefix {
+            preferred_lifetime: 0,
+            valid_lifetime: 0,
+            prefix_length,
+            prefix,
+            status_code: None,
+        }],
+        status_code: None,
+    }
+}
+
+/// The IA without prefixes.
+fn ia_pd_status(iaid: u32, status_code: StatusCode) -> IaPd {
+    IaPd {
+        iaid,
+        renewal_time: 0,
+        rebinding_time: 0,
+        prefixes: Vec::new(),
+        status_code: Some(status_code),
+    }
+}
+
+/// The IA with the address the client must stop using (RFC 8415 §18.3.5).
+fn ia_na_invalid(iaid: u32, address: Ipv6Addr) -> IaNa {
+    IaNa {
+        iaid,
+        renewal_time: 0,
+        rebinding_time: 0,
+        addresses: vec![IaAddress {
+            address,
+            preferred_lifetime: 0,
+            valid_lifetime: 0,
+            status_code: None,
+        }],
+        status_code: None,
+    }
+}
+
+/// The IA without addresses.
+fn ia_na_status(iaid: u32, status_code: StatusCode) -> IaNa {
+    IaNa {
+        iaid,
+        renewal_time: 0,
+        rebinding_time: 0,
+        addresses: Vec::new(),
+        status_code: Some(status_code),
+    }
+}
+
+fn status_code(status: Status, message: &str) -> StatusCode {
+    StatusCode {
+        code: status as u16,
+        message: message.to_owned(),
+    }
+}
+
+fn lease_record(lease: &Lease) -> LeaseRecord {
+    let (preferred_lifetime, valid_lifetime) = lease.remaining_lifetimes();
+    LeaseRecord {
+        duid: hex(&lease.duid),
+        iaid: lease.iaid,
+        address: lease.address,
+        preferred_lifetime,
+        valid_lifetime,
+        expires_at: lease.expires_at(),
+        state: if lease.is_advertised {
+            "advertised"
+        } else {
+            "assigned"
+        },
+    }
+}
+
+/// The variables of the DHCPv4 hooks, where `DHCP_CLIENT_ID` is the DUID and `DHCP_IAID` is added.
+///
+/// The lifetime ones are set only on the commits and the renewals.
+fn lease_variables(event: &'static str, lease: &Lease) -> Vec<(&'static str, String)> {
+    let mut variables = vec![
+        ("DHCP_EVENT", event.to_owned()),
+        ("DHCP_CLIENT_ID", hex(&lease.duid)),
+        ("DHCP_IAID", lease.iaid.to_string()),
+        ("DHCP_ADDRESS", lease.address.to_string()),
+    ];
+    if event == "commit" || event == "renew" {
+        variables.push(("DHCP_LEASE_TIME", lease.valid_lifetime.to_string()));
+        variables.push(("DHCP_EXPIRES_AT", lease.expires_at().to_string()));
+    }
+    variables
+}
+
+/// The variables of a delegated prefix, where `DHCP_PREFIX` replaces `DHCP_ADDRESS`.
+fn prefix_variables(event: &'static str, lease: &PrefixLease) -> Vec<(&'static str, String)> {
+    let mut variables = vec![
+        ("DHCP_EVENT", event.to_owned()),
+        ("DHCP_CLIENT_ID", hex(&lease.duid)),
+        ("DHCP_IAID", lease.iaid.to_string()),
+        (
+            "DHCP_PREFIX",
+            format!("{}/{}", lease.prefix, lease.prefix_length),
+        ),
+    ];
+    if event == "commit" || event == "renew" {
+        variables.push(("DHCP_LEASE_TIME", lease.valid_lifetime.to_string()));
+        variables.push(("DHCP_EXPIRES_AT", lease.expires_at().to_string()));
+    }
+    variables
+}
+
+fn prefix_record(lease: &PrefixLease) -> PrefixRecord {
+    let (preferred_lifetime, valid_lifetime) = lease.remaining_lifetimes();
+    PrefixRecord {
+        duid: hex(&lease.duid),
+        iaid: lease.iaid,
+        prefix: format!("{}/{}", lease.prefix, lease.prefix_length),
+        preferred_lifetime,
+        valid_lifetime,
+        expires_at: lease.expires_at(),
+        state: if lease.is_advertised {
+            "advertised"
+        } else {
+            "delegated"
+        },
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
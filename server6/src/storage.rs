@@ -0,0 +1,42 @@
+//! The DHCPv6 lease storage trait.
+//!
+//! The errors are the ones of the DHCPv4 storage, so a backend may serve both servers.
+
+use std::net::Ipv6Addr;
+
+use dhcp_server::StorageError as Error;
+
+use lease::{Lease, PrefixLease};
+
+/// Must be implemented by the DHCPv6 server crate user.
+///
+/// The leases are keyed by the client DUID and the IAID.
+/// Be sure your storage is `ACID`.
+pub trait Storage: Send + Sync {
+    /// Returns the DUID and the IAID the address is bound to.
+    fn get_binding(&self, address: &Ipv6Addr) -> Result<Option<(Vec<u8>, u32)>, Error>;
+
+    /// Returns the lease of the identity association.
+    fn get_lease(&self, duid: &[u8], iaid: u32) -> Result<Option<Lease>, Error>;
+    /// Adds or replaces the lease of the identity association,
+    /// binding its address to it and unbinding the previous one.
+    fn add_lease(&mut self, lease: Lease) -> Result<(), Error>;
+    /// Deletes the lease of the identity association, unbinding its address.
+    fn delete_lease(&mut self, duid: &[u8], iaid: u32) -> Result<(), Error>;
+    /// Returns all the leases.
+    fn get_leases(&self) -> Result<Vec<Lease>, Error>;
+
+    /// Returns the delegated prefix of the identity association.
+    fn get_prefix_lease(&self, duid: &[u8], iaid: u32) -> Result<Option<PrefixLease>, Error>;
+    /// Adds or replaces the delegated prefix of the identity association.
+    fn add_prefix_lease(&mut self, lease: PrefixLease) -> Result<(), Error>;
+    /// Deletes the delegated prefix of the identity association.
+    fn delete_prefix_lease(&mut self, duid: &[u8], iaid: u32) -> Result<(), Error>;
+    /// Returns all the delegated prefixes.
+    fn get_prefix_leases(&self) -> Result<Vec<PrefixLease>, Error>;
+
+    /// Checks if the address has been declined by a client.
+    fn check_frozen(&self, address: &Ipv6Addr) -> Result<bool, Error>;
+    /// Marks the address declined by a client, so it is never leased again.
+    fn add_frozen(&mut self, address: &Ipv6Addr) -> Result<(), Error>;
+}
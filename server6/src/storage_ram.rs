@@ -0,0 +1,96 @@
+//! A RAM example of the persistent DHCPv6 lease storage.
+//! (well, not persistent, eventually)
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::Ipv6Addr,
+};
+
+use dhcp_server::StorageError as Error;
+
+use lease::{Lease, PrefixLease};
+use storage::Storage;
+
+pub struct RamStorage {
+    /// `IPv6` to DUID and IAID mapping.
+    address_binding_map: HashMap<Ipv6Addr, (Vec<u8>, u32)>,
+    /// DUID and IAID to `Lease` mapping.
+    binding_lease_map: HashMap<(Vec<u8>, u32), Lease>,
+    /// DUID and IAID to `PrefixLease` mapping.
+    binding_prefix_map: HashMap<(Vec<u8>, u32), PrefixLease>,
+    /// `IPv6` addresses reported by `DECLINE`.
+    frozen_addresses: HashSet<Ipv6Addr>,
+}
+
+impl RamStorage {
+    pub fn new() -> Self {
+        RamStorage {
+            address_binding_map: HashMap::new(),
+            binding_lease_map: HashMap::new(),
+            binding_prefix_map: HashMap::new(),
+            frozen_addresses: HashSet::new(),
+        }
+    }
+}
+
+impl Storage for RamStorage {
+    fn get_binding(&self, address: &Ipv6Addr) -> Result<Option<(Vec<u8>, u32)>, Error> {
+        Ok(self.address_binding_map.get(address).cloned())
+    }
+
+    fn get_lease(&self, duid: &[u8], iaid: u32) -> Result<Option<Lease>, Error> {
+        Ok(self.binding_lease_map.get(&(duid.to_vec(), iaid)).cloned())
+    }
+
+    fn add_lease(&mut self, lease: Lease) -> Result<(), Error> {
+        let key = (lease.duid.to_owned(), lease.iaid);
+        if let Some(previous) = self.binding_lease_map.get(&key) {
+            if previous.address != lease.address {
+                self.address_binding_map.remove(&previous.address);
+            }
+        }
+        self.address_binding_map
+            .insert(lease.address, key.to_owned());
+        self.binding_lease_map.insert(key, lease);
+        Ok(())
+    }
+
+    fn delete_lease(&mut self, duid: &[u8], iaid: u32) -> Result<(), Error> {
+        if let Some(lease) = self.binding_lease_map.remove(&(duid.to_vec(), iaid)) {
+            self.address_binding_map.remove(&lease.address);
+        }
+        Ok(())
+    }
+
+    fn get_leases(&self) -> Result<Vec<Lease>, Error> {
+        Ok(self.binding_lease_map.values().cloned().collect())
+    }
+
+    fn get_prefix_lease(&self, duid: &[u8], iaid: u32) -> Result<Option<PrefixLease>, Error> {
+        Ok(self.binding_prefix_map.get(&(duid.to_vec(), iaid)).cloned())
+    }
+
+    fn add_prefix_lease(&mut self, lease: PrefixLease) -> Result<(), Error> {
+        self.binding_prefix_map
+            .insert((lease.duid.to_owned(), lease.iaid), lease);
+        Ok(())
+    }
+
+    fn delete_prefix_lease(&mut self, duid: &[u8], iaid: u32) -> Result<(), Error> {
+        self.binding_prefix_map.remove(&(duid.to_vec(), iaid));
+        Ok(())
+    }
+
+    fn get_prefix_leases(&self) -> Result<Vec<PrefixLease>, Error> {
+        Ok(self.binding_prefix_map.values().cloned().collect())
+    }
+
+    fn check_frozen(&self, address: &Ipv6Addr) -> Result<bool, Error> {
+        Ok(self.frozen_addresses.contains(address))
+    }
+
+    fn add_frozen(&mut self, address: &Ipv6Addr) -> Result<(), Error> {
+        self.frozen_addresses.insert(address.to_owned());
+        Ok(())
+    }
+}